@@ -0,0 +1,440 @@
+use anyhow::{bail, Result};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    expr::ScalarValue,
+    relation::Relation,
+    sql::error::{PgError, SqlState},
+    storage::{
+        buffer::Buffer,
+        item_id_data_from_page,
+        page::{page_add_item, page_init, ItemPointer, ITEM_ID_SIZE, PAGE_HEADER_SIZE},
+        value_from_page_item, wal, BufferPool, PageNumber, INVALID_PAGE_NUMBER, PAGE_SIZE,
+    },
+    Datum, Oid,
+};
+
+/// Fixed oid of the B-tree index access method.
+pub const BTREE_AM_OID: Oid = 403;
+
+/// Page number of the meta page that store the root page of the tree.
+const BTREE_METAPAGE: PageNumber = 1;
+
+/// Maximum size of a serialized node. Each B-tree page store a single item
+/// containing the entire node.
+const BTREE_MAX_NODE_SIZE: usize = PAGE_SIZE - PAGE_HEADER_SIZE - ITEM_ID_SIZE;
+
+/// Maximum size of an encoded key. It guarantees that a node always fit on page
+/// after a split.
+const BTREE_MAX_KEY_SIZE: usize = BTREE_MAX_NODE_SIZE / 4;
+
+/// Information about the tree stored on the first page of an index relation.
+#[derive(Serialize, Deserialize, Debug)]
+struct BTreeMetaPage {
+    /// Page number of the current root node.
+    root: PageNumber,
+}
+
+/// A node of the tree. Keys are always sorted in ascending order.
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct BTreeNode {
+    /// True if the node is a leaf, which store the heap tuple pointers.
+    is_leaf: bool,
+
+    /// Encoded keys. On internal nodes each key is the lowest key of the
+    /// respective right child.
+    keys: Vec<Vec<u8>>,
+
+    /// Heap tuple pointers of each key on leaf nodes.
+    tids: Vec<ItemPointer>,
+
+    /// Child page numbers on internal nodes, always one more than keys.
+    children: Vec<PageNumber>,
+
+    /// Right sibling on the same level of the tree, INVALID_PAGE_NUMBER if the
+    /// node is the rightmost.
+    right: PageNumber,
+}
+
+impl BTreeNode {
+    fn size(&self) -> Result<usize> {
+        Ok(bincode::serialized_size(self)? as usize)
+    }
+
+    /// Split the node on two halves with approximately the same size in bytes,
+    /// returning the separator key and the new right node.
+    fn split(&mut self) -> (Vec<u8>, BTreeNode) {
+        let total: usize = self.keys.iter().map(|key| key.len()).sum();
+
+        let mut mid = 0;
+        let mut size = 0;
+        while mid < self.keys.len() && size < total / 2 {
+            size += self.keys[mid].len();
+            mid += 1;
+        }
+        let mid = mid.clamp(1, self.keys.len() - 1);
+
+        if self.is_leaf {
+            let right = BTreeNode {
+                is_leaf: true,
+                keys: self.keys.split_off(mid),
+                tids: self.tids.split_off(mid),
+                children: Vec::new(),
+                right: self.right,
+            };
+            (right.keys[0].clone(), right)
+        } else {
+            let keys = self.keys.split_off(mid + 1);
+            let separator = self.keys.pop().unwrap();
+            let right = BTreeNode {
+                is_leaf: false,
+                keys,
+                tids: Vec::new(),
+                children: self.children.split_off(mid + 1),
+                right: self.right,
+            };
+            (separator, right)
+        }
+    }
+}
+
+/// Encode the given datum of the given type to a B-tree key. Encoded keys
+/// preserve the order of values when compared byte by byte.
+pub fn btree_encode_key(datum: &Datum, typ: Oid) -> Result<Vec<u8>> {
+    match ScalarValue::from_datum(&Some(datum.clone()), typ)? {
+        ScalarValue::SmallInt(value) => Ok(((value as u16) ^ (1 << 15)).to_be_bytes().to_vec()),
+        ScalarValue::Int(value) => Ok(((value as u32) ^ (1 << 31)).to_be_bytes().to_vec()),
+        ScalarValue::BigInt(value) => Ok(((value as u64) ^ (1 << 63)).to_be_bytes().to_vec()),
+        ScalarValue::Float4(value) => Ok(encode_float(value as f64)),
+        ScalarValue::Float8(value) => Ok(encode_float(value)),
+        ScalarValue::Varchar(value) => Ok(value.into_bytes()),
+        ScalarValue::Bool(value) => Ok(vec![value as u8]),
+        ScalarValue::Date(value) => Ok(((value as u32) ^ (1 << 31)).to_be_bytes().to_vec()),
+        ScalarValue::Timestamp(value) => Ok(((value as u64) ^ (1 << 63)).to_be_bytes().to_vec()),
+        ScalarValue::Null => bail!("can not encode NULL value of type {}", typ),
+    }
+}
+
+/// Encode a float so that the byte-wise order of keys is the numeric order of values. Positive
+/// values have the sign bit flipped and negative values have all bits flipped.
+fn encode_float(value: f64) -> Vec<u8> {
+    // Negative and positive zeros are equal.
+    let value = if value == 0.0 { 0.0 } else { value };
+    let bits = value.to_bits();
+    let bits = if bits >> 63 == 0 {
+        bits ^ (1 << 63)
+    } else {
+        !bits
+    };
+    bits.to_be_bytes().to_vec()
+}
+
+/// Initialize an empty B-tree on the given empty index relation.
+pub fn btree_init(buffer_pool: &BufferPool, rel: &Relation) -> Result<()> {
+    let mut metapage = buffer_pool.alloc_buffer(rel)?;
+    let mut root = buffer_pool.alloc_buffer(rel)?;
+
+    write_node(
+        buffer_pool,
+        rel,
+        &root,
+        &BTreeNode {
+            is_leaf: true,
+            ..Default::default()
+        },
+    )?;
+    write_node(
+        buffer_pool,
+        rel,
+        &metapage,
+        &BTreeMetaPage {
+            root: root.page_number(),
+        },
+    )?;
+    root.mark_dirty();
+    metapage.mark_dirty();
+
+    Ok(())
+}
+
+/// Insert a new entry on the B-tree of the given index relation pointing the
+/// key to the given heap tuple.
+pub fn btree_insert(
+    buffer_pool: &BufferPool,
+    rel: &Relation,
+    key: &[u8],
+    tid: &ItemPointer,
+) -> Result<()> {
+    if key.len() > BTREE_MAX_KEY_SIZE {
+        bail!(PgError::new(
+            SqlState::ProgramLimitExceeded,
+            format!(
+                "index row size {} exceeds btree maximum {}",
+                key.len(),
+                BTREE_MAX_KEY_SIZE
+            )
+        ));
+    }
+
+    // Inserts read and rewrite the nodes from the root to a leaf without locking the pages, so
+    // concurrent inserts on the same index could overwrite each other's changes.
+    let insert_lock = buffer_pool.index_insert_lock(rel);
+    let _guard = insert_lock.lock().unwrap();
+
+    let mut metapage = buffer_pool.fetch_buffer(rel, BTREE_METAPAGE)?;
+    let mut meta: BTreeMetaPage = read_node(&metapage)?;
+
+    if let Some((separator, right)) = insert_into_node(buffer_pool, rel, meta.root, key, tid)? {
+        // The root was splitted, so a new root is created pointing to both halves.
+        let mut new_root = buffer_pool.alloc_buffer(rel)?;
+        write_node(
+            buffer_pool,
+            rel,
+            &new_root,
+            &BTreeNode {
+                is_leaf: false,
+                keys: vec![separator],
+                tids: Vec::new(),
+                children: vec![meta.root, right],
+                right: INVALID_PAGE_NUMBER,
+            },
+        )?;
+
+        meta.root = new_root.page_number();
+        write_node(buffer_pool, rel, &metapage, &meta)?;
+
+        new_root.mark_dirty();
+        metapage.mark_dirty();
+    }
+
+    Ok(())
+}
+
+/// Return the heap tuple pointers of all entries of the B-tree of the given
+/// index relation that are equal to the given key.
+pub fn btree_search(
+    buffer_pool: &BufferPool,
+    rel: &Relation,
+    key: &[u8],
+) -> Result<Vec<ItemPointer>> {
+    let metapage = buffer_pool.fetch_buffer(rel, BTREE_METAPAGE)?;
+    let meta: BTreeMetaPage = read_node(&metapage)?;
+    drop(metapage);
+
+    // Descend to the leftmost leaf that may contain the key.
+    let mut page_number = meta.root;
+    let mut node = loop {
+        let buffer = buffer_pool.fetch_buffer(rel, page_number)?;
+        let node: BTreeNode = read_node(&buffer)?;
+
+        if node.is_leaf {
+            break node;
+        }
+        page_number = node.children[node.keys.partition_point(|k| k.as_slice() < key)];
+    };
+
+    // Equal keys can be spread over multiple leaves, so follow the right
+    // siblings until a greater key is found.
+    let mut tids = Vec::new();
+    loop {
+        for (k, tid) in node.keys.iter().zip(node.tids.iter()) {
+            if k.as_slice() == key {
+                tids.push(*tid);
+            } else if k.as_slice() > key {
+                return Ok(tids);
+            }
+        }
+
+        if node.right == INVALID_PAGE_NUMBER {
+            return Ok(tids);
+        }
+
+        let buffer = buffer_pool.fetch_buffer(rel, node.right)?;
+        node = read_node(&buffer)?;
+    }
+}
+
+/// Insert the key on the subtree of the given page number. If the node had to
+/// be splitted return the separator key and the page number of the new right
+/// node, that should be inserted on parent node.
+fn insert_into_node(
+    buffer_pool: &BufferPool,
+    rel: &Relation,
+    page_number: PageNumber,
+    key: &[u8],
+    tid: &ItemPointer,
+) -> Result<Option<(Vec<u8>, PageNumber)>> {
+    let mut buffer = buffer_pool.fetch_buffer(rel, page_number)?;
+    let mut node: BTreeNode = read_node(&buffer)?;
+
+    // New keys are inserted after all equal keys.
+    let pos = node.keys.partition_point(|k| k.as_slice() <= key);
+
+    if node.is_leaf {
+        node.keys.insert(pos, key.to_vec());
+        node.tids.insert(pos, *tid);
+    } else {
+        match insert_into_node(buffer_pool, rel, node.children[pos], key, tid)? {
+            Some((separator, right)) => {
+                node.keys.insert(pos, separator);
+                node.children.insert(pos + 1, right);
+            }
+            None => return Ok(None),
+        }
+    }
+
+    if node.size()? <= BTREE_MAX_NODE_SIZE {
+        write_node(buffer_pool, rel, &buffer, &node)?;
+        buffer.mark_dirty();
+        return Ok(None);
+    }
+
+    let (separator, right) = node.split();
+    let mut right_buffer = buffer_pool.alloc_buffer(rel)?;
+    node.right = right_buffer.page_number();
+
+    write_node(buffer_pool, rel, &right_buffer, &right)?;
+    write_node(buffer_pool, rel, &buffer, &node)?;
+
+    right_buffer.mark_dirty();
+    buffer.mark_dirty();
+
+    Ok(Some((separator, node.right)))
+}
+
+/// Read the node stored on the page of the given buffer.
+fn read_node<T: DeserializeOwned>(buffer: &Buffer) -> Result<T> {
+    let item_ids = item_id_data_from_page(&buffer.page)?;
+    match item_ids.first() {
+        Some(item_id) => Ok(bincode::deserialize(&value_from_page_item(
+            &buffer.page,
+            item_id,
+        )?)?),
+        None => bail!("btree page {} is not initialized", buffer.page_number()),
+    }
+}
+
+/// Replace the contents of the page of the given buffer with the given node.
+fn write_node<T: Serialize>(
+    buffer_pool: &BufferPool,
+    rel: &Relation,
+    buffer: &Buffer,
+    node: &T,
+) -> Result<()> {
+    page_init(&buffer.page)?;
+    page_add_item(&buffer.page, &bincode::serialize(node)?)?;
+    wal::log_page_image(buffer_pool, rel, buffer)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::{
+        access,
+        catalog::{pg_tablespace::DEFAULTTABLESPACE_OID, pg_type},
+        storage::smgr::StorageManager,
+    };
+
+    fn int_key(value: i32) -> Result<Vec<u8>> {
+        btree_encode_key(&Datum::from(bincode::serialize(&value)?), pg_type::INT_OID)
+    }
+
+    #[test]
+    fn test_encode_key_order() -> Result<()> {
+        assert!(int_key(-10)? < int_key(-1)?);
+        assert!(int_key(-1)? < int_key(0)?);
+        assert!(int_key(0)? < int_key(256)?);
+        assert!(int_key(256)? < int_key(i32::MAX)?);
+
+        let float_key = |value: f64| {
+            btree_encode_key(
+                &Datum::from(bincode::serialize(&value)?),
+                pg_type::FLOAT8_OID,
+            )
+        };
+        assert!(float_key(f64::NEG_INFINITY)? < float_key(-2.5)?);
+        assert!(float_key(-2.5)? < float_key(-0.5)?);
+        assert!(float_key(-0.5)? < float_key(0.0)?);
+        assert_eq!(float_key(-0.0)?, float_key(0.0)?);
+        assert!(float_key(0.0)? < float_key(0.5)?);
+        assert!(float_key(0.5)? < float_key(1e10)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_btree_insert_and_search() -> Result<()> {
+        let db_data = tempfile::tempdir()?;
+        fs::create_dir_all(db_data.path().join("base").join("5"))?;
+
+        let buffer_pool = BufferPool::new(20, StorageManager::new(db_data.path()));
+        let rel = access::open_relation(10000, DEFAULTTABLESPACE_OID, &5, "test_idx");
+        btree_init(&buffer_pool, &rel)?;
+
+        // Insert keys in a non sorted order with duplicates, enough to split
+        // leaves and internal nodes.
+        let total = 5000;
+        for i in 0..total {
+            let value = (i * 7919) % total;
+            let tid = ItemPointer {
+                page_number: i as PageNumber + 1,
+                offset: 1,
+            };
+            btree_insert(&buffer_pool, &rel, &int_key(value / 2)?, &tid)?;
+        }
+
+        assert!(buffer_pool.size_of_relation(&rel)? > 3);
+
+        for value in 0..total / 2 {
+            let tids = btree_search(&buffer_pool, &rel, &int_key(value)?)?;
+            assert_eq!(tids.len(), 2, "Expected two entries for key {}", value);
+        }
+        assert!(btree_search(&buffer_pool, &rel, &int_key(total)?)?.is_empty());
+        assert!(btree_search(&buffer_pool, &rel, &int_key(-1)?)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_btree_concurrent_inserts() -> Result<()> {
+        let db_data = tempfile::tempdir()?;
+        fs::create_dir_all(db_data.path().join("base").join("5"))?;
+
+        let buffer_pool = BufferPool::new(50, StorageManager::new(db_data.path()));
+        let rel = access::open_relation(10000, DEFAULTTABLESPACE_OID, &5, "test_idx");
+        btree_init(&buffer_pool, &rel)?;
+
+        // Each thread inserts its own keys, enough to split leaves while the other threads are
+        // inserting on them.
+        let threads = 4;
+        let total = 2000;
+        let handles = (0..threads)
+            .map(|thread| {
+                let buffer_pool = buffer_pool.clone();
+                let rel = rel.clone();
+                std::thread::spawn(move || -> Result<()> {
+                    for i in 0..total {
+                        let tid = ItemPointer {
+                            page_number: i as PageNumber + 1,
+                            offset: thread as u16 + 1,
+                        };
+                        btree_insert(&buffer_pool, &rel, &int_key(i * threads + thread)?, &tid)?;
+                    }
+                    Ok(())
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().unwrap()?;
+        }
+
+        for value in 0..total * threads {
+            let tids = btree_search(&buffer_pool, &rel, &int_key(value)?)?;
+            assert_eq!(tids.len(), 1, "Expected one entry for key {}", value);
+        }
+
+        Ok(())
+    }
+}
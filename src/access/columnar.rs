@@ -0,0 +1,561 @@
+use std::{convert::TryInto, sync::Mutex, vec::IntoIter};
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    catalog::{
+        self, heap::drop_relation_storage, index::index_insert_tuple, pg_attribute::PgAttribute,
+    },
+    relation::Relation,
+    storage::{
+        freespace, item_id_data_from_page,
+        page::{page_add_item, page_get_free_space, page_init, ItemPointer, ITEM_ID_SIZE},
+        value_from_page_item,
+        wal::{self, WalRecord},
+        BufferPool, PageNumber,
+    },
+    Datum, NullableDatum, Oid, FIRST_NORMAL_OBJECT_ID, INVALID_OID,
+};
+
+use super::{
+    heap::{check_heap_tuple_size, heap_mark_dead},
+    heaptuple::{HeapTuple, TupleDesc},
+    open_relation,
+    tableam::{TableAm, TableScan},
+};
+
+/// Fixed oid of the experimental columnar table access method.
+pub const COLUMNAR_TABLE_AM_OID: Oid = 6001;
+
+/// Page number of the meta page that store the columns of the table.
+const COLUMNAR_METAPAGE: PageNumber = 1;
+
+/// Information about the columns of a table stored on the first page of the table relation.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct ColumnarMetaPage {
+    columns: Vec<Column>,
+}
+
+/// A column of a columnar table.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct Column {
+    /// Oid of the relation that store the values of the column.
+    oid: Oid,
+
+    /// Size of the column values, as stored on pg_attribute.attlen.
+    attlen: i64,
+}
+
+/// Table access method that stores the values of each attribute contiguously on a separated
+/// relation file, without the header of each tuple.
+///
+/// Each column file has one item per row, in insertion order, with a flag byte telling if the
+/// value is NULL followed by the value bytes. The table relation itself has the meta page with
+/// the oids of the column files, followed by the row directory: one item per row storing the row
+/// number. The location of the row directory item identify the row, deleted rows have their row
+/// directory item marked as dead.
+///
+/// Rows are only appended to the end of the files, the space of deleted rows is never reused.
+/// Fetching a single row walk the column files until the row number, so columnar tables are only
+/// efficient for sequential scans.
+pub struct ColumnarTableAm;
+
+/// Inserts on columnar tables append the values to the end of each column file, so concurrent
+/// inserts are serialized to keep the row numbers of all columns aligned.
+static INSERT_LOCK: Mutex<()> = Mutex::new(());
+
+impl TableAm for ColumnarTableAm {
+    fn oid(&self) -> Oid {
+        COLUMNAR_TABLE_AM_OID
+    }
+
+    fn name(&self) -> &'static str {
+        "columnar"
+    }
+
+    fn relation_create(
+        &self,
+        buffer_pool: &BufferPool,
+        rel: &Relation,
+        tuple_desc: &TupleDesc,
+    ) -> Result<()> {
+        let locator = &rel.locator;
+        let mut columns = Vec::with_capacity(tuple_desc.attrs.len());
+        for attr in &tuple_desc.attrs {
+            let oid =
+                catalog::new_relation_oid(buffer_pool, &locator.tablespace, &locator.database)?;
+
+            // Extend the column file so the oid is not used by other relations.
+            let column = Column {
+                oid,
+                attlen: if attr.is_varlena() { -1 } else { attr.attlen },
+            };
+            freespace::extend_relation(buffer_pool, &column_relation(rel, &column))?.mark_dirty();
+            columns.push(column);
+        }
+
+        let mut metapage = buffer_pool.alloc_buffer(rel)?;
+        page_init(&metapage.page)?;
+        page_add_item(
+            &metapage.page,
+            &bincode::serialize(&ColumnarMetaPage { columns })?,
+        )?;
+        wal::log_page_image(buffer_pool, rel, &metapage)?;
+        metapage.mark_dirty();
+
+        Ok(())
+    }
+
+    fn relation_drop(&self, buffer_pool: &BufferPool, rel: &Relation) -> Result<()> {
+        let meta = read_metapage(buffer_pool, rel)?;
+        for column in &meta.columns {
+            drop_relation_storage(buffer_pool, &column_relation(rel, column))?;
+        }
+        drop_relation_storage(buffer_pool, rel)
+    }
+
+    fn insert(
+        &self,
+        buffer_pool: &BufferPool,
+        rel: &Relation,
+        tuple: &HeapTuple,
+    ) -> Result<ItemPointer> {
+        let meta = read_metapage(buffer_pool, rel)?;
+        let tuple_desc = meta.tuple_desc(rel);
+
+        // All values are checked before any of them is appended, so a value that don't fit on a
+        // page don't leave the columns of the row partially written.
+        let items = tuple
+            .deform(&tuple_desc, None)?
+            .into_iter()
+            .map(|value| match value {
+                Some(value) => [&[1], &value[..]].concat(),
+                None => vec![0],
+            })
+            .collect::<Vec<_>>();
+        for item in &items {
+            check_heap_tuple_size(item.len())?;
+        }
+
+        let guard = INSERT_LOCK.lock().unwrap();
+        let row = next_row_number(buffer_pool, rel)?;
+
+        // The values are appended before the row directory entry, so scans never see a row
+        // without all of its values.
+        for (column, item) in meta.columns.iter().zip(items) {
+            append_item(buffer_pool, &column_relation(rel, column), 1, item)?;
+        }
+        let tid = append_item(
+            buffer_pool,
+            rel,
+            COLUMNAR_METAPAGE + 1,
+            row.to_le_bytes().to_vec(),
+        )?;
+        drop(guard);
+
+        if rel.locator.oid >= FIRST_NORMAL_OBJECT_ID {
+            index_insert_tuple(buffer_pool, rel, tuple, &tid)?;
+        }
+
+        Ok(tid)
+    }
+
+    fn delete(&self, buffer_pool: &BufferPool, rel: &Relation, tid: &ItemPointer) -> Result<()> {
+        // Row directory entries are never reused, since the row number of a new entry follows the
+        // row number of the last entry.
+        heap_mark_dead(buffer_pool, rel, tid)
+    }
+
+    fn fetch(
+        &self,
+        buffer_pool: &BufferPool,
+        rel: &Relation,
+        tid: &ItemPointer,
+    ) -> Result<Option<HeapTuple>> {
+        if tid.page_number <= COLUMNAR_METAPAGE
+            || tid.page_number > buffer_pool.size_of_relation(rel)?
+        {
+            return Ok(None);
+        }
+        let row = match read_items(buffer_pool, rel, tid.page_number)?
+            .into_iter()
+            .nth(tid.offset.saturating_sub(1) as usize)
+        {
+            Some(Some(item)) => row_number(&item)?,
+            _ => return Ok(None),
+        };
+
+        let meta = read_metapage(buffer_pool, rel)?;
+        let mut values = Vec::with_capacity(meta.columns.len());
+        for column in &meta.columns {
+            let mut cursor = ColumnCursor::new(buffer_pool, column_relation(rel, column));
+            for _ in 0..row {
+                cursor.next_value()?;
+            }
+            values.push(cursor.next_value()?);
+        }
+
+        let mut tuple = HeapTuple::from_datums(values, &meta.tuple_desc(rel))?;
+        tuple.t_self = *tid;
+        Ok(Some(tuple))
+    }
+
+    fn begin_scan(&self, buffer_pool: &BufferPool, rel: &Relation) -> Result<Box<dyn TableScan>> {
+        let meta = read_metapage(buffer_pool, rel)?;
+        Ok(Box::new(ColumnarScan {
+            buffer_pool: buffer_pool.clone(),
+            rel: rel.clone(),
+            tuple_desc: meta.tuple_desc(rel),
+            columns: meta
+                .columns
+                .iter()
+                .map(|column| ColumnCursor::new(buffer_pool, column_relation(rel, column)))
+                .collect(),
+            nblocks: buffer_pool.size_of_relation(rel)?,
+            page_number: COLUMNAR_METAPAGE,
+            rows: Vec::new().into_iter(),
+            next_row: 0,
+        }))
+    }
+}
+
+impl ColumnarMetaPage {
+    /// Return a tuple description with the columns of the table, used to encode and decode the
+    /// tuples of the table.
+    fn tuple_desc(&self, rel: &Relation) -> TupleDesc {
+        let attrs = self
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(attnum, column)| PgAttribute {
+                attrelid: rel.locator.oid,
+                attname: String::new(),
+                attnum: attnum + 1,
+                attlen: column.attlen,
+                atttypid: INVALID_OID,
+                attnotnull: false,
+                attdefault: String::new(),
+            })
+            .collect();
+        TupleDesc { attrs }
+    }
+}
+
+/// Sequential scan over the rows of a columnar table, reading each column file in parallel with
+/// the row directory.
+struct ColumnarScan {
+    buffer_pool: BufferPool,
+
+    rel: Relation,
+
+    tuple_desc: TupleDesc,
+
+    /// Cursor over the values of each column.
+    columns: Vec<ColumnCursor>,
+
+    /// Number of pages of the table relation when the scan started.
+    nblocks: PageNumber,
+
+    /// Page number of the row directory page being scanned.
+    page_number: PageNumber,
+
+    /// Row directory entries of the current page, None for deleted rows, with their offset.
+    rows: IntoIter<(u16, Option<Vec<u8>>)>,
+
+    /// Row number of the next value returned by the column cursors.
+    next_row: u64,
+}
+
+impl TableScan for ColumnarScan {
+    fn next_tuple(&mut self) -> Result<Option<HeapTuple>> {
+        loop {
+            let (offset, item) = match self.rows.next() {
+                Some(row) => row,
+                None if self.page_number < self.nblocks => {
+                    self.page_number += 1;
+                    self.rows = read_items(&self.buffer_pool, &self.rel, self.page_number)?
+                        .into_iter()
+                        .enumerate()
+                        .map(|(index, item)| (index as u16 + 1, item))
+                        .collect::<Vec<_>>()
+                        .into_iter();
+                    continue;
+                }
+                None => return Ok(None),
+            };
+
+            // Values of deleted rows are skipped when reading the next live row.
+            let item = match item {
+                Some(item) => item,
+                None => continue,
+            };
+            let row = row_number(&item)?;
+            let mut values = Vec::with_capacity(self.columns.len());
+            for column in &mut self.columns {
+                for _ in self.next_row..row {
+                    column.next_value()?;
+                }
+                values.push(column.next_value()?);
+            }
+            self.next_row = row + 1;
+
+            let mut tuple = HeapTuple::from_datums(values, &self.tuple_desc)?;
+            tuple.t_self = ItemPointer {
+                page_number: self.page_number,
+                offset,
+            };
+            return Ok(Some(tuple));
+        }
+    }
+}
+
+/// Cursor over the values of a column file, in row order.
+struct ColumnCursor {
+    buffer_pool: BufferPool,
+
+    rel: Relation,
+
+    /// Page number of the current page.
+    page_number: PageNumber,
+
+    /// Values of the current page not yet returned.
+    values: IntoIter<Option<Vec<u8>>>,
+}
+
+impl ColumnCursor {
+    fn new(buffer_pool: &BufferPool, rel: Relation) -> Self {
+        Self {
+            buffer_pool: buffer_pool.clone(),
+            rel,
+            page_number: 0,
+            values: Vec::new().into_iter(),
+        }
+    }
+
+    /// Return the value of the next row.
+    fn next_value(&mut self) -> Result<NullableDatum> {
+        loop {
+            if let Some(item) = self.values.next() {
+                return match item.as_deref() {
+                    Some([0]) => Ok(None),
+                    Some([1, value @ ..]) => Ok(Some(Datum::from(value.to_vec()))),
+                    _ => bail!("invalid value on column relation {}", self.rel.rel_name),
+                };
+            }
+
+            if self.page_number >= self.buffer_pool.size_of_relation(&self.rel)? {
+                bail!("missing value on column relation {}", self.rel.rel_name);
+            }
+            self.page_number += 1;
+            self.values = read_items(&self.buffer_pool, &self.rel, self.page_number)?.into_iter();
+        }
+    }
+}
+
+/// Return the relation that store the values of the given column of the given table.
+fn column_relation(rel: &Relation, column: &Column) -> Relation {
+    open_relation(
+        column.oid,
+        rel.locator.tablespace,
+        &rel.locator.database,
+        &format!("{}_{}", rel.rel_name, column.oid),
+    )
+}
+
+fn read_metapage(buffer_pool: &BufferPool, rel: &Relation) -> Result<ColumnarMetaPage> {
+    match read_items(buffer_pool, rel, COLUMNAR_METAPAGE)?.first() {
+        Some(Some(item)) => Ok(bincode::deserialize(item)?),
+        _ => bail!("columnar relation {} is not initialized", rel.rel_name),
+    }
+}
+
+/// Return the items of the given page, None for items marked as dead.
+fn read_items(
+    buffer_pool: &BufferPool,
+    rel: &Relation,
+    page_number: PageNumber,
+) -> Result<Vec<Option<Vec<u8>>>> {
+    let buffer = buffer_pool.fetch_buffer(rel, page_number)?;
+    item_id_data_from_page(&buffer.page)?
+        .iter()
+        .map(|item_id| {
+            if item_id.is_normal() {
+                Ok(Some(value_from_page_item(&buffer.page, item_id)?))
+            } else {
+                Ok(None)
+            }
+        })
+        .collect()
+}
+
+fn row_number(item: &[u8]) -> Result<u64> {
+    Ok(u64::from_le_bytes(item.try_into()?))
+}
+
+/// Return the row number of the next row inserted on the given table, one after the row number
+/// stored on the last row directory entry.
+fn next_row_number(buffer_pool: &BufferPool, rel: &Relation) -> Result<u64> {
+    // The last page may be empty if the server crashed after extending the relation.
+    for page_number in (COLUMNAR_METAPAGE + 1..=buffer_pool.size_of_relation(rel)?).rev() {
+        let buffer = buffer_pool.fetch_buffer(rel, page_number)?;
+        // Deleted rows keep their row number on page.
+        if let Some(item_id) = item_id_data_from_page(&buffer.page)?.last() {
+            return Ok(row_number(&value_from_page_item(&buffer.page, item_id)?)? + 1);
+        }
+    }
+    Ok(0)
+}
+
+/// Append a new item at the end of the given relation, without using the pages before the given
+/// first page, extending the relation if the last page don't have enough free space.
+fn append_item(
+    buffer_pool: &BufferPool,
+    rel: &Relation,
+    first_page: PageNumber,
+    item: Vec<u8>,
+) -> Result<ItemPointer> {
+    let nblocks = buffer_pool.size_of_relation(rel)?;
+    let mut buffer = match nblocks {
+        nblocks if nblocks >= first_page => {
+            let buffer = buffer_pool.fetch_buffer(rel, nblocks)?;
+            if page_get_free_space(&buffer.page)? >= item.len() + ITEM_ID_SIZE {
+                buffer
+            } else {
+                drop(buffer);
+                freespace::extend_relation(buffer_pool, rel)?
+            }
+        }
+        _ => freespace::extend_relation(buffer_pool, rel)?,
+    };
+
+    let tid = ItemPointer {
+        page_number: buffer.page_number(),
+        offset: page_add_item(&buffer.page, &item)?,
+    };
+    wal::log_page_change(
+        buffer_pool,
+        &buffer,
+        &WalRecord::HeapInsert {
+            locator: rel.locator.as_ref().clone(),
+            page_number: buffer.page_number(),
+            data: item,
+        },
+    )?;
+    buffer.mark_dirty();
+
+    Ok(tid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        catalog::{
+            heap::table_create, pg_authid::BOOTSTRAP_SUPERUSER_ID,
+            pg_namespace::PG_PUBLIC_NAMESPACE, pg_tablespace::DEFAULTTABLESPACE_OID, pg_type,
+        },
+        initdb::init_catalogs,
+        storage::smgr::StorageManager,
+    };
+
+    fn attr(attnum: usize, attlen: i64, atttypid: Oid) -> PgAttribute {
+        PgAttribute {
+            attrelid: 20000,
+            attname: format!("a{}", attnum),
+            attnum,
+            attlen,
+            atttypid,
+            attnotnull: false,
+            attdefault: String::new(),
+        }
+    }
+
+    fn scan(buffer_pool: &BufferPool, rel: &Relation) -> Result<Vec<HeapTuple>> {
+        let mut scan = ColumnarTableAm.begin_scan(buffer_pool, rel)?;
+        let mut tuples = Vec::new();
+        while let Some(tuple) = scan.next_tuple()? {
+            tuples.push(tuple);
+        }
+        Ok(tuples)
+    }
+
+    #[test]
+    fn test_columnar_table_am() -> Result<()> {
+        let buffer_pool = BufferPool::new(50, StorageManager::in_memory());
+        init_catalogs(&buffer_pool, "tinydb", None)?;
+        let tuple_desc = TupleDesc {
+            attrs: vec![attr(1, 4, pg_type::INT_OID), attr(2, -1, pg_type::TEXT_OID)],
+        };
+        let rel = table_create(
+            &buffer_pool,
+            DEFAULTTABLESPACE_OID,
+            &5,
+            PG_PUBLIC_NAMESPACE,
+            BOOTSTRAP_SUPERUSER_ID,
+            "t",
+            20000,
+            &tuple_desc,
+            &ColumnarTableAm,
+        )?;
+
+        let total: i32 = 3000;
+        let mut tids = Vec::new();
+        for i in 0..total {
+            let text = if i % 3 == 0 {
+                None
+            } else {
+                Some(Datum::from(format!("value {}", i).into_bytes()))
+            };
+            let tuple = HeapTuple::from_datums(
+                vec![Some(Datum::from(i.to_le_bytes().to_vec())), text],
+                &tuple_desc,
+            )?;
+            tids.push(ColumnarTableAm.insert(&buffer_pool, &rel, &tuple)?);
+        }
+
+        let int = |tuple: &HeapTuple| -> Result<i32> {
+            Ok(i32::from_le_bytes(
+                tuple.get_attr(1, &tuple_desc)?.unwrap()[..].try_into()?,
+            ))
+        };
+        let tuples = scan(&buffer_pool, &rel)?;
+        assert_eq!(tuples.len(), total as usize);
+        assert_eq!(int(&tuples[10])?, 10);
+        assert_eq!(tuples[9].get_attr(2, &tuple_desc)?, None);
+        assert_eq!(
+            tuples[2999].get_attr(2, &tuple_desc)?,
+            Some(Datum::from(b"value 2999".to_vec()))
+        );
+        assert_eq!(tuples[2999].t_self, tids[2999]);
+
+        let tuple = ColumnarTableAm
+            .fetch(&buffer_pool, &rel, &tids[1500])?
+            .unwrap();
+        assert_eq!(int(&tuple)?, 1500);
+
+        for tid in &tids[1..2999] {
+            ColumnarTableAm.delete(&buffer_pool, &rel, tid)?;
+        }
+        assert!(ColumnarTableAm
+            .fetch(&buffer_pool, &rel, &tids[1500])?
+            .is_none());
+        let tuples = scan(&buffer_pool, &rel)?;
+        assert_eq!(
+            tuples.iter().map(int).collect::<Result<Vec<_>>>()?,
+            vec![0, 2999]
+        );
+
+        // Deleted rows keep their row numbers.
+        let tuple = HeapTuple::from_datums(
+            vec![Some(Datum::from(42i32.to_le_bytes().to_vec())), None],
+            &tuple_desc,
+        )?;
+        ColumnarTableAm.insert(&buffer_pool, &rel, &tuple)?;
+        let tuples = scan(&buffer_pool, &rel)?;
+        assert_eq!(tuples.len(), 3);
+        assert_eq!(int(&tuples[2])?, 42);
+
+        Ok(())
+    }
+}
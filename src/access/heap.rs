@@ -1,30 +1,215 @@
 use std::vec::IntoIter;
 
 use crate::storage;
-use crate::storage::buffer::Buffer;
+use crate::storage::buffer::{BufferAccessStrategy, BufferGuard, Prefetch};
 use crate::{
+    catalog::{self, index::index_insert_tuple},
     relation::Relation,
+    sql::error::{PgError, SqlState},
     storage::{
         freespace,
-        page::{page_add_item, ItemId},
-        BufferPool,
+        page::{
+            page_add_item, page_get_free_space, page_get_item_count, page_set_item_flags, ItemId,
+            ItemIdFlags, ItemPointer, OffsetNumber, FIRST_OFFSET_NUMBER, ITEM_ID_SIZE, LP_DEAD,
+            LP_UNUSED, PAGE_HEADER_SIZE,
+        },
+        wal::{self, WalRecord},
+        BufferPool, PageNumber, PAGE_SIZE,
     },
+    FIRST_NORMAL_OBJECT_ID,
 };
-use anyhow::Result;
+use anyhow::{bail, Result};
+use bytes::Bytes;
 
-use super::heaptuple::HeapTuple;
+use super::{heaptuple::HeapTuple, tableam::TableScan};
 
-/// Insert a new tuple into a heap page of the given relation.
-pub fn heap_insert(buffer_pool: &BufferPool, rel: &Relation, tuple: &HeapTuple) -> Result<()> {
-    let buffer = freespace::get_page_with_free_space(buffer_pool, rel)?;
+/// Maximum size of an encoded tuple, so it fits with its line pointer on an empty page. Tuples
+/// are never split across pages.
+pub const MAX_HEAP_TUPLE_SIZE: usize = PAGE_SIZE - PAGE_HEADER_SIZE - ITEM_ID_SIZE;
 
-    page_add_item(&buffer.page, &tuple.encode()?)?;
+/// Return an error if an item of the given size, stored on a heap page, is larger than
+/// MAX_HEAP_TUPLE_SIZE.
+pub fn check_heap_tuple_size(size: usize) -> Result<()> {
+    if size > MAX_HEAP_TUPLE_SIZE {
+        bail!(PgError::new(
+            SqlState::ProgramLimitExceeded,
+            format!(
+                "row is too big: size {}, maximum size {}",
+                size, MAX_HEAP_TUPLE_SIZE
+            )
+        ));
+    }
+    Ok(())
+}
+
+/// Insert a new tuple into a heap page of the given relation and on all indexes of the relation.
+///
+/// Return the physical location of the new tuple.
+pub fn heap_insert(
+    buffer_pool: &BufferPool,
+    rel: &Relation,
+    tuple: &HeapTuple,
+) -> Result<ItemPointer> {
+    let tids = heap_multi_insert(buffer_pool, rel, std::slice::from_ref(tuple))?;
+    Ok(tids[0])
+}
+
+/// Insert the given tuples into heap pages of the given relation and on all indexes of the
+/// relation.
+///
+/// Each page is filled with as many tuples as fit on it before the page is unpinned, so the free
+/// space map is searched once per page instead of once per tuple. All tuples are checked to fit
+/// on a page before any of them is inserted.
+///
+/// Return the physical location of each tuple, in the same order of the given tuples.
+pub fn heap_multi_insert(
+    buffer_pool: &BufferPool,
+    rel: &Relation,
+    tuples: &[HeapTuple],
+) -> Result<Vec<ItemPointer>> {
+    let items = tuples
+        .iter()
+        .map(|tuple| {
+            let data = tuple.encode()?;
+            check_heap_tuple_size(data.len())?;
+            Ok(data)
+        })
+        .collect::<Result<Vec<_>>>()?;
 
-    buffer_pool.unpin_buffer(&buffer, true)?;
+    let mut tids = Vec::with_capacity(items.len());
+    let mut items = items.into_iter().peekable();
+    while let Some(item) = items.next() {
+        // The tuple requires space for its data and line pointer. The relation is extended if no
+        // page has enough free space.
+        let mut buffer =
+            freespace::get_page_with_free_space(buffer_pool, rel, item.len() + ITEM_ID_SIZE)?;
+
+        let mut next = Some(item);
+        while let Some(data) = next {
+            tids.push(ItemPointer {
+                page_number: buffer.page_number(),
+                offset: page_add_item(&buffer.page, &data)?,
+            });
+            wal::log_page_change(
+                buffer_pool,
+                &buffer,
+                &WalRecord::HeapInsert {
+                    locator: rel.locator.as_ref().clone(),
+                    page_number: buffer.page_number(),
+                    data,
+                },
+            )?;
+
+            let free_space = page_get_free_space(&buffer.page)?;
+            next = items.next_if(|item| free_space >= item.len() + ITEM_ID_SIZE);
+        }
+
+        freespace::record_page_with_free_space(
+            buffer_pool,
+            rel,
+            buffer.page_number(),
+            page_get_free_space(&buffer.page)?,
+        );
+        buffer.mark_dirty();
+    }
+
+    // System catalogs don't have indexes.
+    if rel.locator.oid >= FIRST_NORMAL_OBJECT_ID {
+        for (tuple, tid) in tuples.iter().zip(&tids) {
+            index_insert_tuple(buffer_pool, rel, tuple, tid)?;
+        }
+    }
+
+    Ok(tids)
+}
+
+/// Delete the tuple pointed by the given item pointer from a heap page of the given relation.
+///
+/// The tuple data is not physically removed from the page. If the relation has no indexes the
+/// line pointer is marked as unused, so its slot can be reused by a future insert on the same
+/// page. Otherwise index entries may still point to the tuple location, so the line pointer is
+/// marked as dead and never reused. Heap scans skip both.
+pub fn heap_delete(buffer_pool: &BufferPool, rel: &Relation, tid: &ItemPointer) -> Result<()> {
+    // System catalogs don't have indexes.
+    let has_indexes = rel.locator.oid >= FIRST_NORMAL_OBJECT_ID
+        && !catalog::get_relation_indexes(buffer_pool, &rel.locator.database, &rel.locator.oid)?
+            .is_empty();
+
+    let flags = if has_indexes { LP_DEAD } else { LP_UNUSED };
+    delete_item(buffer_pool, rel, tid, flags)
+}
+
+/// Mark the line pointer of the given item pointer as dead, so it is never reused. Used by
+/// relations that depend on the order of their line pointers.
+pub fn heap_mark_dead(buffer_pool: &BufferPool, rel: &Relation, tid: &ItemPointer) -> Result<()> {
+    delete_item(buffer_pool, rel, tid, LP_DEAD)
+}
+
+/// Change the flags of the line pointer of a deleted tuple and update the free space map with
+/// the space that can be reused on the page.
+fn delete_item(
+    buffer_pool: &BufferPool,
+    rel: &Relation,
+    tid: &ItemPointer,
+    flags: ItemIdFlags,
+) -> Result<()> {
+    let mut buffer = buffer_pool.fetch_buffer(rel, tid.page_number)?;
+
+    page_set_item_flags(&buffer.page, tid.offset, flags)?;
+    wal::log_page_change(
+        buffer_pool,
+        &buffer,
+        &WalRecord::HeapDelete {
+            locator: rel.locator.as_ref().clone(),
+            page_number: tid.page_number,
+            offset: tid.offset,
+            flags,
+        },
+    )?;
+
+    if flags == LP_UNUSED {
+        freespace::record_page_with_free_space(
+            buffer_pool,
+            rel,
+            tid.page_number,
+            page_get_free_space(&buffer.page)?,
+        );
+    }
+    buffer.mark_dirty();
 
     Ok(())
 }
 
+/// Fetch the tuple pointed by the given item pointer from a heap page of the given relation.
+///
+/// Return None if the line pointer don't exist or don't point to a live tuple.
+pub fn heap_fetch(
+    buffer_pool: &BufferPool,
+    rel: &Relation,
+    tid: &ItemPointer,
+) -> Result<Option<HeapTuple>> {
+    if tid.page_number == 0
+        || tid.page_number > buffer_pool.size_of_relation(rel)?
+        || tid.offset < FIRST_OFFSET_NUMBER
+    {
+        return Ok(None);
+    }
+
+    let buffer = buffer_pool.fetch_buffer(rel, tid.page_number)?;
+    let item_ids = storage::item_id_data_from_page(&buffer.page)?;
+    let item_id = match item_ids.get((tid.offset - FIRST_OFFSET_NUMBER) as usize) {
+        Some(item_id) if item_id.is_normal() => item_id,
+        _ => return Ok(None),
+    };
+
+    let mut tuple = HeapTuple::decode_bytes(storage::value_from_page_snapshot(
+        &buffer.page.snapshot(),
+        item_id,
+    )?)?;
+    tuple.t_self = *tid;
+    Ok(Some(tuple))
+}
+
 /// Heap tuple iterator iterate over all heap tuples of a given relation.
 ///
 /// HeapTupleIterator implements the Iterator trait.
@@ -32,56 +217,194 @@ pub struct HeapScanner {
     /// Buffer pool used to fetch buffers and get buffer page contents.
     buffer_pool: BufferPool,
 
+    /// Relation being scanned.
+    rel: Relation,
+
+    /// Number of pages of relation when the scan started.
+    nblocks: PageNumber,
+
     /// Cursor used to read item id pointers.
     item_id_iter: IntoIter<ItemId>,
 
-    /// Current buffer used to scan. None if there is no more
-    /// buffer to scan on page.
-    buffer: Option<Buffer>,
+    /// Offset number of the next item id returned by item_id_iter.
+    next_offset: OffsetNumber,
+
+    /// Page number of the current buffer being scanned.
+    page_number: PageNumber,
+
+    /// Current buffer used to scan, unpinned when the scan moves to the next page. None if there
+    /// is no more buffer to scan on page.
+    buffer: Option<BufferGuard>,
+
+    /// Snapshot of the page of the current buffer, shared by the tuples returned from the page.
+    page: Bytes,
+
+    /// Access strategy used to read pages of large relations, so the scan don't replace all
+    /// pages of the buffer pool. None if the relation is small.
+    strategy: Option<BufferAccessStrategy>,
+
+    /// Last page number requested to be read ahead.
+    prefetched: PageNumber,
+
+    /// Background read-ahead of the next pages. None if no read-ahead is running.
+    prefetch: Option<Prefetch>,
 }
 
 impl HeapScanner {
     /// Create a new heap tuple iterator over the given relation.
     pub fn new(buffer_pool: &BufferPool, rel: &Relation) -> Result<Self> {
-        // TODO: Iterate over all pages on relation
-        let buffer = buffer_pool.fetch_buffer(rel, 1)?;
+        let nblocks = buffer_pool.size_of_relation(rel)?;
 
-        let item_id_data = storage::item_id_data_from_page(&buffer.page)?;
+        // Like Postgres, only relations larger than 1/4 of buffer pool use a ring buffer.
+        let strategy = if nblocks as usize > buffer_pool.size() / 4 {
+            Some(BufferAccessStrategy::bulk_read(buffer_pool))
+        } else {
+            None
+        };
 
-        Ok(Self {
+        let mut scanner = Self {
             buffer_pool: buffer_pool.clone(),
-            buffer: Some(buffer),
-            item_id_iter: item_id_data.into_iter(),
-        })
+            rel: rel.clone(),
+            nblocks,
+            strategy,
+            buffer: None,
+            page: Bytes::new(),
+            item_id_iter: Vec::new().into_iter(),
+            next_offset: FIRST_OFFSET_NUMBER,
+            page_number: 0,
+            prefetched: 1,
+            prefetch: None,
+        };
+
+        if scanner.nblocks > 0 {
+            scanner.read_page(1)?;
+        }
+
+        Ok(scanner)
     }
 
     /// Return the next tuple from buffer if exists. If the all tuples was readed
     /// from current buffer, next_tuple will check if there is more buffer's to
     /// be readed, if not, return None.
     pub fn next_tuple(&mut self) -> Result<Option<HeapTuple>> {
-        match &self.buffer {
-            Some(buffer) => {
-                match self.item_id_iter.next() {
-                    Some(item_id) => {
-                        // Slice the raw page to get a refenrece to a tuple inside the page.
-                        let data = storage::value_from_page_item(&buffer.page, &item_id)?;
-                        let tuple = HeapTuple::decode(&data)?;
-
-                        Ok(Some(tuple))
+        loop {
+            if self.buffer.is_none() {
+                // There is no more buffer's to scan.
+                return Ok(None);
+            }
+
+            match self.item_id_iter.next() {
+                Some(item_id) => {
+                    let offset = self.next_offset;
+                    self.next_offset += 1;
+
+                    // Skip line pointers that don't point to a live tuple.
+                    if !item_id.is_normal() {
+                        continue;
                     }
-                    None => {
-                        // All item data pointers was readed, unpin the buffer
-                        // and return None.
-                        //
-                        // TODO: Check if there is more buffers to read.
-                        self.buffer_pool
-                            .unpin_buffer(&buffer, false /* is_dirty*/)?;
-                        return Ok(None);
+
+                    // Slice the page snapshot to get a reference to a tuple inside the page,
+                    // without copying the tuple.
+                    let mut tuple = HeapTuple::decode_bytes(storage::value_from_page_snapshot(
+                        &self.page, &item_id,
+                    )?)?;
+                    tuple.t_self = ItemPointer {
+                        page_number: self.page_number,
+                        offset,
+                    };
+
+                    return Ok(Some(tuple));
+                }
+                None => {
+                    // All item data pointers was readed, unpin the buffer
+                    // and move to the next page if exists.
+                    self.buffer = None;
+
+                    if self.page_number < self.nblocks {
+                        self.read_page(self.page_number + 1)?;
                     }
                 }
             }
-            // There is no more buffer's to scan.
-            None => Ok(None),
         }
     }
+
+    /// Fetch the given page number of relation to be scanned.
+    fn read_page(&mut self, page_number: PageNumber) -> Result<()> {
+        self.read_ahead(page_number);
+
+        let buffer = match &mut self.strategy {
+            Some(strategy) => {
+                self.buffer_pool
+                    .fetch_buffer_with_strategy(&self.rel, page_number, strategy)?
+            }
+            None => self.buffer_pool.fetch_buffer(&self.rel, page_number)?,
+        };
+
+        // The snapshot is taken after the line pointers are read, so it has the data of all of
+        // the items. Pages without live tuples are skipped without reading their line pointers.
+        let item_id_data = if page_get_item_count(&buffer.page)? == 0 {
+            Vec::new()
+        } else {
+            storage::item_id_data_from_page(&buffer.page)?
+        };
+        self.page = buffer.page.snapshot();
+
+        self.item_id_iter = item_id_data.into_iter();
+        self.next_offset = FIRST_OFFSET_NUMBER;
+        self.page_number = page_number;
+        self.buffer = Some(buffer);
+
+        Ok(())
+    }
+
+    /// Read ahead the pages after the given page number if the scan is half way through the pages
+    /// being read ahead, so the next pages are already in memory when the scan reach them.
+    fn read_ahead(&mut self, page_number: PageNumber) {
+        let depth = self.buffer_pool.prefetch_depth() as PageNumber;
+        if depth == 0
+            || self.prefetched >= self.nblocks
+            || page_number + depth / 2 < self.prefetched
+        {
+            return;
+        }
+
+        // Only one read-ahead is running at a time, the scan reads the pages by itself while the
+        // previous one is still in flight.
+        if self
+            .prefetch
+            .as_ref()
+            .is_some_and(|prefetch| !prefetch.is_finished())
+        {
+            return;
+        }
+
+        let last = self.nblocks.min(page_number + depth);
+        if let Some(prefetch) = self
+            .buffer_pool
+            .prefetch(&self.rel, self.prefetched + 1..=last)
+        {
+            self.prefetch = Some(prefetch);
+            self.prefetched = last;
+        }
+    }
+
+    /// Wait until the running read-ahead, if any, is finished.
+    fn wait_prefetch(&mut self) {
+        if let Some(prefetch) = self.prefetch.take() {
+            prefetch.wait();
+        }
+    }
+}
+
+impl TableScan for HeapScanner {
+    fn next_tuple(&mut self) -> Result<Option<HeapTuple>> {
+        HeapScanner::next_tuple(self)
+    }
+}
+
+impl Drop for HeapScanner {
+    /// Wait for the running read-ahead, so the buffer pool is not used after the scan is done.
+    fn drop(&mut self) {
+        self.wait_prefetch();
+    }
 }
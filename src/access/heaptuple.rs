@@ -1,20 +1,36 @@
-use anyhow::{anyhow, Result};
-use std::mem::size_of;
+//! Heap tuples are stored on disk with an explicit layout, independent of how values are
+//! represented in memory:
+//!
+//! - The fixed header fields, stored as little-endian u16 values.
+//! - The NULL bitmap, with one bit per attribute set for NULL values. The bitmap is only stored
+//!   if the tuple has NULL values.
+//! - The attribute values, one after the other without alignment padding. NULL values are not
+//!   stored. Fixed-width values are stored with their raw little-endian bytes and varlena values
+//!   with a little-endian u32 length header followed by the value bytes.
+//!
+//! Catalog rows use the same layout, so they can also be read using get_attr.
 
-use serde::{Deserialize, Serialize};
+use anyhow::{anyhow, bail, Result};
+use std::{convert::TryInto, mem::size_of};
 
-use crate::{catalog::pg_attribute::PgAttribute, sql::encode::Varlena, Datum, NullableDatum};
+use bytes::Bytes;
+
+use crate::{catalog::pg_attribute::PgAttribute, storage::page::ItemPointer, Datum, NullableDatum};
 
 /// Represents the size of a heap header tuple.
-pub const HEAP_TUPLE_HEADER_SIZE: usize = size_of::<HeapTupleHeaderFields>();
+pub const HEAP_TUPLE_HEADER_SIZE: usize = 3 * size_of::<u16>();
+
+/// Size of the length header of varlena values.
+pub const VARLENA_HEADER_SIZE: usize = size_of::<u32>();
 
 /// Bit flag stored on t_infomask informing if a tuple has null values.
-const HEAP_HASNULL: u16 = 0x0001;
+pub const HEAP_HASNULL: u16 = 0x0001;
 
 /// Bit flag stored on t_infomask informing if a tuple has variable-width attribute(s).
 const HEAP_HASVARWIDTH: u16 = 0x0002;
 
 /// Describe the structure of tuples. Basically it holds the columns of tables.
+#[derive(Clone)]
 pub struct TupleDesc {
     /// Columns of table.
     pub attrs: Vec<PgAttribute>,
@@ -27,12 +43,12 @@ impl Default for TupleDesc {
 }
 
 /// Hold all fields that is writen on heap tuple header section on disk.
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Debug)]
 pub struct HeapTupleHeaderFields {
     /// Varios bit flags.
     pub t_infomask: u16,
 
-    /// Number of attributes.
+    /// Number of attributes, including NULL attributes.
     pub t_nattrs: u16,
 
     /// Offset to user data.
@@ -56,11 +72,160 @@ pub struct HeapTupleHeader {
     /// Fixed heap tuple fields.
     pub fields: HeapTupleHeaderFields,
 
-    /// Bitmap of NULLs.
+    /// Bitmap of NULLs, with exactly one bit for each of the t_nattrs attributes. The bit of
+    /// the attribute number n is the bit n % 8 of the byte n / 8, starting at 0 for the first
+    /// attribute, and is set if the attribute value is NULL.
     ///
-    /// The bitmap is *not* stored if t_infomask shows that there
-    /// are no nulls in the tuple.
-    pub t_bits: Vec<bool>,
+    /// The bitmap is empty, and is *not* stored, if t_infomask shows that there are no nulls
+    /// in the tuple.
+    pub t_bits: Vec<u8>,
+}
+
+impl HeapTupleHeaderFields {
+    /// Return the on-disk representation of the header fields.
+    fn encode(&self) -> [u8; HEAP_TUPLE_HEADER_SIZE] {
+        let mut fields = [0; HEAP_TUPLE_HEADER_SIZE];
+        fields[0..2].copy_from_slice(&self.t_infomask.to_le_bytes());
+        fields[2..4].copy_from_slice(&self.t_nattrs.to_le_bytes());
+        fields[4..6].copy_from_slice(&self.t_hoff.to_le_bytes());
+        fields
+    }
+
+    /// Decode the header fields from the start of a raw tuple.
+    fn decode(tuple: &[u8]) -> Result<Self> {
+        if tuple.len() < HEAP_TUPLE_HEADER_SIZE {
+            bail!("heap tuple too short: {} bytes", tuple.len());
+        }
+        let field = |offset: usize| u16::from_le_bytes([tuple[offset], tuple[offset + 1]]);
+        Ok(Self {
+            t_infomask: field(0),
+            t_nattrs: field(2),
+            t_hoff: field(4),
+        })
+    }
+}
+
+/// Return the size in bytes of the NULL bitmap of a tuple with the given number of attributes.
+fn null_bitmap_size(nattrs: usize) -> usize {
+    nattrs.div_ceil(8)
+}
+
+/// Values that can be stored as the data of a heap tuple using the on-disk attribute layout,
+/// e.g. catalog rows.
+pub trait HeapTupleData: Sized {
+    /// Append the attributes of the value.
+    fn encode(&self, writer: &mut TupleDataWriter);
+
+    /// Decode a value from the attributes of a tuple data.
+    fn decode(reader: &mut TupleDataReader<'_>) -> Result<Self>;
+}
+
+/// A single integer attribute.
+impl HeapTupleData for i32 {
+    fn encode(&self, writer: &mut TupleDataWriter) {
+        writer.put_i32(*self);
+    }
+
+    fn decode(reader: &mut TupleDataReader<'_>) -> Result<Self> {
+        reader.get_i32()
+    }
+}
+
+/// Writer of tuple data attributes using the on-disk layout. NULL values are not supported.
+#[derive(Default)]
+pub struct TupleDataWriter {
+    data: Vec<u8>,
+}
+
+impl TupleDataWriter {
+    pub fn put_i32(&mut self, value: i32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn put_u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn put_i64(&mut self, value: i64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn put_bool(&mut self, value: bool) {
+        self.data.push(value as u8);
+    }
+
+    /// Append a varlena string value.
+    pub fn put_str(&mut self, value: &str) {
+        put_varlena(&mut self.data, value.as_bytes());
+    }
+}
+
+/// Reader of tuple data attributes written by TupleDataWriter.
+pub struct TupleDataReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> TupleDataReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    /// Return the next size bytes of the data.
+    fn take(&mut self, size: usize) -> Result<&'a [u8]> {
+        if self.data.len() < size {
+            bail!("unexpected end of tuple data");
+        }
+        let (value, data) = self.data.split_at(size);
+        self.data = data;
+        Ok(value)
+    }
+
+    /// Return true if all attributes were read.
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn get_i32(&mut self) -> Result<i32> {
+        Ok(i32::from_le_bytes(self.take(size_of::<i32>())?.try_into()?))
+    }
+
+    pub fn get_u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(size_of::<u64>())?.try_into()?))
+    }
+
+    pub fn get_i64(&mut self) -> Result<i64> {
+        Ok(i64::from_le_bytes(self.take(size_of::<i64>())?.try_into()?))
+    }
+
+    pub fn get_bool(&mut self) -> Result<bool> {
+        Ok(self.take(size_of::<u8>())?[0] != 0)
+    }
+
+    /// Read a varlena string value.
+    pub fn get_str(&mut self) -> Result<String> {
+        let len = varlena_size(self.data)? - VARLENA_HEADER_SIZE;
+        self.take(VARLENA_HEADER_SIZE)?;
+        Ok(String::from_utf8(self.take(len)?.to_vec())?)
+    }
+}
+
+/// Append a varlena value with the given bytes.
+fn put_varlena(data: &mut Vec<u8>, value: &[u8]) {
+    data.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    data.extend_from_slice(value);
+}
+
+/// Return the total size, including the length header, of the varlena value at the start of the
+/// given data.
+fn varlena_size(data: &[u8]) -> Result<usize> {
+    let header = data
+        .get(..VARLENA_HEADER_SIZE)
+        .ok_or_else(|| anyhow!("unexpected end of tuple data"))?;
+    let size = VARLENA_HEADER_SIZE + u32::from_le_bytes(header.try_into()?) as usize;
+    if data.len() < size {
+        bail!("unexpected end of tuple data");
+    }
+    Ok(size)
 }
 
 /// HeapTuple is an in-memory data structure that points to a tuple on some page.
@@ -72,8 +237,13 @@ pub struct HeapTuple {
     /// Actual heap tuple data (header NOT included).
     ///
     // A raw list of bytes is used here so other componets can deserialize an
-    // entire tuple to a catalog struct.
-    pub data: Vec<u8>,
+    // entire tuple to a catalog struct. Tuples fetched from a page share the bytes of a page
+    // snapshot, so the datums of the tuple are slices of the page without any copy.
+    pub data: Bytes,
+
+    /// Pointer to this tuple on relation. Only valid for tuples that was
+    /// fetched from a page.
+    pub t_self: ItemPointer,
 }
 
 impl HeapTupleHeader {
@@ -86,25 +256,60 @@ impl HeapTupleHeader {
     pub fn has_var_width(&self) -> bool {
         self.fields.t_infomask & HEAP_HASVARWIDTH != 0
     }
+
+    /// Return true if the attribute of the given number, starting at 1, is NULL. Attributes that
+    /// are not on the bitmap are not NULL, e.g. on tuples without NULL values.
+    pub fn attr_is_null(&self, attnum: usize) -> bool {
+        let attr = attnum.wrapping_sub(1);
+        match self.t_bits.get(attr / 8) {
+            Some(byte) => attr < self.fields.t_nattrs as usize && byte & (1 << (attr % 8)) != 0,
+            None => false,
+        }
+    }
+
+    /// Mark the attribute of the given number, starting at 1, as NULL. The bitmap must have
+    /// been allocated for all t_nattrs attributes.
+    fn set_attr_null(&mut self, attnum: usize) {
+        let attr = attnum - 1;
+        self.t_bits[attr / 8] |= 1 << (attr % 8);
+        self.fields.t_infomask |= HEAP_HASNULL;
+    }
 }
 
 impl HeapTuple {
     /// Create a new heap tuple with the given data and default header values.
-    pub fn with_default_header<T>(data: T) -> Result<Self>
-    where
-        T: serde::Serialize,
-    {
-        Ok(Self {
+    pub fn with_default_header<T: HeapTupleData>(data: &T) -> Result<Self> {
+        let mut writer = TupleDataWriter::default();
+        data.encode(&mut writer);
+        Ok(Self::from_writer(writer))
+    }
+
+    /// Create a new heap tuple with the attributes appended on the given writer and default
+    /// header values.
+    pub fn from_writer(writer: TupleDataWriter) -> Self {
+        Self {
             header: HeapTupleHeader::default(),
-            data: bincode::serialize(&data)?,
-        })
+            data: Bytes::from(writer.data),
+            t_self: ItemPointer::default(),
+        }
+    }
+
+    /// Decode the data of a heap tuple created by with_default_header.
+    pub fn decode_data<T: HeapTupleData>(&self) -> Result<T> {
+        T::decode(&mut TupleDataReader::new(&self.data))
     }
+
     /// Construct a heap tuple for the given vector of possible datum values.
     ///
     /// The tuple desc attributes should be aligned with datum values index, wich
-    /// means that values[i] should references tuple_desc.attrs[i].
+    /// means that values[i] should references tuple_desc.attrs[i]. Values of varlena attributes
+    /// are the raw value bytes, the same representation returned by get_attr.
     pub fn from_datums(values: Vec<NullableDatum>, tuple_desc: &TupleDesc) -> Result<Self> {
         let mut heaptuple = Self::default();
+        heaptuple.header.fields.t_nattrs = values.len() as u16;
+        heaptuple.header.t_bits = vec![0; null_bitmap_size(values.len())];
+
+        let mut data = Vec::new();
         for (attrnum, datum) in values.iter().enumerate() {
             let attr = tuple_desc
                 .attrs
@@ -113,57 +318,65 @@ impl HeapTuple {
 
             match datum {
                 Some(datum) => {
-                    if attr.attlen < 0 {
+                    if attr.is_varlena() {
                         // Add HEAP_HASVARWIDTH flag on tuple header to inform that
                         // the tuple has varlena fields.
                         heaptuple.header.fields.t_infomask |= HEAP_HASVARWIDTH;
+                        put_varlena(&mut data, datum);
+                    } else {
+                        data.extend_from_slice(datum);
                     }
-
-                    heaptuple.header.t_bits.push(false);
-                    heaptuple.header.fields.t_nattrs += 1;
-
-                    heaptuple.data.extend_from_slice(datum);
-                }
-                None => {
-                    // Add HEAP_HASNULL bit flag on heap header and add true on t_bits
-                    // informing that the value of the attr is NULL.
-                    heaptuple.header.fields.t_infomask |= HEAP_HASNULL;
-                    heaptuple.header.t_bits.push(true);
                 }
+                None => heaptuple.header.set_attr_null(attrnum + 1),
             }
         }
 
         if heaptuple.header.has_nulls() {
-            // TODO: Find a better way to compute t_hoff
-            let t_bits_data = bincode::serialize(&heaptuple.header.t_bits)?;
-            heaptuple.header.fields.t_hoff += t_bits_data.len() as u16;
+            heaptuple.header.fields.t_hoff += heaptuple.header.t_bits.len() as u16;
+        } else {
+            heaptuple.header.t_bits.clear();
         }
+        heaptuple.data = Bytes::from(data);
         Ok(heaptuple)
     }
 
-    /// Create a new heap tuple from raw tuple bytes.
+    /// Create a new heap tuple from a copy of the given raw tuple bytes.
     pub fn decode(tuple: &[u8]) -> Result<Self> {
+        Self::decode_bytes(Bytes::copy_from_slice(tuple))
+    }
+
+    /// Create a new heap tuple from raw tuple bytes, sharing the bytes with the tuple data.
+    pub fn decode_bytes(tuple: Bytes) -> Result<Self> {
         let mut header = HeapTupleHeader {
-            fields: bincode::deserialize(&tuple[0..HEAP_TUPLE_HEADER_SIZE])?,
+            fields: HeapTupleHeaderFields::decode(&tuple)?,
             t_bits: Vec::new(),
         };
         let t_hoff = header.fields.t_hoff as usize;
+        if t_hoff < HEAP_TUPLE_HEADER_SIZE || t_hoff > tuple.len() {
+            bail!("invalid heap tuple header offset {}", t_hoff);
+        }
 
         if header.has_nulls() {
-            header.t_bits = bincode::deserialize(&tuple[HEAP_TUPLE_HEADER_SIZE..t_hoff])?;
+            let bitmap = &tuple[HEAP_TUPLE_HEADER_SIZE..t_hoff];
+            let nattrs = header.fields.t_nattrs as usize;
+            if bitmap.len() < null_bitmap_size(nattrs) {
+                bail!("heap tuple NULL bitmap too short for {} attributes", nattrs);
+            }
+            header.t_bits = bitmap[..null_bitmap_size(nattrs)].to_vec();
         }
 
         Ok(Self {
             header,
-            data: tuple[t_hoff..].to_vec(),
+            data: tuple.slice(t_hoff..),
+            t_self: ItemPointer::default(),
         })
     }
 
     /// Return the heap tuple representation in raw bytes.
     pub fn encode(&self) -> Result<Vec<u8>> {
-        let mut tuple = bincode::serialize(&self.header.fields)?.to_vec();
+        let mut tuple = self.header.fields.encode().to_vec();
         if self.header.has_nulls() {
-            bincode::serialize_into(&mut tuple, &self.header.t_bits)?;
+            tuple.extend_from_slice(&self.header.t_bits);
         }
 
         tuple.extend_from_slice(&self.data);
@@ -192,28 +405,34 @@ impl HeapTuple {
                 continue;
             }
 
-            if attr.attlen > 0 {
+            if !attr.is_varlena() {
                 off_end += attr.attlen as usize;
             } else {
                 // If we don't know the size of attribute value we
-                // decode a varlena struct to get the actual size of
+                // read the varlena header to get the actual size of
                 // field.
-                let varlena = bincode::deserialize::<Varlena>(&self.data[off_start..])?;
+                let size = varlena_size(self.data.get(off_start..).unwrap_or_default())?;
 
                 // Return the varlena value if its the field that was fetched
                 if attr.attnum == attnum {
-                    return Ok(Some(Datum::from(varlena.v_data)));
+                    return Ok(Some(Datum::from(
+                        self.data
+                            .slice(off_start + VARLENA_HEADER_SIZE..off_start + size),
+                    )));
                 }
 
                 // Otherwise, just sum the total size of varlena tuple field.
-                off_end += varlena.len();
+                off_end += size;
             }
 
             if attr.attnum == attnum {
                 if self.attr_is_null(attr.attnum) {
                     return Ok(None);
                 }
-                return Ok(Some(Datum::from(self.data[off_start..off_end].to_vec())));
+                if off_end > self.data.len() {
+                    bail!("unexpected end of tuple data");
+                }
+                return Ok(Some(Datum::from(self.data.slice(off_start..off_end))));
             }
 
             off_start = off_end;
@@ -222,10 +441,441 @@ impl HeapTuple {
         Ok(None)
     }
 
+    /// Decode the used attributes of the tuple, on the same order of the given tuple description,
+    /// or all of its attributes if used_attrs is None. Attributes that are not used are returned
+    /// as NULL, like NULL values.
+    ///
+    /// The tuple data is walked only once, so deforming all attributes of a tuple is linear on
+    /// the number of attributes, while calling get_attr for each attribute walks all attributes
+    /// before it again. The attributes after the last used attribute are not walked.
+    ///
+    /// The values share the bytes of the tuple data, so no value is copied.
+    pub fn deform(
+        &self,
+        tuple_desc: &TupleDesc,
+        used_attrs: Option<&[bool]>,
+    ) -> Result<Vec<NullableDatum>> {
+        let is_used = |index: usize| used_attrs.is_none_or(|used_attrs| used_attrs[index]);
+        let last_used = (0..tuple_desc.attrs.len())
+            .rev()
+            .find(|index| is_used(*index));
+
+        let mut values = Vec::with_capacity(tuple_desc.attrs.len());
+        let mut offset = 0;
+        for (index, attr) in tuple_desc.attrs.iter().enumerate() {
+            if Some(index) > last_used || self.attr_is_null(attr.attnum) {
+                values.push(None);
+                continue;
+            }
+
+            let (start, end) = if attr.is_varlena() {
+                let size = varlena_size(self.data.get(offset..).unwrap_or_default())?;
+                (offset + VARLENA_HEADER_SIZE, offset + size)
+            } else {
+                (offset, offset + attr.attlen as usize)
+            };
+            if is_used(index) {
+                if end > self.data.len() {
+                    bail!("unexpected end of tuple data");
+                }
+                values.push(Some(Datum::from(self.data.slice(start..end))));
+            } else {
+                values.push(None);
+            }
+            offset = end;
+        }
+
+        Ok(values)
+    }
+
     /// Return true if the given attnum on tuple has a NULL value.
     fn attr_is_null(&self, attnum: usize) -> bool {
-        self.header.has_nulls()
-            && attnum <= self.header.t_bits.len()
-            && self.header.t_bits[attnum - 1]
+        self.header.attr_is_null(attnum)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        access::tableam::HEAP_TABLE_AM_OID,
+        catalog::{pg_class::PgClass, pg_namespace::PG_PUBLIC_NAMESPACE, pg_type},
+        expr::ScalarValue,
+        sql::encode::encode_scalar,
+        Oid,
+    };
+    use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+    fn tuple_desc() -> TupleDesc {
+        let attr = |attnum: usize, attlen: i64, atttypid| PgAttribute {
+            attrelid: 1,
+            attname: format!("a{}", attnum),
+            attnum,
+            attlen,
+            atttypid,
+            attnotnull: false,
+            attdefault: String::new(),
+        };
+        TupleDesc {
+            attrs: vec![
+                attr(1, 4, pg_type::INT_OID),
+                attr(2, -1, pg_type::TEXT_OID),
+                attr(3, 1, pg_type::BOOL_OID),
+                // Varchar values are varlena even with a maximum length.
+                attr(4, 10, pg_type::VARCHAR_OID),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_heap_tuple_layout() -> Result<()> {
+        let tuple_desc = tuple_desc();
+        let values = vec![
+            Some(Datum::from(42_i32.to_le_bytes().to_vec())),
+            Some(Datum::from(b"tiny".to_vec())),
+            None,
+            Some(Datum::from(b"db".to_vec())),
+        ];
+        let encoded = HeapTuple::from_datums(values.clone(), &tuple_desc)?.encode()?;
+
+        #[rustfmt::skip]
+        let expected: Vec<u8> = vec![
+            // t_infomask, t_nattrs and t_hoff.
+            0x03, 0, 4, 0, 7, 0,
+            // NULL bitmap with the third attribute set.
+            0b0000_0100,
+            42, 0, 0, 0,
+            4, 0, 0, 0, b't', b'i', b'n', b'y',
+            2, 0, 0, 0, b'd', b'b',
+        ];
+        assert_eq!(encoded, expected);
+
+        let tuple = HeapTuple::decode(&encoded)?;
+        for (attnum, value) in values.iter().enumerate() {
+            assert_eq!(&tuple.get_attr(attnum + 1, &tuple_desc)?, value);
+        }
+
+        // Truncated tuples are rejected instead of returning invalid values.
+        let tuple = HeapTuple::decode(&encoded[..encoded.len() - 1])?;
+        assert!(tuple.get_attr(4, &tuple_desc).is_err());
+        assert!(HeapTuple::decode(&encoded[..3]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_null_bitmap() -> Result<()> {
+        // Attributes cross the byte boundaries of the bitmap.
+        let attrs = (1..=19)
+            .map(|attnum| match attnum % 2 {
+                0 => (attnum, 4, pg_type::INT_OID),
+                _ => (attnum, -1, pg_type::TEXT_OID),
+            })
+            .map(|(attnum, attlen, atttypid)| PgAttribute {
+                attrelid: 1,
+                attname: format!("a{}", attnum),
+                attnum,
+                attlen,
+                atttypid,
+                attnotnull: false,
+                attdefault: String::new(),
+            })
+            .collect::<Vec<_>>();
+        let tuple_desc = TupleDesc { attrs };
+        let value = |attnum: usize| match attnum % 2 {
+            0 => Datum::from((attnum as i32).to_le_bytes().to_vec()),
+            _ => Datum::from(format!("value {}", attnum).into_bytes()),
+        };
+
+        let patterns: Vec<Box<dyn Fn(usize) -> bool>> = vec![
+            Box::new(|_| false),
+            Box::new(|_| true),
+            Box::new(|attnum| attnum == 1),
+            Box::new(|attnum| attnum == 19),
+            Box::new(|attnum| attnum % 2 == 0),
+            Box::new(|attnum| attnum % 3 == 1),
+            Box::new(|attnum| attnum == 8 || attnum == 9 || attnum == 17),
+        ];
+        for is_null in patterns {
+            let values = (1..=19)
+                .map(|attnum| Some(value(attnum)).filter(|_| !is_null(attnum)))
+                .collect::<Vec<_>>();
+            let tuple = HeapTuple::from_datums(values.clone(), &tuple_desc)?;
+            let encoded = tuple.encode()?;
+
+            let nulls = (1..=19).filter(|attnum| is_null(*attnum)).count();
+            if nulls == 0 {
+                assert!(!tuple.header.has_nulls());
+                assert_eq!(tuple.header.fields.t_hoff as usize, HEAP_TUPLE_HEADER_SIZE);
+            } else {
+                // Exactly one bit is stored for each attribute.
+                assert!(tuple.header.has_nulls());
+                assert_eq!(tuple.header.t_bits.len(), 3);
+                assert_eq!(
+                    tuple.header.fields.t_hoff as usize,
+                    HEAP_TUPLE_HEADER_SIZE + 3
+                );
+                let bits = tuple
+                    .header
+                    .t_bits
+                    .iter()
+                    .map(|byte| byte.count_ones() as usize)
+                    .sum::<usize>();
+                assert_eq!(bits, nulls);
+            }
+
+            let decoded = HeapTuple::decode(&encoded)?;
+            assert_eq!(decoded.encode()?, encoded);
+            assert_eq!(decoded.deform(&tuple_desc, None)?, values);
+            for (attnum, value) in (1..=19).zip(&values) {
+                assert_eq!(decoded.header.attr_is_null(attnum), is_null(attnum));
+                assert_eq!(&decoded.get_attr(attnum, &tuple_desc)?, value);
+            }
+
+            // Attributes after the last one of the tuple are not on the bitmap.
+            assert!(!decoded.header.attr_is_null(20));
+            assert!(!decoded.header.attr_is_null(0));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_deform() -> Result<()> {
+        let tuple_desc = tuple_desc();
+        let values = vec![
+            None,
+            Some(Datum::from(b"tiny".to_vec())),
+            Some(Datum::from(vec![1])),
+            None,
+        ];
+        let tuple =
+            HeapTuple::decode(&HeapTuple::from_datums(values.clone(), &tuple_desc)?.encode()?)?;
+        assert_eq!(tuple.deform(&tuple_desc, None)?, values);
+        for (attnum, value) in values.iter().enumerate() {
+            assert_eq!(&tuple.get_attr(attnum + 1, &tuple_desc)?, value);
+        }
+
+        // Attributes that are not used are returned as NULL.
+        assert_eq!(
+            tuple.deform(&tuple_desc, Some(&[true, false, true, true]))?,
+            vec![None, None, Some(Datum::from(vec![1])), None]
+        );
+        assert_eq!(tuple.deform(&tuple_desc, Some(&[false; 4]))?, vec![None; 4]);
+
+        // Truncated tuples are rejected, unless the truncated attributes are not used.
+        let encoded = HeapTuple::from_datums(values, &tuple_desc)?.encode()?;
+        let tuple = HeapTuple::decode(&encoded[..encoded.len() - 1])?;
+        assert!(tuple.deform(&tuple_desc, None).is_err());
+        assert_eq!(
+            tuple.deform(&tuple_desc, Some(&[true, true, false, false]))?,
+            vec![None, Some(Datum::from(b"tiny".to_vec())), None, None]
+        );
+
+        Ok(())
+    }
+
+    /// Environment variable to set the seed of test_random_tuples_round_trip, e.g. to reproduce
+    /// a failure.
+    const SEED_VAR: &str = "TINYDB_TEST_SEED";
+
+    /// Types of the attributes of random tuple descriptions.
+    const RANDOM_TYPES: &[Oid] = &[
+        pg_type::INT2_OID,
+        pg_type::INT_OID,
+        pg_type::INT8_OID,
+        pg_type::FLOAT4_OID,
+        pg_type::FLOAT8_OID,
+        pg_type::BOOL_OID,
+        pg_type::DATE_OID,
+        pg_type::TIMESTAMP_OID,
+        pg_type::TEXT_OID,
+        pg_type::VARCHAR_OID,
+    ];
+
+    /// Return a tuple description with a random number of attributes of random types. Varchar
+    /// attributes have a random maximum length, or no maximum length.
+    fn random_tuple_desc(rng: &mut StdRng) -> TupleDesc {
+        let attrs = (1..=rng.gen_range(1..=40))
+            .map(|attnum| {
+                let atttypid = *RANDOM_TYPES.choose(rng).unwrap();
+                let attlen = match atttypid {
+                    pg_type::VARCHAR_OID if rng.gen_bool(0.8) => rng.gen_range(0..=300),
+                    typ => pg_type::type_len(typ),
+                };
+                PgAttribute {
+                    attrelid: 1,
+                    attname: format!("a{}", attnum),
+                    attnum,
+                    attlen,
+                    atttypid,
+                    attnotnull: false,
+                    attdefault: String::new(),
+                }
+            })
+            .collect();
+        TupleDesc { attrs }
+    }
+
+    /// Return a random value of the given attribute, or NULL. Numeric values are often the
+    /// limits of their types.
+    fn random_value(rng: &mut StdRng, attr: &PgAttribute) -> ScalarValue {
+        if rng.gen_bool(0.25) {
+            return ScalarValue::Null;
+        }
+        let limit = rng.gen_bool(0.3);
+        match attr.atttypid {
+            pg_type::INT2_OID if limit => {
+                ScalarValue::SmallInt(*[i16::MIN, -1, 0, i16::MAX].choose(rng).unwrap())
+            }
+            pg_type::INT2_OID => ScalarValue::SmallInt(rng.gen()),
+            pg_type::INT_OID if limit => {
+                ScalarValue::Int(*[i32::MIN, -1, 0, i32::MAX].choose(rng).unwrap())
+            }
+            pg_type::INT_OID => ScalarValue::Int(rng.gen()),
+            pg_type::INT8_OID if limit => {
+                ScalarValue::BigInt(*[i64::MIN, -1, 0, i64::MAX].choose(rng).unwrap())
+            }
+            pg_type::INT8_OID => ScalarValue::BigInt(rng.gen()),
+            pg_type::FLOAT4_OID if limit => ScalarValue::Float4(
+                *[f32::MIN, -0.0, f32::EPSILON, f32::MAX]
+                    .choose(rng)
+                    .unwrap(),
+            ),
+            pg_type::FLOAT4_OID => ScalarValue::Float4(rng.gen_range(-1e6..1e6)),
+            pg_type::FLOAT8_OID if limit => ScalarValue::Float8(
+                *[f64::MIN, -0.0, f64::EPSILON, f64::MAX]
+                    .choose(rng)
+                    .unwrap(),
+            ),
+            pg_type::FLOAT8_OID => ScalarValue::Float8(rng.gen_range(-1e12..1e12)),
+            pg_type::BOOL_OID => ScalarValue::Bool(rng.gen()),
+            pg_type::DATE_OID => ScalarValue::Date(rng.gen_range(-1_000_000..1_000_000)),
+            pg_type::TIMESTAMP_OID => ScalarValue::Timestamp(rng.gen()),
+            pg_type::VARCHAR_OID if attr.attlen >= 0 => {
+                let max = attr.attlen as usize;
+                let len = if limit { max } else { rng.gen_range(0..=max) };
+                ScalarValue::Varchar(random_string(rng, len))
+            }
+            _ => {
+                let len = match rng.gen_range(0..10) {
+                    0 => 0,
+                    1 => rng.gen_range(1000..5000),
+                    _ => rng.gen_range(1..64),
+                };
+                ScalarValue::Varchar(random_string(rng, len))
+            }
+        }
+    }
+
+    /// Return a random string of exactly the given length in bytes, with multibyte characters.
+    fn random_string(rng: &mut StdRng, len: usize) -> String {
+        let mut value = String::with_capacity(len);
+        while value.len() < len {
+            match rng.gen_range(0..10) {
+                0 if len - value.len() >= 2 => value.push('é'),
+                1 => value.push('\0'),
+                _ => value.push(rng.gen_range(b' '..=b'~') as char),
+            }
+        }
+        value
+    }
+
+    #[test]
+    fn test_random_tuples_round_trip() -> Result<()> {
+        let seed = match std::env::var(SEED_VAR) {
+            Ok(seed) => seed.parse()?,
+            Err(_) => rand::random(),
+        };
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        for case in 0..500 {
+            let tuple_desc = random_tuple_desc(&mut rng);
+            let scalars = tuple_desc
+                .attrs
+                .iter()
+                .map(|attr| random_value(&mut rng, attr))
+                .collect::<Vec<_>>();
+            let context = || {
+                let types = tuple_desc
+                    .attrs
+                    .iter()
+                    .map(|attr| attr.type_name())
+                    .collect::<Vec<_>>();
+                format!(
+                    "case {} of {}={} with types {:?} and values {:?}",
+                    case, SEED_VAR, seed, types, scalars
+                )
+            };
+
+            let mut values = Vec::new();
+            for (attr, value) in tuple_desc.attrs.iter().zip(&scalars) {
+                encode_scalar(&mut values, value.clone(), attr)?;
+            }
+            let encoded = HeapTuple::from_datums(values.clone(), &tuple_desc)?.encode()?;
+            let tuple = HeapTuple::decode(&encoded)?;
+
+            assert_eq!(tuple.encode()?, encoded, "{}", context());
+            assert_eq!(
+                tuple.header.has_nulls(),
+                scalars.contains(&ScalarValue::Null),
+                "{}",
+                context()
+            );
+            assert_eq!(tuple.deform(&tuple_desc, None)?, values, "{}", context());
+            for (attr, scalar) in tuple_desc.attrs.iter().zip(&scalars) {
+                let datum = tuple.get_attr(attr.attnum, &tuple_desc)?;
+                assert_eq!(
+                    &ScalarValue::from_datum(&datum, attr.atttypid)?,
+                    scalar,
+                    "attribute {} of {}",
+                    attr.attnum,
+                    context()
+                );
+            }
+
+            // Varchar values longer than the maximum length are rejected.
+            for attr in &tuple_desc.attrs {
+                if attr.atttypid == pg_type::VARCHAR_OID && attr.attlen >= 0 {
+                    let value = ScalarValue::Varchar("x".repeat(attr.attlen as usize + 1));
+                    assert!(encode_scalar(&mut Vec::new(), value, attr).is_err());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_catalog_row_data() -> Result<()> {
+        let pg_class = PgClass {
+            oid: 10_000,
+            relname: String::from("t"),
+            reltablespace: 1663,
+            relisshared: false,
+            relam: HEAP_TABLE_AM_OID,
+            relnamespace: PG_PUBLIC_NAMESPACE,
+            relowner: 20_000,
+            relacl: String::from("{20000=arwd/20000}"),
+        };
+        let tuple = HeapTuple::with_default_header(&pg_class)?;
+        let decoded = tuple.decode_data::<PgClass>()?;
+        assert_eq!(decoded.oid, pg_class.oid);
+        assert_eq!(decoded.relname, pg_class.relname);
+        assert_eq!(decoded.reltablespace, pg_class.reltablespace);
+        assert_eq!(decoded.relisshared, pg_class.relisshared);
+        assert_eq!(decoded.relam, pg_class.relam);
+        assert_eq!(decoded.relnamespace, pg_class.relnamespace);
+        assert_eq!(decoded.relowner, pg_class.relowner);
+        assert_eq!(decoded.relacl, pg_class.relacl);
+
+        // Catalog rows can also be read using the catalog tuple description.
+        let tuple = HeapTuple::decode(&tuple.encode()?)?;
+        assert_eq!(
+            tuple.get_attr(2, &PgClass::tuple_desc())?,
+            Some(Datum::from(b"t".to_vec()))
+        );
+
+        Ok(())
     }
 }
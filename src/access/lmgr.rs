@@ -0,0 +1,531 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+
+use crate::{
+    catalog::{self, namespace::RelationName, pg_class::PgClass},
+    sql::error::{PgError, SqlState},
+    storage::BufferPool,
+    Oid, INVALID_OID,
+};
+
+/// Interval between checks of the cancel flag while waiting for a lock.
+const LOCK_WAIT_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Time waiting for a lock before checking for deadlocks. Most waits finish before this timeout,
+/// so the waits-for graph is only searched for waits that are probably blocked forever.
+const DEADLOCK_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Relation lock modes, from the weakest to the strongest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LockMode {
+    /// Acquired by queries that only read the relation.
+    AccessShare,
+
+    /// Acquired by statements that insert or delete tuples of the relation.
+    RowExclusive,
+
+    /// Acquired by CREATE INDEX, so the tuples of the relation don't change while the index is
+    /// built.
+    Share,
+
+    /// Acquired on unique indexes by statements that insert tuples, so only one statement at a
+    /// time checks and inserts keys of the index. It conflicts with itself, but not with
+    /// AccessShare, so scans of the index are never blocked.
+    ShareRowExclusive,
+
+    /// Acquired by DROP TABLE, so nobody else is using the relation while it is removed.
+    AccessExclusive,
+}
+
+impl LockMode {
+    /// Return true if a lock of this mode can not be granted while another owner holds a lock of
+    /// the other mode.
+    pub fn conflicts(self, other: Self) -> bool {
+        use LockMode::*;
+
+        matches!(
+            (self, other),
+            (AccessExclusive, _)
+                | (_, AccessExclusive)
+                | (RowExclusive, Share)
+                | (Share, RowExclusive)
+                | (ShareRowExclusive, RowExclusive | Share | ShareRowExclusive)
+                | (RowExclusive | Share, ShareRowExclusive)
+        )
+    }
+}
+
+impl fmt::Display for LockMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LockMode::AccessShare => write!(f, "AccessShareLock"),
+            LockMode::RowExclusive => write!(f, "RowExclusiveLock"),
+            LockMode::Share => write!(f, "ShareLock"),
+            LockMode::ShareRowExclusive => write!(f, "ShareRowExclusiveLock"),
+            LockMode::AccessExclusive => write!(f, "AccessExclusiveLock"),
+        }
+    }
+}
+
+/// Object that is locked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LockTag {
+    /// Database of the relation, INVALID_OID for shared relations.
+    pub database: Oid,
+
+    /// Oid of the relation.
+    pub relation: Oid,
+}
+
+impl LockTag {
+    /// Return the tag of the given relation of the given database.
+    pub fn relation(db_oid: &Oid, pg_class: &PgClass) -> Self {
+        Self {
+            database: if pg_class.relisshared {
+                INVALID_OID
+            } else {
+                *db_oid
+            },
+            relation: pg_class.oid,
+        }
+    }
+}
+
+/// Owner of locks, usually a connection. Locks held by an owner never conflict with new locks
+/// requested by the same owner.
+#[derive(Clone)]
+pub struct LockOwner {
+    /// Unique identifier of the owner.
+    id: u64,
+
+    /// Flag that interrupt lock waits with an error when it is set.
+    canceled: Option<Arc<AtomicBool>>,
+}
+
+impl LockOwner {
+    /// Create a new lock owner whose lock waits can not be canceled.
+    pub fn new() -> Self {
+        static NEXT_OWNER_ID: AtomicU64 = AtomicU64::new(1);
+
+        Self {
+            id: NEXT_OWNER_ID.fetch_add(1, Ordering::SeqCst),
+            canceled: None,
+        }
+    }
+
+    /// Interrupt the lock waits of this owner with an error when the given flag is set.
+    pub fn with_cancel_flag(mut self, canceled: Arc<AtomicBool>) -> Self {
+        self.canceled = Some(canceled);
+        self
+    }
+
+    /// Return an error if the owner was canceled.
+    fn check_canceled(&self) -> Result<()> {
+        if let Some(canceled) = &self.canceled {
+            if canceled.load(Ordering::SeqCst) {
+                bail!(PgError::new(
+                    SqlState::QueryCanceled,
+                    "canceling statement due to user request"
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for LockOwner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Locks granted by the lock manager, shared by all lock guards.
+#[derive(Default)]
+struct LockTable {
+    state: Mutex<LockState>,
+
+    /// Notified when any lock is released.
+    released: Condvar,
+}
+
+/// Granted and requested locks of a lock table.
+#[derive(Default)]
+struct LockState {
+    /// Owner and mode of each granted lock, by lock tag. The same owner may hold the same lock
+    /// multiple times.
+    granted: HashMap<LockTag, Vec<(u64, LockMode)>>,
+
+    /// Lock that each waiting owner requested, by owner id. An owner waits for at most one lock
+    /// at a time.
+    waiting: HashMap<u64, (LockTag, LockMode)>,
+}
+
+impl LockState {
+    /// Return the owners, other than the given owner, that hold locks conflicting with the given
+    /// lock mode on the given tag.
+    fn blockers(&self, tag: &LockTag, mode: LockMode, owner: u64) -> Vec<u64> {
+        let mut blockers: Vec<u64> = self
+            .granted
+            .get(tag)
+            .into_iter()
+            .flatten()
+            .filter(|(holder, held)| *holder != owner && mode.conflicts(*held))
+            .map(|(holder, _)| *holder)
+            .collect();
+        blockers.sort_unstable();
+        blockers.dedup();
+        blockers
+    }
+
+    /// Search the waits-for graph for a cycle that starts and ends on the given waiting owner.
+    /// Return the owners of the cycle, in the order they wait for each other.
+    fn find_deadlock(&self, owner: u64) -> Option<Vec<u64>> {
+        let mut path = vec![owner];
+        let mut visited = HashSet::new();
+        if self.search_cycle(owner, &mut path, &mut visited) {
+            Some(path)
+        } else {
+            None
+        }
+    }
+
+    /// Depth-first search for a path from the last owner of the given path back to the given
+    /// start owner, following the edges from waiting owners to the owners blocking them.
+    fn search_cycle(&self, start: u64, path: &mut Vec<u64>, visited: &mut HashSet<u64>) -> bool {
+        let current = *path.last().unwrap();
+        let (tag, mode) = match self.waiting.get(&current) {
+            Some(waiting) => waiting,
+            None => return false,
+        };
+
+        for blocker in self.blockers(tag, *mode, current) {
+            if blocker == start {
+                return true;
+            }
+            if visited.insert(blocker) {
+                path.push(blocker);
+                if self.search_cycle(start, path, visited) {
+                    return true;
+                }
+                path.pop();
+            }
+        }
+        false
+    }
+
+    /// Return the error reported to the owner aborted to break the given cycle of waiting
+    /// owners, describing the lock that each owner waits for.
+    fn deadlock_error(&self, cycle: &[u64]) -> PgError {
+        let detail = cycle
+            .iter()
+            .enumerate()
+            .map(|(i, owner)| {
+                let (tag, mode) = &self.waiting[owner];
+                format!(
+                    "Process {} waits for {} on relation {} of database {}; blocked by process {}.",
+                    owner,
+                    mode,
+                    tag.relation,
+                    tag.database,
+                    cycle[(i + 1) % cycle.len()]
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        PgError::new(SqlState::DeadlockDetected, "deadlock detected").with_detail(detail)
+    }
+}
+
+/// Lock manager granting relation level locks, so that relations being used by other connections
+/// are not removed.
+///
+/// Lock manager is reference counted and clonning will just increase the reference counter.
+#[derive(Default, Clone)]
+pub struct LockManager {
+    table: Arc<LockTable>,
+}
+
+impl LockManager {
+    /// Acquire a lock of the given mode on the given object, waiting until all conflicting locks
+    /// held by other owners are released. The lock is released when the returned guard is
+    /// dropped.
+    ///
+    /// Waits longer than DEADLOCK_TIMEOUT periodically search for a cycle of owners waiting for
+    /// each other, and the owner that finds the cycle gives up the wait with a deadlock error, so
+    /// the others can proceed. Return an error if the owner is canceled while waiting.
+    pub fn acquire(&self, tag: LockTag, mode: LockMode, owner: &LockOwner) -> Result<LockGuard> {
+        let mut state = self.table.state.lock().unwrap();
+        let started = Instant::now();
+        while !state.blockers(&tag, mode, owner.id).is_empty() {
+            state.waiting.insert(owner.id, (tag, mode));
+
+            if let Err(err) = owner.check_canceled() {
+                state.waiting.remove(&owner.id);
+                return Err(err);
+            }
+            if started.elapsed() >= DEADLOCK_TIMEOUT {
+                if let Some(cycle) = state.find_deadlock(owner.id) {
+                    let err = state.deadlock_error(&cycle);
+                    state.waiting.remove(&owner.id);
+                    log::error!("{}: {}", err.message, err.detail.as_deref().unwrap_or(""));
+                    bail!(err);
+                }
+            }
+
+            log::debug!("waiting for {:?} lock on {:?}", mode, tag);
+            state = self
+                .table
+                .released
+                .wait_timeout(state, LOCK_WAIT_INTERVAL)
+                .unwrap()
+                .0;
+        }
+
+        state.waiting.remove(&owner.id);
+        state.granted.entry(tag).or_default().push((owner.id, mode));
+        Ok(LockGuard {
+            table: self.table.clone(),
+            tag,
+            owner: owner.id,
+            mode,
+        })
+    }
+}
+
+/// A granted lock, released when the guard is dropped.
+pub struct LockGuard {
+    table: Arc<LockTable>,
+    tag: LockTag,
+    owner: u64,
+    mode: LockMode,
+}
+
+impl LockGuard {
+    /// Return the mode of the granted lock.
+    pub fn mode(&self) -> LockMode {
+        self.mode
+    }
+}
+
+impl Drop for LockGuard {
+    fn drop(&mut self) {
+        let mut state = self.table.state.lock().unwrap();
+        let granted = &mut state.granted;
+        if let Some(locks) = granted.get_mut(&self.tag) {
+            if let Some(pos) = locks
+                .iter()
+                .position(|lock| *lock == (self.owner, self.mode))
+            {
+                locks.remove(pos);
+            }
+            if locks.is_empty() {
+                granted.remove(&self.tag);
+            }
+        }
+        self.table.released.notify_all();
+    }
+}
+
+/// Lock the relation with the given name, searched on the given search path, and return its
+/// pg_class tuple.
+///
+/// The relation is searched again after the lock is granted, since it may be dropped, or replaced
+/// by another relation with the same name, while waiting for the lock.
+pub fn lock_relation(
+    buffer_pool: &BufferPool,
+    db_oid: &Oid,
+    search_path: &[String],
+    rel_name: &RelationName,
+    mode: LockMode,
+    owner: &LockOwner,
+) -> Result<(PgClass, LockGuard)> {
+    loop {
+        let pg_class = catalog::get_pg_class_relation(buffer_pool, db_oid, search_path, rel_name)?;
+        let tag = LockTag::relation(db_oid, &pg_class);
+        let lock = buffer_pool.lock_manager().acquire(tag, mode, owner)?;
+
+        let current = catalog::get_pg_class_relation(buffer_pool, db_oid, search_path, rel_name)?;
+        if current.oid == pg_class.oid {
+            return Ok((current, lock));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::mpsc, thread};
+
+    use super::*;
+
+    const TAG: LockTag = LockTag {
+        database: 5,
+        relation: 10000,
+    };
+
+    #[test]
+    fn test_lock_conflicts() -> Result<()> {
+        use LockMode::*;
+
+        let manager = LockManager::default();
+        let (owner, other) = (LockOwner::new(), LockOwner::new());
+        let granted = |mode, owner: &LockOwner| {
+            let (sender, receiver) = mpsc::channel();
+            let (manager, owner) = (manager.clone(), owner.clone());
+            thread::spawn(move || {
+                let lock = manager.acquire(TAG, mode, &owner);
+                let _ = sender.send(lock.is_ok());
+            });
+            receiver
+                .recv_timeout(Duration::from_millis(200))
+                .unwrap_or(false)
+        };
+
+        let share = manager.acquire(TAG, AccessShare, &owner)?;
+        assert!(granted(AccessShare, &other));
+        assert!(granted(RowExclusive, &other));
+        assert!(!granted(AccessExclusive, &other));
+        // Locks of the same owner don't conflict.
+        assert!(granted(AccessExclusive, &owner));
+        drop(share);
+
+        let _row_exclusive = manager.acquire(TAG, RowExclusive, &owner)?;
+        assert!(!granted(Share, &other));
+        assert!(granted(RowExclusive, &other));
+        assert!(granted(AccessShare, &other));
+
+        assert!(AccessExclusive.conflicts(AccessShare));
+        assert!(!Share.conflicts(Share));
+        assert!(ShareRowExclusive.conflicts(ShareRowExclusive));
+        assert!(!ShareRowExclusive.conflicts(AccessShare));
+        assert!(!AccessShare.conflicts(ShareRowExclusive));
+        Ok(())
+    }
+
+    #[test]
+    fn test_lock_wait() -> Result<()> {
+        let manager = LockManager::default();
+        let owner = LockOwner::new();
+        let lock = manager.acquire(TAG, LockMode::AccessShare, &owner)?;
+
+        let waiter = {
+            let manager = manager.clone();
+            thread::spawn(move || {
+                manager
+                    .acquire(TAG, LockMode::AccessExclusive, &LockOwner::new())
+                    .map(|lock| lock.mode())
+            })
+        };
+        thread::sleep(Duration::from_millis(100));
+        assert!(!waiter.is_finished());
+
+        drop(lock);
+        assert_eq!(waiter.join().unwrap()?, LockMode::AccessExclusive);
+
+        // Waits are interrupted when the owner is canceled.
+        let _lock = manager.acquire(TAG, LockMode::AccessShare, &owner)?;
+        let canceled = Arc::new(AtomicBool::new(false));
+        let waiter = {
+            let manager = manager.clone();
+            let owner = LockOwner::new().with_cancel_flag(canceled.clone());
+            thread::spawn(move || {
+                manager
+                    .acquire(TAG, LockMode::AccessExclusive, &owner)
+                    .map(|lock| lock.mode())
+            })
+        };
+        canceled.store(true, Ordering::SeqCst);
+        let err = waiter.join().unwrap().err().unwrap();
+        assert_eq!(err.downcast::<PgError>()?.code, SqlState::QueryCanceled);
+        Ok(())
+    }
+
+    #[test]
+    fn test_deadlock_detection() -> Result<()> {
+        let manager = LockManager::default();
+        let tags = [
+            TAG,
+            LockTag {
+                database: 5,
+                relation: 10001,
+            },
+        ];
+
+        // Each owner holds an exclusive lock on a relation and then waits for the relation
+        // locked by the other owner.
+        let (sender, receiver) = mpsc::channel();
+        let waiters = (0..2)
+            .map(|i| {
+                let (manager, sender) = (manager.clone(), sender.clone());
+                let (held, wanted) = (tags[i], tags[1 - i]);
+                thread::spawn(move || -> Result<Option<SqlState>> {
+                    let owner = LockOwner::new();
+                    let _held = manager.acquire(held, LockMode::AccessExclusive, &owner)?;
+                    sender.send(()).unwrap();
+                    thread::sleep(Duration::from_millis(100));
+                    let result = manager.acquire(wanted, LockMode::AccessExclusive, &owner);
+                    Ok(result
+                        .err()
+                        .map(|err| err.downcast::<PgError>().unwrap().code))
+                })
+            })
+            .collect::<Vec<_>>();
+        receiver.recv()?;
+        receiver.recv()?;
+
+        // Only one of the owners is aborted, the other one is granted the lock once the
+        // aborted owner releases its locks.
+        let mut results = waiters
+            .into_iter()
+            .map(|waiter| waiter.join().unwrap())
+            .collect::<Result<Vec<_>>>()?;
+        results.sort_by_key(|result| result.is_none());
+        assert_eq!(results, vec![Some(SqlState::DeadlockDetected), None]);
+
+        let state = manager.table.state.lock().unwrap();
+        assert!(state.granted.is_empty());
+        assert!(state.waiting.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_deadlock() {
+        let tag = |relation| LockTag {
+            database: 5,
+            relation,
+        };
+        let mut state = LockState::default();
+        for (owner, relation) in [(1, 10), (2, 20), (3, 30)] {
+            state
+                .granted
+                .insert(tag(relation), vec![(owner, LockMode::RowExclusive)]);
+        }
+        state.waiting.insert(1, (tag(20), LockMode::Share));
+        state
+            .waiting
+            .insert(2, (tag(30), LockMode::AccessExclusive));
+        assert_eq!(state.find_deadlock(1), None);
+
+        state.waiting.insert(3, (tag(10), LockMode::Share));
+        let cycle = state.find_deadlock(1).unwrap();
+        assert_eq!(cycle, vec![1, 2, 3]);
+        assert_eq!(
+            state.deadlock_error(&cycle).detail.unwrap(),
+            "Process 1 waits for ShareLock on relation 20 of database 5; blocked by process 2.\n\
+             Process 2 waits for AccessExclusiveLock on relation 30 of database 5; blocked by process 3.\n\
+             Process 3 waits for ShareLock on relation 10 of database 5; blocked by process 1."
+        );
+
+        // Locks that don't conflict are not part of a cycle.
+        state.waiting.insert(3, (tag(10), LockMode::AccessShare));
+        assert_eq!(state.find_deadlock(1), None);
+    }
+}
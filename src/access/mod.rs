@@ -1,5 +1,9 @@
+pub mod btree;
+pub mod columnar;
 pub mod heap;
 pub mod heaptuple;
+pub mod lmgr;
 pub mod relation;
+pub mod tableam;
 
 pub use relation::*;
@@ -2,7 +2,8 @@ use std::sync::Arc;
 
 use crate::{
     catalog::{
-        pg_attribute, pg_class, pg_database,
+        pg_attribute, pg_auth_members, pg_authid, pg_class, pg_database, pg_index, pg_namespace,
+        pg_statistic,
         pg_tablespace::{self, DEFAULTTABLESPACE_OID, GLOBALTABLESPACE_OID},
     },
     relation::{Relation, RelationData},
@@ -42,6 +43,36 @@ pub fn open_pg_attribute_relation(db_oid: &Oid) -> Relation {
     )
 }
 
+/// Return the pg_index Relation.
+pub fn open_pg_index_relation(db_oid: &Oid) -> Relation {
+    open_relation(
+        pg_index::RELATION_OID,
+        DEFAULTTABLESPACE_OID,
+        db_oid,
+        pg_index::RELATION_NAME,
+    )
+}
+
+/// Return the pg_namespace Relation.
+pub fn open_pg_namespace_relation(db_oid: &Oid) -> Relation {
+    open_relation(
+        pg_namespace::RELATION_OID,
+        DEFAULTTABLESPACE_OID,
+        db_oid,
+        pg_namespace::RELATION_NAME,
+    )
+}
+
+/// Return the pg_statistic Relation.
+pub fn open_pg_statistic_relation(db_oid: &Oid) -> Relation {
+    open_relation(
+        pg_statistic::RELATION_OID,
+        DEFAULTTABLESPACE_OID,
+        db_oid,
+        pg_statistic::RELATION_NAME,
+    )
+}
+
 /// Return the pg_database Relation.
 pub fn open_pg_database_relation() -> Relation {
     open_relation(
@@ -52,6 +83,26 @@ pub fn open_pg_database_relation() -> Relation {
     )
 }
 
+/// Return the pg_authid Relation.
+pub fn open_pg_authid_relation() -> Relation {
+    open_relation(
+        pg_authid::RELATION_OID,
+        GLOBALTABLESPACE_OID,
+        &INVALID_OID,
+        pg_authid::RELATION_NAME,
+    )
+}
+
+/// Return the pg_auth_members Relation.
+pub fn open_pg_auth_members_relation() -> Relation {
+    open_relation(
+        pg_auth_members::RELATION_OID,
+        GLOBALTABLESPACE_OID,
+        &INVALID_OID,
+        pg_auth_members::RELATION_NAME,
+    )
+}
+
 /// Return the pg_tablespace Relation.
 pub fn open_pg_tablespace_relation() -> Relation {
     open_relation(
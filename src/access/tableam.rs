@@ -0,0 +1,285 @@
+use anyhow::{anyhow, Result};
+
+use crate::{
+    catalog::heap::{drop_relation_storage, initialize_default_page_header},
+    relation::Relation,
+    sql::error::{PgError, SqlState},
+    storage::{page::ItemPointer, BufferPool},
+    Oid,
+};
+
+use super::{
+    columnar::ColumnarTableAm,
+    heap::{heap_delete, heap_fetch, heap_insert, heap_multi_insert, HeapScanner},
+    heaptuple::{HeapTuple, TupleDesc},
+};
+
+/// Fixed oid of the heap table access method, the default access method of tables.
+pub const HEAP_TABLE_AM_OID: Oid = 2;
+
+/// A table access method defines how the tuples of a table are stored on the relation files.
+///
+/// The access method of each table is stored on pg_class.relam, and the executor only
+/// interacts with tables using this trait, so new storage layouts can be added without changing
+/// the plan nodes.
+pub trait TableAm: Send + Sync {
+    /// Oid of the access method, stored on pg_class.relam of the tables using it.
+    fn oid(&self) -> Oid;
+
+    /// Name of the access method, used to select it on CREATE TABLE.
+    fn name(&self) -> &'static str;
+
+    /// Initialize the storage of a new relation with the given tuple description.
+    fn relation_create(
+        &self,
+        buffer_pool: &BufferPool,
+        rel: &Relation,
+        tuple_desc: &TupleDesc,
+    ) -> Result<()>;
+
+    /// Remove all the storage of the given relation.
+    fn relation_drop(&self, buffer_pool: &BufferPool, rel: &Relation) -> Result<()>;
+
+    /// Insert a new tuple into the given relation and on all indexes of the relation.
+    ///
+    /// Return the physical location of the new tuple.
+    fn insert(
+        &self,
+        buffer_pool: &BufferPool,
+        rel: &Relation,
+        tuple: &HeapTuple,
+    ) -> Result<ItemPointer>;
+
+    /// Insert the given tuples into the given relation and on all indexes of the relation.
+    /// Access methods can override it to insert many tuples more efficiently than inserting
+    /// each one of them.
+    ///
+    /// Return the physical location of each tuple, in the same order of the given tuples.
+    fn multi_insert(
+        &self,
+        buffer_pool: &BufferPool,
+        rel: &Relation,
+        tuples: &[HeapTuple],
+    ) -> Result<Vec<ItemPointer>> {
+        tuples
+            .iter()
+            .map(|tuple| self.insert(buffer_pool, rel, tuple))
+            .collect()
+    }
+
+    /// Delete the tuple stored on the given physical location of the given relation.
+    fn delete(&self, buffer_pool: &BufferPool, rel: &Relation, tid: &ItemPointer) -> Result<()>;
+
+    /// Fetch the tuple stored on the given physical location of the given relation. Return None
+    /// if there is no live tuple on the location.
+    fn fetch(
+        &self,
+        buffer_pool: &BufferPool,
+        rel: &Relation,
+        tid: &ItemPointer,
+    ) -> Result<Option<HeapTuple>>;
+
+    /// Start a new scan over all live tuples of the given relation.
+    fn begin_scan(&self, buffer_pool: &BufferPool, rel: &Relation) -> Result<Box<dyn TableScan>>;
+}
+
+/// A scan over the tuples of a relation started by TableAm::begin_scan.
+pub trait TableScan: Send {
+    /// Return the next tuple of the relation, or None if all tuples were returned. The t_self
+    /// field of returned tuples is the physical location used by fetch and delete.
+    fn next_tuple(&mut self) -> Result<Option<HeapTuple>>;
+}
+
+/// Table access method that stores tuples on slotted heap pages.
+pub struct HeapTableAm;
+
+impl TableAm for HeapTableAm {
+    fn oid(&self) -> Oid {
+        HEAP_TABLE_AM_OID
+    }
+
+    fn name(&self) -> &'static str {
+        "heap"
+    }
+
+    fn relation_create(
+        &self,
+        buffer_pool: &BufferPool,
+        rel: &Relation,
+        _: &TupleDesc,
+    ) -> Result<()> {
+        initialize_default_page_header(buffer_pool, rel)
+    }
+
+    fn relation_drop(&self, buffer_pool: &BufferPool, rel: &Relation) -> Result<()> {
+        drop_relation_storage(buffer_pool, rel)
+    }
+
+    fn insert(
+        &self,
+        buffer_pool: &BufferPool,
+        rel: &Relation,
+        tuple: &HeapTuple,
+    ) -> Result<ItemPointer> {
+        heap_insert(buffer_pool, rel, tuple)
+    }
+
+    fn multi_insert(
+        &self,
+        buffer_pool: &BufferPool,
+        rel: &Relation,
+        tuples: &[HeapTuple],
+    ) -> Result<Vec<ItemPointer>> {
+        heap_multi_insert(buffer_pool, rel, tuples)
+    }
+
+    fn delete(&self, buffer_pool: &BufferPool, rel: &Relation, tid: &ItemPointer) -> Result<()> {
+        heap_delete(buffer_pool, rel, tid)
+    }
+
+    fn fetch(
+        &self,
+        buffer_pool: &BufferPool,
+        rel: &Relation,
+        tid: &ItemPointer,
+    ) -> Result<Option<HeapTuple>> {
+        heap_fetch(buffer_pool, rel, tid)
+    }
+
+    fn begin_scan(&self, buffer_pool: &BufferPool, rel: &Relation) -> Result<Box<dyn TableScan>> {
+        Ok(Box::new(HeapScanner::new(buffer_pool, rel)?))
+    }
+}
+
+/// All table access methods.
+static TABLE_AMS: &[&dyn TableAm] = &[&HeapTableAm, &ColumnarTableAm];
+
+/// Return the table access method with the given oid, as stored on pg_class.relam.
+pub fn table_am(relam: Oid) -> Result<&'static dyn TableAm> {
+    TABLE_AMS
+        .iter()
+        .find(|am| am.oid() == relam)
+        .copied()
+        .ok_or_else(|| {
+            anyhow!(PgError::new(
+                SqlState::UndefinedObject,
+                format!("table access method with oid {} does not exist", relam)
+            ))
+        })
+}
+
+/// Return the table access method with the given name.
+pub fn table_am_by_name(name: &str) -> Result<&'static dyn TableAm> {
+    TABLE_AMS
+        .iter()
+        .find(|am| am.name() == name)
+        .copied()
+        .ok_or_else(|| {
+            anyhow!(PgError::new(
+                SqlState::UndefinedObject,
+                format!("access method \"{}\" does not exist", name)
+            ))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        access,
+        catalog::pg_tablespace::{PgTablespace, DEFAULTTABLESPACE_OID},
+        storage::{smgr::StorageManager, PAGE_SIZE},
+    };
+
+    #[test]
+    fn test_heap_table_am() -> Result<()> {
+        let buffer_pool = BufferPool::new(20, StorageManager::in_memory());
+        let rel = access::open_relation(10000, DEFAULTTABLESPACE_OID, &5, "test");
+        let am = table_am(HEAP_TABLE_AM_OID)?;
+        assert_eq!(am.name(), "heap");
+
+        let tids = (0..3)
+            .map(|i| am.insert(&buffer_pool, &rel, &HeapTuple::with_default_header(&i)?))
+            .collect::<Result<Vec<_>>>()?;
+        let tuple = am.fetch(&buffer_pool, &rel, &tids[1])?.unwrap();
+        assert_eq!(tuple.decode_data::<i32>()?, 1);
+        assert_eq!(tuple.t_self, tids[1]);
+
+        am.delete(&buffer_pool, &rel, &tids[1])?;
+        assert!(am.fetch(&buffer_pool, &rel, &tids[1])?.is_none());
+
+        // The relation has no indexes, so the slot of the deleted tuple is reused.
+        let tid = am.insert(&buffer_pool, &rel, &HeapTuple::with_default_header(&3)?)?;
+        assert_eq!(tid, tids[1]);
+        am.delete(&buffer_pool, &rel, &tid)?;
+
+        let missing = ItemPointer {
+            page_number: 2,
+            offset: 1,
+        };
+        assert!(am.fetch(&buffer_pool, &rel, &missing)?.is_none());
+
+        let mut scan = am.begin_scan(&buffer_pool, &rel)?;
+        let mut values = Vec::new();
+        while let Some(tuple) = scan.next_tuple()? {
+            values.push(tuple.decode_data::<i32>()?);
+        }
+        assert_eq!(values, vec![0, 2]);
+
+        assert_eq!(
+            table_am(42).err().unwrap().downcast::<PgError>()?.code,
+            SqlState::UndefinedObject
+        );
+        assert_eq!(table_am_by_name("heap")?.oid(), HEAP_TABLE_AM_OID);
+        assert_eq!(
+            table_am_by_name("unknown").err().unwrap().to_string(),
+            "access method \"unknown\" does not exist"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_heap_multi_insert() -> Result<()> {
+        let buffer_pool = BufferPool::new(20, StorageManager::in_memory());
+        let am = table_am(HEAP_TABLE_AM_OID)?;
+        let tuples = (0..2000)
+            .map(|i| HeapTuple::with_default_header(&i))
+            .collect::<Result<Vec<_>>>()?;
+
+        // Tuples are stored on the same locations of tuples inserted one at a time.
+        let rel = access::open_relation(10000, DEFAULTTABLESPACE_OID, &5, "test");
+        let tids = am.multi_insert(&buffer_pool, &rel, &tuples)?;
+        let other = access::open_relation(10001, DEFAULTTABLESPACE_OID, &5, "other");
+        for (tuple, tid) in tuples.iter().zip(&tids) {
+            assert_eq!(am.insert(&buffer_pool, &other, tuple)?, *tid);
+        }
+        assert!(buffer_pool.size_of_relation(&rel)? > 1);
+
+        let mut scan = am.begin_scan(&buffer_pool, &rel)?;
+        let mut values = Vec::new();
+        while let Some(tuple) = scan.next_tuple()? {
+            assert_eq!(tuple.t_self, tids[values.len()]);
+            values.push(tuple.decode_data::<i32>()?);
+        }
+        assert_eq!(values, (0..2000).collect::<Vec<_>>());
+
+        // No tuple is inserted if any of them is too big.
+        let tuples = vec![
+            HeapTuple::with_default_header(&1)?,
+            HeapTuple::with_default_header(&PgTablespace {
+                oid: 1,
+                spcname: "a".repeat(PAGE_SIZE),
+            })?,
+        ];
+        let err = am.multi_insert(&buffer_pool, &rel, &tuples).unwrap_err();
+        assert_eq!(
+            err.downcast::<PgError>()?.code,
+            SqlState::ProgramLimitExceeded
+        );
+        assert_eq!(
+            am.insert(&buffer_pool, &rel, &tuples[0])?,
+            am.insert(&buffer_pool, &other, &tuples[0])?
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,233 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::SystemTime,
+};
+
+use anyhow::Result;
+
+use crate::{
+    access::heaptuple::TupleDesc,
+    catalog::{
+        pg_type,
+        virtual_table::{virtual_tuple_desc, ScanContext, VirtualTable, PG_STAT_ACTIVITY_OID},
+    },
+    sql::encode::datetime::timestamp_from_system_time,
+    Datum, NullableDatum, Oid,
+};
+
+/// Current activity of a backend connection.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Activity {
+    /// Name of the database that the connection is connected to.
+    pub datname: String,
+
+    /// Name of the user logged into the connection.
+    pub usename: String,
+
+    /// Name of the application that opened the connection.
+    pub application_name: String,
+
+    /// Address of the client, or [local] for unix domain socket connections.
+    pub client_addr: String,
+
+    /// Time when the connection was started, as microseconds since 2000-01-01.
+    pub backend_start: i64,
+
+    /// Time when the running query was started, or the last query if the connection is idle.
+    /// None if no query was executed yet.
+    pub query_start: Option<i64>,
+
+    /// Current state of the connection, active while a query is running and idle otherwise.
+    pub state: String,
+
+    /// Text of the running query, or the last query if the connection is idle.
+    pub query: String,
+}
+
+impl Activity {
+    /// Create the activity of a new idle connection started now.
+    pub fn new(datname: &str, usename: &str, application_name: &str, client_addr: &str) -> Self {
+        Self {
+            datname: datname.to_string(),
+            usename: usename.to_string(),
+            application_name: application_name.to_string(),
+            client_addr: client_addr.to_string(),
+            backend_start: timestamp_from_system_time(SystemTime::now()),
+            query_start: None,
+            state: String::from("idle"),
+            query: String::new(),
+        }
+    }
+}
+
+/// Registry of the activity of all backend connections, by process id.
+///
+/// The registry is cheap to clone, all clones share the same registered connections.
+#[derive(Clone, Default)]
+pub struct Activities {
+    connections: Arc<Mutex<HashMap<u32, Activity>>>,
+
+    /// Number of queries started by all connections.
+    queries: Arc<AtomicU64>,
+}
+
+impl Activities {
+    /// Register a new idle connection with the given process id.
+    pub fn register(&self, process_id: u32, activity: Activity) {
+        self.connections
+            .lock()
+            .unwrap()
+            .insert(process_id, activity);
+    }
+
+    /// Remove the connection with the given process id.
+    pub fn unregister(&self, process_id: u32) {
+        self.connections.lock().unwrap().remove(&process_id);
+    }
+
+    /// Mark the connection with the given process id as running the given query.
+    pub fn start_query(&self, process_id: u32, query: &str) {
+        self.queries.fetch_add(1, Ordering::Relaxed);
+        if let Some(activity) = self.connections.lock().unwrap().get_mut(&process_id) {
+            activity.state = String::from("active");
+            activity.query = query.to_string();
+            activity.query_start = Some(timestamp_from_system_time(SystemTime::now()));
+        }
+    }
+
+    /// Mark the connection with the given process id as idle.
+    pub fn finish_query(&self, process_id: u32) {
+        if let Some(activity) = self.connections.lock().unwrap().get_mut(&process_id) {
+            activity.state = String::from("idle");
+        }
+    }
+
+    /// Return the number of registered connections.
+    pub fn connections(&self) -> usize {
+        self.connections.lock().unwrap().len()
+    }
+
+    /// Return the number of queries started since the server was started.
+    pub fn queries(&self) -> u64 {
+        self.queries.load(Ordering::Relaxed)
+    }
+
+    /// Return the activity of all connections, ordered by process id.
+    pub fn all(&self) -> Vec<(u32, Activity)> {
+        let mut all = self
+            .connections
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(process_id, activity)| (*process_id, activity.clone()))
+            .collect::<Vec<_>>();
+        all.sort_by_key(|(process_id, _)| *process_id);
+        all
+    }
+}
+
+/// The view pg_stat_activity has one row per connection, showing the current activity of that
+/// connection.
+pub struct PgStatActivity {
+    activities: Activities,
+}
+
+impl PgStatActivity {
+    /// Create a new view of the connections registered on the given registry.
+    pub fn new(activities: Activities) -> Self {
+        Self { activities }
+    }
+}
+
+impl VirtualTable for PgStatActivity {
+    fn oid(&self) -> Oid {
+        PG_STAT_ACTIVITY_OID
+    }
+
+    fn name(&self) -> &'static str {
+        "pg_stat_activity"
+    }
+
+    fn tuple_desc(&self) -> TupleDesc {
+        virtual_tuple_desc(
+            self.oid(),
+            &[
+                ("pid", pg_type::INT_OID),
+                ("datname", pg_type::TEXT_OID),
+                ("usename", pg_type::TEXT_OID),
+                ("application_name", pg_type::TEXT_OID),
+                ("client_addr", pg_type::TEXT_OID),
+                ("backend_start", pg_type::TIMESTAMP_OID),
+                ("query_start", pg_type::TIMESTAMP_OID),
+                ("state", pg_type::TEXT_OID),
+                ("query", pg_type::TEXT_OID),
+            ],
+        )
+    }
+
+    fn scan(&self, _: &ScanContext) -> Result<Vec<Vec<NullableDatum>>> {
+        self.activities
+            .all()
+            .into_iter()
+            .map(|(process_id, activity)| {
+                let text = |value: &String| -> Result<NullableDatum> {
+                    Ok(Some(Datum::from(value.as_bytes().to_vec())))
+                };
+                let timestamp = |value: Option<i64>| -> Result<NullableDatum> {
+                    value
+                        .map(|value| Ok(Datum::from(bincode::serialize(&value)?)))
+                        .transpose()
+                };
+                Ok(vec![
+                    Some(Datum::from(bincode::serialize(&(process_id as i32))?)),
+                    text(&activity.datname)?,
+                    text(&activity.usename)?,
+                    text(&activity.application_name)?,
+                    text(&activity.client_addr)?,
+                    timestamp(Some(activity.backend_start))?,
+                    timestamp(activity.query_start)?,
+                    text(&activity.state)?,
+                    text(&activity.query)?,
+                ])
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn activity(datname: &str) -> Activity {
+        Activity::new(datname, "tinydb", "psql", "[local]")
+    }
+
+    #[test]
+    fn test_activities() {
+        let activities = Activities::default();
+        activities.register(2, activity("db2"));
+        activities.register(1, activity("db1"));
+
+        activities.start_query(1, "SELECT 1");
+        let all = activities.all();
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].0, 1);
+        assert_eq!(all[0].1.state, "active");
+        assert_eq!(all[0].1.query, "SELECT 1");
+        assert!(all[0].1.query_start.unwrap() >= all[0].1.backend_start);
+        assert_eq!(all[1].1.query_start, None);
+        assert_eq!(activities.connections(), 2);
+        assert_eq!(activities.queries(), 1);
+
+        activities.finish_query(1);
+        activities.unregister(2);
+        let all = activities.all();
+        assert_eq!(all.len(), 1);
+        assert_eq!(all[0].1.state, "idle");
+        assert_eq!(all[0].1.query, "SELECT 1");
+    }
+}
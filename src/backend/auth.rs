@@ -0,0 +1,266 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::{bail, Result};
+
+use crate::{
+    catalog::{self, pg_authid::PgAuthId},
+    postgres_protocol::{commands::Message, Connection, Stream},
+    sql::error::{PgError, SqlState},
+    storage::BufferPool,
+};
+
+/// Methods used to authenticate clients on connection startup.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AuthMethod {
+    /// Allow any client to connect without a password. Should only be used for local
+    /// development.
+    Trust,
+
+    /// Ask the client for a cleartext password.
+    Password,
+
+    /// Ask the client for a md5 hashed password using a random salt.
+    Md5,
+}
+
+impl FromStr for AuthMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "trust" => Ok(Self::Trust),
+            "password" => Ok(Self::Password),
+            "md5" => Ok(Self::Md5),
+            _ => Err(format!("invalid authentication method {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for AuthMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Trust => write!(f, "trust"),
+            Self::Password => write!(f, "password"),
+            Self::Md5 => write!(f, "md5"),
+        }
+    }
+}
+
+/// Authenticate the given user on connection using the given authentication method and return
+/// the role of the user.
+///
+/// The password sent by the client is checked against the role password stored on pg_authid.
+/// Even if authentication is disabled the role must exist and be allowed to log in.
+pub async fn authenticate<S: Stream>(
+    connection: &mut Connection<S>,
+    buffer_pool: &BufferPool,
+    method: AuthMethod,
+    user: &str,
+) -> Result<PgAuthId> {
+    // Only report the failure to the client after reading the password, so an attacker can not
+    // know if the role exists.
+    let role = catalog::get_pg_authid(buffer_pool, user);
+
+    let authenticated = match method {
+        AuthMethod::Trust => true,
+        AuthMethod::Password => {
+            connection.send_authentication_cleartext_password().await?;
+            let password = receive_password(connection).await?;
+            match &role {
+                Ok(role) => check_cleartext_password(role, &password),
+                Err(_) => false,
+            }
+        }
+        AuthMethod::Md5 => {
+            let salt = rand::random::<[u8; 4]>();
+            connection.send_authentication_md5_password(salt).await?;
+            let password = receive_password(connection).await?;
+            match &role {
+                Ok(role) => check_md5_password(role, &password, &salt),
+                Err(_) => false,
+            }
+        }
+    };
+
+    if !authenticated {
+        bail!(PgError::new(
+            SqlState::InvalidPassword,
+            format!("password authentication failed for user \"{}\"", user)
+        ));
+    }
+
+    let role = match role {
+        Ok(role) => role,
+        Err(err) => match err.downcast_ref::<catalog::Error>() {
+            Some(catalog::Error::RoleNotFound(_)) => bail!(PgError::new(
+                SqlState::InvalidAuthorizationSpecification,
+                format!("role \"{}\" does not exist", user)
+            )),
+            _ => return Err(err),
+        },
+    };
+    if !role.rolcanlogin {
+        bail!(PgError::new(
+            SqlState::InvalidAuthorizationSpecification,
+            format!("role \"{}\" is not permitted to log in", user)
+        ));
+    }
+    Ok(role)
+}
+
+/// Return the password encrypted on the same format that is stored on pg_authid.
+pub fn encrypt_password(user: &str, password: &str) -> String {
+    format!("md5{}", md5_hex(format!("{}{}", password, user).as_bytes()))
+}
+
+/// Wait for a password message from client.
+async fn receive_password<S: Stream>(connection: &mut Connection<S>) -> Result<String> {
+    match connection.receive().await? {
+        Message::PasswordMessage(password) => Ok(password),
+        _ => bail!(PgError::new(
+            SqlState::ProtocolViolation,
+            "expected password response"
+        )),
+    }
+}
+
+/// Check if the cleartext password sent by client match with the role password.
+fn check_cleartext_password(role: &PgAuthId, password: &str) -> bool {
+    !role.rolpassword.is_empty() && encrypt_password(&role.rolname, password) == role.rolpassword
+}
+
+/// Check if the md5 hashed password sent by client match with the role password.
+///
+/// The client send "md5" followed by the md5 of the role encrypted password (without the md5
+/// prefix) concatenated with the salt.
+fn check_md5_password(role: &PgAuthId, password: &str, salt: &[u8; 4]) -> bool {
+    let stored = match role.rolpassword.strip_prefix("md5") {
+        Some(stored) => stored,
+        None => return false,
+    };
+
+    let mut data = stored.as_bytes().to_vec();
+    data.extend_from_slice(salt);
+
+    password == format!("md5{}", md5_hex(&data))
+}
+
+/// Return the lowercase hexadecimal md5 digest of the given data.
+pub(crate) fn md5_hex(data: &[u8]) -> String {
+    md5(data).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Per round shift amounts of md5.
+const MD5_SHIFTS: [u32; 64] = [
+    7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 5, 9, 14, 20, 5, 9, 14, 20, 5, 9,
+    14, 20, 5, 9, 14, 20, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 6, 10, 15,
+    21, 6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21,
+];
+
+/// Compute the md5 digest of the given data as described on RFC 1321.
+fn md5(data: &[u8]) -> [u8; 16] {
+    // Binary integer part of the sines of integers (radians) as constants.
+    let k: Vec<u32> = (0..64)
+        .map(|i| ((i as f64 + 1.0).sin().abs() * 4294967296.0) as u32)
+        .collect();
+
+    let mut state: [u32; 4] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476];
+
+    // Pad the message with a single 1 bit, zeros and the message length in bits so the total
+    // length is a multiple of 64 bytes.
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64).wrapping_mul(8)).to_le_bytes());
+
+    for chunk in message.chunks(64) {
+        let m: Vec<u32> = chunk
+            .chunks(4)
+            .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+            .collect();
+
+        let [mut a, mut b, mut c, mut d] = state;
+
+        for i in 0..64 {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+
+            let f = f.wrapping_add(a).wrapping_add(k[i]).wrapping_add(m[g]);
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(f.rotate_left(MD5_SHIFTS[i]));
+        }
+
+        state[0] = state[0].wrapping_add(a);
+        state[1] = state[1].wrapping_add(b);
+        state[2] = state[2].wrapping_add(c);
+        state[3] = state[3].wrapping_add(d);
+    }
+
+    let mut digest = [0; 16];
+    for (i, word) in state.iter().enumerate() {
+        digest[i * 4..i * 4 + 4].copy_from_slice(&word.to_le_bytes());
+    }
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_md5() {
+        assert_eq!(md5_hex(b""), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(md5_hex(b"abc"), "900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(
+            md5_hex(b"The quick brown fox jumps over the lazy dog"),
+            "9e107d9d372bb6826bd81d3542a419d6"
+        );
+        // Message with more than one 64 bytes block.
+        assert_eq!(
+            md5_hex(
+                b"12345678901234567890123456789012345678901234567890123456789012345678901234567890"
+            ),
+            "57edf4a22be3c955ac49da2e2107b67a"
+        );
+    }
+
+    #[test]
+    fn test_check_passwords() {
+        let role = PgAuthId {
+            oid: 10,
+            rolname: String::from("tinydb"),
+            rolpassword: encrypt_password("tinydb", "secret"),
+            rolsuper: true,
+            rolcanlogin: true,
+        };
+
+        assert!(check_cleartext_password(&role, "secret"));
+        assert!(!check_cleartext_password(&role, "wrong"));
+
+        // Compute the client response as described on postgres protocol.
+        let salt = [1, 2, 3, 4];
+        let mut data = md5_hex(b"secrettinydb").into_bytes();
+        data.extend_from_slice(&salt);
+        let response = format!("md5{}", md5_hex(&data));
+
+        assert!(check_md5_password(&role, &response, &salt));
+        assert!(!check_md5_password(&role, &response, &[4, 3, 2, 1]));
+
+        let role = PgAuthId {
+            oid: 10,
+            rolname: String::from("tinydb"),
+            rolpassword: String::new(),
+            rolsuper: true,
+            rolcanlogin: true,
+        };
+        assert!(!check_cleartext_password(&role, ""));
+    }
+}
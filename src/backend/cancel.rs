@@ -0,0 +1,93 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use crate::postgres_protocol::commands::BackendKeyData;
+
+/// Registry of the key data of all backend connections, used to cancel the query running on a
+/// connection when a cancel request is received on a new connection.
+///
+/// The registry is cheap to clone, all clones share the same registered keys.
+#[derive(Clone, Default)]
+pub struct CancelKeys {
+    /// Registered connections, by process id.
+    keys: Arc<Mutex<HashMap<u32, CancelKey>>>,
+
+    /// Process id of the next registered connection. Tinydb don't use a process per
+    /// connection, so process ids are just unique connection ids.
+    next_process_id: Arc<AtomicU32>,
+}
+
+/// Secret key and cancellation flag of a registered connection.
+struct CancelKey {
+    secret_key: u32,
+    canceled: Arc<AtomicBool>,
+}
+
+impl CancelKeys {
+    /// Register a new connection that can be canceled by setting the given flag, and return the
+    /// key data that clients must use to cancel it.
+    pub fn register(&self, canceled: Arc<AtomicBool>) -> BackendKeyData {
+        let key = BackendKeyData {
+            process_id: self.next_process_id.fetch_add(1, Ordering::SeqCst) + 1,
+            secret_key: rand::random(),
+        };
+        self.keys.lock().unwrap().insert(
+            key.process_id,
+            CancelKey {
+                secret_key: key.secret_key,
+                canceled,
+            },
+        );
+        key
+    }
+
+    /// Remove the connection with the given key data, so it can not be canceled anymore.
+    pub fn unregister(&self, key: &BackendKeyData) {
+        self.keys.lock().unwrap().remove(&key.process_id);
+    }
+
+    /// Cancel the query running on the connection with the given key data. Return false if there
+    /// is no connection with the given process id or the secret key does not match.
+    pub fn cancel(&self, key: &BackendKeyData) -> bool {
+        match self.keys.lock().unwrap().get(&key.process_id) {
+            Some(registered) if registered.secret_key == key.secret_key => {
+                registered.canceled.store(true, Ordering::SeqCst);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancel_keys() {
+        let keys = CancelKeys::default();
+
+        let canceled = Arc::new(AtomicBool::new(false));
+        let key = keys.register(canceled.clone());
+        let other = keys.register(Arc::new(AtomicBool::new(false)));
+        assert_ne!(key.process_id, other.process_id);
+
+        // The secret key must match.
+        assert!(!keys.cancel(&BackendKeyData {
+            process_id: key.process_id,
+            secret_key: key.secret_key.wrapping_add(1),
+        }));
+        assert!(!canceled.load(Ordering::SeqCst));
+
+        assert!(keys.cancel(&key));
+        assert!(canceled.load(Ordering::SeqCst));
+
+        keys.unregister(&key);
+        assert!(!keys.cancel(&key));
+    }
+}
@@ -0,0 +1,223 @@
+use std::fmt::Write;
+
+use anyhow::Result;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    task,
+};
+
+use crate::storage::BufferPool;
+
+use super::activity::Activities;
+
+/// Maximum size of a HTTP request head accepted by the metrics listener.
+const MAX_REQUEST_SIZE: usize = 8192;
+
+/// Server metrics exposed on the metrics listener.
+#[derive(Clone)]
+pub struct Metrics {
+    buffer_pool: BufferPool,
+    activities: Activities,
+}
+
+impl Metrics {
+    /// Create the metrics of the given buffer pool and connections registry.
+    pub fn new(buffer_pool: BufferPool, activities: Activities) -> Self {
+        Self {
+            buffer_pool,
+            activities,
+        }
+    }
+
+    /// Return the current value of all metrics using the Prometheus text format.
+    ///
+    /// Queries per second are not exposed directly, they can be computed from the
+    /// tinydb_queries_total counter, e.g rate(tinydb_queries_total[1m]).
+    pub fn render(&self) -> String {
+        let buffer = self.buffer_pool.stats();
+        let mut metrics = vec![
+            (
+                "tinydb_buffer_pool_buffers",
+                "gauge",
+                "Number of buffers of the buffer pool.",
+                buffer.buffers,
+            ),
+            (
+                "tinydb_buffer_pool_used",
+                "gauge",
+                "Number of buffers holding a page.",
+                buffer.used,
+            ),
+            (
+                "tinydb_buffer_pool_dirty",
+                "gauge",
+                "Number of buffers with changes not written on disk.",
+                buffer.dirty,
+            ),
+            (
+                "tinydb_buffer_pool_pinned",
+                "gauge",
+                "Number of buffers pinned.",
+                buffer.pinned,
+            ),
+            (
+                "tinydb_buffer_pool_hits_total",
+                "counter",
+                "Number of pages found on the buffer pool.",
+                buffer.hits,
+            ),
+            (
+                "tinydb_buffer_pool_reads_total",
+                "counter",
+                "Number of pages read from disk.",
+                buffer.reads,
+            ),
+            (
+                "tinydb_buffer_pool_writes_total",
+                "counter",
+                "Number of pages written on disk.",
+                buffer.writes,
+            ),
+            (
+                "tinydb_connections",
+                "gauge",
+                "Number of open connections.",
+                self.activities.connections() as u64,
+            ),
+            (
+                "tinydb_queries_total",
+                "counter",
+                "Number of queries started.",
+                self.activities.queries(),
+            ),
+        ];
+
+        if let Some(wal) = self.buffer_pool.wal() {
+            let wal = wal.stats();
+            metrics.extend_from_slice(&[
+                (
+                    "tinydb_wal_records_total",
+                    "counter",
+                    "Number of records inserted on WAL.",
+                    wal.records,
+                ),
+                (
+                    "tinydb_wal_bytes_total",
+                    "counter",
+                    "Number of bytes inserted on WAL.",
+                    wal.bytes,
+                ),
+                (
+                    "tinydb_wal_flushes_total",
+                    "counter",
+                    "Number of WAL writes on disk.",
+                    wal.flushes,
+                ),
+                (
+                    "tinydb_wal_insert_lsn",
+                    "gauge",
+                    "LSN of the end of the last inserted WAL record.",
+                    wal.insert_lsn,
+                ),
+                (
+                    "tinydb_wal_flushed_lsn",
+                    "gauge",
+                    "LSN up to where the WAL was flushed on disk.",
+                    wal.flushed_lsn,
+                ),
+            ]);
+        }
+
+        let mut out = String::new();
+        for (name, typ, help, value) in metrics {
+            // Writing on a String never fails.
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, typ);
+            let _ = writeln!(out, "{} {}", name, value);
+        }
+        out
+    }
+}
+
+/// Serve the given metrics over HTTP on the given listener. Only GET requests to /metrics are
+/// answered, other requests receive a 404 response.
+pub async fn serve(listener: TcpListener, metrics: Metrics) -> Result<()> {
+    loop {
+        let (socket, _) = listener.accept().await?;
+        let metrics = metrics.clone();
+        task::spawn(async move {
+            if let Err(err) = handle(socket, &metrics).await {
+                log::debug!("metrics request error: {}", err);
+            }
+        });
+    }
+}
+
+/// Read a single HTTP request from the given socket and send the response.
+async fn handle(mut socket: TcpStream, metrics: &Metrics) -> Result<()> {
+    let mut request = Vec::new();
+    let mut buf = [0; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < MAX_REQUEST_SIZE {
+        let n = socket.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+
+    let request = String::from_utf8_lossy(&request);
+    let mut request_line = request
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .split_whitespace();
+    let response = match (request_line.next(), request_line.next()) {
+        (Some("GET"), Some("/metrics")) => response(
+            "200 OK",
+            "text/plain; version=0.0.4; charset=utf-8",
+            &metrics.render(),
+        ),
+        _ => response("404 Not Found", "text/plain; charset=utf-8", "not found\n"),
+    };
+
+    socket.write_all(response.as_bytes()).await?;
+    socket.shutdown().await?;
+    Ok(())
+}
+
+/// Return a HTTP response with the given status, content type and body.
+fn response(status: &str, content_type: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{smgr::StorageManager, wal::Wal};
+
+    #[test]
+    fn test_render_metrics() -> Result<()> {
+        let data_dir = tempfile::tempdir()?;
+        let buffer_pool = BufferPool::new(4, StorageManager::new(data_dir.path()))
+            .with_wal(Wal::open(data_dir.path())?);
+        let activities = Activities::default();
+        activities.start_query(1, "SELECT 1");
+
+        let out = Metrics::new(buffer_pool, activities).render();
+        assert!(
+            out.contains("# TYPE tinydb_buffer_pool_buffers gauge\ntinydb_buffer_pool_buffers 4\n")
+        );
+        assert!(out.contains("\ntinydb_connections 0\n"));
+        assert!(out.contains("\ntinydb_queries_total 1\n"));
+        assert!(out.contains("\ntinydb_wal_records_total 0\n"));
+
+        Ok(())
+    }
+}
@@ -1,13 +1,58 @@
+pub mod activity;
+pub mod auth;
+pub mod cancel;
+pub mod metrics;
+
 use crate::{
-    catalog::get_datase_oid,
-    postgres_protocol::{commands::Message, Connection},
-    sql::{ConnectionExecutor, ExecutorConfig, SQLError},
-    storage::{smgr::StorageManager, BufferPool},
+    backup,
+    catalog::virtual_table::VirtualTables,
+    lru::ReplacementPolicy,
+    postgres_protocol::{
+        commands::{BackendKeyData, Message, Target, TransactionStatus},
+        Connection, Stream,
+    },
+    sql::{
+        self,
+        copy::{self, CopyOptions, CopyReader},
+        cursor, database, encode,
+        error::{PgError, Severity, SqlState},
+        guc::{self, Reset, SessionVariables},
+        insert, psql, role, ConnectionExecutor, ExecutorConfig, PGResult, SQLError,
+    },
+    storage::{
+        control::{ClusterState, ControlFile},
+        smgr::StorageManager,
+        wal::{self, Wal, WalSyncMethod},
+        BufferPool,
+    },
+    Oid,
 };
 use anyhow::{anyhow, bail, Result};
-use sqlparser::{ast::Statement, dialect::PostgreSqlDialect, parser::Parser};
-use std::{collections::HashMap, future::Future, path::PathBuf};
-use tokio::{net::TcpListener, task};
+use socket2::{SockRef, TcpKeepalive};
+use sqlparser::ast::{CopyTarget, Ident, ObjectName, ObjectType, Statement};
+use std::{
+    collections::HashMap,
+    fs,
+    future::Future,
+    io,
+    net::SocketAddr,
+    panic,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{
+    net::{unix, TcpListener, UnixListener, UnixStream},
+    sync::{mpsc, OwnedSemaphorePermit, Semaphore},
+    task, time,
+};
+
+use self::{
+    activity::{Activities, Activity, PgStatActivity},
+    auth::AuthMethod,
+    cancel::CancelKeys,
+    metrics::Metrics,
+};
 
 /// Backend TCP listener. It includes a `start` method which performs the TCP listening and
 /// initialization of per-connection state.
@@ -15,13 +60,49 @@ pub struct Backend {
     /// TCP listener supplied by the `start` caller.
     listener: TcpListener,
 
+    /// Unix domain socket listener. None if connections are only accepted over TCP.
+    unix_listener: Option<UnixListener>,
+
     /// Shared buffer pool used by all connection handlers.
     buffer_pool: BufferPool,
+
+    /// Method used to authenticate new connections.
+    auth_method: AuthMethod,
+
+    /// Key data of all connections, used to handle cancel requests.
+    cancel_keys: CancelKeys,
+
+    /// Activity of all connections, exposed on pg_stat_activity.
+    activities: Activities,
+
+    /// Virtual relations that can be queried by all connections.
+    virtual_tables: VirtualTables,
+
+    /// Log each statement that runs at least this duration. None if statements are not logged.
+    log_min_duration: Option<Duration>,
+
+    /// Default values of session variables of new connections.
+    session_variables: SessionVariables,
+
+    /// Close connections that are idle for more than this duration. None if idle connections
+    /// are never closed.
+    idle_session_timeout: Option<Duration>,
+
+    /// Keepalive probes sent on idle TCP connections, so connections of clients that are gone
+    /// are closed by the operating system.
+    tcp_keepalive: TcpKeepalive,
+
+    /// Slots of the connections that can be open at the same time, one for each connection.
+    connection_slots: Arc<Semaphore>,
+
+    /// Maximum time to complete the startup and authentication of a new connection, so clients
+    /// that stall before authenticating don't keep a connection slot.
+    authentication_timeout: Duration,
 }
 
 /// Per-connection handler. Reads requests from `connection` and applies the
 /// SQL commands using conn_executor.
-struct Handler {
+struct Handler<S: Stream> {
     /// The TCP connection decorated with the postgres protocol encoder / decoder.
     ///
     /// When `Backend` receives an inbound connection, the `TcpStream` is
@@ -29,17 +110,94 @@ struct Handler {
     ///
     /// `Connection` allows the handler to operate at the "message" level and keep
     /// the byte level protocol parsing details encapsulated in `Connection`.
-    connection: Connection,
+    connection: Connection<S>,
 
     /// Database connection executor. A connection executor for each connection handler.
     ///
-    /// When a command is received from `connection`, it is executed with `conn_executor`.
-    conn_executor: ConnectionExecutor,
+    /// When a command is received from `connection`, it is executed with `conn_executor`. None
+    /// only while a statement runs on the blocking thread pool, which owns the executor.
+    conn_executor: Option<ConnectionExecutor>,
+
+    /// Key data sent to the client, so it can cancel the running query on a new connection.
+    key: BackendKeyData,
+
+    /// Registry where `key` is registered. The key is unregistered when the handler is dropped.
+    cancel_keys: CancelKeys,
+
+    /// Registry where the connection activity is registered using the `key` process id. The
+    /// activity is unregistered when the handler is dropped.
+    activities: Activities,
+
+    /// Set when an error happens while executing a message of the extended query protocol, so
+    /// the next messages are ignored until a Sync is received.
+    ignore_till_sync: bool,
+
+    /// Log each statement that runs at least this duration. None if statements are not logged.
+    log_min_duration: Option<Duration>,
+
+    /// Close the connection if no message is received for this duration while waiting for the
+    /// next query. None if the connection is never closed for being idle.
+    idle_session_timeout: Option<Duration>,
+
+    /// Slot of this connection on the limit of open connections, released when the handler is
+    /// dropped.
+    _connection_slot: OwnedSemaphorePermit,
 }
 
-const DIALECT: PostgreSqlDialect = PostgreSqlDialect {};
+/// Maximum number of connections open at the same time by default, the same of Postgres.
+pub const DEFAULT_MAX_CONNECTIONS: usize = 100;
+
+/// Maximum time to complete the startup and authentication of a connection by default, the same
+/// of Postgres.
+pub const DEFAULT_AUTHENTICATION_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Number of rows of a COPY FROM STDIN inserted at once.
+const COPY_BATCH_SIZE: usize = 1000;
+
+/// Maximum size of each CopyData message of a base backup.
+const BACKUP_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Number of chunks of a base backup that can be copied before being sent to the client.
+const BACKUP_QUEUE_SIZE: usize = 16;
+
+impl<S: Stream> Handler<S> {
+    /// Connection executor of the handler.
+    fn executor(&mut self) -> &mut ConnectionExecutor {
+        self.conn_executor
+            .as_mut()
+            .expect("connection executor is running a statement")
+    }
+
+    /// Run `f` with the connection executor on the blocking thread pool of the tokio runtime,
+    /// like `BufferPool::fetch_buffer_async` reads pages. Statements read and write pages and
+    /// wait for locks held by other connections, so the runtime threads are left to other
+    /// connections, including the ones sending cancel requests, on any runtime flavor.
+    async fn run_blocking<T, F>(&mut self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut ConnectionExecutor) -> Result<T> + Send + 'static,
+    {
+        let mut conn_executor = self
+            .conn_executor
+            .take()
+            .expect("connection executor is running a statement");
+        let result = task::spawn_blocking(move || {
+            let result = f(&mut conn_executor);
+            (conn_executor, result)
+        })
+        .await;
+        match result {
+            Ok((conn_executor, result)) => {
+                self.conn_executor = Some(conn_executor);
+                result
+            }
+            Err(err) => match err.try_into_panic() {
+                Ok(panic) => panic::resume_unwind(panic),
+                Err(err) => Err(err.into()),
+            },
+        }
+    }
 
-impl Handler {
     /// Process a single connection.
     ///
     /// Request message are read from the socket and processed. Responses are
@@ -50,99 +208,1044 @@ impl Handler {
     async fn run(&mut self) -> Result<()> {
         log::info!("new connection accepted");
         loop {
-            let msg = self.connection.receive().await?;
-            if let Message::Terminate = msg {
-                log::info!("closing connection with {}", self.connection.peer_addr()?);
-                return Ok(());
+            let message = match self.idle_session_timeout {
+                Some(timeout) => match time::timeout(timeout, self.connection.receive()).await {
+                    Ok(message) => message?,
+                    Err(_) => {
+                        log::info!("closing idle connection with {}", self.connection.peer());
+                        let err = PgError::new(
+                            SqlState::IdleSessionTimeout,
+                            "terminating connection due to idle-session timeout",
+                        )
+                        .with_severity(Severity::Fatal);
+                        self.connection.send_error(anyhow!(err)).await?;
+                        return Ok(());
+                    }
+                },
+                None => self.connection.receive().await?,
+            };
+            match message {
+                Message::Terminate => {
+                    log::info!("closing connection with {}", self.connection.peer());
+                    return Ok(());
+                }
+                Message::Sync => {
+                    self.ignore_till_sync = false;
+                    self.connection.ready_for_query().await?;
+                }
+                // Messages are written directly on the stream, so there is nothing to flush.
+                Message::Flush => {}
+                // After an error on the extended query protocol all messages are ignored until
+                // a Sync is received.
+                _ if self.ignore_till_sync => {}
+                Message::Query(query) => {
+                    self.activities
+                        .start_query(self.key.process_id, &query.query);
+                    if let Err(err) = self.exec_query(&query.query).await {
+                        self.send_error(err).await?;
+                    }
+                    self.activities.finish_query(self.key.process_id);
+                    self.connection.ready_for_query().await?;
+                }
+                message => {
+                    if let Err(err) = self.exec_extended(message).await {
+                        self.send_error(err).await?;
+                        self.ignore_till_sync = true;
+                    }
+                }
             }
+        }
+    }
 
-            if let Err(err) = self.exec_message(msg).await {
-                self.connection.send_error(err).await?;
-                self.connection.ready_for_query().await?;
+    /// Send the given error back to the client.
+    async fn send_error(&mut self, err: anyhow::Error) -> Result<()> {
+        // An error inside a transaction block abort the transaction.
+        if self.connection.transaction_status() == TransactionStatus::InTransaction {
+            self.connection
+                .set_transaction_status(TransactionStatus::Failed);
+        }
+        self.connection.send_error(err).await
+    }
+
+    /// Execute all statements of a query received using the simple query protocol, stopping at
+    /// the first error. Each statement is completed with its own command tag, and an empty
+    /// query is answered with an EmptyQueryResponse.
+    async fn exec_query(&mut self, query: &str) -> Result<()> {
+        self.executor().reset_cancel();
+
+        let statements = sql::split_statements(query);
+        if statements.is_empty() {
+            return self.connection.empty_query_response().await;
+        }
+        for statement in statements {
+            self.exec_query_statement(statement).await?;
+        }
+        Ok(())
+    }
+
+    /// Execute a single statement of a query received using the simple query protocol.
+    async fn exec_query_statement(&mut self, query: &str) -> Result<()> {
+        // RESET is not supported by the SQL parser.
+        if let Some(reset) = guc::parse_reset(query) {
+            let conn_executor = self.executor();
+            conn_executor.exec_reset(&reset)?;
+            let variables = conn_executor.variables();
+            let reported = match &reset {
+                Reset::All => variables.all_reported(),
+                Reset::Variable(name) => variables.reported(name)?.into_iter().collect(),
+            };
+            let reported = reported
+                .into_iter()
+                .map(|(name, value)| (name, value.to_string()))
+                .collect::<Vec<_>>();
+            for (name, value) in reported {
+                self.connection.send_parameter_status(name, &value).await?;
             }
+            self.connection.command_complete("RESET").await?;
+            return Ok(());
         }
+
+        if backup::is_base_backup(query) {
+            return self.exec_base_backup().await;
+        }
+
+        // Statements of roles are not supported by the SQL parser.
+        if let Some(statement) = role::parse_role_statement(query) {
+            let statement = statement?;
+            let tag = statement.command_tag();
+            self.run_blocking(move |executor| executor.exec_role_statement(&statement))
+                .await?;
+            self.connection.command_complete(tag).await?;
+            return Ok(());
+        }
+
+        // The TEMPLATE option of CREATE DATABASE is not supported by the SQL parser.
+        if let Some(statement) = database::parse_create_database(query) {
+            let statement = statement?;
+            let template_name = statement
+                .template
+                .as_deref()
+                .unwrap_or(database::DEFAULT_TEMPLATE);
+            self.check_database_not_used(template_name)?;
+            // All files of the template are copied on the blocking thread pool.
+            self.run_blocking(move |executor| executor.exec_create_database(&statement))
+                .await?;
+            self.connection.command_complete("CREATE DATABASE").await?;
+            return Ok(());
+        }
+
+        // Statements of cursors are not supported by the SQL parser.
+        if let Some(statement) = cursor::parse_cursor_statement(query) {
+            let statement = statement?;
+            let command_tag = statement.command_tag();
+            let result = self
+                .run_blocking(move |executor| executor.exec_cursor_statement(&statement))
+                .await?;
+            match result {
+                Some(result) => {
+                    let tag = format!("{} {}", command_tag, result.tuples.len());
+                    self.send_result_with_tag(result, None, &tag).await?;
+                }
+                None => self.connection.command_complete(command_tag).await?,
+            }
+            return Ok(());
+        }
+
+        // Queries of psql meta-commands that list relations are not supported by the planner.
+        if let Some(list) = psql::parse_list_relations(query) {
+            let result = self.executor().exec_list_relations(&list)?;
+            self.connection.send_result(result).await?;
+            return Ok(());
+        }
+
+        for stmt in sql::parse_sql(query)? {
+            self.exec_statement_logged(stmt, None).await?;
+        }
+        Ok(())
     }
 
-    async fn exec_message(&mut self, message: Message) -> Result<()> {
+    /// Execute a message of the extended query protocol.
+    async fn exec_extended(&mut self, message: Message) -> Result<()> {
         match message {
-            Message::Query(query) => {
-                let ast = Parser::parse_sql(&DIALECT, &query.query)?;
-                for stmt in ast {
-                    match stmt {
-                        Statement::Query(query) => {
-                            let result = self.conn_executor.exec_query(&query)?;
-                            self.connection.send_result(result).await?;
-                        }
-                        Statement::Insert {
-                            table_name,
-                            columns,
-                            source,
-                            ..
-                        } => {
-                            self.conn_executor
-                                .exec_insert(&table_name, &columns, &source)?;
-                            self.connection.command_complete(&"INSERT").await?;
-                        }
-                        Statement::CreateTable { name, columns, .. } => {
-                            self.conn_executor.exec_create_table(&name, &columns)?;
-                            self.connection.command_complete(&"CREATE").await?;
+            Message::Parse(parse) => {
+                self.executor()
+                    .exec_parse(&parse.statement, &parse.query, &parse.param_types)?;
+                self.connection.parse_complete().await?;
+            }
+            Message::Bind(bind) => {
+                self.executor().exec_bind(
+                    &bind.portal,
+                    &bind.statement,
+                    &bind.param_formats,
+                    &bind.params,
+                    bind.result_formats,
+                )?;
+                self.connection.bind_complete().await?;
+            }
+            Message::Describe(target) => {
+                let desc = match target {
+                    Target::Statement(name) => {
+                        let (param_types, desc) = self.executor().describe_statement(&name)?;
+                        self.connection
+                            .send_parameter_description(param_types)
+                            .await?;
+                        desc
+                    }
+                    Target::Portal(name) => self.executor().describe_portal(&name)?,
+                };
+                match desc {
+                    Some(desc) => self.connection.send_row_description(desc).await?,
+                    None => self.connection.send_no_data().await?,
+                }
+            }
+            Message::Execute(execute) => {
+                self.executor().reset_cancel();
+                let portal = self.executor().portal(&execute.portal)?;
+                let statement = portal.statement.clone();
+                let result_formats = portal.result_formats.clone();
+
+                // Queries executed with a row limit are suspended when the limit is reached, and
+                // the next Execute messages of the portal continue from where they stopped.
+                let suspendable = portal.cursor.is_some()
+                    || (execute.max_rows > 0 && matches!(statement, Statement::Query(_)));
+
+                self.activities
+                    .start_query(self.key.process_id, &statement.to_string());
+                let result = if suspendable {
+                    self.exec_portal_rows(&execute.portal, execute.max_rows, &result_formats)
+                        .await
+                } else {
+                    self.exec_statement_logged(statement, Some(&result_formats))
+                        .await
+                };
+                self.activities.finish_query(self.key.process_id);
+                result?;
+            }
+            Message::Close(target) => {
+                match target {
+                    Target::Statement(name) => self.executor().close_statement(&name),
+                    Target::Portal(name) => self.executor().close_portal(&name),
+                }
+                self.connection.close_complete().await?;
+            }
+            _ => anyhow::bail!(PgError::new(
+                SqlState::ProtocolViolation,
+                "Unexpected message type to execute"
+            )),
+        };
+        Ok(())
+    }
+
+    /// Execute a single statement and send the result back to the client.
+    ///
+    /// Statements executed from a portal of the extended query protocol use the result formats
+    /// of the portal, and the row description is not sent since clients ask for it using a
+    /// Describe message.
+    async fn exec_statement(
+        &mut self,
+        stmt: Statement,
+        result_formats: Option<&[i16]>,
+    ) -> Result<()> {
+        // Prepared statements are executed as the statement bound with the given parameters.
+        let stmt = match stmt {
+            Statement::Execute { name, parameters } => {
+                self.executor().bind_prepared(&name, &parameters)?
+            }
+            stmt => stmt,
+        };
+
+        // INSERT ... RETURNING sends the inserted rows like a query, completed with the tag of
+        // the INSERT.
+        if let Some((insert, returning)) = insert::insert_returning(&stmt) {
+            let (insert, returning) = (insert.clone(), returning.to_vec());
+            let result = self
+                .run_blocking(move |executor| executor.exec_insert(&insert, &returning))
+                .await?;
+            let tag = format!("INSERT 0 {}", result.tuples.len());
+            return self
+                .send_result_with_tag(result, result_formats, &tag)
+                .await;
+        }
+
+        match stmt {
+            Statement::Query(query) => {
+                // Queries can run for a long time, so they run on the blocking thread pool.
+                let result = self
+                    .run_blocking(move |executor| executor.exec_query(&query))
+                    .await?;
+                log::debug!(
+                    "executed query in {:.3} ms, {} rows, {} kB of memory",
+                    result.stats.elapsed.as_secs_f64() * 1000.0,
+                    result.stats.rows,
+                    result.stats.memory / 1024
+                );
+                self.send_result(result, result_formats).await?;
+            }
+            stmt @ Statement::Insert { .. } => {
+                // Inserts may wait for locks held by other connections.
+                let inserted = self
+                    .run_blocking(move |executor| executor.exec_insert(&stmt, &[]))
+                    .await?
+                    .tuples
+                    .len();
+                // The oid of the inserted row is always 0, as tables don't have oids.
+                self.connection
+                    .command_complete(&format!("INSERT 0 {}", inserted))
+                    .await?;
+            }
+            Statement::Delete {
+                table_name,
+                selection,
+            } => {
+                let deleted = self
+                    .run_blocking(move |executor| executor.exec_delete(&table_name, &selection))
+                    .await?;
+                self.connection
+                    .command_complete(&format!("DELETE {}", deleted))
+                    .await?;
+            }
+            Statement::CreateTable {
+                name,
+                columns,
+                query: Some(query),
+                with_options,
+                ..
+            } => {
+                if !columns.is_empty() {
+                    bail!(SQLError::Unsupported(String::from(
+                        "column names on CREATE TABLE AS"
+                    )));
+                }
+                let inserted = self
+                    .run_blocking(move |executor| {
+                        executor.exec_create_table_as(&name, query, &with_options)
+                    })
+                    .await?;
+                self.connection
+                    .command_complete(&format!("SELECT {}", inserted))
+                    .await?;
+            }
+            Statement::CreateTable {
+                name,
+                columns,
+                with_options,
+                ..
+            } => {
+                // Catalog pages may be read from disk.
+                self.run_blocking(move |executor| {
+                    executor.exec_create_table(&name, &columns, &with_options)
+                })
+                .await?;
+                self.connection.command_complete("CREATE").await?;
+            }
+            Statement::CreateIndex {
+                name,
+                table_name,
+                columns,
+                unique,
+                ..
+            } => {
+                self.run_blocking(move |executor| {
+                    executor.exec_create_index(&name, &table_name, &columns, unique)
+                })
+                .await?;
+                self.connection.command_complete("CREATE INDEX").await?;
+            }
+            Statement::CreateSchema {
+                schema_name,
+                if_not_exists,
+            } => {
+                self.run_blocking(move |executor| {
+                    executor.exec_create_schema(&schema_name, if_not_exists)
+                })
+                .await?;
+                self.connection.command_complete("CREATE SCHEMA").await?;
+            }
+            Statement::Analyze { table_name, .. } => {
+                // Tables are scanned entirely.
+                self.run_blocking(move |executor| executor.exec_analyze(&table_name))
+                    .await?;
+                self.connection.command_complete("ANALYZE").await?;
+            }
+            Statement::Drop {
+                object_type: ObjectType::Table,
+                if_exists,
+                names,
+                ..
+            } => {
+                // The table is dropped only after all queries using it are finished.
+                self.run_blocking(move |executor| executor.exec_drop_table(&names, if_exists))
+                    .await?;
+                self.connection.command_complete("DROP TABLE").await?;
+            }
+            Statement::Prepare {
+                name,
+                data_types,
+                statement,
+            } => {
+                self.executor()
+                    .exec_prepare(&name, &data_types, *statement)?;
+                self.connection.command_complete("PREPARE").await?;
+            }
+            Statement::SetVariable {
+                variable, value, ..
+            } => {
+                self.executor().exec_set(&variable, &value)?;
+                let reported = self
+                    .executor()
+                    .variables()
+                    .reported(&variable.to_string())?
+                    .map(|(name, value)| (name, value.to_string()));
+                if let Some((name, value)) = reported {
+                    self.connection.send_parameter_status(name, &value).await?;
+                }
+                self.connection.command_complete("SET").await?;
+            }
+            Statement::ShowVariable { variable } => {
+                let result = self.executor().exec_show(&variable)?;
+                self.send_result(result, result_formats).await?;
+            }
+            Statement::Deallocate { name, .. } => {
+                self.executor().exec_deallocate(&name)?;
+                self.connection.command_complete("DEALLOCATE").await?;
+            }
+            Statement::SetRole { role_name, .. } => {
+                self.executor().exec_set_role(&role_name)?;
+                self.connection.command_complete("SET").await?;
+            }
+            Statement::Grant {
+                privileges,
+                objects,
+                grantees,
+                with_grant_option,
+                ..
+            } => {
+                // The tables are locked until queries using them are finished.
+                self.run_blocking(move |executor| {
+                    executor.exec_grant(&privileges, &objects, &grantees, with_grant_option)
+                })
+                .await?;
+                self.connection.command_complete("GRANT").await?;
+            }
+            Statement::Revoke {
+                privileges,
+                objects,
+                grantees,
+                ..
+            } => {
+                self.run_blocking(move |executor| {
+                    executor.exec_revoke(&privileges, &objects, &grantees)
+                })
+                .await?;
+                self.connection.command_complete("REVOKE").await?;
+            }
+            Statement::Copy {
+                table_name,
+                columns,
+                to,
+                target,
+                options,
+                legacy_options,
+                values,
+            } => {
+                // Data is only received using the COPY sub-protocol.
+                if !values.is_empty() {
+                    bail!(SQLError::Unsupported(String::from("COPY with inline data")));
+                }
+                let options = CopyOptions::new(&options, &legacy_options)?;
+                let copied = match (to, target) {
+                    (false, CopyTarget::Stdin) => {
+                        self.exec_copy_from(&table_name, &columns, options).await?
+                    }
+                    (true, CopyTarget::Stdout) => {
+                        self.exec_copy_to(&table_name, &columns, options).await?
+                    }
+                    (to, target) => bail!(SQLError::Unsupported(format!(
+                        "COPY {} {}",
+                        if to { "TO" } else { "FROM" },
+                        target
+                    ))),
+                };
+                self.connection
+                    .command_complete(&format!("COPY {}", copied))
+                    .await?;
+            }
+            _ => bail!(SQLError::Unsupported(stmt.to_string())),
+        }
+        Ok(())
+    }
+
+    /// Insert the rows sent by the client on a COPY FROM STDIN and return the number of inserted
+    /// rows.
+    ///
+    /// Rows are inserted in batches while the data is received. If an error happens, the
+    /// remaining data is discarded and the error is returned after the client finish the copy.
+    async fn exec_copy_from(
+        &mut self,
+        table_name: &ObjectName,
+        columns: &[Ident],
+        options: CopyOptions,
+    ) -> Result<u64> {
+        let attrs = self.executor().copy_attributes(table_name, columns)?;
+        self.connection.send_copy_in_response(attrs.len()).await?;
+
+        let mut reader = CopyReader::new(options);
+        let mut rows = Vec::new();
+        let mut copied = 0;
+        let mut error = None;
+        loop {
+            match self.connection.receive().await? {
+                Message::CopyData(data) => {
+                    if error.is_some() {
+                        continue;
+                    }
+                    match reader.read(&data) {
+                        Ok(read) => rows.extend(read),
+                        Err(err) => {
+                            error = Some(err);
+                            continue;
                         }
-                        _ => bail!(SQLError::Unsupported(stmt.to_string())),
                     }
+                    if rows.len() < COPY_BATCH_SIZE {
+                        continue;
+                    }
+                    let batch = std::mem::take(&mut rows);
+                    let (table_name, columns) = (table_name.clone(), columns.to_vec());
+                    let result = self
+                        .run_blocking(move |executor| {
+                            executor.exec_copy_from(&table_name, &columns, &batch)
+                        })
+                        .await;
+                    match result {
+                        Ok(inserted) => copied += inserted,
+                        Err(err) => error = Some(err),
+                    }
+                }
+                Message::CopyDone => break,
+                Message::CopyFail(message) => bail!(PgError::new(
+                    SqlState::QueryCanceled,
+                    format!("COPY from stdin failed: {}", message)
+                )),
+                // Clients may send Flush and Sync while sending the data, which are ignored.
+                Message::Flush | Message::Sync => {}
+                message => bail!(PgError::new(
+                    SqlState::ProtocolViolation,
+                    format!("unexpected message during COPY from stdin: {:?}", message)
+                )),
+            }
+        }
+        if let Some(err) = error {
+            return Err(err);
+        }
+
+        rows.extend(reader.finish()?);
+        let (table_name, columns) = (table_name.clone(), columns.to_vec());
+        copied += self
+            .run_blocking(move |executor| executor.exec_copy_from(&table_name, &columns, &rows))
+            .await?;
+
+        Ok(copied)
+    }
+
+    /// Send all rows of the given table for a COPY TO STDOUT and return the number of rows
+    /// sent.
+    async fn exec_copy_to(
+        &mut self,
+        table_name: &ObjectName,
+        columns: &[Ident],
+        options: CopyOptions,
+    ) -> Result<u64> {
+        let (table_name, columns) = (table_name.clone(), columns.to_vec());
+        let result = self
+            .run_blocking(move |executor| executor.exec_copy_to(&table_name, &columns))
+            .await?;
+        self.connection
+            .send_copy_out_response(result.desc.fields.len())
+            .await?;
+
+        if options.header {
+            let names = result
+                .desc
+                .fields
+                .iter()
+                .map(|field| Ok(Some(String::from_utf8(field.name.clone())?)))
+                .collect::<Result<Vec<_>>>()?;
+            self.connection
+                .send_copy_data(copy::format_row(&names, &options).into_bytes())
+                .await?;
+        }
+
+        // Each row is sent on its own CopyData message.
+        for tuple in &result.tuples {
+            let row = tuple
+                .iter()
+                .zip(&result.desc.fields)
+                .map(|(datum, field)| {
+                    datum
+                        .as_ref()
+                        .map(|datum| encode::decode(datum, field.data_type_oid as Oid))
+                        .transpose()
+                })
+                .collect::<Result<Vec<_>>>()?;
+            self.connection
+                .send_copy_data(copy::format_row(&row, &options).into_bytes())
+                .await?;
+        }
+        self.connection.send_copy_done().await?;
+
+        Ok(result.tuples.len() as u64)
+    }
+
+    /// Send a tarball with a base backup of the data directory as the data of a COPY TO STDOUT.
+    ///
+    /// The files are copied on a blocking task while the chunks already copied are sent, so the
+    /// backup is never entirely kept in memory.
+    async fn exec_base_backup(&mut self) -> Result<()> {
+        let (sender, mut receiver) = mpsc::channel(BACKUP_QUEUE_SIZE);
+        let buffer_pool = self.executor().buffer_pool().clone();
+        let backup = task::spawn_blocking(move || {
+            backup::base_backup(&buffer_pool, ChannelWriter::new(sender))
+        });
+
+        // The copy is only started after the first chunk, so errors found before copying any
+        // file are reported as a regular command error.
+        let mut started = false;
+        while let Some(chunk) = receiver.recv().await {
+            if !started {
+                self.connection.send_copy_out_response(0).await?;
+                started = true;
+            }
+            self.connection.send_copy_data(chunk).await?;
+        }
+        backup.await??;
+
+        self.connection.send_copy_done().await?;
+        self.connection.command_complete("BASE_BACKUP").await
+    }
+
+    /// Execute the given statement like exec_statement, logging the statement with its duration,
+    /// number of rows and error if it runs at least log_min_duration.
+    async fn exec_statement_logged(
+        &mut self,
+        stmt: Statement,
+        result_formats: Option<&[i16]>,
+    ) -> Result<()> {
+        let log_min_duration = match self.log_min_duration {
+            Some(log_min_duration) => log_min_duration,
+            None => return self.exec_statement(stmt, result_formats).await,
+        };
+
+        let statement = stmt.to_string();
+        let start = Instant::now();
+        let result = self.exec_statement(stmt, result_formats).await;
+        let duration = start.elapsed();
+
+        if duration >= log_min_duration {
+            let millis = duration.as_secs_f64() * 1000.0;
+            match &result {
+                Ok(()) => {
+                    // The number of rows is the last word of the command tag, e.g INSERT 0 1.
+                    let rows = self
+                        .connection
+                        .last_command_tag()
+                        .and_then(|tag| tag.rsplit(' ').next())
+                        .and_then(|rows| rows.parse::<u64>().ok())
+                        .unwrap_or(0);
+                    log::info!(
+                        "duration: {:.3} ms  rows: {}  statement: {}",
+                        millis,
+                        rows,
+                        statement
+                    );
                 }
+                Err(err) => log::info!(
+                    "duration: {:.3} ms  error: {}  statement: {}",
+                    millis,
+                    err,
+                    statement
+                ),
             }
-            _ => anyhow::bail!("Unexpected message type to execute"),
+        }
+
+        result
+    }
+
+    /// Send at most max_rows rows of the query of the given portal, or all remaining rows if
+    /// max_rows is 0, using the given result formats of the portal. The portal is suspended if
+    /// the row limit is reached.
+    async fn exec_portal_rows(
+        &mut self,
+        portal: &str,
+        max_rows: i32,
+        result_formats: &[i16],
+    ) -> Result<()> {
+        let max_rows = (max_rows > 0).then_some(max_rows as u64);
+        let portal = portal.to_string();
+        let (result, suspended) = self
+            .run_blocking(move |executor| executor.exec_portal_rows(&portal, max_rows))
+            .await?;
+        let result = PGResult {
+            desc: result.desc.with_formats(result_formats)?,
+            ..result
         };
+
+        if suspended {
+            self.connection.send_suspended_rows(result).await
+        } else {
+            let tag = format!("SELECT {}", result.tuples.len());
+            self.connection.send_rows(result, &tag).await
+        }
+    }
+
+    /// Send a query result back to the client, using the given result formats of a portal.
+    async fn send_result(
+        &mut self,
+        result: PGResult,
+        result_formats: Option<&[i16]>,
+    ) -> Result<()> {
+        let tag = format!("SELECT {}", result.tuples.len());
+        self.send_result_with_tag(result, result_formats, &tag)
+            .await
+    }
+
+    /// Send the rows of a statement result like send_result, completing the statement with the
+    /// given command tag.
+    async fn send_result_with_tag(
+        &mut self,
+        result: PGResult,
+        result_formats: Option<&[i16]>,
+        tag: &str,
+    ) -> Result<()> {
+        match result_formats {
+            Some(formats) => {
+                let result = PGResult {
+                    desc: result.desc.with_formats(formats)?,
+                    ..result
+                };
+                self.connection.send_rows(result, tag).await
+            }
+            None => {
+                self.connection
+                    .send_row_description(result.desc.clone())
+                    .await?;
+                self.connection.send_rows(result, tag).await
+            }
+        }
+    }
+
+    /// Return an error if other connections are connected to the given database, e.g. before
+    /// the database is copied by CREATE DATABASE, since they could change it during the copy.
+    fn check_database_not_used(&self, datname: &str) -> Result<()> {
+        let sessions = self
+            .activities
+            .all()
+            .into_iter()
+            .filter(|(process_id, activity)| {
+                *process_id != self.key.process_id && activity.datname == datname
+            })
+            .count();
+        if sessions > 0 {
+            bail!(PgError::new(
+                SqlState::ObjectInUse,
+                format!(
+                    "source database \"{}\" is being accessed by other users",
+                    datname
+                )
+            )
+            .with_detail(format!(
+                "There are {} other session(s) using the database.",
+                sessions
+            )));
+        }
         Ok(())
     }
 }
 
+impl<S: Stream> Drop for Handler<S> {
+    fn drop(&mut self) {
+        self.cancel_keys.unregister(&self.key);
+        self.activities.unregister(self.key.process_id);
+    }
+}
+
 impl Backend {
     /// Create a new backend using the given listener to accept incoming tcp connections. The given
     /// buffer pool is shared with all connections handlers.
-    pub fn new(listener: TcpListener, buffer_pool: BufferPool) -> Self {
+    pub fn new(listener: TcpListener, buffer_pool: BufferPool, auth_method: AuthMethod) -> Self {
+        let activities = Activities::default();
+        let mut virtual_tables = VirtualTables::builtin();
+        virtual_tables.register(Arc::new(PgStatActivity::new(activities.clone())));
+
         Self {
             listener,
+            unix_listener: None,
             buffer_pool,
+            auth_method,
+            cancel_keys: CancelKeys::default(),
+            activities,
+            virtual_tables,
+            log_min_duration: None,
+            session_variables: SessionVariables::default(),
+            idle_session_timeout: None,
+            tcp_keepalive: TcpKeepalive::new(),
+            connection_slots: Arc::new(Semaphore::new(DEFAULT_MAX_CONNECTIONS)),
+            authentication_timeout: DEFAULT_AUTHENTICATION_TIMEOUT,
         }
     }
 
+    /// Accept at most the given number of open connections. Clients connecting while all
+    /// connections are in use are rejected.
+    pub fn with_max_connections(mut self, max_connections: usize) -> Self {
+        self.connection_slots = Arc::new(Semaphore::new(max_connections));
+        self
+    }
+
+    /// Close new connections that don't complete the startup and authentication within the
+    /// given duration.
+    pub fn with_authentication_timeout(mut self, authentication_timeout: Duration) -> Self {
+        self.authentication_timeout = authentication_timeout;
+        self
+    }
+
+    /// Close connections that don't send any message for the given duration while they are
+    /// idle.
+    pub fn with_idle_session_timeout(mut self, idle_session_timeout: Option<Duration>) -> Self {
+        self.idle_session_timeout = idle_session_timeout;
+        self
+    }
+
+    /// Send keepalive probes on idle TCP connections with the given parameters.
+    pub fn with_tcp_keepalive(mut self, tcp_keepalive: TcpKeepalive) -> Self {
+        self.tcp_keepalive = tcp_keepalive;
+        self
+    }
+
+    /// Log each statement that runs at least the given duration.
+    pub fn with_log_min_duration(mut self, log_min_duration: Option<Duration>) -> Self {
+        self.log_min_duration = log_min_duration;
+        self
+    }
+
+    /// Use the given session variables as the default values of new connections.
+    pub fn with_session_variables(mut self, session_variables: SessionVariables) -> Self {
+        self.session_variables = session_variables;
+        self
+    }
+
+    /// Also accept connections on the given unix domain socket listener.
+    pub fn with_unix_listener(mut self, listener: UnixListener) -> Self {
+        self.unix_listener = Some(listener);
+        self
+    }
+
     /// Start the backend.
     ///
     /// Listen for inbound connections. For each inbound connection, spawn a
-    /// task to process that connection.
+    /// task to authenticate and process that connection.
     pub async fn start(&self) -> Result<()> {
         log::info!("database system is ready to accept connections");
         loop {
-            let (socket, _) = self.listener.accept().await?;
-
-            let mut connection = Connection::new(socket);
+            tokio::select! {
+                res = self.listener.accept() => {
+                    let (socket, _) = res?;
+                    if let Err(err) = SockRef::from(&socket).set_tcp_keepalive(&self.tcp_keepalive) {
+                        log::warn!("failed to enable keepalive on connection: {}", err);
+                    }
+                    self.spawn_handler(Connection::new(socket));
+                }
+                res = accept_unix(&self.unix_listener) => {
+                    let (socket, _) = res?;
+                    self.spawn_handler(Connection::new(socket));
+                }
+            }
+        }
+    }
 
-            let startup_message = connection.startup_message().await?;
+    /// Spawn a new task to authenticate and process the given connection.
+    fn spawn_handler<S: Stream + 'static>(&self, connection: Connection<S>) {
+        let buffer_pool = self.buffer_pool.clone();
+        let auth_method = self.auth_method;
+        let cancel_keys = self.cancel_keys.clone();
+        let activities = self.activities.clone();
+        let virtual_tables = self.virtual_tables.clone();
+        let log_min_duration = self.log_min_duration;
+        let idle_session_timeout = self.idle_session_timeout;
+        let session_variables = self.session_variables.clone();
+        let connection_slots = self.connection_slots.clone();
+        let authentication_timeout = self.authentication_timeout;
 
-            match executor_config_from_startup_parameters(
-                &self.buffer_pool,
-                startup_message.parameters,
-            ) {
-                Ok(config) => {
-                    connection.send_authentication_ok().await?;
+        // The startup is handled inside the connection task, so a client waiting to send
+        // its password don't block new connections.
+        task::spawn(async move {
+            if let Some(mut handler) = startup(
+                connection,
+                buffer_pool,
+                auth_method,
+                cancel_keys,
+                activities,
+                virtual_tables,
+                session_variables,
+                connection_slots,
+                authentication_timeout,
+            )
+            .await
+            {
+                handler.log_min_duration = log_min_duration;
+                handler.idle_session_timeout = idle_session_timeout;
+                if let Err(err) = handler.run().await {
+                    log::error!("connection serve error: {}", err);
+                }
+            }
+        });
+    }
+}
 
-                    let mut handler = Handler {
-                        connection,
-                        conn_executor: ConnectionExecutor::new(config, self.buffer_pool.clone()),
-                    };
+/// Accept a new connection on the given unix domain socket listener. Never complete if there is
+/// no unix listener.
+async fn accept_unix(
+    listener: &Option<UnixListener>,
+) -> io::Result<(UnixStream, unix::SocketAddr)> {
+    match listener {
+        Some(listener) => listener.accept().await,
+        None => std::future::pending().await,
+    }
+}
 
-                    task::spawn(async move {
-                        if let Err(err) = handler.run().await {
-                            log::error!("connection serve error: {}", err);
-                        }
-                    });
-                }
-                Err(err) => {
-                    log::error!("failed to authenticate: {}", err);
-                    connection.send_error(err).await?;
+/// Handle the startup message and authentication of the given connection.
+///
+/// Return None if the client failed to authenticate or the startup parameters are invalid, in
+/// this case the error is already sent back to the client. None is also returned for cancel
+/// requests, which close the connection after canceling the query of the requested connection.
+///
+/// Each connection takes one of the given connection slots before authenticating the client, and
+/// is rejected if all slots are taken. Cancel requests don't take a slot, so running queries can
+/// still be canceled when all connections are in use. The connection is closed if the startup
+/// doesn't complete within the given timeout, so a client stalled before authenticating doesn't
+/// keep its slot.
+#[allow(clippy::too_many_arguments)]
+async fn startup<S: Stream>(
+    mut connection: Connection<S>,
+    buffer_pool: BufferPool,
+    auth_method: AuthMethod,
+    cancel_keys: CancelKeys,
+    activities: Activities,
+    virtual_tables: VirtualTables,
+    session_variables: SessionVariables,
+    connection_slots: Arc<Semaphore>,
+    authentication_timeout: Duration,
+) -> Option<Handler<S>> {
+    let result = time::timeout(authentication_timeout, async {
+        let startup_message = match connection.startup_message().await? {
+            Message::StartupMessage(startup_message) => startup_message,
+            Message::CancelRequest(key) => {
+                // Like Postgres, nothing is sent back to the client, even if the key is invalid.
+                if !cancel_keys.cancel(&key) {
+                    log::warn!("cancel request for invalid key {}", key.process_id);
                 }
+                return Ok(None);
             }
+            message => bail!(PgError::new(
+                SqlState::ProtocolViolation,
+                format!("unexpected startup message {:?}", message)
+            )),
+        };
+
+        let connection_slot = connection_slots.try_acquire_owned().map_err(|_| {
+            anyhow!(PgError::new(
+                SqlState::TooManyConnections,
+                "sorry, too many clients already"
+            ))
+        })?;
+
+        let user = startup_message
+            .parameters
+            .get("user")
+            .ok_or_else(|| {
+                anyhow!(PgError::new(
+                    SqlState::InvalidAuthorizationSpecification,
+                    "user name does not exists on connection parameters"
+                ))
+            })?
+            .clone();
+
+        let role = auth::authenticate(&mut connection, &buffer_pool, auth_method, &user).await?;
+
+        let datname = startup_message
+            .parameters
+            .get("database")
+            .cloned()
+            .unwrap_or_default();
+        let config = executor_config_from_startup_parameters(
+            &buffer_pool,
+            startup_message.parameters,
+            session_variables,
+            virtual_tables,
+            role.oid,
+        )?;
+        Ok(Some((config, datname, user, connection_slot)))
+    })
+    .await
+    .unwrap_or_else(|_| {
+        Err(anyhow!(PgError::new(
+            SqlState::QueryCanceled,
+            "canceling authentication due to timeout"
+        )))
+    });
+
+    match result {
+        Ok(None) => None,
+        Ok(Some((config, datname, usename, connection_slot))) => {
+            let conn_executor = ConnectionExecutor::new(config, buffer_pool);
+            let key = cancel_keys.register(conn_executor.cancel_flag());
+
+            // Clients connected over TCP are reported only by their IP address, like Postgres.
+            let peer = connection.peer();
+            let client_addr = match peer.parse::<SocketAddr>() {
+                Ok(addr) => addr.ip().to_string(),
+                Err(_) => peer,
+            };
+            let application_name = conn_executor
+                .variables()
+                .get("application_name")
+                .unwrap_or_default();
+            activities.register(
+                key.process_id,
+                Activity::new(&datname, &usename, application_name, &client_addr),
+            );
+
+            let mut handler = Handler {
+                connection,
+                conn_executor: Some(conn_executor),
+                key,
+                cancel_keys,
+                activities,
+                ignore_till_sync: false,
+                log_min_duration: None,
+                idle_session_timeout: None,
+                _connection_slot: connection_slot,
+            };
+            let parameters = match &handler.conn_executor {
+                Some(conn_executor) => conn_executor.variables().all_reported(),
+                None => unreachable!("no statement runs before the startup is completed"),
+            };
+            if let Err(err) = handler
+                .connection
+                .send_authentication_ok(parameters, key)
+                .await
+            {
+                log::error!("connection serve error: {}", err);
+                return None;
+            }
+            Some(handler)
+        }
+        Err(err) => {
+            log::error!("failed to authenticate: {}", err);
+            // Startup errors close the connection.
+            let err = PgError::from_error(&err).with_severity(Severity::Fatal);
+            if let Err(err) = connection.send_error(anyhow!(err)).await {
+                log::error!("connection serve error: {}", err);
+            }
+            None
         }
     }
 }
@@ -154,6 +1257,87 @@ pub struct Config {
 
     /// Size of buffer pool.
     pub buffer_pool_size: usize,
+
+    /// Method used to authenticate clients. AuthMethod::Trust disable the authentication.
+    pub auth_method: AuthMethod,
+
+    /// Time between automatic checkpoints.
+    pub checkpoint_interval: Duration,
+
+    /// Time between background writer rounds.
+    pub bgwriter_delay: Duration,
+
+    /// Maximum number of buffers written by the background writer on each round. Zero disables
+    /// the background writer.
+    pub bgwriter_lru_maxpages: usize,
+
+    /// Number of pages that sequential scans read ahead in background. Zero disables the
+    /// read-ahead.
+    pub prefetch_depth: usize,
+
+    /// Open relation files with direct I/O, bypassing the page cache of the operating system.
+    pub direct_io: bool,
+
+    /// Maximum number of relation files kept open.
+    pub max_open_files: usize,
+
+    /// Policy used to replace buffers when the buffer pool is full.
+    pub replacement_policy: ReplacementPolicy,
+
+    /// Verify the checksum of pages read from disk.
+    pub data_checksums: bool,
+
+    /// Force changes to be stored on the physical device on WAL flushes and checkpoints.
+    pub fsync: bool,
+
+    /// Method used to force WAL writes to disk.
+    pub wal_sync_method: WalSyncMethod,
+
+    /// Log each statement that runs at least this duration. None if statements are not logged.
+    pub log_min_duration: Option<Duration>,
+
+    /// Address to serve metrics over HTTP in Prometheus text format. None if metrics are not
+    /// served.
+    pub metrics_addr: Option<String>,
+
+    /// Path of the unix domain socket to also accept connections. None if connections are only
+    /// accepted over TCP.
+    pub unix_socket: Option<PathBuf>,
+
+    /// Default values of session variables, reported to clients on connection startup.
+    pub session_variables: SessionVariables,
+
+    /// Close connections that are idle for more than this duration. None if idle connections
+    /// are never closed.
+    pub idle_session_timeout: Option<Duration>,
+
+    /// Time that a TCP connection is idle before the first keepalive probe is sent. None to use
+    /// the default of the operating system.
+    pub tcp_keepalives_idle: Option<Duration>,
+
+    /// Time between keepalive probes that are not acknowledged by the client. None to use the
+    /// default of the operating system.
+    pub tcp_keepalives_interval: Option<Duration>,
+
+    /// Maximum number of connections open at the same time.
+    pub max_connections: usize,
+
+    /// Maximum time to complete the startup and authentication of a new connection.
+    pub authentication_timeout: Duration,
+}
+
+impl Config {
+    /// Return the parameters of keepalive probes sent on idle TCP connections.
+    fn tcp_keepalive(&self) -> TcpKeepalive {
+        let mut keepalive = TcpKeepalive::new();
+        if let Some(idle) = self.tcp_keepalives_idle {
+            keepalive = keepalive.with_time(idle);
+        }
+        if let Some(interval) = self.tcp_keepalives_interval {
+            keepalive = keepalive.with_interval(interval);
+        }
+        keepalive
+    }
 }
 
 /// Start the tinydb backend server.
@@ -166,12 +1350,115 @@ pub struct Config {
 /// `tokio::signal::ctrl_c()` can be used as the `shutdown` argument. This will
 /// listen for a SIGINT signal.
 pub async fn start(config: &Config, listener: TcpListener, shutdown: impl Future) {
+    let control = match ControlFile::open(&config.data_dir) {
+        Ok(control) => control,
+        Err(err) => {
+            log::error!("failed to open control file: {}", err);
+            return;
+        }
+    };
+    match control.data().state {
+        ClusterState::Shutdown => log::info!("database system was shut down"),
+        state => log::info!(
+            "database system was interrupted while {}; automatic recovery in progress",
+            state
+        ),
+    }
+
+    let wal = Wal::open_with_sync_method(&config.data_dir, config.fsync, config.wal_sync_method);
+    let wal = match wal {
+        Ok(wal) => wal,
+        Err(err) => {
+            log::error!("failed to open write-ahead log: {}", err);
+            return;
+        }
+    };
+
     let buffer = BufferPool::new(
         config.buffer_pool_size,
-        StorageManager::new(&config.data_dir),
-    );
+        StorageManager::new(&config.data_dir)
+            .with_direct_io(config.direct_io)
+            .with_max_open_files(config.max_open_files),
+    )
+    .with_replacement_policy(config.replacement_policy)
+    .with_checksum_verification(config.data_checksums)
+    .with_fsync(config.fsync)
+    .with_prefetch_depth(config.prefetch_depth)
+    .with_control_file(control.clone())
+    .with_wal(wal);
 
-    let backend = Backend::new(listener, buffer.clone());
+    // Replay all changes that was not written on relation files before the
+    // last shutdown.
+    if let Err(err) = wal::recover(&buffer) {
+        log::error!("failed to recover from write-ahead log: {}", err);
+        return;
+    }
+    if let Err(err) = control.set_state(ClusterState::InProduction) {
+        log::error!("failed to update control file: {}", err);
+        return;
+    }
+
+    let checkpointer = task::spawn(checkpointer(buffer.clone(), config.checkpoint_interval));
+    let bgwriter = if config.bgwriter_lru_maxpages > 0 {
+        Some(task::spawn(bgwriter(
+            buffer.clone(),
+            config.bgwriter_delay,
+            config.bgwriter_lru_maxpages,
+        )))
+    } else {
+        None
+    };
+
+    let mut backend = Backend::new(listener, buffer.clone(), config.auth_method)
+        .with_session_variables(config.session_variables.clone())
+        .with_log_min_duration(config.log_min_duration)
+        .with_idle_session_timeout(config.idle_session_timeout)
+        .with_tcp_keepalive(config.tcp_keepalive())
+        .with_max_connections(config.max_connections)
+        .with_authentication_timeout(config.authentication_timeout);
+
+    if let Some(unix_socket) = &config.unix_socket {
+        // Remove the socket file of a server that was not shut down cleanly.
+        if unix_socket.exists() {
+            if let Err(err) = fs::remove_file(unix_socket) {
+                log::error!(
+                    "failed to remove old unix socket {:?}: {}",
+                    unix_socket,
+                    err
+                );
+                return;
+            }
+        }
+        match UnixListener::bind(unix_socket) {
+            Ok(listener) => {
+                log::info!("listening on unix socket {:?}", unix_socket);
+                backend = backend.with_unix_listener(listener);
+            }
+            Err(err) => {
+                log::error!("failed to bind unix socket {:?}: {}", unix_socket, err);
+                return;
+            }
+        }
+    }
+
+    let metrics = match &config.metrics_addr {
+        Some(addr) => match TcpListener::bind(addr).await {
+            Ok(listener) => {
+                log::info!("serving metrics on address {}", addr);
+                let metrics = Metrics::new(buffer.clone(), backend.activities.clone());
+                Some(task::spawn(async move {
+                    if let Err(err) = metrics::serve(listener, metrics).await {
+                        log::error!("failed to accept metrics connection: {}", err);
+                    }
+                }))
+            }
+            Err(err) => {
+                log::error!("failed to bind metrics address {}: {}", addr, err);
+                return;
+            }
+        },
+        None => None,
+    };
 
     tokio::select! {
         res = backend.start() => {
@@ -188,30 +1475,159 @@ pub async fn start(config: &Config, listener: TcpListener, shutdown: impl Future
         _ = shutdown => {
             // Shutdown signal has been received.
             //
-            // The buffer pool will be droped at this point that will force all
+            // Stop the checkpointer and perform a last checkpoint to force all
             // in memory dirty pages to be written on disk.
-            drop(buffer);
+            checkpointer.abort();
+            if let Some(bgwriter) = &bgwriter {
+                bgwriter.abort();
+            }
+            if let Some(metrics) = &metrics {
+                metrics.abort();
+            }
+            if let Err(err) = shutdown_checkpoint(&buffer, &control) {
+                log::error!("failed to perform shutdown checkpoint: {}", err);
+            }
 
             log::info!("shutting down");
         }
     }
+
+    if let Some(unix_socket) = &config.unix_socket {
+        if let Err(err) = fs::remove_file(unix_socket) {
+            log::error!("failed to remove unix socket {:?}: {}", unix_socket, err);
+        }
+    }
+}
+
+/// Perform the last checkpoint before shutting down, marking the cluster as shut down on the
+/// control file after all changes are stored on disk.
+fn shutdown_checkpoint(buffer: &BufferPool, control: &ControlFile) -> Result<()> {
+    control.set_state(ClusterState::ShuttingDown)?;
+    buffer.checkpoint()?;
+    control.set_state(ClusterState::Shutdown)
+}
+
+/// Periodically perform a checkpoint on the given buffer pool, so that dirty pages are written on
+/// relation files and the write-ahead log that needs to be replayed on recovery is bounded.
+async fn checkpointer(buffer_pool: BufferPool, interval: Duration) {
+    let mut interval = time::interval(interval);
+
+    // The first tick completes immediately.
+    interval.tick().await;
+
+    loop {
+        interval.tick().await;
+
+        log::debug!("starting checkpoint");
+        let buffer_pool = buffer_pool.clone();
+        match task::spawn_blocking(move || buffer_pool.checkpoint()).await {
+            Ok(Ok(())) => log::debug!("checkpoint complete"),
+            Ok(Err(err)) => log::error!("failed to perform checkpoint: {}", err),
+            Err(err) => log::error!("checkpointer task failed: {}", err),
+        }
+    }
 }
 
-/// Return the connection executor configuration for the given map of connection parameters.
+/// Periodically write dirty buffers that are not pinned, so that backends rarely need to write a
+/// page before reusing its buffer. Each round writes at most max_pages buffers and stops when 1/4
+/// of the buffer pool is free or clean.
+async fn bgwriter(buffer_pool: BufferPool, delay: Duration, max_pages: usize) {
+    let mut interval = time::interval(delay);
+    loop {
+        interval.tick().await;
+
+        let buffer_pool = buffer_pool.clone();
+        let clean_target = buffer_pool.size() / 4;
+        match task::spawn_blocking(move || buffer_pool.write_dirty_buffers(max_pages, clean_target))
+            .await
+        {
+            Ok(Ok(_)) => {}
+            Ok(Err(err)) => log::error!("background writer failed to write buffers: {}", err),
+            Err(err) => log::error!("background writer task failed: {}", err),
+        }
+    }
+}
+
+/// Return the connection executor configuration for the given map of connection parameters, of
+/// a session started by the given role.
 ///
 /// The database name is read from the given parameters and the respective OID is searched on
-/// database catalog, an error is returned if the database don't exists.
+/// database catalog, an error is returned if the database don't exists. Other parameters
+/// override the given default values of session variables, unknown parameters are ignored.
 ///
 // TODO: Make this HashMap of connection parameters into a struct.
 fn executor_config_from_startup_parameters(
     buffer_pool: &BufferPool,
     parameters: HashMap<String, String>,
+    mut variables: SessionVariables,
+    virtual_tables: VirtualTables,
+    session_role: Oid,
 ) -> Result<ExecutorConfig> {
-    let dbname = parameters
-        .get("database")
-        .ok_or_else(|| anyhow!("database name does not exists on connection parameters",))?;
+    let dbname = parameters.get("database").ok_or_else(|| {
+        anyhow!(PgError::new(
+            SqlState::ProtocolViolation,
+            "database name does not exists on connection parameters"
+        ))
+    })?;
+
+    let dboid = database::connect_database(buffer_pool, dbname)?;
+
+    for (name, value) in &parameters {
+        if name != "user" && name != "database" && variables.get(name).is_ok() {
+            variables.set(name, value)?;
+        }
+    }
+
+    Ok(ExecutorConfig {
+        database: dboid,
+        variables,
+        virtual_tables,
+        session_role,
+    })
+}
+
+/// Writer that sends the written data on a channel, on chunks of BACKUP_CHUNK_SIZE bytes.
+struct ChannelWriter {
+    sender: mpsc::Sender<Vec<u8>>,
+
+    /// Data written and not sent yet.
+    buffer: Vec<u8>,
+}
+
+impl ChannelWriter {
+    fn new(sender: mpsc::Sender<Vec<u8>>) -> Self {
+        Self {
+            sender,
+            buffer: Vec::with_capacity(BACKUP_CHUNK_SIZE),
+        }
+    }
 
-    let dboid = get_datase_oid(buffer_pool, dbname)?;
+    /// Send the buffered data, waiting if the channel is full.
+    fn send(&mut self) -> io::Result<()> {
+        let chunk = std::mem::replace(&mut self.buffer, Vec::with_capacity(BACKUP_CHUNK_SIZE));
+        self.sender.blocking_send(chunk).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "connection closed while sending data",
+            )
+        })
+    }
+}
 
-    Ok(ExecutorConfig { database: dboid })
+impl io::Write for ChannelWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let len = data.len().min(BACKUP_CHUNK_SIZE - self.buffer.len());
+        self.buffer.extend_from_slice(&data[..len]);
+        if self.buffer.len() == BACKUP_CHUNK_SIZE {
+            self.send()?;
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.buffer.is_empty() {
+            self.send()?;
+        }
+        Ok(())
+    }
 }
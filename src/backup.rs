@@ -0,0 +1,343 @@
+//! Online base backups of a running server.
+//!
+//! A base backup is a tarball with all files of the data directory, copied while the server keeps
+//! running. The backup starts with a checkpoint and holds off later checkpoints and relation
+//! unlinks until the copy is finished, so the WAL copied at the end has all changes made to the
+//! relation files after the checkpoint. Starting a server on the extracted tarball redo these
+//! changes, as after a crash, and the data directory is consistent again.
+
+use std::{
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Result};
+
+use crate::{
+    sql::error::{PgError, SqlState},
+    storage::{
+        buffile::TEMP_FILES_DIR,
+        wal::{WAL_DIR, WAL_FILE},
+        BufferPool, PAGE_SIZE,
+    },
+};
+
+/// Size of each block of a tar archive.
+const TAR_BLOCK_SIZE: usize = 512;
+
+/// Number of bytes copied at once from the data files, a multiple of the page size so pages are
+/// always read entirely.
+const COPY_CHUNK_SIZE: usize = 8 * PAGE_SIZE;
+
+/// Return true if the given query is the BASE_BACKUP command.
+pub fn is_base_backup(query: &str) -> bool {
+    query
+        .trim()
+        .trim_end_matches(';')
+        .trim_end()
+        .eq_ignore_ascii_case("BASE_BACKUP")
+}
+
+/// Write a tarball with all files of the data directory of the given buffer pool on the given
+/// writer.
+///
+/// The WAL file is the last file of the tarball, copied up to the last record flushed when all
+/// other files were already copied.
+pub fn base_backup<W: Write>(buffer_pool: &BufferPool, out: W) -> Result<()> {
+    let data_dir = match buffer_pool.data_dir() {
+        Some(data_dir) => data_dir,
+        None => bail!(PgError::new(
+            SqlState::FeatureNotSupported,
+            "base backups are not supported on in-memory databases"
+        )),
+    };
+
+    buffer_pool.checkpoint()?;
+    let _backup = buffer_pool.start_backup();
+    log::info!("starting base backup of {:?}", data_dir);
+
+    let wal_path = Path::new(WAL_DIR).join(WAL_FILE);
+    let mut tar = TarWriter::new(out);
+    for path in data_dir_entries(&data_dir, Path::new(""))? {
+        let full_path = data_dir.join(&path);
+        let metadata = fs::symlink_metadata(&full_path)?;
+        if metadata.is_dir() {
+            tar.append_dir(&path)?;
+        } else if metadata.is_file() && path != wal_path {
+            copy_file(buffer_pool, &mut tar, &full_path, &path, metadata.len())?;
+        }
+    }
+
+    if let Some(wal) = buffer_pool.wal() {
+        wal.flush_all()?;
+        copy_file(
+            buffer_pool,
+            &mut tar,
+            &data_dir.join(&wal_path),
+            &wal_path,
+            wal.flushed_size(),
+        )?;
+    }
+
+    tar.finish()?;
+    log::info!("base backup completed");
+    Ok(())
+}
+
+/// Return the relative paths of all entries inside the given directory of the data directory,
+/// recursively. Each directory comes before its entries, and entries are sorted by name.
+fn data_dir_entries(data_dir: &Path, dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut names = fs::read_dir(data_dir.join(dir))?
+        .map(|entry| Ok(entry?.file_name()))
+        .collect::<Result<Vec<_>>>()?;
+    names.sort();
+
+    let mut entries = Vec::new();
+    for name in names {
+        let path = dir.join(name);
+        let is_dir = fs::symlink_metadata(data_dir.join(&path))?.is_dir();
+        entries.push(path.clone());
+        // Temporary files are removed at startup, so only their directory is copied.
+        if is_dir && path != Path::new(TEMP_FILES_DIR) {
+            entries.extend(data_dir_entries(data_dir, &path)?);
+        }
+    }
+    Ok(entries)
+}
+
+/// Append the first size bytes of the given file on the tarball. Files that were truncated after
+/// their size was read are filled with zeros, which are removed by the recovery as partially
+/// written pages.
+fn copy_file<W: Write>(
+    buffer_pool: &BufferPool,
+    tar: &mut TarWriter<W>,
+    full_path: &Path,
+    path: &Path,
+    size: u64,
+) -> Result<()> {
+    let mut file = File::open(full_path)?;
+    tar.append_file_header(path, size)?;
+
+    let mut chunk = vec![0; COPY_CHUNK_SIZE];
+    let mut remaining = size;
+    while remaining > 0 {
+        let len = remaining.min(COPY_CHUNK_SIZE as u64) as usize;
+        let read = buffer_pool.read_data_file(&mut file, &mut chunk[..len])?;
+        chunk[read..len].fill(0);
+        tar.write_data(&chunk[..len])?;
+        remaining -= len as u64;
+    }
+    tar.finish_file(size)
+}
+
+/// Writer of tar archives on the ustar format.
+struct TarWriter<W: Write> {
+    out: W,
+}
+
+impl<W: Write> TarWriter<W> {
+    fn new(out: W) -> Self {
+        Self { out }
+    }
+
+    /// Append a directory entry.
+    fn append_dir(&mut self, path: &Path) -> Result<()> {
+        let header = tar_header(path, b'5', 0o755, 0)?;
+        self.out.write_all(&header)?;
+        Ok(())
+    }
+
+    /// Append the header of a file with the given size. The data of the file must be written
+    /// next using write_data followed by finish_file.
+    fn append_file_header(&mut self, path: &Path, size: u64) -> Result<()> {
+        let header = tar_header(path, b'0', 0o600, size)?;
+        self.out.write_all(&header)?;
+        Ok(())
+    }
+
+    /// Write data of the current file.
+    fn write_data(&mut self, data: &[u8]) -> Result<()> {
+        self.out.write_all(data)?;
+        Ok(())
+    }
+
+    /// Pad the data of a file of the given size to a whole block.
+    fn finish_file(&mut self, size: u64) -> Result<()> {
+        let padding = (TAR_BLOCK_SIZE - (size as usize % TAR_BLOCK_SIZE)) % TAR_BLOCK_SIZE;
+        self.out.write_all(&vec![0; padding])?;
+        Ok(())
+    }
+
+    /// Write the end of the archive, two empty blocks.
+    fn finish(mut self) -> Result<()> {
+        self.out.write_all(&[0; 2 * TAR_BLOCK_SIZE])?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// Return a ustar header block of an entry with the given path, type, mode and size.
+fn tar_header(path: &Path, typeflag: u8, mode: u32, size: u64) -> Result<[u8; TAR_BLOCK_SIZE]> {
+    let mut name = match path.to_str() {
+        Some(name) => name.to_string(),
+        None => bail!("path {:?} is not valid UTF-8", path),
+    };
+    if typeflag == b'5' {
+        name.push('/');
+    }
+    if name.len() > 100 {
+        bail!("path {} is too long to be stored on a tar archive", name);
+    }
+    // The size field has 11 octal digits.
+    if size >= 1 << 33 {
+        bail!("file {} is too large to be stored on a tar archive", name);
+    }
+
+    let mut header = [0; TAR_BLOCK_SIZE];
+    header[..name.len()].copy_from_slice(name.as_bytes());
+    put_octal(&mut header[100..108], mode as u64);
+    put_octal(&mut header[108..116], 0);
+    put_octal(&mut header[116..124], 0);
+    put_octal(&mut header[124..136], size);
+    put_octal(&mut header[136..148], 0);
+    header[156] = typeflag;
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+
+    // The checksum is computed with the checksum field filled with spaces.
+    header[148..156].fill(b' ');
+    let checksum: u64 = header.iter().map(|byte| *byte as u64).sum();
+    put_octal(&mut header[148..155], checksum);
+    Ok(header)
+}
+
+/// Store the given value on the given field as a NUL terminated octal number, padded with zeros.
+fn put_octal(field: &mut [u8], value: u64) {
+    let digits = format!("{:0width$o}", value, width = field.len() - 1);
+    field[..digits.len()].copy_from_slice(digits.as_bytes());
+    field[digits.len()] = 0;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::mpsc, thread, time::Duration};
+
+    use super::*;
+    use crate::{access, database::Database, storage::smgr::StorageManager};
+
+    /// Parse the given tar archive, returning the data of each file and None for directories.
+    fn read_tar(mut data: &[u8]) -> Result<HashMap<String, Option<Vec<u8>>>> {
+        let octal = |field: &[u8]| -> Result<u64> {
+            let digits = std::str::from_utf8(field)?.trim_end_matches(['\0', ' ']);
+            Ok(u64::from_str_radix(digits, 8)?)
+        };
+
+        let mut entries = HashMap::new();
+        while data[..TAR_BLOCK_SIZE].iter().any(|byte| *byte != 0) {
+            let header = &data[..TAR_BLOCK_SIZE];
+            let mut sum: u64 = header.iter().map(|byte| *byte as u64).sum();
+            sum -= header[148..156]
+                .iter()
+                .map(|byte| *byte as u64)
+                .sum::<u64>();
+            sum += 8 * b' ' as u64;
+            assert_eq!(octal(&header[148..156])?, sum);
+            assert_eq!(&header[257..263], b"ustar\0");
+
+            let name = std::str::from_utf8(&header[..100])?.trim_end_matches('\0');
+            let size = octal(&header[124..136])? as usize;
+            data = &data[TAR_BLOCK_SIZE..];
+            if header[156] == b'5' {
+                entries.insert(name.trim_end_matches('/').to_string(), None);
+            } else {
+                entries.insert(name.to_string(), Some(data[..size].to_vec()));
+                data = &data[size.div_ceil(TAR_BLOCK_SIZE) * TAR_BLOCK_SIZE..];
+            }
+        }
+        assert_eq!(data, &[0; 2 * TAR_BLOCK_SIZE]);
+        Ok(entries)
+    }
+
+    #[test]
+    fn test_tar_writer() -> Result<()> {
+        let mut data = Vec::new();
+        let mut tar = TarWriter::new(&mut data);
+        tar.append_dir(Path::new("base"))?;
+        tar.append_file_header(Path::new("base/1"), 3)?;
+        tar.write_data(b"abc")?;
+        tar.finish_file(3)?;
+        tar.finish()?;
+        assert_eq!(data.len(), 5 * TAR_BLOCK_SIZE);
+
+        let entries = read_tar(&data)?;
+        assert_eq!(entries["base"], None);
+        assert_eq!(entries["base/1"], Some(b"abc".to_vec()));
+
+        assert!(tar_header(&Path::new("a").join("b".repeat(100)), b'0', 0o600, 0).is_err());
+        assert!(is_base_backup(" base_backup; "));
+        assert!(!is_base_backup("SELECT 1"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_base_backup() -> Result<()> {
+        let data_dir = tempfile::tempdir()?;
+
+        let mut data = Vec::new();
+        {
+            let mut db = Database::open(data_dir.path())?;
+            db.execute("CREATE TABLE t(a int); INSERT INTO t VALUES (1), (2);")?;
+            db.base_backup(&mut data)?;
+            // Changes made after the backup are not restored.
+            db.execute("INSERT INTO t VALUES (3);")?;
+        }
+
+        let entries = read_tar(&data)?;
+        assert!(entries.contains_key("global"));
+        assert!(entries.contains_key("pg_wal/wal"));
+
+        let restored = tempfile::tempdir()?;
+        for (name, contents) in &entries {
+            let path = restored.path().join(name);
+            match contents {
+                Some(contents) => {
+                    fs::create_dir_all(path.parent().unwrap())?;
+                    fs::write(path, contents)?;
+                }
+                None => fs::create_dir_all(path)?,
+            }
+        }
+
+        let mut db = Database::open(restored.path())?;
+        assert_eq!(db.execute("SELECT * FROM t;")?, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_backup_holds_off_unlinks() -> Result<()> {
+        let buffer_pool = BufferPool::new(10, StorageManager::in_memory());
+        assert!(base_backup(&buffer_pool, Vec::new()).is_err());
+
+        let rel = access::open_relation(10000, 1663, &5, "t");
+        buffer_pool.alloc_buffer(&rel)?;
+
+        let backup = buffer_pool.start_backup();
+        let (sender, receiver) = mpsc::channel();
+        let unlink = {
+            let (buffer_pool, rel) = (buffer_pool.clone(), rel.clone());
+            thread::spawn(move || {
+                let result = buffer_pool.drop_relation(&rel);
+                sender.send(()).unwrap();
+                result
+            })
+        };
+        assert!(receiver.recv_timeout(Duration::from_millis(100)).is_err());
+
+        drop(backup);
+        receiver.recv()?;
+        unlink.join().unwrap()?;
+        assert_eq!(buffer_pool.size_of_relation(&rel)?, 0);
+        Ok(())
+    }
+}
@@ -1,53 +1,165 @@
-use std::env;
+use std::{
+    env,
+    fs::File,
+    io::{self, BufReader, BufWriter, IsTerminal},
+    path::Path,
+    time::Duration,
+};
+
+use anyhow::bail;
 
 use structopt::StructOpt;
 use tinydb::{
     backend,
-    cli::Flags,
-    initdb::init_database,
-    storage::{smgr::StorageManager, BufferPool},
+    cli::{Command, Flags},
+    database::Database,
+    initdb::{self, init_database, is_initialized, remove_database},
+    shell::Shell,
+    storage::{
+        control::{ClusterState, ControlFile},
+        smgr::StorageManager,
+        wal::Wal,
+        BufferPool,
+    },
+    upgrade::upgrade_database,
 };
 use tokio::{net::TcpListener, signal};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let flags = Flags::from_args();
+    let settings = flags.settings()?;
 
     stderrlog::new()
         .module(module_path!())
         .quiet(flags.quiet)
         .timestamp(stderrlog::Timestamp::Second)
-        .verbosity(flags.log_level)
+        .verbosity(settings.log_level)
         .init()
         .unwrap();
 
     let cwd = env::current_dir().expect("Failed to get current working directory");
-    let data_dir = cwd.join(&flags.data_dir);
+    let data_dir = cwd.join(&settings.data_dir);
+
+    if flags.command == Some(Command::Upgrade) {
+        log::info!("upgrading database directory");
+        upgrade_database(&data_dir)?;
+        return Ok(());
+    }
+
+    match &flags.command {
+        Some(Command::Dump { file }) => {
+            let mut db = open_offline(&data_dir, false)?;
+            match file {
+                Some(file) => db.dump(BufWriter::new(File::create(file)?))?,
+                None => db.dump(io::stdout().lock())?,
+            }
+            return Ok(());
+        }
+        Some(Command::Restore { file }) => {
+            let mut db = open_offline(&data_dir, true)?;
+            match file {
+                Some(file) => db.restore(BufReader::new(File::open(file)?))?,
+                None => db.restore(io::stdin().lock())?,
+            }
+            log::info!("database restored");
+            return Ok(());
+        }
+        Some(Command::Shell { command, file }) => {
+            let mut shell = Shell::new(open_offline(&data_dir, true)?);
+            let mut out = io::stdout().lock();
+            match (command, file) {
+                (Some(command), _) => shell.exec_line(command, &mut out)?,
+                (None, Some(file)) => shell.run(BufReader::new(File::open(file)?), out, false)?,
+                (None, None) => {
+                    let interactive = io::stdin().is_terminal();
+                    shell.run(io::stdin().lock(), out, interactive)?
+                }
+            }
+            return Ok(());
+        }
+        _ => {}
+    }
 
     if flags.init {
+        // Check before opening the WAL, so the WAL of an existing database is not changed.
+        if flags.force {
+            log::info!("removing existing database files");
+            remove_database(&data_dir)?;
+        } else if is_initialized(&data_dir)? {
+            return Err(initdb::Error::AlreadyInitialized(data_dir).into());
+        }
+
         log::info!("initializing database directory");
-        let buffer = BufferPool::new(120, StorageManager::new(&data_dir));
-        init_database(&buffer, &data_dir).expect("Failed init default database");
+        let wal = Wal::open_with_sync_method(&data_dir, settings.fsync, settings.wal_sync_method)
+            .expect("Failed to open write-ahead log");
+        let buffer = BufferPool::new(settings.buffer_pool_size, StorageManager::new(&data_dir))
+            .with_fsync(settings.fsync)
+            .with_wal(wal);
+        init_database(
+            &buffer,
+            &data_dir,
+            &flags.username,
+            flags.password.as_deref(),
+        )?;
         log::info!("database directory initialized");
     }
 
-    env::set_current_dir(&data_dir).unwrap();
-
-    let listener = TcpListener::bind(format!("{}:{}", flags.hostname, flags.port)).await?;
+    let listener = TcpListener::bind(format!("{}:{}", settings.hostname, settings.port)).await?;
 
     let config = backend::Config {
         data_dir,
-        buffer_pool_size: 120,
+        buffer_pool_size: settings.buffer_pool_size,
+        auth_method: flags.auth_method,
+        checkpoint_interval: Duration::from_secs(flags.checkpoint_timeout),
+        replacement_policy: flags.replacement_policy,
+        data_checksums: settings.data_checksums,
+        fsync: settings.fsync,
+        wal_sync_method: settings.wal_sync_method,
+        log_min_duration: settings.log_min_duration,
+        bgwriter_delay: settings.bgwriter_delay,
+        bgwriter_lru_maxpages: settings.bgwriter_lru_maxpages,
+        prefetch_depth: settings.prefetch_depth,
+        direct_io: settings.direct_io,
+        max_open_files: settings.max_open_files,
+        metrics_addr: flags.metrics_addr.clone(),
+        unix_socket: settings.unix_socket_path(),
+        session_variables: settings.session_variables.clone(),
+        max_connections: settings.max_connections,
+        authentication_timeout: settings.authentication_timeout,
+        idle_session_timeout: settings.idle_session_timeout,
+        tcp_keepalives_idle: settings.tcp_keepalives_idle,
+        tcp_keepalives_interval: settings.tcp_keepalives_interval,
     };
 
     log::info!("starting tinydb server");
     log::info!(
         "listening on address {}, port {}",
-        flags.hostname,
-        flags.port
+        settings.hostname,
+        settings.port
     );
     backend::start(&config, listener, signal::ctrl_c()).await;
     log::info!("database system is shut down");
 
     Ok(())
 }
+
+/// Open the database of the given data directory without starting the server, initializing the
+/// data directory if create is true. The server must be shut down, otherwise both would change
+/// the same files.
+fn open_offline(data_dir: &Path, create: bool) -> anyhow::Result<Database> {
+    if !is_initialized(data_dir)? {
+        if !create {
+            bail!("data directory {:?} is not initialized", data_dir);
+        }
+        return Database::open(data_dir);
+    }
+    let state = ControlFile::open(data_dir)?.data().state;
+    if state != ClusterState::Shutdown {
+        bail!(
+            "database system is not shut down (state: {}), stop the server first",
+            state
+        );
+    }
+    Database::open(data_dir)
+}
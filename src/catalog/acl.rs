@@ -0,0 +1,310 @@
+//! Access privileges of relations, stored on pg_class.relacl.
+//!
+//! Like Postgres, the privileges are formatted as a list of grantee=privileges/grantor items,
+//! e.g. {10=arwd/10,=r/10}, where the grantee and the grantor are role oids and an empty grantee
+//! is PUBLIC, which means all roles. Each privilege is a single letter: a for INSERT, r for
+//! SELECT, w for UPDATE and d for DELETE.
+//!
+//! Relations are created with an empty relacl, which means the default privileges: all
+//! privileges to the owner and, for system catalogs, SELECT to PUBLIC. pg_authid is the
+//! exception, since it stores the role passwords. The default privileges are copied to relacl
+//! when the privileges of the relation are changed for the first time.
+
+use std::fmt;
+
+use anyhow::{bail, Result};
+
+use crate::{
+    sql::error::{PgError, SqlState},
+    storage::BufferPool,
+    Oid, FIRST_NORMAL_OBJECT_ID, INVALID_OID,
+};
+
+use super::{get_pg_authid_by_oid, pg_authid, pg_class::PgClass, role};
+
+/// Set of privileges, each privilege is a bit.
+pub type AclMode = u8;
+
+pub const ACL_INSERT: AclMode = 1 << 0;
+pub const ACL_SELECT: AclMode = 1 << 1;
+pub const ACL_UPDATE: AclMode = 1 << 2;
+pub const ACL_DELETE: AclMode = 1 << 3;
+
+/// All privileges that can be granted on relations.
+pub const ACL_ALL_RIGHTS_RELATION: AclMode = ACL_INSERT | ACL_SELECT | ACL_UPDATE | ACL_DELETE;
+
+/// Grantee of privileges granted to all roles.
+pub const ACL_ID_PUBLIC: Oid = INVALID_OID;
+
+/// Letter of each privilege, on the order that they are formatted.
+const ACL_MODE_CHARS: &[(AclMode, char)] = &[
+    (ACL_INSERT, 'a'),
+    (ACL_SELECT, 'r'),
+    (ACL_UPDATE, 'w'),
+    (ACL_DELETE, 'd'),
+];
+
+/// Privileges granted to a role by another role.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AclItem {
+    /// Role that has the privileges, ACL_ID_PUBLIC for all roles.
+    pub grantee: Oid,
+
+    /// Role that granted the privileges.
+    pub grantor: Oid,
+
+    pub privileges: AclMode,
+}
+
+/// Access privileges of a relation.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Acl {
+    pub items: Vec<AclItem>,
+}
+
+impl Acl {
+    /// Parse the privileges formatted as described on the module documentation.
+    pub fn parse(acl: &str) -> Result<Self> {
+        let invalid = || anyhow::anyhow!("invalid access privileges {:?}", acl);
+
+        let inner = acl
+            .strip_prefix('{')
+            .and_then(|acl| acl.strip_suffix('}'))
+            .ok_or_else(invalid)?;
+
+        let mut items = Vec::new();
+        for item in inner.split(',').filter(|item| !item.is_empty()) {
+            let (grantee, rest) = item.split_once('=').ok_or_else(invalid)?;
+            let (privileges, grantor) = rest.split_once('/').ok_or_else(invalid)?;
+
+            let mut mode = 0;
+            for c in privileges.chars() {
+                match ACL_MODE_CHARS.iter().find(|(_, privilege)| *privilege == c) {
+                    Some((privilege, _)) => mode |= privilege,
+                    None => return Err(invalid()),
+                }
+            }
+            items.push(AclItem {
+                grantee: match grantee {
+                    "" => ACL_ID_PUBLIC,
+                    grantee => grantee.parse().map_err(|_| invalid())?,
+                },
+                grantor: grantor.parse().map_err(|_| invalid())?,
+                privileges: mode,
+            });
+        }
+        Ok(Self { items })
+    }
+
+    /// Return the privileges of the given relation.
+    pub fn of_relation(pg_class: &PgClass) -> Result<Self> {
+        if !pg_class.relacl.is_empty() {
+            return Self::parse(&pg_class.relacl);
+        }
+
+        let mut acl = Self::default();
+        acl.grant(
+            pg_class.relowner,
+            pg_class.relowner,
+            ACL_ALL_RIGHTS_RELATION,
+        );
+        if pg_class.oid < FIRST_NORMAL_OBJECT_ID && pg_class.oid != pg_authid::RELATION_OID {
+            acl.grant(ACL_ID_PUBLIC, pg_class.relowner, ACL_SELECT);
+        }
+        Ok(acl)
+    }
+
+    /// Grant the given privileges to grantee.
+    pub fn grant(&mut self, grantee: Oid, grantor: Oid, privileges: AclMode) {
+        match self
+            .items
+            .iter_mut()
+            .find(|item| item.grantee == grantee && item.grantor == grantor)
+        {
+            Some(item) => item.privileges |= privileges,
+            None => self.items.push(AclItem {
+                grantee,
+                grantor,
+                privileges,
+            }),
+        }
+    }
+
+    /// Revoke the given privileges of grantee, regardless of which role granted them.
+    pub fn revoke(&mut self, grantee: Oid, privileges: AclMode) {
+        for item in self.items.iter_mut().filter(|item| item.grantee == grantee) {
+            item.privileges &= !privileges;
+        }
+        self.items.retain(|item| item.privileges != 0);
+    }
+
+    /// Return the privileges granted to PUBLIC and to any of the given roles.
+    pub fn privileges(&self, roles: &[Oid]) -> AclMode {
+        self.items
+            .iter()
+            .filter(|item| item.grantee == ACL_ID_PUBLIC || roles.contains(&item.grantee))
+            .fold(0, |mode, item| mode | item.privileges)
+    }
+}
+
+impl fmt::Display for Acl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        for (i, item) in self.items.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            if item.grantee != ACL_ID_PUBLIC {
+                write!(f, "{}", item.grantee)?;
+            }
+            write!(f, "=")?;
+            for (privilege, c) in ACL_MODE_CHARS {
+                if item.privileges & privilege != 0 {
+                    write!(f, "{}", c)?;
+                }
+            }
+            write!(f, "/{}", item.grantor)?;
+        }
+        write!(f, "}}")
+    }
+}
+
+/// Privileges of a role, used to check if the role can access relations.
+#[derive(Debug)]
+pub struct RolePrivileges {
+    /// Superusers bypass all permission checks.
+    superuser: bool,
+
+    /// The role and all roles that it is a member of.
+    roles: Vec<Oid>,
+}
+
+impl RolePrivileges {
+    /// Return the privileges of the given role.
+    pub fn new(buffer_pool: &BufferPool, role: &Oid) -> Result<Self> {
+        Ok(Self {
+            superuser: get_pg_authid_by_oid(buffer_pool, role)?.rolsuper,
+            roles: role::roles_of(buffer_pool, role)?,
+        })
+    }
+
+    /// Return true if the role is a superuser.
+    pub fn is_superuser(&self) -> bool {
+        self.superuser
+    }
+
+    /// Return true if the role is a superuser or a member of the given role.
+    pub fn has_privileges_of(&self, role: &Oid) -> bool {
+        self.superuser || self.roles.contains(role)
+    }
+
+    /// Return an error if the role don't have all the given privileges on the given relation.
+    pub fn check_relation(&self, pg_class: &PgClass, mode: AclMode) -> Result<()> {
+        if self.superuser || Acl::of_relation(pg_class)?.privileges(&self.roles) & mode == mode {
+            return Ok(());
+        }
+        bail!(PgError::new(
+            SqlState::InsufficientPrivilege,
+            format!("permission denied for table {}", pg_class.relname)
+        ))
+    }
+
+    /// Return an error if the role is not the owner of the given relation, or a member of the
+    /// owner role.
+    pub fn check_owner(&self, pg_class: &PgClass) -> Result<()> {
+        if self.has_privileges_of(&pg_class.relowner) {
+            return Ok(());
+        }
+        bail!(PgError::new(
+            SqlState::InsufficientPrivilege,
+            format!("must be owner of table {}", pg_class.relname)
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::catalog::{
+        pg_authid::BOOTSTRAP_SUPERUSER_ID, pg_class, pg_namespace::PG_PUBLIC_NAMESPACE,
+    };
+
+    use super::*;
+
+    fn relation(oid: Oid, relacl: &str) -> PgClass {
+        PgClass {
+            oid,
+            relname: String::from("t"),
+            reltablespace: 0,
+            relisshared: false,
+            relam: 0,
+            relnamespace: PG_PUBLIC_NAMESPACE,
+            relowner: 20000,
+            relacl: relacl.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_acl_format() -> Result<()> {
+        let mut acl = Acl::of_relation(&relation(30000, ""))?;
+        assert_eq!(acl.to_string(), "{20000=arwd/20000}");
+
+        acl.grant(20001, 20000, ACL_SELECT | ACL_DELETE);
+        acl.grant(ACL_ID_PUBLIC, 20000, ACL_INSERT);
+        acl.grant(20001, 20000, ACL_SELECT);
+        let formatted = acl.to_string();
+        assert_eq!(formatted, "{20000=arwd/20000,20001=rd/20000,=a/20000}");
+        assert_eq!(Acl::parse(&formatted)?, acl);
+        assert_eq!(
+            Acl::of_relation(&relation(30000, &formatted))?.items.len(),
+            3
+        );
+
+        acl.revoke(20001, ACL_DELETE);
+        acl.revoke(ACL_ID_PUBLIC, ACL_ALL_RIGHTS_RELATION);
+        assert_eq!(acl.to_string(), "{20000=arwd/20000,20001=r/20000}");
+        assert_eq!(Acl::parse("{}")?, Acl::default());
+
+        assert!(Acl::parse("20000=r/10").is_err());
+        assert!(Acl::parse("{20000=x/10}").is_err());
+        assert!(Acl::parse("{20000=r}").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_acl_privileges() -> Result<()> {
+        let acl = Acl::parse("{20000=arwd/20000,20001=r/20000,=a/20000}")?;
+        assert_eq!(acl.privileges(&[20000]), ACL_ALL_RIGHTS_RELATION);
+        assert_eq!(acl.privileges(&[20001]), ACL_SELECT | ACL_INSERT);
+        assert_eq!(acl.privileges(&[20002]), ACL_INSERT);
+        assert_eq!(acl.privileges(&[20002, 20001]), ACL_SELECT | ACL_INSERT);
+
+        // System catalogs can be read by all roles, except pg_authid.
+        let mut catalog = relation(pg_class::RELATION_OID, "");
+        catalog.relowner = BOOTSTRAP_SUPERUSER_ID;
+        assert_eq!(Acl::of_relation(&catalog)?.privileges(&[20000]), ACL_SELECT);
+        catalog.oid = pg_authid::RELATION_OID;
+        assert_eq!(Acl::of_relation(&catalog)?.privileges(&[20000]), 0);
+
+        let privileges = RolePrivileges {
+            superuser: false,
+            roles: vec![20001],
+        };
+        let rel = relation(30000, "{20000=arwd/20000,20001=r/20000}");
+        privileges.check_relation(&rel, ACL_SELECT)?;
+        let err = privileges
+            .check_relation(&rel, ACL_SELECT | ACL_INSERT)
+            .unwrap_err();
+        let err = PgError::from_error(&err);
+        assert_eq!(err.code, SqlState::InsufficientPrivilege);
+        assert_eq!(err.message, "permission denied for table t");
+        assert!(privileges.check_owner(&rel).is_err());
+
+        let superuser = RolePrivileges {
+            superuser: true,
+            roles: vec![BOOTSTRAP_SUPERUSER_ID],
+        };
+        superuser.check_relation(&rel, ACL_ALL_RIGHTS_RELATION)?;
+        superuser.check_owner(&rel)?;
+        Ok(())
+    }
+}
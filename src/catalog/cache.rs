@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+
+use crate::{access::heaptuple::TupleDesc, Oid};
+
+use super::pg_class::PgClass;
+
+/// In-memory cache of system catalog tuples, so that relations and their attributes don't need
+/// to be searched on pg_class and pg_attribute for every query.
+///
+/// Each database has its own cache, created when the first entry of the database is cached, so
+/// the relations of a database are never found on the cache of another database with the same
+/// relation names.
+///
+/// Only relations that exist are cached. Entries of a relation must be invalidated when its
+/// pg_class or pg_attribute tuples are changed.
+#[derive(Default)]
+pub struct CatalogCache {
+    /// Cache of each database, by database oid.
+    databases: HashMap<Oid, DatabaseCache>,
+}
+
+/// Cached catalog tuples of a single database.
+#[derive(Default)]
+struct DatabaseCache {
+    /// pg_class tuples of the relations of all schemas with the same name, by relation name.
+    relations: HashMap<String, Vec<PgClass>>,
+
+    /// Tuple descriptions by relation oid.
+    tuple_descs: HashMap<Oid, TupleDesc>,
+
+    /// Schema oids by schema name.
+    namespaces: HashMap<String, Oid>,
+}
+
+impl CatalogCache {
+    /// Return the cached pg_class tuples of the relations of all schemas with the given name.
+    pub fn relations(&self, db_oid: &Oid, rel_name: &str) -> Option<Vec<PgClass>> {
+        self.databases.get(db_oid)?.relations.get(rel_name).cloned()
+    }
+
+    /// Cache the pg_class tuples of all relations with the given name, which must not be empty.
+    pub fn insert_relations(&mut self, db_oid: &Oid, rel_name: &str, relations: &[PgClass]) {
+        self.database(db_oid)
+            .relations
+            .insert(rel_name.to_string(), relations.to_vec());
+    }
+
+    /// Return the cached oid of the schema with the given name.
+    pub fn namespace(&self, db_oid: &Oid, nspname: &str) -> Option<Oid> {
+        self.databases.get(db_oid)?.namespaces.get(nspname).copied()
+    }
+
+    /// Cache the oid of the schema with the given name.
+    pub fn insert_namespace(&mut self, db_oid: &Oid, nspname: &str, oid: &Oid) {
+        self.database(db_oid)
+            .namespaces
+            .insert(nspname.to_string(), *oid);
+    }
+
+    /// Return the cached tuple description of the given relation oid.
+    pub fn tuple_desc(&self, db_oid: &Oid, rel_oid: &Oid) -> Option<TupleDesc> {
+        self.databases
+            .get(db_oid)?
+            .tuple_descs
+            .get(rel_oid)
+            .cloned()
+    }
+
+    /// Cache the tuple description of the given relation oid.
+    pub fn insert_tuple_desc(&mut self, db_oid: &Oid, rel_oid: &Oid, tuple_desc: &TupleDesc) {
+        self.database(db_oid)
+            .tuple_descs
+            .insert(*rel_oid, tuple_desc.clone());
+    }
+
+    /// Remove all cached entries of the given relation, including the relations of other schemas
+    /// with the same name.
+    pub fn invalidate(&mut self, db_oid: &Oid, rel_name: &str, rel_oid: &Oid) {
+        if let Some(database) = self.databases.get_mut(db_oid) {
+            database.relations.remove(rel_name);
+            database.tuple_descs.remove(rel_oid);
+        }
+    }
+
+    /// Return the number of databases with cached entries.
+    pub fn databases(&self) -> usize {
+        self.databases.len()
+    }
+
+    /// Return the cache of the given database, creating an empty cache if needed.
+    fn database(&mut self, db_oid: &Oid) -> &mut DatabaseCache {
+        self.databases.entry(*db_oid).or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use crate::{
+        catalog::{
+            self, heap::heap_create, namespace::RelationName, pg_attribute::PgAttribute,
+            pg_authid::BOOTSTRAP_SUPERUSER_ID, pg_database::TINYDB_OID,
+            pg_namespace::PG_PUBLIC_NAMESPACE, pg_tablespace::DEFAULTTABLESPACE_OID, pg_type,
+        },
+        initdb::init_database,
+        storage::{smgr::StorageManager, BufferPool},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_cache_relations() -> Result<()> {
+        let data_dir = tempfile::tempdir()?;
+        let buffer_pool = BufferPool::new(50, StorageManager::new(data_dir.path()));
+        init_database(&buffer_pool, data_dir.path(), "tinydb", None)?;
+
+        let search_path = vec![String::from("public")];
+        let rel_name = RelationName::new("t");
+
+        // Relations that don't exist are not cached.
+        assert!(
+            catalog::get_pg_class_relation(&buffer_pool, &TINYDB_OID, &search_path, &rel_name)
+                .is_err()
+        );
+        assert!(buffer_pool
+            .catalog_cache()
+            .relations(&TINYDB_OID, "t")
+            .is_none());
+
+        let tuple_desc = TupleDesc {
+            attrs: vec![PgAttribute {
+                attrelid: 20000,
+                attname: String::from("a"),
+                attnum: 1,
+                attlen: 4,
+                atttypid: pg_type::INT_OID,
+                attnotnull: false,
+                attdefault: String::new(),
+            }],
+        };
+        heap_create(
+            &buffer_pool,
+            DEFAULTTABLESPACE_OID,
+            &TINYDB_OID,
+            PG_PUBLIC_NAMESPACE,
+            BOOTSTRAP_SUPERUSER_ID,
+            "t",
+            20000,
+            &tuple_desc,
+        )?;
+
+        let tuple_desc =
+            catalog::tuple_desc_from_relation(&buffer_pool, &TINYDB_OID, &search_path, &rel_name)?;
+        assert_eq!(tuple_desc.attrs.len(), 1);
+
+        let cache = buffer_pool.catalog_cache();
+        assert_eq!(
+            cache
+                .relations(&TINYDB_OID, "t")
+                .map(|rels| rels.iter().map(|rel| rel.oid).collect::<Vec<_>>()),
+            Some(vec![20000])
+        );
+        assert_eq!(
+            cache
+                .tuple_desc(&TINYDB_OID, &20000)
+                .map(|tuple_desc| tuple_desc.attrs[0].attname.clone()),
+            Some(String::from("a"))
+        );
+        drop(cache);
+
+        buffer_pool
+            .catalog_cache()
+            .invalidate(&TINYDB_OID, "t", &20000);
+        assert!(buffer_pool
+            .catalog_cache()
+            .relations(&TINYDB_OID, "t")
+            .is_none());
+        assert!(buffer_pool
+            .catalog_cache()
+            .tuple_desc(&TINYDB_OID, &20000)
+            .is_none());
+
+        Ok(())
+    }
+}
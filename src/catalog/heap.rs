@@ -1,26 +1,62 @@
+use std::collections::HashSet;
+
 use crate::{
     access::{
         self,
-        heap::heap_insert,
+        heap::{heap_delete, heap_insert, HeapScanner},
         heaptuple::{HeapTuple, TupleDesc},
+        tableam::{self, HeapTableAm, TableAm},
     },
     relation::Relation,
-    storage::{page::PageHeader, BufferPool, PageWriter},
+    storage::{page::page_init, wal, BufferPool},
     Oid,
 };
 use anyhow::Result;
 use log::debug;
 
-use super::{pg_class::PgClass, pg_tablespace::GLOBALTABLESPACE_OID};
+use super::{
+    acl::Acl, pg_attribute::PgAttribute, pg_class::PgClass, pg_index::PgIndex,
+    pg_statistic::PgStatistic, pg_tablespace::GLOBALTABLESPACE_OID,
+};
 
-/// Create a new cataloged heap relation.
+/// Create a new cataloged heap relation on the given schema, owned by the given role.
+#[allow(clippy::too_many_arguments)]
 pub fn heap_create(
     buffer: &BufferPool,
     tablespace: Oid,
     db_oid: &Oid,
+    namespace: Oid,
+    owner: Oid,
     rel_name: &str,
     new_rel_oid: Oid,
     tupledesc: &TupleDesc,
+) -> Result<Relation> {
+    table_create(
+        buffer,
+        tablespace,
+        db_oid,
+        namespace,
+        owner,
+        rel_name,
+        new_rel_oid,
+        tupledesc,
+        &HeapTableAm,
+    )
+}
+
+/// Create a new cataloged relation on the given schema, owned by the given role and stored
+/// using the given table access method.
+#[allow(clippy::too_many_arguments)]
+pub fn table_create(
+    buffer: &BufferPool,
+    tablespace: Oid,
+    db_oid: &Oid,
+    namespace: Oid,
+    owner: Oid,
+    rel_name: &str,
+    new_rel_oid: Oid,
+    tupledesc: &TupleDesc,
+    table_am: &dyn TableAm,
 ) -> Result<Relation> {
     // Create a new relation object for the new heap relation.
     let new_rel = access::open_relation(new_rel_oid, tablespace, db_oid, rel_name);
@@ -32,15 +68,174 @@ pub fn heap_create(
     let pg_class = access::open_pg_class_relation(db_oid);
 
     // Now create an entry in pg_class for the relation.
-    add_new_relation_tuple(buffer, &pg_class, &new_rel)?;
+    add_new_relation_tuple(
+        buffer,
+        &pg_class,
+        &new_rel,
+        namespace,
+        owner,
+        table_am.oid(),
+    )?;
 
-    // Now that the new relation is already stored on pg_class, initialize the default page header
-    // data
-    initialize_default_page_header(buffer, &new_rel)?;
+    // Now that the new relation is already stored on pg_class, initialize the storage of the
+    // relation.
+    table_am.relation_create(buffer, &new_rel, tupledesc)?;
 
     Ok(new_rel)
 }
 
+/// Remove the given cataloged relation and all of its indexes.
+///
+/// The pg_class, pg_attribute, pg_index and pg_statistic tuples of the relation are deleted
+/// before the storage is removed, so the relation can not be found anymore when its files are unlinked. The caller
+/// must hold an AccessExclusive lock on the relation.
+pub fn heap_drop(buffer: &BufferPool, db_oid: &Oid, pg_class: &PgClass) -> Result<()> {
+    let pg_class_rel = access::open_pg_class_relation(db_oid);
+    let pg_attribute_rel = access::open_pg_attribute_relation(db_oid);
+    let pg_index_rel = access::open_pg_index_relation(db_oid);
+
+    // Delete the pg_index tuples of the indexes defined on the relation.
+    let mut index_oids = HashSet::new();
+    delete_catalog_tuples(buffer, &pg_index_rel, |tuple| {
+        let index = tuple.decode_data::<PgIndex>()?;
+        if index.indrelid == pg_class.oid {
+            index_oids.insert(index.indexrelid);
+            return Ok(true);
+        }
+        Ok(false)
+    })?;
+
+    // Delete the pg_class tuples of the relation and its indexes.
+    let mut indexes = Vec::new();
+    delete_catalog_tuples(buffer, &pg_class_rel, |tuple| {
+        let class = tuple.decode_data::<PgClass>()?;
+        if class.oid == pg_class.oid {
+            return Ok(true);
+        }
+        if index_oids.contains(&class.oid) {
+            indexes.push(class);
+            return Ok(true);
+        }
+        Ok(false)
+    })?;
+
+    // Delete the pg_attribute tuples of the relation.
+    delete_catalog_tuples(buffer, &pg_attribute_rel, |tuple| {
+        Ok(tuple.decode_data::<PgAttribute>()?.attrelid == pg_class.oid)
+    })?;
+
+    // Delete the pg_statistic tuples of the relation, if it was analyzed.
+    delete_catalog_tuples(
+        buffer,
+        &access::open_pg_statistic_relation(db_oid),
+        |tuple| Ok(tuple.decode_data::<PgStatistic>()?.starelid == pg_class.oid),
+    )?;
+
+    let mut cache = buffer.catalog_cache();
+    cache.invalidate(db_oid, &pg_class.relname, &pg_class.oid);
+    for index in &indexes {
+        cache.invalidate(db_oid, &index.relname, &index.oid);
+    }
+    drop(cache);
+
+    // Now that the relation is not cataloged anymore, remove its storage.
+    for index in &indexes {
+        let index_rel =
+            access::open_relation(index.oid, index.reltablespace, db_oid, &index.relname);
+        drop_relation_storage(buffer, &index_rel)?;
+    }
+    let rel = access::open_relation(
+        pg_class.oid,
+        pg_class.reltablespace,
+        db_oid,
+        &pg_class.relname,
+    );
+    tableam::table_am(pg_class.relam)?.relation_drop(buffer, &rel)?;
+
+    debug!("dropped relation {}", pg_class.relname);
+
+    Ok(())
+}
+
+/// Replace the pg_statistic tuples of the given relation oid with the given statistics.
+pub fn update_relation_statistics(
+    buffer: &BufferPool,
+    db_oid: &Oid,
+    rel_oid: &Oid,
+    statistics: &[PgStatistic],
+) -> Result<()> {
+    let pg_statistic_rel = access::open_pg_statistic_relation(db_oid);
+
+    delete_catalog_tuples(buffer, &pg_statistic_rel, |tuple| {
+        Ok(tuple.decode_data::<PgStatistic>()?.starelid == *rel_oid)
+    })?;
+    for statistic in statistics {
+        heap_insert(
+            buffer,
+            &pg_statistic_rel,
+            &HeapTuple::with_default_header(statistic)?,
+        )?;
+    }
+    Ok(())
+}
+
+/// Replace the access privileges of the given relation on its pg_class tuple.
+pub fn update_relation_acl(
+    buffer: &BufferPool,
+    db_oid: &Oid,
+    pg_class: &PgClass,
+    acl: &Acl,
+) -> Result<()> {
+    let pg_class_rel = access::open_pg_class_relation(db_oid);
+
+    delete_catalog_tuples(buffer, &pg_class_rel, |tuple| {
+        Ok(tuple.decode_data::<PgClass>()?.oid == pg_class.oid)
+    })?;
+    heap_insert(
+        buffer,
+        &pg_class_rel,
+        &HeapTuple::with_default_header(&PgClass {
+            relacl: acl.to_string(),
+            ..pg_class.clone()
+        })?,
+    )?;
+
+    buffer
+        .catalog_cache()
+        .invalidate(db_oid, &pg_class.relname, &pg_class.oid);
+    Ok(())
+}
+
+/// Delete all tuples of the given catalog relation that match the given predicate.
+pub(super) fn delete_catalog_tuples<F>(
+    buffer: &BufferPool,
+    rel: &Relation,
+    mut predicate: F,
+) -> Result<()>
+where
+    F: FnMut(&HeapTuple) -> Result<bool>,
+{
+    let mut tids = Vec::new();
+    let mut heap = HeapScanner::new(buffer, rel)?;
+    while let Some(tuple) = heap.next_tuple()? {
+        if predicate(&tuple)? {
+            tids.push(tuple.t_self);
+        }
+    }
+    drop(heap);
+
+    for tid in tids {
+        heap_delete(buffer, rel, &tid)?;
+    }
+    Ok(())
+}
+
+/// Remove the files of the given relation, logging the removal on the WAL first.
+pub fn drop_relation_storage(buffer: &BufferPool, rel: &Relation) -> Result<()> {
+    wal::log_drop_relation(buffer, rel)?;
+    buffer.drop_relation(rel)
+}
+
 /// Registers the new relation's schema by adding tuples to pg_attribute.
 fn add_new_attribute_tuples(
     buffer: &BufferPool,
@@ -50,31 +245,46 @@ fn add_new_attribute_tuples(
     // Open pg_attribute relation to store the new relation attributes.
     let pg_attribute = access::open_pg_attribute_relation(&rel.locator.database);
 
+    buffer
+        .catalog_cache()
+        .invalidate(&rel.locator.database, &rel.rel_name, &rel.locator.oid);
+
     // Now insert a new tuple on pg_attribute containing the new attributes information.
     for attr in &tupledesc.attrs {
         heap_insert(
             buffer,
             &pg_attribute,
-            &HeapTuple::with_default_header(&attr)?,
+            &HeapTuple::with_default_header(attr)?,
         )?;
     }
 
     Ok(())
 }
 
-/// Registers the new relation in the catalogs by adding a tuple to pg_class. If the pg_class is
+/// Registers the new relation owned by the given role and stored using the given access method
+/// in the catalogs by adding a tuple to pg_class. The relation is created with the default
+/// privileges. If the pg_class is
 /// empty the buffer pool is used to alloc a new page on pg_class file and initialize the default
 /// header values.
-fn add_new_relation_tuple(
+pub(super) fn add_new_relation_tuple(
     buffer: &BufferPool,
     pg_class: &Relation,
     new_rel: &Relation,
+    namespace: Oid,
+    owner: Oid,
+    relam: Oid,
 ) -> Result<()> {
     // Initialize default page header of pg_class relation if needed.
     if buffer.size_of_relation(pg_class)? == 0 {
         initialize_default_page_header(buffer, pg_class)?;
     }
 
+    buffer.catalog_cache().invalidate(
+        &new_rel.locator.database,
+        &new_rel.rel_name,
+        &new_rel.locator.oid,
+    );
+
     // Now insert a new tuple on pg_class containing the new relation information.
     heap_insert(
         buffer,
@@ -84,6 +294,10 @@ fn add_new_relation_tuple(
             relname: new_rel.rel_name.clone(),
             reltablespace: new_rel.locator.tablespace,
             relisshared: new_rel.locator.tablespace == GLOBALTABLESPACE_OID,
+            relam,
+            relnamespace: namespace,
+            relowner: owner,
+            relacl: String::new(),
         })?,
     )?;
 
@@ -98,14 +312,13 @@ pub fn initialize_default_page_header(buffer_pool: &BufferPool, rel: &Relation)
         return Ok(());
     }
 
-    let buffer = buffer_pool.alloc_buffer(rel)?;
+    let mut buffer = buffer_pool.alloc_buffer(rel)?;
 
-    let mut page_writer = PageWriter::new(&buffer.page);
-    bincode::serialize_into(&mut page_writer, &PageHeader::default())?;
+    page_init(&buffer.page)?;
 
     // Force a write to make sure that future fetches of this page see the page header correctly.
     buffer_pool.flush_buffer(&buffer)?;
-    buffer_pool.unpin_buffer(&buffer, true)?;
+    buffer.mark_dirty();
 
     debug!(
         "initialized default pager header data for relation: {}",
@@ -0,0 +1,381 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{bail, Result};
+use log::debug;
+
+use crate::{
+    access::{
+        self,
+        btree::{btree_encode_key, btree_init, btree_insert, btree_search, BTREE_AM_OID},
+        heap::heap_insert,
+        heaptuple::{HeapTuple, TupleDesc},
+        lmgr::{LockGuard, LockMode, LockOwner, LockTag},
+        tableam::TableAm,
+    },
+    relation::Relation,
+    sql::{
+        encode,
+        error::{PgError, SqlState},
+    },
+    storage::{page::ItemPointer, BufferPool},
+    NullableDatum, Oid,
+};
+
+use super::{heap::add_new_relation_tuple, pg_attribute::PgAttribute, pg_index::PgIndex, Error};
+
+/// Create a new cataloged B-tree index on the given attribute number of the given heap relation.
+/// The index is created on the same schema of the heap relation, owned by the owner of the heap
+/// relation.
+///
+/// All tuples already stored on heap relation are inserted on the new index, scanned using the
+/// given table access method of the relation. If the index is unique, an error is returned
+/// before creating the index if any value is duplicated on these tuples.
+#[allow(clippy::too_many_arguments)]
+pub fn index_create(
+    buffer_pool: &BufferPool,
+    heap_rel: &Relation,
+    namespace: Oid,
+    owner: Oid,
+    table_am: &dyn TableAm,
+    tuple_desc: &TupleDesc,
+    index_name: &str,
+    index_oid: Oid,
+    attnum: usize,
+    unique: bool,
+) -> Result<Relation> {
+    let db_oid = &heap_rel.locator.database;
+
+    if super::get_namespace_relation(buffer_pool, db_oid, &namespace, index_name)?.is_some() {
+        bail!(Error::RelationAlreadyExists(index_name.to_string()));
+    }
+
+    let attr = match tuple_desc.attrs.get(attnum - 1) {
+        Some(attr) => attr,
+        None => bail!("attribute number {} does not exist", attnum),
+    };
+
+    // Collect the index entries of the tuples that already exists on heap relation.
+    let mut entries = Vec::new();
+    let mut keys = HashSet::new();
+    let mut heap = table_am.begin_scan(buffer_pool, heap_rel)?;
+    while let Some(tuple) = heap.next_tuple()? {
+        if let Some(datum) = tuple.get_attr(attnum, tuple_desc)? {
+            let key = btree_encode_key(&datum, attr.atttypid)?;
+            if unique && !keys.insert(key.clone()) {
+                bail!(PgError::new(
+                    SqlState::UniqueViolation,
+                    format!("could not create unique index \"{}\"", index_name)
+                )
+                .with_detail(format!(
+                    "Key ({})=({}) is duplicated.",
+                    attr.attname,
+                    encode::decode(&datum, attr.atttypid)?
+                )));
+            }
+            entries.push((key, tuple.t_self));
+        }
+    }
+
+    // Create a new relation object for the new index relation.
+    let index_rel =
+        access::open_relation(index_oid, heap_rel.locator.tablespace, db_oid, index_name);
+
+    // Now create an entry in pg_class for the index relation.
+    add_new_relation_tuple(
+        buffer_pool,
+        &access::open_pg_class_relation(db_oid),
+        &index_rel,
+        namespace,
+        owner,
+        BTREE_AM_OID,
+    )?;
+
+    // And an entry in pg_index linking the index to the heap relation.
+    heap_insert(
+        buffer_pool,
+        &access::open_pg_index_relation(db_oid),
+        &HeapTuple::with_default_header(&PgIndex {
+            indexrelid: index_oid,
+            indrelid: heap_rel.locator.oid,
+            indkey: attnum,
+            indisunique: unique,
+        })?,
+    )?;
+
+    btree_init(buffer_pool, &index_rel)?;
+    for (key, tid) in &entries {
+        btree_insert(buffer_pool, &index_rel, key, tid)?;
+    }
+
+    debug!(
+        "created index {} on relation {}",
+        index_name, heap_rel.rel_name
+    );
+
+    Ok(index_rel)
+}
+
+/// Insert the given heap tuple stored at the given location on all indexes of the given heap
+/// relation.
+///
+/// NULL values are not indexed.
+pub fn index_insert_tuple(
+    buffer_pool: &BufferPool,
+    heap_rel: &Relation,
+    tuple: &HeapTuple,
+    tid: &ItemPointer,
+) -> Result<()> {
+    let db_oid = &heap_rel.locator.database;
+
+    let indexes = super::get_relation_indexes(buffer_pool, db_oid, &heap_rel.locator.oid)?;
+    if indexes.is_empty() {
+        return Ok(());
+    }
+
+    let tuple_desc =
+        super::tuple_desc_from_relation_oid(buffer_pool, db_oid, &heap_rel.locator.oid)?;
+
+    for index in indexes {
+        let attr = match tuple_desc.attrs.get(index.indkey - 1) {
+            Some(attr) => attr,
+            None => bail!("attribute number {} does not exist", index.indkey),
+        };
+
+        if let Some(datum) = tuple.get_attr(index.indkey, &tuple_desc)? {
+            let index_rel = access::open_relation(
+                index.indexrelid,
+                heap_rel.locator.tablespace,
+                db_oid,
+                &index.indexrelid.to_string(),
+            );
+            let key = btree_encode_key(&datum, attr.atttypid)?;
+            btree_insert(buffer_pool, &index_rel, &key, tid)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// A unique index of a heap relation, used to find the live tuple that has the same value of
+/// the indexed attribute of a new tuple.
+pub struct UniqueIndex {
+    /// Name of the index, used on error messages.
+    pub name: String,
+
+    /// The indexed attribute of the heap relation.
+    pub attr: PgAttribute,
+
+    index_rel: Relation,
+}
+
+/// Return all unique indexes of the given heap relation, described by the given tuple
+/// description.
+pub fn unique_indexes(
+    buffer_pool: &BufferPool,
+    heap_rel: &Relation,
+    tuple_desc: &TupleDesc,
+) -> Result<Vec<UniqueIndex>> {
+    let db_oid = &heap_rel.locator.database;
+
+    let indexes = super::get_relation_indexes(buffer_pool, db_oid, &heap_rel.locator.oid)?
+        .into_iter()
+        .filter(|index| index.indisunique)
+        .collect::<Vec<_>>();
+    if indexes.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let names = super::get_pg_class_relations(buffer_pool, db_oid)?
+        .into_iter()
+        .map(|pg_class| (pg_class.oid, pg_class.relname))
+        .collect::<HashMap<_, _>>();
+
+    indexes
+        .into_iter()
+        .map(|index| {
+            let attr = match tuple_desc.attrs.get(index.indkey - 1) {
+                Some(attr) => attr.clone(),
+                None => bail!("attribute number {} does not exist", index.indkey),
+            };
+            let name = match names.get(&index.indexrelid) {
+                Some(name) => name.clone(),
+                None => index.indexrelid.to_string(),
+            };
+            Ok(UniqueIndex {
+                index_rel: access::open_relation(
+                    index.indexrelid,
+                    heap_rel.locator.tablespace,
+                    db_oid,
+                    &name,
+                ),
+                name,
+                attr,
+            })
+        })
+        .collect()
+}
+
+impl UniqueIndex {
+    /// Return the index key of the given tuple values, or None if the indexed value is NULL,
+    /// since NULL values never conflict.
+    pub fn key(&self, values: &[NullableDatum]) -> Result<Option<Vec<u8>>> {
+        match values.get(self.attr.attnum - 1) {
+            Some(Some(datum)) => Ok(Some(btree_encode_key(datum, self.attr.atttypid)?)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Lock the keys of the index on behalf of the given owner, so that other owners don't insert
+    /// tuples on the heap relation that could conflict with the index while the lock is held.
+    ///
+    /// The lock is a ShareRowExclusive lock on the index relation: it waits for other inserts
+    /// on the same index, but not for scans, which lock the heap relation with AccessShare.
+    pub fn lock_keys(&self, buffer_pool: &BufferPool, owner: &LockOwner) -> Result<LockGuard> {
+        let tag = LockTag {
+            database: self.index_rel.locator.database,
+            relation: self.index_rel.locator.oid,
+        };
+        buffer_pool
+            .lock_manager()
+            .acquire(tag, LockMode::ShareRowExclusive, owner)
+    }
+
+    /// Return the location of the live tuple of the heap relation that has the given key, or
+    /// None if there is no such tuple. Tuples on the ignored locations are never returned.
+    ///
+    /// The index still points to the location of deleted tuples, so each location is fetched to
+    /// check that the tuple is live.
+    pub fn find(
+        &self,
+        buffer_pool: &BufferPool,
+        heap_rel: &Relation,
+        table_am: &dyn TableAm,
+        key: &[u8],
+        ignored: &HashSet<ItemPointer>,
+    ) -> Result<Option<ItemPointer>> {
+        for tid in btree_search(buffer_pool, &self.index_rel, key)? {
+            if !ignored.contains(&tid) && table_am.fetch(buffer_pool, heap_rel, &tid)?.is_some() {
+                return Ok(Some(tid));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Return the error of a new tuple with the given values that has the same key of another
+    /// tuple.
+    pub fn violation(&self, values: &[NullableDatum]) -> Result<PgError> {
+        let value = match values.get(self.attr.attnum - 1) {
+            Some(Some(datum)) => encode::decode(datum, self.attr.atttypid)?,
+            _ => String::from("NULL"),
+        };
+        Ok(PgError::new(
+            SqlState::UniqueViolation,
+            format!(
+                "duplicate key value violates unique constraint \"{}\"",
+                self.name
+            ),
+        )
+        .with_detail(format!(
+            "Key ({})=({}) already exists.",
+            self.attr.attname, value
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        access::{btree::btree_search, heap::heap_delete, tableam::HeapTableAm},
+        catalog::{
+            heap::heap_create, pg_attribute::PgAttribute, pg_authid::BOOTSTRAP_SUPERUSER_ID,
+            pg_database::TINYDB_OID, pg_namespace::PG_PUBLIC_NAMESPACE,
+            pg_tablespace::DEFAULTTABLESPACE_OID, pg_type,
+        },
+        initdb::init_database,
+        storage::smgr::StorageManager,
+        Datum,
+    };
+
+    fn int_datum(value: i32) -> Result<Datum> {
+        Ok(Datum::from(bincode::serialize(&value)?))
+    }
+
+    #[test]
+    fn test_heap_insert_maintain_index() -> Result<()> {
+        let data_dir = tempfile::tempdir()?;
+        let buffer_pool = BufferPool::new(50, StorageManager::new(data_dir.path()));
+        init_database(&buffer_pool, data_dir.path(), "tinydb", None)?;
+
+        let tuple_desc = TupleDesc {
+            attrs: vec![PgAttribute {
+                attrelid: 20000,
+                attname: String::from("a"),
+                attnum: 1,
+                attlen: 4,
+                atttypid: pg_type::INT_OID,
+                attnotnull: false,
+                attdefault: String::new(),
+            }],
+        };
+        let rel = heap_create(
+            &buffer_pool,
+            DEFAULTTABLESPACE_OID,
+            &TINYDB_OID,
+            PG_PUBLIC_NAMESPACE,
+            BOOTSTRAP_SUPERUSER_ID,
+            "t",
+            20000,
+            &tuple_desc,
+        )?;
+
+        let key = |value: i32| btree_encode_key(&int_datum(value)?, pg_type::INT_OID);
+
+        // Tuples inserted before the index creation are indexed when building the index.
+        let old_tid = heap_insert(
+            &buffer_pool,
+            &rel,
+            &HeapTuple::from_datums(vec![Some(int_datum(1)?)], &tuple_desc)?,
+        )?;
+        let index_rel = index_create(
+            &buffer_pool,
+            &rel,
+            PG_PUBLIC_NAMESPACE,
+            BOOTSTRAP_SUPERUSER_ID,
+            &HeapTableAm,
+            &tuple_desc,
+            "t_a",
+            20001,
+            1,
+            false,
+        )?;
+
+        let new_tid = heap_insert(
+            &buffer_pool,
+            &rel,
+            &HeapTuple::from_datums(vec![Some(int_datum(2)?)], &tuple_desc)?,
+        )?;
+
+        assert_eq!(
+            btree_search(&buffer_pool, &index_rel, &key(1)?)?,
+            vec![old_tid]
+        );
+        assert_eq!(
+            btree_search(&buffer_pool, &index_rel, &key(2)?)?,
+            vec![new_tid]
+        );
+        assert!(btree_search(&buffer_pool, &index_rel, &key(3)?)?.is_empty());
+
+        // The index still points to the location of deleted tuples, so it is never reused.
+        heap_delete(&buffer_pool, &rel, &new_tid)?;
+        let tid = heap_insert(
+            &buffer_pool,
+            &rel,
+            &HeapTuple::from_datums(vec![Some(int_datum(3)?)], &tuple_desc)?,
+        )?;
+        assert_ne!(tid, new_tid);
+        assert_eq!(btree_search(&buffer_pool, &index_rel, &key(3)?)?, vec![tid]);
+
+        Ok(())
+    }
+}
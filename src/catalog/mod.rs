@@ -1,20 +1,39 @@
 use anyhow::{bail, Result};
 
 use crate::{
-    access::{self, heap::HeapScanner, heaptuple::TupleDesc},
+    access::{
+        self,
+        heap::{heap_insert, HeapScanner},
+        heaptuple::{HeapTuple, TupleDesc},
+    },
     new_object_id,
-    storage::{relation_locator::relation_path, BufferPool},
+    storage::BufferPool,
     Oid,
 };
 
-use self::{pg_attribute::PgAttribute, pg_class::PgClass, pg_database::PgDatabase};
+use self::{
+    namespace::RelationName, pg_attribute::PgAttribute, pg_authid::PgAuthId, pg_class::PgClass,
+    pg_database::PgDatabase, pg_index::PgIndex, pg_statistic::PgStatistic,
+    pg_tablespace::DEFAULTTABLESPACE_OID,
+};
 
+pub mod acl;
+pub mod cache;
 pub mod heap;
+pub mod index;
+pub mod namespace;
 pub mod pg_attribute;
+pub mod pg_auth_members;
+pub mod pg_authid;
 pub mod pg_class;
 pub mod pg_database;
+pub mod pg_index;
+pub mod pg_namespace;
+pub mod pg_statistic;
 pub mod pg_tablespace;
 pub mod pg_type;
+pub mod role;
+pub mod virtual_table;
 
 /// Errors related with system catalog relation operations.
 #[derive(thiserror::Error, Debug)]
@@ -24,65 +43,228 @@ pub enum Error {
 
     #[error("database {0} does not exist")]
     DatabaseNotFound(String),
+
+    #[error("relation {0} already exists")]
+    RelationAlreadyExists(String),
+
+    #[error("role {0} does not exist")]
+    RoleNotFound(String),
+
+    #[error("role {0} already exists")]
+    RoleAlreadyExists(String),
+
+    #[error("schema {0} does not exist")]
+    NamespaceNotFound(String),
+
+    #[error("schema {0} already exists")]
+    NamespaceAlreadyExists(String),
 }
 
-/// Return the tuple description of the given relation name.
+/// Return the tuple description of the given relation name, searched on the given search path.
 pub fn tuple_desc_from_relation(
     buffer_pool: &BufferPool,
     db_oid: &Oid,
-    rel_name: &str,
+    search_path: &[String],
+    rel_name: &RelationName,
 ) -> Result<TupleDesc> {
-    let pg_attribute = access::open_pg_attribute_relation(db_oid);
+    let pg_class_rel = get_pg_class_relation(buffer_pool, db_oid, search_path, rel_name)?;
+
+    tuple_desc_from_relation_oid(buffer_pool, db_oid, &pg_class_rel.oid)
+}
+
+/// Return the tuple description of the given relation oid.
+pub fn tuple_desc_from_relation_oid(
+    buffer_pool: &BufferPool,
+    db_oid: &Oid,
+    rel_oid: &Oid,
+) -> Result<TupleDesc> {
+    if let Some(tuple_desc) = buffer_pool.catalog_cache().tuple_desc(db_oid, rel_oid) {
+        return Ok(tuple_desc);
+    }
 
-    let pg_class_rel = get_pg_class_relation(buffer_pool, db_oid, rel_name)?;
+    let pg_attribute = access::open_pg_attribute_relation(db_oid);
 
     let mut attributes = Vec::new();
 
     let mut heap = HeapScanner::new(buffer_pool, &pg_attribute)?;
     while let Some(tuple) = heap.next_tuple()? {
-        let attr = bincode::deserialize::<PgAttribute>(&tuple.data)?;
-        if attr.attrelid == pg_class_rel.oid {
+        let attr = tuple.decode_data::<PgAttribute>()?;
+        if attr.attrelid == *rel_oid {
             attributes.push(attr);
         }
     }
 
-    Ok(TupleDesc { attrs: attributes })
+    let tuple_desc = TupleDesc { attrs: attributes };
+    buffer_pool
+        .catalog_cache()
+        .insert_tuple_desc(db_oid, rel_oid, &tuple_desc);
+
+    Ok(tuple_desc)
 }
 
-/// Return the pg class tuple from the given relation name.
-pub fn get_pg_class_relation(
+/// Return all pg_index tuples of indexes defined on the given relation oid.
+pub fn get_relation_indexes(
     buffer_pool: &BufferPool,
     db_oid: &Oid,
-    rel_name: &str,
-) -> Result<PgClass> {
+    rel_oid: &Oid,
+) -> Result<Vec<PgIndex>> {
+    Ok(get_pg_indexes(buffer_pool, db_oid)?
+        .into_iter()
+        .filter(|index| index.indrelid == *rel_oid)
+        .collect())
+}
+
+/// Return all pg_index tuples of the given database.
+pub fn get_pg_indexes(buffer_pool: &BufferPool, db_oid: &Oid) -> Result<Vec<PgIndex>> {
+    let pg_index_rel = access::open_pg_index_relation(db_oid);
+
+    let mut indexes = Vec::new();
+
+    let mut heap = HeapScanner::new(buffer_pool, &pg_index_rel)?;
+    while let Some(tuple) = heap.next_tuple()? {
+        indexes.push(tuple.decode_data::<PgIndex>()?);
+    }
+
+    Ok(indexes)
+}
+
+/// Return the pg_statistic tuples of the columns of the given relation oid, ordered by column
+/// number. Empty if the relation was never analyzed.
+pub fn get_relation_statistics(
+    buffer_pool: &BufferPool,
+    db_oid: &Oid,
+    rel_oid: &Oid,
+) -> Result<Vec<PgStatistic>> {
+    let pg_statistic_rel = access::open_pg_statistic_relation(db_oid);
+
+    let mut statistics = Vec::new();
+
+    let mut heap = HeapScanner::new(buffer_pool, &pg_statistic_rel)?;
+    while let Some(tuple) = heap.next_tuple()? {
+        let statistic = tuple.decode_data::<PgStatistic>()?;
+        if statistic.starelid == *rel_oid {
+            statistics.push(statistic);
+        }
+    }
+    statistics.sort_by_key(|statistic| statistic.staattnum);
+
+    Ok(statistics)
+}
+
+/// Return all pg_class tuples of the given database.
+pub fn get_pg_class_relations(buffer_pool: &BufferPool, db_oid: &Oid) -> Result<Vec<PgClass>> {
     let pg_class_rel = access::open_pg_class_relation(db_oid);
 
-    let mut pg_class_tuple = None;
+    let mut relations = Vec::new();
 
     let mut heap = HeapScanner::new(buffer_pool, &pg_class_rel)?;
     while let Some(tuple) = heap.next_tuple()? {
-        // Do nothing if the oid is already founded.
-        if pg_class_tuple.is_none() {
-            let pg_class = bincode::deserialize::<PgClass>(&tuple.data)?;
-            if pg_class.relname == rel_name {
-                pg_class_tuple = Some(pg_class);
-            }
-        }
+        relations.push(tuple.decode_data::<PgClass>()?);
+    }
+
+    Ok(relations)
+}
+
+/// Return the pg class tuple from the given relation name. Names without a schema are searched
+/// on the schemas of the given search path, and the relation of the first schema is returned.
+pub fn get_pg_class_relation(
+    buffer_pool: &BufferPool,
+    db_oid: &Oid,
+    search_path: &[String],
+    rel_name: &RelationName,
+) -> Result<PgClass> {
+    let namespaces = namespace::search_namespaces(buffer_pool, db_oid, search_path, rel_name)?;
+    match find_relation(buffer_pool, db_oid, &namespaces, &rel_name.name)? {
+        Some(pg_class) => Ok(pg_class),
+        None => bail!(Error::RelationNotFound(rel_name.to_string())),
     }
+}
 
-    match pg_class_tuple {
-        Some(tuple) => Ok(tuple),
+/// Return the pg_class tuple of the relation with the given name of the given schema, None if
+/// the schema has no relation with this name.
+pub fn get_namespace_relation(
+    buffer_pool: &BufferPool,
+    db_oid: &Oid,
+    namespace: &Oid,
+    rel_name: &str,
+) -> Result<Option<PgClass>> {
+    find_relation(buffer_pool, db_oid, &[*namespace], rel_name)
+}
+
+/// Return the pg_class tuple of the relation with the given name and oid, e.g. of a relation
+/// opened by a query plan.
+pub fn get_pg_class_relation_by_oid(
+    buffer_pool: &BufferPool,
+    db_oid: &Oid,
+    rel_name: &str,
+    rel_oid: &Oid,
+) -> Result<PgClass> {
+    match relations_with_name(buffer_pool, db_oid, rel_name)?
+        .into_iter()
+        .find(|pg_class| pg_class.oid == *rel_oid)
+    {
+        Some(pg_class) => Ok(pg_class),
         None => bail!(Error::RelationNotFound(rel_name.to_string())),
     }
 }
 
+/// Return the pg_class tuple of the relation with the given name of the first of the given
+/// schemas that has a relation with this name.
+fn find_relation(
+    buffer_pool: &BufferPool,
+    db_oid: &Oid,
+    namespaces: &[Oid],
+    rel_name: &str,
+) -> Result<Option<PgClass>> {
+    // Relations with the same name of all schemas, to return the one of the first schema.
+    let pg_class_tuples = relations_with_name(buffer_pool, db_oid, rel_name)?;
+
+    Ok(namespaces.iter().find_map(|namespace| {
+        pg_class_tuples
+            .iter()
+            .find(|pg_class| pg_class.relnamespace == *namespace)
+            .cloned()
+    }))
+}
+
+/// Return the pg_class tuples of all relations with the given name, of any schema.
+fn relations_with_name(
+    buffer_pool: &BufferPool,
+    db_oid: &Oid,
+    rel_name: &str,
+) -> Result<Vec<PgClass>> {
+    let cached = buffer_pool.catalog_cache().relations(db_oid, rel_name);
+    let pg_class_tuples = match cached {
+        Some(pg_class_tuples) => pg_class_tuples,
+        None => {
+            let pg_class_rel = access::open_pg_class_relation(db_oid);
+
+            let mut pg_class_tuples = Vec::new();
+            let mut heap = HeapScanner::new(buffer_pool, &pg_class_rel)?;
+            while let Some(tuple) = heap.next_tuple()? {
+                let pg_class = tuple.decode_data::<PgClass>()?;
+                if pg_class.relname == rel_name {
+                    pg_class_tuples.push(pg_class);
+                }
+            }
+            if !pg_class_tuples.is_empty() {
+                buffer_pool
+                    .catalog_cache()
+                    .insert_relations(db_oid, rel_name, &pg_class_tuples);
+            }
+            pg_class_tuples
+        }
+    };
+    Ok(pg_class_tuples)
+}
+
 /// Return the database oid for the given database name.
 pub fn get_datase_oid(buffer_pool: &BufferPool, dbname: &str) -> Result<Oid> {
     let pg_database_rel = access::open_pg_database_relation();
 
     let mut heap = HeapScanner::new(buffer_pool, &pg_database_rel)?;
     while let Some(tuple) = heap.next_tuple()? {
-        let pg_database = bincode::deserialize::<PgDatabase>(&tuple.data)?;
+        let pg_database = tuple.decode_data::<PgDatabase>()?;
         if pg_database.datname == dbname {
             return Ok(pg_database.oid);
         }
@@ -91,14 +273,86 @@ pub fn get_datase_oid(buffer_pool: &BufferPool, dbname: &str) -> Result<Oid> {
     bail!(Error::DatabaseNotFound(dbname.to_string()))
 }
 
-/// Genereate a new relation oid that is unique to the given the database.
+/// Return all pg_database tuples.
+pub fn get_pg_databases(buffer_pool: &BufferPool) -> Result<Vec<PgDatabase>> {
+    let pg_database_rel = access::open_pg_database_relation();
+
+    let mut databases = Vec::new();
+    let mut heap = HeapScanner::new(buffer_pool, &pg_database_rel)?;
+    while let Some(tuple) = heap.next_tuple()? {
+        databases.push(tuple.decode_data::<PgDatabase>()?);
+    }
+
+    Ok(databases)
+}
+
+/// Create a new database with the given name, with a copy of all relations of the template
+/// database, and return the oid of the new database.
 ///
-/// Note that the current working directory is expected to be the data directory.
-pub fn new_relation_oid(tablespace: &Oid, db_oid: &Oid) -> Result<Oid> {
+/// The caller must ensure that no other database has the same name and that the template is
+/// not being changed while it is copied.
+pub fn create_database(buffer_pool: &BufferPool, datname: &str, template_oid: &Oid) -> Result<Oid> {
+    let databases = get_pg_databases(buffer_pool)?;
+    let db_oid = loop {
+        let db_oid = new_object_id();
+        let used = databases.iter().any(|database| database.oid == db_oid);
+        if !used && !buffer_pool.database_exists(&db_oid) {
+            break db_oid;
+        }
+    };
+
+    // The directory of the database is created before the pg_database tuple, so a database
+    // listed on pg_database always has a directory.
+    buffer_pool.copy_database(template_oid, &db_oid)?;
+    heap_insert(
+        buffer_pool,
+        &access::open_pg_database_relation(),
+        &HeapTuple::with_default_header(&PgDatabase {
+            oid: db_oid,
+            datname: datname.to_string(),
+            dattablespace: DEFAULTTABLESPACE_OID,
+        })?,
+    )?;
+
+    Ok(db_oid)
+}
+
+/// Return the pg_authid tuple of the given role name.
+pub fn get_pg_authid(buffer_pool: &BufferPool, rolname: &str) -> Result<PgAuthId> {
+    let pg_authid_rel = access::open_pg_authid_relation();
+
+    let mut heap = HeapScanner::new(buffer_pool, &pg_authid_rel)?;
+    while let Some(tuple) = heap.next_tuple()? {
+        let pg_authid = tuple.decode_data::<PgAuthId>()?;
+        if pg_authid.rolname == rolname {
+            return Ok(pg_authid);
+        }
+    }
+
+    bail!(Error::RoleNotFound(rolname.to_string()))
+}
+
+/// Return the pg_authid tuple of the given role oid.
+pub fn get_pg_authid_by_oid(buffer_pool: &BufferPool, oid: &Oid) -> Result<PgAuthId> {
+    let pg_authid_rel = access::open_pg_authid_relation();
+
+    let mut heap = HeapScanner::new(buffer_pool, &pg_authid_rel)?;
+    while let Some(tuple) = heap.next_tuple()? {
+        let pg_authid = tuple.decode_data::<PgAuthId>()?;
+        if pg_authid.oid == *oid {
+            return Ok(pg_authid);
+        }
+    }
+
+    bail!(Error::RoleNotFound(oid.to_string()))
+}
+
+/// Genereate a new relation oid that is unique to the given the database, which has no storage
+/// on the given buffer pool.
+pub fn new_relation_oid(buffer_pool: &BufferPool, tablespace: &Oid, db_oid: &Oid) -> Result<Oid> {
     loop {
         let rel_oid = new_object_id();
-        let relpath = relation_path(tablespace, db_oid, &rel_oid)?;
-        if !relpath.exists() {
+        if !buffer_pool.relation_exists(tablespace, db_oid, &rel_oid)? {
             return Ok(rel_oid);
         }
     }
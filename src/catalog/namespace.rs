@@ -0,0 +1,315 @@
+//! Resolution of relation names to the schemas where the relations are stored.
+//!
+//! A relation name qualified with a schema name, e.g. app.users, is searched only on the given
+//! schema. Names without a schema are searched on the schemas of the search_path session
+//! variable, in order, after the pg_catalog schema, like Postgres.
+
+use std::fmt;
+
+use anyhow::{bail, Result};
+use sqlparser::ast;
+
+use crate::{
+    access::{
+        self,
+        heap::{heap_insert, HeapScanner},
+        heaptuple::HeapTuple,
+    },
+    new_object_id,
+    sql::error::{PgError, SqlState},
+    storage::BufferPool,
+    Oid,
+};
+
+use super::{
+    heap::initialize_default_page_header,
+    pg_namespace::{PgNamespace, BUILTIN_NAMESPACES, PG_CATALOG_NAMESPACE},
+    Error,
+};
+
+/// Name of a relation referenced on a statement, optionally qualified with a schema name.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RelationName {
+    /// Schema of the relation. None if the relation is searched on the search path.
+    pub schema: Option<String>,
+
+    /// Name of the relation inside its schema.
+    pub name: String,
+}
+
+impl RelationName {
+    /// Return the name of a relation that is searched on the search path.
+    pub fn new(name: &str) -> Self {
+        Self {
+            schema: None,
+            name: name.to_string(),
+        }
+    }
+
+    /// Return the relation name of the given object name of a statement, which may be
+    /// qualified with a schema name.
+    pub fn from_object_name(name: &ast::ObjectName) -> Result<Self> {
+        match name.0.as_slice() {
+            [name] => Ok(Self {
+                schema: None,
+                name: name.value.clone(),
+            }),
+            [schema, name] => Ok(Self {
+                schema: Some(schema.value.clone()),
+                name: name.value.clone(),
+            }),
+            _ => bail!(PgError::new(
+                SqlState::FeatureNotSupported,
+                format!("cross-database references are not implemented: {}", name)
+            )),
+        }
+    }
+
+    /// Return the relation name of a name written as schema.name or name, like the names given
+    /// to psql meta-commands.
+    pub fn parse(name: &str) -> Self {
+        match name.split_once('.') {
+            Some((schema, name)) => Self {
+                schema: Some(schema.to_string()),
+                name: name.to_string(),
+            },
+            None => Self::new(name),
+        }
+    }
+
+    /// Return true if the name may refer to a relation of the pg_catalog schema.
+    pub fn is_catalog(&self) -> bool {
+        self.schema
+            .as_ref()
+            .is_none_or(|schema| schema == "pg_catalog")
+    }
+}
+
+impl fmt::Display for RelationName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.schema {
+            Some(schema) => write!(f, "{}.{}", schema, self.name),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// Return the oid of the schema with the given name, or an error if the schema does not exist.
+pub fn get_namespace_oid(buffer_pool: &BufferPool, db_oid: &Oid, nspname: &str) -> Result<Oid> {
+    match find_namespace(buffer_pool, db_oid, nspname)? {
+        Some(oid) => Ok(oid),
+        None => bail!(Error::NamespaceNotFound(nspname.to_string())),
+    }
+}
+
+/// Return the oid of the schema with the given name, None if the schema does not exist.
+fn find_namespace(buffer_pool: &BufferPool, db_oid: &Oid, nspname: &str) -> Result<Option<Oid>> {
+    // The builtin schemas are always known, even on databases initialized before pg_namespace
+    // existed.
+    if let Some((oid, _)) = BUILTIN_NAMESPACES.iter().find(|(_, name)| *name == nspname) {
+        return Ok(Some(*oid));
+    }
+    if let Some(oid) = buffer_pool.catalog_cache().namespace(db_oid, nspname) {
+        return Ok(Some(oid));
+    }
+
+    let namespace = get_pg_namespaces(buffer_pool, db_oid)?
+        .into_iter()
+        .find(|namespace| namespace.nspname == nspname);
+    if let Some(namespace) = &namespace {
+        buffer_pool
+            .catalog_cache()
+            .insert_namespace(db_oid, &namespace.nspname, &namespace.oid);
+    }
+    Ok(namespace.map(|namespace| namespace.oid))
+}
+
+/// Return the name of the schema with the given oid.
+pub fn get_namespace_name(buffer_pool: &BufferPool, db_oid: &Oid, oid: &Oid) -> Result<String> {
+    if let Some((_, name)) = BUILTIN_NAMESPACES.iter().find(|(nspoid, _)| nspoid == oid) {
+        return Ok(name.to_string());
+    }
+    match get_pg_namespaces(buffer_pool, db_oid)?
+        .into_iter()
+        .find(|namespace| namespace.oid == *oid)
+    {
+        Some(namespace) => Ok(namespace.nspname),
+        None => bail!(Error::NamespaceNotFound(oid.to_string())),
+    }
+}
+
+/// Return all pg_namespace tuples of the given database.
+pub fn get_pg_namespaces(buffer_pool: &BufferPool, db_oid: &Oid) -> Result<Vec<PgNamespace>> {
+    let pg_namespace_rel = access::open_pg_namespace_relation(db_oid);
+
+    let mut namespaces = Vec::new();
+    let mut heap = HeapScanner::new(buffer_pool, &pg_namespace_rel)?;
+    while let Some(tuple) = heap.next_tuple()? {
+        namespaces.push(tuple.decode_data::<PgNamespace>()?);
+    }
+
+    Ok(namespaces)
+}
+
+/// Return the oids of the schemas where the given relation name is searched, in order.
+///
+/// Qualified names are searched only on their schema, which must exist. Other names are searched
+/// on pg_catalog first, unless it is listed explicitly on the search path, and then on the
+/// schemas of the search path that exist.
+pub fn search_namespaces(
+    buffer_pool: &BufferPool,
+    db_oid: &Oid,
+    search_path: &[String],
+    name: &RelationName,
+) -> Result<Vec<Oid>> {
+    if let Some(schema) = &name.schema {
+        return Ok(vec![get_namespace_oid(buffer_pool, db_oid, schema)?]);
+    }
+
+    let mut namespaces = Vec::new();
+    if !search_path.iter().any(|schema| schema == "pg_catalog") {
+        namespaces.push(PG_CATALOG_NAMESPACE);
+    }
+    for schema in search_path {
+        if let Some(oid) = find_namespace(buffer_pool, db_oid, schema)? {
+            if !namespaces.contains(&oid) {
+                namespaces.push(oid);
+            }
+        }
+    }
+    Ok(namespaces)
+}
+
+/// Return the oid of the schema where a relation with the given name is created: the schema of
+/// qualified names, or the first schema of the search path that exists.
+pub fn creation_namespace(
+    buffer_pool: &BufferPool,
+    db_oid: &Oid,
+    search_path: &[String],
+    name: &RelationName,
+) -> Result<Oid> {
+    if let Some(schema) = &name.schema {
+        return get_namespace_oid(buffer_pool, db_oid, schema);
+    }
+    for schema in search_path {
+        if let Some(oid) = find_namespace(buffer_pool, db_oid, schema)? {
+            return Ok(oid);
+        }
+    }
+    bail!(PgError::new(
+        SqlState::InvalidSchemaName,
+        "no schema has been selected to create in"
+    ))
+}
+
+/// Create a new schema with the given name on the given database and return its oid. Return an
+/// error if a schema with the same name already exists.
+pub fn namespace_create(buffer_pool: &BufferPool, db_oid: &Oid, nspname: &str) -> Result<Oid> {
+    if find_namespace(buffer_pool, db_oid, nspname)?.is_some() {
+        bail!(Error::NamespaceAlreadyExists(nspname.to_string()));
+    }
+    if nspname.starts_with("pg_") {
+        bail!(PgError::new(
+            SqlState::ReservedName,
+            format!("unacceptable schema name \"{}\"", nspname)
+        )
+        .with_detail("The prefix \"pg_\" is reserved for system schemas."));
+    }
+
+    let pg_namespace_rel = access::open_pg_namespace_relation(db_oid);
+    initialize_default_page_header(buffer_pool, &pg_namespace_rel)?;
+
+    let namespaces = get_pg_namespaces(buffer_pool, db_oid)?;
+    let oid = loop {
+        let oid = new_object_id();
+        if !namespaces.iter().any(|namespace| namespace.oid == oid) {
+            break oid;
+        }
+    };
+    heap_insert(
+        buffer_pool,
+        &pg_namespace_rel,
+        &HeapTuple::with_default_header(&PgNamespace {
+            oid,
+            nspname: nspname.to_string(),
+        })?,
+    )?;
+    buffer_pool
+        .catalog_cache()
+        .insert_namespace(db_oid, nspname, &oid);
+
+    Ok(oid)
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlparser::ast::Ident;
+
+    use crate::{
+        catalog::{pg_database::TINYDB_OID, pg_namespace::PG_PUBLIC_NAMESPACE},
+        initdb::init_catalogs,
+        storage::smgr::StorageManager,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_relation_name() -> Result<()> {
+        let name = RelationName::from_object_name(&ast::ObjectName(vec![Ident::new("t")]))?;
+        assert_eq!(name, RelationName::new("t"));
+        assert!(name.is_catalog());
+
+        let name = RelationName::from_object_name(&ast::ObjectName(vec![
+            Ident::new("app"),
+            Ident::new("t"),
+        ]))?;
+        assert_eq!(name.schema.as_deref(), Some("app"));
+        assert_eq!(name.to_string(), "app.t");
+        assert!(!name.is_catalog());
+        assert_eq!(RelationName::parse("app.t"), name);
+        assert_eq!(RelationName::parse("t"), RelationName::new("t"));
+
+        assert!(RelationName::from_object_name(&ast::ObjectName(vec![
+            Ident::new("db"),
+            Ident::new("app"),
+            Ident::new("t"),
+        ]))
+        .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_namespaces() -> Result<()> {
+        let buffer_pool = BufferPool::new(50, StorageManager::in_memory());
+        init_catalogs(&buffer_pool, "tinydb", None)?;
+        let app = namespace_create(&buffer_pool, &TINYDB_OID, "app")?;
+        assert!(namespace_create(&buffer_pool, &TINYDB_OID, "app").is_err());
+        assert!(namespace_create(&buffer_pool, &TINYDB_OID, "pg_app").is_err());
+        assert_eq!(get_namespace_name(&buffer_pool, &TINYDB_OID, &app)?, "app");
+
+        let search_path = vec![
+            String::from("$user"),
+            String::from("app"),
+            String::from("public"),
+        ];
+        let unqualified = RelationName::new("t");
+        assert_eq!(
+            search_namespaces(&buffer_pool, &TINYDB_OID, &search_path, &unqualified)?,
+            vec![PG_CATALOG_NAMESPACE, app, PG_PUBLIC_NAMESPACE]
+        );
+        assert_eq!(
+            creation_namespace(&buffer_pool, &TINYDB_OID, &search_path, &unqualified)?,
+            app
+        );
+
+        let qualified = RelationName {
+            schema: Some(String::from("missing")),
+            name: String::from("t"),
+        };
+        assert!(search_namespaces(&buffer_pool, &TINYDB_OID, &search_path, &qualified).is_err());
+
+        let err = creation_namespace(&buffer_pool, &TINYDB_OID, &[], &unqualified).unwrap_err();
+        assert_eq!(PgError::from_error(&err).code, SqlState::InvalidSchemaName);
+        Ok(())
+    }
+}
@@ -1,11 +1,10 @@
+use anyhow::Result;
+
+use super::pg_type;
 use crate::{
-    access::heaptuple::TupleDesc,
-    sql::encode::{varlena_deserializer, varlena_serializer},
+    access::heaptuple::{HeapTupleData, TupleDataReader, TupleDataWriter, TupleDesc},
     Oid,
 };
-use serde::{Deserialize, Serialize};
-
-use super::pg_type;
 
 /// Fixed oid of pg_attribute relation.
 pub const RELATION_OID: Oid = 1249;
@@ -14,14 +13,12 @@ pub const RELATION_NAME: &'static str = "pg_attribute";
 
 /// The catalog pg_attribute stores information about table columns. There will be exactly one pg_attribute row for
 /// every column in every table in the database.
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Debug, Clone)]
 pub struct PgAttribute {
     /// The relation this column belongs to.
     pub attrelid: Oid,
 
     /// The column name.
-    #[serde(deserialize_with = "varlena_deserializer")]
-    #[serde(serialize_with = "varlena_serializer")]
     pub attname: String,
 
     /// The number of the column (start at 1).
@@ -32,9 +29,55 @@ pub struct PgAttribute {
 
     /// The data type of this column
     pub atttypid: Oid,
+
+    /// This represents a not-null constraint.
+    pub attnotnull: bool,
+
+    /// The SQL text of the column default expression. Empty if the column has no default.
+    pub attdefault: String,
+}
+
+impl HeapTupleData for PgAttribute {
+    fn encode(&self, writer: &mut TupleDataWriter) {
+        writer.put_u64(self.attrelid);
+        writer.put_str(&self.attname);
+        writer.put_u64(self.attnum as u64);
+        writer.put_i64(self.attlen);
+        writer.put_u64(self.atttypid);
+        writer.put_bool(self.attnotnull);
+        writer.put_str(&self.attdefault);
+    }
+
+    fn decode(reader: &mut TupleDataReader<'_>) -> Result<Self> {
+        Ok(Self {
+            attrelid: reader.get_u64()?,
+            attname: reader.get_str()?,
+            attnum: reader.get_u64()? as usize,
+            attlen: reader.get_i64()?,
+            atttypid: reader.get_u64()?,
+            attnotnull: reader.get_bool()?,
+            attdefault: reader.get_str()?,
+        })
+    }
 }
 
 impl PgAttribute {
+    /// Return true if the values of the attribute are stored as varlena. Varchar attributes store
+    /// their maximum length on attlen, but their values are variable length like text values.
+    pub fn is_varlena(&self) -> bool {
+        self.attlen < 0 || self.atttypid == pg_type::VARCHAR_OID
+    }
+
+    /// Return the SQL name of the attribute type, with the maximum length of varchar attributes.
+    pub fn type_name(&self) -> String {
+        let name = pg_type::type_name(self.atttypid);
+        if self.atttypid == pg_type::VARCHAR_OID && self.attlen >= 0 {
+            format!("{}({})", name, self.attlen)
+        } else {
+            String::from(name)
+        }
+    }
+
     /// Return the tuple description from pg_attribute system relation.
     pub fn tuple_desc() -> TupleDesc {
         TupleDesc {
@@ -45,6 +88,8 @@ impl PgAttribute {
                     attnum: 1,
                     attlen: 8,
                     atttypid: pg_type::INT_OID,
+                    attnotnull: false,
+                    attdefault: String::new(),
                 },
                 PgAttribute {
                     attrelid: RELATION_OID,
@@ -52,6 +97,8 @@ impl PgAttribute {
                     attnum: 2,
                     attlen: -1,
                     atttypid: pg_type::VARCHAR_OID,
+                    attnotnull: false,
+                    attdefault: String::new(),
                 },
                 PgAttribute {
                     attrelid: RELATION_OID,
@@ -59,6 +106,8 @@ impl PgAttribute {
                     attnum: 3,
                     attlen: 8,
                     atttypid: pg_type::INT_OID,
+                    attnotnull: false,
+                    attdefault: String::new(),
                 },
                 PgAttribute {
                     attrelid: RELATION_OID,
@@ -66,6 +115,8 @@ impl PgAttribute {
                     attnum: 4,
                     attlen: 8,
                     atttypid: pg_type::INT_OID,
+                    attnotnull: false,
+                    attdefault: String::new(),
                 },
             ],
         }
@@ -0,0 +1,67 @@
+use anyhow::Result;
+
+use crate::{
+    access::heaptuple::{HeapTupleData, TupleDataReader, TupleDataWriter, TupleDesc},
+    Oid,
+};
+
+use super::{pg_attribute::PgAttribute, pg_type};
+
+/// Fixed oid of pg_auth_members relation.
+pub const RELATION_OID: Oid = 1261;
+
+pub const RELATION_NAME: &str = "pg_auth_members";
+
+/// The catalog pg_auth_members shows the membership relations between roles. A member of a role
+/// has all privileges of the role. Like pg_authid, pg_auth_members is shared across all
+/// databases of a cluster.
+#[derive(Debug, Clone)]
+pub struct PgAuthMembers {
+    /// Oid of the role that has a member.
+    pub roleid: Oid,
+
+    /// Oid of the role that is a member of roleid.
+    pub member: Oid,
+}
+
+impl HeapTupleData for PgAuthMembers {
+    fn encode(&self, writer: &mut TupleDataWriter) {
+        writer.put_u64(self.roleid);
+        writer.put_u64(self.member);
+    }
+
+    fn decode(reader: &mut TupleDataReader<'_>) -> Result<Self> {
+        Ok(Self {
+            roleid: reader.get_u64()?,
+            member: reader.get_u64()?,
+        })
+    }
+}
+
+impl PgAuthMembers {
+    /// Return the tuple description from pg_auth_members system relation.
+    pub fn tuple_desc() -> TupleDesc {
+        TupleDesc {
+            attrs: vec![
+                PgAttribute {
+                    attrelid: RELATION_OID,
+                    attname: String::from("roleid"),
+                    attnum: 1,
+                    attlen: 8,
+                    atttypid: pg_type::INT_OID,
+                    attnotnull: false,
+                    attdefault: String::new(),
+                },
+                PgAttribute {
+                    attrelid: RELATION_OID,
+                    attname: String::from("member"),
+                    attnum: 2,
+                    attlen: 8,
+                    atttypid: pg_type::INT_OID,
+                    attnotnull: false,
+                    attdefault: String::new(),
+                },
+            ],
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use anyhow::Result;
+
+use crate::{
+    access::heaptuple::{HeapTupleData, TupleDataReader, TupleDataWriter, TupleDesc},
+    Oid,
+};
+
+use super::{pg_attribute::PgAttribute, pg_type};
+
+/// Fixed oid of pg_authid relation.
+pub const RELATION_OID: Oid = 1260;
+
+pub const RELATION_NAME: &str = "pg_authid";
+
+/// Oid of the bootstrap superuser created by initdb.
+pub const BOOTSTRAP_SUPERUSER_ID: Oid = 10;
+
+/// The catalog pg_authid contains information about database authorization identifiers (roles).
+/// Because user identities are cluster-wide, pg_authid is shared across all databases of a
+/// cluster.
+#[derive(Debug)]
+pub struct PgAuthId {
+    /// Oid of role.
+    pub oid: Oid,
+
+    /// Role name.
+    pub rolname: String,
+
+    /// Password of role stored as "md5" followed by the md5 hash of the password concatenated
+    /// with the role name. Empty if the role has no password.
+    pub rolpassword: String,
+
+    /// Role has superuser privileges, which bypass all permission checks.
+    pub rolsuper: bool,
+
+    /// Role can log in, so it can be given as the user name of a connection.
+    pub rolcanlogin: bool,
+}
+
+impl HeapTupleData for PgAuthId {
+    fn encode(&self, writer: &mut TupleDataWriter) {
+        writer.put_u64(self.oid);
+        writer.put_str(&self.rolname);
+        writer.put_str(&self.rolpassword);
+        writer.put_bool(self.rolsuper);
+        writer.put_bool(self.rolcanlogin);
+    }
+
+    fn decode(reader: &mut TupleDataReader<'_>) -> Result<Self> {
+        Ok(Self {
+            oid: reader.get_u64()?,
+            rolname: reader.get_str()?,
+            rolpassword: reader.get_str()?,
+            // Rows written before roles had attributes are of the bootstrap superuser, the only
+            // role that could exist.
+            rolsuper: reader.is_empty() || reader.get_bool()?,
+            rolcanlogin: reader.is_empty() || reader.get_bool()?,
+        })
+    }
+}
+
+impl PgAuthId {
+    /// Return the tuple description from pg_authid system relation.
+    pub fn tuple_desc() -> TupleDesc {
+        TupleDesc {
+            attrs: vec![
+                PgAttribute {
+                    attrelid: RELATION_OID,
+                    attname: String::from("oid"),
+                    attnum: 1,
+                    attlen: 8,
+                    atttypid: pg_type::INT_OID,
+                    attnotnull: false,
+                    attdefault: String::new(),
+                },
+                PgAttribute {
+                    attrelid: RELATION_OID,
+                    attname: String::from("rolname"),
+                    attnum: 2,
+                    attlen: -1,
+                    atttypid: pg_type::VARCHAR_OID,
+                    attnotnull: false,
+                    attdefault: String::new(),
+                },
+                PgAttribute {
+                    attrelid: RELATION_OID,
+                    attname: String::from("rolpassword"),
+                    attnum: 3,
+                    attlen: -1,
+                    atttypid: pg_type::VARCHAR_OID,
+                    attnotnull: false,
+                    attdefault: String::new(),
+                },
+                PgAttribute {
+                    attrelid: RELATION_OID,
+                    attname: String::from("rolsuper"),
+                    attnum: 4,
+                    attlen: 1,
+                    atttypid: pg_type::BOOL_OID,
+                    attnotnull: false,
+                    attdefault: String::new(),
+                },
+                PgAttribute {
+                    attrelid: RELATION_OID,
+                    attname: String::from("rolcanlogin"),
+                    attnum: 5,
+                    attlen: 1,
+                    atttypid: pg_type::BOOL_OID,
+                    attnotnull: false,
+                    attdefault: String::new(),
+                },
+            ],
+        }
+    }
+}
@@ -1,12 +1,19 @@
-use serde::{Deserialize, Serialize};
+use anyhow::Result;
 
 use crate::{
-    access::heaptuple::TupleDesc,
-    sql::encode::{varlena_deserializer, varlena_serializer},
-    Oid,
+    access::{
+        heaptuple::{HeapTupleData, TupleDataReader, TupleDataWriter, TupleDesc},
+        tableam::HEAP_TABLE_AM_OID,
+    },
+    Oid, FIRST_NORMAL_OBJECT_ID,
 };
 
-use super::{pg_attribute::PgAttribute, pg_type};
+use super::{
+    pg_attribute::PgAttribute,
+    pg_authid::BOOTSTRAP_SUPERUSER_ID,
+    pg_namespace::{PG_CATALOG_NAMESPACE, PG_PUBLIC_NAMESPACE},
+    pg_type,
+};
 
 /// Fixed oid of pg_class relation.
 pub const RELATION_OID: Oid = 1259;
@@ -14,14 +21,12 @@ pub const RELATION_OID: Oid = 1259;
 pub const RELATION_NAME: &'static str = "pg_class";
 
 /// The catalog pg_class catalogs tables and most everything else that has columns or is otherwise similar to a table.
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Debug, Clone)]
 pub struct PgClass {
     /// OID of relation.
     pub oid: Oid,
 
     /// Relation name.
-    #[serde(deserialize_with = "varlena_deserializer")]
-    #[serde(serialize_with = "varlena_serializer")]
     pub relname: String,
 
     /// The tablespace in which this relation is stored.
@@ -30,6 +35,70 @@ pub struct PgClass {
     /// True if this table is shared across all databases in the cluster. Only certain system
     /// catalogs (such as pg_database) are shared.
     pub relisshared: bool,
+
+    /// The access method used to store the relation: a table access method for tables, or the
+    /// index access method for indexes.
+    pub relam: Oid,
+
+    /// The oid of the schema that contains this relation.
+    pub relnamespace: Oid,
+
+    /// The oid of the role that owns this relation.
+    pub relowner: Oid,
+
+    /// Access privileges of the relation, formatted as described on acl. Empty if the relation
+    /// has the default privileges.
+    pub relacl: String,
+}
+
+impl HeapTupleData for PgClass {
+    fn encode(&self, writer: &mut TupleDataWriter) {
+        writer.put_u64(self.oid);
+        writer.put_str(&self.relname);
+        writer.put_u64(self.reltablespace);
+        writer.put_bool(self.relisshared);
+        writer.put_u64(self.relam);
+        writer.put_u64(self.relnamespace);
+        writer.put_u64(self.relowner);
+        writer.put_str(&self.relacl);
+    }
+
+    fn decode(reader: &mut TupleDataReader<'_>) -> Result<Self> {
+        let oid = reader.get_u64()?;
+        Ok(Self {
+            oid,
+            relname: reader.get_str()?,
+            reltablespace: reader.get_u64()?,
+            relisshared: reader.get_bool()?,
+            // Rows written before the relam column was added are heap tables.
+            relam: if reader.is_empty() {
+                HEAP_TABLE_AM_OID
+            } else {
+                reader.get_u64()?
+            },
+            // Rows written before schemas were added are system catalogs or user relations of
+            // the public schema.
+            relnamespace: if !reader.is_empty() {
+                reader.get_u64()?
+            } else if oid < FIRST_NORMAL_OBJECT_ID {
+                PG_CATALOG_NAMESPACE
+            } else {
+                PG_PUBLIC_NAMESPACE
+            },
+            // Rows written before roles had privileges are owned by the bootstrap superuser,
+            // the only role that could create relations.
+            relowner: if reader.is_empty() {
+                BOOTSTRAP_SUPERUSER_ID
+            } else {
+                reader.get_u64()?
+            },
+            relacl: if reader.is_empty() {
+                String::new()
+            } else {
+                reader.get_str()?
+            },
+        })
+    }
 }
 
 impl PgClass {
@@ -43,6 +112,8 @@ impl PgClass {
                     attnum: 1,
                     attlen: 8,
                     atttypid: pg_type::INT_OID,
+                    attnotnull: false,
+                    attdefault: String::new(),
                 },
                 PgAttribute {
                     attrelid: RELATION_OID,
@@ -50,6 +121,8 @@ impl PgClass {
                     attnum: 2,
                     attlen: -1,
                     atttypid: pg_type::VARCHAR_OID,
+                    attnotnull: false,
+                    attdefault: String::new(),
                 },
                 PgAttribute {
                     attrelid: RELATION_OID,
@@ -57,6 +130,8 @@ impl PgClass {
                     attnum: 3,
                     attlen: 8,
                     atttypid: pg_type::INT_OID,
+                    attnotnull: false,
+                    attdefault: String::new(),
                 },
                 PgAttribute {
                     attrelid: RELATION_OID,
@@ -64,6 +139,44 @@ impl PgClass {
                     attnum: 4,
                     attlen: 1,
                     atttypid: pg_type::BOOL_OID,
+                    attnotnull: false,
+                    attdefault: String::new(),
+                },
+                PgAttribute {
+                    attrelid: RELATION_OID,
+                    attname: String::from("relam"),
+                    attnum: 5,
+                    attlen: 8,
+                    atttypid: pg_type::INT_OID,
+                    attnotnull: false,
+                    attdefault: String::new(),
+                },
+                PgAttribute {
+                    attrelid: RELATION_OID,
+                    attname: String::from("relnamespace"),
+                    attnum: 6,
+                    attlen: 8,
+                    atttypid: pg_type::INT_OID,
+                    attnotnull: false,
+                    attdefault: String::new(),
+                },
+                PgAttribute {
+                    attrelid: RELATION_OID,
+                    attname: String::from("relowner"),
+                    attnum: 7,
+                    attlen: 8,
+                    atttypid: pg_type::INT_OID,
+                    attnotnull: false,
+                    attdefault: String::new(),
+                },
+                PgAttribute {
+                    attrelid: RELATION_OID,
+                    attname: String::from("relacl"),
+                    attnum: 8,
+                    attlen: -1,
+                    atttypid: pg_type::VARCHAR_OID,
+                    attnotnull: false,
+                    attdefault: String::new(),
                 },
             ],
         }
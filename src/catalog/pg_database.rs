@@ -1,8 +1,7 @@
-use serde::{Deserialize, Serialize};
+use anyhow::Result;
 
 use crate::{
-    access::heaptuple::TupleDesc,
-    sql::encode::{varlena_deserializer, varlena_serializer},
+    access::heaptuple::{HeapTupleData, TupleDataReader, TupleDataWriter, TupleDesc},
     Oid,
 };
 
@@ -15,20 +14,34 @@ pub const RELATION_NAME: &'static str = "pg_database";
 
 pub const TINYDB_OID: Oid = 5;
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Debug)]
 pub struct PgDatabase {
     /// Oid of database.
     pub oid: Oid,
 
     /// Database name.
-    #[serde(deserialize_with = "varlena_deserializer")]
-    #[serde(serialize_with = "varlena_serializer")]
     pub datname: String,
 
     /// The default tablespace for the database.
     pub dattablespace: Oid,
 }
 
+impl HeapTupleData for PgDatabase {
+    fn encode(&self, writer: &mut TupleDataWriter) {
+        writer.put_u64(self.oid);
+        writer.put_str(&self.datname);
+        writer.put_u64(self.dattablespace);
+    }
+
+    fn decode(reader: &mut TupleDataReader<'_>) -> Result<Self> {
+        Ok(Self {
+            oid: reader.get_u64()?,
+            datname: reader.get_str()?,
+            dattablespace: reader.get_u64()?,
+        })
+    }
+}
+
 impl PgDatabase {
     /// Return the tuple description from pg_database system relation.
     pub fn tuple_desc() -> TupleDesc {
@@ -40,6 +53,8 @@ impl PgDatabase {
                     attnum: 1,
                     attlen: 8,
                     atttypid: pg_type::INT_OID,
+                    attnotnull: false,
+                    attdefault: String::new(),
                 },
                 PgAttribute {
                     attrelid: RELATION_OID,
@@ -47,6 +62,8 @@ impl PgDatabase {
                     attnum: 2,
                     attlen: -1,
                     atttypid: pg_type::VARCHAR_OID,
+                    attnotnull: false,
+                    attdefault: String::new(),
                 },
                 PgAttribute {
                     attrelid: RELATION_OID,
@@ -54,6 +71,8 @@ impl PgDatabase {
                     attnum: 3,
                     attlen: 8,
                     atttypid: pg_type::INT_OID,
+                    attnotnull: false,
+                    attdefault: String::new(),
                 },
             ],
         }
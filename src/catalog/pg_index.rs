@@ -0,0 +1,96 @@
+use anyhow::Result;
+
+use crate::{
+    access::heaptuple::{HeapTupleData, TupleDataReader, TupleDataWriter, TupleDesc},
+    Oid,
+};
+
+use super::{pg_attribute::PgAttribute, pg_type};
+
+/// Fixed oid of pg_index relation.
+pub const RELATION_OID: Oid = 2610;
+
+pub const RELATION_NAME: &str = "pg_index";
+
+/// The catalog pg_index contains part of the information about indexes. The rest is mostly in
+/// pg_class.
+#[derive(Debug, Clone)]
+pub struct PgIndex {
+    /// The OID of the pg_class entry for this index.
+    pub indexrelid: Oid,
+
+    /// The OID of the pg_class entry for the table this index is for.
+    pub indrelid: Oid,
+
+    /// The table column number (start at 1) that this index indexes.
+    pub indkey: usize,
+
+    /// If true, this is a unique index, where no two live tuples of the table have the same
+    /// non NULL value of the indexed column.
+    pub indisunique: bool,
+}
+
+impl HeapTupleData for PgIndex {
+    fn encode(&self, writer: &mut TupleDataWriter) {
+        writer.put_u64(self.indexrelid);
+        writer.put_u64(self.indrelid);
+        writer.put_u64(self.indkey as u64);
+        writer.put_bool(self.indisunique);
+    }
+
+    fn decode(reader: &mut TupleDataReader<'_>) -> Result<Self> {
+        Ok(Self {
+            indexrelid: reader.get_u64()?,
+            indrelid: reader.get_u64()?,
+            indkey: reader.get_u64()? as usize,
+            // Indexes created before unique indexes existed are never unique.
+            indisunique: !reader.is_empty() && reader.get_bool()?,
+        })
+    }
+}
+
+impl PgIndex {
+    /// Return the tuple description from pg_index system relation.
+    pub fn tuple_desc() -> TupleDesc {
+        TupleDesc {
+            attrs: vec![
+                PgAttribute {
+                    attrelid: RELATION_OID,
+                    attname: String::from("indexrelid"),
+                    attnum: 1,
+                    attlen: 8,
+                    atttypid: pg_type::INT_OID,
+                    attnotnull: false,
+                    attdefault: String::new(),
+                },
+                PgAttribute {
+                    attrelid: RELATION_OID,
+                    attname: String::from("indrelid"),
+                    attnum: 2,
+                    attlen: 8,
+                    atttypid: pg_type::INT_OID,
+                    attnotnull: false,
+                    attdefault: String::new(),
+                },
+                PgAttribute {
+                    attrelid: RELATION_OID,
+                    attname: String::from("indkey"),
+                    attnum: 3,
+                    attlen: 8,
+                    atttypid: pg_type::INT_OID,
+                    attnotnull: false,
+                    attdefault: String::new(),
+                },
+                PgAttribute {
+                    attrelid: RELATION_OID,
+                    attname: String::from("indisunique"),
+                    attnum: 4,
+                    attlen: 1,
+                    atttypid: pg_type::BOOL_OID,
+                    attnotnull: false,
+                    attdefault: String::new(),
+                },
+            ],
+        }
+    }
+}
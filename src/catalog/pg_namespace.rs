@@ -0,0 +1,78 @@
+use anyhow::Result;
+
+use crate::{
+    access::heaptuple::{HeapTupleData, TupleDataReader, TupleDataWriter, TupleDesc},
+    Oid,
+};
+
+use super::{pg_attribute::PgAttribute, pg_type};
+
+/// Fixed oid of pg_namespace relation.
+pub const RELATION_OID: Oid = 2615;
+
+pub const RELATION_NAME: &str = "pg_namespace";
+
+/// Oid of the pg_catalog schema, where the system catalogs are stored.
+pub const PG_CATALOG_NAMESPACE: Oid = 11;
+
+/// Oid of the public schema, where relations are created by default.
+pub const PG_PUBLIC_NAMESPACE: Oid = 2200;
+
+/// Schemas that exist on every database, by oid and name.
+pub const BUILTIN_NAMESPACES: &[(Oid, &str)] = &[
+    (PG_CATALOG_NAMESPACE, "pg_catalog"),
+    (PG_PUBLIC_NAMESPACE, "public"),
+];
+
+/// The catalog pg_namespace stores the schemas of a database, which group relations so that
+/// relations of different schemas can have the same name.
+#[derive(Debug, Clone)]
+pub struct PgNamespace {
+    /// Oid of schema.
+    pub oid: Oid,
+
+    /// Schema name.
+    pub nspname: String,
+}
+
+impl HeapTupleData for PgNamespace {
+    fn encode(&self, writer: &mut TupleDataWriter) {
+        writer.put_u64(self.oid);
+        writer.put_str(&self.nspname);
+    }
+
+    fn decode(reader: &mut TupleDataReader<'_>) -> Result<Self> {
+        Ok(Self {
+            oid: reader.get_u64()?,
+            nspname: reader.get_str()?,
+        })
+    }
+}
+
+impl PgNamespace {
+    /// Return the tuple description from pg_namespace system relation.
+    pub fn tuple_desc() -> TupleDesc {
+        TupleDesc {
+            attrs: vec![
+                PgAttribute {
+                    attrelid: RELATION_OID,
+                    attname: String::from("oid"),
+                    attnum: 1,
+                    attlen: 8,
+                    atttypid: pg_type::INT_OID,
+                    attnotnull: false,
+                    attdefault: String::new(),
+                },
+                PgAttribute {
+                    attrelid: RELATION_OID,
+                    attname: String::from("nspname"),
+                    attnum: 2,
+                    attlen: -1,
+                    atttypid: pg_type::VARCHAR_OID,
+                    attnotnull: false,
+                    attdefault: String::new(),
+                },
+            ],
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use anyhow::Result;
+
+use crate::{
+    access::heaptuple::{HeapTupleData, TupleDataReader, TupleDataWriter, TupleDesc},
+    Oid,
+};
+
+use super::{pg_attribute::PgAttribute, pg_type};
+
+/// Fixed oid of pg_statistic relation.
+pub const RELATION_OID: Oid = 2619;
+
+pub const RELATION_NAME: &str = "pg_statistic";
+
+/// The catalog pg_statistic stores statistics about the contents of table columns, computed by
+/// ANALYZE and used by the planner to estimate the cost of plans. There is one entry for each
+/// column of an analyzed table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PgStatistic {
+    /// The OID of the pg_class entry for the table that the column belongs to.
+    pub starelid: Oid,
+
+    /// The table column number (start at 1) that the statistics are about.
+    pub staattnum: usize,
+
+    /// Number of rows of the table when the statistics were computed.
+    pub stareltuples: u64,
+
+    /// Estimated number of distinct non-null values of the column.
+    pub stadistinct: u64,
+}
+
+impl HeapTupleData for PgStatistic {
+    fn encode(&self, writer: &mut TupleDataWriter) {
+        writer.put_u64(self.starelid);
+        writer.put_u64(self.staattnum as u64);
+        writer.put_u64(self.stareltuples);
+        writer.put_u64(self.stadistinct);
+    }
+
+    fn decode(reader: &mut TupleDataReader<'_>) -> Result<Self> {
+        Ok(Self {
+            starelid: reader.get_u64()?,
+            staattnum: reader.get_u64()? as usize,
+            stareltuples: reader.get_u64()?,
+            stadistinct: reader.get_u64()?,
+        })
+    }
+}
+
+impl PgStatistic {
+    /// Return the tuple description from pg_statistic system relation.
+    pub fn tuple_desc() -> TupleDesc {
+        let attr = |attname: &str, attnum: usize| PgAttribute {
+            attrelid: RELATION_OID,
+            attname: String::from(attname),
+            attnum,
+            attlen: 8,
+            atttypid: pg_type::INT_OID,
+            attnotnull: false,
+            attdefault: String::new(),
+        };
+        TupleDesc {
+            attrs: vec![
+                attr("starelid", 1),
+                attr("staattnum", 2),
+                attr("stareltuples", 3),
+                attr("stadistinct", 4),
+            ],
+        }
+    }
+
+    /// Return the estimated fraction of the rows of the table where the column is equal to a
+    /// given non-null value.
+    pub fn eq_selectivity(&self) -> f64 {
+        if self.stadistinct == 0 {
+            0.0
+        } else {
+            1.0 / self.stadistinct as f64
+        }
+    }
+}
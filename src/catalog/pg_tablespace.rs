@@ -1,11 +1,10 @@
+use anyhow::Result;
+
 use crate::{
-    access::heaptuple::TupleDesc,
-    sql::encode::{varlena_deserializer, varlena_serializer},
+    access::heaptuple::{HeapTupleData, TupleDataReader, TupleDataWriter, TupleDesc},
     Oid,
 };
 
-use serde::{Deserialize, Serialize};
-
 use super::{pg_attribute::PgAttribute, pg_type};
 
 /// Fixed oid of pg_attribute relation.
@@ -20,17 +19,29 @@ pub const DEFAULTTABLESPACE_OID: Oid = 1663;
 /// pg_tablespace.
 pub const GLOBALTABLESPACE_OID: Oid = 1664;
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Debug)]
 pub struct PgTablespace {
     /// OID of tablespace.
     pub oid: Oid,
 
     /// Tablespace name.
-    #[serde(deserialize_with = "varlena_deserializer")]
-    #[serde(serialize_with = "varlena_serializer")]
     pub spcname: String,
 }
 
+impl HeapTupleData for PgTablespace {
+    fn encode(&self, writer: &mut TupleDataWriter) {
+        writer.put_u64(self.oid);
+        writer.put_str(&self.spcname);
+    }
+
+    fn decode(reader: &mut TupleDataReader<'_>) -> Result<Self> {
+        Ok(Self {
+            oid: reader.get_u64()?,
+            spcname: reader.get_str()?,
+        })
+    }
+}
+
 impl PgTablespace {
     /// Return the tuple description from pg_class system relation.
     pub fn tuple_desc() -> TupleDesc {
@@ -42,6 +53,8 @@ impl PgTablespace {
                     attnum: 1,
                     attlen: 8,
                     atttypid: pg_type::INT_OID,
+                    attnotnull: false,
+                    attdefault: String::new(),
                 },
                 PgAttribute {
                     attrelid: RELATION_OID,
@@ -49,6 +62,8 @@ impl PgTablespace {
                     attnum: 2,
                     attlen: -1,
                     atttypid: pg_type::VARCHAR_OID,
+                    attnotnull: false,
+                    attdefault: String::new(),
                 },
             ],
         }
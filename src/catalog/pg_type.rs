@@ -1,8 +1,191 @@
-use crate::Oid;
+use std::mem::size_of;
+
+use anyhow::{bail, Result};
+use sqlparser::ast;
+
+use crate::{
+    sql::error::{PgError, SqlState},
+    Oid,
+};
 
 // List of Oids for each type that tinydb supports
 // Copy and pasted from src/backend/catalog/pg_type_d.h
 
+pub const INT2_OID: Oid = 21;
 pub const INT_OID: Oid = 23;
+pub const INT8_OID: Oid = 20;
+pub const FLOAT4_OID: Oid = 700;
+pub const FLOAT8_OID: Oid = 701;
+pub const TEXT_OID: Oid = 25;
 pub const VARCHAR_OID: Oid = 1043;
 pub const BOOL_OID: Oid = 16;
+pub const DATE_OID: Oid = 1082;
+pub const TIMESTAMP_OID: Oid = 1114;
+
+/// Return the fixed length in bytes of values of the given type, -1 for variable length types.
+pub fn type_len(typ: Oid) -> i64 {
+    match typ {
+        INT2_OID => size_of::<i16>() as i64,
+        INT_OID => size_of::<i32>() as i64,
+        INT8_OID => size_of::<i64>() as i64,
+        FLOAT4_OID => size_of::<f32>() as i64,
+        FLOAT8_OID => size_of::<f64>() as i64,
+        BOOL_OID => size_of::<bool>() as i64,
+        DATE_OID => size_of::<i32>() as i64,
+        TIMESTAMP_OID => size_of::<i64>() as i64,
+        _ => -1,
+    }
+}
+
+/// Return the SQL name of the given type.
+pub fn type_name(typ: Oid) -> &'static str {
+    match typ {
+        INT2_OID => "smallint",
+        INT_OID => "integer",
+        INT8_OID => "bigint",
+        FLOAT4_OID => "real",
+        FLOAT8_OID => "double precision",
+        TEXT_OID => "text",
+        VARCHAR_OID => "character varying",
+        BOOL_OID => "boolean",
+        DATE_OID => "date",
+        TIMESTAMP_OID => "timestamp without time zone",
+        _ => "unknown",
+    }
+}
+
+/// Return the oid and the lenght of the given data type.
+pub fn oid_type_and_size(typ: &ast::DataType) -> Result<(Oid, i64)> {
+    match typ {
+        ast::DataType::SmallInt(_) => Ok((INT2_OID, size_of::<i16>() as i64)),
+        ast::DataType::Int(len) => Ok((INT_OID, (len.unwrap_or(size_of::<i32>() as u64)) as i64)),
+        ast::DataType::BigInt(_) => Ok((INT8_OID, size_of::<i64>() as i64)),
+        ast::DataType::Real => Ok((FLOAT4_OID, size_of::<f32>() as i64)),
+        // FLOAT(p) with p up to 24 is single precision, as described on Postgres docs.
+        ast::DataType::Float(Some(precision)) if *precision <= 24 => {
+            Ok((FLOAT4_OID, size_of::<f32>() as i64))
+        }
+        ast::DataType::Float(_) | ast::DataType::Double => {
+            Ok((FLOAT8_OID, size_of::<f64>() as i64))
+        }
+        ast::DataType::Custom(name) => match name.to_string().to_lowercase().as_str() {
+            "int2" => Ok((INT2_OID, size_of::<i16>() as i64)),
+            "int4" => Ok((INT_OID, size_of::<i32>() as i64)),
+            "int8" => Ok((INT8_OID, size_of::<i64>() as i64)),
+            "float4" => Ok((FLOAT4_OID, size_of::<f32>() as i64)),
+            "float8" => Ok((FLOAT8_OID, size_of::<f64>() as i64)),
+            _ => bail!(PgError::new(
+                SqlState::UndefinedObject,
+                format!("Not supported data type: {}", typ)
+            )),
+        },
+        ast::DataType::Varchar(len) => match len {
+            Some(len) => Ok((VARCHAR_OID, *len as i64)),
+            None => Ok((VARCHAR_OID, -1)),
+        },
+        // Text values are always stored as varlena, without a maximum length.
+        ast::DataType::Text => Ok((TEXT_OID, -1)),
+        ast::DataType::Boolean => Ok((BOOL_OID, size_of::<bool>() as i64)),
+        ast::DataType::Date => Ok((DATE_OID, size_of::<i32>() as i64)),
+        ast::DataType::Timestamp => Ok((TIMESTAMP_OID, size_of::<i64>() as i64)),
+        _ => bail!(PgError::new(
+            SqlState::UndefinedObject,
+            format!("Not supported data type: {}", typ)
+        )),
+    }
+}
+
+/// Contexts where a cast between two types can be applied. Each context also allows the casts
+/// of the previous contexts, like the castcontext column of pg_cast.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CastContext {
+    /// Cast applied on any expression, e.g. when comparing values of different types.
+    Implicit,
+
+    /// Cast applied when a value is stored on a column, e.g. by INSERT.
+    Assignment,
+
+    /// Cast only applied by CAST(x AS type) or x::type.
+    Explicit,
+}
+
+/// Casts between the supported types, with the context where each cast can be applied.
+/// Copied from src/include/catalog/pg_cast.dat.
+static CASTS: &[(Oid, Oid, CastContext)] = &[
+    (INT2_OID, INT_OID, CastContext::Implicit),
+    (INT2_OID, INT8_OID, CastContext::Implicit),
+    (INT2_OID, FLOAT4_OID, CastContext::Implicit),
+    (INT2_OID, FLOAT8_OID, CastContext::Implicit),
+    (INT_OID, INT2_OID, CastContext::Assignment),
+    (INT_OID, INT8_OID, CastContext::Implicit),
+    (INT_OID, FLOAT4_OID, CastContext::Implicit),
+    (INT_OID, FLOAT8_OID, CastContext::Implicit),
+    (INT_OID, BOOL_OID, CastContext::Explicit),
+    (INT8_OID, INT2_OID, CastContext::Assignment),
+    (INT8_OID, INT_OID, CastContext::Assignment),
+    (INT8_OID, FLOAT4_OID, CastContext::Implicit),
+    (INT8_OID, FLOAT8_OID, CastContext::Implicit),
+    (FLOAT4_OID, INT2_OID, CastContext::Assignment),
+    (FLOAT4_OID, INT_OID, CastContext::Assignment),
+    (FLOAT4_OID, INT8_OID, CastContext::Assignment),
+    (FLOAT4_OID, FLOAT8_OID, CastContext::Implicit),
+    (FLOAT8_OID, INT2_OID, CastContext::Assignment),
+    (FLOAT8_OID, INT_OID, CastContext::Assignment),
+    (FLOAT8_OID, INT8_OID, CastContext::Assignment),
+    (FLOAT8_OID, FLOAT4_OID, CastContext::Assignment),
+    (BOOL_OID, INT_OID, CastContext::Explicit),
+    (TEXT_OID, VARCHAR_OID, CastContext::Implicit),
+    (VARCHAR_OID, TEXT_OID, CastContext::Implicit),
+    (DATE_OID, TIMESTAMP_OID, CastContext::Implicit),
+    (TIMESTAMP_OID, DATE_OID, CastContext::Assignment),
+];
+
+/// Return the context where values of the source type can be cast to the target type, None if
+/// the types can not be cast.
+///
+/// Besides the casts of the cast table, any type can be cast to a string type using its text
+/// representation, and string values can be explicitly cast to any type by parsing them.
+pub fn cast_context(source: Oid, target: Oid) -> Option<CastContext> {
+    if source == target {
+        return Some(CastContext::Implicit);
+    }
+    let is_string = |typ| typ == TEXT_OID || typ == VARCHAR_OID;
+    CASTS
+        .iter()
+        .find(|(s, t, _)| *s == source && *t == target)
+        .map(|(_, _, context)| *context)
+        .or_else(|| {
+            if is_string(target) {
+                Some(CastContext::Assignment)
+            } else if is_string(source) {
+                Some(CastContext::Explicit)
+            } else {
+                None
+            }
+        })
+}
+
+/// Return true if values of the source type can be cast to the target type on the given
+/// context.
+pub fn can_cast(source: Oid, target: Oid, context: CastContext) -> bool {
+    matches!(cast_context(source, target), Some(cast) if cast <= context)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cast_context() {
+        assert!(can_cast(INT_OID, INT8_OID, CastContext::Implicit));
+        assert!(!can_cast(INT8_OID, INT_OID, CastContext::Implicit));
+        assert!(can_cast(INT8_OID, INT_OID, CastContext::Assignment));
+        assert!(can_cast(FLOAT8_OID, TEXT_OID, CastContext::Assignment));
+        assert!(!can_cast(TEXT_OID, INT_OID, CastContext::Assignment));
+        assert!(can_cast(TEXT_OID, INT_OID, CastContext::Explicit));
+        assert!(can_cast(BOOL_OID, INT_OID, CastContext::Explicit));
+        assert!(!can_cast(BOOL_OID, INT_OID, CastContext::Assignment));
+        assert!(can_cast(DATE_OID, DATE_OID, CastContext::Implicit));
+        assert_eq!(cast_context(DATE_OID, BOOL_OID), None);
+    }
+}
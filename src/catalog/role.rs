@@ -0,0 +1,209 @@
+//! Roles and memberships between roles, stored on pg_authid and pg_auth_members.
+//!
+//! A member of a role has all privileges of the role and of the roles that the role is a member
+//! of, recursively. Memberships can not be circular, so a role is never a member of itself.
+
+use anyhow::{bail, Result};
+
+use crate::{
+    access::{
+        self,
+        heap::{heap_insert, HeapScanner},
+        heaptuple::HeapTuple,
+    },
+    backend::auth::encrypt_password,
+    new_object_id,
+    sql::error::{PgError, SqlState},
+    storage::BufferPool,
+    Oid,
+};
+
+use super::{
+    heap::{delete_catalog_tuples, initialize_default_page_header},
+    pg_auth_members::PgAuthMembers,
+    pg_authid::PgAuthId,
+    Error,
+};
+
+/// Attributes of a new role.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RoleOptions {
+    /// The role bypass all permission checks.
+    pub superuser: bool,
+
+    /// The role can be used as the user name of a connection.
+    pub login: bool,
+
+    /// Cleartext password of the role, stored encrypted. None if the role has no password.
+    pub password: Option<String>,
+}
+
+/// Create a new role with the given name and attributes and return its oid. Return an error if
+/// a role with the same name already exists.
+pub fn role_create(buffer_pool: &BufferPool, rolname: &str, options: &RoleOptions) -> Result<Oid> {
+    let roles = get_pg_authids(buffer_pool)?;
+    if roles.iter().any(|role| role.rolname == rolname) {
+        bail!(Error::RoleAlreadyExists(rolname.to_string()));
+    }
+
+    let oid = loop {
+        let oid = new_object_id();
+        if !roles.iter().any(|role| role.oid == oid) {
+            break oid;
+        }
+    };
+    heap_insert(
+        buffer_pool,
+        &access::open_pg_authid_relation(),
+        &HeapTuple::with_default_header(&PgAuthId {
+            oid,
+            rolname: rolname.to_string(),
+            rolpassword: options
+                .password
+                .as_ref()
+                .map(|password| encrypt_password(rolname, password))
+                .unwrap_or_default(),
+            rolsuper: options.superuser,
+            rolcanlogin: options.login,
+        })?,
+    )?;
+
+    Ok(oid)
+}
+
+/// Return the given role followed by all roles that it is a member of, directly or indirectly.
+pub fn roles_of(buffer_pool: &BufferPool, role: &Oid) -> Result<Vec<Oid>> {
+    let memberships = get_pg_auth_members(buffer_pool)?;
+
+    let mut roles = vec![*role];
+    let mut i = 0;
+    while i < roles.len() {
+        for membership in &memberships {
+            if membership.member == roles[i] && !roles.contains(&membership.roleid) {
+                roles.push(membership.roleid);
+            }
+        }
+        i += 1;
+    }
+    Ok(roles)
+}
+
+/// Make the member role a member of the given role. Nothing is done if the member is already a
+/// member of the role, and an error is returned if the membership would be circular.
+pub fn add_role_member(buffer_pool: &BufferPool, role: &PgAuthId, member: &PgAuthId) -> Result<()> {
+    if roles_of(buffer_pool, &role.oid)?.contains(&member.oid) {
+        bail!(PgError::new(
+            SqlState::InvalidGrantOperation,
+            format!(
+                "role \"{}\" is a member of role \"{}\"",
+                role.rolname, member.rolname
+            )
+        ));
+    }
+    let exists = get_pg_auth_members(buffer_pool)?
+        .iter()
+        .any(|membership| membership.roleid == role.oid && membership.member == member.oid);
+    if exists {
+        return Ok(());
+    }
+
+    let pg_auth_members_rel = access::open_pg_auth_members_relation();
+    initialize_default_page_header(buffer_pool, &pg_auth_members_rel)?;
+    heap_insert(
+        buffer_pool,
+        &pg_auth_members_rel,
+        &HeapTuple::with_default_header(&PgAuthMembers {
+            roleid: role.oid,
+            member: member.oid,
+        })?,
+    )?;
+    Ok(())
+}
+
+/// Remove the membership of the member role on the given role, if it exists.
+pub fn remove_role_member(buffer_pool: &BufferPool, role: &Oid, member: &Oid) -> Result<()> {
+    delete_catalog_tuples(
+        buffer_pool,
+        &access::open_pg_auth_members_relation(),
+        |tuple| {
+            let membership = tuple.decode_data::<PgAuthMembers>()?;
+            Ok(membership.roleid == *role && membership.member == *member)
+        },
+    )
+}
+
+/// Return all pg_authid tuples.
+fn get_pg_authids(buffer_pool: &BufferPool) -> Result<Vec<PgAuthId>> {
+    let mut roles = Vec::new();
+    let mut heap = HeapScanner::new(buffer_pool, &access::open_pg_authid_relation())?;
+    while let Some(tuple) = heap.next_tuple()? {
+        roles.push(tuple.decode_data::<PgAuthId>()?);
+    }
+    Ok(roles)
+}
+
+/// Return all pg_auth_members tuples. Data directories initialized before pg_auth_members
+/// existed have no memberships.
+fn get_pg_auth_members(buffer_pool: &BufferPool) -> Result<Vec<PgAuthMembers>> {
+    let pg_auth_members_rel = access::open_pg_auth_members_relation();
+    if buffer_pool.size_of_relation(&pg_auth_members_rel)? == 0 {
+        return Ok(Vec::new());
+    }
+
+    let mut memberships = Vec::new();
+    let mut heap = HeapScanner::new(buffer_pool, &pg_auth_members_rel)?;
+    while let Some(tuple) = heap.next_tuple()? {
+        memberships.push(tuple.decode_data::<PgAuthMembers>()?);
+    }
+    Ok(memberships)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        catalog::{self, pg_authid::BOOTSTRAP_SUPERUSER_ID},
+        initdb::init_catalogs,
+        storage::smgr::StorageManager,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_role_memberships() -> Result<()> {
+        let buffer_pool = BufferPool::new(50, StorageManager::in_memory());
+        init_catalogs(&buffer_pool, "tinydb", None)?;
+
+        let options = RoleOptions {
+            login: true,
+            password: Some(String::from("secret")),
+            ..Default::default()
+        };
+        let alice = role_create(&buffer_pool, "alice", &options)?;
+        let readers = role_create(&buffer_pool, "readers", &RoleOptions::default())?;
+        let staff = role_create(&buffer_pool, "staff", &RoleOptions::default())?;
+        assert!(role_create(&buffer_pool, "alice", &options).is_err());
+        assert_ne!(alice, BOOTSTRAP_SUPERUSER_ID);
+
+        let role = catalog::get_pg_authid(&buffer_pool, "alice")?;
+        assert!(role.rolcanlogin && !role.rolsuper);
+        assert_eq!(role.rolpassword, encrypt_password("alice", "secret"));
+
+        let get = |rolname| catalog::get_pg_authid(&buffer_pool, rolname);
+        add_role_member(&buffer_pool, &get("readers")?, &get("alice")?)?;
+        add_role_member(&buffer_pool, &get("readers")?, &get("alice")?)?;
+        add_role_member(&buffer_pool, &get("staff")?, &get("readers")?)?;
+        assert_eq!(roles_of(&buffer_pool, &alice)?, vec![alice, readers, staff]);
+        assert_eq!(roles_of(&buffer_pool, &staff)?, vec![staff]);
+
+        // Circular memberships are not allowed.
+        let err = add_role_member(&buffer_pool, &get("alice")?, &get("staff")?).unwrap_err();
+        assert_eq!(
+            PgError::from_error(&err).code,
+            SqlState::InvalidGrantOperation
+        );
+
+        remove_role_member(&buffer_pool, &readers, &alice)?;
+        assert_eq!(roles_of(&buffer_pool, &alice)?, vec![alice]);
+        Ok(())
+    }
+}
@@ -0,0 +1,209 @@
+use std::{collections::HashMap, sync::Arc};
+
+use anyhow::Result;
+
+use crate::{
+    access::heaptuple::TupleDesc, sql::guc::SessionVariables, storage::BufferPool, Datum,
+    NullableDatum, Oid,
+};
+
+use super::{pg_attribute::PgAttribute, pg_type};
+
+/// Fixed oid of pg_settings virtual relation.
+pub const PG_SETTINGS_OID: Oid = 9000;
+
+/// Fixed oid of pg_stat_activity virtual relation.
+pub const PG_STAT_ACTIVITY_OID: Oid = 9001;
+
+/// Fixed oid of pg_stat_bufferpool virtual relation.
+pub const PG_STAT_BUFFERPOOL_OID: Oid = 9002;
+
+/// State available to virtual relations to generate their tuples.
+pub struct ScanContext<'a> {
+    /// Buffer pool used by the session running the scan.
+    pub buffer_pool: &'a BufferPool,
+
+    /// Configuration variables of the session running the scan.
+    pub variables: &'a SessionVariables,
+}
+
+/// A relation that is not stored on disk, its tuples are generated each time that the relation
+/// is scanned. Virtual relations can be queried like any other relation but can not be changed.
+pub trait VirtualTable: Send + Sync {
+    /// Oid of the relation.
+    fn oid(&self) -> Oid;
+
+    /// Name of the relation.
+    fn name(&self) -> &'static str;
+
+    /// Return the description of the relation tuples.
+    fn tuple_desc(&self) -> TupleDesc;
+
+    /// Return the values of all tuples of the relation, on the same order of the tuple
+    /// description attributes.
+    fn scan(&self, ctx: &ScanContext) -> Result<Vec<Vec<NullableDatum>>>;
+}
+
+/// Registry of virtual relations, by name.
+#[derive(Clone, Default)]
+pub struct VirtualTables {
+    tables: HashMap<&'static str, Arc<dyn VirtualTable>>,
+}
+
+impl VirtualTables {
+    /// Create a new registry with the builtin virtual relations that only depend on the scan
+    /// context.
+    pub fn builtin() -> Self {
+        let mut tables = Self::default();
+        tables.register(Arc::new(PgSettings));
+        tables.register(Arc::new(PgStatBufferPool));
+        tables
+    }
+
+    /// Register the given virtual relation, replacing any relation with the same name.
+    pub fn register(&mut self, table: Arc<dyn VirtualTable>) {
+        self.tables.insert(table.name(), table);
+    }
+
+    /// Return the virtual relation with the given name, if any.
+    pub fn get(&self, name: &str) -> Option<Arc<dyn VirtualTable>> {
+        self.tables.get(name).cloned()
+    }
+}
+
+/// Return the tuple description of a virtual relation with the given column names and types.
+pub fn virtual_tuple_desc(rel_oid: Oid, columns: &[(&str, Oid)]) -> TupleDesc {
+    TupleDesc {
+        attrs: columns
+            .iter()
+            .enumerate()
+            .map(|(i, (name, typ))| PgAttribute {
+                attrelid: rel_oid,
+                attname: name.to_string(),
+                attnum: i + 1,
+                attlen: pg_type::type_len(*typ),
+                atttypid: *typ,
+                attnotnull: false,
+                attdefault: String::new(),
+            })
+            .collect(),
+    }
+}
+
+/// The view pg_settings provides the current values of the session configuration variables.
+struct PgSettings;
+
+impl VirtualTable for PgSettings {
+    fn oid(&self) -> Oid {
+        PG_SETTINGS_OID
+    }
+
+    fn name(&self) -> &'static str {
+        "pg_settings"
+    }
+
+    fn tuple_desc(&self) -> TupleDesc {
+        virtual_tuple_desc(
+            self.oid(),
+            &[("name", pg_type::TEXT_OID), ("setting", pg_type::TEXT_OID)],
+        )
+    }
+
+    fn scan(&self, ctx: &ScanContext) -> Result<Vec<Vec<NullableDatum>>> {
+        ctx.variables
+            .all()
+            .into_iter()
+            .map(|(name, setting)| {
+                Ok(vec![
+                    Some(Datum::from(name.as_bytes().to_vec())),
+                    Some(Datum::from(setting.as_bytes().to_vec())),
+                ])
+            })
+            .collect()
+    }
+}
+
+/// The view pg_stat_bufferpool has a single row with the usage and activity statistics of the
+/// buffer pool.
+struct PgStatBufferPool;
+
+impl VirtualTable for PgStatBufferPool {
+    fn oid(&self) -> Oid {
+        PG_STAT_BUFFERPOOL_OID
+    }
+
+    fn name(&self) -> &'static str {
+        "pg_stat_bufferpool"
+    }
+
+    fn tuple_desc(&self) -> TupleDesc {
+        virtual_tuple_desc(
+            self.oid(),
+            &[
+                ("buffers", pg_type::INT8_OID),
+                ("used", pg_type::INT8_OID),
+                ("dirty", pg_type::INT8_OID),
+                ("pinned", pg_type::INT8_OID),
+                ("hits", pg_type::INT8_OID),
+                ("reads", pg_type::INT8_OID),
+                ("writes", pg_type::INT8_OID),
+            ],
+        )
+    }
+
+    fn scan(&self, ctx: &ScanContext) -> Result<Vec<Vec<NullableDatum>>> {
+        let stats = ctx.buffer_pool.stats();
+        let row = [
+            stats.buffers,
+            stats.used,
+            stats.dirty,
+            stats.pinned,
+            stats.hits,
+            stats.reads,
+            stats.writes,
+        ]
+        .iter()
+        .map(|value| Ok(Some(Datum::from(bincode::serialize(&(*value as i64))?))))
+        .collect::<Result<Vec<_>>>()?;
+        Ok(vec![row])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{sql::encode::decode, storage::smgr::StorageManager};
+
+    #[test]
+    fn test_builtin_virtual_tables() -> Result<()> {
+        let data_dir = tempfile::tempdir()?;
+        let buffer_pool = BufferPool::new(4, StorageManager::new(data_dir.path()));
+        let mut variables = SessionVariables::default();
+        variables.set("DateStyle", "German")?;
+        let ctx = ScanContext {
+            buffer_pool: &buffer_pool,
+            variables: &variables,
+        };
+
+        let tables = VirtualTables::builtin();
+        assert!(tables.get("pg_unknown").is_none());
+
+        let pg_settings = tables.get("pg_settings").unwrap();
+        let rows = pg_settings.scan(&ctx)?;
+        assert!(rows.iter().any(|row| {
+            decode(row[0].as_ref().unwrap(), pg_type::TEXT_OID).unwrap() == "DateStyle"
+                && decode(row[1].as_ref().unwrap(), pg_type::TEXT_OID).unwrap() == "German"
+        }));
+
+        let pg_stat_bufferpool = tables.get("pg_stat_bufferpool").unwrap();
+        let rows = pg_stat_bufferpool.scan(&ctx)?;
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].len(), pg_stat_bufferpool.tuple_desc().attrs.len());
+        assert_eq!(
+            decode(rows[0][0].as_ref().unwrap(), pg_type::INT8_OID)?,
+            "4"
+        );
+
+        Ok(())
+    }
+}
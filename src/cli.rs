@@ -1,5 +1,24 @@
+use std::{
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Result};
 use structopt::StructOpt;
 
+use crate::{
+    backend::{auth::AuthMethod, DEFAULT_AUTHENTICATION_TIMEOUT, DEFAULT_MAX_CONNECTIONS},
+    lru::ReplacementPolicy,
+    sql::guc::SessionVariables,
+    storage::{smgr::DEFAULT_MAX_OPEN_FILES, wal::WalSyncMethod},
+};
+
+/// Name of the settings file searched on data directory if --config-file is not used.
+pub const SETTINGS_FILE_NAME: &str = "tinydb.conf";
+
 /// Command line arguments
 #[derive(StructOpt)]
 #[structopt()]
@@ -12,19 +31,543 @@ pub struct Flags {
     #[structopt(long = "init")]
     pub init: bool,
 
+    /// Remove the existing database files before initializing the database directory.
+    #[structopt(long = "force", requires = "init")]
+    pub force: bool,
+
+    /// Name of the superuser created when initializing the database directory.
+    #[structopt(short = "U", long = "username", default_value = "tinydb")]
+    pub username: String,
+
+    /// Password of the superuser created when initializing the database directory.
+    #[structopt(long = "password")]
+    pub password: Option<String>,
+
+    /// Authentication method used on new connections (trust, password or md5).
+    #[structopt(long = "auth-method", default_value = "md5")]
+    pub auth_method: AuthMethod,
+
+    /// Maximum time between automatic checkpoints, in seconds.
+    #[structopt(long = "checkpoint-timeout", default_value = "300")]
+    pub checkpoint_timeout: u64,
+
+    /// Policy used to replace buffers when the buffer pool is full (lru or clock).
+    #[structopt(long = "replacement-policy", default_value = "lru")]
+    pub replacement_policy: ReplacementPolicy,
+
+    /// Path to a settings file. Default to tinydb.conf on data directory, if exists.
+    #[structopt(long = "config-file")]
+    pub config_file: Option<String>,
+
+    /// Path to store database files [default: data]
+    #[structopt(long = "data-dir")]
+    pub data_dir: Option<String>,
+
+    /// Log level [default: info]
+    #[structopt(long = "log-level")]
+    pub log_level: Option<log::Level>,
+
+    /// Host name or IP address to listen on [default: 127.0.0.1]
+    #[structopt(long = "hostname")]
+    pub hostname: Option<String>,
+
+    /// Database server port [default: 6379]
+    #[structopt(short = "p", long = "port")]
+    pub port: Option<u32>,
+
+    /// Number of pages that the buffer pool can hold in memory [default: 120]
+    #[structopt(long = "buffer-pool-size")]
+    pub buffer_pool_size: Option<usize>,
+
+    /// Directory of the unix domain socket to accept connections, e.g /tmp. Connections are
+    /// only accepted over TCP if not set.
+    #[structopt(long = "unix-socket-dir")]
+    pub unix_socket_dir: Option<String>,
+
+    /// Address to serve metrics over HTTP in Prometheus text format, e.g 127.0.0.1:9187. Metrics
+    /// are not served if not set.
+    #[structopt(long = "metrics-addr")]
+    pub metrics_addr: Option<String>,
+
+    #[structopt(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Commands executed instead of starting the server.
+#[derive(StructOpt, Debug, PartialEq)]
+pub enum Command {
+    /// Convert the files of a data directory initialized by the previous server version to the
+    /// current format. The server must be shut down cleanly before upgrading.
+    Upgrade,
+
+    /// Write a SQL script that recreates all user tables, with their rows and indexes. The
+    /// server must be shut down before dumping.
+    Dump {
+        /// File to write the script to, the standard output if not set.
+        #[structopt(short = "f", long = "file", parse(from_os_str))]
+        file: Option<PathBuf>,
+    },
+
+    /// Execute a SQL script written by dump. The server must be shut down before restoring.
+    Restore {
+        /// File to read the script from, the standard input if not set.
+        #[structopt(short = "f", long = "file", parse(from_os_str))]
+        file: Option<PathBuf>,
+    },
+
+    /// Execute statements read from the standard input on an embedded database, without
+    /// starting the server. The server must be shut down while the shell is running.
+    Shell {
+        /// Execute the given statements or meta-command and exit.
+        #[structopt(short = "c", long = "command", conflicts_with = "file")]
+        command: Option<String>,
+
+        /// Execute the lines of the given file instead of the standard input and exit.
+        #[structopt(short = "f", long = "file", parse(from_os_str))]
+        file: Option<PathBuf>,
+    },
+}
+
+impl Flags {
+    /// Return the server settings. Values given as command line arguments take precedence over
+    /// the values of the settings file.
+    pub fn settings(&self) -> Result<Settings> {
+        let mut settings = Settings::default();
+
+        let data_dir = self.data_dir.as_deref().unwrap_or(&settings.data_dir);
+        match &self.config_file {
+            Some(config_file) => settings.load(config_file)?,
+            None => {
+                let config_file = Path::new(data_dir).join(SETTINGS_FILE_NAME);
+                if config_file.exists() {
+                    settings.load(config_file)?;
+                }
+            }
+        }
+
+        if let Some(data_dir) = &self.data_dir {
+            settings.data_dir = data_dir.clone();
+        }
+        if let Some(log_level) = self.log_level {
+            settings.log_level = log_level;
+        }
+        if let Some(hostname) = &self.hostname {
+            settings.hostname = hostname.clone();
+        }
+        if let Some(port) = self.port {
+            settings.port = port;
+        }
+        if let Some(buffer_pool_size) = self.buffer_pool_size {
+            settings.buffer_pool_size = buffer_pool_size;
+        }
+        if let Some(unix_socket_dir) = &self.unix_socket_dir {
+            settings.unix_socket_dir = Some(unix_socket_dir.clone());
+        }
+
+        if settings.buffer_pool_size == 0 {
+            bail!("buffer_pool_size must be greater than 0");
+        }
+        if settings.max_open_files == 0 {
+            bail!("max_open_files must be greater than 0");
+        }
+        if settings.max_connections == 0 {
+            bail!("max_connections must be greater than 0");
+        }
+
+        Ok(settings)
+    }
+}
+
+/// Server settings that can be configured using a settings file or command line arguments.
+#[derive(Debug, PartialEq)]
+pub struct Settings {
     /// Path to store database files.
-    #[structopt(long = "data-dir", default_value = "data")]
     pub data_dir: String,
 
-    /// Log level
-    #[structopt(long = "log-level", default_value = "info")]
+    /// Log level.
     pub log_level: log::Level,
 
     /// Host name or IP address to listen on.
-    #[structopt(long = "hostname", default_value = "127.0.0.1")]
     pub hostname: String,
 
     /// Database server port.
-    #[structopt(short = "p", long = "port", default_value = "6379")]
     pub port: u32,
+
+    /// Number of pages that the buffer pool can hold in memory.
+    pub buffer_pool_size: usize,
+
+    /// Directory of the unix domain socket to accept connections. None if connections are only
+    /// accepted over TCP.
+    pub unix_socket_dir: Option<String>,
+
+    /// Verify the checksum of pages read from disk to detect corrupted pages.
+    pub data_checksums: bool,
+
+    /// Force changes to be stored on the physical device on WAL flushes and checkpoints.
+    pub fsync: bool,
+
+    /// Method used to force WAL writes to disk.
+    pub wal_sync_method: WalSyncMethod,
+
+    /// Log each statement that runs at least this duration. None if statements are not logged.
+    pub log_min_duration: Option<Duration>,
+
+    /// Time between background writer rounds.
+    pub bgwriter_delay: Duration,
+
+    /// Maximum number of buffers written by the background writer on each round. Zero disables
+    /// the background writer.
+    pub bgwriter_lru_maxpages: usize,
+
+    /// Number of pages that sequential scans read ahead in background. Zero disables the
+    /// read-ahead.
+    pub prefetch_depth: usize,
+
+    /// Open relation files with direct I/O, bypassing the page cache of the operating system.
+    pub direct_io: bool,
+
+    /// Maximum number of relation files kept open.
+    pub max_open_files: usize,
+
+    /// Maximum number of connections open at the same time.
+    pub max_connections: usize,
+
+    /// Maximum time to complete the startup and authentication of a new connection.
+    pub authentication_timeout: Duration,
+
+    /// Close connections that are idle for more than this duration. None if idle connections
+    /// are never closed.
+    pub idle_session_timeout: Option<Duration>,
+
+    /// Time that a TCP connection is idle before the first keepalive probe is sent. None to use
+    /// the default of the operating system.
+    pub tcp_keepalives_idle: Option<Duration>,
+
+    /// Time between keepalive probes that are not acknowledged. None to use the default of the
+    /// operating system.
+    pub tcp_keepalives_interval: Option<Duration>,
+
+    /// Default values of session variables, reported to clients on connection startup.
+    pub session_variables: SessionVariables,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            data_dir: String::from("data"),
+            log_level: log::Level::Info,
+            hostname: String::from("127.0.0.1"),
+            port: 6379,
+            buffer_pool_size: 120,
+            unix_socket_dir: None,
+            data_checksums: true,
+            fsync: true,
+            wal_sync_method: WalSyncMethod::default(),
+            log_min_duration: None,
+            bgwriter_delay: Duration::from_millis(200),
+            bgwriter_lru_maxpages: 100,
+            prefetch_depth: 8,
+            direct_io: false,
+            max_open_files: DEFAULT_MAX_OPEN_FILES,
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            authentication_timeout: DEFAULT_AUTHENTICATION_TIMEOUT,
+            idle_session_timeout: None,
+            tcp_keepalives_idle: None,
+            tcp_keepalives_interval: None,
+            session_variables: SessionVariables::default(),
+        }
+    }
+}
+
+impl Settings {
+    /// Return the path of the unix domain socket for the configured port, using the same file
+    /// name as Postgres so clients can connect using the socket directory as host name.
+    pub fn unix_socket_path(&self) -> Option<PathBuf> {
+        self.unix_socket_dir
+            .as_ref()
+            .map(|dir| Path::new(dir).join(format!(".s.PGSQL.{}", self.port)))
+    }
+}
+
+impl Settings {
+    /// Load the settings from the given file, overriding the current values.
+    pub fn load<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)
+            .map_err(|err| anyhow!("could not open settings file {:?}: {}", path, err))?;
+        self.parse(&content)
+    }
+
+    /// Parse the settings from the given content, where each line has a `name = value` setting.
+    /// Empty lines and everything after a # are ignored.
+    ///
+    /// Values can optionally be single quoted. Names of session variables (e.g DateStyle) set
+    /// the default value of the variable for all sessions.
+    pub fn parse(&mut self, content: &str) -> Result<()> {
+        for (lineno, line) in content.lines().enumerate() {
+            let line = match line.find('#') {
+                Some(pos) => &line[..pos],
+                None => line,
+            }
+            .trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (name, value) = match line.find('=') {
+                Some(pos) => (line[..pos].trim(), line[pos + 1..].trim()),
+                None => bail!(
+                    "syntax error in settings file line {}: {}",
+                    lineno + 1,
+                    line
+                ),
+            };
+            let value = value
+                .strip_prefix('\'')
+                .and_then(|value| value.strip_suffix('\''))
+                .unwrap_or(value);
+
+            match name {
+                "data_dir" => self.data_dir = value.to_string(),
+                "log_level" => self.log_level = parse_value(name, value)?,
+                "listen_address" => self.hostname = value.to_string(),
+                "port" => self.port = parse_value(name, value)?,
+                "buffer_pool_size" => self.buffer_pool_size = parse_value(name, value)?,
+                "unix_socket_directory" => self.unix_socket_dir = Some(value.to_string()),
+                "data_checksums" => self.data_checksums = parse_bool(name, value)?,
+                "fsync" => self.fsync = parse_bool(name, value)?,
+                "wal_sync_method" => self.wal_sync_method = parse_value(name, value)?,
+                "log_min_duration" => self.log_min_duration = parse_duration(name, value)?,
+                "bgwriter_delay" => match parse_duration(name, value)? {
+                    Some(delay) if delay > Duration::ZERO => self.bgwriter_delay = delay,
+                    _ => bail!(
+                        "invalid value for parameter \"{}\": \"{}\": must be greater than 0",
+                        name,
+                        value
+                    ),
+                },
+                "bgwriter_lru_maxpages" => self.bgwriter_lru_maxpages = parse_value(name, value)?,
+                "prefetch_depth" => self.prefetch_depth = parse_value(name, value)?,
+                "direct_io" => self.direct_io = parse_bool(name, value)?,
+                "max_open_files" => self.max_open_files = parse_value(name, value)?,
+                "max_connections" => self.max_connections = parse_value(name, value)?,
+                "authentication_timeout" => match parse_duration(name, value)? {
+                    Some(timeout) if timeout > Duration::ZERO => {
+                        self.authentication_timeout = timeout
+                    }
+                    _ => bail!(
+                        "invalid value for parameter \"{}\": \"{}\": must be greater than 0",
+                        name,
+                        value
+                    ),
+                },
+                // Like Postgres, zero disables the timeout or uses the default of the operating
+                // system for keepalive settings.
+                "idle_session_timeout" => {
+                    self.idle_session_timeout = parse_nonzero_duration(name, value)?
+                }
+                "tcp_keepalives_idle" => {
+                    self.tcp_keepalives_idle = parse_nonzero_duration(name, value)?
+                }
+                "tcp_keepalives_interval" => {
+                    self.tcp_keepalives_interval = parse_nonzero_duration(name, value)?
+                }
+                _ => self.session_variables.set_default(name, value)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Parse the value of the given setting.
+fn parse_value<T>(name: &str, value: &str) -> Result<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    value.parse().map_err(|err| {
+        anyhow!(
+            "invalid value for parameter \"{}\": \"{}\": {}",
+            name,
+            value,
+            err
+        )
+    })
+}
+
+/// Parse the value of the given duration setting, in milliseconds if no unit is given. Negative
+/// values disable the setting.
+fn parse_duration(name: &str, value: &str) -> Result<Option<Duration>> {
+    let lower = value.to_lowercase();
+    let (number, unit_millis) = if let Some(number) = lower.strip_suffix("ms") {
+        (number, 1)
+    } else if let Some(number) = lower.strip_suffix("min") {
+        (number, 60_000)
+    } else if let Some(number) = lower.strip_suffix('s') {
+        (number, 1000)
+    } else {
+        (lower.as_str(), 1)
+    };
+
+    let number = parse_value::<i64>(name, number.trim())?;
+    if number < 0 {
+        return Ok(None);
+    }
+    Ok(Some(Duration::from_millis(number as u64 * unit_millis)))
+}
+
+/// Parse the value of the given duration setting like parse_duration, where zero also disables
+/// the setting.
+fn parse_nonzero_duration(name: &str, value: &str) -> Result<Option<Duration>> {
+    Ok(parse_duration(name, value)?.filter(|duration| !duration.is_zero()))
+}
+
+/// Parse the value of the given boolean setting, accepting the same values as Postgres.
+fn parse_bool(name: &str, value: &str) -> Result<bool> {
+    match value.to_lowercase().as_str() {
+        "on" | "true" | "yes" | "1" => Ok(true),
+        "off" | "false" | "no" | "0" => Ok(false),
+        _ => bail!(
+            "invalid value for parameter \"{}\": \"{}\": expected a boolean",
+            name,
+            value
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_settings() -> Result<()> {
+        let mut settings = Settings::default();
+        settings.parse(
+            "
+            # Comments and empty lines are ignored.
+
+            buffer_pool_size = 1024
+            port=5432 # Trailing comment
+            listen_address = '0.0.0.0'
+            log_level = debug
+            unix_socket_directory = /tmp
+            TimeZone = 'America/Sao_Paulo'
+            data_checksums = off
+            fsync = off
+            wal_sync_method = open_datasync
+            log_min_duration = 250ms
+            bgwriter_delay = 1s
+            bgwriter_lru_maxpages = 0
+            prefetch_depth = 16
+            direct_io = on
+            max_open_files = 64
+            max_connections = 20
+            authentication_timeout = 10s
+            idle_session_timeout = 10min
+            tcp_keepalives_idle = 30s
+            ",
+        )?;
+
+        let mut session_variables = SessionVariables::default();
+        session_variables.set_default("TimeZone", "America/Sao_Paulo")?;
+        assert_eq!(
+            settings,
+            Settings {
+                data_dir: String::from("data"),
+                log_level: log::Level::Debug,
+                hostname: String::from("0.0.0.0"),
+                port: 5432,
+                buffer_pool_size: 1024,
+                unix_socket_dir: Some(String::from("/tmp")),
+                data_checksums: false,
+                fsync: false,
+                wal_sync_method: WalSyncMethod::OpenDatasync,
+                log_min_duration: Some(Duration::from_millis(250)),
+                bgwriter_delay: Duration::from_secs(1),
+                bgwriter_lru_maxpages: 0,
+                prefetch_depth: 16,
+                direct_io: true,
+                max_open_files: 64,
+                max_connections: 20,
+                authentication_timeout: Duration::from_secs(10),
+                idle_session_timeout: Some(Duration::from_secs(600)),
+                tcp_keepalives_idle: Some(Duration::from_secs(30)),
+                tcp_keepalives_interval: None,
+                session_variables,
+            }
+        );
+
+        assert!(settings.parse("port = abc").is_err());
+        assert!(settings.parse("unknown = 1").is_err());
+        assert!(settings.parse("port").is_err());
+        assert!(settings.parse("data_checksums = maybe").is_err());
+        assert!(settings
+            .parse("wal_sync_method = fsync_writethrough")
+            .is_err());
+
+        settings.parse("log_min_duration = 2s")?;
+        assert_eq!(settings.log_min_duration, Some(Duration::from_secs(2)));
+        settings.parse("log_min_duration = 0")?;
+        assert_eq!(settings.log_min_duration, Some(Duration::ZERO));
+        settings.parse("log_min_duration = -1")?;
+        assert_eq!(settings.log_min_duration, None);
+        assert!(settings.parse("log_min_duration = fast").is_err());
+        assert!(settings.parse("bgwriter_delay = 0").is_err());
+        assert!(settings.parse("authentication_timeout = 0").is_err());
+        settings.parse("idle_session_timeout = 0")?;
+        assert_eq!(settings.idle_session_timeout, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_flags_override_settings_file() -> Result<()> {
+        let data_dir = tempfile::tempdir()?;
+        fs::write(
+            data_dir.path().join(SETTINGS_FILE_NAME),
+            "port = 5432\nbuffer_pool_size = 64\n",
+        )?;
+
+        let flags = Flags::from_iter_safe(&[
+            "tinydb",
+            "--data-dir",
+            data_dir.path().to_str().unwrap(),
+            "--port",
+            "7000",
+        ])?;
+        let settings = flags.settings()?;
+
+        assert_eq!(settings.port, 7000);
+        assert_eq!(settings.buffer_pool_size, 64);
+        assert_eq!(settings.data_dir, data_dir.path().to_str().unwrap());
+        assert_eq!(settings.unix_socket_path(), None);
+        assert_eq!(flags.command, None);
+
+        let flags = Flags::from_iter_safe(&["tinydb", "--data-dir", "data", "upgrade"])?;
+        assert_eq!(flags.command, Some(Command::Upgrade));
+
+        let flags = Flags::from_iter_safe(&["tinydb", "dump", "-f", "dump.sql"])?;
+        assert_eq!(
+            flags.command,
+            Some(Command::Dump {
+                file: Some(PathBuf::from("dump.sql"))
+            })
+        );
+        let flags = Flags::from_iter_safe(&["tinydb", "restore"])?;
+        assert_eq!(flags.command, Some(Command::Restore { file: None }));
+        let flags = Flags::from_iter_safe(&["tinydb", "shell", "-c", "SELECT 1"])?;
+        assert_eq!(
+            flags.command,
+            Some(Command::Shell {
+                command: Some(String::from("SELECT 1")),
+                file: None
+            })
+        );
+        assert!(
+            Flags::from_iter_safe(&["tinydb", "shell", "-c", "SELECT 1", "-f", "a.sql"]).is_err()
+        );
+
+        Ok(())
+    }
 }
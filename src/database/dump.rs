@@ -0,0 +1,260 @@
+use std::io::{Read, Write};
+
+use anyhow::Result;
+
+use crate::{
+    access::tableam::{self, HEAP_TABLE_AM_OID},
+    catalog::{
+        self, namespace, pg_attribute::PgAttribute, pg_namespace::PG_PUBLIC_NAMESPACE, pg_type,
+    },
+    sql::encode,
+    FIRST_NORMAL_OBJECT_ID,
+};
+
+use super::Database;
+
+/// Maximum number of rows of each INSERT statement of a dump.
+const DUMP_INSERT_ROWS: usize = 100;
+
+impl Database {
+    /// Write a SQL script with CREATE SCHEMA, CREATE TABLE, INSERT and CREATE INDEX statements
+    /// that recreate all user schemas and tables of the connected database, with their rows and
+    /// indexes, on the given writer. Tables of schemas other than public are written with
+    /// qualified names.
+    ///
+    /// Unlike base backups, dumps don't depend on the on-disk format, so they can be used to
+    /// move data between server versions.
+    pub fn dump<W: Write>(&mut self, mut out: W) -> Result<()> {
+        let db_oid = self.executor.database();
+        let indexes = catalog::get_pg_indexes(&self.buffer_pool, &db_oid)?;
+        let mut relations = catalog::get_pg_class_relations(&self.buffer_pool, &db_oid)?;
+        relations.sort_by_key(|relation| relation.oid);
+
+        let mut schemas = namespace::get_pg_namespaces(&self.buffer_pool, &db_oid)?;
+        schemas.retain(|schema| schema.oid >= FIRST_NORMAL_OBJECT_ID);
+        schemas.sort_by_key(|schema| schema.oid);
+        for schema in &schemas {
+            writeln!(out, "CREATE SCHEMA {};", schema.nspname)?;
+        }
+        if !schemas.is_empty() {
+            writeln!(out)?;
+        }
+
+        let tables = relations.iter().filter(|relation| {
+            relation.oid >= FIRST_NORMAL_OBJECT_ID
+                && !indexes.iter().any(|index| index.indexrelid == relation.oid)
+        });
+        for table in tables {
+            let table_name = match schemas
+                .iter()
+                .find(|schema| schema.oid == table.relnamespace)
+            {
+                Some(schema) if table.relnamespace != PG_PUBLIC_NAMESPACE => {
+                    format!("{}.{}", schema.nspname, table.relname)
+                }
+                _ => table.relname.clone(),
+            };
+            let tuple_desc =
+                catalog::tuple_desc_from_relation_oid(&self.buffer_pool, &db_oid, &table.oid)?;
+
+            let columns = tuple_desc
+                .attrs
+                .iter()
+                .map(column_definition)
+                .collect::<Vec<_>>();
+            write!(out, "CREATE TABLE {} ({})", table_name, columns.join(", "))?;
+            if table.relam != HEAP_TABLE_AM_OID {
+                let am = tableam::table_am(table.relam)?;
+                write!(out, " WITH (access_method = '{}')", am.name())?;
+            }
+            writeln!(out, ";")?;
+
+            let rows = self.query(&format!("SELECT * FROM {};", table_name))?;
+            for chunk in rows.values().chunks(DUMP_INSERT_ROWS) {
+                let values = chunk
+                    .iter()
+                    .map(|row| {
+                        let values = row
+                            .iter()
+                            .zip(&tuple_desc.attrs)
+                            .map(|(value, attr)| match value {
+                                Some(datum) => {
+                                    Ok(quote_literal(&encode::decode(datum, attr.atttypid)?))
+                                }
+                                None => Ok(String::from("NULL")),
+                            })
+                            .collect::<Result<Vec<_>>>()?;
+                        Ok(format!("({})", values.join(", ")))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                writeln!(
+                    out,
+                    "INSERT INTO {} VALUES {};",
+                    table_name,
+                    values.join(", ")
+                )?;
+            }
+
+            // Indexes are created after the rows are inserted, so they are built at once.
+            for index in indexes.iter().filter(|index| index.indrelid == table.oid) {
+                let name = relations
+                    .iter()
+                    .find(|relation| relation.oid == index.indexrelid)
+                    .map_or_else(|| index.indexrelid.to_string(), |rel| rel.relname.clone());
+                let column = &tuple_desc.attrs[index.indkey - 1].attname;
+                let unique = if index.indisunique { "UNIQUE " } else { "" };
+                writeln!(
+                    out,
+                    "CREATE {}INDEX {} ON {}({});",
+                    unique, name, table_name, column
+                )?;
+            }
+            writeln!(out)?;
+        }
+
+        out.flush()?;
+        Ok(())
+    }
+
+    /// Execute all statements of a SQL script written by dump, recreating the dumped tables.
+    pub fn restore<R: Read>(&mut self, mut input: R) -> Result<()> {
+        let mut script = String::new();
+        input.read_to_string(&mut script)?;
+        if !script.trim().is_empty() {
+            self.execute(&script)?;
+        }
+        Ok(())
+    }
+}
+
+/// Return the column definition of a CREATE TABLE statement for the given attribute.
+fn column_definition(attr: &PgAttribute) -> String {
+    let typ = match attr.atttypid {
+        pg_type::VARCHAR_OID if attr.attlen >= 0 => format!("varchar({})", attr.attlen),
+        pg_type::VARCHAR_OID => String::from("varchar"),
+        pg_type::TIMESTAMP_OID => String::from("timestamp"),
+        typ => String::from(pg_type::type_name(typ)),
+    };
+
+    let mut column = format!("{} {}", attr.attname, typ);
+    if attr.attnotnull {
+        column.push_str(" NOT NULL");
+    }
+    if !attr.attdefault.is_empty() {
+        column.push_str(&format!(" DEFAULT {}", attr.attdefault));
+    }
+    column
+}
+
+/// Return the given value as a quoted string literal, which is cast to the column type when it
+/// is inserted.
+fn quote_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dump_restore() -> Result<()> {
+        let mut db = Database::open_in_memory()?;
+        db.execute(
+            "CREATE TABLE t(a int NOT NULL, b varchar(10) DEFAULT 'none', c double precision, \
+             d boolean, e date, f timestamp, g smallint, h bigint, i real, j text);",
+        )?;
+        db.execute(
+            "INSERT INTO t VALUES (1, 'it''s', 1.5, true, '2024-02-29', '2024-02-29 10:20:30', \
+             '-2', 9000000000, 0.25, 'x'), (2, NULL, NULL, NULL, NULL, NULL, NULL, NULL, NULL, NULL);",
+        )?;
+        db.execute("INSERT INTO t(a) VALUES (3);")?;
+        db.execute("CREATE INDEX t_a ON t(a);")?;
+        db.execute("CREATE UNIQUE INDEX t_h ON t(h);")?;
+        db.execute("CREATE TABLE c(a int) WITH (access_method = 'columnar');")?;
+        db.execute("CREATE TABLE empty(a int);")?;
+        for i in 0..250 {
+            db.execute(&format!("INSERT INTO c VALUES ({});", i))?;
+        }
+
+        let mut script = Vec::new();
+        db.dump(&mut script)?;
+        let script = String::from_utf8(script)?;
+        assert!(script.starts_with(
+            "CREATE TABLE t (a integer NOT NULL, b varchar(10) DEFAULT 'none', \
+             c double precision, d boolean, e date, f timestamp, g smallint, h bigint, i real, \
+             j text);\n"
+        ));
+        assert!(script.contains("CREATE INDEX t_a ON t(a);\n"));
+        assert!(script.contains("CREATE UNIQUE INDEX t_h ON t(h);\n"));
+        assert!(script.contains("CREATE TABLE c (a integer) WITH (access_method = 'columnar');"));
+        assert_eq!(script.matches("INSERT INTO c VALUES").count(), 3);
+
+        let mut restored = Database::open_in_memory()?;
+        restored.restore(script.as_bytes())?;
+
+        let query = "SELECT * FROM t;";
+        assert_eq!(restored.query(query)?.values(), db.query(query)?.values());
+        assert_eq!(
+            restored
+                .query("SELECT b FROM t WHERE a = 3;")?
+                .get(0)
+                .unwrap()
+                .get::<_, String>(0)?,
+            "none"
+        );
+        assert_eq!(restored.execute("SELECT * FROM t WHERE a = 2;")?, 1);
+        assert_eq!(restored.execute("SELECT * FROM c;")?, 250);
+        assert_eq!(restored.execute("SELECT * FROM empty;")?, 0);
+
+        // Dumping the restored database gives the same script.
+        let mut dumped_again = Vec::new();
+        restored.dump(&mut dumped_again)?;
+        assert_eq!(String::from_utf8(dumped_again)?, script);
+
+        Database::open_in_memory()?.restore("".as_bytes())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_dump_schemas() -> Result<()> {
+        let mut db = Database::open_in_memory()?;
+        db.execute("CREATE SCHEMA app;")?;
+        db.execute("CREATE TABLE t(a int); INSERT INTO t VALUES (1);")?;
+        db.execute("CREATE TABLE app.t(a int); INSERT INTO app.t VALUES (2);")?;
+        db.execute("CREATE INDEX t_a ON app.t(a);")?;
+
+        let mut script = Vec::new();
+        db.dump(&mut script)?;
+        let script = String::from_utf8(script)?;
+        assert!(script.starts_with("CREATE SCHEMA app;\n\nCREATE TABLE t (a integer);\n"));
+        assert!(script.contains("CREATE INDEX t_a ON app.t(a);\n"));
+
+        let mut restored = Database::open_in_memory()?;
+        restored.restore(script.as_bytes())?;
+        for query in &["SELECT * FROM t;", "SELECT * FROM app.t;"] {
+            assert_eq!(restored.query(query)?.values(), db.query(query)?.values());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_dump_connected_database() -> Result<()> {
+        let mut db = Database::open_in_memory()?;
+        db.execute("CREATE TABLE t(a int); INSERT INTO t VALUES (1);")?;
+        db.execute("CREATE DATABASE other;")?;
+        db.connect("other")?;
+        db.execute("CREATE TABLE u(b int); INSERT INTO u VALUES (2);")?;
+
+        let mut script = Vec::new();
+        db.dump(&mut script)?;
+        let script = String::from_utf8(script)?;
+        assert!(script.contains("CREATE TABLE u (b integer);\nINSERT INTO u VALUES ('2');\n"));
+        assert!(script.contains("CREATE TABLE t (a integer);\n"));
+
+        db.connect("tinydb")?;
+        let mut script = Vec::new();
+        db.dump(&mut script)?;
+        assert!(!String::from_utf8(script)?.contains("CREATE TABLE u"));
+        Ok(())
+    }
+}
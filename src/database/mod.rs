@@ -0,0 +1,1504 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Result};
+use sqlparser::ast::{ObjectType, Statement};
+
+use crate::{
+    backup,
+    catalog::{pg_authid::BOOTSTRAP_SUPERUSER_ID, virtual_table::VirtualTables},
+    initdb::{init_catalogs, init_database, is_initialized},
+    sql::{
+        self,
+        cursor::{self, CursorStatement},
+        database,
+        guc::{self, SessionVariables},
+        insert,
+        psql::ListRelations,
+        role, ConnectionExecutor, ExecutorConfig, SQLError,
+    },
+    storage::{
+        control::{ClusterState, ControlFile},
+        smgr::StorageManager,
+        wal::{self, Wal},
+        BufferPool,
+    },
+};
+
+mod dump;
+pub mod row;
+
+pub use row::{FromValue, Row, RowIndex, Rows};
+
+/// Number of pages that the buffer pool of an embedded database can hold in memory.
+pub const DEFAULT_BUFFER_POOL_SIZE: usize = 120;
+
+/// Name of the database and superuser of data directories initialized by Database::open.
+const DEFAULT_DATABASE: &str = "tinydb";
+
+/// Errors of executing statements on an embedded database.
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("statement does not return rows")]
+    NoRows,
+
+    #[error("column index {0} is out of range")]
+    ColumnIndexOutOfRange(usize),
+
+    #[error("column \"{0}\" does not exist")]
+    ColumnNotFound(String),
+
+    #[error("column \"{0}\" is null")]
+    UnexpectedNull(String),
+
+    #[error("column \"{column}\" of type {typ} can not be converted to {target}")]
+    InvalidType {
+        column: String,
+        typ: &'static str,
+        target: &'static str,
+    },
+}
+
+/// An embedded database, executing queries on the same process without a server.
+///
+/// The database owns the buffer pool of the data directory, so a data directory must not be used
+/// by a server and an embedded database at the same time. When the database is dropped a
+/// checkpoint is performed, so that all changes are stored on relation files.
+///
+/// Databases opened by open_in_memory have no data directory, all data is kept in memory.
+pub struct Database {
+    /// Data directory of the database. None if the database is in memory.
+    data_dir: Option<PathBuf>,
+    buffer_pool: BufferPool,
+
+    /// Control file of the data directory. None if the database is in memory.
+    control: Option<ControlFile>,
+    executor: ConnectionExecutor,
+}
+
+/// Result of executing a single statement.
+#[derive(Debug)]
+pub enum StatementResult {
+    /// Rows returned by a query.
+    Rows(Rows),
+
+    /// Command tag, e.g INSERT 0 1, and number of rows affected by a statement that don't
+    /// return rows.
+    Command { tag: String, affected: u64 },
+}
+
+impl Database {
+    /// Open the database stored on the given data directory, initializing the data directory if
+    /// it is not initialized yet. Changes that were not stored on relation files when the
+    /// database was last closed are recovered from the write-ahead log.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::open_with_storage_manager(&path, StorageManager::new(path.as_ref()))
+    }
+
+    /// Same as open, but the pages of relations are read and written by the given storage
+    /// manager, which must use the same data directory, e.g. to inject faults on page writes.
+    /// The data directory is initialized without it.
+    pub fn open_with_storage_manager<P: AsRef<Path>>(
+        path: P,
+        smgr: StorageManager,
+    ) -> Result<Self> {
+        let data_dir = path.as_ref().to_path_buf();
+        if !is_initialized(&data_dir)? {
+            let buffer_pool =
+                BufferPool::new(DEFAULT_BUFFER_POOL_SIZE, StorageManager::new(&data_dir))
+                    .with_wal(Wal::open(&data_dir)?);
+            init_database(&buffer_pool, &data_dir, DEFAULT_DATABASE, None)?;
+        }
+
+        let control = ControlFile::open(&data_dir)?;
+        let buffer_pool = BufferPool::new(DEFAULT_BUFFER_POOL_SIZE, smgr)
+            .with_control_file(control.clone())
+            .with_wal(Wal::open(&data_dir)?);
+        wal::recover(&buffer_pool)?;
+        control.set_state(ClusterState::InProduction)?;
+
+        Self::with_buffer_pool(Some(data_dir), buffer_pool, Some(control))
+    }
+
+    /// Open a new empty database that keep all data in memory, without any file. All data is
+    /// lost when the database is dropped, which is useful for tests and ephemeral databases.
+    pub fn open_in_memory() -> Result<Self> {
+        let buffer_pool = BufferPool::new(DEFAULT_BUFFER_POOL_SIZE, StorageManager::in_memory());
+        init_catalogs(&buffer_pool, DEFAULT_DATABASE, None)?;
+        // Shared catalogs are created using a different locator than the one used to read them,
+        // so their pages must be stored before they are read.
+        buffer_pool.checkpoint()?;
+        Self::with_buffer_pool(None, buffer_pool, None)
+    }
+
+    /// Create a database executing statements on the given buffer pool, which should already
+    /// have the system catalogs.
+    fn with_buffer_pool(
+        data_dir: Option<PathBuf>,
+        buffer_pool: BufferPool,
+        control: Option<ControlFile>,
+    ) -> Result<Self> {
+        let config = ExecutorConfig {
+            database: database::connect_database(&buffer_pool, DEFAULT_DATABASE)?,
+            variables: SessionVariables::default(),
+            virtual_tables: VirtualTables::builtin(),
+            session_role: BOOTSTRAP_SUPERUSER_ID,
+        };
+        let executor = ConnectionExecutor::new(config, buffer_pool.clone());
+
+        Ok(Self {
+            data_dir,
+            buffer_pool,
+            control,
+            executor,
+        })
+    }
+
+    /// Connect to another database of the data directory, e.g. created by CREATE DATABASE. The
+    /// session variables of the current database are kept.
+    pub fn connect(&mut self, datname: &str) -> Result<()> {
+        let config = ExecutorConfig {
+            database: database::connect_database(&self.buffer_pool, datname)?,
+            variables: self.executor.variables().clone(),
+            virtual_tables: VirtualTables::builtin(),
+            session_role: self.executor.session_role(),
+        };
+        self.executor = ConnectionExecutor::new(config, self.buffer_pool.clone());
+        Ok(())
+    }
+
+    /// Write all changes on relation files and write a checkpoint record on the write-ahead log,
+    /// so they don't need to be recovered if the database crashes. Checkpoints are also performed
+    /// when the database is dropped.
+    pub fn checkpoint(&self) -> Result<()> {
+        self.buffer_pool.checkpoint()
+    }
+
+    /// Write a tarball with a consistent copy of all files of the data directory on the given
+    /// writer. The database can be restored by opening the directory where the tarball is
+    /// extracted. In-memory databases can not be backed up.
+    pub fn base_backup<W: Write>(&self, out: W) -> Result<()> {
+        backup::base_backup(&self.buffer_pool, out)
+    }
+
+    /// Return the path of the data directory. None if the database is in memory.
+    pub fn data_dir(&self) -> Option<&Path> {
+        self.data_dir.as_deref()
+    }
+
+    /// Return the user tables of the database, as listed by the psql \dt meta-command.
+    pub fn list_tables(&self) -> Result<Rows> {
+        let list = ListRelations {
+            relkinds: vec!['r'],
+            name_pattern: None,
+            schema_pattern: None,
+            exclude_system: true,
+            with_table: false,
+        };
+        Ok(Rows::new(self.executor.exec_list_relations(&list)?))
+    }
+
+    /// Return the columns of the given table, as listed by the psql \d meta-command.
+    pub fn describe_table(&self, name: &str) -> Result<Rows> {
+        Ok(Rows::new(self.executor.exec_describe_relation(name)?))
+    }
+
+    /// Return all databases, as listed by the psql \l meta-command.
+    pub fn list_databases(&self) -> Result<Rows> {
+        Ok(Rows::new(self.executor.exec_list_databases()?))
+    }
+
+    /// Execute the given statements and return the rows of the last one, which must be a query.
+    /// Nothing is executed if the last statement does not return rows.
+    pub fn query(&mut self, sql: &str) -> Result<Rows> {
+        let statements = sql::split_statements(sql);
+        let returns_rows = match statements.last() {
+            // FETCH returns the next rows of a cursor like a query.
+            Some(last) => match cursor::parse_cursor_statement(last) {
+                Some(statement) => matches!(statement?, CursorStatement::Fetch { .. }),
+                None => matches!(
+                    sql::parse_sql(last)?.last(),
+                    Some(
+                        Statement::Query(_)
+                            | Statement::ShowVariable { .. }
+                            | Statement::Execute { .. }
+                    )
+                ),
+            },
+            None => false,
+        };
+        if !returns_rows {
+            bail!(Error::NoRows);
+        }
+
+        let mut last = None;
+        for statement in statements {
+            self.execute_statement(statement, &mut |result| {
+                last = Some(result);
+                Ok(())
+            })?;
+        }
+        match last {
+            Some(StatementResult::Rows(rows)) => Ok(rows),
+            _ => bail!(Error::NoRows),
+        }
+    }
+
+    /// Execute the given statements and return the total number of rows that they affected.
+    /// Queries are executed and their number of rows are counted as affected rows.
+    pub fn execute(&mut self, sql: &str) -> Result<u64> {
+        let mut total = 0;
+        self.execute_each(sql, |result| {
+            total += match result {
+                StatementResult::Rows(rows) => rows.len() as u64,
+                StatementResult::Command { affected, .. } => affected,
+            };
+            Ok(())
+        })?;
+        Ok(total)
+    }
+
+    /// Execute the given statements, calling f with the result of each statement after it is
+    /// executed. Statements after the first error are not executed.
+    pub fn execute_each<F>(&mut self, sql: &str, mut f: F) -> Result<()>
+    where
+        F: FnMut(StatementResult) -> Result<()>,
+    {
+        for statement in sql::split_statements(sql) {
+            self.execute_statement(statement, &mut f)?;
+        }
+        Ok(())
+    }
+
+    /// Execute a single statement of the statements given to execute_each.
+    fn execute_statement(
+        &mut self,
+        sql: &str,
+        f: &mut dyn FnMut(StatementResult) -> Result<()>,
+    ) -> Result<()> {
+        // RESET is not supported by the SQL parser.
+        if let Some(reset) = guc::parse_reset(sql) {
+            self.executor.exec_reset(&reset)?;
+            return f(StatementResult::Command {
+                tag: String::from("RESET"),
+                affected: 0,
+            });
+        }
+
+        // Statements of roles are not supported by the SQL parser.
+        if let Some(statement) = role::parse_role_statement(sql) {
+            let statement = statement?;
+            self.executor.exec_role_statement(&statement)?;
+            return f(StatementResult::Command {
+                tag: String::from(statement.command_tag()),
+                affected: 0,
+            });
+        }
+
+        // The TEMPLATE option of CREATE DATABASE is not supported by the SQL parser.
+        if let Some(statement) = database::parse_create_database(sql) {
+            self.executor.exec_create_database(&statement?)?;
+            return f(StatementResult::Command {
+                tag: String::from("CREATE DATABASE"),
+                affected: 0,
+            });
+        }
+
+        // Statements of cursors are not supported by the SQL parser.
+        if let Some(statement) = cursor::parse_cursor_statement(sql) {
+            let statement = statement?;
+            return match self.executor.exec_cursor_statement(&statement)? {
+                Some(result) => f(StatementResult::Rows(Rows::new(result))),
+                None => f(StatementResult::Command {
+                    tag: String::from(statement.command_tag()),
+                    affected: 0,
+                }),
+            };
+        }
+
+        for stmt in sql::parse_sql(sql)? {
+            f(self.exec_statement(stmt)?)?;
+        }
+        Ok(())
+    }
+
+    /// Execute a single statement. COPY is not supported since there is no client to send or
+    /// receive the data.
+    fn exec_statement(&mut self, stmt: Statement) -> Result<StatementResult> {
+        // Prepared statements are executed as the statement bound with the given parameters.
+        let stmt = match stmt {
+            Statement::Execute { name, parameters } => {
+                self.executor.bind_prepared(&name, &parameters)?
+            }
+            stmt => stmt,
+        };
+
+        // INSERT ... RETURNING returns the inserted rows like a query.
+        if let Some((insert, returning)) = insert::insert_returning(&stmt) {
+            return Ok(StatementResult::Rows(Rows::new(
+                self.executor.exec_insert(insert, returning)?,
+            )));
+        }
+
+        let tag = command_tag(&stmt);
+        let affected = match stmt {
+            Statement::Query(query) => {
+                return Ok(StatementResult::Rows(Rows::new(
+                    self.executor.exec_query(&query)?,
+                )))
+            }
+            Statement::ShowVariable { variable } => {
+                return Ok(StatementResult::Rows(Rows::new(
+                    self.executor.exec_show(&variable)?,
+                )))
+            }
+            stmt @ Statement::Insert { .. } => {
+                self.executor.exec_insert(&stmt, &[])?.tuples.len() as u64
+            }
+            Statement::Delete {
+                table_name,
+                selection,
+            } => self.executor.exec_delete(&table_name, &selection)?,
+            Statement::CreateTable {
+                name,
+                columns,
+                query: Some(query),
+                with_options,
+                ..
+            } => {
+                if !columns.is_empty() {
+                    bail!(SQLError::Unsupported(String::from(
+                        "column names on CREATE TABLE AS"
+                    )));
+                }
+                self.executor
+                    .exec_create_table_as(&name, query, &with_options)?
+            }
+            Statement::CreateTable {
+                name,
+                columns,
+                with_options,
+                ..
+            } => {
+                self.executor
+                    .exec_create_table(&name, &columns, &with_options)?;
+                0
+            }
+            Statement::CreateIndex {
+                name,
+                table_name,
+                columns,
+                unique,
+                ..
+            } => {
+                self.executor
+                    .exec_create_index(&name, &table_name, &columns, unique)?;
+                0
+            }
+            Statement::CreateSchema {
+                schema_name,
+                if_not_exists,
+            } => {
+                self.executor
+                    .exec_create_schema(&schema_name, if_not_exists)?;
+                0
+            }
+            Statement::Analyze { table_name, .. } => {
+                self.executor.exec_analyze(&table_name)?;
+                0
+            }
+            Statement::Drop {
+                object_type: ObjectType::Table,
+                if_exists,
+                names,
+                ..
+            } => {
+                self.executor.exec_drop_table(&names, if_exists)?;
+                0
+            }
+            Statement::Prepare {
+                name,
+                data_types,
+                statement,
+            } => {
+                self.executor.exec_prepare(&name, &data_types, *statement)?;
+                0
+            }
+            Statement::SetVariable {
+                variable, value, ..
+            } => {
+                self.executor.exec_set(&variable, &value)?;
+                0
+            }
+            Statement::Deallocate { name, .. } => {
+                self.executor.exec_deallocate(&name)?;
+                0
+            }
+            Statement::SetRole { role_name, .. } => {
+                self.executor.exec_set_role(&role_name)?;
+                0
+            }
+            Statement::Grant {
+                privileges,
+                objects,
+                grantees,
+                with_grant_option,
+                ..
+            } => {
+                self.executor
+                    .exec_grant(&privileges, &objects, &grantees, with_grant_option)?;
+                0
+            }
+            Statement::Revoke {
+                privileges,
+                objects,
+                grantees,
+                ..
+            } => {
+                self.executor
+                    .exec_revoke(&privileges, &objects, &grantees)?;
+                0
+            }
+            _ => bail!(SQLError::Unsupported(stmt.to_string())),
+        };
+        let tag = match tag {
+            "INSERT" => format!("INSERT 0 {}", affected),
+            "DELETE" | "SELECT" => format!("{} {}", tag, affected),
+            tag => String::from(tag),
+        };
+        Ok(StatementResult::Command { tag, affected })
+    }
+}
+
+/// Return the command tag of the given statement, without the number of affected rows.
+fn command_tag(stmt: &Statement) -> &'static str {
+    match stmt {
+        Statement::Query(_) => "SELECT",
+        Statement::ShowVariable { .. } => "SHOW",
+        Statement::Insert { .. } => "INSERT",
+        Statement::Delete { .. } => "DELETE",
+        // CREATE TABLE AS reports the number of rows inserted, like Postgres.
+        Statement::CreateTable { query: Some(_), .. } => "SELECT",
+        Statement::CreateTable { .. } => "CREATE TABLE",
+        Statement::CreateIndex { .. } => "CREATE INDEX",
+        Statement::CreateSchema { .. } => "CREATE SCHEMA",
+        Statement::Analyze { .. } => "ANALYZE",
+        Statement::Drop { .. } => "DROP TABLE",
+        Statement::Prepare { .. } => "PREPARE",
+        Statement::SetVariable { .. } | Statement::SetRole { .. } => "SET",
+        Statement::Grant { .. } => "GRANT",
+        Statement::Revoke { .. } => "REVOKE",
+        Statement::Deallocate { .. } => "DEALLOCATE",
+        _ => "",
+    }
+}
+
+impl Drop for Database {
+    fn drop(&mut self) {
+        // Nothing is stored on disk by in-memory databases.
+        let control = match &self.control {
+            Some(control) => control,
+            None => return,
+        };
+        let checkpoint = control
+            .set_state(ClusterState::ShuttingDown)
+            .and_then(|_| self.buffer_pool.checkpoint())
+            .and_then(|_| control.set_state(ClusterState::Shutdown));
+        if let Err(err) = checkpoint {
+            log::error!("failed to perform shutdown checkpoint: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        thread,
+        time::Duration,
+    };
+
+    use super::*;
+    use crate::{
+        access::lmgr::LockOwner,
+        catalog,
+        planner::{Plan, PlanNodeType},
+        sql::{
+            error::{PgError, SqlState},
+            SQLError,
+        },
+        storage::{buffile::TEMP_FILES_DIR, relation_locator::database_path},
+    };
+
+    #[test]
+    fn test_embedded_database() -> Result<()> {
+        let data_dir = tempfile::tempdir()?;
+
+        {
+            let mut db = Database::open(data_dir.path())?;
+            assert_eq!(db.execute("CREATE TABLE t(a int, b text);")?, 0);
+            assert_eq!(
+                db.execute("INSERT INTO t VALUES (1, 'one'); INSERT INTO t VALUES (2, 'two');")?,
+                2
+            );
+            assert!(matches!(
+                db.query("INSERT INTO t VALUES (3, 'three');")
+                    .unwrap_err()
+                    .downcast_ref::<Error>(),
+                Some(Error::NoRows)
+            ));
+            db.execute("INSERT INTO t VALUES (3, 'three');")?;
+            assert!(db.execute("SELECT * FROM missing;").is_err());
+        }
+
+        // Changes are kept after reopening the database.
+        let mut db = Database::open(data_dir.path())?;
+        let rows = db.query("SELECT b, a FROM t WHERE a > 1;")?;
+        assert_eq!(rows.columns(), vec!["b", "a"]);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows.get(1).unwrap().get::<_, String>(0)?, "three");
+        assert_eq!(db.execute("DELETE FROM t WHERE a = 1;")?, 1);
+        assert_eq!(db.execute("SELECT * FROM t;")?, 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_databases_on_same_process() -> Result<()> {
+        let (first_dir, second_dir) = (tempfile::tempdir()?, tempfile::tempdir()?);
+        let mut first = Database::open(first_dir.path())?;
+        let mut second = Database::open(second_dir.path())?;
+        let mut memory = Database::open_in_memory()?;
+
+        // Each database stores its files only inside its own data directory.
+        first.execute("CREATE TABLE t(a int);")?;
+        second.execute("CREATE TABLE t(a text, b int);")?;
+        memory.execute("CREATE TABLE t(a int, b int, c int);")?;
+        first.execute("INSERT INTO t VALUES (1), (2);")?;
+        second.execute("INSERT INTO t VALUES ('one', 1);")?;
+        drop(second);
+
+        assert_eq!(first.query("SELECT * FROM t;")?.len(), 2);
+        assert_eq!(memory.query("SELECT * FROM t;")?.len(), 0);
+        let mut second = Database::open(second_dir.path())?;
+        let rows = second.query("SELECT a, b FROM t;")?;
+        assert_eq!(rows.get(0).unwrap().get::<_, String>("a")?, "one");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_database() -> Result<()> {
+        let data_dir = tempfile::tempdir()?;
+
+        {
+            let mut db = Database::open(data_dir.path())?;
+            db.execute("CREATE TABLE t(a int);")?;
+            db.execute("INSERT INTO t VALUES (1), (2);")?;
+            db.execute("CREATE DATABASE other;")?;
+            db.execute("CREATE DATABASE IF NOT EXISTS other;")?;
+
+            let mut err = |sql: &str| db.execute(sql).unwrap_err().downcast::<PgError>().unwrap();
+            assert_eq!(
+                err("CREATE DATABASE other;").code,
+                SqlState::DuplicateDatabase
+            );
+            let missing = err("CREATE DATABASE db TEMPLATE missing;");
+            assert_eq!(missing.code, SqlState::InvalidCatalogName);
+            assert_eq!(
+                missing.message,
+                "template database \"missing\" does not exist"
+            );
+            assert_eq!(db.list_databases()?.len(), 2);
+
+            // The new database has a copy of the tables of the template, changed independently.
+            db.connect("other")?;
+            db.execute("INSERT INTO t VALUES (3);")?;
+            assert_eq!(db.query("SELECT * FROM t;")?.len(), 3);
+            db.connect("tinydb")?;
+            assert_eq!(db.query("SELECT * FROM t;")?.len(), 2);
+            assert_eq!(db.buffer_pool.catalog_cache().databases(), 2);
+        }
+
+        let mut db = Database::open(data_dir.path())?;
+        db.connect("other")?;
+        assert_eq!(db.query("SELECT * FROM t;")?.len(), 3);
+
+        // Databases without a directory can not be connected.
+        let db_oid = catalog::get_datase_oid(&db.buffer_pool, "other")?;
+        drop(db);
+        fs::remove_dir_all(data_dir.path().join(database_path(&db_oid)))?;
+        let mut db = Database::open(data_dir.path())?;
+        let err = db.connect("other").unwrap_err().downcast::<PgError>()?;
+        assert_eq!(err.code, SqlState::InvalidCatalogName);
+        assert_eq!(
+            err.detail,
+            Some(format!(
+                "The database subdirectory \"base/{}\" is missing.",
+                db_oid
+            ))
+        );
+
+        // Databases of in-memory databases are copied in memory.
+        let mut db = Database::open_in_memory()?;
+        db.execute("CREATE TABLE t(a int); INSERT INTO t VALUES (1);")?;
+        db.execute("CREATE DATABASE other WITH TEMPLATE = tinydb;")?;
+        db.connect("other")?;
+        assert_eq!(db.query("SELECT * FROM t;")?.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_schemas() -> Result<()> {
+        let data_dir = tempfile::tempdir()?;
+
+        {
+            let mut db = Database::open(data_dir.path())?;
+            db.execute("CREATE SCHEMA app;")?;
+            db.execute("CREATE SCHEMA IF NOT EXISTS app;")?;
+            db.execute("CREATE TABLE t(a int); INSERT INTO t VALUES (1);")?;
+            db.execute("CREATE TABLE app.t(a text); INSERT INTO app.t VALUES ('app');")?;
+
+            let mut err = |sql: &str| PgError::from_error(&db.execute(sql).unwrap_err());
+            assert_eq!(err("CREATE SCHEMA app;").code, SqlState::DuplicateSchema);
+            assert_eq!(err("CREATE SCHEMA pg_app;").code, SqlState::ReservedName);
+            assert_eq!(
+                err("CREATE TABLE app.t(b int);").code,
+                SqlState::DuplicateTable
+            );
+            let missing = err("SELECT * FROM missing.t;");
+            assert_eq!(missing.code, SqlState::InvalidSchemaName);
+            assert_eq!(missing.message, "schema missing does not exist");
+        }
+
+        // Schemas are stored on the catalogs and unqualified names are searched on search_path.
+        let mut db = Database::open(data_dir.path())?;
+        let value = |db: &mut Database, sql: &str| -> Result<String> {
+            let rows = db.query(sql)?;
+            let value = rows.get(0).unwrap().get::<_, String>("a")?;
+            Ok(value)
+        };
+        assert_eq!(value(&mut db, "SELECT a FROM app.t;")?, "app");
+        assert_eq!(db.query("SELECT a FROM public.t;")?.len(), 1);
+        db.execute("SET search_path TO app, public;")?;
+        assert_eq!(value(&mut db, "SELECT a FROM t;")?, "app");
+        db.execute("CREATE TABLE u(a int);")?;
+        assert_eq!(db.query("SELECT a FROM app.u;")?.len(), 0);
+
+        // System catalogs are found even if pg_catalog is not on the search path.
+        db.execute("SET search_path TO app;")?;
+        assert!(!db.query("SELECT relname FROM pg_class;")?.is_empty());
+        db.execute("SET search_path TO missing;")?;
+        let err = PgError::from_error(&db.execute("CREATE TABLE v(a int);").unwrap_err());
+        assert_eq!(err.code, SqlState::InvalidSchemaName);
+        assert!(db.query("SELECT a FROM t;").is_err());
+
+        db.execute("RESET search_path;")?;
+        db.execute("DROP TABLE app.t;")?;
+        assert_eq!(db.query("SELECT a FROM t;")?.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_insert_returning() -> Result<()> {
+        let mut db = Database::open_in_memory()?;
+        db.execute("CREATE TABLE t(a int, b text DEFAULT 'none');")?;
+
+        let rows = db.query("INSERT INTO t (a) VALUES (1), (2) RETURNING b, a + 1 AS next;")?;
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows.get(0).unwrap().get::<_, String>("b")?, "none");
+        assert_eq!(rows.get(1).unwrap().get::<_, i32>("next")?, 3);
+
+        let rows = db.query("INSERT INTO t VALUES (3, 'three') RETURNING *;")?;
+        assert_eq!(rows.get(0).unwrap().get::<_, i32>("a")?, 3);
+        assert_eq!(rows.get(0).unwrap().get::<_, String>("b")?, "three");
+
+        db.execute("PREPARE ins AS INSERT INTO t VALUES ($1, $2) RETURNING a;")?;
+        let rows = db.query("EXECUTE ins (4, 'four');")?;
+        assert_eq!(rows.get(0).unwrap().get::<_, i32>("a")?, 4);
+
+        // Rows that fail to be returned are not inserted.
+        let err = db
+            .execute("INSERT INTO t VALUES (5, 'five') RETURNING count(a);")
+            .unwrap_err();
+        assert_eq!(PgError::from_error(&err).code, SqlState::GroupingError);
+        assert!(db.execute("INSERT INTO t VALUES (5) RETURNING c;").is_err());
+        assert_eq!(db.query("SELECT a FROM t;")?.len(), 4);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_insert_on_conflict() -> Result<()> {
+        let mut db = Database::open_in_memory()?;
+        db.execute("CREATE TABLE t(a int, b text, c int);")?;
+        db.execute("INSERT INTO t VALUES (1, 'one', 10), (1, 'uno', 11);")?;
+
+        let mut err = |sql: &str| PgError::from_error(&db.execute(sql).unwrap_err());
+        let duplicated = err("CREATE UNIQUE INDEX t_a ON t(a);");
+        assert_eq!(duplicated.code, SqlState::UniqueViolation);
+        assert_eq!(duplicated.detail.unwrap(), "Key (a)=(1) is duplicated.");
+
+        db.execute("DELETE FROM t WHERE b = 'uno';")?;
+        db.execute("CREATE UNIQUE INDEX t_a ON t(a); CREATE UNIQUE INDEX t_c ON t(c);")?;
+
+        let mut err = |sql: &str| PgError::from_error(&db.execute(sql).unwrap_err());
+        let duplicated = err("INSERT INTO t VALUES (2, 'two', 20), (1, 'uno', 11);");
+        assert_eq!(duplicated.code, SqlState::UniqueViolation);
+        assert_eq!(
+            duplicated.message,
+            "duplicate key value violates unique constraint \"t_a\""
+        );
+        assert_eq!(duplicated.detail.unwrap(), "Key (a)=(1) already exists.");
+        assert_eq!(
+            err("INSERT INTO t VALUES (2, 'two', 20), (2, 'dos', 21);").code,
+            SqlState::UniqueViolation
+        );
+        assert_eq!(
+            err("INSERT INTO t VALUES (1) ON CONFLICT (b) DO NOTHING;").code,
+            SqlState::InvalidColumnReference
+        );
+        assert_eq!(
+            err("INSERT INTO t VALUES (1) ON CONFLICT DO UPDATE SET b = 'x';").code,
+            SqlState::SyntaxError
+        );
+        assert_eq!(
+            err("INSERT INTO t VALUES (1, 'uno', 11) ON CONFLICT (a) DO UPDATE SET b = b;").code,
+            SqlState::AmbiguousColumn
+        );
+        // Rows that conflict on a unique index that is not the conflict target are errors.
+        assert_eq!(
+            err("INSERT INTO t VALUES (2, 'two', 10) ON CONFLICT (a) DO NOTHING;").code,
+            SqlState::UniqueViolation
+        );
+        assert_eq!(
+            err("INSERT INTO t VALUES (1), (1) ON CONFLICT (a) DO UPDATE SET b = 'x';").code,
+            SqlState::CardinalityViolation
+        );
+
+        // NULL values never conflict.
+        assert_eq!(db.execute("INSERT INTO t VALUES (NULL), (NULL);")?, 2);
+        assert_eq!(
+            db.execute(
+                "INSERT INTO t VALUES (1, 'uno', 11), (2, 'two', 20) ON CONFLICT DO NOTHING;"
+            )?,
+            1
+        );
+        assert_eq!(
+            db.execute("INSERT INTO t VALUES (1, 'uno', 11) ON CONFLICT (a) DO NOTHING;")?,
+            0
+        );
+
+        let rows = db.query(
+            "INSERT INTO t VALUES (1, 'uno', 11), (3, 'three', 30) ON CONFLICT (a) \
+             DO UPDATE SET b = t.b || '/' || excluded.b, c = excluded.c RETURNING a, b, c;",
+        )?;
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows.get(0).unwrap().get::<_, String>("b")?, "one/uno");
+        assert_eq!(rows.get(0).unwrap().get::<_, i32>("c")?, 11);
+        assert_eq!(rows.get(1).unwrap().get::<_, String>("b")?, "three");
+
+        // The updated row is replaced, so its old values don't conflict anymore.
+        db.execute("INSERT INTO t VALUES (4, 'four', 10);")?;
+        let rows = db.query("SELECT b, c FROM t WHERE a = 1;")?;
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows.get(0).unwrap().get::<_, i32>("c")?, 11);
+
+        db.execute(
+            "PREPARE upsert AS INSERT INTO t VALUES ($1, 'x', 0) \
+             ON CONFLICT (a) DO UPDATE SET c = $2;",
+        )?;
+        assert_eq!(db.execute("EXECUTE upsert (4, 40);")?, 1);
+        assert_eq!(
+            db.query("SELECT c FROM t WHERE a = 4;")?
+                .get(0)
+                .unwrap()
+                .get::<_, i32>("c")?,
+            40
+        );
+        assert_eq!(db.query("SELECT a FROM t;")?.len(), 6);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cursors() -> Result<()> {
+        let mut db = Database::open_in_memory()?;
+        db.execute("CREATE TABLE t(a int, b text);")?;
+        db.execute("INSERT INTO t VALUES (1, 'one'), (2, 'two'), (3, 'three'), (4, 'four');")?;
+
+        let fetch = |db: &mut Database, sql: &str| -> Result<Vec<i32>> {
+            db.query(sql)?
+                .iter()
+                .map(|row| row.get::<_, i32>("a"))
+                .collect()
+        };
+
+        db.execute("DECLARE c CURSOR FOR SELECT a FROM t ORDER BY a DESC;")?;
+        assert_eq!(fetch(&mut db, "FETCH c;")?, vec![4]);
+        assert!(fetch(&mut db, "FETCH 0 FROM c;")?.is_empty());
+        assert_eq!(fetch(&mut db, "FETCH 2 FROM c;")?, vec![3, 2]);
+        assert_eq!(fetch(&mut db, "FETCH ALL IN c;")?, vec![1]);
+        assert!(fetch(&mut db, "FETCH NEXT FROM c;")?.is_empty());
+
+        // Cursors are kept open until they are closed, so their names can't be reused.
+        let mut err = |sql: &str| PgError::from_error(&db.execute(sql).unwrap_err());
+        assert_eq!(
+            err("DECLARE c CURSOR FOR SELECT a FROM t;").code,
+            SqlState::DuplicateCursor
+        );
+        assert_eq!(err("FETCH d;").code, SqlState::InvalidCursorName);
+        assert_eq!(
+            err("FETCH BACKWARD FROM c;").code,
+            SqlState::ObjectNotInPrerequisiteState
+        );
+        assert_eq!(
+            err("DECLARE d CURSOR FOR SELECT x FROM t;").code,
+            SqlState::UndefinedColumn
+        );
+        db.execute("CLOSE c;")?;
+        let mut err = |sql: &str| PgError::from_error(&db.execute(sql).unwrap_err());
+        assert_eq!(err("CLOSE c;").code, SqlState::InvalidCursorName);
+
+        db.execute("DECLARE c CURSOR FOR SELECT a FROM t LIMIT 2 OFFSET 1;")?;
+        db.execute("DECLARE s CURSOR FOR SELECT count(*) AS a FROM t;")?;
+        assert_eq!(fetch(&mut db, "FETCH FORWARD 5 FROM c;")?, vec![2, 3]);
+        assert_eq!(db.execute("FETCH ALL FROM s;")?, 1);
+        assert_eq!(db.execute("FETCH ALL FROM s;")?, 0);
+
+        db.execute("CLOSE ALL;")?;
+        let mut err = |sql: &str| PgError::from_error(&db.execute(sql).unwrap_err());
+        assert_eq!(err("FETCH s;").code, SqlState::InvalidCursorName);
+        Ok(())
+    }
+
+    #[test]
+    fn test_multiple_statements() -> Result<()> {
+        assert_eq!(
+            sql::split_statements("SELECT ';', \"a;b\" FROM t; -- x;\n /* y; */ ;; SELECT 2"),
+            vec!["SELECT ';', \"a;b\" FROM t", "SELECT 2"]
+        );
+        assert!(sql::split_statements(" ;; -- only a comment;").is_empty());
+
+        let mut db = Database::open_in_memory()?;
+        let mut tags = Vec::new();
+        db.execute_each(
+            "CREATE TABLE t(a int);; CREATE ROLE r; INSERT INTO t VALUES (1), (2); \
+             DECLARE c CURSOR FOR SELECT a FROM t; FETCH ALL FROM c;",
+            |result| {
+                tags.push(match result {
+                    StatementResult::Rows(rows) => format!("ROWS {}", rows.len()),
+                    StatementResult::Command { tag, .. } => tag,
+                });
+                Ok(())
+            },
+        )?;
+        assert_eq!(
+            tags,
+            vec![
+                "CREATE TABLE",
+                "CREATE ROLE",
+                "INSERT 0 2",
+                "DECLARE CURSOR",
+                "ROWS 2"
+            ]
+        );
+        assert_eq!(db.execute("")?, 0);
+
+        // Nothing is executed if the last statement does not return rows.
+        assert!(db.query("INSERT INTO t VALUES (3); CLOSE c;").is_err());
+        let rows = db.query("INSERT INTO t VALUES (3); RESET ALL; SELECT a FROM t;;")?;
+        assert_eq!(rows.len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_portal_row_limit() -> Result<()> {
+        let mut db = Database::open_in_memory()?;
+        db.execute("CREATE TABLE t(a int);")?;
+        db.execute("INSERT INTO t VALUES (1), (2), (3);")?;
+
+        let executor = &mut db.executor;
+        executor.exec_parse("s", "SELECT a FROM t WHERE a > $1", &[])?;
+        executor.exec_bind("p", "s", &[], &[Some(b"0".to_vec())], vec![])?;
+
+        let mut execute = |max_rows| -> Result<(usize, bool)> {
+            let (result, suspended) = executor.exec_portal_rows("p", max_rows)?;
+            Ok((result.tuples.len(), suspended))
+        };
+        assert_eq!(execute(Some(2))?, (2, true));
+        assert_eq!(execute(Some(2))?, (1, false));
+        assert_eq!(execute(None)?, (0, false));
+
+        // The portal name is used by the cursor until the portal is closed.
+        let err = db
+            .execute("DECLARE p CURSOR FOR SELECT a FROM t;")
+            .unwrap_err();
+        assert_eq!(PgError::from_error(&err).code, SqlState::DuplicateCursor);
+        db.executor.close_portal("p");
+        db.execute("DECLARE p CURSOR FOR SELECT a FROM t;")?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_roles_and_privileges() -> Result<()> {
+        let data_dir = tempfile::tempdir()?;
+
+        {
+            let mut db = Database::open(data_dir.path())?;
+            db.execute("CREATE TABLE t(a int); INSERT INTO t VALUES (1);")?;
+            db.execute("CREATE USER alice PASSWORD 'secret';")?;
+            db.execute("CREATE ROLE readers;")?;
+            db.execute("GRANT readers TO alice;")?;
+
+            let mut err = |sql: &str| PgError::from_error(&db.execute(sql).unwrap_err());
+            assert_eq!(err("CREATE ROLE alice;").code, SqlState::DuplicateObject);
+            assert_eq!(
+                err("GRANT missing TO alice;").code,
+                SqlState::UndefinedObject
+            );
+            assert_eq!(
+                err("GRANT alice TO readers;").code,
+                SqlState::InvalidGrantOperation
+            );
+        }
+
+        // Roles and privileges are stored on the catalogs.
+        let mut db = Database::open(data_dir.path())?;
+        db.execute("SET ROLE alice;")?;
+        let denied = |db: &mut Database, sql: &str| {
+            let err = PgError::from_error(&db.execute(sql).unwrap_err());
+            assert_eq!(err.code, SqlState::InsufficientPrivilege, "{}", sql);
+            err.message
+        };
+        assert_eq!(
+            denied(&mut db, "SELECT a FROM t;"),
+            "permission denied for table t"
+        );
+        denied(&mut db, "INSERT INTO t VALUES (2);");
+        denied(&mut db, "DELETE FROM t;");
+        denied(&mut db, "CREATE ROLE bob;");
+        denied(&mut db, "GRANT SELECT ON t TO alice;");
+        assert_eq!(denied(&mut db, "DROP TABLE t;"), "must be owner of table t");
+        denied(&mut db, "SELECT rolname FROM pg_authid;");
+        assert!(!db.query("SELECT relname FROM pg_class;")?.is_empty());
+
+        // Privileges granted to a role are also granted to its members.
+        db.execute("RESET ROLE;")?;
+        db.execute("GRANT SELECT ON t TO readers;")?;
+        db.execute("GRANT INSERT ON TABLE t TO PUBLIC;")?;
+        db.execute("SET ROLE alice;")?;
+        assert_eq!(db.query("SELECT a FROM t;")?.len(), 1);
+        db.execute("INSERT INTO t VALUES (2);")?;
+        denied(&mut db, "DELETE FROM t WHERE a = 2;");
+
+        // Tables are owned by the role that created them.
+        db.execute("CREATE TABLE u AS SELECT a FROM t;")?;
+        db.execute("CREATE INDEX u_a ON u(a);")?;
+        db.execute("GRANT ALL ON u TO readers;")?;
+        db.execute("ANALYZE;")?;
+        denied(&mut db, "ANALYZE t;");
+
+        db.execute("SET ROLE NONE;")?;
+        db.execute("REVOKE SELECT ON t FROM readers;")?;
+        db.execute("REVOKE readers FROM alice;")?;
+        db.execute("SET ROLE alice;")?;
+        denied(&mut db, "SELECT a FROM t;");
+        db.execute("INSERT INTO t VALUES (3);")?;
+        assert_eq!(db.query("SELECT a FROM u;")?.len(), 2);
+        db.execute("DROP TABLE u;")?;
+
+        db.execute("RESET ROLE;")?;
+        assert_eq!(db.query("SELECT a FROM t;")?.len(), 3);
+        let err = PgError::from_error(&db.execute("SET ROLE bob;").unwrap_err());
+        assert_eq!(err.code, SqlState::UndefinedObject);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_columnar_table() -> Result<()> {
+        let data_dir = tempfile::tempdir()?;
+
+        {
+            let mut db = Database::open(data_dir.path())?;
+            db.execute("CREATE TABLE t(a int, b text) WITH (access_method = 'columnar');")?;
+            db.execute("INSERT INTO t VALUES (1, 'one'), (2, NULL);")?;
+        }
+
+        let mut db = Database::open(data_dir.path())?;
+        let rows = db.query("SELECT a, b FROM t;")?;
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows.get(1).unwrap().get::<_, Option<String>>("b")?, None);
+
+        let mut err = |sql: &str| db.execute(sql).unwrap_err().downcast::<PgError>().unwrap();
+        assert_eq!(
+            err("CREATE TABLE t2(a int) WITH (access_method = 'unknown');").message,
+            "access method \"unknown\" does not exist"
+        );
+        assert_eq!(
+            err("CREATE TABLE t2(a int) WITH (fillfactor = 10);").message,
+            "unrecognized parameter \"fillfactor\""
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_row_too_big() -> Result<()> {
+        let mut db = Database::open_in_memory()?;
+        db.execute("CREATE TABLE t(a int, b text);")?;
+        db.execute("CREATE TABLE c(a int, b text) WITH (access_method = 'columnar');")?;
+
+        for table in ["t", "c"] {
+            // Rows that don't fit on the page with free space are stored on a new page.
+            let insert =
+                |len: usize| format!("INSERT INTO {} VALUES (1, '{}');", table, "x".repeat(len));
+            db.execute(&insert(5000))?;
+            db.execute(&insert(5000))?;
+
+            let err = db.execute(&insert(9000)).unwrap_err();
+            let err = PgError::from_error(&err);
+            assert_eq!(err.code, SqlState::ProgramLimitExceeded);
+            assert!(err.message.starts_with("row is too big"), "{}", err.message);
+
+            let rows = db.query(&format!("SELECT length(b) FROM {};", table))?;
+            assert_eq!(rows.len(), 2);
+            assert_eq!(rows.get(1).unwrap().get::<_, i32>(0)?, 5000);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_order_by() -> Result<()> {
+        let data_dir = tempfile::tempdir()?;
+        let mut db = Database::open(data_dir.path())?;
+        db.execute("CREATE TABLE t(a int, b text);")?;
+        let mut expected = (0..20000)
+            .map(|i| ((i * 7919) % 20000, i % 3))
+            .collect::<Vec<_>>();
+        let values = expected
+            .iter()
+            .map(|(a, b)| format!("({}, '{}')", a, b))
+            .collect::<Vec<_>>();
+        db.execute(&format!("INSERT INTO t VALUES {};", values.join(", ")))?;
+
+        // Sorts that don't fit on work_mem are merged from temporary files.
+        db.execute("SET work_mem = '64kB';")?;
+        let rows = db.query("SELECT a FROM t ORDER BY b DESC, a;")?;
+        assert_eq!(rows.len(), 20000);
+        let values = (0..rows.len())
+            .map(|i| rows.get(i).unwrap().get::<_, i32>(0))
+            .collect::<Result<Vec<_>>>()?;
+        expected.sort_by_key(|(a, b)| (std::cmp::Reverse(*b), *a));
+        assert_eq!(values, expected.iter().map(|(a, _)| *a).collect::<Vec<_>>());
+        let temp_files = data_dir.path().join(TEMP_FILES_DIR);
+        assert_eq!(std::fs::read_dir(temp_files)?.count(), 0);
+
+        let rows = db.query("SELECT a AS x FROM t ORDER BY x DESC LIMIT 2 OFFSET 1;")?;
+        assert_eq!(rows.get(0).unwrap().get::<_, i32>(0)?, 19998);
+        assert_eq!(rows.get(1).unwrap().get::<_, i32>(0)?, 19997);
+
+        let mut err = |sql: &str| PgError::from_error(&db.execute(sql).unwrap_err());
+        let position = err("SELECT a FROM t ORDER BY 2;");
+        assert_eq!(position.code, SqlState::InvalidColumnReference);
+        assert_eq!(
+            position.message,
+            "ORDER BY position 2 is not in select list"
+        );
+        assert_eq!(
+            err("SELECT a AS c, b AS c FROM t ORDER BY c;").code,
+            SqlState::AmbiguousColumn
+        );
+        assert_eq!(
+            err("SET work_mem = '1kB';").code,
+            SqlState::InvalidParameterValue
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_out_of_memory() -> Result<()> {
+        let mut db = Database::open_in_memory()?;
+        db.execute("CREATE TABLE t(a int, b text);")?;
+        let values = (0..5000)
+            .map(|i| format!("({}, '{}')", i, "x".repeat(100)))
+            .collect::<Vec<_>>();
+        db.execute(&format!("INSERT INTO t VALUES {};", values.join(", ")))?;
+        let subquery = "SELECT count(*) FROM (SELECT * FROM t) AS s;";
+        assert_eq!(db.execute(subquery)?, 1);
+
+        // The values of the rows are allocated on chunks shared by many rows.
+        let memory = db.query("SELECT * FROM t;")?.stats().memory;
+        assert!((5000 * 100..2 * 5000 * 100).contains(&memory), "{}", memory);
+
+        // Subquery tuples that don't fit on work_mem fail the query, while sorts use temporary
+        // files.
+        db.execute("SET work_mem = '64kB';")?;
+        let err = PgError::from_error(&db.execute(subquery).unwrap_err());
+        assert_eq!(err.code, SqlState::OutOfMemory);
+        assert_eq!(err.message, "out of memory for query");
+        assert_eq!(db.execute("SELECT a FROM t ORDER BY b, a DESC;")?, 5000);
+        assert_eq!(
+            db.execute("SELECT count(*) FROM (SELECT * FROM t WHERE a < 10) AS s;")?,
+            1
+        );
+
+        db.execute("SET work_mem = '1MB';")?;
+        let rows = db.query(subquery)?;
+        assert_eq!(rows.get(0).unwrap().get::<_, i64>(0)?, 5000);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_in_memory_database() -> Result<()> {
+        let mut db = Database::open_in_memory()?;
+        assert_eq!(db.data_dir(), None);
+        db.execute("CREATE TABLE t(a int, b text);")?;
+        db.execute("CREATE INDEX t_a ON t(a);")?;
+
+        // Insert more pages than the buffer pool can hold, so pages are replaced and read again
+        // from the in-memory storage.
+        let text = "x".repeat(1000);
+        for i in 0..2000 {
+            db.execute(&format!("INSERT INTO t VALUES ({}, '{}');", i, text))?;
+        }
+        assert_eq!(db.execute("SELECT * FROM t;")?, 2000);
+        let rows = db.query("SELECT length(b) FROM t WHERE a = 1999;")?;
+        assert_eq!(rows.get(0).unwrap().get::<_, i32>(0)?, 1000);
+
+        // Each in-memory database has its own data.
+        let mut other = Database::open_in_memory()?;
+        assert!(other.execute("SELECT * FROM t;").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_drop_table() -> Result<()> {
+        let data_dir = tempfile::tempdir()?;
+
+        {
+            let mut db = Database::open(data_dir.path())?;
+            db.execute("CREATE TABLE t(a int); CREATE INDEX t_a ON t(a);")?;
+            db.execute("CREATE TABLE c(a int) WITH (access_method = 'columnar');")?;
+            db.execute("INSERT INTO t VALUES (1); INSERT INTO c VALUES (1);")?;
+
+            db.execute("DROP TABLE t, c;")?;
+            assert!(db.execute("SELECT * FROM t;").is_err());
+            assert!(db.execute("SELECT * FROM c;").is_err());
+
+            // The names of the dropped table and index can be used again.
+            db.execute("CREATE TABLE t(b text); CREATE INDEX t_a ON t(b);")?;
+            db.execute("INSERT INTO t VALUES ('one'), ('two');")?;
+            assert_eq!(db.execute("SELECT * FROM t WHERE b = 'two';")?, 1);
+
+            db.execute("DROP TABLE IF EXISTS missing;")?;
+            // Tables are only dropped if all of them can be dropped.
+            assert!(db.execute("DROP TABLE t, missing;").is_err());
+            assert_eq!(db.execute("SELECT * FROM t;")?, 2);
+
+            let mut err = |sql: &str| db.execute(sql).unwrap_err();
+            assert_eq!(
+                PgError::from_error(&err("DROP TABLE missing;")).code,
+                SqlState::UndefinedTable
+            );
+            assert_eq!(
+                PgError::from_error(&err("DROP TABLE pg_class;")).message,
+                "permission denied: \"pg_class\" is a system catalog"
+            );
+            assert!(err("DROP VIEW t;").is::<SQLError>());
+        }
+
+        // The drop is recovered from the write-ahead log after reopening the database.
+        let mut db = Database::open(data_dir.path())?;
+        let rows = db.query("SELECT b FROM t;")?;
+        assert_eq!(rows.columns(), vec!["b"]);
+        assert_eq!(rows.len(), 2);
+        assert!(db.execute("SELECT * FROM c;").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_drop_table_waits_for_scans() -> Result<()> {
+        let mut db = Database::open_in_memory()?;
+        db.execute("CREATE TABLE t(a int); INSERT INTO t VALUES (1);")?;
+
+        let query = match sql::parse_sql("SELECT * FROM t;")?.pop() {
+            Some(Statement::Query(query)) => query,
+            _ => unreachable!(),
+        };
+        let db_oid = catalog::get_datase_oid(&db.buffer_pool, DEFAULT_DATABASE)?;
+        let plan = Plan::create(
+            &db.buffer_pool,
+            &db_oid,
+            &db.executor.variables().search_path(),
+            &VirtualTables::builtin(),
+            &LockOwner::new(),
+            &query,
+        )?;
+
+        // The plan holds a lock on the table until it is dropped by another thread.
+        let released = Arc::new(AtomicBool::new(false));
+        let scan = {
+            let released = released.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(100));
+                released.store(true, Ordering::SeqCst);
+                drop(plan);
+            })
+        };
+
+        db.execute("DROP TABLE t;")?;
+        assert!(released.load(Ordering::SeqCst));
+        scan.join().unwrap();
+
+        Ok(())
+    }
+
+    /// Return the plan of the given query or delete.
+    fn create_plan(db: &Database, sql: &str) -> Result<Plan> {
+        let db_oid = catalog::get_datase_oid(&db.buffer_pool, DEFAULT_DATABASE)?;
+        let owner = LockOwner::new();
+        let search_path = db.executor.variables().search_path();
+        let plan = match sql::parse_sql(sql)?.pop() {
+            Some(Statement::Query(query)) => Plan::create(
+                &db.buffer_pool,
+                &db_oid,
+                &search_path,
+                &VirtualTables::builtin(),
+                &owner,
+                &query,
+            )?,
+            Some(Statement::Delete {
+                table_name,
+                selection,
+            }) => Plan::create_delete(
+                &db.buffer_pool,
+                &db_oid,
+                &search_path,
+                &owner,
+                &table_name,
+                &selection,
+            )?,
+            _ => unreachable!(),
+        };
+        Ok(plan)
+    }
+
+    /// Return the type of the plan node that scan the relation of the given query or delete.
+    fn scan_node_type(db: &Database, sql: &str) -> Result<String> {
+        fn scan(plan: &Plan) -> String {
+            match &plan.node_type {
+                PlanNodeType::Projection { state } => scan(&state.child),
+                PlanNodeType::Filter { state } => scan(&state.child),
+                PlanNodeType::Delete { state } => scan(&state.child),
+                node_type => node_type.to_string(),
+            }
+        }
+
+        Ok(scan(&create_plan(db, sql)?))
+    }
+
+    #[test]
+    fn test_scan_key_pushdown() -> Result<()> {
+        /// Return the number of scan keys of the sequential scan of the given query, and whether
+        /// the scan is still below a filter.
+        fn scan_keys(db: &Database, sql: &str) -> Result<(usize, bool)> {
+            fn scan(plan: &Plan, filtered: bool) -> (usize, bool) {
+                match &plan.node_type {
+                    PlanNodeType::SeqScan { state } => (state.scan_keys.len(), filtered),
+                    PlanNodeType::Projection { state } => scan(&state.child, filtered),
+                    PlanNodeType::Delete { state } => scan(&state.child, filtered),
+                    PlanNodeType::Filter { state } => scan(&state.child, true),
+                    node_type => panic!("unexpected plan node {}", node_type),
+                }
+            }
+            Ok(scan(&create_plan(db, sql)?, false))
+        }
+
+        let mut db = Database::open_in_memory()?;
+        db.execute("CREATE TABLE t(a int, b int, c text);")?;
+        db.execute("INSERT INTO t VALUES (1, 10, 'x'), (2, 20, NULL), (3, 30, 'z');")?;
+
+        assert_eq!(scan_keys(&db, "SELECT * FROM t;")?, (0, false));
+        assert_eq!(
+            scan_keys(&db, "SELECT * FROM t WHERE a > 1 AND c IS NOT NULL;")?,
+            (2, false)
+        );
+        assert_eq!(
+            scan_keys(&db, "SELECT * FROM t WHERE a > 1 AND a + b > 20;")?,
+            (1, true)
+        );
+        assert_eq!(
+            scan_keys(&db, "SELECT * FROM t WHERE a > 1 OR b = 10;")?,
+            (0, true)
+        );
+        assert_eq!(scan_keys(&db, "DELETE FROM t WHERE b = 30;")?, (1, false));
+
+        let rows = db.query("SELECT c FROM t WHERE a > 1 AND c IS NOT NULL;")?;
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows.get(0).unwrap().get::<_, String>(0)?, "z");
+
+        let rows = db.query("SELECT a, b FROM t WHERE b >= 20 AND a + b < 30;")?;
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows.get(0).unwrap().get::<_, i32>(1)?, 20);
+
+        assert_eq!(db.execute("DELETE FROM t WHERE b = 30;")?, 1);
+        assert_eq!(db.query("SELECT * FROM t;")?.len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_projection_pruning() -> Result<()> {
+        /// Return the attributes used of each relation scanned by the plan of the given query.
+        fn pruned(db: &Database, sql: &str) -> Result<Vec<Option<Vec<bool>>>> {
+            Ok(used_attrs(&create_plan(db, sql)?))
+        }
+
+        fn used_attrs(plan: &Plan) -> Vec<Option<Vec<bool>>> {
+            match &plan.node_type {
+                PlanNodeType::SeqScan { state } => vec![state.used_attrs.clone()],
+                PlanNodeType::Projection { state } => used_attrs(&state.child),
+                PlanNodeType::Filter { state } => used_attrs(&state.child),
+                PlanNodeType::Aggregate { state } => used_attrs(&state.child),
+                PlanNodeType::Delete { state } => used_attrs(&state.child),
+                PlanNodeType::NestedLoopJoin { state } => {
+                    let mut used = used_attrs(&state.outer);
+                    used.extend(used_attrs(&state.inner));
+                    used
+                }
+                _ => Vec::new(),
+            }
+        }
+        let mut db = Database::open_in_memory()?;
+        db.execute("CREATE TABLE t(a int, b int, c text);")?;
+        db.execute("CREATE TABLE u(a int, d text);")?;
+        db.execute("INSERT INTO t VALUES (1, 10, 'x'), (2, 20, 'y');")?;
+        db.execute("INSERT INTO u VALUES (1, 'one'), (2, 'two');")?;
+
+        assert_eq!(pruned(&db, "SELECT * FROM t;")?, vec![None]);
+        assert_eq!(pruned(&db, "SELECT c FROM t WHERE a + b > 1;")?, vec![None]);
+        assert_eq!(
+            pruned(&db, "SELECT a FROM t WHERE b + a > 1;")?,
+            vec![Some(vec![true, true, false])]
+        );
+
+        // Attributes of scan keys are decoded by the scan itself.
+        assert_eq!(
+            pruned(&db, "SELECT c FROM t WHERE a > 1;")?,
+            vec![Some(vec![false, false, true])]
+        );
+        assert_eq!(
+            pruned(&db, "SELECT count(*) FROM t;")?,
+            vec![Some(vec![false, false, false])]
+        );
+        assert_eq!(
+            pruned(&db, "DELETE FROM t WHERE a + b = 11;")?,
+            vec![Some(vec![true, true, false])]
+        );
+        assert_eq!(
+            pruned(&db, "SELECT u.d FROM t JOIN u ON t.a = u.a;")?,
+            vec![Some(vec![true, false, false]), None]
+        );
+
+        // Attributes that are not used are not decoded.
+        let rows = db.query("SELECT u.d, t.b FROM t JOIN u ON t.a = u.a WHERE u.a = 2;")?;
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows.get(0).unwrap().get::<_, String>(0)?, "two");
+        assert_eq!(rows.get(0).unwrap().get::<_, i32>(1)?, 20);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_analyze() -> Result<()> {
+        let mut db = Database::open_in_memory()?;
+        db.execute("CREATE TABLE t(a int, b int, c text);")?;
+        db.execute("CREATE INDEX t_a ON t(a); CREATE INDEX t_b ON t(b);")?;
+        let values = (0..2000)
+            .map(|i| format!("({}, {}, NULL)", i, i % 2))
+            .collect::<Vec<_>>();
+        db.execute(&format!("INSERT INTO t VALUES {};", values.join(", ")))?;
+
+        // Relations that were never analyzed are scanned sequentially.
+        let equal_a = "SELECT * FROM t WHERE a = 42;";
+        assert_eq!(scan_node_type(&db, equal_a)?, "SeqScan");
+
+        assert_eq!(db.execute("ANALYZE t;")?, 0);
+        let rows = db.query("SELECT staattnum, stareltuples, stadistinct FROM pg_statistic;")?;
+        let statistics = (0..rows.len())
+            .map(|i| {
+                let row = rows.get(i).unwrap();
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })
+            .collect::<Result<Vec<(i64, i64, i64)>>>()?;
+        assert_eq!(
+            statistics,
+            vec![(1, 2000, 2000), (2, 2000, 2), (3, 2000, 0)]
+        );
+
+        // The index is only used for selective conditions.
+        assert_eq!(scan_node_type(&db, equal_a)?, "IndexScan");
+        assert_eq!(
+            scan_node_type(&db, "SELECT * FROM t WHERE b = 1;")?,
+            "SeqScan"
+        );
+        assert_eq!(
+            scan_node_type(&db, "SELECT * FROM t WHERE a = 1.5;")?,
+            "SeqScan"
+        );
+        assert_eq!(
+            scan_node_type(&db, "SELECT * FROM t WHERE b = 1 AND a = 43;")?,
+            "IndexScan"
+        );
+        assert_eq!(
+            scan_node_type(&db, "DELETE FROM t WHERE a = 42;")?,
+            "IndexScan"
+        );
+
+        let rows = db.query(equal_a)?;
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows.get(0).unwrap().get::<_, i32>(1)?, 0);
+        assert_eq!(db.execute("SELECT * FROM t WHERE b = 1 AND a = 43;")?, 1);
+        assert_eq!(db.execute("SELECT * FROM t WHERE b = 1 AND a = 42;")?, 0);
+        assert_eq!(db.execute("SELECT * FROM t WHERE a = 1.5;")?, 0);
+
+        // Deleted tuples are skipped by index scans.
+        assert_eq!(db.execute("DELETE FROM t WHERE a = 42;")?, 1);
+        assert_eq!(db.execute(equal_a)?, 0);
+        assert_eq!(db.execute("SELECT * FROM t;")?, 1999);
+
+        // Without a table name all tables are analyzed.
+        db.execute("CREATE TABLE empty(a int);")?;
+        assert_eq!(db.execute("ANALYZE;")?, 0);
+        assert_eq!(
+            db.query("SELECT stareltuples FROM pg_statistic WHERE staattnum = 1;")?
+                .len(),
+            2
+        );
+
+        let mut err = |sql: &str| PgError::from_error(&db.execute(sql).unwrap_err());
+        assert_eq!(err("ANALYZE t_a;").code, SqlState::WrongObjectType);
+        assert_eq!(err("ANALYZE missing;").code, SqlState::UndefinedTable);
+
+        // Statistics are removed with the table.
+        db.execute("DROP TABLE t, empty;")?;
+        assert_eq!(db.execute("SELECT * FROM pg_statistic;")?, 0);
+
+        Ok(())
+    }
+}
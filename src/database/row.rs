@@ -0,0 +1,268 @@
+use anyhow::{bail, Result};
+
+use crate::{
+    catalog::pg_type::{self, type_name},
+    executor::ExecutionStats,
+    sql::{encode::decode, PGResult, RowDescriptor},
+    NullableDatum, Oid,
+};
+
+use super::Error;
+
+/// Rows returned by a query.
+#[derive(Debug)]
+pub struct Rows {
+    result: PGResult,
+}
+
+impl Rows {
+    /// Create the rows of the given query result.
+    pub fn new(result: PGResult) -> Self {
+        Self { result }
+    }
+
+    /// Return the names of the columns.
+    pub fn columns(&self) -> Vec<String> {
+        self.result
+            .desc
+            .fields
+            .iter()
+            .map(|field| String::from_utf8_lossy(&field.name).to_string())
+            .collect()
+    }
+
+    /// Return the type oids of the columns.
+    pub fn column_types(&self) -> Vec<Oid> {
+        self.result
+            .desc
+            .fields
+            .iter()
+            .map(|field| field.data_type_oid as Oid)
+            .collect()
+    }
+
+    /// Return the statistics of executing the query.
+    pub fn stats(&self) -> ExecutionStats {
+        self.result.stats
+    }
+
+    /// Return the row at the given position, if any.
+    pub fn get(&self, idx: usize) -> Option<Row<'_>> {
+        self.result.tuples.get(idx).map(|values| Row {
+            desc: &self.result.desc,
+            values,
+        })
+    }
+
+    /// Return an iterator over all rows.
+    pub fn iter(&self) -> impl Iterator<Item = Row<'_>> {
+        self.result.tuples.iter().map(move |values| Row {
+            desc: &self.result.desc,
+            values,
+        })
+    }
+
+    /// Return the raw values of all rows.
+    pub fn values(&self) -> &[Vec<NullableDatum>] {
+        &self.result.tuples
+    }
+
+    /// Return the number of rows.
+    pub fn len(&self) -> usize {
+        self.result.tuples.len()
+    }
+
+    /// Return true if the query returned no rows.
+    pub fn is_empty(&self) -> bool {
+        self.result.tuples.is_empty()
+    }
+}
+
+/// A single row returned by a query.
+#[derive(Debug, Clone, Copy)]
+pub struct Row<'a> {
+    desc: &'a RowDescriptor,
+    values: &'a [NullableDatum],
+}
+
+impl<'a> Row<'a> {
+    /// Return the value of the given column, by position or name, converted to the requested
+    /// type. An error is returned if the column type can not be converted to the requested type.
+    pub fn get<I: RowIndex, T: FromValue>(&self, idx: I) -> Result<T> {
+        let idx = idx.index(self.desc)?;
+        let field = &self.desc.fields[idx];
+        let column = String::from_utf8_lossy(&field.name);
+        T::from_value(&self.values[idx], field.data_type_oid as Oid).map_err(|err| {
+            match err.downcast::<Error>() {
+                Ok(Error::UnexpectedNull(_)) => Error::UnexpectedNull(column.to_string()).into(),
+                Ok(Error::InvalidType { typ, target, .. }) => Error::InvalidType {
+                    column: column.to_string(),
+                    typ,
+                    target,
+                }
+                .into(),
+                Ok(err) => err.into(),
+                Err(err) => err,
+            }
+        })
+    }
+
+    /// Return the raw values of the row.
+    pub fn values(&self) -> &'a [NullableDatum] {
+        self.values
+    }
+
+    /// Return the number of columns.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Return true if the row has no columns.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+/// A column of a row, by position or by name.
+pub trait RowIndex {
+    /// Return the position of the column on the given row description.
+    fn index(&self, desc: &RowDescriptor) -> Result<usize>;
+}
+
+impl RowIndex for usize {
+    fn index(&self, desc: &RowDescriptor) -> Result<usize> {
+        if *self >= desc.fields.len() {
+            bail!(Error::ColumnIndexOutOfRange(*self));
+        }
+        Ok(*self)
+    }
+}
+
+impl RowIndex for &str {
+    fn index(&self, desc: &RowDescriptor) -> Result<usize> {
+        desc.fields
+            .iter()
+            .position(|field| field.name == self.as_bytes())
+            .ok_or_else(|| Error::ColumnNotFound(self.to_string()).into())
+    }
+}
+
+/// A type that can be converted from a value returned by a query.
+pub trait FromValue: Sized {
+    /// Convert the given value of the given type.
+    fn from_value(value: &NullableDatum, typ: Oid) -> Result<Self>;
+}
+
+/// Return the error of converting a value of the given type to the given target type. The
+/// column name is filled by Row::get.
+fn invalid_type(typ: Oid, target: &'static str) -> anyhow::Error {
+    Error::InvalidType {
+        column: String::new(),
+        typ: type_name(typ),
+        target,
+    }
+    .into()
+}
+
+/// Implement FromValue for a numeric type using the given conversions of each accepted SQL type.
+macro_rules! from_value {
+    ($target:ty, $($typ:path => $source:ty),+) => {
+        impl FromValue for $target {
+            fn from_value(value: &NullableDatum, typ: Oid) -> Result<Self> {
+                let datum = value
+                    .as_ref()
+                    .ok_or_else(|| Error::UnexpectedNull(String::new()))?;
+                match typ {
+                    $($typ => Ok(bincode::deserialize::<$source>(datum)? as $target),)+
+                    _ => Err(invalid_type(typ, stringify!($target))),
+                }
+            }
+        }
+    };
+}
+
+from_value!(i16, pg_type::INT2_OID => i16);
+// Dates are converted to days and timestamps to microseconds since 2000-01-01.
+from_value!(
+    i32,
+    pg_type::INT2_OID => i16,
+    pg_type::INT_OID => i32,
+    pg_type::DATE_OID => i32
+);
+from_value!(
+    i64,
+    pg_type::INT2_OID => i16,
+    pg_type::INT_OID => i32,
+    pg_type::INT8_OID => i64,
+    pg_type::TIMESTAMP_OID => i64
+);
+from_value!(f32, pg_type::FLOAT4_OID => f32);
+from_value!(f64, pg_type::FLOAT4_OID => f32, pg_type::FLOAT8_OID => f64);
+
+impl FromValue for bool {
+    fn from_value(value: &NullableDatum, typ: Oid) -> Result<Self> {
+        let datum = value
+            .as_ref()
+            .ok_or_else(|| Error::UnexpectedNull(String::new()))?;
+        match typ {
+            pg_type::BOOL_OID => Ok(bincode::deserialize(datum)?),
+            _ => Err(invalid_type(typ, "bool")),
+        }
+    }
+}
+
+/// Values of any type can be converted to their text representation.
+impl FromValue for String {
+    fn from_value(value: &NullableDatum, typ: Oid) -> Result<Self> {
+        let datum = value
+            .as_ref()
+            .ok_or_else(|| Error::UnexpectedNull(String::new()))?;
+        decode(datum, typ)
+    }
+}
+
+/// Null values are converted to None.
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: &NullableDatum, typ: Oid) -> Result<Self> {
+        match value {
+            Some(_) => Ok(Some(T::from_value(value, typ)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::Database;
+
+    #[test]
+    fn test_row_get() -> Result<()> {
+        let mut db = Database::open_in_memory()?;
+        db.execute("CREATE TABLE t(a int, b text, c boolean, d float8, e bigint);")?;
+        db.execute("INSERT INTO t VALUES (42, 'tinydb', true, 1.5, NULL);")?;
+
+        let rows = db.query("SELECT * FROM t;")?;
+        let row = rows.get(0).unwrap();
+        assert_eq!(row.get::<_, i32>(0)?, 42);
+        assert_eq!(row.get::<_, i64>("a")?, 42);
+        assert_eq!(row.get::<_, String>("a")?, "42");
+        assert_eq!(row.get::<_, String>(1)?, "tinydb");
+        assert!(row.get::<_, bool>("c")?);
+        assert_eq!(row.get::<_, f64>("d")?, 1.5);
+        assert_eq!(row.get::<_, Option<i64>>("e")?, None);
+        assert_eq!(rows.iter().count(), 1);
+        assert_eq!(rows.stats().rows, 1);
+
+        let err = |result: Result<i32>| result.unwrap_err().downcast::<Error>().unwrap();
+        assert!(matches!(err(row.get(5)), Error::ColumnIndexOutOfRange(5)));
+        assert!(matches!(err(row.get("z")), Error::ColumnNotFound(_)));
+        assert!(matches!(err(row.get("e")), Error::UnexpectedNull(column) if column == "e"));
+        assert!(matches!(
+            err(row.get("b")),
+            Error::InvalidType { column, typ: "text", target: "i32" } if column == "b"
+        ));
+
+        Ok(())
+    }
+}
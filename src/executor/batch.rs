@@ -0,0 +1,106 @@
+use crate::{storage::page::ItemPointer, NullableDatum};
+
+use super::TupleTableSlot;
+
+/// Maximum number of tuples of a batch.
+pub const BATCH_SIZE: usize = 1024;
+
+/// A batch of tuples that flows between the plan nodes, stored by attribute so expressions can
+/// be evaluated over all values of an attribute at once.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TupleBatch {
+    /// Values of each attribute, on the same order of the tuple descriptor from the plan node
+    /// that produced the batch. All columns have one value per tuple.
+    pub columns: Vec<Vec<NullableDatum>>,
+
+    /// Physical location of each tuple, None for tuples that were not fetched from a relation.
+    pub tids: Vec<Option<ItemPointer>>,
+}
+
+impl TupleBatch {
+    /// Create an empty batch of tuples with the given number of attributes.
+    pub fn new(natts: usize) -> Self {
+        Self {
+            columns: vec![Vec::with_capacity(BATCH_SIZE); natts],
+            tids: Vec::with_capacity(BATCH_SIZE),
+        }
+    }
+
+    /// Return the number of tuples of the batch.
+    pub fn len(&self) -> usize {
+        self.tids.len()
+    }
+
+    /// Return true if the batch has no tuples.
+    pub fn is_empty(&self) -> bool {
+        self.tids.is_empty()
+    }
+
+    /// Return true if the batch already has BATCH_SIZE tuples.
+    pub fn is_full(&self) -> bool {
+        self.len() >= BATCH_SIZE
+    }
+
+    /// Append the tuple of the given slot to the batch. The attributes of the first tuple define
+    /// the number of columns of an empty batch created by default.
+    pub fn push(&mut self, slot: TupleTableSlot) {
+        if self.is_empty() && self.columns.len() != slot.values.len() {
+            self.columns = vec![Vec::with_capacity(BATCH_SIZE); slot.values.len()];
+        }
+        for (column, value) in self.columns.iter_mut().zip(slot.values) {
+            column.push(value);
+        }
+        self.tids.push(slot.tid);
+    }
+
+    /// Return a copy of the values of the tuple at the given position.
+    pub fn row(&self, index: usize) -> Vec<NullableDatum> {
+        self.columns
+            .iter()
+            .map(|column| column[index].clone())
+            .collect()
+    }
+
+    /// Keep only the tuples whose position is selected on the given vector.
+    pub fn retain(&mut self, selected: &[bool]) {
+        for column in self.columns.iter_mut() {
+            let mut selection = selected.iter();
+            column.retain(|_| *selection.next().unwrap_or(&false));
+        }
+        let mut selection = selected.iter();
+        self.tids.retain(|_| *selection.next().unwrap_or(&false));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Datum;
+
+    fn slot(value: u8) -> TupleTableSlot {
+        TupleTableSlot {
+            tid: None,
+            values: vec![Some(Datum::from(vec![value])), None],
+        }
+    }
+
+    #[test]
+    fn test_tuple_batch() {
+        let mut batch = TupleBatch::default();
+        for value in 0..4 {
+            batch.push(slot(value));
+        }
+        assert_eq!(batch.len(), 4);
+        assert_eq!(batch.columns.len(), 2);
+        assert_eq!(batch.row(2), vec![Some(Datum::from(vec![2])), None]);
+
+        batch.retain(&[true, false, false, true]);
+        assert_eq!(batch.len(), 2);
+        assert_eq!(
+            batch.columns[0],
+            vec![Some(Datum::from(vec![0])), Some(Datum::from(vec![3]))]
+        );
+        assert_eq!(batch.columns[1], vec![None, None]);
+        assert!(!batch.is_full());
+    }
+}
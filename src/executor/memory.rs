@@ -0,0 +1,278 @@
+//! Memory accounting of the plan nodes of a query.
+//!
+//! Plan nodes that keep tuples in memory, like sorts and subqueries, reserve the memory used by
+//! their tuples on the memory context of the query, which is limited by work_mem. Sorts write
+//! the tuples that don't fit on the reserved memory to temporary files, and the other nodes fail
+//! with an out of memory error, so a large query fails alone instead of exhausting the memory of
+//! the server process.
+//!
+//! The values of the tuples decoded by a query are allocated on a DatumArena, which copies many
+//! small values to the same large chunk of memory instead of allocating each value separately.
+//! The values are returned to the client, so the memory of the arena is only measured, not
+//! limited.
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use anyhow::{bail, Result};
+use bytes::BytesMut;
+
+use crate::{
+    sql::error::{PgError, SqlState},
+    Datum,
+};
+
+/// Size of the chunks of memory allocated by a DatumArena.
+const ARENA_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Values larger than this size are allocated separately by a DatumArena, so a large value don't
+/// waste the free space of the current chunk.
+const ARENA_MAX_VALUE_SIZE: usize = ARENA_CHUNK_SIZE / 8;
+
+/// Memory used by the plan nodes of a single query. Cloned contexts share the same memory.
+#[derive(Debug, Clone)]
+pub struct MemoryContext {
+    inner: Arc<MemoryContextData>,
+}
+
+#[derive(Debug)]
+struct MemoryContextData {
+    /// Maximum amount of memory, in bytes, that can be reserved at the same time.
+    limit: usize,
+
+    /// Amount of memory currently reserved.
+    used: AtomicUsize,
+
+    /// Maximum amount of memory reserved since the context was created.
+    peak: AtomicUsize,
+
+    /// Amount of memory allocated by the datum arenas of the context, which is not limited.
+    allocated: AtomicUsize,
+}
+
+impl MemoryContext {
+    /// Create a new memory context where at most the given amount of bytes can be reserved.
+    pub fn new(limit: usize) -> Self {
+        Self {
+            inner: Arc::new(MemoryContextData {
+                limit,
+                used: AtomicUsize::new(0),
+                peak: AtomicUsize::new(0),
+                allocated: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    /// Return the maximum amount of memory that can be reserved, in bytes.
+    pub fn limit(&self) -> usize {
+        self.inner.limit
+    }
+
+    /// Return the amount of memory currently reserved, in bytes.
+    pub fn used(&self) -> usize {
+        self.inner.used.load(Ordering::SeqCst)
+    }
+
+    /// Return the maximum amount of memory reserved at the same time, in bytes.
+    pub fn peak(&self) -> usize {
+        self.inner.peak.load(Ordering::SeqCst)
+    }
+
+    /// Return the amount of memory allocated for the values of tuples by the datum arenas of
+    /// this context, in bytes.
+    pub fn allocated(&self) -> usize {
+        self.inner.allocated.load(Ordering::SeqCst)
+    }
+
+    /// Return a new empty reservation of memory of this context.
+    pub fn reservation(&self) -> MemoryReservation {
+        MemoryReservation {
+            context: self.clone(),
+            size: 0,
+        }
+    }
+
+    /// Reserve the given amount of bytes. Return false if the limit would be exceeded.
+    fn try_reserve(&self, bytes: usize) -> bool {
+        let inner = &self.inner;
+        let reserved = inner
+            .used
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |used| {
+                used.checked_add(bytes).filter(|used| *used <= inner.limit)
+            });
+        match reserved {
+            Ok(used) => {
+                inner.peak.fetch_max(used + bytes, Ordering::SeqCst);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    fn release(&self, bytes: usize) {
+        self.inner.used.fetch_sub(bytes, Ordering::SeqCst);
+    }
+}
+
+/// Memory reserved by a plan node on a memory context. The memory is released when the
+/// reservation is dropped.
+#[derive(Debug)]
+pub struct MemoryReservation {
+    context: MemoryContext,
+
+    /// Amount of memory reserved, in bytes.
+    size: usize,
+}
+
+impl MemoryReservation {
+    /// Return the amount of memory reserved, in bytes.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Reserve more bytes. Return false if the limit of the memory context would be exceeded,
+    /// in which case the reservation is not changed.
+    pub fn try_grow(&mut self, bytes: usize) -> bool {
+        if !self.context.try_reserve(bytes) {
+            return false;
+        }
+        self.size += bytes;
+        true
+    }
+
+    /// Reserve more bytes, returning an out of memory error if the limit of the memory context
+    /// would be exceeded.
+    pub fn grow(&mut self, bytes: usize) -> Result<()> {
+        if !self.try_grow(bytes) {
+            let detail = format!(
+                "Failed on request of size {} bytes, {} of {} bytes of work_mem are in use.",
+                bytes,
+                self.context.used(),
+                self.context.limit()
+            );
+            bail!(
+                PgError::new(SqlState::OutOfMemory, "out of memory for query")
+                    .with_detail(detail)
+                    .with_hint("Increase work_mem or reduce the number of rows of the query.")
+            );
+        }
+        Ok(())
+    }
+
+    /// Release all memory of the reservation.
+    pub fn free(&mut self) {
+        self.context.release(self.size);
+        self.size = 0;
+    }
+}
+
+impl Drop for MemoryReservation {
+    fn drop(&mut self) {
+        self.free();
+    }
+}
+
+/// Allocator of the values of tuples decoded by a query. Values are copied to chunks of memory
+/// shared by many values, and each chunk is released when all of its values are dropped.
+#[derive(Debug)]
+pub struct DatumArena {
+    context: MemoryContext,
+
+    /// Free space of the current chunk.
+    chunk: BytesMut,
+}
+
+impl DatumArena {
+    /// Create a new arena counting its allocations on the given memory context.
+    pub fn new(context: &MemoryContext) -> Self {
+        Self {
+            context: context.clone(),
+            chunk: BytesMut::new(),
+        }
+    }
+
+    /// Return a new datum with a copy of the given value.
+    pub fn alloc(&mut self, value: &[u8]) -> Datum {
+        if value.len() > ARENA_MAX_VALUE_SIZE {
+            self.record_allocation(value.len());
+            return Datum::from(value.to_vec());
+        }
+
+        if self.chunk.capacity() < value.len() {
+            self.chunk = BytesMut::with_capacity(ARENA_CHUNK_SIZE);
+            self.record_allocation(ARENA_CHUNK_SIZE);
+        }
+        self.chunk.extend_from_slice(value);
+        Datum::from(self.chunk.split().freeze())
+    }
+
+    fn record_allocation(&self, bytes: usize) {
+        self.context
+            .inner
+            .allocated
+            .fetch_add(bytes, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_context() -> Result<()> {
+        let context = MemoryContext::new(100);
+        let mut sort = context.reservation();
+        let mut subquery = context.reservation();
+
+        assert!(sort.try_grow(60));
+        assert!(!subquery.try_grow(50));
+        subquery.grow(40)?;
+        assert_eq!(context.used(), 100);
+
+        // Failed reservations don't change the memory in use.
+        let err = PgError::from_error(&sort.grow(1).unwrap_err());
+        assert_eq!(err.code, SqlState::OutOfMemory);
+        assert_eq!(err.message, "out of memory for query");
+        assert_eq!(sort.size(), 60);
+
+        sort.free();
+        assert_eq!(context.used(), 40);
+        subquery.grow(50)?;
+        drop(subquery);
+        assert_eq!(context.used(), 0);
+        assert_eq!(context.peak(), 100);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_datum_arena() {
+        let context = MemoryContext::new(0);
+        let mut arena = DatumArena::new(&context);
+
+        // Small values share the same chunk.
+        let values = (0..1000u32)
+            .map(|i| arena.alloc(&i.to_le_bytes()))
+            .collect::<Vec<_>>();
+        assert_eq!(context.allocated(), ARENA_CHUNK_SIZE);
+        for (i, value) in values.iter().enumerate() {
+            assert_eq!(&value[..], &(i as u32).to_le_bytes());
+        }
+
+        // Values that don't fit on the current chunk are allocated on a new chunk.
+        let fill = vec![1; ARENA_MAX_VALUE_SIZE];
+        while arena.chunk.capacity() >= fill.len() {
+            arena.alloc(&fill);
+        }
+        assert_eq!(&arena.alloc(&fill)[..], &fill[..]);
+        assert_eq!(context.allocated(), 2 * ARENA_CHUNK_SIZE);
+
+        // Large values are allocated separately.
+        let large = vec![2; ARENA_MAX_VALUE_SIZE + 1];
+        assert_eq!(&arena.alloc(&large)[..], &large[..]);
+        assert_eq!(context.allocated(), 2 * ARENA_CHUNK_SIZE + large.len());
+        assert_eq!(context.used(), 0);
+    }
+}
@@ -1,26 +1,130 @@
-use std::sync::Arc;
+use std::{
+    cell::RefCell,
+    mem,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 
 use anyhow::{bail, Result};
 
 use crate::{
-    access::heaptuple::{HeapTuple, TupleDesc},
-    planner::{Plan, PlanNodeType},
+    access::{
+        btree::btree_search,
+        heaptuple::{HeapTuple, TupleDesc},
+    },
+    catalog::virtual_table::ScanContext,
+    expr::Expr,
+    planner::{Plan, PlanNodeType, ScanKey},
+    sql::{
+        error::{PgError, SqlState},
+        guc::{SessionVariables, DEFAULT_WORK_MEM},
+    },
+    storage::{page::ItemPointer, BufferPool},
     NullableDatum,
 };
 
+use self::{
+    batch::TupleBatch,
+    memory::{DatumArena, MemoryContext, MemoryReservation},
+    sort::Tuplesort,
+};
+
+pub mod batch;
+pub mod memory;
+pub mod sort;
+
+/// Statistics of executing a plan.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ExecutionStats {
+    /// Number of rows returned by the plan.
+    pub rows: u64,
+
+    /// Time spent executing the plan, without planning it.
+    pub elapsed: Duration,
+
+    /// Memory used by the plan, in bytes: the peak memory reserved by plan nodes plus the memory
+    /// allocated for the values of tuples.
+    pub memory: usize,
+}
+
 /// A plan tree executor. Contains function to execute each type of PlanNodeType.
-pub struct Executor;
+pub struct Executor {
+    /// Buffer pool used by plan nodes that modify relations.
+    buffer_pool: BufferPool,
+
+    /// Flag checked between tuples to interrupt the execution. None if the execution can not be
+    /// canceled.
+    canceled: Option<Arc<AtomicBool>>,
+
+    /// Configuration variables of the session running the plan, used to generate the tuples of
+    /// virtual relations.
+    variables: SessionVariables,
+
+    /// Memory used by the plan nodes, limited by the work_mem of the session.
+    memory: MemoryContext,
+
+    /// Arena of the values kept until the end of the query, like the values of the tuple table.
+    arena: RefCell<DatumArena>,
+}
 
 impl Executor {
     /// Create a new executor using the given buffer pool to fetch page buffers.
-    pub fn new() -> Self {
-        Self {}
+    pub fn new(buffer_pool: &BufferPool) -> Self {
+        let memory = MemoryContext::new(DEFAULT_WORK_MEM);
+        Self {
+            buffer_pool: buffer_pool.clone(),
+            canceled: None,
+            variables: SessionVariables::default(),
+            arena: RefCell::new(DatumArena::new(&memory)),
+            memory,
+        }
+    }
+
+    /// Use the given session configuration variables to generate the tuples of virtual
+    /// relations, and to limit the memory used by plan nodes to work_mem.
+    pub fn with_session_variables(mut self, variables: &SessionVariables) -> Self {
+        self.variables = variables.clone();
+        // Invalid values are rejected when the variable is set.
+        self.memory = MemoryContext::new(variables.work_mem().unwrap_or(DEFAULT_WORK_MEM));
+        self.arena = RefCell::new(DatumArena::new(&self.memory));
+        self
+    }
+
+    /// Interrupt the execution with an error when the given flag is set.
+    pub fn with_cancel_flag(mut self, canceled: Arc<AtomicBool>) -> Self {
+        self.canceled = Some(canceled);
+        self
+    }
+
+    /// Execute the given plan like exec, also returning the statistics of the execution.
+    pub fn exec_with_stats(&self, node: &mut Plan) -> Result<(TupleTable, ExecutionStats)> {
+        let start = Instant::now();
+        let tuple_table = self.exec(node)?;
+        let stats = ExecutionStats {
+            rows: tuple_table.values.len() as u64,
+            elapsed: start.elapsed(),
+            memory: self.memory.peak() + self.memory.allocated(),
+        };
+        Ok((tuple_table, stats))
     }
 
     /// Main entrypoint of a planner executor, it recursivily exec all nodes
     /// for the planer and return a tuple table result with all operations
     /// of the planner performed.
     pub fn exec(&self, node: &mut Plan) -> Result<TupleTable> {
+        self.exec_node(node, None)
+    }
+
+    /// Execute the given plan like exec, reserving the memory used by the tuples of the
+    /// returned tuple table on the given reservation.
+    fn exec_node(
+        &self,
+        node: &mut Plan,
+        mut reservation: Option<&mut MemoryReservation>,
+    ) -> Result<TupleTable> {
         match &mut node.node_type {
             PlanNodeType::Projection { state } => {
                 let mut tuple_table = TupleTable {
@@ -30,23 +134,55 @@ impl Executor {
                     values: Vec::new(),
                 };
 
-                loop {
-                    match self.fetch_next_tuple(&mut state.child)? {
-                        Some(tuple) => {
-                            let mut slot = Vec::new();
+                while let Some(batch) = self.fetch_next_batch(&mut state.child)? {
+                    let rows = self.project_batch(&state.exprs, &batch)?;
+                    if let Some(reservation) = reservation.as_deref_mut() {
+                        reservation.grow(values_memory(&rows))?;
+                    }
+                    tuple_table.values.extend(rows);
+                }
 
-                            for attr in &tuple_table.tuple_desc.attrs {
-                                // Use the tuple descriptor from projection state since
-                                // it is in the same order that is stored on disk page.
-                                let datum = tuple.get_attr(attr.attnum, &state.tuple_desc)?;
-                                slot.push(datum);
-                            }
-                            tuple_table.values.push(slot);
-                        }
-                        None => break,
+                Ok(tuple_table)
+            }
+
+            PlanNodeType::Aggregate { state } => {
+                state.finished = true;
+                let mut accumulators = state
+                    .aggregates
+                    .iter()
+                    .map(|aggregate| aggregate.accumulator())
+                    .collect::<Vec<_>>();
+
+                while let Some(batch) = self.fetch_next_batch(&mut state.child)? {
+                    for (accumulator, aggregate) in accumulators.iter_mut().zip(&state.aggregates) {
+                        accumulator.accumulate_batch(aggregate, &batch)?;
                     }
                 }
 
+                // Aggregates without GROUP BY always return a single row, even if the
+                // child plan don't return any tuple.
+                let mut slot = Vec::with_capacity(accumulators.len());
+                for accumulator in accumulators {
+                    slot.push(accumulator.finish().to_datum()?);
+                }
+
+                Ok(TupleTable {
+                    tuple_desc: Arc::new(TupleDesc {
+                        attrs: state.output.clone(),
+                    }),
+                    values: vec![slot],
+                })
+            }
+
+            PlanNodeType::Limit { state } => {
+                let mut tuple_table = self.exec_node(&mut state.child, reservation)?;
+                tuple_table.values = tuple_table
+                    .values
+                    .into_iter()
+                    .skip(state.offset as usize)
+                    .take(state.limit.map_or(usize::MAX, |limit| limit as usize))
+                    .collect();
+
                 Ok(tuple_table)
             }
 
@@ -54,9 +190,314 @@ impl Executor {
         }
     }
 
-    fn fetch_next_tuple(&self, node: &mut Plan) -> Result<Option<HeapTuple>> {
+    /// Execute the given plan incrementally, returning its next rows instead of all of them like
+    /// exec. Return None when all rows were returned.
+    ///
+    /// Used to fetch the rows of cursors on demand, so the relations are only scanned up to the
+    /// last fetched row.
+    pub fn exec_next(&self, node: &mut Plan) -> Result<Option<Vec<Vec<NullableDatum>>>> {
+        match &mut node.node_type {
+            PlanNodeType::Projection { state } => match self.fetch_next_batch(&mut state.child)? {
+                Some(batch) => Ok(Some(self.project_batch(&state.exprs, &batch)?)),
+                None => Ok(None),
+            },
+
+            // The single row of aggregates is computed on the first fetch.
+            PlanNodeType::Aggregate { state } if state.finished => Ok(None),
+            PlanNodeType::Aggregate { .. } => Ok(Some(self.exec(node)?.values)),
+
+            PlanNodeType::Limit { state } => loop {
+                if let Some(limit) = state.limit {
+                    if state.returned >= limit {
+                        return Ok(None);
+                    }
+                }
+                let mut rows = match self.exec_next(&mut state.child)? {
+                    Some(rows) => rows,
+                    None => return Ok(None),
+                };
+
+                let skipped = rows.len().min(state.offset as usize);
+                rows.drain(..skipped);
+                state.offset -= skipped as u64;
+                if let Some(limit) = state.limit {
+                    rows.truncate((limit - state.returned) as usize);
+                }
+                state.returned += rows.len() as u64;
+                if !rows.is_empty() {
+                    return Ok(Some(rows));
+                }
+            },
+
+            _ => bail!("Unexpected root plan node of type {}", node.node_type),
+        }
+    }
+
+    /// Evaluate the given projection expressions against each tuple of the given batch and
+    /// return the values of each projected row.
+    fn project_batch(&self, exprs: &[Expr], batch: &TupleBatch) -> Result<Vec<Vec<NullableDatum>>> {
+        let mut columns = Vec::with_capacity(exprs.len());
+        for expr in exprs {
+            match expr {
+                // Column values are copied without being decoded.
+                Expr::Column { index, .. } => match batch.columns.get(*index) {
+                    Some(column) => columns.push(self.materialize(column)),
+                    None => columns.push(vec![None; batch.len()]),
+                },
+                _ => columns.push(
+                    expr.eval_batch(batch)?
+                        .iter()
+                        .map(|value| value.to_datum())
+                        .collect::<Result<Vec<_>>>()?,
+                ),
+            }
+        }
+
+        // Rows store the values of each tuple together.
+        let mut columns = columns
+            .into_iter()
+            .map(|column| column.into_iter())
+            .collect::<Vec<_>>();
+        Ok((0..batch.len())
+            .map(|_| {
+                columns
+                    .iter_mut()
+                    .map(|column| column.next().unwrap_or_default())
+                    .collect()
+            })
+            .collect())
+    }
+
+    /// Execute a delete plan and return the number of tuples deleted.
+    pub fn exec_delete(&self, node: &mut Plan) -> Result<u64> {
+        match &mut node.node_type {
+            PlanNodeType::Delete { state } => {
+                let mut deleted = 0;
+
+                while let Some(batch) = self.fetch_next_batch(&mut state.child)? {
+                    for tid in batch.tids {
+                        let tid = match tid {
+                            Some(tid) => tid,
+                            None => bail!("Can not delete a tuple without a physical location"),
+                        };
+                        state
+                            .table_am
+                            .delete(&self.buffer_pool, &state.relation, &tid)?;
+                        deleted += 1;
+                    }
+                }
+
+                Ok(deleted)
+            }
+            _ => bail!("Unexpected root plan node of type {}", node.node_type),
+        }
+    }
+
+    /// Fetch the next batch of tuples of the given plan node. Return None when all tuples were
+    /// returned, returned batches always have at least one tuple.
+    ///
+    /// Plan nodes that do not produce batches yet are adapted by collecting their tuples.
+    fn fetch_next_batch(&self, node: &mut Plan) -> Result<Option<TupleBatch>> {
+        match &mut node.node_type {
+            PlanNodeType::SeqScan { ref mut state } => {
+                self.check_canceled()?;
+                let mut batch = TupleBatch::new(state.tuple_desc.attrs.len());
+                while !batch.is_full() {
+                    match state.scan.next_tuple()? {
+                        Some(tuple) => {
+                            let slot = heap_tuple_slot(
+                                &tuple,
+                                &state.tuple_desc,
+                                &state.used_attrs,
+                                &state.scan_keys,
+                            )?;
+                            if let Some(slot) = slot {
+                                batch.push(slot);
+                            }
+                        }
+                        None => break,
+                    }
+                }
+                Ok(Some(batch).filter(|batch| !batch.is_empty()))
+            }
+            PlanNodeType::Filter { ref mut state } => {
+                while let Some(mut batch) = self.fetch_next_batch(&mut state.child)? {
+                    let selected = state
+                        .qual
+                        .eval_batch(&batch)?
+                        .iter()
+                        .map(|value| value.is_true())
+                        .collect::<Vec<_>>();
+                    batch.retain(&selected);
+                    if !batch.is_empty() {
+                        return Ok(Some(batch));
+                    }
+                }
+                Ok(None)
+            }
+            _ => {
+                let mut batch = TupleBatch::default();
+                while !batch.is_full() {
+                    match self.fetch_next_tuple(node)? {
+                        Some(tuple) => batch.push(tuple),
+                        None => break,
+                    }
+                }
+                Ok(Some(batch).filter(|batch| !batch.is_empty()))
+            }
+        }
+    }
+
+    fn fetch_next_tuple(&self, node: &mut Plan) -> Result<Option<TupleTableSlot>> {
         match &mut node.node_type {
-            PlanNodeType::SeqScan { ref mut state } => state.heap_scanner.next_tuple(),
+            PlanNodeType::SeqScan { ref mut state } => {
+                self.check_canceled()?;
+                while let Some(tuple) = state.scan.next_tuple()? {
+                    let slot = heap_tuple_slot(
+                        &tuple,
+                        &state.tuple_desc,
+                        &state.used_attrs,
+                        &state.scan_keys,
+                    )?;
+                    if slot.is_some() {
+                        return Ok(slot);
+                    }
+                }
+                Ok(None)
+            }
+            PlanNodeType::IndexScan { ref mut state } => {
+                self.check_canceled()?;
+                if state.tids.is_none() {
+                    let tids = btree_search(&self.buffer_pool, &state.index, &state.key)?;
+                    state.tids = Some(tids.into_iter());
+                }
+
+                // Deleted tuples are still referenced by the index, so locations without a live
+                // tuple are skipped.
+                while let Some(tid) = state.tids.as_mut().and_then(|tids| tids.next()) {
+                    if let Some(tuple) =
+                        state
+                            .table_am
+                            .fetch(&self.buffer_pool, &state.relation, &tid)?
+                    {
+                        return heap_tuple_slot(&tuple, &state.tuple_desc, &state.used_attrs, &[]);
+                    }
+                }
+                Ok(None)
+            }
+            PlanNodeType::VirtualScan { ref mut state } => {
+                self.check_canceled()?;
+                if state.tuples.is_none() {
+                    let ctx = ScanContext {
+                        buffer_pool: &self.buffer_pool,
+                        variables: &self.variables,
+                    };
+                    state.tuples = Some(state.table.scan(&ctx)?.into_iter());
+                }
+                Ok(state
+                    .tuples
+                    .as_mut()
+                    .and_then(|tuples| tuples.next())
+                    .map(|values| TupleTableSlot { tid: None, values }))
+            }
+            PlanNodeType::SubqueryScan { ref mut state } => {
+                self.check_canceled()?;
+                if state.tuples.is_none() {
+                    // The subquery tuples are kept in memory until the query is finished.
+                    let mut reservation = self.memory.reservation();
+                    let tuple_table = self.exec_node(&mut state.child, Some(&mut reservation))?;
+                    state.tuples = Some(tuple_table.values);
+                    state.memory = Some(reservation);
+                }
+                let tuple = state
+                    .tuples
+                    .as_ref()
+                    .and_then(|tuples| tuples.get(state.next))
+                    .map(|values| TupleTableSlot {
+                        tid: None,
+                        values: values.clone(),
+                    });
+                state.next += 1;
+                Ok(tuple)
+            }
+            PlanNodeType::Filter { ref mut state } => {
+                while let Some(tuple) = self.fetch_next_tuple(&mut state.child)? {
+                    if state.qual.eval(&tuple.values)?.is_true() {
+                        return Ok(Some(tuple));
+                    }
+                }
+                Ok(None)
+            }
+            PlanNodeType::NestedLoopJoin { ref mut state } => loop {
+                let outer_tuple = match &state.outer_tuple {
+                    Some(tuple) => tuple,
+                    None => match self.fetch_next_tuple(&mut state.outer)? {
+                        Some(tuple) => {
+                            // Start a new scan on inner plan for each outer tuple.
+                            self.rescan(&mut state.inner)?;
+                            state.outer_tuple.insert(tuple)
+                        }
+                        None => return Ok(None),
+                    },
+                };
+
+                match self.fetch_next_tuple(&mut state.inner)? {
+                    Some(inner_tuple) => {
+                        let mut values = outer_tuple.values.clone();
+                        values.extend(inner_tuple.values);
+
+                        let matched = match &state.qual {
+                            Some(qual) => qual.eval(&values)?.is_true(),
+                            None => true,
+                        };
+                        if matched {
+                            // Joined tuples don't have a single physical location.
+                            return Ok(Some(TupleTableSlot { tid: None, values }));
+                        }
+                    }
+                    None => state.outer_tuple = None,
+                }
+            },
+            PlanNodeType::Sort { ref mut state } => {
+                if state.sorted.is_none() {
+                    // All tuples of the child plan are sorted on the first fetch.
+                    let mut sort = Tuplesort::new(&self.buffer_pool, &state.keys, &self.memory);
+                    while let Some(tuple) = self.fetch_next_tuple(&mut state.child)? {
+                        self.check_canceled()?;
+                        sort.put(self.materialize(&tuple.values))?;
+                    }
+                    state.sorted = Some(sort.finish()?);
+                }
+                match state.sorted.as_mut() {
+                    Some(sorted) => Ok(sorted
+                        .next_tuple()?
+                        .map(|values| TupleTableSlot { tid: None, values })),
+                    None => Ok(None),
+                }
+            }
+            PlanNodeType::Limit { ref mut state } => {
+                // Skip the offset tuples only on the first fetch.
+                while state.offset > 0 {
+                    if self.fetch_next_tuple(&mut state.child)?.is_none() {
+                        return Ok(None);
+                    }
+                    state.offset -= 1;
+                }
+
+                if let Some(limit) = state.limit {
+                    if state.returned >= limit {
+                        // Don't fetch from child anymore, so the relation is not entirely
+                        // scanned if the limit was already reached.
+                        return Ok(None);
+                    }
+                }
+
+                let tuple = self.fetch_next_tuple(&mut state.child)?;
+                if tuple.is_some() {
+                    state.returned += 1;
+                }
+                Ok(tuple)
+            }
             _ => bail!(
                 "Unsupported plan node type {} to fetch next page",
                 node.node_type
@@ -65,6 +506,109 @@ impl Executor {
     }
 }
 
+impl Executor {
+    /// Return an error if the execution was canceled.
+    fn check_canceled(&self) -> Result<()> {
+        if let Some(canceled) = &self.canceled {
+            if canceled.load(Ordering::SeqCst) {
+                bail!(PgError::new(
+                    SqlState::QueryCanceled,
+                    "canceling statement due to user request"
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Copy the given values to the arena of the executor. Values fetched from relations share
+    /// the bytes of the page where their tuple is stored, so values kept until the end of the
+    /// query are copied to not keep the whole page in memory.
+    fn materialize(&self, values: &[NullableDatum]) -> Vec<NullableDatum> {
+        let mut arena = self.arena.borrow_mut();
+        values
+            .iter()
+            .map(|value| value.as_ref().map(|datum| arena.alloc(datum)))
+            .collect()
+    }
+
+    /// Restart the scan of the given plan node, so the next fetch return the first tuple again.
+    fn rescan(&self, node: &mut Plan) -> Result<()> {
+        match &mut node.node_type {
+            PlanNodeType::SeqScan { ref mut state } => {
+                state.scan = state
+                    .table_am
+                    .begin_scan(&self.buffer_pool, &state.relation)?;
+                Ok(())
+            }
+            PlanNodeType::IndexScan { ref mut state } => {
+                // The index is searched again by the next fetch.
+                state.tids = None;
+                Ok(())
+            }
+            PlanNodeType::VirtualScan { ref mut state } => {
+                // The tuples are generated again by the next fetch.
+                state.tuples = None;
+                Ok(())
+            }
+            PlanNodeType::SubqueryScan { ref mut state } => {
+                // The subquery is executed only once, its tuples are returned again.
+                state.next = 0;
+                Ok(())
+            }
+            PlanNodeType::Filter { ref mut state } => self.rescan(&mut state.child),
+            PlanNodeType::Sort { ref mut state } => {
+                // The child plan is sorted again by the next fetch.
+                state.sorted = None;
+                self.rescan(&mut state.child)
+            }
+            _ => bail!("Unsupported plan node type {} to rescan", node.node_type),
+        }
+    }
+}
+
+/// Decode the given used attributes of the given tuple fetched from a relation, or all of its
+/// attributes if used_attrs is None. Attributes that are not used are returned as NULL.
+///
+/// Return None if the tuple don't satisfy the given scan keys. The attributes of the scan keys
+/// are decoded first, so the other attributes of tuples that are skipped are never decoded.
+fn heap_tuple_slot(
+    tuple: &HeapTuple,
+    tuple_desc: &TupleDesc,
+    used_attrs: &Option<Vec<bool>>,
+    scan_keys: &[ScanKey],
+) -> Result<Option<TupleTableSlot>> {
+    for key in scan_keys {
+        let value = tuple.get_attr(tuple_desc.attrs[key.attr].attnum, tuple_desc)?;
+        if !key.qual.eval(std::slice::from_ref(&value))?.is_true() {
+            return Ok(None);
+        }
+    }
+
+    Ok(Some(TupleTableSlot {
+        tid: Some(tuple.t_self),
+        values: tuple.deform(tuple_desc, used_attrs.as_deref())?,
+    }))
+}
+
+/// Return an estimate of the amount of memory used by the given rows of tuple values.
+fn values_memory(rows: &[Vec<NullableDatum>]) -> usize {
+    rows.iter()
+        .flatten()
+        .map(|value| mem::size_of::<NullableDatum>() + value.as_ref().map_or(0, |v| v.len()))
+        .sum()
+}
+
+/// A tuple table slot holds the values of a single tuple that flows between the plan nodes.
+pub struct TupleTableSlot {
+    /// Physical location of the tuple on relation. None if the tuple was not fetched
+    /// from a heap page.
+    pub tid: Option<ItemPointer>,
+
+    /// Attribute values of the tuple on the same order of the tuple descriptor from the
+    /// plan node that produced this slot.
+    pub values: Vec<NullableDatum>,
+}
+
 /// The planner executor store tuples in a tuple table which is essentially a list of independent
 /// tuple table slots.
 #[derive(Default)]
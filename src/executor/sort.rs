@@ -0,0 +1,435 @@
+//! External merge sort of the tuples of sort plan nodes.
+//!
+//! Tuples are sorted in memory while they fit in work_mem. Larger inputs are split in sorted
+//! runs that are written to temporary files, which are merged while the sorted tuples are
+//! returned, so sorting a large input don't need to keep all of its tuples in memory.
+
+use std::{
+    cmp::Ordering,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    mem, vec,
+};
+
+use anyhow::Result;
+
+use crate::{
+    executor::memory::{MemoryContext, MemoryReservation},
+    expr::ScalarValue,
+    planner::SortKey,
+    storage::{buffile::BufFile, BufferPool},
+    Datum, NullableDatum,
+};
+
+/// Maximum number of runs merged at the same time. Inputs with more runs are merged in multiple
+/// passes, so the number of opened temporary files is bounded.
+const MERGE_ORDER: usize = 64;
+
+/// A tuple being sorted, with the values of its sort keys already evaluated.
+struct SortTuple {
+    keys: Vec<ScalarValue>,
+    values: Vec<NullableDatum>,
+}
+
+impl SortTuple {
+    fn new(keys: &[SortKey], values: Vec<NullableDatum>) -> Result<Self> {
+        Ok(Self {
+            keys: keys
+                .iter()
+                .map(|key| key.expr.eval(&values))
+                .collect::<Result<_>>()?,
+            values,
+        })
+    }
+
+    /// Return an estimate of the amount of memory used by the tuple.
+    fn memory(&self) -> usize {
+        let values = self
+            .values
+            .iter()
+            .map(|value| mem::size_of::<NullableDatum>() + value.as_ref().map_or(0, |v| v.len()));
+        let keys = self.keys.iter().map(|key| match key {
+            ScalarValue::Varchar(value) => mem::size_of::<ScalarValue>() + value.len(),
+            _ => mem::size_of::<ScalarValue>(),
+        });
+        mem::size_of::<Self>() + values.sum::<usize>() + keys.sum::<usize>()
+    }
+}
+
+/// Sort the tuples added by put, returning them in order after finish is called.
+pub struct Tuplesort {
+    /// Buffer pool used to create the temporary files of sorted runs.
+    buffer_pool: BufferPool,
+
+    keys: Vec<SortKey>,
+
+    /// Tuples kept in memory, not yet written to a sorted run.
+    tuples: Vec<SortTuple>,
+
+    /// Estimated memory used by the tuples kept in memory. Tuples are written to a sorted run
+    /// when no more memory can be reserved.
+    memory: MemoryReservation,
+
+    /// Temporary files of the sorted runs written, in the order that they were written.
+    runs: Vec<BufFile>,
+}
+
+impl Tuplesort {
+    /// Create a new sort of tuples by the given keys, keeping in memory only the tuples that fit
+    /// on the given memory context.
+    pub fn new(buffer_pool: &BufferPool, keys: &[SortKey], memory: &MemoryContext) -> Self {
+        Self {
+            buffer_pool: buffer_pool.clone(),
+            keys: keys.to_vec(),
+            tuples: Vec::new(),
+            memory: memory.reservation(),
+            runs: Vec::new(),
+        }
+    }
+
+    /// Add a tuple with the given values to the sort.
+    pub fn put(&mut self, values: Vec<NullableDatum>) -> Result<()> {
+        let tuple = SortTuple::new(&self.keys, values)?;
+        let size = tuple.memory();
+        if !self.memory.try_grow(size) {
+            if !self.tuples.is_empty() {
+                self.dump_run()?;
+            }
+            // Fails only if the tuple alone don't fit on the free memory.
+            self.memory.grow(size)?;
+        }
+        self.tuples.push(tuple);
+        Ok(())
+    }
+
+    /// Return the number of sorted runs written to temporary files.
+    pub fn runs(&self) -> usize {
+        self.runs.len()
+    }
+
+    /// Sort all tuples added, merging the sorted runs written to temporary files if the tuples
+    /// did not fit in memory.
+    pub fn finish(mut self) -> Result<SortedTuples> {
+        if self.runs.is_empty() {
+            sort_tuples(&self.keys, &mut self.tuples)?;
+            return Ok(SortedTuples {
+                source: Source::InMemory(self.tuples.into_iter()),
+                _memory: self.memory,
+            });
+        }
+
+        if !self.tuples.is_empty() {
+            self.dump_run()?;
+        }
+
+        // Adjacent runs are merged together, so tuples with equal keys keep the order that they
+        // were added.
+        let mut runs = mem::take(&mut self.runs);
+        while runs.len() > MERGE_ORDER {
+            let mut merged = Vec::new();
+            let mut remaining = runs.into_iter().peekable();
+            while remaining.peek().is_some() {
+                let group = remaining.by_ref().take(MERGE_ORDER).collect::<Vec<_>>();
+                if group.len() == 1 {
+                    merged.extend(group);
+                    continue;
+                }
+
+                let mut merge = Merge::new(&self.keys, group)?;
+                let mut run = self.buffer_pool.create_temp_file()?;
+                let mut writer = BufWriter::new(run.file());
+                while let Some(tuple) = merge.next_tuple()? {
+                    write_tuple(&mut writer, &tuple.values)?;
+                }
+                writer.flush()?;
+                drop(writer);
+                run.rewind()?;
+                merged.push(run);
+            }
+            runs = merged;
+        }
+
+        Ok(SortedTuples {
+            source: Source::Merge(Merge::new(&self.keys, runs)?),
+            _memory: self.memory,
+        })
+    }
+
+    /// Sort the tuples kept in memory and write them to a new sorted run.
+    fn dump_run(&mut self) -> Result<()> {
+        sort_tuples(&self.keys, &mut self.tuples)?;
+
+        let mut run = self.buffer_pool.create_temp_file()?;
+        let mut writer = BufWriter::new(run.file());
+        for tuple in self.tuples.drain(..) {
+            write_tuple(&mut writer, &tuple.values)?;
+        }
+        writer.flush()?;
+        drop(writer);
+        run.rewind()?;
+
+        self.runs.push(run);
+        self.memory.free();
+        Ok(())
+    }
+}
+
+/// Tuples returned in order by a sort.
+pub struct SortedTuples {
+    source: Source,
+
+    /// Memory reserved by the tuples kept in memory, released when the tuples are dropped.
+    _memory: MemoryReservation,
+}
+
+enum Source {
+    InMemory(vec::IntoIter<SortTuple>),
+    Merge(Merge),
+}
+
+impl SortedTuples {
+    /// Return the values of the next tuple in order, or None if all tuples were returned.
+    pub fn next_tuple(&mut self) -> Result<Option<Vec<NullableDatum>>> {
+        match &mut self.source {
+            Source::InMemory(tuples) => Ok(tuples.next().map(|tuple| tuple.values)),
+            Source::Merge(merge) => Ok(merge.next_tuple()?.map(|tuple| tuple.values)),
+        }
+    }
+}
+
+/// A sorted run being read from its temporary file.
+struct RunReader {
+    reader: BufReader<File>,
+
+    /// Temporary file of the run, removed when the run is dropped.
+    _file: BufFile,
+}
+
+/// Merge of sorted runs, returning the smallest tuple of all runs on each call of next.
+struct Merge {
+    keys: Vec<SortKey>,
+    runs: Vec<RunReader>,
+
+    /// Next tuple of each run. None if all tuples of the run were returned.
+    heads: Vec<Option<SortTuple>>,
+}
+
+impl Merge {
+    fn new(keys: &[SortKey], files: Vec<BufFile>) -> Result<Self> {
+        let mut runs = Vec::with_capacity(files.len());
+        let mut heads = Vec::with_capacity(files.len());
+        for file in files {
+            let mut run = RunReader {
+                reader: BufReader::new(file.file().try_clone()?),
+                _file: file,
+            };
+            heads.push(read_sort_tuple(keys, &mut run.reader)?);
+            runs.push(run);
+        }
+        Ok(Self {
+            keys: keys.to_vec(),
+            runs,
+            heads,
+        })
+    }
+
+    fn next_tuple(&mut self) -> Result<Option<SortTuple>> {
+        // The number of runs is small, so the smallest head is found by comparing all of them.
+        // Ties go to the first run, so the merge is stable.
+        let mut smallest: Option<usize> = None;
+        for (index, head) in self.heads.iter().enumerate() {
+            if let Some(head) = head {
+                let is_smaller = match smallest.and_then(|i| self.heads[i].as_ref()) {
+                    Some(current) => {
+                        compare(&self.keys, &head.keys, &current.keys)? == Ordering::Less
+                    }
+                    None => true,
+                };
+                if is_smaller {
+                    smallest = Some(index);
+                }
+            }
+        }
+
+        match smallest {
+            Some(index) => {
+                let next = read_sort_tuple(&self.keys, &mut self.runs[index].reader)?;
+                Ok(mem::replace(&mut self.heads[index], next))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+/// Sort the given tuples by the given keys. The sort is stable, so tuples with equal keys keep
+/// the order that they were added.
+fn sort_tuples(keys: &[SortKey], tuples: &mut [SortTuple]) -> Result<()> {
+    let mut error = None;
+    tuples.sort_by(|a, b| {
+        if error.is_some() {
+            return Ordering::Equal;
+        }
+        compare(keys, &a.keys, &b.keys).unwrap_or_else(|err| {
+            error = Some(err);
+            Ordering::Equal
+        })
+    });
+    match error {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
+}
+
+/// Compare the evaluated keys of two tuples.
+fn compare(keys: &[SortKey], a: &[ScalarValue], b: &[ScalarValue]) -> Result<Ordering> {
+    for ((key, a), b) in keys.iter().zip(a).zip(b) {
+        let nulls = if key.nulls_first {
+            Ordering::Less
+        } else {
+            Ordering::Greater
+        };
+        let ordering = match (a, b) {
+            (ScalarValue::Null, ScalarValue::Null) => Ordering::Equal,
+            (ScalarValue::Null, _) => nulls,
+            (_, ScalarValue::Null) => nulls.reverse(),
+            _ if key.descending => a.compare(b)?.reverse(),
+            _ => a.compare(b)?,
+        };
+        if ordering != Ordering::Equal {
+            return Ok(ordering);
+        }
+    }
+    Ok(Ordering::Equal)
+}
+
+/// Write the values of a tuple to a sorted run. Each tuple is stored as its number of values,
+/// followed by the length and bytes of each value. NULL values have a length of -1.
+fn write_tuple(writer: &mut impl Write, values: &[NullableDatum]) -> Result<()> {
+    writer.write_all(&(values.len() as u32).to_le_bytes())?;
+    for value in values {
+        match value {
+            Some(datum) => {
+                writer.write_all(&(datum.len() as i32).to_le_bytes())?;
+                writer.write_all(datum)?;
+            }
+            None => writer.write_all(&(-1i32).to_le_bytes())?,
+        }
+    }
+    Ok(())
+}
+
+/// Read the next tuple of a sorted run, evaluating its sort keys. Return None at the end of the
+/// run.
+fn read_sort_tuple(keys: &[SortKey], reader: &mut impl Read) -> Result<Option<SortTuple>> {
+    let mut natts = [0; 4];
+    match reader.read_exact(&mut natts) {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err.into()),
+    }
+
+    let natts = u32::from_le_bytes(natts) as usize;
+    let mut values = Vec::with_capacity(natts);
+    for _ in 0..natts {
+        let mut len = [0; 4];
+        reader.read_exact(&mut len)?;
+        match i32::from_le_bytes(len) {
+            -1 => values.push(None),
+            len => {
+                let mut datum = vec![0; len as usize];
+                reader.read_exact(&mut datum)?;
+                values.push(Some(Datum::from(datum)));
+            }
+        }
+    }
+    Ok(Some(SortTuple::new(keys, values)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        catalog::pg_type,
+        expr::Expr,
+        storage::{buffile::TEMP_FILES_DIR, smgr::StorageManager, BufferPool},
+    };
+
+    #[test]
+    fn test_external_sort() -> Result<()> {
+        let data_dir = tempfile::tempdir()?;
+        let buffer_pool = BufferPool::new(4, StorageManager::new(data_dir.path()));
+        let key = |descending, nulls_first| SortKey {
+            expr: Expr::Column {
+                index: 0,
+                typ: pg_type::INT_OID,
+            },
+            descending,
+            nulls_first,
+        };
+        let tuple = |value: Option<i32>, position: usize| {
+            vec![
+                value.map(|value| Datum::from(bincode::serialize(&value).unwrap())),
+                Some(Datum::from(bincode::serialize(&(position as i32)).unwrap())),
+            ]
+        };
+        let decode = |datum: &NullableDatum| {
+            datum
+                .as_ref()
+                .map(|datum| bincode::deserialize::<i32>(datum).unwrap())
+        };
+
+        // Values repeat, so the sort must keep the order of tuples with equal keys.
+        let input = (0..40000)
+            .map(|position| {
+                let value = (position % 7 != 0).then_some(((position * 7919) % 1000) as i32);
+                (value, position)
+            })
+            .collect::<Vec<_>>();
+
+        for (descending, nulls_first) in [(false, false), (true, true), (false, true)] {
+            for work_mem in [64 * 1024, 64 * 1024 * 1024] {
+                let memory = MemoryContext::new(work_mem);
+                let mut sort =
+                    Tuplesort::new(&buffer_pool, &[key(descending, nulls_first)], &memory);
+                for (value, position) in &input {
+                    sort.put(tuple(*value, *position))?;
+                }
+                // Small work_mem spills to more runs than can be merged at once.
+                if work_mem == 64 * 1024 {
+                    assert!(sort.runs() > MERGE_ORDER, "{}", sort.runs());
+                } else {
+                    assert_eq!(sort.runs(), 0);
+                }
+
+                let mut sorted = sort.finish()?;
+                let mut output = Vec::new();
+                while let Some(values) = sorted.next_tuple()? {
+                    output.push((decode(&values[0]), decode(&values[1]).unwrap() as usize));
+                }
+
+                let mut expected = input.clone();
+                expected.sort_by(|(a, _), (b, _)| match (a, b) {
+                    (None, None) => Ordering::Equal,
+                    (None, _) if nulls_first => Ordering::Less,
+                    (None, _) => Ordering::Greater,
+                    (_, None) if nulls_first => Ordering::Greater,
+                    (_, None) => Ordering::Less,
+                    (Some(a), Some(b)) if descending => b.cmp(a),
+                    (Some(a), Some(b)) => a.cmp(b),
+                });
+                assert_eq!(output, expected);
+
+                // Temporary files of sorted runs are removed after being merged, and the memory
+                // of tuples is released.
+                drop(sorted);
+                assert_eq!(memory.used(), 0);
+                let temp_files = data_dir.path().join(TEMP_FILES_DIR);
+                if temp_files.exists() {
+                    assert_eq!(std::fs::read_dir(temp_files)?.count(), 0);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,375 @@
+use std::{cmp::Ordering, convert::TryFrom};
+
+use anyhow::{anyhow, bail, Result};
+use sqlparser::ast;
+
+use crate::{
+    catalog::{pg_attribute::PgAttribute, pg_type},
+    executor::batch::TupleBatch,
+    sql::{
+        error::{PgError, SqlState},
+        SQLError,
+    },
+    NullableDatum, Oid, INVALID_OID,
+};
+
+use super::{Expr, RangeTable, ScalarValue};
+
+/// Aggregate functions supported on queries.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AggregateFunc {
+    Count,
+    Sum,
+    Min,
+    Max,
+}
+
+impl AggregateFunc {
+    /// Return the aggregate function for the given function name, None if the function is not an
+    /// aggregate.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "count" => Some(Self::Count),
+            "sum" => Some(Self::Sum),
+            "min" => Some(Self::Min),
+            "max" => Some(Self::Max),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            Self::Count => "count",
+            Self::Sum => "sum",
+            Self::Min => "min",
+            Self::Max => "max",
+        }
+    }
+}
+
+/// An aggregate function call ready to be accumulated by the executor.
+#[derive(Debug, Clone)]
+pub struct AggregateExpr {
+    /// Aggregate function to call.
+    pub func: AggregateFunc,
+
+    /// Argument of the function. None for count(*).
+    pub arg: Option<Expr>,
+
+    /// Type of the aggregate result.
+    pub typ: Oid,
+}
+
+impl AggregateExpr {
+    /// Return true if the given parsed expression is an aggregate function call.
+    pub fn is_aggregate(expr: &ast::Expr) -> bool {
+        match expr {
+            ast::Expr::Function(function) => {
+                AggregateFunc::from_name(&function.name.to_string()).is_some()
+            }
+            _ => false,
+        }
+    }
+
+    /// Create a new aggregate expression from the given parsed function call, resolving column
+    /// references of the function argument using the given range table.
+    pub fn create(function: &ast::Function, range_table: &RangeTable) -> Result<Self> {
+        let func = AggregateFunc::from_name(&function.name.to_string()).ok_or_else(|| {
+            anyhow!(PgError::new(
+                SqlState::UndefinedFunction,
+                format!("function {} does not exist", function.name)
+            )
+            .with_hint("Only the aggregate functions count, sum, min and max are supported."))
+        })?;
+
+        if function.distinct || function.over.is_some() {
+            bail!(SQLError::Unsupported(function.to_string()));
+        }
+
+        let arg = match function.args.as_slice() {
+            [ast::FunctionArg::Unnamed(ast::FunctionArgExpr::Wildcard)]
+                if func == AggregateFunc::Count =>
+            {
+                None
+            }
+            [ast::FunctionArg::Unnamed(ast::FunctionArgExpr::Expr(expr))] => {
+                Some(Expr::create(expr, range_table)?)
+            }
+            _ => bail!(SQLError::Unsupported(function.to_string())),
+        };
+
+        let typ = match func {
+            AggregateFunc::Count => pg_type::INT_OID,
+            AggregateFunc::Sum => match arg.as_ref().and_then(|arg| arg.return_type()) {
+                Some(pg_type::INT2_OID) | Some(pg_type::INT_OID) => pg_type::INT_OID,
+                Some(typ @ pg_type::INT8_OID)
+                | Some(typ @ pg_type::FLOAT4_OID)
+                | Some(typ @ pg_type::FLOAT8_OID) => typ,
+                _ => bail!(PgError::new(
+                    SqlState::DatatypeMismatch,
+                    format!("function {} argument must be a numeric type", function)
+                )),
+            },
+            AggregateFunc::Min | AggregateFunc::Max => {
+                match arg.as_ref().and_then(|arg| arg.return_type()) {
+                    Some(typ) => typ,
+                    None => bail!(PgError::new(
+                        SqlState::DatatypeMismatch,
+                        format!("could not determine type of function {} argument", function)
+                    )),
+                }
+            }
+        };
+
+        Ok(Self { func, arg, typ })
+    }
+
+    /// Return the attribute that describe the aggregate result with the given name.
+    pub fn output_attr(&self, name: Option<&str>) -> PgAttribute {
+        PgAttribute {
+            attrelid: INVALID_OID,
+            attname: name.unwrap_or_else(|| self.func.name()).to_string(),
+            attnum: 0,
+            attlen: pg_type::type_len(self.typ),
+            atttypid: self.typ,
+            attnotnull: false,
+            attdefault: String::new(),
+        }
+    }
+
+    /// Create a new empty accumulator for this aggregate.
+    pub fn accumulator(&self) -> Accumulator {
+        Accumulator {
+            func: self.func,
+            value: match self.func {
+                AggregateFunc::Count => ScalarValue::Int(0),
+                _ => ScalarValue::Null,
+            },
+        }
+    }
+}
+
+/// Accumulate the values of an aggregate function over multiple tuples.
+pub struct Accumulator {
+    func: AggregateFunc,
+
+    /// Current aggregate value.
+    value: ScalarValue,
+}
+
+impl Accumulator {
+    /// Accumulate the argument of the aggregate expression evaluated against the given tuple
+    /// values. NULL values are ignored, except by count(*).
+    pub fn accumulate(
+        &mut self,
+        aggregate: &AggregateExpr,
+        values: &[NullableDatum],
+    ) -> Result<()> {
+        let value = match &aggregate.arg {
+            Some(arg) => arg.eval(values)?,
+            // count(*) counts all rows, so any non NULL value can be used.
+            None => ScalarValue::Bool(true),
+        };
+        self.accumulate_value(aggregate, value)
+    }
+
+    /// Accumulate the argument of the aggregate expression evaluated against all tuples of the
+    /// given batch.
+    pub fn accumulate_batch(
+        &mut self,
+        aggregate: &AggregateExpr,
+        batch: &TupleBatch,
+    ) -> Result<()> {
+        match &aggregate.arg {
+            Some(arg) => {
+                for value in arg.eval_batch(batch)? {
+                    self.accumulate_value(aggregate, value)?;
+                }
+                Ok(())
+            }
+            // count(*) just add the number of tuples of the batch.
+            None => {
+                if let ScalarValue::Int(count) = self.value {
+                    self.value = ScalarValue::Int(
+                        i32::try_from(batch.len())
+                            .ok()
+                            .and_then(|len| count.checked_add(len))
+                            .ok_or_else(count_out_of_range)?,
+                    );
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Accumulate an already evaluated argument value.
+    fn accumulate_value(&mut self, aggregate: &AggregateExpr, value: ScalarValue) -> Result<()> {
+        if value == ScalarValue::Null {
+            return Ok(());
+        }
+
+        self.value = match (self.func, &self.value) {
+            (AggregateFunc::Count, ScalarValue::Int(count)) => {
+                ScalarValue::Int(count.checked_add(1).ok_or_else(count_out_of_range)?)
+            }
+            (AggregateFunc::Sum, sum) => add(aggregate.typ, sum, &value)?,
+            (AggregateFunc::Min, current) if *current != ScalarValue::Null => {
+                if value.compare(current)? == Ordering::Less {
+                    value
+                } else {
+                    return Ok(());
+                }
+            }
+            (AggregateFunc::Max, current) if *current != ScalarValue::Null => {
+                if value.compare(current)? == Ordering::Greater {
+                    value
+                } else {
+                    return Ok(());
+                }
+            }
+            // First non NULL value of min and max.
+            (_, _) => value,
+        };
+
+        Ok(())
+    }
+
+    /// Return the final aggregate value.
+    pub fn finish(self) -> ScalarValue {
+        self.value
+    }
+}
+
+/// Return the error of a count that does not fit on its integer result.
+fn count_out_of_range() -> anyhow::Error {
+    anyhow!(PgError::new(
+        SqlState::NumericValueOutOfRange,
+        "integer out of range"
+    ))
+}
+
+/// Add the given value to the current sum of the given type. The current sum is NULL if no value
+/// was added yet.
+fn add(typ: Oid, sum: &ScalarValue, value: &ScalarValue) -> Result<ScalarValue> {
+    let out_of_range = || {
+        anyhow!(PgError::new(
+            SqlState::NumericValueOutOfRange,
+            format!("{} out of range", pg_type::type_name(typ))
+        ))
+    };
+
+    match typ {
+        pg_type::INT_OID | pg_type::INT8_OID => {
+            let value = value.to_i64().ok_or_else(|| {
+                anyhow!(PgError::new(
+                    SqlState::DatatypeMismatch,
+                    "function sum argument must be a numeric type"
+                ))
+            })?;
+            let sum = sum
+                .to_i64()
+                .unwrap_or(0)
+                .checked_add(value)
+                .ok_or_else(out_of_range)?;
+            if typ == pg_type::INT_OID {
+                Ok(ScalarValue::Int(
+                    i32::try_from(sum).map_err(|_| out_of_range())?,
+                ))
+            } else {
+                Ok(ScalarValue::BigInt(sum))
+            }
+        }
+        pg_type::FLOAT4_OID | pg_type::FLOAT8_OID => {
+            let value = value.to_f64().ok_or_else(|| {
+                anyhow!(PgError::new(
+                    SqlState::DatatypeMismatch,
+                    "function sum argument must be a numeric type"
+                ))
+            })?;
+            let sum = sum.to_f64().unwrap_or(0.0) + value;
+            if typ == pg_type::FLOAT4_OID {
+                Ok(ScalarValue::Float4(sum as f32))
+            } else {
+                Ok(ScalarValue::Float8(sum))
+            }
+        }
+        _ => bail!(PgError::new(
+            SqlState::DatatypeMismatch,
+            "function sum argument must be a numeric type"
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{access::heaptuple::TupleDesc, executor::TupleTableSlot};
+    use sqlparser::{dialect::PostgreSqlDialect, parser::Parser, tokenizer::Tokenizer};
+
+    fn tuple_desc() -> TupleDesc {
+        TupleDesc {
+            attrs: vec![PgAttribute {
+                attrelid: 1,
+                attname: String::from("a"),
+                attnum: 1,
+                attlen: 4,
+                atttypid: pg_type::INT_OID,
+                attnotnull: false,
+                attdefault: String::new(),
+            }],
+        }
+    }
+
+    fn aggregate(sql: &str, rows: &[Option<i32>]) -> Result<ScalarValue> {
+        let dialect = PostgreSqlDialect {};
+        let tokens = Tokenizer::new(&dialect, sql).tokenize()?;
+        let function = match Parser::new(tokens, &dialect).parse_expr()? {
+            ast::Expr::Function(function) => function,
+            expr => bail!("expected function, got {}", expr),
+        };
+
+        let aggregate = AggregateExpr::create(&function, &RangeTable::new("t", tuple_desc()))?;
+        let mut accumulator = aggregate.accumulator();
+        let mut batch_accumulator = aggregate.accumulator();
+        let mut batch = TupleBatch::default();
+        for row in rows {
+            let value = match row {
+                Some(value) => ScalarValue::Int(*value),
+                None => ScalarValue::Null,
+            };
+            accumulator.accumulate(&aggregate, &[value.to_datum()?])?;
+            batch.push(TupleTableSlot {
+                tid: None,
+                values: vec![value.to_datum()?],
+            });
+        }
+
+        // Accumulating a whole batch must produce the same value of each tuple.
+        batch_accumulator.accumulate_batch(&aggregate, &batch)?;
+        let value = accumulator.finish();
+        assert_eq!(batch_accumulator.finish(), value);
+        Ok(value)
+    }
+
+    #[test]
+    fn test_aggregates() -> Result<()> {
+        let rows = [Some(3), None, Some(-1), Some(10)];
+
+        assert_eq!(aggregate("count(*)", &rows)?, ScalarValue::Int(4));
+        assert_eq!(aggregate("count(a)", &rows)?, ScalarValue::Int(3));
+        assert_eq!(aggregate("sum(a)", &rows)?, ScalarValue::Int(12));
+        assert_eq!(aggregate("min(a)", &rows)?, ScalarValue::Int(-1));
+        assert_eq!(aggregate("max(a)", &rows)?, ScalarValue::Int(10));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_aggregates_empty_input() -> Result<()> {
+        assert_eq!(aggregate("count(*)", &[])?, ScalarValue::Int(0));
+        assert_eq!(aggregate("sum(a)", &[None])?, ScalarValue::Null);
+        assert_eq!(aggregate("max(a)", &[])?, ScalarValue::Null);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,356 @@
+use std::time::SystemTime;
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::{
+    catalog::pg_type::{self, type_name},
+    sql::{
+        encode::datetime::timestamp_from_system_time,
+        error::{PgError, SqlState},
+    },
+    NullableDatum, Oid,
+};
+
+use super::{common_type, is_string, numeric_type, Expr, ScalarValue};
+
+/// Implementation of a built-in scalar function.
+#[derive(Debug, Clone, Copy)]
+enum Implementation {
+    /// Function called with the values of all arguments. The function is not called and return
+    /// NULL if any argument is NULL.
+    Strict(fn(&[ScalarValue]) -> Result<ScalarValue>),
+
+    /// Function that evaluate only the arguments that it needs.
+    Lazy(fn(&Args<'_>) -> Result<ScalarValue>),
+}
+
+/// Return the type of a function result for the given argument types.
+type ReturnType = fn(&ScalarFunction, &[Option<Oid>]) -> Result<Option<Oid>>;
+
+/// A built-in scalar function that can be called on expressions.
+#[derive(Debug)]
+pub struct ScalarFunction {
+    /// Name of the function.
+    pub name: &'static str,
+
+    /// Minimum number of arguments.
+    min_args: usize,
+
+    /// Maximum number of arguments. None if the function accept any number of arguments.
+    max_args: Option<usize>,
+
+    /// Return the type of the function result for the given argument types, failing if the
+    /// argument types are not accepted by the function.
+    return_type: ReturnType,
+
+    implementation: Implementation,
+}
+
+/// All built-in scalar functions.
+static FUNCTIONS: &[ScalarFunction] = &[
+    ScalarFunction {
+        name: "upper",
+        min_args: 1,
+        max_args: Some(1),
+        return_type: text_return_type,
+        implementation: Implementation::Strict(upper),
+    },
+    ScalarFunction {
+        name: "lower",
+        min_args: 1,
+        max_args: Some(1),
+        return_type: text_return_type,
+        implementation: Implementation::Strict(lower),
+    },
+    ScalarFunction {
+        name: "length",
+        min_args: 1,
+        max_args: Some(1),
+        return_type: length_return_type,
+        implementation: Implementation::Strict(length),
+    },
+    ScalarFunction {
+        name: "abs",
+        min_args: 1,
+        max_args: Some(1),
+        return_type: numeric_return_type,
+        implementation: Implementation::Strict(abs),
+    },
+    ScalarFunction {
+        name: "coalesce",
+        min_args: 1,
+        max_args: None,
+        return_type: coalesce_return_type,
+        implementation: Implementation::Lazy(coalesce),
+    },
+    ScalarFunction {
+        name: "now",
+        min_args: 0,
+        max_args: Some(0),
+        return_type: |_, _| Ok(Some(pg_type::TIMESTAMP_OID)),
+        implementation: Implementation::Strict(now),
+    },
+];
+
+/// Built-in functions are identified by their names.
+impl PartialEq for ScalarFunction {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+impl ScalarFunction {
+    /// Return the built-in function with the given name, if any.
+    pub fn lookup(name: &str) -> Option<&'static Self> {
+        let name = name.to_lowercase();
+        FUNCTIONS.iter().find(|function| function.name == name)
+    }
+
+    /// Return the type of the function result for arguments of the given types. An error is
+    /// returned if the function don't accept the number or the types of the arguments.
+    pub fn check_args(&self, args: &[Option<Oid>]) -> Result<Option<Oid>> {
+        if args.len() < self.min_args || matches!(self.max_args, Some(max) if args.len() > max) {
+            return Err(self.undefined(args));
+        }
+        (self.return_type)(self, args)
+    }
+
+    /// Return the error of calling the function with arguments of the given types.
+    fn undefined(&self, args: &[Option<Oid>]) -> anyhow::Error {
+        undefined_function(self.name, args)
+    }
+
+    /// Call the function with the given argument expressions evaluated against the given tuple
+    /// values. The type of the result is the type returned by check_args.
+    pub fn eval(
+        &self,
+        args: &[Expr],
+        typ: Option<Oid>,
+        values: &[NullableDatum],
+    ) -> Result<ScalarValue> {
+        match self.implementation {
+            Implementation::Strict(function) => {
+                let args = args
+                    .iter()
+                    .map(|arg| arg.eval(values))
+                    .collect::<Result<Vec<_>>>()?;
+                if args.contains(&ScalarValue::Null) {
+                    return Ok(ScalarValue::Null);
+                }
+                function(&args)
+            }
+            Implementation::Lazy(function) => function(&Args { args, typ, values }),
+        }
+    }
+}
+
+/// Return the error of calling a function that don't exist with arguments of the given types.
+pub fn undefined_function(name: &str, args: &[Option<Oid>]) -> anyhow::Error {
+    let args = args
+        .iter()
+        .map(|arg| arg.map_or("unknown", type_name))
+        .collect::<Vec<_>>()
+        .join(", ");
+    anyhow!(PgError::new(
+        SqlState::UndefinedFunction,
+        format!("function {}({}) does not exist", name, args)
+    )
+    .with_hint(
+        "No function matches the given name and argument types. You might need to add explicit type casts."
+    ))
+}
+
+/// Arguments of a lazy function call, evaluated on demand.
+struct Args<'a> {
+    args: &'a [Expr],
+
+    /// Type of the function result.
+    typ: Option<Oid>,
+
+    values: &'a [NullableDatum],
+}
+
+impl<'a> Args<'a> {
+    /// Return the number of arguments.
+    fn len(&self) -> usize {
+        self.args.len()
+    }
+
+    /// Evaluate the argument of the given position.
+    fn eval(&self, index: usize) -> Result<ScalarValue> {
+        self.args[index].eval(self.values)
+    }
+}
+
+fn text_return_type(function: &ScalarFunction, args: &[Option<Oid>]) -> Result<Option<Oid>> {
+    match args {
+        [None] => Ok(Some(pg_type::TEXT_OID)),
+        [Some(typ)] if is_string(*typ) => Ok(Some(pg_type::TEXT_OID)),
+        _ => Err(function.undefined(args)),
+    }
+}
+
+fn length_return_type(function: &ScalarFunction, args: &[Option<Oid>]) -> Result<Option<Oid>> {
+    text_return_type(function, args).map(|_| Some(pg_type::INT_OID))
+}
+
+fn numeric_return_type(function: &ScalarFunction, args: &[Option<Oid>]) -> Result<Option<Oid>> {
+    match args {
+        [None] => Ok(None),
+        [Some(typ)] => match numeric_type(*typ, *typ) {
+            Some(typ) => Ok(Some(typ)),
+            None => Err(function.undefined(args)),
+        },
+        _ => Err(function.undefined(args)),
+    }
+}
+
+fn coalesce_return_type(_: &ScalarFunction, args: &[Option<Oid>]) -> Result<Option<Oid>> {
+    args.iter()
+        .try_fold(None, |typ, arg| common_type("COALESCE", typ, *arg))
+}
+
+/// Return the string of a text argument. The argument types are checked before calling the
+/// function, so other types are never received.
+fn text_arg(value: &ScalarValue) -> Result<&str> {
+    match value {
+        ScalarValue::Varchar(value) => Ok(value),
+        value => bail!("unexpected text argument {:?}", value),
+    }
+}
+
+fn upper(args: &[ScalarValue]) -> Result<ScalarValue> {
+    Ok(ScalarValue::Varchar(text_arg(&args[0])?.to_uppercase()))
+}
+
+fn lower(args: &[ScalarValue]) -> Result<ScalarValue> {
+    Ok(ScalarValue::Varchar(text_arg(&args[0])?.to_lowercase()))
+}
+
+/// Return the number of characters of a string.
+fn length(args: &[ScalarValue]) -> Result<ScalarValue> {
+    Ok(ScalarValue::Int(text_arg(&args[0])?.chars().count() as i32))
+}
+
+fn abs(args: &[ScalarValue]) -> Result<ScalarValue> {
+    let out_of_range = |typ| {
+        anyhow!(PgError::new(
+            SqlState::NumericValueOutOfRange,
+            format!("{} out of range", type_name(typ))
+        ))
+    };
+    match &args[0] {
+        ScalarValue::SmallInt(value) => value
+            .checked_abs()
+            .map(ScalarValue::SmallInt)
+            .ok_or_else(|| out_of_range(pg_type::INT2_OID)),
+        ScalarValue::Int(value) => value
+            .checked_abs()
+            .map(ScalarValue::Int)
+            .ok_or_else(|| out_of_range(pg_type::INT_OID)),
+        ScalarValue::BigInt(value) => value
+            .checked_abs()
+            .map(ScalarValue::BigInt)
+            .ok_or_else(|| out_of_range(pg_type::INT8_OID)),
+        ScalarValue::Float4(value) => Ok(ScalarValue::Float4(value.abs())),
+        ScalarValue::Float8(value) => Ok(ScalarValue::Float8(value.abs())),
+        value => bail!("unexpected numeric argument {:?}", value),
+    }
+}
+
+/// Return the first argument that is not NULL, converted to the type of the function result.
+/// The arguments after it are not evaluated.
+fn coalesce(args: &Args<'_>) -> Result<ScalarValue> {
+    for index in 0..args.len() {
+        let value = args.eval(index)?;
+        if value != ScalarValue::Null {
+            return Ok(match args.typ {
+                Some(typ) => value.coerce(typ),
+                None => value,
+            });
+        }
+    }
+    Ok(ScalarValue::Null)
+}
+
+/// Return the current time.
+fn now(_: &[ScalarValue]) -> Result<ScalarValue> {
+    Ok(ScalarValue::Timestamp(timestamp_from_system_time(
+        SystemTime::now(),
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        access::heaptuple::TupleDesc, catalog::pg_attribute::PgAttribute, expr::RangeTable,
+    };
+    use sqlparser::{dialect::PostgreSqlDialect, parser::Parser, tokenizer::Tokenizer};
+
+    fn expr(sql: &str) -> Result<Expr> {
+        let tuple_desc = TupleDesc {
+            attrs: vec![PgAttribute {
+                attrelid: 1,
+                attname: String::from("a"),
+                attnum: 1,
+                attlen: -1,
+                atttypid: pg_type::VARCHAR_OID,
+                attnotnull: false,
+                attdefault: String::new(),
+            }],
+        };
+        let dialect = PostgreSqlDialect {};
+        let tokens = Tokenizer::new(&dialect, sql).tokenize()?;
+        let expr = Parser::new(tokens, &dialect).parse_expr()?;
+        Expr::create(&expr, &RangeTable::new("t", tuple_desc))
+    }
+
+    fn eval(sql: &str) -> Result<ScalarValue> {
+        expr(sql)?.eval(&[ScalarValue::Varchar(String::from("Tiny")).to_datum()?])
+    }
+
+    #[test]
+    fn test_scalar_functions() -> Result<()> {
+        let text = |value: &str| ScalarValue::Varchar(value.to_string());
+
+        assert_eq!(eval("upper(a)")?, text("TINY"));
+        assert_eq!(eval("LOWER(a || 'DB')")?, text("tinydb"));
+        assert_eq!(eval("length(a)")?, ScalarValue::Int(4));
+        assert_eq!(eval("length(NULL)")?, ScalarValue::Null);
+        assert_eq!(eval("abs(-5)")?, ScalarValue::Int(5));
+        assert_eq!(eval("abs(-2.5)")?, ScalarValue::Float8(2.5));
+        assert_eq!(eval("coalesce(NULL, a, 'x')")?, text("Tiny"));
+        assert_eq!(eval("coalesce(NULL, 1, 2.5)")?, ScalarValue::Float8(1.0));
+        assert_eq!(eval("coalesce(NULL, NULL)")?, ScalarValue::Null);
+        assert!(matches!(eval("now()")?, ScalarValue::Timestamp(_)));
+
+        // Arguments after the first non NULL value are not evaluated.
+        assert_eq!(eval("coalesce(1, 1 / 0)")?, ScalarValue::Int(1));
+
+        assert_eq!(expr("upper(a)")?.return_type(), Some(pg_type::TEXT_OID));
+        assert_eq!(expr("abs(NULL)")?.return_type(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_function_args() {
+        let err = |sql: &str| expr(sql).unwrap_err().downcast::<PgError>().unwrap();
+
+        assert_eq!(
+            err("unknown(a)").message,
+            "function unknown(character varying) does not exist"
+        );
+        assert_eq!(
+            err("upper(1)").message,
+            "function upper(integer) does not exist"
+        );
+        assert_eq!(err("upper(a, a)").code, SqlState::UndefinedFunction);
+        assert_eq!(err("now(1)").code, SqlState::UndefinedFunction);
+        assert_eq!(
+            err("abs(a)").message,
+            "function abs(character varying) does not exist"
+        );
+        assert_eq!(err("coalesce(1, a)").code, SqlState::DatatypeMismatch);
+    }
+}
@@ -0,0 +1,1891 @@
+use std::{cmp::Ordering, collections::BTreeSet, convert::TryFrom};
+
+use anyhow::{anyhow, bail, Result};
+use sqlparser::ast;
+
+use self::{aggregate::AggregateFunc, function::ScalarFunction};
+use crate::{
+    catalog::pg_type::{self, type_name, CastContext},
+    executor::batch::TupleBatch,
+    sql::{
+        encode::{
+            datetime::{self, parse_date, parse_timestamp},
+            decode,
+        },
+        error::{PgError, SqlState},
+        SQLError,
+    },
+    Datum, NullableDatum, Oid,
+};
+
+pub mod aggregate;
+pub mod function;
+pub mod range_table;
+
+pub use range_table::RangeTable;
+
+/// A SQL value produced when evaluating an expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScalarValue {
+    Null,
+    SmallInt(i16),
+    Int(i32),
+    BigInt(i64),
+    Float4(f32),
+    Float8(f64),
+    Varchar(String),
+    Bool(bool),
+
+    /// Number of days since 2000-01-01.
+    Date(i32),
+
+    /// Number of microseconds since 2000-01-01 00:00:00.
+    Timestamp(i64),
+}
+
+impl ScalarValue {
+    /// Decode the given datum of the given type to a scalar value.
+    pub fn from_datum(datum: &NullableDatum, typ: Oid) -> Result<Self> {
+        match datum {
+            Some(datum) => match typ {
+                pg_type::INT2_OID => Ok(Self::SmallInt(bincode::deserialize(datum)?)),
+                pg_type::INT_OID => Ok(Self::Int(bincode::deserialize(datum)?)),
+                pg_type::INT8_OID => Ok(Self::BigInt(bincode::deserialize(datum)?)),
+                pg_type::FLOAT4_OID => Ok(Self::Float4(bincode::deserialize(datum)?)),
+                pg_type::FLOAT8_OID => Ok(Self::Float8(bincode::deserialize(datum)?)),
+                // Text and varchar values have the same representation.
+                pg_type::TEXT_OID | pg_type::VARCHAR_OID => {
+                    Ok(Self::Varchar(String::from_utf8(datum.to_vec())?))
+                }
+                pg_type::BOOL_OID => Ok(Self::Bool(bincode::deserialize(datum)?)),
+                pg_type::DATE_OID => Ok(Self::Date(bincode::deserialize(datum)?)),
+                pg_type::TIMESTAMP_OID => Ok(Self::Timestamp(bincode::deserialize(datum)?)),
+                _ => bail!("Unsupported type {} to decode", typ),
+            },
+            None => Ok(Self::Null),
+        }
+    }
+
+    /// Encode the scalar value to a datum in the same representation returned by
+    /// HeapTuple::get_attr.
+    pub fn to_datum(&self) -> Result<NullableDatum> {
+        let datum = match self {
+            Self::Null => return Ok(None),
+            Self::SmallInt(value) => bincode::serialize(value)?,
+            Self::Int(value) => bincode::serialize(value)?,
+            Self::BigInt(value) => bincode::serialize(value)?,
+            Self::Float4(value) => bincode::serialize(value)?,
+            Self::Float8(value) => bincode::serialize(value)?,
+            Self::Varchar(value) => value.as_bytes().to_vec(),
+            Self::Bool(value) => bincode::serialize(value)?,
+            Self::Date(value) => bincode::serialize(value)?,
+            Self::Timestamp(value) => bincode::serialize(value)?,
+        };
+        Ok(Some(Datum::from(datum)))
+    }
+
+    /// Return true if the value is the boolean true.
+    pub fn is_true(&self) -> bool {
+        matches!(self, Self::Bool(true))
+    }
+
+    /// Return the type of the value. None for NULL.
+    pub fn typ(&self) -> Option<Oid> {
+        match self {
+            Self::Null => None,
+            Self::SmallInt(_) => Some(pg_type::INT2_OID),
+            Self::Int(_) => Some(pg_type::INT_OID),
+            Self::BigInt(_) => Some(pg_type::INT8_OID),
+            Self::Float4(_) => Some(pg_type::FLOAT4_OID),
+            Self::Float8(_) => Some(pg_type::FLOAT8_OID),
+            Self::Varchar(_) => Some(pg_type::VARCHAR_OID),
+            Self::Bool(_) => Some(pg_type::BOOL_OID),
+            Self::Date(_) => Some(pg_type::DATE_OID),
+            Self::Timestamp(_) => Some(pg_type::TIMESTAMP_OID),
+        }
+    }
+
+    /// Return the value as a 64 bit integer if it is an integer value.
+    pub fn to_i64(&self) -> Option<i64> {
+        match self {
+            Self::SmallInt(value) => Some(*value as i64),
+            Self::Int(value) => Some(*value as i64),
+            Self::BigInt(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Return the value as a 64 bit float if it is a numeric value.
+    pub fn to_f64(&self) -> Option<f64> {
+        match self {
+            Self::Float4(value) => Some(*value as f64),
+            Self::Float8(value) => Some(*value),
+            _ => self.to_i64().map(|value| value as f64),
+        }
+    }
+
+    /// Return the text representation of a non NULL value.
+    fn to_text(&self) -> Result<String> {
+        match self {
+            Self::Varchar(value) => Ok(value.clone()),
+            _ => match (self.to_datum()?, self.typ()) {
+                (Some(datum), Some(typ)) => decode(&datum, typ),
+                _ => bail!("can not convert NULL to text"),
+            },
+        }
+    }
+
+    /// Convert a numeric value to the given wider numeric type. Values of other types are
+    /// returned unchanged.
+    fn coerce(self, typ: Oid) -> Self {
+        if self.typ() == Some(typ) {
+            return self;
+        }
+        match typ {
+            pg_type::INT8_OID => self.to_i64().map_or(self, Self::BigInt),
+            pg_type::INT_OID => match self {
+                Self::SmallInt(value) => Self::Int(value as i32),
+                value => value,
+            },
+            pg_type::FLOAT4_OID => self
+                .to_f64()
+                .map_or(self, |value| Self::Float4(value as f32)),
+            pg_type::FLOAT8_OID => self.to_f64().map_or(self, Self::Float8),
+            _ => self,
+        }
+    }
+
+    /// Convert the value to the given type, failing if the value can not be represented on the
+    /// type. String values are parsed using the text representation of the type.
+    ///
+    /// The cast is applied even if it is only allowed on explicit casts, callers must check
+    /// the cast context with pg_type::can_cast.
+    pub fn cast(self, typ: Oid) -> Result<Self> {
+        let source = match self.typ() {
+            Some(source) if source != typ && !(is_string(source) && is_string(typ)) => source,
+            _ => return Ok(self),
+        };
+        if pg_type::cast_context(source, typ).is_none() {
+            return Err(cannot_cast(source, typ));
+        }
+        if is_string(typ) {
+            return Ok(Self::Varchar(self.to_text()?));
+        }
+
+        let out_of_range = || {
+            anyhow!(PgError::new(
+                SqlState::NumericValueOutOfRange,
+                format!("{} out of range", type_name(typ))
+            ))
+        };
+        match (self, typ) {
+            (Self::Varchar(value), _) => Self::parse(&value, typ),
+            (Self::Bool(value), _) => Ok(Self::Int(value as i32)),
+            (value, pg_type::BOOL_OID) => Ok(Self::Bool(value.to_i64() != Some(0))),
+            (Self::Date(value), _) => Ok(Self::Timestamp(datetime::date_to_timestamp(value))),
+            (Self::Timestamp(value), _) => Ok(Self::Date(datetime::timestamp_to_date(value))),
+            (value, pg_type::FLOAT8_OID) => Ok(value.to_f64().map_or(value, Self::Float8)),
+            (value, pg_type::FLOAT4_OID) => {
+                // Both values are numeric, otherwise the types don't have a cast.
+                let value = value.to_f64().unwrap();
+                if value.is_finite() && (value as f32).is_infinite() {
+                    return Err(out_of_range());
+                }
+                Ok(Self::Float4(value as f32))
+            }
+            (value, _) => {
+                // Float values are rounded to the nearest integer, like Postgres.
+                let value = match value.to_i64() {
+                    Some(value) => value,
+                    None => {
+                        let value = value.to_f64().unwrap().round_ties_even();
+                        if !value.is_finite() || value < i64::MIN as f64 || value >= i64::MAX as f64
+                        {
+                            return Err(out_of_range());
+                        }
+                        value as i64
+                    }
+                };
+                match typ {
+                    pg_type::INT2_OID => Ok(Self::SmallInt(
+                        i16::try_from(value).map_err(|_| out_of_range())?,
+                    )),
+                    pg_type::INT_OID => {
+                        Ok(Self::Int(i32::try_from(value).map_err(|_| out_of_range())?))
+                    }
+                    _ => Ok(Self::BigInt(value)),
+                }
+            }
+        }
+    }
+
+    /// Parse the text representation of a value of the given non string type.
+    fn parse(value: &str, typ: Oid) -> Result<Self> {
+        let invalid = || {
+            anyhow!(PgError::new(
+                SqlState::InvalidTextRepresentation,
+                format!(
+                    "invalid input syntax for type {}: \"{}\"",
+                    type_name(typ),
+                    value
+                )
+            ))
+        };
+        let out_of_range = || {
+            anyhow!(PgError::new(
+                SqlState::NumericValueOutOfRange,
+                format!(
+                    "value \"{}\" is out of range for type {}",
+                    value,
+                    type_name(typ)
+                )
+            ))
+        };
+
+        let trimmed = value.trim();
+        match typ {
+            pg_type::INT2_OID | pg_type::INT_OID | pg_type::INT8_OID => {
+                let value = trimmed.parse::<i64>().map_err(|_| invalid())?;
+                match typ {
+                    pg_type::INT2_OID => Ok(Self::SmallInt(
+                        i16::try_from(value).map_err(|_| out_of_range())?,
+                    )),
+                    pg_type::INT_OID => {
+                        Ok(Self::Int(i32::try_from(value).map_err(|_| out_of_range())?))
+                    }
+                    _ => Ok(Self::BigInt(value)),
+                }
+            }
+            pg_type::FLOAT4_OID => {
+                let value = trimmed.parse::<f32>().map_err(|_| invalid())?;
+                if value.is_infinite() && !trimmed.to_lowercase().contains("inf") {
+                    return Err(out_of_range());
+                }
+                Ok(Self::Float4(value))
+            }
+            pg_type::FLOAT8_OID => Ok(Self::Float8(trimmed.parse().map_err(|_| invalid())?)),
+            pg_type::BOOL_OID => match trimmed.to_lowercase().as_str() {
+                "t" | "true" | "y" | "yes" | "on" | "1" => Ok(Self::Bool(true)),
+                "f" | "false" | "n" | "no" | "off" | "0" => Ok(Self::Bool(false)),
+                _ => Err(invalid()),
+            },
+            pg_type::DATE_OID => Ok(Self::Date(parse_date(value)?)),
+            pg_type::TIMESTAMP_OID => Ok(Self::Timestamp(parse_timestamp(value)?)),
+            _ => Err(cannot_cast(pg_type::TEXT_OID, typ)),
+        }
+    }
+
+    /// Return the value as microseconds since 2000-01-01 if it is a date or timestamp value.
+    fn to_timestamp(&self) -> Option<i64> {
+        match self {
+            Self::Date(value) => Some(datetime::date_to_timestamp(*value)),
+            Self::Timestamp(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Compare two non NULL values of the same type. Numeric values of different types are
+    /// compared as the wider type.
+    ///
+    /// String values compared with date and timestamp values are parsed as a value of the same
+    /// type, so literals can be compared with date and timestamp columns.
+    pub fn compare(&self, other: &Self) -> Result<Ordering> {
+        match (self, other) {
+            (Self::Varchar(l), Self::Varchar(r)) => Ok(l.cmp(r)),
+            (Self::Bool(l), Self::Bool(r)) => Ok(l.cmp(r)),
+            (Self::Date(_), Self::Varchar(r)) => self.compare(&Self::Date(parse_date(r)?)),
+            (Self::Timestamp(_), Self::Varchar(r)) => {
+                self.compare(&Self::Timestamp(parse_timestamp(r)?))
+            }
+            (Self::Varchar(_), Self::Date(_)) | (Self::Varchar(_), Self::Timestamp(_)) => {
+                Ok(other.compare(self)?.reverse())
+            }
+            (Self::Date(_), _) | (Self::Timestamp(_), _) => {
+                match (self.to_timestamp(), other.to_timestamp()) {
+                    (Some(l), Some(r)) => Ok(l.cmp(&r)),
+                    _ => bail!(PgError::new(
+                        SqlState::DatatypeMismatch,
+                        format!("can not compare {:?} with {:?}", self, other)
+                    )),
+                }
+            }
+            _ => match (self.to_i64(), other.to_i64()) {
+                (Some(l), Some(r)) => Ok(l.cmp(&r)),
+                _ => match (self.to_f64(), other.to_f64()) {
+                    // NaN is considered greater than any other value.
+                    (Some(l), Some(r)) => Ok(l
+                        .partial_cmp(&r)
+                        .unwrap_or_else(|| l.is_nan().cmp(&r.is_nan()))),
+                    _ => bail!(PgError::new(
+                        SqlState::DatatypeMismatch,
+                        format!("can not compare {:?} with {:?}", self, other)
+                    )),
+                },
+            },
+        }
+    }
+}
+
+/// Return the error of casting a value of the source type to the target type.
+fn cannot_cast(source: Oid, target: Oid) -> anyhow::Error {
+    anyhow!(PgError::new(
+        SqlState::CannotCoerce,
+        format!(
+            "cannot cast type {} to {}",
+            type_name(source),
+            type_name(target)
+        )
+    ))
+}
+
+/// Truth value of a boolean expression using the SQL three-valued logic, where NULL is the
+/// unknown truth value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TruthValue {
+    True,
+    False,
+    Unknown,
+}
+
+impl TruthValue {
+    /// Return the truth value of the given boolean or NULL value, failing for values of other
+    /// types. The construct using the value is used on the error message.
+    pub fn from_scalar(value: &ScalarValue, construct: &str) -> Result<Self> {
+        match value {
+            ScalarValue::Bool(true) => Ok(Self::True),
+            ScalarValue::Bool(false) => Ok(Self::False),
+            ScalarValue::Null => Ok(Self::Unknown),
+            value => bail!(PgError::new(
+                SqlState::DatatypeMismatch,
+                format!(
+                    "argument of {} must be type boolean, not type {}",
+                    construct,
+                    value.typ().map_or("unknown", type_name)
+                )
+            )),
+        }
+    }
+
+    /// Return the boolean value of the truth value, NULL if it is unknown.
+    pub fn to_scalar(self) -> ScalarValue {
+        match self {
+            Self::True => ScalarValue::Bool(true),
+            Self::False => ScalarValue::Bool(false),
+            Self::Unknown => ScalarValue::Null,
+        }
+    }
+
+    /// False if any value is false, otherwise unknown if any value is unknown.
+    pub fn and(self, other: Self) -> Self {
+        match (self, other) {
+            (Self::False, _) | (_, Self::False) => Self::False,
+            (Self::True, Self::True) => Self::True,
+            _ => Self::Unknown,
+        }
+    }
+
+    /// True if any value is true, otherwise unknown if any value is unknown.
+    pub fn or(self, other: Self) -> Self {
+        match (self, other) {
+            (Self::True, _) | (_, Self::True) => Self::True,
+            (Self::False, Self::False) => Self::False,
+            _ => Self::Unknown,
+        }
+    }
+
+    /// The negation of unknown is still unknown.
+    pub fn negate(self) -> Self {
+        match self {
+            Self::True => Self::False,
+            Self::False => Self::True,
+            Self::Unknown => Self::Unknown,
+        }
+    }
+}
+
+/// Binary operators supported on expressions.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOperator {
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+    And,
+    Or,
+    Plus,
+    Minus,
+    Multiply,
+    Divide,
+    Modulo,
+    StringConcat,
+}
+
+impl BinaryOperator {
+    fn from_ast(op: &ast::BinaryOperator) -> Result<Self> {
+        match op {
+            ast::BinaryOperator::Eq => Ok(Self::Eq),
+            ast::BinaryOperator::NotEq => Ok(Self::NotEq),
+            ast::BinaryOperator::Lt => Ok(Self::Lt),
+            ast::BinaryOperator::LtEq => Ok(Self::LtEq),
+            ast::BinaryOperator::Gt => Ok(Self::Gt),
+            ast::BinaryOperator::GtEq => Ok(Self::GtEq),
+            ast::BinaryOperator::And => Ok(Self::And),
+            ast::BinaryOperator::Or => Ok(Self::Or),
+            ast::BinaryOperator::Plus => Ok(Self::Plus),
+            ast::BinaryOperator::Minus => Ok(Self::Minus),
+            ast::BinaryOperator::Multiply => Ok(Self::Multiply),
+            ast::BinaryOperator::Divide => Ok(Self::Divide),
+            ast::BinaryOperator::Modulo => Ok(Self::Modulo),
+            ast::BinaryOperator::StringConcat => Ok(Self::StringConcat),
+            _ => bail!(SQLError::Unsupported(op.to_string())),
+        }
+    }
+
+    /// Return true if the operator is an arithmetic operator.
+    fn is_arithmetic(&self) -> bool {
+        matches!(
+            self,
+            Self::Plus | Self::Minus | Self::Multiply | Self::Divide | Self::Modulo
+        )
+    }
+
+    /// Return the SQL symbol of the operator.
+    fn symbol(&self) -> &'static str {
+        match self {
+            Self::Eq => "=",
+            Self::NotEq => "<>",
+            Self::Lt => "<",
+            Self::LtEq => "<=",
+            Self::Gt => ">",
+            Self::GtEq => ">=",
+            Self::And => "AND",
+            Self::Or => "OR",
+            Self::Plus => "+",
+            Self::Minus => "-",
+            Self::Multiply => "*",
+            Self::Divide => "/",
+            Self::Modulo => "%",
+            Self::StringConcat => "||",
+        }
+    }
+
+    /// Return the type of the value produced by the operator for operands of the given types.
+    /// Operands of unknown type, e.g. NULL constants, are assumed to have the type of the other
+    /// operand.
+    ///
+    /// An error is returned if the operator don't accept operands of the given types.
+    fn return_type(&self, left: Option<Oid>, right: Option<Oid>) -> Result<Option<Oid>> {
+        if self.is_arithmetic() {
+            return match (left, right) {
+                (Some(l), Some(r)) => match numeric_type(l, r) {
+                    Some(typ) if *self != Self::Modulo || !is_float(typ) => Ok(Some(typ)),
+                    _ => Err(self.undefined(l, r)),
+                },
+                (Some(typ), None) | (None, Some(typ)) => Ok(Some(typ)),
+                (None, None) => Ok(None),
+            };
+        }
+
+        match self {
+            // Any value can be concatenated with a string value.
+            Self::StringConcat => match (left, right) {
+                (Some(l), Some(r)) if !is_string(l) && !is_string(r) => Err(self.undefined(l, r)),
+                _ => Ok(Some(pg_type::TEXT_OID)),
+            },
+            // Values can only be compared if one of the types can be implicitly cast to the
+            // other type.
+            Self::Eq | Self::NotEq | Self::Lt | Self::LtEq | Self::Gt | Self::GtEq => {
+                match (left, right) {
+                    (Some(l), Some(r))
+                        if !pg_type::can_cast(l, r, CastContext::Implicit)
+                            && !pg_type::can_cast(r, l, CastContext::Implicit) =>
+                    {
+                        Err(self.undefined(l, r))
+                    }
+                    _ => Ok(Some(pg_type::BOOL_OID)),
+                }
+            }
+            _ => Ok(Some(pg_type::BOOL_OID)),
+        }
+    }
+
+    /// Return the error of using the operator with operands of the given types.
+    fn undefined(&self, left: Oid, right: Oid) -> anyhow::Error {
+        anyhow!(PgError::new(
+            SqlState::UndefinedFunction,
+            format!(
+                "operator does not exist: {} {} {}",
+                type_name(left),
+                self.symbol(),
+                type_name(right)
+            )
+        ))
+    }
+}
+
+/// Return the type of an arithmetic operation between the given numeric types, which is the
+/// wider of both types. None if any of the types is not numeric.
+fn numeric_type(left: Oid, right: Oid) -> Option<Oid> {
+    const NUMERIC_TYPES: [Oid; 5] = [
+        pg_type::INT2_OID,
+        pg_type::INT_OID,
+        pg_type::INT8_OID,
+        pg_type::FLOAT4_OID,
+        pg_type::FLOAT8_OID,
+    ];
+    let rank = |typ| NUMERIC_TYPES.iter().position(|t| *t == typ);
+    Some(NUMERIC_TYPES[rank(left)?.max(rank(right)?)])
+}
+
+/// Return the type that values of both given types can be converted to, used for the results of
+/// the given construct, e.g. CASE. Unknown types, e.g. of NULL constants, match any type.
+fn common_type(construct: &str, left: Option<Oid>, right: Option<Oid>) -> Result<Option<Oid>> {
+    match (left, right) {
+        (Some(l), Some(r)) if l == r => Ok(Some(l)),
+        (Some(l), Some(r)) if is_string(l) && is_string(r) => Ok(Some(pg_type::TEXT_OID)),
+        (Some(l), Some(r)) => match numeric_type(l, r) {
+            Some(typ) => Ok(Some(typ)),
+            None => bail!(PgError::new(
+                SqlState::DatatypeMismatch,
+                format!(
+                    "{} types {} and {} cannot be matched",
+                    construct,
+                    type_name(l),
+                    type_name(r)
+                )
+            )),
+        },
+        (l, r) => Ok(l.or(r)),
+    }
+}
+
+/// Return true if the given type is a floating point type.
+fn is_float(typ: Oid) -> bool {
+    typ == pg_type::FLOAT4_OID || typ == pg_type::FLOAT8_OID
+}
+
+/// Return true if the given type is a string type.
+fn is_string(typ: Oid) -> bool {
+    typ == pg_type::TEXT_OID || typ == pg_type::VARCHAR_OID
+}
+
+/// An expression tree ready to be evaluated by the executor.
+///
+/// Column references are already resolved to the index of the attribute on
+/// the tuple that the expression is evaluated against.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    /// Reference to an attribute value of the input tuple.
+    Column { index: usize, typ: Oid },
+
+    /// Constant value.
+    Const(ScalarValue),
+
+    /// Binary operation between two expressions.
+    BinaryOp {
+        op: BinaryOperator,
+        left: Box<Expr>,
+        right: Box<Expr>,
+    },
+
+    /// Logical negation.
+    Not(Box<Expr>),
+
+    /// Arithmetic negation.
+    Negate(Box<Expr>),
+
+    /// IS NULL test.
+    IsNull(Box<Expr>),
+
+    /// IS NOT NULL test.
+    IsNotNull(Box<Expr>),
+
+    /// Conversion of a value to another type.
+    Cast { expr: Box<Expr>, typ: Oid },
+
+    /// Built-in scalar function call.
+    Function {
+        func: &'static ScalarFunction,
+        args: Vec<Expr>,
+
+        /// Type of the function result. None if it can not be determined, e.g. abs(NULL).
+        typ: Option<Oid>,
+    },
+
+    /// Conditional expression, returning the result of the first condition that is true. If an
+    /// operand is present each condition is compared with the operand value instead.
+    Case {
+        operand: Option<Box<Expr>>,
+        conditions: Vec<(Expr, Expr)>,
+        else_result: Option<Box<Expr>>,
+
+        /// Type of all results. None if all results are NULL.
+        typ: Option<Oid>,
+    },
+}
+
+impl Expr {
+    /// Create a new expression from the given parsed expression, resolving column references
+    /// using the given range table.
+    pub fn create(expr: &ast::Expr, range_table: &RangeTable) -> Result<Self> {
+        match expr {
+            ast::Expr::Identifier(ident) => Self::column(range_table, std::slice::from_ref(ident)),
+            ast::Expr::CompoundIdentifier(idents) => Self::column(range_table, idents),
+            ast::Expr::Value(value) => Ok(Self::Const(const_value(value)?)),
+            ast::Expr::TypedString { data_type, value } => {
+                let (typ, _) = pg_type::oid_type_and_size(data_type)?;
+                Ok(Self::Const(ScalarValue::Varchar(value.clone()).cast(typ)?))
+            }
+            ast::Expr::Cast { expr, data_type } => {
+                let (typ, _) = pg_type::oid_type_and_size(data_type)?;
+                Self::cast(Self::create(expr, range_table)?, typ, CastContext::Explicit)
+            }
+            ast::Expr::Nested(expr) => Self::create(expr, range_table),
+            ast::Expr::BinaryOp { left, op, right } => {
+                let op = BinaryOperator::from_ast(op)?;
+                let left = Self::create(left, range_table)?;
+                let right = Self::create(right, range_table)?;
+                Self::binary(op, left, right)
+            }
+            ast::Expr::UnaryOp {
+                op: ast::UnaryOperator::Not,
+                expr,
+            } => Ok(Self::Not(Box::new(Self::create(expr, range_table)?))),
+            ast::Expr::UnaryOp {
+                op: ast::UnaryOperator::Minus,
+                expr,
+            } => {
+                let expr = Self::create(expr, range_table)?;
+                match expr.return_type() {
+                    Some(typ) if numeric_type(typ, typ).is_none() => {
+                        bail!(PgError::new(
+                            SqlState::UndefinedFunction,
+                            format!("operator does not exist: - {}", type_name(typ))
+                        ))
+                    }
+                    _ => Ok(Self::Negate(Box::new(expr))),
+                }
+            }
+            ast::Expr::UnaryOp {
+                op: ast::UnaryOperator::Plus,
+                expr,
+            } => Self::create(expr, range_table),
+            ast::Expr::IsNull(expr) => Ok(Self::IsNull(Box::new(Self::create(expr, range_table)?))),
+            ast::Expr::IsNotNull(expr) => {
+                Ok(Self::IsNotNull(Box::new(Self::create(expr, range_table)?)))
+            }
+            ast::Expr::Case {
+                operand,
+                conditions,
+                results,
+                else_result,
+            } => Self::case(
+                operand.as_deref(),
+                conditions,
+                results,
+                else_result.as_deref(),
+                range_table,
+            ),
+            ast::Expr::Function(function) => Self::function(function, range_table),
+            // IN and BETWEEN are rewritten using comparison operators, so the executor don't
+            // need to handle them.
+            ast::Expr::InList {
+                expr: operand,
+                list,
+                negated,
+            } => {
+                let operand = Self::create(operand, range_table)?;
+                let mut in_list: Option<Self> = None;
+                for value in list {
+                    let value = Self::create(value, range_table)?;
+                    let eq = Self::binary(BinaryOperator::Eq, operand.clone(), value)?;
+                    in_list = Some(match in_list {
+                        Some(in_list) => Self::binary(BinaryOperator::Or, in_list, eq)?,
+                        None => eq,
+                    });
+                }
+                match in_list {
+                    Some(in_list) if *negated => Ok(Self::Not(Box::new(in_list))),
+                    Some(in_list) => Ok(in_list),
+                    None => bail!(SQLError::Unsupported(expr.to_string())),
+                }
+            }
+            ast::Expr::Between {
+                expr,
+                negated,
+                low,
+                high,
+            } => {
+                let expr = Self::create(expr, range_table)?;
+                let low = Self::binary(
+                    BinaryOperator::GtEq,
+                    expr.clone(),
+                    Self::create(low, range_table)?,
+                )?;
+                let high =
+                    Self::binary(BinaryOperator::LtEq, expr, Self::create(high, range_table)?)?;
+                let between = Self::binary(BinaryOperator::And, low, high)?;
+                if *negated {
+                    Ok(Self::Not(Box::new(between)))
+                } else {
+                    Ok(between)
+                }
+            }
+            _ => bail!(SQLError::Unsupported(expr.to_string())),
+        }
+    }
+
+    /// Create a built-in scalar function call, checking the number and types of the arguments.
+    fn function(function: &ast::Function, range_table: &RangeTable) -> Result<Self> {
+        let name = function.name.to_string();
+        if AggregateFunc::from_name(&name).is_some() || function.over.is_some() {
+            bail!(SQLError::Unsupported(function.to_string()));
+        }
+
+        let mut args = Vec::with_capacity(function.args.len());
+        for arg in &function.args {
+            match arg {
+                ast::FunctionArg::Unnamed(ast::FunctionArgExpr::Expr(expr)) => {
+                    args.push(Self::create(expr, range_table)?)
+                }
+                _ => bail!(SQLError::Unsupported(function.to_string())),
+            }
+        }
+
+        let arg_types = args.iter().map(|arg| arg.return_type()).collect::<Vec<_>>();
+        let func = match ScalarFunction::lookup(&name) {
+            Some(func) if !function.distinct => func,
+            _ => return Err(function::undefined_function(&name, &arg_types)),
+        };
+        let typ = func.check_args(&arg_types)?;
+
+        Ok(Self::Function { func, args, typ })
+    }
+
+    /// Create a CASE expression. All results must have the same type, or numeric types that are
+    /// converted to the wider type.
+    fn case(
+        operand: Option<&ast::Expr>,
+        conditions: &[ast::Expr],
+        results: &[ast::Expr],
+        else_result: Option<&ast::Expr>,
+        range_table: &RangeTable,
+    ) -> Result<Self> {
+        let operand = match operand {
+            Some(operand) => Some(Box::new(Self::create(operand, range_table)?)),
+            None => None,
+        };
+
+        let mut typ = None;
+        let mut unify = |expr: &Expr| -> Result<()> {
+            typ = common_type("CASE", typ, expr.return_type())?;
+            Ok(())
+        };
+
+        let mut branches = Vec::with_capacity(conditions.len());
+        for (condition, result) in conditions.iter().zip(results) {
+            // Conditions are compared with the operand value, so literals are converted to the
+            // operand type.
+            let condition = Self::create(condition, range_table)?
+                .coerce_literal(operand.as_ref().and_then(|operand| operand.return_type()))?;
+            match condition.return_type() {
+                Some(typ) if operand.is_none() && typ != pg_type::BOOL_OID => {
+                    bail!(PgError::new(
+                        SqlState::DatatypeMismatch,
+                        format!(
+                            "argument of CASE/WHEN must be type boolean, not type {}",
+                            type_name(typ)
+                        )
+                    ))
+                }
+                _ => {}
+            }
+
+            let result = Self::create(result, range_table)?;
+            unify(&result)?;
+            branches.push((condition, result));
+        }
+
+        let else_result = match else_result {
+            Some(else_result) => {
+                let else_result = Self::create(else_result, range_table)?;
+                unify(&else_result)?;
+                Some(Box::new(else_result))
+            }
+            None => None,
+        };
+
+        Ok(Self::Case {
+            operand,
+            conditions: branches,
+            else_result,
+            typ,
+        })
+    }
+
+    /// Create a binary operation between the given expressions, checking that the operator
+    /// accept the operand types before executing.
+    fn binary(op: BinaryOperator, left: Self, right: Self) -> Result<Self> {
+        let (left, right) = match op {
+            BinaryOperator::And | BinaryOperator::Or | BinaryOperator::StringConcat => {
+                (left, right)
+            }
+            _ => {
+                let (left_type, right_type) = (left.return_type(), right.return_type());
+                (
+                    left.coerce_literal(right_type)?,
+                    right.coerce_literal(left_type)?,
+                )
+            }
+        };
+        op.return_type(left.return_type(), right.return_type())?;
+
+        Ok(Self::BinaryOp {
+            op,
+            left: Box::new(left),
+            right: Box::new(right),
+        })
+    }
+
+    /// Create a conversion of the given expression to the given type, failing if the expression
+    /// type can not be cast to the type on the given context. Constants are converted when the
+    /// expression is created.
+    fn cast(expr: Self, typ: Oid, context: CastContext) -> Result<Self> {
+        match expr.return_type() {
+            Some(source) if !pg_type::can_cast(source, typ, context) => {
+                Err(cannot_cast(source, typ))
+            }
+            _ => match expr {
+                Self::Const(value) => Ok(Self::Const(value.cast(typ)?)),
+                expr => Ok(Self::Cast {
+                    expr: Box::new(expr),
+                    typ,
+                }),
+            },
+        }
+    }
+
+    /// Convert a string literal to the given type of the other operand. String literals have an
+    /// unknown type on Postgres, so they can be compared with values of any type, e.g. an integer
+    /// column with '42'.
+    fn coerce_literal(self, typ: Option<Oid>) -> Result<Self> {
+        match (self, typ) {
+            (Self::Const(value @ ScalarValue::Varchar(_)), Some(typ)) if !is_string(typ) => {
+                Ok(Self::Const(value.cast(typ)?))
+            }
+            (expr, _) => Ok(expr),
+        }
+    }
+
+    /// Create a column reference expression for the given identifiers.
+    fn column(range_table: &RangeTable, idents: &[ast::Ident]) -> Result<Self> {
+        let index = range_table.resolve_column(idents)?;
+        Ok(Self::column_index(range_table, index))
+    }
+
+    /// Create a column reference expression for the attribute of the given index on range table.
+    pub fn column_index(range_table: &RangeTable, index: usize) -> Self {
+        Self::Column {
+            index,
+            typ: range_table.tuple_desc.attrs[index].atttypid,
+        }
+    }
+
+    /// Return the type of the value produced by the expression. None if the type can not be
+    /// determined, e.g. a NULL constant.
+    pub fn return_type(&self) -> Option<Oid> {
+        match self {
+            Self::Column { typ, .. } => Some(*typ),
+            Self::Const(value) => value.typ(),
+            // The operand types were already checked when the expression was created.
+            Self::BinaryOp { op, left, right } => op
+                .return_type(left.return_type(), right.return_type())
+                .unwrap_or_default(),
+            Self::Negate(expr) => expr.return_type(),
+            Self::Cast { typ, .. } => Some(*typ),
+            Self::Not(_) | Self::IsNull(_) | Self::IsNotNull(_) => Some(pg_type::BOOL_OID),
+            Self::Function { typ, .. } | Self::Case { typ, .. } => *typ,
+        }
+    }
+
+    /// Return the value, of the column type, that the column of the given index must be equal to
+    /// for the expression to be true. None if the expression don't require the column to be
+    /// equal to a constant.
+    ///
+    /// Only the conditions of a conjunction that compare the column with a non NULL constant of
+    /// the same kind of type are considered, and the constant must be exactly representable on
+    /// the column type, e.g. an integer column is never equal to 1.5.
+    pub fn column_equality(&self, column: usize) -> Option<ScalarValue> {
+        match self {
+            Self::BinaryOp {
+                op: BinaryOperator::And,
+                left,
+                right,
+            } => left
+                .column_equality(column)
+                .or_else(|| right.column_equality(column)),
+            Self::BinaryOp {
+                op: BinaryOperator::Eq,
+                left,
+                right,
+            } => {
+                let (typ, value) = match (left.as_ref(), right.as_ref()) {
+                    (Self::Column { index, typ }, Self::Const(value))
+                    | (Self::Const(value), Self::Column { index, typ })
+                        if *index == column =>
+                    {
+                        (*typ, value)
+                    }
+                    _ => return None,
+                };
+                let source = value.typ()?;
+                if source != typ
+                    && numeric_type(source, typ).is_none()
+                    && !(is_string(source) && is_string(typ))
+                {
+                    return None;
+                }
+
+                let converted = value.clone().cast(typ).ok()?;
+                match converted.compare(value) {
+                    Ok(Ordering::Equal) => Some(converted),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Return the direct subexpressions of the expression.
+    fn children(&self) -> Vec<&Self> {
+        match self {
+            Self::Column { .. } | Self::Const(_) => Vec::new(),
+            Self::BinaryOp { left, right, .. } => vec![left, right],
+            Self::Not(expr)
+            | Self::Negate(expr)
+            | Self::IsNull(expr)
+            | Self::IsNotNull(expr)
+            | Self::Cast { expr, .. } => vec![expr],
+            Self::Function { args, .. } => args.iter().collect(),
+            Self::Case {
+                operand,
+                conditions,
+                else_result,
+                ..
+            } => operand
+                .iter()
+                .map(|operand| operand.as_ref())
+                .chain(
+                    conditions
+                        .iter()
+                        .flat_map(|(condition, result)| vec![condition, result]),
+                )
+                .chain(else_result.iter().map(|result| result.as_ref()))
+                .collect(),
+        }
+    }
+
+    /// Return the expression with each direct subexpression replaced by the result of the given
+    /// function.
+    fn map_children<F>(self, mut f: F) -> Self
+    where
+        F: FnMut(Self) -> Self,
+    {
+        let mut map = |expr: Box<Self>| Box::new(f(*expr));
+        match self {
+            Self::Column { .. } | Self::Const(_) => self,
+            Self::BinaryOp { op, left, right } => Self::BinaryOp {
+                op,
+                left: map(left),
+                right: map(right),
+            },
+            Self::Not(expr) => Self::Not(map(expr)),
+            Self::Negate(expr) => Self::Negate(map(expr)),
+            Self::IsNull(expr) => Self::IsNull(map(expr)),
+            Self::IsNotNull(expr) => Self::IsNotNull(map(expr)),
+            Self::Cast { expr, typ } => Self::Cast {
+                expr: map(expr),
+                typ,
+            },
+            Self::Function { func, args, typ } => Self::Function {
+                func,
+                args: args.into_iter().map(|arg| *map(Box::new(arg))).collect(),
+                typ,
+            },
+            Self::Case {
+                operand,
+                conditions,
+                else_result,
+                typ,
+            } => Self::Case {
+                operand: operand.map(&mut map),
+                conditions: conditions
+                    .into_iter()
+                    .map(|(condition, result)| (*map(Box::new(condition)), *map(Box::new(result))))
+                    .collect(),
+                else_result: else_result.map(&mut map),
+                typ,
+            },
+        }
+    }
+
+    /// Add the index of each column referenced by the expression to the given set.
+    pub fn referenced_columns(&self, columns: &mut BTreeSet<usize>) {
+        match self {
+            Self::Column { index, .. } => {
+                columns.insert(*index);
+            }
+            _ => {
+                for child in self.children() {
+                    child.referenced_columns(columns);
+                }
+            }
+        }
+    }
+
+    /// Return the expression with each column reference replaced by a reference to the column
+    /// of the index returned by the given function.
+    pub fn remap_columns<F>(self, f: &F) -> Self
+    where
+        F: Fn(usize) -> usize,
+    {
+        match self {
+            Self::Column { index, typ } => Self::Column {
+                index: f(index),
+                typ,
+            },
+            expr => expr.map_children(|child| child.remap_columns(f)),
+        }
+    }
+
+    /// Split the expression into the conditions of a conjunction, so the expression is true
+    /// only if all of the returned conditions are true.
+    pub fn into_conjuncts(self) -> Vec<Self> {
+        match self {
+            Self::BinaryOp {
+                op: BinaryOperator::And,
+                left,
+                right,
+            } => {
+                let mut conjuncts = left.into_conjuncts();
+                conjuncts.extend(right.into_conjuncts());
+                conjuncts
+            }
+            expr => vec![expr],
+        }
+    }
+
+    /// Return the conjunction of the given conditions, evaluated on the same order. None if
+    /// there is no condition.
+    pub fn conjunction(conjuncts: Vec<Self>) -> Option<Self> {
+        conjuncts.into_iter().reduce(|left, right| Self::BinaryOp {
+            op: BinaryOperator::And,
+            left: Box::new(left),
+            right: Box::new(right),
+        })
+    }
+
+    /// Return the expression with each subexpression that don't reference any column replaced
+    /// by its value, so it is not evaluated again for each tuple.
+    ///
+    /// Subexpressions that fail to evaluate are kept, so the error is only returned if the
+    /// expression is evaluated. Logical operators with a constant operand that determines the
+    /// result, or that don't change it, are also simplified, e.g. `a > 1 AND true` is folded to
+    /// `a > 1`.
+    pub fn fold_constants(self) -> Self {
+        let expr = self.map_children(Self::fold_constants);
+        if let Self::Column { .. } | Self::Const(_) = expr {
+            return expr;
+        }
+
+        if expr
+            .children()
+            .iter()
+            .all(|child| matches!(child, Self::Const(_)))
+        {
+            return match expr.eval(&[]) {
+                Ok(value) => Self::Const(value),
+                Err(_) => expr,
+            };
+        }
+
+        match expr {
+            Self::BinaryOp {
+                op: op @ (BinaryOperator::And | BinaryOperator::Or),
+                left,
+                right,
+            } => Self::simplify_logical(op, *left, *right),
+            expr => expr,
+        }
+    }
+
+    /// Simplify a logical operation where at most one of the operands is constant.
+    ///
+    /// The right operand is not evaluated if the left operand determines the result, so it
+    /// can be removed regardless of its type. Otherwise the non constant operand is only
+    /// removed or returned alone if it is a boolean, so type errors are still returned.
+    fn simplify_logical(op: BinaryOperator, left: Self, right: Self) -> Self {
+        let truth = |expr: &Self| match expr {
+            Self::Const(value) => TruthValue::from_scalar(value, op.symbol()).ok(),
+            _ => None,
+        };
+        let is_bool = |expr: &Self| expr.return_type() == Some(pg_type::BOOL_OID);
+
+        // Value that determines the result of the operation, and value that don't change it.
+        let (absorbing, neutral) = match op {
+            BinaryOperator::And => (TruthValue::False, TruthValue::True),
+            _ => (TruthValue::True, TruthValue::False),
+        };
+
+        match (truth(&left), truth(&right)) {
+            (Some(value), _) if value == absorbing => left,
+            (Some(value), _) if value == neutral && is_bool(&right) => right,
+            (_, Some(value)) if value == absorbing && is_bool(&left) => right,
+            (_, Some(value)) if value == neutral && is_bool(&left) => left,
+            _ => Self::BinaryOp {
+                op,
+                left: Box::new(left),
+                right: Box::new(right),
+            },
+        }
+    }
+
+    /// Evaluate the expression against the given tuple values.
+    pub fn eval(&self, values: &[NullableDatum]) -> Result<ScalarValue> {
+        match self {
+            Self::Column { index, typ } => match values.get(*index) {
+                Some(datum) => ScalarValue::from_datum(datum, *typ),
+                None => bail!("attribute index {} out of range", index),
+            },
+            Self::Const(value) => Ok(value.clone()),
+            Self::BinaryOp {
+                op: op @ (BinaryOperator::And | BinaryOperator::Or),
+                left,
+                right,
+            } => {
+                let construct = op.symbol();
+                let left = TruthValue::from_scalar(&left.eval(values)?, construct)?;
+
+                // The right operand don't change the result if the left is false for AND or
+                // true for OR, so it is not evaluated.
+                let value = match (op, left) {
+                    (BinaryOperator::And, TruthValue::False) => TruthValue::False,
+                    (BinaryOperator::Or, TruthValue::True) => TruthValue::True,
+                    _ => {
+                        let right = TruthValue::from_scalar(&right.eval(values)?, construct)?;
+                        if *op == BinaryOperator::And {
+                            left.and(right)
+                        } else {
+                            left.or(right)
+                        }
+                    }
+                };
+                Ok(value.to_scalar())
+            }
+            Self::BinaryOp { op, left, right } => {
+                let left = left.eval(values)?;
+                let right = right.eval(values)?;
+                eval_binary_op(*op, &left, &right)
+            }
+            Self::Not(expr)
+            | Self::Negate(expr)
+            | Self::IsNull(expr)
+            | Self::IsNotNull(expr)
+            | Self::Cast { expr, .. } => self.eval_unary(expr.eval(values)?),
+            Self::Function { func, args, typ } => func.eval(args, *typ, values),
+            Self::Case {
+                operand,
+                conditions,
+                else_result,
+                typ,
+            } => {
+                let operand = match operand {
+                    Some(operand) => Some(operand.eval(values)?),
+                    None => None,
+                };
+
+                // Results are converted to the type of the CASE expression.
+                let coerce = |value: ScalarValue| match typ {
+                    Some(typ) => value.coerce(*typ),
+                    None => value,
+                };
+
+                // Only the result of the first matched condition is evaluated.
+                for (condition, result) in conditions {
+                    let condition = condition.eval(values)?;
+                    let matched = match &operand {
+                        Some(operand) => eval_binary_op(BinaryOperator::Eq, operand, &condition)?,
+                        None => condition,
+                    };
+                    if matched.is_true() {
+                        return Ok(coerce(result.eval(values)?));
+                    }
+                }
+
+                match else_result {
+                    Some(else_result) => Ok(coerce(else_result.eval(values)?)),
+                    None => Ok(ScalarValue::Null),
+                }
+            }
+        }
+    }
+
+    /// Evaluate the expression against all tuples of the given batch, returning one value per
+    /// tuple.
+    ///
+    /// Columns, constants and operators are evaluated over whole columns of values. Logical
+    /// operators, CASE and functions are evaluated tuple by tuple, so operands that are not
+    /// needed to produce the result of a tuple are still not evaluated.
+    pub fn eval_batch(&self, batch: &TupleBatch) -> Result<Vec<ScalarValue>> {
+        match self {
+            Self::Column { index, typ } => match batch.columns.get(*index) {
+                Some(column) => column
+                    .iter()
+                    .map(|datum| ScalarValue::from_datum(datum, *typ))
+                    .collect(),
+                // Empty batches created by default have no columns.
+                None if batch.is_empty() => Ok(Vec::new()),
+                None => bail!("attribute index {} out of range", index),
+            },
+            Self::Const(value) => Ok(vec![value.clone(); batch.len()]),
+            Self::BinaryOp { op, left, right }
+                if !matches!(op, BinaryOperator::And | BinaryOperator::Or) =>
+            {
+                let left = left.eval_batch(batch)?;
+                let right = right.eval_batch(batch)?;
+                left.iter()
+                    .zip(&right)
+                    .map(|(left, right)| eval_binary_op(*op, left, right))
+                    .collect()
+            }
+            Self::Not(expr)
+            | Self::Negate(expr)
+            | Self::IsNull(expr)
+            | Self::IsNotNull(expr)
+            | Self::Cast { expr, .. } => expr
+                .eval_batch(batch)?
+                .into_iter()
+                .map(|value| self.eval_unary(value))
+                .collect(),
+            _ => (0..batch.len())
+                .map(|index| self.eval(&batch.row(index)))
+                .collect(),
+        }
+    }
+
+    /// Apply an unary expression to the already evaluated value of its operand.
+    fn eval_unary(&self, value: ScalarValue) -> Result<ScalarValue> {
+        match self {
+            Self::Not(_) => Ok(TruthValue::from_scalar(&value, "NOT")?.negate().to_scalar()),
+            Self::Negate(_) => match value {
+                ScalarValue::Null => Ok(ScalarValue::Null),
+                value => eval_arithmetic(BinaryOperator::Minus, &ScalarValue::SmallInt(0), &value),
+            },
+            Self::IsNull(_) => Ok(ScalarValue::Bool(value == ScalarValue::Null)),
+            Self::IsNotNull(_) => Ok(ScalarValue::Bool(value != ScalarValue::Null)),
+            Self::Cast { typ, .. } => value.cast(*typ),
+            _ => unreachable!("{:?} is not an unary expression", self),
+        }
+    }
+}
+
+/// Evaluate a binary operation. Any NULL operand produces a NULL result.
+fn eval_binary_op(
+    op: BinaryOperator,
+    left: &ScalarValue,
+    right: &ScalarValue,
+) -> Result<ScalarValue> {
+    if *left == ScalarValue::Null || *right == ScalarValue::Null {
+        return Ok(ScalarValue::Null);
+    }
+
+    if op.is_arithmetic() {
+        return eval_arithmetic(op, left, right);
+    }
+
+    let value = match op {
+        BinaryOperator::Eq => left.compare(right)? == Ordering::Equal,
+        BinaryOperator::NotEq => left.compare(right)? != Ordering::Equal,
+        BinaryOperator::Lt => left.compare(right)? == Ordering::Less,
+        BinaryOperator::LtEq => left.compare(right)? != Ordering::Greater,
+        BinaryOperator::Gt => left.compare(right)? == Ordering::Greater,
+        BinaryOperator::GtEq => left.compare(right)? != Ordering::Less,
+        BinaryOperator::StringConcat => {
+            op.return_type(left.typ(), right.typ())?;
+            return Ok(ScalarValue::Varchar(left.to_text()? + &right.to_text()?));
+        }
+        BinaryOperator::And | BinaryOperator::Or => {
+            unreachable!("logical operators are evaluated by Expr::eval")
+        }
+        _ => unreachable!("arithmetic operators are evaluated by eval_arithmetic"),
+    };
+
+    Ok(ScalarValue::Bool(value))
+}
+
+/// Evaluate an arithmetic operation between two non NULL numeric values. The operation is
+/// computed on the wider type of both values, failing if the result overflow the type.
+fn eval_arithmetic(
+    op: BinaryOperator,
+    left: &ScalarValue,
+    right: &ScalarValue,
+) -> Result<ScalarValue> {
+    let typ = match op.return_type(left.typ(), right.typ())? {
+        Some(typ) => typ,
+        None => return Ok(ScalarValue::Null),
+    };
+    let division_by_zero = || anyhow!(PgError::new(SqlState::DivisionByZero, "division by zero"));
+
+    if is_float(typ) {
+        // Both values are numeric, otherwise return_type would fail.
+        let (l, r) = (left.to_f64().unwrap(), right.to_f64().unwrap());
+        let value = match op {
+            BinaryOperator::Plus => l + r,
+            BinaryOperator::Minus => l - r,
+            BinaryOperator::Multiply => l * r,
+            BinaryOperator::Divide if r == 0.0 => return Err(division_by_zero()),
+            BinaryOperator::Divide => l / r,
+            _ => unreachable!("{:?} is not a float operator", op),
+        };
+        return Ok(if typ == pg_type::FLOAT4_OID {
+            ScalarValue::Float4(value as f32)
+        } else {
+            ScalarValue::Float8(value)
+        });
+    }
+
+    let (l, r) = (left.to_i64().unwrap(), right.to_i64().unwrap());
+    let value = match op {
+        BinaryOperator::Plus => l.checked_add(r),
+        BinaryOperator::Minus => l.checked_sub(r),
+        BinaryOperator::Multiply => l.checked_mul(r),
+        BinaryOperator::Divide | BinaryOperator::Modulo if r == 0 => return Err(division_by_zero()),
+        BinaryOperator::Divide => l.checked_div(r),
+        BinaryOperator::Modulo => Some(l.wrapping_rem(r)),
+        _ => unreachable!("{:?} is not an integer operator", op),
+    };
+
+    let out_of_range = || {
+        anyhow!(PgError::new(
+            SqlState::NumericValueOutOfRange,
+            format!("{} out of range", type_name(typ))
+        ))
+    };
+    let value = value.ok_or_else(out_of_range)?;
+    match typ {
+        pg_type::INT2_OID => Ok(ScalarValue::SmallInt(
+            i16::try_from(value).map_err(|_| out_of_range())?,
+        )),
+        pg_type::INT_OID => Ok(ScalarValue::Int(
+            i32::try_from(value).map_err(|_| out_of_range())?,
+        )),
+        _ => Ok(ScalarValue::BigInt(value)),
+    }
+}
+
+/// Parse a numeric literal. Integer literals are integer if fit, otherwise bigint, and literals
+/// with a decimal point are double precision.
+fn parse_number(value: &str) -> Result<ScalarValue> {
+    if let Ok(value) = value.parse::<i32>() {
+        return Ok(ScalarValue::Int(value));
+    }
+    if let Ok(value) = value.parse::<i64>() {
+        return Ok(ScalarValue::BigInt(value));
+    }
+    value.parse::<f64>().map(ScalarValue::Float8).map_err(|_| {
+        anyhow!(PgError::new(
+            SqlState::InvalidTextRepresentation,
+            format!("invalid numeric literal {}", value)
+        ))
+    })
+}
+
+/// Convert a parsed literal value to a scalar value.
+pub fn const_value(value: &ast::Value) -> Result<ScalarValue> {
+    match value {
+        ast::Value::Number(value, _) => parse_number(value),
+        ast::Value::SingleQuotedString(value) => Ok(ScalarValue::Varchar(value.clone())),
+        ast::Value::Boolean(value) => Ok(ScalarValue::Bool(*value)),
+        ast::Value::Null => Ok(ScalarValue::Null),
+        _ => bail!(SQLError::Unsupported(value.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        access::heaptuple::TupleDesc, catalog::pg_attribute::PgAttribute, executor::TupleTableSlot,
+    };
+    use sqlparser::{dialect::PostgreSqlDialect, parser::Parser, tokenizer::Tokenizer};
+
+    fn tuple_desc() -> TupleDesc {
+        TupleDesc {
+            attrs: vec![
+                PgAttribute {
+                    attrelid: 1,
+                    attname: String::from("a"),
+                    attnum: 1,
+                    attlen: 4,
+                    atttypid: pg_type::INT_OID,
+                    attnotnull: false,
+                    attdefault: String::new(),
+                },
+                PgAttribute {
+                    attrelid: 1,
+                    attname: String::from("b"),
+                    attnum: 2,
+                    attlen: -1,
+                    atttypid: pg_type::VARCHAR_OID,
+                    attnotnull: false,
+                    attdefault: String::new(),
+                },
+            ],
+        }
+    }
+
+    fn expr(sql: &str) -> Result<Expr> {
+        let dialect = PostgreSqlDialect {};
+        let tokens = Tokenizer::new(&dialect, sql).tokenize()?;
+        let expr = Parser::new(tokens, &dialect).parse_expr()?;
+        Expr::create(&expr, &RangeTable::new("t", tuple_desc()))
+    }
+
+    fn eval(sql: &str, values: &[NullableDatum]) -> Result<ScalarValue> {
+        expr(sql)?.eval(values)
+    }
+
+    #[test]
+    fn test_eval_comparison() -> Result<()> {
+        let values = vec![
+            ScalarValue::Int(10).to_datum()?,
+            ScalarValue::Varchar(String::from("abc")).to_datum()?,
+        ];
+
+        assert_eq!(eval("a = 10", &values)?, ScalarValue::Bool(true));
+        assert_eq!(eval("a > 10", &values)?, ScalarValue::Bool(false));
+        assert_eq!(
+            eval("a >= 10 AND b = 'abc'", &values)?,
+            ScalarValue::Bool(true)
+        );
+        assert_eq!(
+            eval("a < 5 OR b <> 'abc'", &values)?,
+            ScalarValue::Bool(false)
+        );
+        assert_eq!(eval("NOT (a <= 9)", &values)?, ScalarValue::Bool(true));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_column_equality() -> Result<()> {
+        let equality = |sql: &str, column: usize| -> Result<Option<ScalarValue>> {
+            Ok(expr(sql)?.column_equality(column))
+        };
+
+        assert_eq!(equality("a = 10", 0)?, Some(ScalarValue::Int(10)));
+        assert_eq!(equality("'10' = a", 0)?, Some(ScalarValue::Int(10)));
+        assert_eq!(equality("a = 10.0", 0)?, Some(ScalarValue::Int(10)));
+        assert_eq!(
+            equality("a > 1 AND b = 'abc'", 1)?,
+            Some(ScalarValue::Varchar(String::from("abc")))
+        );
+        assert_eq!(equality("a = 1.5", 0)?, None);
+        assert_eq!(equality("a = 10", 1)?, None);
+        assert_eq!(equality("a = 10 OR a = 11", 0)?, None);
+        assert_eq!(equality("a = NULL", 0)?, None);
+        assert_eq!(equality("a = a", 0)?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fold_constants() -> Result<()> {
+        let fold = |sql: &str| -> Result<Expr> { Ok(expr(sql)?.fold_constants()) };
+
+        assert_eq!(fold("1 + 2 * 3")?, Expr::Const(ScalarValue::Int(7)));
+        assert_eq!(fold("a = 1 + 2")?, expr("a = 3")?);
+        assert_eq!(fold("upper('abc') || b")?, expr("'ABC' || b")?);
+        assert_eq!(
+            fold("CASE WHEN 1 > 2 THEN 'x' ELSE 'y' END")?,
+            Expr::Const(ScalarValue::Varchar(String::from("y")))
+        );
+
+        // Logical operators with a constant operand.
+        assert_eq!(fold("a > 1 AND 1 = 1")?, expr("a > 1")?);
+        assert_eq!(fold("true AND a > 1")?, expr("a > 1")?);
+        assert_eq!(
+            fold("a > 1 AND false")?,
+            Expr::Const(ScalarValue::Bool(false))
+        );
+        assert_eq!(
+            fold("1 = 1 OR a > 1")?,
+            Expr::Const(ScalarValue::Bool(true))
+        );
+        assert_eq!(fold("a > 1 OR 1 = 2")?, expr("a > 1")?);
+        assert_eq!(fold("a > 1 AND NULL")?, expr("a > 1 AND NULL")?);
+
+        // Operands that are not boolean still fail when evaluated.
+        assert_eq!(fold("true AND a")?, expr("true AND a")?);
+
+        // Errors are only returned when the expression is evaluated.
+        assert_eq!(fold("a = 1 / 0")?, expr("a = 1 / 0")?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_conjuncts() -> Result<()> {
+        let conjuncts = expr("a > 1 AND (b = 'x' OR a = 2) AND a < 10")?.into_conjuncts();
+        assert_eq!(
+            conjuncts,
+            vec![expr("a > 1")?, expr("b = 'x' OR a = 2")?, expr("a < 10")?]
+        );
+        assert_eq!(
+            Expr::conjunction(conjuncts),
+            Some(expr("a > 1 AND (b = 'x' OR a = 2) AND a < 10")?)
+        );
+        assert_eq!(Expr::conjunction(Vec::new()), None);
+
+        let mut columns = BTreeSet::new();
+        expr("b = 'x' OR a + 1 > 2")?.referenced_columns(&mut columns);
+        assert_eq!(columns.into_iter().collect::<Vec<_>>(), vec![0, 1]);
+
+        assert_eq!(
+            expr("a > 1")?.remap_columns(&|index| index + 1),
+            Expr::BinaryOp {
+                op: BinaryOperator::Gt,
+                left: Box::new(Expr::Column {
+                    index: 1,
+                    typ: pg_type::INT_OID
+                }),
+                right: Box::new(Expr::Const(ScalarValue::Int(1))),
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_eval_null() -> Result<()> {
+        let values = vec![None, ScalarValue::Varchar(String::from("abc")).to_datum()?];
+
+        assert_eq!(eval("a = 10", &values)?, ScalarValue::Null);
+        assert_eq!(eval("a IS NULL", &values)?, ScalarValue::Bool(true));
+        assert_eq!(eval("b IS NOT NULL", &values)?, ScalarValue::Bool(true));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_eval_numeric_types() -> Result<()> {
+        let values = vec![
+            ScalarValue::Int(10).to_datum()?,
+            ScalarValue::Varchar(String::from("abc")).to_datum()?,
+        ];
+
+        assert_eq!(eval("a < 10.5", &values)?, ScalarValue::Bool(true));
+        assert_eq!(eval("a < 3000000000", &values)?, ScalarValue::Bool(true));
+        assert_eq!(eval("a = 10.0", &values)?, ScalarValue::Bool(true));
+        assert!(eval("b = 1", &values).is_err());
+
+        assert_eq!(
+            ScalarValue::SmallInt(2).compare(&ScalarValue::BigInt(1))?,
+            Ordering::Greater
+        );
+        assert_eq!(
+            ScalarValue::Float8(f64::NAN).compare(&ScalarValue::Float4(1.0))?,
+            Ordering::Greater
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_compare_datetime() -> Result<()> {
+        let date = ScalarValue::Date(parse_date("2024-01-01")?);
+        let timestamp = ScalarValue::Timestamp(parse_timestamp("2024-01-01 10:00:00")?);
+
+        assert_eq!(date.compare(&timestamp)?, Ordering::Less);
+        assert_eq!(
+            date.compare(&ScalarValue::Varchar(String::from("2024-01-01")))?,
+            Ordering::Equal
+        );
+        assert_eq!(
+            ScalarValue::Varchar(String::from("2024-01-01 09:00:00")).compare(&timestamp)?,
+            Ordering::Less
+        );
+        assert!(date
+            .compare(&ScalarValue::Varchar(String::from("not a date")))
+            .is_err());
+        assert!(date.compare(&ScalarValue::Int(1)).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_eval_arithmetic() -> Result<()> {
+        let values = vec![
+            ScalarValue::Int(10).to_datum()?,
+            ScalarValue::Varchar(String::from("abc")).to_datum()?,
+        ];
+
+        assert_eq!(eval("a + 1", &values)?, ScalarValue::Int(11));
+        assert_eq!(eval("a - 2 * 3", &values)?, ScalarValue::Int(4));
+        assert_eq!(eval("(a - 2) * 3", &values)?, ScalarValue::Int(24));
+        assert_eq!(eval("a / 3", &values)?, ScalarValue::Int(3));
+        assert_eq!(eval("a % 3", &values)?, ScalarValue::Int(1));
+        assert_eq!(eval("-a", &values)?, ScalarValue::Int(-10));
+        assert_eq!(eval("a / 4.0", &values)?, ScalarValue::Float8(2.5));
+        assert_eq!(
+            eval("a * 3000000000", &values)?,
+            ScalarValue::BigInt(30000000000)
+        );
+        assert_eq!(eval("a + NULL", &values)?, ScalarValue::Null);
+        assert!(eval("a * 1000000000", &values).is_err());
+        assert!(eval("a / 0", &values).is_err());
+        assert!(eval("b + 1", &values).is_err());
+        assert!(eval("a % 1.5", &values).is_err());
+
+        assert_eq!(expr("a + 1")?.return_type(), Some(pg_type::INT_OID));
+        assert_eq!(expr("a * 1.5")?.return_type(), Some(pg_type::FLOAT8_OID));
+        assert_eq!(expr("a + NULL")?.return_type(), Some(pg_type::INT_OID));
+        assert_eq!(expr("a > 1")?.return_type(), Some(pg_type::BOOL_OID));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_eval_string_concat() -> Result<()> {
+        let values = vec![
+            ScalarValue::Int(10).to_datum()?,
+            ScalarValue::Varchar(String::from("abc")).to_datum()?,
+        ];
+
+        assert_eq!(
+            eval("b || 'def'", &values)?,
+            ScalarValue::Varchar(String::from("abcdef"))
+        );
+        assert_eq!(
+            eval("b || a", &values)?,
+            ScalarValue::Varchar(String::from("abc10"))
+        );
+        assert_eq!(eval("b || NULL", &values)?, ScalarValue::Null);
+        assert!(eval("a || 1", &values).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_eval_case() -> Result<()> {
+        let values = vec![
+            ScalarValue::Int(10).to_datum()?,
+            ScalarValue::Varchar(String::from("abc")).to_datum()?,
+        ];
+
+        assert_eq!(
+            eval("CASE WHEN a > 5 THEN 'big' ELSE 'small' END", &values)?,
+            ScalarValue::Varchar(String::from("big"))
+        );
+        assert_eq!(
+            eval("CASE WHEN a > 50 THEN 'big' END", &values)?,
+            ScalarValue::Null
+        );
+        assert_eq!(
+            eval("CASE b WHEN 'x' THEN 1 WHEN 'abc' THEN 2 END", &values)?,
+            ScalarValue::Int(2)
+        );
+
+        // Results are converted to the wider numeric type.
+        assert_eq!(
+            eval("CASE WHEN a = 10 THEN 1 ELSE 2.5 END", &values)?,
+            ScalarValue::Float8(1.0)
+        );
+        assert_eq!(
+            expr("CASE WHEN a = 10 THEN NULL ELSE a END")?.return_type(),
+            Some(pg_type::INT_OID)
+        );
+
+        // Branches that are not matched are not evaluated.
+        assert_eq!(
+            eval("CASE WHEN a > 0 THEN 1 ELSE 1 / 0 END", &values)?,
+            ScalarValue::Int(1)
+        );
+
+        assert!(expr("CASE WHEN a = 10 THEN 1 ELSE 'x' END").is_err());
+        assert!(expr("CASE WHEN a THEN 1 END").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_eval_in_between() -> Result<()> {
+        let values = vec![
+            ScalarValue::Int(10).to_datum()?,
+            ScalarValue::Varchar(String::from("abc")).to_datum()?,
+        ];
+
+        assert_eq!(eval("a IN (1, 10, 20)", &values)?, ScalarValue::Bool(true));
+        assert_eq!(eval("a IN (1, 2)", &values)?, ScalarValue::Bool(false));
+        assert_eq!(
+            eval("b NOT IN ('x', 'y')", &values)?,
+            ScalarValue::Bool(true)
+        );
+        assert_eq!(
+            eval("a BETWEEN 5 AND 10", &values)?,
+            ScalarValue::Bool(true)
+        );
+        assert_eq!(
+            eval("a BETWEEN 11 AND 20", &values)?,
+            ScalarValue::Bool(false)
+        );
+        assert_eq!(
+            eval("a NOT BETWEEN 11 AND 20", &values)?,
+            ScalarValue::Bool(true)
+        );
+        assert!(matches!(
+            expr("a BETWEEN 1 AND 2")?,
+            Expr::BinaryOp {
+                op: BinaryOperator::And,
+                ..
+            }
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_eval_cast() -> Result<()> {
+        let values = vec![
+            ScalarValue::Int(42).to_datum()?,
+            ScalarValue::Varchar(String::from("7")).to_datum()?,
+        ];
+        let text = |value: &str| ScalarValue::Varchar(value.to_string());
+
+        assert_eq!(eval("CAST(a AS text)", &values)?, text("42"));
+        assert_eq!(eval("b::int + 1", &values)?, ScalarValue::Int(8));
+        assert_eq!(eval("CAST(2.5 AS int)", &values)?, ScalarValue::Int(2));
+        assert_eq!(eval("CAST(3.5 AS int)", &values)?, ScalarValue::Int(4));
+        assert_eq!(eval("a::bigint", &values)?, ScalarValue::BigInt(42));
+        assert_eq!(eval("a::real", &values)?, ScalarValue::Float4(42.0));
+        assert_eq!(eval("' yes '::boolean", &values)?, ScalarValue::Bool(true));
+        assert_eq!(eval("true::int", &values)?, ScalarValue::Int(1));
+        assert_eq!(eval("NULL::int", &values)?, ScalarValue::Null);
+        assert_eq!(
+            eval("'2024-02-29'::date::timestamp", &values)?,
+            ScalarValue::Timestamp(datetime::date_to_timestamp(8825))
+        );
+        assert_eq!(expr("b::int")?.return_type(), Some(pg_type::INT_OID));
+
+        // String literals are converted to the type of the other operand.
+        assert_eq!(eval("a = '42'", &values)?, ScalarValue::Bool(true));
+        assert_eq!(eval("a + '1'", &values)?, ScalarValue::Int(43));
+        assert_eq!(eval("a IN ('1', '42')", &values)?, ScalarValue::Bool(true));
+        assert_eq!(
+            eval("CASE a WHEN '42' THEN 'yes' END", &values)?,
+            text("yes")
+        );
+
+        let err = |sql: &str| expr(sql).unwrap_err().downcast::<PgError>().unwrap();
+        assert_eq!(err("a = 'x'").code, SqlState::InvalidTextRepresentation);
+        assert_eq!(
+            err("a = b").message,
+            "operator does not exist: integer = character varying"
+        );
+        assert_eq!(err("a::date").message, "cannot cast type integer to date");
+        assert_eq!(err("a::date").code, SqlState::CannotCoerce);
+        assert_eq!(
+            err("70000::smallint").code,
+            SqlState::NumericValueOutOfRange
+        );
+
+        let err = eval("b::boolean", &values)
+            .unwrap_err()
+            .downcast::<PgError>()
+            .unwrap();
+        assert_eq!(err.message, "invalid input syntax for type boolean: \"7\"");
+        Ok(())
+    }
+
+    #[test]
+    fn test_three_valued_logic() -> Result<()> {
+        let values = vec![ScalarValue::Int(1).to_datum()?, None];
+        let null = ScalarValue::Null;
+        let bool = ScalarValue::Bool;
+
+        assert_eq!(eval("b IS NULL AND NULL", &values)?, null);
+        assert_eq!(eval("b = 'x' AND a = 2", &values)?, bool(false));
+        assert_eq!(eval("a = 2 AND b = 'x'", &values)?, bool(false));
+        assert_eq!(eval("b = 'x' AND a = 1", &values)?, null);
+        assert_eq!(eval("b = 'x' OR a = 1", &values)?, bool(true));
+        assert_eq!(eval("a = 1 OR b = 'x'", &values)?, bool(true));
+        assert_eq!(eval("b = 'x' OR a = 2", &values)?, null);
+        assert_eq!(eval("NOT b = 'x'", &values)?, null);
+        assert_eq!(eval("NOT (a = 2 AND b = 'x')", &values)?, bool(true));
+        assert_eq!(eval("a NOT IN (2, NULL)", &values)?, null);
+        assert_eq!(eval("a IN (1, NULL)", &values)?, bool(true));
+
+        // The right operand is not evaluated if the left operand decides the result.
+        assert_eq!(eval("a = 2 AND 1 / 0 = 1", &values)?, bool(false));
+        assert_eq!(eval("a = 1 OR 1 / 0 = 1", &values)?, bool(true));
+
+        assert_eq!(
+            TruthValue::Unknown.and(TruthValue::False),
+            TruthValue::False
+        );
+        assert_eq!(TruthValue::Unknown.or(TruthValue::True), TruthValue::True);
+        assert_eq!(TruthValue::Unknown.negate(), TruthValue::Unknown);
+
+        let err = eval("a AND true", &values)
+            .unwrap_err()
+            .downcast::<PgError>()
+            .unwrap();
+        assert_eq!(err.code, SqlState::DatatypeMismatch);
+        Ok(())
+    }
+
+    #[test]
+    fn test_eval_batch() -> Result<()> {
+        let mut batch = TupleBatch::default();
+        for a in 1..=4 {
+            let b = match a % 2 {
+                0 => ScalarValue::Varchar(format!("b{}", a)).to_datum()?,
+                _ => None,
+            };
+            batch.push(TupleTableSlot {
+                tid: None,
+                values: vec![ScalarValue::Int(a).to_datum()?, b],
+            });
+        }
+
+        // Batch evaluation must produce the same values of evaluating each tuple.
+        for sql in [
+            "a",
+            "a * 2 + 1",
+            "-a > -3",
+            "b IS NULL",
+            "CAST(a AS bigint)",
+            "a = 2 AND b = 'b2'",
+            "a = 1 OR 1 / (a - 1) = 1",
+            "CASE WHEN b IS NULL THEN 'null' ELSE b END",
+            "length(b)",
+            "1",
+        ] {
+            let expr = expr(sql)?;
+            let expected = (0..batch.len())
+                .map(|index| expr.eval(&batch.row(index)))
+                .collect::<Result<Vec<_>>>()?;
+            assert_eq!(expr.eval_batch(&batch)?, expected, "{}", sql);
+        }
+
+        assert!(expr("1 / (a - 1)")?.eval_batch(&batch).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_unknown_column() {
+        assert!(eval("c = 1", &[]).is_err());
+    }
+}
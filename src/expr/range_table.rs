@@ -0,0 +1,166 @@
+use std::ops::Range;
+
+use anyhow::{bail, Result};
+use sqlparser::ast;
+
+use crate::{
+    access::heaptuple::TupleDesc,
+    catalog::pg_attribute::PgAttribute,
+    sql::error::{PgError, SqlState},
+};
+
+/// A relation referenced on query and the range of its attributes on the range
+/// table tuple descriptor.
+struct RangeTableEntry {
+    /// Name or alias of the relation.
+    name: String,
+
+    /// Index range of relation attributes.
+    attrs: Range<usize>,
+}
+
+/// Relations referenced by a query.
+///
+/// The attributes of all relations are stored on a single tuple descriptor, in
+/// the same order of the tuple values returned by the plan nodes that scan and
+/// join the relations. Column references on expressions are resolved to the
+/// index of the attribute on this tuple descriptor.
+#[derive(Default)]
+pub struct RangeTable {
+    /// Attributes of all relations.
+    pub tuple_desc: TupleDesc,
+
+    entries: Vec<RangeTableEntry>,
+}
+
+impl RangeTable {
+    /// Create a new range table with a single relation.
+    pub fn new(name: &str, tuple_desc: TupleDesc) -> Self {
+        Self {
+            entries: vec![RangeTableEntry {
+                name: name.to_string(),
+                attrs: 0..tuple_desc.attrs.len(),
+            }],
+            tuple_desc,
+        }
+    }
+
+    /// Append all relations of the given range table after the relations of
+    /// this range table.
+    pub fn join(mut self, other: RangeTable) -> Result<Self> {
+        let offset = self.tuple_desc.attrs.len();
+        for entry in other.entries {
+            if self.entries.iter().any(|e| e.name == entry.name) {
+                bail!(PgError::new(
+                    SqlState::DuplicateAlias,
+                    format!("table name {} specified more than once", entry.name)
+                ));
+            }
+            self.entries.push(RangeTableEntry {
+                name: entry.name,
+                attrs: entry.attrs.start + offset..entry.attrs.end + offset,
+            });
+        }
+        self.tuple_desc.attrs.extend(other.tuple_desc.attrs);
+        Ok(self)
+    }
+
+    /// Return the index of the attribute referenced by the given identifiers,
+    /// that can be a column name optionally qualified with a relation name.
+    pub fn resolve_column(&self, idents: &[ast::Ident]) -> Result<usize> {
+        let (range, column) = match idents {
+            [column] => (0..self.tuple_desc.attrs.len(), column),
+            [relation, column] => (self.relation_attrs(&relation.value)?, column),
+            _ => bail!(PgError::new(
+                SqlState::SyntaxError,
+                format!(
+                    "improper qualified name (too many dotted names): {}",
+                    ast::ObjectName(idents.to_vec())
+                )
+            )),
+        };
+
+        let mut found = range.filter(|index| self.tuple_desc.attrs[*index].attname == column.value);
+        match (found.next(), found.next()) {
+            (Some(index), None) => Ok(index),
+            (Some(_), Some(_)) => bail!(PgError::new(
+                SqlState::AmbiguousColumn,
+                format!("column reference {} is ambiguous", column.value)
+            )),
+            (None, _) => bail!(PgError::new(
+                SqlState::UndefinedColumn,
+                format!("column {} does not exist", ast::ObjectName(idents.to_vec()))
+            )),
+        }
+    }
+
+    /// Return the attribute referenced by the given identifiers.
+    pub fn column_attr(&self, idents: &[ast::Ident]) -> Result<&PgAttribute> {
+        Ok(&self.tuple_desc.attrs[self.resolve_column(idents)?])
+    }
+
+    /// Return the index range of all attributes of the given relation name.
+    pub fn relation_attrs(&self, name: &str) -> Result<Range<usize>> {
+        match self.entries.iter().find(|entry| entry.name == name) {
+            Some(entry) => Ok(entry.attrs.clone()),
+            None => bail!(PgError::new(
+                SqlState::UndefinedTable,
+                format!("missing FROM-clause entry for table {}", name)
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog::pg_type;
+
+    fn tuple_desc(attrelid: u64, names: &[&str]) -> TupleDesc {
+        TupleDesc {
+            attrs: names
+                .iter()
+                .enumerate()
+                .map(|(i, name)| PgAttribute {
+                    attrelid,
+                    attname: name.to_string(),
+                    attnum: i + 1,
+                    attlen: 4,
+                    atttypid: pg_type::INT_OID,
+                    attnotnull: false,
+                    attdefault: String::new(),
+                })
+                .collect(),
+        }
+    }
+
+    fn idents(name: &str) -> Vec<ast::Ident> {
+        name.split('.').map(ast::Ident::new).collect()
+    }
+
+    #[test]
+    fn test_resolve_column() -> Result<()> {
+        let range_table = RangeTable::new("t1", tuple_desc(1, &["a", "b"]))
+            .join(RangeTable::new("t2", tuple_desc(2, &["b", "c"])))?;
+
+        assert_eq!(range_table.resolve_column(&idents("a"))?, 0);
+        assert_eq!(range_table.resolve_column(&idents("t1.b"))?, 1);
+        assert_eq!(range_table.resolve_column(&idents("t2.b"))?, 2);
+        assert_eq!(range_table.resolve_column(&idents("c"))?, 3);
+        assert_eq!(range_table.relation_attrs("t2")?, 2..4);
+
+        assert!(range_table.resolve_column(&idents("b")).is_err());
+        assert!(range_table.resolve_column(&idents("t1.c")).is_err());
+        assert!(range_table.resolve_column(&idents("t3.a")).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_join_same_name() {
+        let range_table = RangeTable::new("t", tuple_desc(1, &["a"]));
+        assert!(range_table
+            .join(RangeTable::new("t", tuple_desc(1, &["a"])))
+            .is_err());
+    }
+}
@@ -0,0 +1,465 @@
+use std::{iter::Peekable, str::Chars};
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::{
+    access::{
+        self,
+        heap::heap_insert,
+        heaptuple::{HeapTuple, TupleDataWriter, TupleDesc},
+    },
+    backend::auth::encrypt_password,
+    catalog::{
+        heap::{self, heap_create},
+        pg_attribute::PgAttribute,
+        pg_authid::BOOTSTRAP_SUPERUSER_ID,
+        pg_namespace::PG_CATALOG_NAMESPACE,
+        pg_tablespace::{DEFAULTTABLESPACE_OID, GLOBALTABLESPACE_OID},
+        pg_type,
+    },
+    storage::BufferPool,
+    Oid,
+};
+
+/// Bootstrap description of the system catalogs.
+pub const BOOTSTRAP_CATALOGS: &str = include_str!("bootstrap.bki");
+
+/// A system catalog declared by a create command of the bootstrap description.
+pub struct BootstrapCatalog {
+    /// Name of the catalog relation.
+    pub name: String,
+
+    /// Fixed oid of the catalog relation.
+    pub oid: Oid,
+
+    /// True if the catalog is shared by all databases, stored on the pg_global tablespace.
+    pub shared: bool,
+
+    /// True if the storage of the catalog must be initialized before the catalog is stored on
+    /// pg_class and pg_attribute.
+    pub bootstrap: bool,
+
+    /// Columns of the catalog.
+    pub tuple_desc: TupleDesc,
+
+    /// Initial rows of the catalog.
+    pub rows: Vec<Vec<BootstrapValue>>,
+}
+
+impl BootstrapCatalog {
+    /// Return the oid of tablespace that store the catalog.
+    pub fn tablespace(&self) -> Oid {
+        if self.shared {
+            GLOBALTABLESPACE_OID
+        } else {
+            DEFAULTTABLESPACE_OID
+        }
+    }
+}
+
+/// A value of a bootstrap insert command.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BootstrapValue {
+    Literal(String),
+
+    /// A $name reference replaced when the catalogs are created.
+    Variable(String),
+}
+
+/// Values of the variables referenced by the bootstrap description.
+pub struct BootstrapVariables<'a> {
+    /// Name of the bootstrap superuser.
+    pub superuser: &'a str,
+
+    /// Password of the bootstrap superuser, None to create the superuser without password.
+    pub password: Option<&'a str>,
+}
+
+impl BootstrapVariables<'_> {
+    /// Return the value of the variable with the given name.
+    fn get(&self, name: &str) -> Result<String> {
+        match name {
+            "superuser" => Ok(self.superuser.to_string()),
+            "password" => Ok(self
+                .password
+                .map(|password| encrypt_password(self.superuser, password))
+                .unwrap_or_default()),
+            _ => bail!("bootstrap variable ${} does not exist", name),
+        }
+    }
+}
+
+/// Create all catalogs of the bootstrap description, on the declared order, and insert their
+/// initial rows.
+pub fn bootstrap_catalogs(
+    buffer: &BufferPool,
+    db_oid: &Oid,
+    variables: &BootstrapVariables<'_>,
+) -> Result<()> {
+    for catalog in parse(BOOTSTRAP_CATALOGS)? {
+        log::info!("initializing {}", catalog.name);
+
+        if catalog.bootstrap {
+            let rel =
+                access::open_relation(catalog.oid, catalog.tablespace(), db_oid, &catalog.name);
+            heap::initialize_default_page_header(buffer, &rel)?;
+        }
+
+        let rel = heap_create(
+            buffer,
+            catalog.tablespace(),
+            db_oid,
+            PG_CATALOG_NAMESPACE,
+            BOOTSTRAP_SUPERUSER_ID,
+            &catalog.name,
+            catalog.oid,
+            &catalog.tuple_desc,
+        )?;
+
+        for row in &catalog.rows {
+            heap_insert(buffer, &rel, &encode_row(&catalog, row, variables)?)?;
+        }
+    }
+    Ok(())
+}
+
+/// Encode a row of the given catalog using the on-disk layout of catalog rows.
+fn encode_row(
+    catalog: &BootstrapCatalog,
+    row: &[BootstrapValue],
+    variables: &BootstrapVariables<'_>,
+) -> Result<HeapTuple> {
+    let mut writer = TupleDataWriter::default();
+    for (attr, value) in catalog.tuple_desc.attrs.iter().zip(row) {
+        let value = match value {
+            BootstrapValue::Literal(value) => value.clone(),
+            BootstrapValue::Variable(name) => variables.get(name)?,
+        };
+        let invalid = || {
+            anyhow!(
+                "invalid value {:?} for column {} of {}",
+                value,
+                attr.attname,
+                catalog.name
+            )
+        };
+
+        match attr.atttypid {
+            pg_type::INT_OID => writer.put_i64(value.parse().map_err(|_| invalid())?),
+            pg_type::BOOL_OID => writer.put_bool(value.parse().map_err(|_| invalid())?),
+            _ => writer.put_str(&value),
+        }
+    }
+    Ok(HeapTuple::from_writer(writer))
+}
+
+/// Return the catalog column of the given bootstrap type.
+fn column(catalog: Oid, name: String, attnum: usize, typ: &str) -> Option<PgAttribute> {
+    let (atttypid, attlen) = match typ {
+        "int8" => (pg_type::INT_OID, 8),
+        "bool" => (pg_type::BOOL_OID, 1),
+        "text" => (pg_type::VARCHAR_OID, -1),
+        _ => return None,
+    };
+    Some(PgAttribute {
+        attrelid: catalog,
+        attname: name,
+        attnum,
+        attlen,
+        atttypid,
+        attnotnull: false,
+        attdefault: String::new(),
+    })
+}
+
+/// Parse the given bootstrap description.
+pub fn parse(source: &str) -> Result<Vec<BootstrapCatalog>> {
+    let mut parser = Parser {
+        chars: source.chars().peekable(),
+        line: 1,
+    };
+    let mut catalogs: Vec<BootstrapCatalog> = Vec::new();
+
+    while let Some(token) = parser.next_token()? {
+        match token {
+            Token::Word(command) if command == "create" => {
+                let name = parser.expect_word("relation name")?;
+                let oid = parser.expect_word("relation oid")?;
+                let oid = match oid.parse() {
+                    Ok(oid) => oid,
+                    Err(_) => return Err(parser.error(format!("invalid relation oid {}", oid))),
+                };
+
+                let mut catalog = BootstrapCatalog {
+                    name,
+                    oid,
+                    shared: false,
+                    bootstrap: false,
+                    tuple_desc: TupleDesc::default(),
+                    rows: Vec::new(),
+                };
+                loop {
+                    match parser.next_token()? {
+                        Some(Token::Word(option)) if option == "shared" => catalog.shared = true,
+                        Some(Token::Word(option)) if option == "bootstrap" => {
+                            catalog.bootstrap = true
+                        }
+                        Some(Token::Punct('(')) => break,
+                        token => return Err(parser.unexpected(token, "(")),
+                    }
+                }
+
+                loop {
+                    let name = parser.expect_word("column name")?;
+                    parser.expect_punct('=')?;
+                    let typ = parser.expect_word("column type")?;
+                    let attnum = catalog.tuple_desc.attrs.len() + 1;
+                    match column(catalog.oid, name, attnum, &typ) {
+                        Some(attr) => catalog.tuple_desc.attrs.push(attr),
+                        None => return Err(parser.error(format!("unknown column type {}", typ))),
+                    }
+                    if parser.expect_separator()? {
+                        break;
+                    }
+                }
+                catalogs.push(catalog);
+            }
+            Token::Word(command) if command == "insert" => {
+                parser.expect_punct('(')?;
+                let mut row = Vec::new();
+                loop {
+                    match parser.next_token()? {
+                        Some(Token::Word(value)) => match value.strip_prefix('$') {
+                            Some(name) => row.push(BootstrapValue::Variable(name.to_string())),
+                            None => row.push(BootstrapValue::Literal(value)),
+                        },
+                        Some(Token::Quoted(value)) => row.push(BootstrapValue::Literal(value)),
+                        token => return Err(parser.unexpected(token, "value")),
+                    }
+                    if parser.expect_separator()? {
+                        break;
+                    }
+                }
+
+                let catalog = match catalogs.last_mut() {
+                    Some(catalog) => catalog,
+                    None => return Err(parser.error("insert before any create command")),
+                };
+                if row.len() != catalog.tuple_desc.attrs.len() {
+                    return Err(parser.error(format!(
+                        "insert with {} values on {} with {} columns",
+                        row.len(),
+                        catalog.name,
+                        catalog.tuple_desc.attrs.len()
+                    )));
+                }
+                catalog.rows.push(row);
+            }
+            token => return Err(parser.unexpected(Some(token), "create or insert")),
+        }
+    }
+
+    Ok(catalogs)
+}
+
+/// A token of the bootstrap description.
+#[derive(Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Punct(char),
+}
+
+/// Tokenizer of the bootstrap description, keeping the current line for error messages.
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+}
+
+impl Parser<'_> {
+    /// Return the next token, None at the end of the description.
+    fn next_token(&mut self) -> Result<Option<Token>> {
+        while let Some(&c) = self.chars.peek() {
+            match c {
+                '\n' => {
+                    self.line += 1;
+                    self.chars.next();
+                }
+                '#' => {
+                    while matches!(self.chars.peek(), Some(c) if *c != '\n') {
+                        self.chars.next();
+                    }
+                }
+                c if c.is_whitespace() => {
+                    self.chars.next();
+                }
+                '(' | ')' | ',' | '=' => {
+                    self.chars.next();
+                    return Ok(Some(Token::Punct(c)));
+                }
+                '\'' => {
+                    self.chars.next();
+                    let mut value = String::new();
+                    loop {
+                        match self.chars.next() {
+                            // Two quotes are an escaped quote.
+                            Some('\'') if self.chars.peek() == Some(&'\'') => {
+                                self.chars.next();
+                                value.push('\'');
+                            }
+                            Some('\'') => return Ok(Some(Token::Quoted(value))),
+                            Some('\n') | None => {
+                                return Err(self.error("unterminated quoted value"))
+                            }
+                            Some(c) => value.push(c),
+                        }
+                    }
+                }
+                _ => {
+                    let mut word = String::new();
+                    while let Some(&c) = self.chars.peek() {
+                        if c.is_whitespace() || "()',=#".contains(c) {
+                            break;
+                        }
+                        word.push(c);
+                        self.chars.next();
+                    }
+                    return Ok(Some(Token::Word(word)));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    fn expect_word(&mut self, expected: &str) -> Result<String> {
+        match self.next_token()? {
+            Some(Token::Word(word)) => Ok(word),
+            token => Err(self.unexpected(token, expected)),
+        }
+    }
+
+    fn expect_punct(&mut self, expected: char) -> Result<()> {
+        match self.next_token()? {
+            Some(Token::Punct(c)) if c == expected => Ok(()),
+            token => Err(self.unexpected(token, &expected.to_string())),
+        }
+    }
+
+    /// Consume the separator after a list item. Return true at the end of the list.
+    fn expect_separator(&mut self) -> Result<bool> {
+        match self.next_token()? {
+            Some(Token::Punct(',')) => Ok(false),
+            Some(Token::Punct(')')) => Ok(true),
+            token => Err(self.unexpected(token, ", or )")),
+        }
+    }
+
+    fn unexpected(&self, token: Option<Token>, expected: &str) -> anyhow::Error {
+        match token {
+            Some(token) => self.error(format!("expected {}, found {:?}", expected, token)),
+            None => self.error(format!("expected {}, found end of file", expected)),
+        }
+    }
+
+    fn error(&self, message: impl std::fmt::Display) -> anyhow::Error {
+        anyhow!("bootstrap line {}: {}", self.line, message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog::{
+        pg_attribute, pg_auth_members::PgAuthMembers, pg_authid::PgAuthId, pg_class::PgClass,
+        pg_database::PgDatabase, pg_index::PgIndex, pg_namespace::PgNamespace,
+        pg_statistic::PgStatistic, pg_tablespace::PgTablespace,
+    };
+
+    fn columns(tuple_desc: &TupleDesc) -> Vec<(Oid, String, usize, i64, Oid)> {
+        tuple_desc
+            .attrs
+            .iter()
+            .map(|attr| {
+                (
+                    attr.attrelid,
+                    attr.attname.clone(),
+                    attr.attnum,
+                    attr.attlen,
+                    attr.atttypid,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_bootstrap_catalogs_match_tuple_desc() -> Result<()> {
+        let catalogs = parse(BOOTSTRAP_CATALOGS)?;
+        let expected = [
+            (pg_attribute::RELATION_NAME, PgAttribute::tuple_desc()),
+            ("pg_class", PgClass::tuple_desc()),
+            ("pg_namespace", PgNamespace::tuple_desc()),
+            ("pg_index", PgIndex::tuple_desc()),
+            ("pg_statistic", PgStatistic::tuple_desc()),
+            ("pg_tablespace", PgTablespace::tuple_desc()),
+            ("pg_database", PgDatabase::tuple_desc()),
+            ("pg_authid", PgAuthId::tuple_desc()),
+            ("pg_auth_members", PgAuthMembers::tuple_desc()),
+        ];
+        assert_eq!(catalogs.len(), expected.len());
+        for (catalog, (name, tuple_desc)) in catalogs.iter().zip(&expected) {
+            assert_eq!(catalog.name, *name);
+            assert_eq!(
+                columns(&catalog.tuple_desc),
+                columns(tuple_desc),
+                "{}",
+                name
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_bootstrap() -> Result<()> {
+        let catalogs = parse(
+            "# comment\n\
+             create t 1 shared bootstrap (a = int8, b = text) # trailing comment\n\
+             insert (1, 'it''s here')\n\
+             insert (2, $superuser)\n",
+        )?;
+        assert_eq!(catalogs.len(), 1);
+        assert!(catalogs[0].shared && catalogs[0].bootstrap);
+        assert_eq!(catalogs[0].tablespace(), GLOBALTABLESPACE_OID);
+        assert_eq!(
+            catalogs[0].rows,
+            vec![
+                vec![
+                    BootstrapValue::Literal(String::from("1")),
+                    BootstrapValue::Literal(String::from("it's here")),
+                ],
+                vec![
+                    BootstrapValue::Literal(String::from("2")),
+                    BootstrapValue::Variable(String::from("superuser")),
+                ],
+            ]
+        );
+
+        let err = |source: &str| parse(source).err().unwrap().to_string();
+        assert_eq!(
+            err("insert (1)"),
+            "bootstrap line 1: insert before any create command"
+        );
+        assert_eq!(
+            err("create t 1 (a = int8)\ninsert (1, 2)"),
+            "bootstrap line 2: insert with 2 values on t with 1 columns"
+        );
+        assert_eq!(
+            err("create t 1 (a = float)"),
+            "bootstrap line 1: unknown column type float"
+        );
+        assert_eq!(
+            err("create t 1 (a = int8"),
+            "bootstrap line 1: expected , or ), found end of file"
+        );
+        Ok(())
+    }
+}
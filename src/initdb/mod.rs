@@ -0,0 +1,172 @@
+use std::{
+    fs::{self, create_dir_all},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Result};
+
+use crate::{
+    catalog::pg_database,
+    storage::{
+        control::{ControlFile, CONTROL_FILE},
+        relation_locator::database_path,
+        wal::WAL_DIR,
+        BufferPool,
+    },
+};
+
+pub mod bootstrap;
+
+/// Errors of initializing a data directory.
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("data directory {0:?} is already initialized, use --force to remove the existing database files and initialize it again")]
+    AlreadyInitialized(PathBuf),
+}
+
+/// Directories inside the data directory that store database files.
+const DATABASE_DIRS: &[&str] = &["base", "global", WAL_DIR];
+
+/// Return true if the given data directory has a control file or any database file, even if the
+/// initialization was not completed.
+pub fn is_initialized(data_dir: &Path) -> Result<bool> {
+    if data_dir.join(CONTROL_FILE).exists() {
+        return Ok(true);
+    }
+
+    // The WAL directory is ignored because it's created when the WAL is opened, before the
+    // database is initialized.
+    for dir in &["base", "global"] {
+        let path = data_dir.join(dir);
+        if path.exists() && fs::read_dir(path)?.next().is_some() {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+/// Remove all database files of the given data directory. Other files, such as the settings
+/// file, are kept.
+pub fn remove_database(data_dir: &Path) -> Result<()> {
+    for dir in DATABASE_DIRS {
+        let path = data_dir.join(dir);
+        if path.exists() {
+            fs::remove_dir_all(path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Initialize a empty database at the data_dir path using db_name as the database name.
+///
+/// A superuser role is created with the given name and password. The role has no password if
+/// password is None, so it can only connect to the database when authentication is disabled.
+///
+/// Return an error if the data directory is already initialized, remove_database should be used
+/// first to initialize it again.
+pub fn init_database(
+    buffer: &BufferPool,
+    data_dir: &Path,
+    superuser: &str,
+    password: Option<&str>,
+) -> Result<()> {
+    if is_initialized(data_dir)? {
+        bail!(Error::AlreadyInitialized(data_dir.to_path_buf()));
+    }
+
+    let db_path = data_dir.join(database_path(&pg_database::TINYDB_OID));
+    let global_path = Path::new(data_dir).join("global");
+    let wal_path = Path::new(data_dir).join(WAL_DIR);
+
+    if !db_path.exists() {
+        create_dir_all(&db_path)?;
+    }
+
+    if !global_path.exists() {
+        create_dir_all(&global_path)?;
+    }
+
+    if !wal_path.exists() {
+        create_dir_all(&wal_path)?;
+    }
+
+    init_catalogs(buffer, superuser, password)?;
+
+    // The control file is only created after all catalogs are stored on disk, so a data
+    // directory with a control file is always completely initialized.
+    buffer.checkpoint()?;
+    ControlFile::create(data_dir)?;
+
+    Ok(())
+}
+
+/// Create the system catalogs of an empty database on the given buffer pool, without creating
+/// any file on the data directory. Used by init_database and by in-memory databases.
+///
+/// A superuser role is created with the given name and password, see init_database.
+pub fn init_catalogs(buffer: &BufferPool, superuser: &str, password: Option<&str>) -> Result<()> {
+    bootstrap::bootstrap_catalogs(
+        buffer,
+        &pg_database::TINYDB_OID,
+        &bootstrap::BootstrapVariables {
+            superuser,
+            password,
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        access::{self, heap::HeapScanner},
+        catalog::pg_database::RELATION_OID,
+        storage::smgr::StorageManager,
+    };
+
+    use super::*;
+
+    fn count_databases(buffer: &BufferPool) -> Result<usize> {
+        let mut heap = HeapScanner::new(buffer, &access::open_pg_database_relation())?;
+        let mut databases = 0;
+        while heap.next_tuple()?.is_some() {
+            databases += 1;
+        }
+        Ok(databases)
+    }
+
+    #[test]
+    fn test_init_database_once() -> Result<()> {
+        let data_dir = tempfile::tempdir()?;
+        assert!(!is_initialized(data_dir.path())?);
+
+        let buffer = BufferPool::new(50, StorageManager::new(data_dir.path()));
+        init_database(&buffer, data_dir.path(), "tinydb", None)?;
+        assert!(is_initialized(data_dir.path())?);
+
+        let err = init_database(&buffer, data_dir.path(), "tinydb", None).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::AlreadyInitialized(_))
+        ));
+        assert_eq!(count_databases(&buffer)?, 1);
+        drop(buffer);
+
+        // Settings files are not removed.
+        fs::write(data_dir.path().join("tinydb.conf"), "port = 5432")?;
+        remove_database(data_dir.path())?;
+        assert!(!is_initialized(data_dir.path())?);
+        assert!(data_dir.path().join("tinydb.conf").exists());
+        assert!(!data_dir
+            .path()
+            .join("global")
+            .join(RELATION_OID.to_string())
+            .exists());
+
+        let buffer = BufferPool::new(50, StorageManager::new(data_dir.path()));
+        init_database(&buffer, data_dir.path(), "tinydb", None)?;
+        assert_eq!(count_databases(&buffer)?, 1);
+
+        Ok(())
+    }
+}
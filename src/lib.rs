@@ -1,22 +1,29 @@
 use std::ops::Deref;
 use std::sync::atomic::{AtomicU64, Ordering};
 
+use bytes::Bytes;
+
 pub mod access;
 pub mod backend;
+pub mod backup;
 pub mod catalog;
 pub mod cli;
+pub mod database;
 pub mod encoding;
 pub mod executor;
+pub mod expr;
 pub mod initdb;
 pub mod lru;
 pub mod planner;
 pub mod postgres_protocol;
 pub mod relation;
+pub mod shell;
 pub mod sql;
 pub mod storage;
+pub mod upgrade;
 
 /// First object id to assign when creating a new database cluster.
-const FIRST_NORMAL_OBJECT_ID: u64 = 10000;
+pub const FIRST_NORMAL_OBJECT_ID: u64 = 10000;
 
 /// Objecct identifier.
 pub type Oid = u64;
@@ -37,12 +44,19 @@ pub fn new_object_id() -> Oid {
 
 /// A slice of bytes that represents a value of inside a tuple.
 ///
-/// A reference of datum is always read-only.
-#[derive(Debug, Default)]
-pub struct Datum(Vec<u8>);
+/// A reference of datum is always read-only. Datums may share the same buffer, e.g the values
+/// allocated on a DatumArena, so cloning a datum don't copy its bytes.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Datum(Bytes);
 
 impl From<Vec<u8>> for Datum {
     fn from(value: Vec<u8>) -> Self {
+        Self(Bytes::from(value))
+    }
+}
+
+impl From<Bytes> for Datum {
+    fn from(value: Bytes) -> Self {
         Self(value)
     }
 }
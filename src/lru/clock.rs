@@ -0,0 +1,177 @@
+use std::{collections::HashMap, hash::Hash, sync::Mutex};
+
+use super::Replacer;
+
+/// A clock-sweep replacement policy, as used by Postgres.
+///
+/// Unpinned objects are stored on a circular list with a reference bit. When a victim is needed a
+/// clock hand sweeps the list clearing the reference bit of the objects, and the first object
+/// found with the reference bit already cleared is chosen. Objects unpinned again before the hand
+/// reach them get a second chance, so a sequential scan that touch each page only once don't
+/// replace all pages that are frequently used.
+#[derive(Debug)]
+pub struct ClockSweep<T> {
+    state: Mutex<ClockState<T>>,
+}
+
+#[derive(Debug)]
+struct ClockState<T> {
+    /// Circular list of objects and their reference bit. None if the slot is free.
+    slots: Vec<Option<(T, bool)>>,
+
+    /// Slot index of each object.
+    positions: HashMap<T, usize>,
+
+    /// Index of free slots that can be reused.
+    free_slots: Vec<usize>,
+
+    /// Current position of the clock hand.
+    hand: usize,
+}
+
+impl<T> ClockSweep<T>
+where
+    T: Clone + Eq + Hash,
+{
+    /// Create a new empty ClockSweep replacer.
+    pub fn new(size: usize) -> Self {
+        Self {
+            state: Mutex::new(ClockState {
+                slots: Vec::with_capacity(size),
+                positions: HashMap::with_capacity(size),
+                free_slots: Vec::new(),
+                hand: 0,
+            }),
+        }
+    }
+}
+
+impl<T> Replacer<T> for ClockSweep<T>
+where
+    T: Clone + Eq + Hash + Send,
+{
+    fn victim(&self) -> Option<T> {
+        let mut state = self.state.lock().unwrap();
+        if state.positions.is_empty() {
+            return None;
+        }
+
+        // At most two rounds are needed, the first one may only clear the reference bits.
+        for _ in 0..state.slots.len() * 2 {
+            let hand = state.hand;
+            state.hand = (hand + 1) % state.slots.len();
+
+            match &mut state.slots[hand] {
+                Some((_, referenced)) if *referenced => *referenced = false,
+                Some(_) => {
+                    let (id, _) = state.slots[hand].take()?;
+                    state.positions.remove(&id);
+                    state.free_slots.push(hand);
+                    return Some(id);
+                }
+                None => {}
+            }
+        }
+
+        None
+    }
+
+    fn pin(&self, id: &T) {
+        self.remove(id);
+    }
+
+    fn remove(&self, id: &T) -> bool {
+        let mut state = self.state.lock().unwrap();
+        match state.positions.remove(id) {
+            Some(slot) => {
+                state.slots[slot] = None;
+                state.free_slots.push(slot);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn unpin(&self, id: &T) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(slot) = state.positions.get(id).copied() {
+            if let Some((_, referenced)) = &mut state.slots[slot] {
+                *referenced = true;
+            }
+            return;
+        }
+
+        let slot = match state.free_slots.pop() {
+            Some(slot) => {
+                state.slots[slot] = Some((id.clone(), true));
+                slot
+            }
+            None => {
+                state.slots.push(Some((id.clone(), true)));
+                state.slots.len() - 1
+            }
+        };
+        state.positions.insert(id.clone(), slot);
+    }
+
+    fn size(&self) -> usize {
+        self.state.lock().unwrap().positions.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clock_victim() {
+        let replacer = ClockSweep::new(3);
+        replacer.unpin(&10);
+        replacer.unpin(&30);
+        replacer.unpin(&20);
+
+        assert_eq!(replacer.victim(), Some(10));
+        assert_eq!(replacer.victim(), Some(30));
+        assert_eq!(replacer.victim(), Some(20));
+        assert_eq!(replacer.victim(), None);
+        assert_eq!(replacer.size(), 0);
+    }
+
+    #[test]
+    fn test_clock_second_chance() {
+        let replacer = ClockSweep::new(3);
+        replacer.unpin(&1);
+        replacer.unpin(&2);
+        replacer.unpin(&3);
+
+        // The first sweep clear all reference bits and choose 1.
+        assert_eq!(replacer.victim(), Some(1));
+
+        // 2 is referenced again, so 3 is chosen before it.
+        replacer.unpin(&2);
+        assert_eq!(replacer.victim(), Some(3));
+        assert_eq!(replacer.victim(), Some(2));
+    }
+
+    #[test]
+    fn test_clock_pin() {
+        let replacer = ClockSweep::new(10);
+        for i in 0..10 {
+            replacer.unpin(&i);
+        }
+        replacer.pin(&5);
+        replacer.pin(&3);
+        assert_eq!(replacer.size(), 8);
+
+        let mut victims = Vec::new();
+        while let Some(victim) = replacer.victim() {
+            victims.push(victim);
+        }
+        assert_eq!(victims, vec![0, 1, 2, 4, 6, 7, 8, 9]);
+
+        // Free slots are reused.
+        replacer.unpin(&42);
+        assert_eq!(replacer.size(), 1);
+        assert_eq!(replacer.victim(), Some(42));
+    }
+}
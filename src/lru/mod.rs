@@ -1,29 +1,129 @@
+pub mod clock;
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    hash::Hash,
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+use self::clock::ClockSweep;
+
+/// A replacement policy used to choose which buffer should be removed from memory when there is no
+/// more free buffers to use.
+///
+/// Implementations are internally synchronized, so a replacer can be shared between multiple
+/// threads without any external lock.
+pub trait Replacer<T>: Send + Sync {
+    /// Remove the object that should be replaced according with the replacement policy and
+    /// return it. Return None if there is no object that can be replaced.
+    fn victim(&self) -> Option<T>;
+
+    /// Remove the given object from the replacer, so it can not be a victim.
+    ///
+    /// This method should be called after a page is pinned to a frame.
+    fn pin(&self, id: &T);
+
+    /// Remove the given object from the replacer if it can be a victim. Return true if the object
+    /// was removed, so the caller can use it as a victim.
+    fn remove(&self, id: &T) -> bool;
+
+    /// Add the given object to the replacer, so it can be a victim.
+    ///
+    /// This method should be called when the pin_count of a page becomes 0.
+    fn unpin(&self, id: &T);
+
+    /// Returns the number of objects that can be a victim.
+    fn size(&self) -> usize;
+}
+
+/// Replacement policies that can be used by the buffer pool.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplacementPolicy {
+    /// Replace the least recently used buffer.
+    Lru,
+
+    /// Replace the first buffer not used since the last sweep of the clock hand. Perform better
+    /// than LRU when sequential scans read more pages than the buffer pool can hold.
+    ClockSweep,
+}
+
+impl ReplacementPolicy {
+    /// Create a new replacer of this policy to track at most the given number of objects.
+    pub fn replacer<T>(&self, size: usize) -> Arc<dyn Replacer<T>>
+    where
+        T: Clone + Eq + Hash + Send + 'static,
+    {
+        match self {
+            Self::Lru => Arc::new(LRU::new(size)),
+            Self::ClockSweep => Arc::new(ClockSweep::new(size)),
+        }
+    }
+}
+
+impl FromStr for ReplacementPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "lru" => Ok(Self::Lru),
+            "clock" => Ok(Self::ClockSweep),
+            _ => Err(format!("invalid replacement policy {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for ReplacementPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Lru => write!(f, "lru"),
+            Self::ClockSweep => write!(f, "clock"),
+        }
+    }
+}
+
 /// A least recently used (LRU) implementation.
 ///
-// TODO: Improve the implementation.
-//
-// The current implementation does not perform well.
-// All FrameIDs is stored in a Vec and when pin is called is
-// necessary to iterate over all FrameIDs to remove it.
-//
-// TODO: Make this implementation thread safe.
-#[derive(Debug, PartialEq)]
+/// Each unpinned object is stored with an increasing access counter, so the least recently used
+/// object is the one with the smallest counter. All operations are O(log n).
+#[derive(Debug)]
 pub struct LRU<T> {
-    elements: Vec<T>,
+    state: Mutex<LRUState<T>>,
+}
+
+#[derive(Debug)]
+struct LRUState<T> {
+    /// Counter of the last time that each object was unpinned.
+    counters: HashMap<T, u64>,
+
+    /// Objects ordered by the last time that they were unpinned.
+    elements: BTreeMap<u64, T>,
+
+    /// Next value of access counter.
+    next_counter: u64,
 }
 
 impl<T> LRU<T>
 where
-    T: Clone,
-    T: PartialEq,
+    T: Clone + Eq + Hash,
 {
     /// Create a new empty LruReplacer.
     pub fn new(size: usize) -> Self {
         Self {
-            elements: Vec::with_capacity(size),
+            state: Mutex::new(LRUState {
+                counters: HashMap::with_capacity(size),
+                elements: BTreeMap::new(),
+                next_counter: 0,
+            }),
         }
     }
+}
 
+impl<T> Replacer<T> for LRU<T>
+where
+    T: Clone + Eq + Hash + Send,
+{
     /// Remove the object that was accessed least recently compared
     /// to all the other elements being tracked by the Replacer, and
     /// return its contents. If the LruReplacer is empty None.
@@ -32,49 +132,61 @@ where
     // capacity, then a FrameID will be returned contaning the frame id
     // that buffer pool should remove from cache. Note that the FrameID
     // returned will be also removed from LruReplacer internal data structure.
-    pub fn victim(&mut self) -> Option<T> {
-        self.elements.pop()
+    fn victim(&self) -> Option<T> {
+        let mut state = self.state.lock().unwrap();
+        let counter = *state.elements.keys().next()?;
+        let id = state.elements.remove(&counter)?;
+        state.counters.remove(&id);
+        Some(id)
     }
 
     /// Remove the frame containing the pinned page from the LRUReplacer.
-    ///
-    /// This method should be called after a page is pinned to a frame.
     //
     // Technilly this function will be called when buffer pool page is pinned
     // to a frame, which means that a page was be shared between with a client,
     // so since the page is shared we can not remove from buffer pool cache.
-    pub fn pin(&mut self, id: &T) {
-        if let Some(index) = self.elements.iter().position(|v| v == id) {
-            self.elements.remove(index);
+    fn pin(&self, id: &T) {
+        self.remove(id);
+    }
+
+    fn remove(&self, id: &T) -> bool {
+        let mut state = self.state.lock().unwrap();
+        match state.counters.remove(id) {
+            Some(counter) => state.elements.remove(&counter).is_some(),
+            None => false,
         }
     }
 
     /// Add the frame containing the unpinned page to the LRUReplacer.
-    ///
-    /// This method should be called when the pin_count of a page becomes 0.
     //
     // Technilly this function will be called when a page do not have any references
     // to it (which means that your pin_count will be 0). If a Page/FrameID does not
     // have any references we can remove from cache.
-    pub fn unpin(&mut self, id: &T) {
-        if !self.elements.contains(id) {
-            self.elements.insert(0, id.clone());
+    fn unpin(&self, id: &T) {
+        let mut state = self.state.lock().unwrap();
+        if !state.counters.contains_key(id) {
+            let counter = state.next_counter;
+            state.next_counter += 1;
+            state.counters.insert(id.clone(), counter);
+            state.elements.insert(counter, id.clone());
         }
     }
 
     /// Returns the number of frames that are currently in the LRUReplacer.
-    pub fn size(&self) -> usize {
-        self.elements.len()
+    fn size(&self) -> usize {
+        self.state.lock().unwrap().elements.len()
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::{sync::Arc, thread};
+
     use super::*;
 
     #[test]
     fn test_unpin_same_key() {
-        let mut replacer = LRU::new(5);
+        let replacer = LRU::new(5);
         replacer.unpin(&1);
         replacer.unpin(&2);
         replacer.unpin(&1); // Duplicate key
@@ -88,7 +200,7 @@ mod tests {
 
     #[test]
     fn test_lru_victim() {
-        let mut replacer = LRU::new(3);
+        let replacer = LRU::new(3);
         replacer.unpin(&10);
         replacer.unpin(&30);
         replacer.unpin(&20);
@@ -101,7 +213,7 @@ mod tests {
 
     #[test]
     fn test_lru_pin() {
-        let mut replacer = LRU::new(10);
+        let replacer = LRU::new(10);
         for i in 0..10 {
             replacer.unpin(&i);
         }
@@ -109,15 +221,42 @@ mod tests {
         replacer.pin(&5);
         replacer.pin(&3);
         assert_eq!(replacer.size(), 8);
-        assert_eq!(replacer.elements, vec![9, 8, 7, 6, 4, 2, 1, 0]);
-        let _ = replacer.victim();
-        assert_eq!(replacer.elements, vec![9, 8, 7, 6, 4, 2, 1]);
+        assert_eq!(replacer.victim(), Some(0));
+
+        let mut victims = Vec::new();
+        while let Some(victim) = replacer.victim() {
+            victims.push(victim);
+        }
+        assert_eq!(victims, vec![1, 2, 4, 6, 7, 8, 9]);
     }
 
     #[test]
     fn test_lru_unpin() {
-        let mut replacer = LRU::new(1);
+        let replacer = LRU::new(1);
         replacer.unpin(&1);
         assert_eq!(replacer.size(), 1);
     }
+
+    #[test]
+    fn test_lru_concurrent_access() {
+        let replacer = Arc::new(LRU::new(100));
+
+        let handles: Vec<_> = (0..4)
+            .map(|t| {
+                let replacer = replacer.clone();
+                thread::spawn(move || {
+                    for i in 0..25 {
+                        replacer.unpin(&(t * 25 + i));
+                        replacer.pin(&(t * 25 + i));
+                        replacer.unpin(&(t * 25 + i));
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(replacer.size(), 100);
+    }
 }
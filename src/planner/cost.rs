@@ -0,0 +1,77 @@
+//! Cost model used by the planner to choose between alternative plans of the same query.
+//!
+//! Costs are measured in units of a sequential page read, using the default cost constants of
+//! Postgres. Estimates are based on the statistics computed by ANALYZE, so relations that were
+//! never analyzed are always scanned sequentially.
+
+/// Cost of reading a page that is next to the last page read.
+pub const SEQ_PAGE_COST: f64 = 1.0;
+
+/// Cost of reading a page at an arbitrary position of a relation.
+pub const RANDOM_PAGE_COST: f64 = 4.0;
+
+/// Cost of processing each tuple returned by a scan.
+pub const CPU_TUPLE_COST: f64 = 0.01;
+
+/// Cost of processing each index entry read by an index scan.
+pub const CPU_INDEX_TUPLE_COST: f64 = 0.005;
+
+/// Cost of evaluating an operator of a filter on a tuple.
+pub const CPU_OPERATOR_COST: f64 = 0.0025;
+
+/// Estimated number of entries of each B-tree page, used to estimate the height of indexes.
+const BTREE_FANOUT: f64 = 100.0;
+
+/// Return the cost of scanning all tuples of a relation with the given number of pages and
+/// tuples, evaluating a filter on each tuple.
+pub fn seq_scan_cost(pages: f64, tuples: f64) -> f64 {
+    pages * SEQ_PAGE_COST + tuples * (CPU_TUPLE_COST + CPU_OPERATOR_COST)
+}
+
+/// Return the cost of fetching the tuples of a relation with the given number of pages and
+/// tuples that match an index condition with the given selectivity, using a B-tree index with
+/// the given number of pages.
+///
+/// Each matching tuple may be stored on a different page of the relation, so each fetched page
+/// is a random read.
+pub fn index_scan_cost(pages: f64, tuples: f64, index_pages: f64, selectivity: f64) -> f64 {
+    // At least one tuple is assumed to match, so the cost of unselective conditions on empty
+    // relations is not underestimated.
+    let matched = (tuples * selectivity).max(1.0);
+
+    let height = index_pages.max(1.0).log(BTREE_FANOUT).ceil() + 1.0;
+    let leaf_pages = (index_pages * selectivity).ceil();
+    let index_cost = (height + leaf_pages) * RANDOM_PAGE_COST + matched * CPU_INDEX_TUPLE_COST;
+
+    let heap_cost = matched.min(pages.max(1.0)) * RANDOM_PAGE_COST
+        + matched * (CPU_TUPLE_COST + CPU_OPERATOR_COST);
+
+    index_cost + heap_cost
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_index_scan_cost() {
+        // Selective conditions on large relations are cheaper using the index.
+        let (pages, tuples, index_pages) = (1000.0, 100000.0, 300.0);
+        assert!(
+            index_scan_cost(pages, tuples, index_pages, 1.0 / 100000.0)
+                < seq_scan_cost(pages, tuples)
+        );
+
+        // Conditions matching most of the tuples read most of the pages at random.
+        assert!(index_scan_cost(pages, tuples, index_pages, 0.5) > seq_scan_cost(pages, tuples));
+
+        // Small relations are cheaper to scan sequentially.
+        assert!(index_scan_cost(1.0, 10.0, 2.0, 0.1) > seq_scan_cost(1.0, 10.0));
+
+        // Less selective conditions are never cheaper.
+        assert!(
+            index_scan_cost(pages, tuples, index_pages, 0.01)
+                > index_scan_cost(pages, tuples, index_pages, 0.001)
+        );
+    }
+}
@@ -4,14 +4,37 @@ use sqlparser::ast::{self, SetExpr, TableFactor};
 use std::sync::Arc;
 
 use crate::{
-    access::{self, heap::HeapScanner, heaptuple::TupleDesc},
-    catalog::{self, pg_attribute::PgAttribute, pg_class::PgClass},
+    access::{
+        self,
+        btree::btree_encode_key,
+        heaptuple::TupleDesc,
+        lmgr::{self, LockGuard, LockMode, LockOwner, LockTag},
+        tableam::{self, TableAm, TableScan},
+    },
+    catalog::{
+        self,
+        namespace::RelationName,
+        pg_attribute::PgAttribute,
+        pg_class::PgClass,
+        pg_type,
+        virtual_table::{VirtualTable, VirtualTables},
+    },
+    executor::{memory::MemoryReservation, sort::SortedTuples, TupleTableSlot},
+    expr::{aggregate::AggregateExpr, Expr, RangeTable, ScalarValue},
     relation::Relation,
-    sql::SQLError,
-    storage::BufferPool,
-    Oid, INVALID_OID,
+    sql::{
+        error::{PgError, SqlState},
+        SQLError,
+    },
+    storage::{page::ItemPointer, BufferPool},
+    NullableDatum, Oid, FIRST_NORMAL_OBJECT_ID, INVALID_OID,
 };
 
+use self::optimizer::Optimizer;
+
+mod cost;
+pub mod optimizer;
+
 /// Information needed to project a query output.
 pub struct ProjectionState {
     /// Projection output attributes of query.
@@ -21,9 +44,9 @@ pub struct ProjectionState {
     /// tuple_desc_ field if the attributes order on page is required.
     pub projection: Vec<PgAttribute>,
 
-    /// Tuple descriptor from a relation heap tuple. The tuple descriptor
-    /// attributes is in the same order that is stored on page tuple.
-    pub tuple_desc: Arc<TupleDesc>,
+    /// Expression of each projection attribute, evaluated against the tuples
+    /// returned by child plan node.
+    pub exprs: Vec<Expr>,
 
     pub child: Plan,
 }
@@ -36,8 +59,183 @@ pub struct SeqScanState {
     /// Current relation used by executor to operate.
     pub relation: Relation,
 
-    /// Iterator used to fetch tuples.
-    pub heap_scanner: HeapScanner,
+    /// Access method used to store the relation.
+    pub table_am: &'static dyn TableAm,
+
+    /// Scan used to fetch tuples.
+    pub scan: Box<dyn TableScan>,
+
+    /// Attributes used by the plan nodes above the scan, by attribute index. Attributes that are
+    /// not used are not decoded and returned as NULL. None if all attributes are used.
+    pub used_attrs: Option<Vec<bool>>,
+
+    /// Conditions that tuples should satisfy to be returned, checked before the other
+    /// attributes of the tuples are decoded.
+    pub scan_keys: Vec<ScanKey>,
+
+    /// AccessShare lock that keeps the relation from being dropped while it is scanned.
+    pub lock: LockGuard,
+}
+
+/// Condition on a single attribute of a relation, evaluated by a scan on each tuple.
+pub struct ScanKey {
+    /// Index of the attribute on the relation tuple description.
+    pub attr: usize,
+
+    /// Boolean expression evaluated against the attribute value, that is referenced as the
+    /// column of index 0.
+    pub qual: Expr,
+}
+
+/// Index scan information needed by executor.
+pub struct IndexScanState {
+    /// Tuple description of relation being scanned.
+    pub tuple_desc: Arc<TupleDesc>,
+
+    /// Relation that tuples are fetched from.
+    pub relation: Relation,
+
+    /// Access method used to store the relation.
+    pub table_am: &'static dyn TableAm,
+
+    /// B-tree index relation used to find the tuples.
+    pub index: Relation,
+
+    /// Key of the index entries that point to the tuples to return.
+    pub key: Vec<u8>,
+
+    /// Location of the tuples not yet fetched. None if the index was not searched yet.
+    pub tids: Option<std::vec::IntoIter<ItemPointer>>,
+
+    /// Attributes used by the plan nodes above the scan, like on a sequential scan.
+    pub used_attrs: Option<Vec<bool>>,
+
+    /// AccessShare lock that keeps the relation, and its indexes, from being dropped while it is
+    /// scanned.
+    pub lock: LockGuard,
+}
+
+/// Virtual relation scan information needed by executor.
+pub struct VirtualScanState {
+    /// Tuple description of the virtual relation.
+    pub tuple_desc: Arc<TupleDesc>,
+
+    /// Virtual relation that generate the tuples.
+    pub table: Arc<dyn VirtualTable>,
+
+    /// Tuples generated and not yet returned. None if the scan was not started.
+    pub tuples: Option<std::vec::IntoIter<Vec<NullableDatum>>>,
+}
+
+/// Subquery scan information needed by executor.
+pub struct SubqueryScanState {
+    /// Tuple description of the subquery output.
+    pub tuple_desc: Arc<TupleDesc>,
+
+    /// Tuples returned by the subquery. None if the subquery was not executed yet.
+    pub tuples: Option<Vec<Vec<NullableDatum>>>,
+
+    /// Index of the next tuple to return.
+    pub next: usize,
+
+    /// Memory reserved by the subquery tuples. None if the subquery was not executed yet.
+    pub memory: Option<MemoryReservation>,
+
+    pub child: Plan,
+}
+
+/// Information needed to filter the tuples returned by a child plan node.
+pub struct FilterState {
+    /// Boolean expression that tuples should satisfy to be returned.
+    pub qual: Expr,
+
+    pub child: Plan,
+}
+
+/// Information needed to join the tuples returned by two child plan nodes.
+///
+/// For each tuple returned by the outer plan the inner plan is rescanned and
+/// the values of both tuples are concatenated.
+pub struct NestedLoopJoinState {
+    /// Boolean expression that joined tuples should satisfy to be returned.
+    /// None for a cross join.
+    pub qual: Option<Expr>,
+
+    /// Current tuple from outer plan being joined. None if the next outer
+    /// tuple should be fetched.
+    pub outer_tuple: Option<TupleTableSlot>,
+
+    pub outer: Plan,
+
+    pub inner: Plan,
+}
+
+/// Information needed to compute aggregate functions over all tuples returned by a child plan
+/// node.
+pub struct AggregateState {
+    /// Aggregate functions to compute, on the same order of the query output.
+    pub aggregates: Vec<AggregateExpr>,
+
+    /// Output attributes of each aggregate function.
+    pub output: Vec<PgAttribute>,
+
+    /// Set when the aggregates were computed, so the executor returns their row only once.
+    pub finished: bool,
+
+    pub child: Plan,
+}
+
+/// Expression used to order the tuples of a sort plan node.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SortKey {
+    /// Expression evaluated against the tuples returned by the child plan node.
+    pub expr: Expr,
+
+    /// Order the tuples by descending values of the expression.
+    pub descending: bool,
+
+    /// NULL values come before non NULL values. Like Postgres, NULL values are larger than any
+    /// other value by default.
+    pub nulls_first: bool,
+}
+
+/// Information needed to sort the tuples returned by a child plan node.
+pub struct SortState {
+    /// Expressions that define the order of tuples, the first key being the most significant.
+    pub keys: Vec<SortKey>,
+
+    /// Sorted tuples not yet returned. None if the child plan was not sorted yet.
+    pub sorted: Option<SortedTuples>,
+
+    pub child: Plan,
+}
+
+/// Information needed to limit the number of tuples returned by a child plan node.
+pub struct LimitState {
+    /// Maximum number of tuples to return. None if there is no limit.
+    pub limit: Option<u64>,
+
+    /// Number of tuples still to skip before start returning tuples.
+    pub offset: u64,
+
+    /// Number of tuples already returned.
+    pub returned: u64,
+
+    pub child: Plan,
+}
+
+/// Information needed to delete the tuples returned by a child plan node.
+pub struct DeleteState {
+    /// Relation that tuples will be deleted from.
+    pub relation: Relation,
+
+    /// Access method used to store the relation.
+    pub table_am: &'static dyn TableAm,
+
+    /// RowExclusive lock held on the relation while tuples are deleted.
+    pub lock: LockGuard,
+
+    pub child: Plan,
 }
 
 /// Types of a plan node on plan tree.
@@ -46,7 +244,34 @@ pub enum PlanNodeType {
     Projection { state: Box<ProjectionState> },
 
     /// Sequential scan plan node.
-    SeqScan { state: SeqScanState },
+    SeqScan { state: Box<SeqScanState> },
+
+    /// Index scan plan node.
+    IndexScan { state: Box<IndexScanState> },
+
+    /// Virtual relation scan plan node.
+    VirtualScan { state: Box<VirtualScanState> },
+
+    /// Subquery scan plan node.
+    SubqueryScan { state: Box<SubqueryScanState> },
+
+    /// Filter plan node.
+    Filter { state: Box<FilterState> },
+
+    /// Nested loop join plan node.
+    NestedLoopJoin { state: Box<NestedLoopJoinState> },
+
+    /// Aggregate plan node.
+    Aggregate { state: Box<AggregateState> },
+
+    /// Sort plan node.
+    Sort { state: Box<SortState> },
+
+    /// Limit plan node.
+    Limit { state: Box<LimitState> },
+
+    /// Delete plan node.
+    Delete { state: Box<DeleteState> },
 }
 
 /// A tree of nodes to be executed.
@@ -56,94 +281,919 @@ pub struct Plan {
 }
 
 impl Plan {
-    /// Create a new plan for the given parsed query.
-    pub fn create(buffer_pool: &BufferPool, db_oid: &Oid, query: &Box<ast::Query>) -> Result<Plan> {
-        let plan = match &query.body {
-            SetExpr::Select(select) => create_plan_from_select(buffer_pool, db_oid, &select)?,
-            _ => bail!(SQLError::Unsupported(query.body.to_string())),
+    /// Create a new plan for the given parsed query. Relations are searched on the given virtual
+    /// relations before the system catalogs, where unqualified names are searched on the schemas
+    /// of the given search path.
+    ///
+    /// The scanned relations are locked on behalf of the given owner until the plan is dropped.
+    pub fn create(
+        buffer_pool: &BufferPool,
+        db_oid: &Oid,
+        search_path: &[String],
+        virtual_tables: &VirtualTables,
+        owner: &LockOwner,
+        query: &ast::Query,
+    ) -> Result<Plan> {
+        Self::create_with_optimizer(
+            buffer_pool,
+            db_oid,
+            search_path,
+            virtual_tables,
+            owner,
+            query,
+            &Optimizer::default(),
+        )
+    }
+
+    /// Create a new plan for the given parsed query like create, rewriting the plan tree using
+    /// the given optimizer.
+    pub fn create_with_optimizer(
+        buffer_pool: &BufferPool,
+        db_oid: &Oid,
+        search_path: &[String],
+        virtual_tables: &VirtualTables,
+        owner: &LockOwner,
+        query: &ast::Query,
+        optimizer: &Optimizer,
+    ) -> Result<Plan> {
+        let plan = create_query(
+            buffer_pool,
+            db_oid,
+            search_path,
+            virtual_tables,
+            owner,
+            query,
+        )?;
+        optimizer.optimize(plan)
+    }
+
+    /// Return the plan with each child plan node replaced by the result of the given function.
+    pub fn map_children<F>(self, mut f: F) -> Result<Plan>
+    where
+        F: FnMut(Plan) -> Result<Plan>,
+    {
+        let node_type = match self.node_type {
+            PlanNodeType::Projection { mut state } => {
+                state.child = f(state.child)?;
+                PlanNodeType::Projection { state }
+            }
+            PlanNodeType::SubqueryScan { mut state } => {
+                state.child = f(state.child)?;
+                PlanNodeType::SubqueryScan { state }
+            }
+            PlanNodeType::Filter { mut state } => {
+                state.child = f(state.child)?;
+                PlanNodeType::Filter { state }
+            }
+            PlanNodeType::NestedLoopJoin { mut state } => {
+                state.outer = f(state.outer)?;
+                state.inner = f(state.inner)?;
+                PlanNodeType::NestedLoopJoin { state }
+            }
+            PlanNodeType::Aggregate { mut state } => {
+                state.child = f(state.child)?;
+                PlanNodeType::Aggregate { state }
+            }
+            PlanNodeType::Sort { mut state } => {
+                state.child = f(state.child)?;
+                PlanNodeType::Sort { state }
+            }
+            PlanNodeType::Limit { mut state } => {
+                state.child = f(state.child)?;
+                PlanNodeType::Limit { state }
+            }
+            PlanNodeType::Delete { mut state } => {
+                state.child = f(state.child)?;
+                PlanNodeType::Delete { state }
+            }
+            node_type @ (PlanNodeType::SeqScan { .. }
+            | PlanNodeType::IndexScan { .. }
+            | PlanNodeType::VirtualScan { .. }) => node_type,
         };
-        Ok(plan)
+        Ok(Plan { node_type })
+    }
+
+    /// Return the child plan nodes of the plan.
+    pub fn children(&self) -> Vec<&Plan> {
+        match &self.node_type {
+            PlanNodeType::Projection { state } => vec![&state.child],
+            PlanNodeType::SubqueryScan { state } => vec![&state.child],
+            PlanNodeType::Filter { state } => vec![&state.child],
+            PlanNodeType::NestedLoopJoin { state } => vec![&state.outer, &state.inner],
+            PlanNodeType::Aggregate { state } => vec![&state.child],
+            PlanNodeType::Sort { state } => vec![&state.child],
+            PlanNodeType::Limit { state } => vec![&state.child],
+            PlanNodeType::Delete { state } => vec![&state.child],
+            PlanNodeType::SeqScan { .. }
+            | PlanNodeType::IndexScan { .. }
+            | PlanNodeType::VirtualScan { .. } => Vec::new(),
+        }
     }
+
+    /// Return the relations scanned by the plan and by its child plan nodes. Virtual relations
+    /// are not returned.
+    pub fn scanned_relations(&self) -> Vec<&Relation> {
+        match &self.node_type {
+            PlanNodeType::SeqScan { state } => vec![&state.relation],
+            PlanNodeType::IndexScan { state } => vec![&state.relation],
+            _ => self
+                .children()
+                .into_iter()
+                .flat_map(|child| child.scanned_relations())
+                .collect(),
+        }
+    }
+
+    /// Return the number of attributes of the tuples returned by the plan.
+    pub fn width(&self) -> usize {
+        match &self.node_type {
+            PlanNodeType::Projection { state } => state.projection.len(),
+            PlanNodeType::SeqScan { state } => state.tuple_desc.attrs.len(),
+            PlanNodeType::IndexScan { state } => state.tuple_desc.attrs.len(),
+            PlanNodeType::VirtualScan { state } => state.tuple_desc.attrs.len(),
+            PlanNodeType::SubqueryScan { state } => state.tuple_desc.attrs.len(),
+            PlanNodeType::Filter { state } => state.child.width(),
+            PlanNodeType::NestedLoopJoin { state } => state.outer.width() + state.inner.width(),
+            PlanNodeType::Aggregate { state } => state.output.len(),
+            PlanNodeType::Sort { state } => state.child.width(),
+            PlanNodeType::Limit { state } => state.child.width(),
+            PlanNodeType::Delete { .. } => 0,
+        }
+    }
+
+    /// Return the description of the tuples returned by the plan, without executing it. None if
+    /// the plan don't return tuples.
+    pub fn tuple_desc(&self) -> Option<TupleDesc> {
+        match &self.node_type {
+            PlanNodeType::Projection { state } => Some(TupleDesc {
+                attrs: state.projection.clone(),
+            }),
+            PlanNodeType::Aggregate { state } => Some(TupleDesc {
+                attrs: state.output.clone(),
+            }),
+            PlanNodeType::Sort { state } => state.child.tuple_desc(),
+            PlanNodeType::Limit { state } => state.child.tuple_desc(),
+            _ => None,
+        }
+    }
+
+    /// Create a new plan for the given parsed delete statement. The relation is locked on behalf
+    /// of the given owner until the plan is dropped.
+    pub fn create_delete(
+        buffer_pool: &BufferPool,
+        db_oid: &Oid,
+        search_path: &[String],
+        owner: &LockOwner,
+        table_name: &ast::ObjectName,
+        selection: &Option<ast::Expr>,
+    ) -> Result<Plan> {
+        let (pg_class, lock) = lmgr::lock_relation(
+            buffer_pool,
+            db_oid,
+            search_path,
+            &RelationName::from_object_name(table_name)?,
+            LockMode::RowExclusive,
+            owner,
+        )?;
+        let rel_name = pg_class.relname.clone();
+        let scan_lock = buffer_pool.lock_manager().acquire(
+            LockTag::relation(db_oid, &pg_class),
+            LockMode::AccessShare,
+            owner,
+        )?;
+
+        let tuple_desc = Arc::new(catalog::tuple_desc_from_relation_oid(
+            buffer_pool,
+            db_oid,
+            &pg_class.oid,
+        )?);
+
+        let range_table = RangeTable::new(&rel_name, tuple_desc.as_ref().clone());
+        let seq_scan = create_seq_scan(
+            buffer_pool,
+            db_oid,
+            &rel_name,
+            &pg_class,
+            tuple_desc,
+            scan_lock,
+        )?;
+
+        Optimizer::default().optimize(Plan {
+            node_type: PlanNodeType::Delete {
+                state: Box::new(DeleteState {
+                    relation: access::open_relation(
+                        pg_class.oid,
+                        pg_class.reltablespace,
+                        db_oid,
+                        &rel_name,
+                    ),
+                    table_am: tableam::table_am(pg_class.relam)?,
+                    lock,
+                    child: create_filter(buffer_pool, selection, &range_table, seq_scan)?,
+                }),
+            },
+        })
+    }
+}
+
+/// Create a plan for the given parsed query, without optimizing it.
+fn create_query(
+    buffer_pool: &BufferPool,
+    db_oid: &Oid,
+    search_path: &[String],
+    virtual_tables: &VirtualTables,
+    owner: &LockOwner,
+    query: &ast::Query,
+) -> Result<Plan> {
+    let plan = match &query.body {
+        SetExpr::Select(select) => create_plan_from_select(
+            buffer_pool,
+            db_oid,
+            search_path,
+            virtual_tables,
+            owner,
+            select,
+            &query.order_by,
+        )?,
+        _ => bail!(SQLError::Unsupported(query.body.to_string())),
+    };
+    create_limit(query, plan)
 }
 
 fn create_plan_from_select(
     buffer_pool: &BufferPool,
     db_oid: &Oid,
+    search_path: &[String],
+    virtual_tables: &VirtualTables,
+    owner: &LockOwner,
     select: &ast::Select,
+    order_by: &[ast::OrderByExpr],
 ) -> Result<Plan> {
-    if select.from.len() > 1 {
-        bail!(SQLError::Unsupported(
-            "Can not use multiple expressions on FROM".to_string(),
-        ));
+    let (plan, range_table) = create_from(
+        buffer_pool,
+        db_oid,
+        search_path,
+        virtual_tables,
+        owner,
+        &select.from,
+    )?;
+    let plan = create_filter(buffer_pool, &select.selection, &range_table, plan)?;
+
+    if select.projection.iter().any(|item| match item {
+        ast::SelectItem::UnnamedExpr(expr) | ast::SelectItem::ExprWithAlias { expr, .. } => {
+            AggregateExpr::is_aggregate(expr)
+        }
+        _ => false,
+    }) {
+        let plan = create_aggregate(select, &range_table, plan)?;
+
+        // Aggregates without GROUP BY return a single row, so there is nothing to sort.
+        let output = plan.tuple_desc().unwrap_or_default().attrs;
+        for order_by in order_by {
+            if output_columns(&order_by.expr, &output)?.is_empty() {
+                bail!(SQLError::Unsupported(order_by.to_string()));
+            }
+        }
+        return Ok(plan);
     }
 
-    let from = &select.from[0];
+    let (projection, exprs) = create_projection(&select.projection, &range_table)?;
 
-    match &from.relation {
-        TableFactor::Table { name, .. } => {
-            let rel_name = name.0[0].to_string();
-            let pg_class = catalog::get_pg_class_relation(buffer_pool, db_oid, &rel_name)?;
+    // Tuples are sorted before they are projected, so they can be sorted by columns that are
+    // not on the query output.
+    let keys = create_sort_keys(order_by, &projection, &exprs, &range_table)?;
+    let plan = if keys.is_empty() {
+        plan
+    } else {
+        Plan {
+            node_type: PlanNodeType::Sort {
+                state: Box::new(SortState {
+                    keys,
+                    sorted: None,
+                    child: plan,
+                }),
+            },
+        }
+    };
 
-            let tuple_desc = Arc::new(catalog::tuple_desc_from_relation(
-                buffer_pool,
-                db_oid,
-                &rel_name,
-            )?);
-
-            let mut projection = Vec::with_capacity(select.projection.len());
-
-            for item in &select.projection {
-                match item {
-                    ast::SelectItem::UnnamedExpr(expr) => match expr {
-                        ast::Expr::Identifier(ident) => {
-                            match tuple_desc
-                                .attrs
-                                .iter()
-                                .find(|attr| attr.attname == ident.value)
-                            {
-                                Some(attr) => projection.push(attr.clone()),
-                                None => bail!(
-                                    "Attribute {} does not exists on relation {}",
-                                    ident.value,
-                                    rel_name
-                                ),
+    Ok(Plan {
+        node_type: PlanNodeType::Projection {
+            state: Box::new(ProjectionState {
+                projection,
+                exprs,
+                child: plan,
+            }),
+        },
+    })
+}
+
+/// Return the output attributes of the given select items and the expressions that compute
+/// them over the tuples described by the given range table.
+pub fn create_projection(
+    items: &[ast::SelectItem],
+    range_table: &RangeTable,
+) -> Result<(Vec<PgAttribute>, Vec<Expr>)> {
+    let mut projection = Vec::with_capacity(items.len());
+    let mut exprs = Vec::with_capacity(items.len());
+
+    for item in items {
+        let (expr, alias) = match item {
+            ast::SelectItem::UnnamedExpr(expr) => (expr, None),
+            ast::SelectItem::ExprWithAlias { expr, alias } => (expr, Some(&alias.value)),
+            ast::SelectItem::Wildcard => {
+                for index in 0..range_table.tuple_desc.attrs.len() {
+                    projection.push(range_table.tuple_desc.attrs[index].clone());
+                    exprs.push(Expr::column_index(range_table, index));
+                }
+                continue;
+            }
+            ast::SelectItem::QualifiedWildcard(name) => {
+                for index in range_table.relation_attrs(&name.to_string())? {
+                    projection.push(range_table.tuple_desc.attrs[index].clone());
+                    exprs.push(Expr::column_index(range_table, index));
+                }
+                continue;
+            }
+        };
+
+        let expr = Expr::create(expr, range_table)?;
+        let mut attr = match expr {
+            // Column references keep the attribute of the relation being projected.
+            Expr::Column { index, .. } => range_table.tuple_desc.attrs[index].clone(),
+            _ => {
+                // Like Postgres, values of unknown type are returned as text.
+                let typ = expr.return_type().unwrap_or(pg_type::TEXT_OID);
+                PgAttribute {
+                    attrelid: INVALID_OID,
+                    attname: match &expr {
+                        Expr::Function { func, .. } => func.name.to_string(),
+                        Expr::Case { .. } => String::from("case"),
+                        // Casts of a column keep the column name.
+                        Expr::Cast { expr, .. } => match expr.as_ref() {
+                            Expr::Column { index, .. } => {
+                                range_table.tuple_desc.attrs[*index].attname.clone()
                             }
-                        }
-                        _ => bail!(SQLError::Unsupported(from.relation.to_string())),
+                            _ => String::from("?column?"),
+                        },
+                        _ => String::from("?column?"),
                     },
-                    ast::SelectItem::Wildcard => {
-                        projection.extend_from_slice(&tuple_desc.attrs);
+                    attnum: 0,
+                    attlen: pg_type::type_len(typ),
+                    atttypid: typ,
+                    attnotnull: false,
+                    attdefault: String::new(),
+                }
+            }
+        };
+        if let Some(alias) = alias {
+            attr.attname = alias.clone();
+        }
+
+        projection.push(attr);
+        exprs.push(expr);
+    }
+    Ok((projection, exprs))
+}
+
+/// Return the sort keys of the given ORDER BY expressions, for a query whose output attributes
+/// are computed by the given expressions over the tuples described by the given range table.
+///
+/// Like Postgres, an ORDER BY expression may be the position or the name of an output column,
+/// otherwise it is an expression over the columns of the range table.
+fn create_sort_keys(
+    order_by: &[ast::OrderByExpr],
+    output: &[PgAttribute],
+    exprs: &[Expr],
+    range_table: &RangeTable,
+) -> Result<Vec<SortKey>> {
+    order_by
+        .iter()
+        .map(|order_by| {
+            let expr = match output_columns(&order_by.expr, output)?.as_slice() {
+                [] => Expr::create(&order_by.expr, range_table)?,
+                [index, others @ ..] => {
+                    if others.iter().any(|other| exprs[*other] != exprs[*index]) {
+                        bail!(PgError::new(
+                            SqlState::AmbiguousColumn,
+                            format!("ORDER BY \"{}\" is ambiguous", order_by.expr)
+                        ));
                     }
-                    _ => bail!(SQLError::Unsupported(from.relation.to_string())),
+                    exprs[*index].clone()
                 }
+            };
+            let descending = order_by.asc == Some(false);
+            Ok(SortKey {
+                expr,
+                descending,
+                nulls_first: order_by.nulls_first.unwrap_or(descending),
+            })
+        })
+        .collect()
+}
+
+/// Return the indexes of the output attributes referenced by the given ORDER BY expression, by
+/// position or by name. Empty if the expression don't reference an output attribute.
+fn output_columns(expr: &ast::Expr, output: &[PgAttribute]) -> Result<Vec<usize>> {
+    match expr {
+        ast::Expr::Value(ast::Value::Number(number, _)) => match number.parse::<usize>() {
+            Ok(position) if position >= 1 && position <= output.len() => Ok(vec![position - 1]),
+            _ => bail!(PgError::new(
+                SqlState::InvalidColumnReference,
+                format!("ORDER BY position {} is not in select list", number)
+            )),
+        },
+        ast::Expr::Identifier(ident) => Ok(output
+            .iter()
+            .enumerate()
+            .filter(|(_, attr)| attr.attname == ident.value)
+            .map(|(index, _)| index)
+            .collect()),
+        _ => Ok(Vec::new()),
+    }
+}
+
+/// Create a plan that scan and join all relations of the given FROM clause, returning the range
+/// table that describe the tuples returned by the plan.
+///
+/// Multiple FROM items and explicit joins are executed using nested loop joins, where the
+/// relations on the left are the outer plan.
+fn create_from(
+    buffer_pool: &BufferPool,
+    db_oid: &Oid,
+    search_path: &[String],
+    virtual_tables: &VirtualTables,
+    owner: &LockOwner,
+    from: &[ast::TableWithJoins],
+) -> Result<(Plan, RangeTable)> {
+    let mut current: Option<(Plan, RangeTable)> = None;
+
+    for table in from {
+        let (mut plan, mut range_table) = match current.take() {
+            Some((outer, outer_range_table)) => {
+                let (inner, inner_range_table) = create_table_scan(
+                    buffer_pool,
+                    db_oid,
+                    search_path,
+                    virtual_tables,
+                    owner,
+                    &table.relation,
+                )?;
+                create_nested_loop_join(outer, outer_range_table, inner, inner_range_table, None)?
+            }
+            None => create_table_scan(
+                buffer_pool,
+                db_oid,
+                search_path,
+                virtual_tables,
+                owner,
+                &table.relation,
+            )?,
+        };
+
+        for join in &table.joins {
+            let (inner, inner_range_table) = create_table_scan(
+                buffer_pool,
+                db_oid,
+                search_path,
+                virtual_tables,
+                owner,
+                &join.relation,
+            )?;
+            let constraint = match &join.join_operator {
+                ast::JoinOperator::Inner(ast::JoinConstraint::On(expr)) => Some(expr),
+                ast::JoinOperator::CrossJoin => None,
+                _ => bail!(SQLError::Unsupported(join.to_string().trim().to_string())),
+            };
+            let (joined, joined_range_table) =
+                create_nested_loop_join(plan, range_table, inner, inner_range_table, constraint)?;
+            plan = joined;
+            range_table = joined_range_table;
+        }
+
+        current = Some((plan, range_table));
+    }
+
+    match current {
+        Some(current) => Ok(current),
+        None => bail!(SQLError::Unsupported(String::from("SELECT without FROM"))),
+    }
+}
+
+/// Create a nested loop join plan node between the given outer and inner plans. The constraint
+/// expression is resolved against the attributes of both relations.
+fn create_nested_loop_join(
+    outer: Plan,
+    outer_range_table: RangeTable,
+    inner: Plan,
+    inner_range_table: RangeTable,
+    constraint: Option<&ast::Expr>,
+) -> Result<(Plan, RangeTable)> {
+    let range_table = outer_range_table.join(inner_range_table)?;
+    let qual = match constraint {
+        Some(expr) => Some(Expr::create(expr, &range_table)?),
+        None => None,
+    };
+
+    Ok((
+        Plan {
+            node_type: PlanNodeType::NestedLoopJoin {
+                state: Box::new(NestedLoopJoinState {
+                    qual,
+                    outer_tuple: None,
+                    outer,
+                    inner,
+                }),
+            },
+        },
+        range_table,
+    ))
+}
+
+/// Create a sequential scan plan node for the given table of a FROM clause, or a virtual scan
+/// plan node if the table is a virtual relation. The relation attributes are named on range table
+/// using the table alias if present.
+///
+/// Subqueries are planned recursively and scanned by a subquery scan plan node, their output
+/// attributes are named on range table using the subquery alias.
+fn create_table_scan(
+    buffer_pool: &BufferPool,
+    db_oid: &Oid,
+    search_path: &[String],
+    virtual_tables: &VirtualTables,
+    owner: &LockOwner,
+    relation: &TableFactor,
+) -> Result<(Plan, RangeTable)> {
+    match relation {
+        TableFactor::Table { name, alias, .. } => {
+            let name = RelationName::from_object_name(name)?;
+            let rel_name = name.name.clone();
+            let range_name = alias
+                .as_ref()
+                .map_or_else(|| rel_name.clone(), |alias| alias.name.value.clone());
+
+            // Virtual relations are relations of the pg_catalog schema.
+            let virtual_table = match name.is_catalog() {
+                true => virtual_tables.get(&rel_name),
+                false => None,
+            };
+            if let Some(table) = virtual_table {
+                let tuple_desc = table.tuple_desc();
+                let range_table = RangeTable::new(&range_name, tuple_desc.clone());
+                let virtual_scan = Plan {
+                    node_type: PlanNodeType::VirtualScan {
+                        state: Box::new(VirtualScanState {
+                            tuple_desc: Arc::new(tuple_desc),
+                            table,
+                            tuples: None,
+                        }),
+                    },
+                };
+                return Ok((virtual_scan, range_table));
+            }
+
+            let (pg_class, lock) = lmgr::lock_relation(
+                buffer_pool,
+                db_oid,
+                search_path,
+                &name,
+                LockMode::AccessShare,
+                owner,
+            )?;
+
+            let tuple_desc =
+                catalog::tuple_desc_from_relation_oid(buffer_pool, db_oid, &pg_class.oid)?;
+            let range_table = RangeTable::new(&range_name, tuple_desc.clone());
+
+            let seq_scan = create_seq_scan(
+                buffer_pool,
+                db_oid,
+                &rel_name,
+                &pg_class,
+                Arc::new(tuple_desc),
+                lock,
+            )?;
+
+            Ok((seq_scan, range_table))
+        }
+        TableFactor::Derived {
+            lateral: false,
+            subquery,
+            alias,
+        } => {
+            let alias = match alias {
+                Some(alias) => alias,
+                None => bail!(PgError::new(
+                    SqlState::SyntaxError,
+                    "subquery in FROM must have an alias"
+                )),
+            };
+
+            let child = create_query(
+                buffer_pool,
+                db_oid,
+                search_path,
+                virtual_tables,
+                owner,
+                subquery,
+            )?;
+            let mut tuple_desc = match child.tuple_desc() {
+                Some(tuple_desc) => tuple_desc,
+                None => bail!("Subquery plan node {} don't return tuples", child.node_type),
+            };
+
+            // Columns of the subquery can be renamed by the alias column list.
+            if alias.columns.len() > tuple_desc.attrs.len() {
+                bail!(PgError::new(
+                    SqlState::InvalidColumnReference,
+                    format!(
+                        "table {} has {} columns available but {} columns specified",
+                        alias.name.value,
+                        tuple_desc.attrs.len(),
+                        alias.columns.len()
+                    )
+                ));
+            }
+            for (attr, column) in tuple_desc.attrs.iter_mut().zip(&alias.columns) {
+                attr.attname = column.value.clone();
             }
 
+            let range_table = RangeTable::new(&alias.name.value, tuple_desc.clone());
+            let subquery_scan = Plan {
+                node_type: PlanNodeType::SubqueryScan {
+                    state: Box::new(SubqueryScanState {
+                        tuple_desc: Arc::new(tuple_desc),
+                        tuples: None,
+                        next: 0,
+                        memory: None,
+                        child,
+                    }),
+                },
+            };
+
+            Ok((subquery_scan, range_table))
+        }
+        _ => bail!(SQLError::Unsupported(relation.to_string())),
+    }
+}
+
+/// Create an aggregate plan node that compute all aggregate functions of the given select
+/// projection over the tuples returned by the given child plan.
+fn create_aggregate(select: &ast::Select, range_table: &RangeTable, child: Plan) -> Result<Plan> {
+    if !select.group_by.is_empty() || select.having.is_some() {
+        bail!(SQLError::Unsupported(String::from("GROUP BY")));
+    }
+
+    let mut aggregates = Vec::with_capacity(select.projection.len());
+    let mut output = Vec::with_capacity(select.projection.len());
+
+    for item in &select.projection {
+        let (expr, alias) = match item {
+            ast::SelectItem::UnnamedExpr(expr) => (expr, None),
+            ast::SelectItem::ExprWithAlias { expr, alias } => (expr, Some(alias.value.as_str())),
+            _ => bail!(SQLError::Unsupported(item.to_string())),
+        };
+
+        match expr {
+            ast::Expr::Function(function) if AggregateExpr::is_aggregate(expr) => {
+                let aggregate = AggregateExpr::create(function, range_table)?;
+                output.push(aggregate.output_attr(alias));
+                aggregates.push(aggregate);
+            }
+            ast::Expr::Identifier(ident) => bail!(PgError::new(SqlState::GroupingError, format!("column {} must appear in the GROUP BY clause or be used in an aggregate function",
+                ident.value))),
+            _ => bail!(SQLError::Unsupported(expr.to_string())),
+        }
+    }
+
+    Ok(Plan {
+        node_type: PlanNodeType::Aggregate {
+            state: Box::new(AggregateState {
+                aggregates,
+                output,
+                finished: false,
+                child,
+            }),
+        },
+    })
+}
+
+/// Add a limit plan node on the given plan if the query has a LIMIT or OFFSET clause, otherwise
+/// just return the plan.
+///
+/// The limit is placed below the projection so that the executor stop fetching tuples from the
+/// relation once the limit is reached. Aggregates need to consume all tuples, so in this case the
+/// limit is applied on the aggregate output.
+fn create_limit(query: &ast::Query, plan: Plan) -> Result<Plan> {
+    if query.fetch.is_some() {
+        bail!(SQLError::Unsupported(String::from("FETCH")));
+    }
+
+    let limit = match &query.limit {
+        Some(limit) => limit_value("LIMIT", limit)?,
+        None => None,
+    };
+    let offset = match &query.offset {
+        Some(offset) => limit_value("OFFSET", &offset.value)?.unwrap_or(0),
+        None => 0,
+    };
+
+    if limit.is_none() && offset == 0 {
+        return Ok(plan);
+    }
+
+    let new_limit = |child: Plan| Plan {
+        node_type: PlanNodeType::Limit {
+            state: Box::new(LimitState {
+                limit,
+                offset,
+                returned: 0,
+                child,
+            }),
+        },
+    };
+
+    match plan.node_type {
+        PlanNodeType::Projection { state } => {
+            let ProjectionState {
+                projection,
+                exprs,
+                child,
+            } = *state;
             Ok(Plan {
                 node_type: PlanNodeType::Projection {
                     state: Box::new(ProjectionState {
                         projection,
-                        tuple_desc: tuple_desc.clone(),
-                        child: create_seq_scan(
-                            buffer_pool,
-                            db_oid,
-                            &rel_name,
-                            &pg_class,
-                            tuple_desc,
-                        )?,
+                        exprs,
+                        child: new_limit(child),
                     }),
                 },
             })
         }
-        _ => bail!(SQLError::Unsupported(from.relation.to_string())),
+        node_type => Ok(new_limit(Plan { node_type })),
+    }
+}
+
+/// Evaluate the value of a LIMIT or OFFSET clause. Return None if the value is NULL.
+fn limit_value(clause: &str, expr: &ast::Expr) -> Result<Option<u64>> {
+    let value = Expr::create(expr, &RangeTable::default())?.eval(&[])?;
+    match value.to_i64() {
+        Some(value) if value >= 0 => Ok(Some(value as u64)),
+        Some(_) => bail!(PgError::new(
+            SqlState::InvalidRowCountInLimitClause,
+            format!("{} must not be negative", clause)
+        )),
+        None if value == ScalarValue::Null => Ok(None),
+        None => bail!(PgError::new(
+            SqlState::DatatypeMismatch,
+            format!(
+                "argument of {} must be type integer, not {:?}",
+                clause, value
+            )
+        )),
     }
 }
 
+/// Wrap the given child plan into a filter plan node if the given selection is present,
+/// otherwise just return the child plan.
+///
+/// The selection is evaluated against the tuples returned by the child plan, which are described
+/// by the given range table. A sequential scan child may be replaced by a cheaper index scan of
+/// the same relation.
+fn create_filter(
+    buffer_pool: &BufferPool,
+    selection: &Option<ast::Expr>,
+    range_table: &RangeTable,
+    child: Plan,
+) -> Result<Plan> {
+    match selection {
+        Some(selection) => {
+            let qual = Expr::create(selection, range_table)?;
+            let child = match child.node_type {
+                PlanNodeType::SeqScan { state } => create_relation_scan(buffer_pool, &qual, state)?,
+                node_type => Plan { node_type },
+            };
+            Ok(Plan {
+                node_type: PlanNodeType::Filter {
+                    state: Box::new(FilterState { qual, child }),
+                },
+            })
+        }
+        None => Ok(child),
+    }
+}
+
+/// Return the cheapest plan to scan the relation of the given sequential scan, whose tuples are
+/// filtered by the given qual.
+///
+/// An index scan is used when the qual requires an indexed column to be equal to a constant and
+/// the estimated cost of the index scan, based on the statistics of the relation, is lower than
+/// the cost of the sequential scan. The qual is still evaluated on the tuples returned by the
+/// index scan, so any other condition of the qual is also checked.
+fn create_relation_scan(
+    buffer_pool: &BufferPool,
+    qual: &Expr,
+    seq_scan: Box<SeqScanState>,
+) -> Result<Plan> {
+    let locator = seq_scan.relation.locator.clone();
+
+    // System catalogs don't have indexes, and relations that were never analyzed don't have
+    // statistics to estimate the cost of an index scan.
+    let statistics = if locator.oid >= FIRST_NORMAL_OBJECT_ID {
+        catalog::get_relation_statistics(buffer_pool, &locator.database, &locator.oid)?
+    } else {
+        Vec::new()
+    };
+    let tuples = match statistics.first() {
+        Some(statistic) => statistic.stareltuples as f64,
+        None => {
+            return Ok(Plan {
+                node_type: PlanNodeType::SeqScan { state: seq_scan },
+            })
+        }
+    };
+
+    let pages = buffer_pool.size_of_relation(&seq_scan.relation)? as f64;
+    let mut cheapest = cost::seq_scan_cost(pages, tuples);
+    let mut index_scan = None;
+
+    let indexes = catalog::get_relation_indexes(buffer_pool, &locator.database, &locator.oid)?;
+    let classes = if indexes.is_empty() {
+        Vec::new()
+    } else {
+        catalog::get_pg_class_relations(buffer_pool, &locator.database)?
+    };
+    for index in indexes {
+        let index_name = match classes.iter().find(|class| class.oid == index.indexrelid) {
+            Some(class) => &class.relname,
+            None => continue,
+        };
+        let attr = &seq_scan.tuple_desc.attrs[index.indkey - 1];
+        let datum = match qual.column_equality(index.indkey - 1) {
+            Some(value) => match value.to_datum()? {
+                Some(datum) => datum,
+                None => continue,
+            },
+            None => continue,
+        };
+
+        let selectivity = statistics
+            .iter()
+            .find(|statistic| statistic.staattnum == index.indkey)
+            .map_or(1.0, |statistic| statistic.eq_selectivity());
+        let index_rel = access::open_relation(
+            index.indexrelid,
+            locator.tablespace,
+            &locator.database,
+            index_name,
+        );
+        let index_pages = buffer_pool.size_of_relation(&index_rel)? as f64;
+
+        let cost = cost::index_scan_cost(pages, tuples, index_pages, selectivity);
+        if cost < cheapest {
+            cheapest = cost;
+            index_scan = Some((index_rel, btree_encode_key(&datum, attr.atttypid)?));
+        }
+    }
+
+    match index_scan {
+        Some((index, key)) => {
+            let SeqScanState {
+                tuple_desc,
+                relation,
+                table_am,
+                lock,
+                ..
+            } = *seq_scan;
+            Ok(Plan {
+                node_type: PlanNodeType::IndexScan {
+                    state: Box::new(IndexScanState {
+                        tuple_desc,
+                        relation,
+                        table_am,
+                        index,
+                        key,
+                        tids: None,
+                        used_attrs: None,
+                        lock,
+                    }),
+                },
+            })
+        }
+        None => Ok(Plan {
+            node_type: PlanNodeType::SeqScan { state: seq_scan },
+        }),
+    }
+}
+
+/// Create a sequential scan plan node over the given relation, that keeps the given lock until it
+/// is dropped.
 fn create_seq_scan(
     buffer_pool: &BufferPool,
     db_oid: &Oid,
     rel_name: &str,
     pg_class_rel: &PgClass,
     tuple_desc: Arc<TupleDesc>,
+    lock: LockGuard,
 ) -> Result<Plan> {
     let relation = access::open_relation(
         pg_class_rel.oid,
@@ -155,13 +1205,18 @@ fn create_seq_scan(
         },
         &rel_name,
     );
+    let table_am = tableam::table_am(pg_class_rel.relam)?;
     Ok(Plan {
         node_type: PlanNodeType::SeqScan {
-            state: SeqScanState {
+            state: Box::new(SeqScanState {
                 tuple_desc,
                 relation: relation.clone(),
-                heap_scanner: HeapScanner::new(buffer_pool, &relation)?,
-            },
+                table_am,
+                scan: table_am.begin_scan(buffer_pool, &relation)?,
+                used_attrs: None,
+                scan_keys: Vec::new(),
+                lock,
+            }),
         },
     })
 }
@@ -171,6 +1226,15 @@ impl fmt::Display for PlanNodeType {
         match self {
             PlanNodeType::Projection { .. } => write!(f, "Projection"),
             PlanNodeType::SeqScan { .. } => write!(f, "SeqScan"),
+            PlanNodeType::IndexScan { .. } => write!(f, "IndexScan"),
+            PlanNodeType::VirtualScan { .. } => write!(f, "VirtualScan"),
+            PlanNodeType::SubqueryScan { .. } => write!(f, "SubqueryScan"),
+            PlanNodeType::Filter { .. } => write!(f, "Filter"),
+            PlanNodeType::NestedLoopJoin { .. } => write!(f, "NestedLoopJoin"),
+            PlanNodeType::Aggregate { .. } => write!(f, "Aggregate"),
+            PlanNodeType::Sort { .. } => write!(f, "Sort"),
+            PlanNodeType::Limit { .. } => write!(f, "Limit"),
+            PlanNodeType::Delete { .. } => write!(f, "Delete"),
         }
     }
 }
@@ -0,0 +1,730 @@
+//! Rewrite rules applied to plan trees before they are executed.
+//!
+//! The planner creates a plan tree that follows the structure of the query, and the optimizer
+//! rewrites it into an equivalent plan that is cheaper to execute. Each rule is applied on the
+//! whole plan tree rewritten by the previous rule, so rules can rely on the rewrites of the
+//! rules applied before them.
+
+use std::collections::BTreeSet;
+
+use anyhow::Result;
+use log::debug;
+
+use crate::{
+    catalog::pg_type,
+    expr::{Expr, ScalarValue},
+};
+
+use super::{FilterState, Plan, PlanNodeType, ScanKey};
+
+/// A rule that rewrites a plan tree into an equivalent plan tree, returning the same tuples.
+pub trait OptimizerRule {
+    /// Name of the rule.
+    fn name(&self) -> &'static str;
+
+    /// Rewrite the given plan tree.
+    fn rewrite(&self, plan: Plan) -> Result<Plan>;
+}
+
+/// Optimizer that applies a list of rules, in order, to plan trees.
+pub struct Optimizer {
+    rules: Vec<Box<dyn OptimizerRule>>,
+}
+
+impl Default for Optimizer {
+    /// Create an optimizer with all rules. Constants are folded first, so the predicates pushed
+    /// down and the attributes used by each plan node are already simplified.
+    fn default() -> Self {
+        Self::new(vec![
+            Box::new(ConstantFolding),
+            Box::new(PredicatePushdown),
+            Box::new(ScanKeyPushdown),
+            Box::new(ProjectionPruning),
+        ])
+    }
+}
+
+impl Optimizer {
+    /// Create an optimizer that applies the given rules.
+    pub fn new(rules: Vec<Box<dyn OptimizerRule>>) -> Self {
+        Self { rules }
+    }
+
+    /// Rewrite the given plan tree using all rules of the optimizer.
+    pub fn optimize(&self, plan: Plan) -> Result<Plan> {
+        self.rules.iter().try_fold(plan, |plan, rule| {
+            debug!("Applying optimizer rule {}", rule.name());
+            rule.rewrite(plan)
+        })
+    }
+}
+
+/// Replace the expressions of all plan nodes with their constant folded expressions.
+///
+/// Filters whose qual is always true are removed, as is the qual of joins.
+pub struct ConstantFolding;
+
+impl OptimizerRule for ConstantFolding {
+    fn name(&self) -> &'static str {
+        "constant_folding"
+    }
+
+    fn rewrite(&self, plan: Plan) -> Result<Plan> {
+        let plan = plan.map_children(|child| self.rewrite(child))?;
+        let always_true = |qual: &Expr| *qual == Expr::Const(ScalarValue::Bool(true));
+
+        let node_type = match plan.node_type {
+            PlanNodeType::Projection { mut state } => {
+                state.exprs = state.exprs.into_iter().map(Expr::fold_constants).collect();
+                PlanNodeType::Projection { state }
+            }
+            PlanNodeType::Filter { state } => {
+                let FilterState { qual, child } = *state;
+                let qual = qual.fold_constants();
+                if always_true(&qual) {
+                    return Ok(child);
+                }
+                PlanNodeType::Filter {
+                    state: Box::new(FilterState { qual, child }),
+                }
+            }
+            PlanNodeType::NestedLoopJoin { mut state } => {
+                state.qual = state
+                    .qual
+                    .map(Expr::fold_constants)
+                    .filter(|qual| !always_true(qual));
+                PlanNodeType::NestedLoopJoin { state }
+            }
+            PlanNodeType::Aggregate { mut state } => {
+                for aggregate in &mut state.aggregates {
+                    aggregate.arg = aggregate.arg.take().map(Expr::fold_constants);
+                }
+                PlanNodeType::Aggregate { state }
+            }
+            PlanNodeType::Sort { mut state } => {
+                for key in &mut state.keys {
+                    key.expr = key.expr.clone().fold_constants();
+                }
+                PlanNodeType::Sort { state }
+            }
+            node_type => node_type,
+        };
+        Ok(Plan { node_type })
+    }
+}
+
+/// Push the conditions of filters and join quals down to the side of the joins whose
+/// attributes they reference, so tuples are filtered before being joined.
+///
+/// Conditions that reference attributes of both sides of a join are evaluated by the join
+/// qual, which is equivalent to filtering the joined tuples for inner and cross joins.
+pub struct PredicatePushdown;
+
+impl OptimizerRule for PredicatePushdown {
+    fn name(&self) -> &'static str {
+        "predicate_pushdown"
+    }
+
+    fn rewrite(&self, plan: Plan) -> Result<Plan> {
+        let plan = match plan.node_type {
+            PlanNodeType::Filter { state }
+                if matches!(state.child.node_type, PlanNodeType::NestedLoopJoin { .. }) =>
+            {
+                let FilterState { qual, child } = *state;
+                push_into_join(qual, child)
+            }
+            PlanNodeType::NestedLoopJoin { mut state } => match state.qual.take() {
+                Some(qual) => push_into_join(
+                    qual,
+                    Plan {
+                        node_type: PlanNodeType::NestedLoopJoin { state },
+                    },
+                ),
+                None => Plan {
+                    node_type: PlanNodeType::NestedLoopJoin { state },
+                },
+            },
+            node_type => Plan { node_type },
+        };
+        plan.map_children(|child| self.rewrite(child))
+    }
+}
+
+/// Push the conditions of the given qual, evaluated on the tuples returned by the given join,
+/// and of the join qual down to the join sides.
+///
+/// The conditions are only split if all of them are boolean, otherwise a conjunction of them
+/// would fail when evaluated while the split conditions would just be false.
+fn push_into_join(qual: Expr, join: Plan) -> Plan {
+    let mut state = match join.node_type {
+        PlanNodeType::NestedLoopJoin { state } => state,
+        node_type => return add_filter(Plan { node_type }, vec![qual]),
+    };
+
+    // The join qual is evaluated before the filter above the join.
+    let mut conjuncts = match state.qual.take() {
+        Some(join_qual) => join_qual.into_conjuncts(),
+        None => Vec::new(),
+    };
+    conjuncts.extend(qual.into_conjuncts());
+
+    if !all_boolean(&conjuncts) {
+        state.qual = Expr::conjunction(conjuncts);
+        return Plan {
+            node_type: PlanNodeType::NestedLoopJoin { state },
+        };
+    }
+
+    let outer_width = state.outer.width();
+    let (mut outer, mut inner, mut join_qual) = (Vec::new(), Vec::new(), Vec::new());
+    for conjunct in conjuncts {
+        let mut columns = BTreeSet::new();
+        conjunct.referenced_columns(&mut columns);
+        match (columns.iter().next(), columns.iter().next_back()) {
+            (Some(first), _) if *first >= outer_width => {
+                inner.push(conjunct.remap_columns(&|index| index - outer_width))
+            }
+            (_, Some(last)) if *last >= outer_width => join_qual.push(conjunct),
+            _ => outer.push(conjunct),
+        }
+    }
+
+    state.outer = add_filter(state.outer, outer);
+    state.inner = add_filter(state.inner, inner);
+    state.qual = Expr::conjunction(join_qual);
+    Plan {
+        node_type: PlanNodeType::NestedLoopJoin { state },
+    }
+}
+
+/// Push the conditions of filters that reference a single attribute of a sequentially scanned
+/// relation into the scan as scan keys, so tuples that don't satisfy them are skipped before
+/// their other attributes are decoded.
+///
+/// Conditions that reference multiple attributes, or no attribute, are still evaluated by the
+/// filter, after the scan keys.
+pub struct ScanKeyPushdown;
+
+impl OptimizerRule for ScanKeyPushdown {
+    fn name(&self) -> &'static str {
+        "scan_key_pushdown"
+    }
+
+    fn rewrite(&self, plan: Plan) -> Result<Plan> {
+        let plan = plan.map_children(|child| self.rewrite(child))?;
+        let (qual, mut scan) = match plan.node_type {
+            PlanNodeType::Filter { state } => match *state {
+                FilterState {
+                    qual,
+                    child:
+                        Plan {
+                            node_type: PlanNodeType::SeqScan { state },
+                        },
+                } => (qual, state),
+                state => {
+                    return Ok(Plan {
+                        node_type: PlanNodeType::Filter {
+                            state: Box::new(state),
+                        },
+                    })
+                }
+            },
+            node_type => return Ok(Plan { node_type }),
+        };
+
+        // Like when pushing down the conditions of joins, they are only split if all of them
+        // are boolean.
+        let conjuncts = qual.into_conjuncts();
+        if !all_boolean(&conjuncts) {
+            return Ok(add_filter(
+                Plan {
+                    node_type: PlanNodeType::SeqScan { state: scan },
+                },
+                conjuncts,
+            ));
+        }
+
+        let mut remaining = Vec::new();
+        for conjunct in conjuncts {
+            let mut columns = BTreeSet::new();
+            conjunct.referenced_columns(&mut columns);
+            match columns.into_iter().collect::<Vec<_>>().as_slice() {
+                [attr] => scan.scan_keys.push(ScanKey {
+                    attr: *attr,
+                    qual: conjunct.remap_columns(&|_| 0),
+                }),
+                _ => remaining.push(conjunct),
+            }
+        }
+
+        Ok(add_filter(
+            Plan {
+                node_type: PlanNodeType::SeqScan { state: scan },
+            },
+            remaining,
+        ))
+    }
+}
+
+/// Return true if all of the given conditions are boolean.
+fn all_boolean(conjuncts: &[Expr]) -> bool {
+    conjuncts
+        .iter()
+        .all(|conjunct| conjunct.return_type() == Some(pg_type::BOOL_OID))
+}
+
+/// Filter the tuples returned by the given plan using the given conditions, merging them into
+/// the qual of the plan if it is already a filter.
+fn add_filter(plan: Plan, conjuncts: Vec<Expr>) -> Plan {
+    if conjuncts.is_empty() {
+        return plan;
+    }
+
+    let (conjuncts, child) = match plan.node_type {
+        PlanNodeType::Filter { state } => {
+            let FilterState { qual, child } = *state;
+            let mut merged = qual.into_conjuncts();
+            merged.extend(conjuncts);
+            (merged, child)
+        }
+        node_type => (conjuncts, Plan { node_type }),
+    };
+
+    match Expr::conjunction(conjuncts) {
+        Some(qual) => Plan {
+            node_type: PlanNodeType::Filter {
+                state: Box::new(FilterState { qual, child }),
+            },
+        },
+        None => child,
+    }
+}
+
+/// Mark the attributes of relations that are not used by any plan node above the relation
+/// scan, so they are not decoded when the tuples are fetched.
+pub struct ProjectionPruning;
+
+impl OptimizerRule for ProjectionPruning {
+    fn name(&self) -> &'static str {
+        "projection_pruning"
+    }
+
+    fn rewrite(&self, plan: Plan) -> Result<Plan> {
+        Ok(prune(plan, None))
+    }
+}
+
+/// Prune the attributes of the relations scanned by the given plan, where only the attributes
+/// of the given indexes of the tuples returned by the plan are used. None if all attributes are
+/// used.
+fn prune(plan: Plan, used: Option<BTreeSet<usize>>) -> Plan {
+    let referenced = |exprs: &mut dyn Iterator<Item = &Expr>| {
+        let mut columns = BTreeSet::new();
+        for expr in exprs {
+            expr.referenced_columns(&mut columns);
+        }
+        columns
+    };
+
+    let node_type = match plan.node_type {
+        PlanNodeType::Projection { mut state } => {
+            let used = referenced(&mut state.exprs.iter());
+            state.child = prune(state.child, Some(used));
+            PlanNodeType::Projection { state }
+        }
+        PlanNodeType::Aggregate { mut state } => {
+            let used = referenced(&mut state.aggregates.iter().flat_map(|agg| agg.arg.iter()));
+            state.child = prune(state.child, Some(used));
+            PlanNodeType::Aggregate { state }
+        }
+        PlanNodeType::Delete { mut state } => {
+            // Only the location of the tuples is used to delete them.
+            state.child = prune(state.child, Some(BTreeSet::new()));
+            PlanNodeType::Delete { state }
+        }
+        PlanNodeType::Limit { mut state } => {
+            state.child = prune(state.child, used);
+            PlanNodeType::Limit { state }
+        }
+        PlanNodeType::Sort { mut state } => {
+            let used = used.map(|mut used| {
+                for key in &state.keys {
+                    key.expr.referenced_columns(&mut used);
+                }
+                used
+            });
+            state.child = prune(state.child, used);
+            PlanNodeType::Sort { state }
+        }
+        PlanNodeType::Filter { mut state } => {
+            let used = used.map(|mut used| {
+                state.qual.referenced_columns(&mut used);
+                used
+            });
+            state.child = prune(state.child, used);
+            PlanNodeType::Filter { state }
+        }
+        PlanNodeType::NestedLoopJoin { mut state } => {
+            let (outer, inner) = match used {
+                Some(mut used) => {
+                    if let Some(qual) = &state.qual {
+                        qual.referenced_columns(&mut used);
+                    }
+                    let outer_width = state.outer.width();
+                    let inner = used.split_off(&outer_width);
+                    (
+                        Some(used),
+                        Some(inner.into_iter().map(|index| index - outer_width).collect()),
+                    )
+                }
+                None => (None, None),
+            };
+            state.outer = prune(state.outer, outer);
+            state.inner = prune(state.inner, inner);
+            PlanNodeType::NestedLoopJoin { state }
+        }
+        PlanNodeType::SubqueryScan { mut state } => {
+            // The subquery is planned independently, so all of its output is used.
+            state.child = prune(state.child, None);
+            PlanNodeType::SubqueryScan { state }
+        }
+        PlanNodeType::SeqScan { mut state } => {
+            state.used_attrs = used_attrs(state.tuple_desc.attrs.len(), used);
+            PlanNodeType::SeqScan { state }
+        }
+        PlanNodeType::IndexScan { mut state } => {
+            state.used_attrs = used_attrs(state.tuple_desc.attrs.len(), used);
+            PlanNodeType::IndexScan { state }
+        }
+        node_type @ PlanNodeType::VirtualScan { .. } => node_type,
+    };
+    Plan { node_type }
+}
+
+/// Return which of the attributes of a relation with the given number of attributes are used.
+/// None if all attributes are used.
+fn used_attrs(natts: usize, used: Option<BTreeSet<usize>>) -> Option<Vec<bool>> {
+    let used = used.filter(|used| used.len() < natts)?;
+    Some((0..natts).map(|index| used.contains(&index)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use sqlparser::{dialect::PostgreSqlDialect, parser::Parser, tokenizer::Tokenizer};
+
+    use super::*;
+    use crate::{
+        access::heaptuple::TupleDesc,
+        catalog::{
+            pg_attribute::PgAttribute,
+            virtual_table::{ScanContext, VirtualTable},
+        },
+        executor::Executor,
+        expr::RangeTable,
+        planner::{NestedLoopJoinState, ProjectionState, VirtualScanState},
+        storage::{smgr::StorageManager, BufferPool},
+        NullableDatum, Oid,
+    };
+
+    /// Virtual relation with the integer attributes a and b, where b is the double of a.
+    struct Numbers;
+
+    impl VirtualTable for Numbers {
+        fn oid(&self) -> Oid {
+            1
+        }
+
+        fn name(&self) -> &'static str {
+            "numbers"
+        }
+
+        fn tuple_desc(&self) -> TupleDesc {
+            let attr = |attname: &str, attnum: usize| PgAttribute {
+                attrelid: self.oid(),
+                attname: String::from(attname),
+                attnum,
+                attlen: 4,
+                atttypid: pg_type::INT_OID,
+                attnotnull: false,
+                attdefault: String::new(),
+            };
+            TupleDesc {
+                attrs: vec![attr("a", 1), attr("b", 2)],
+            }
+        }
+
+        fn scan(&self, _: &ScanContext) -> Result<Vec<Vec<NullableDatum>>> {
+            (1..=5)
+                .map(|a| {
+                    Ok(vec![
+                        ScalarValue::Int(a).to_datum()?,
+                        ScalarValue::Int(a * 2).to_datum()?,
+                    ])
+                })
+                .collect()
+        }
+    }
+
+    fn scan() -> Plan {
+        Plan {
+            node_type: PlanNodeType::VirtualScan {
+                state: Box::new(VirtualScanState {
+                    tuple_desc: Arc::new(Numbers.tuple_desc()),
+                    table: Arc::new(Numbers),
+                    tuples: None,
+                }),
+            },
+        }
+    }
+
+    fn range_table(name: &str) -> RangeTable {
+        RangeTable::new(name, Numbers.tuple_desc())
+    }
+
+    /// Range table of the join between the relations x and y.
+    fn join_range_table() -> Result<RangeTable> {
+        range_table("x").join(range_table("y"))
+    }
+
+    fn expr(sql: &str, range_table: &RangeTable) -> Result<Expr> {
+        let dialect = PostgreSqlDialect {};
+        let tokens = Tokenizer::new(&dialect, sql).tokenize()?;
+        Expr::create(&Parser::new(tokens, &dialect).parse_expr()?, range_table)
+    }
+
+    fn filter(qual: Expr, child: Plan) -> Plan {
+        Plan {
+            node_type: PlanNodeType::Filter {
+                state: Box::new(FilterState { qual, child }),
+            },
+        }
+    }
+
+    fn join(qual: Option<Expr>) -> Plan {
+        Plan {
+            node_type: PlanNodeType::NestedLoopJoin {
+                state: Box::new(NestedLoopJoinState {
+                    qual,
+                    outer_tuple: None,
+                    outer: scan(),
+                    inner: scan(),
+                }),
+            },
+        }
+    }
+
+    fn projection(exprs: Vec<Expr>, child: Plan) -> Plan {
+        let attr = Numbers.tuple_desc().attrs[0].clone();
+        Plan {
+            node_type: PlanNodeType::Projection {
+                state: Box::new(ProjectionState {
+                    projection: vec![attr; exprs.len()],
+                    exprs,
+                    child,
+                }),
+            },
+        }
+    }
+
+    /// Return the structure of the plan tree, with the qual of each filter and join.
+    fn describe(plan: &Plan) -> String {
+        match &plan.node_type {
+            PlanNodeType::Projection { state } => {
+                format!("Projection({})", describe(&state.child))
+            }
+            PlanNodeType::Filter { state } => {
+                format!("Filter[{:?}]({})", state.qual, describe(&state.child))
+            }
+            PlanNodeType::NestedLoopJoin { state } => format!(
+                "NestedLoopJoin[{:?}]({}, {})",
+                state.qual,
+                describe(&state.outer),
+                describe(&state.inner)
+            ),
+            node_type => node_type.to_string(),
+        }
+    }
+
+    /// Return the values of all tuples returned by the given plan.
+    fn execute(mut plan: Plan) -> Result<Vec<Vec<NullableDatum>>> {
+        let data_dir = tempfile::tempdir()?;
+        let buffer_pool = BufferPool::new(10, StorageManager::new(data_dir.path()));
+        Ok(Executor::new(&buffer_pool).exec(&mut plan)?.values)
+    }
+
+    /// Check that the rule rewrites the plan created by the given function into an equivalent
+    /// plan, returning the rewritten plan.
+    fn rewrite<F>(rule: &dyn OptimizerRule, plan: F) -> Result<Plan>
+    where
+        F: Fn() -> Result<Plan>,
+    {
+        let rewritten = rule.rewrite(plan()?)?;
+        let expected = execute(plan()?)?;
+        assert_eq!(execute(rule.rewrite(plan()?)?)?, expected);
+        Ok(rewritten)
+    }
+
+    #[test]
+    fn test_optimizer() -> Result<()> {
+        let range_table = range_table("t");
+        let plan = || -> Result<Plan> {
+            Ok(projection(
+                vec![expr("a + 1 * 2", &range_table)?],
+                filter(expr("1 = 1", &range_table)?, scan()),
+            ))
+        };
+
+        // No rule is applied by an empty optimizer.
+        let unchanged = Optimizer::new(Vec::new()).optimize(plan()?)?;
+        assert_eq!(describe(&unchanged), describe(&plan()?));
+        assert_eq!(execute(unchanged)?, execute(plan()?)?);
+
+        let optimized = Optimizer::default().optimize(plan()?)?;
+        match &optimized.node_type {
+            PlanNodeType::Projection { state } => {
+                assert_eq!(state.exprs, vec![expr("a + 2", &range_table)?]);
+                assert_eq!(describe(&state.child), "VirtualScan");
+            }
+            _ => panic!("unexpected plan {}", describe(&optimized)),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_constant_folding() -> Result<()> {
+        let range_table = range_table("t");
+
+        // Filters that are always true are removed.
+        let plan = rewrite(&ConstantFolding, || {
+            Ok(projection(
+                vec![expr("a", &range_table)?],
+                filter(expr("2 > 1 AND true", &range_table)?, scan()),
+            ))
+        })?;
+        assert_eq!(describe(&plan), "Projection(VirtualScan)");
+
+        let plan = rewrite(&ConstantFolding, || {
+            Ok(projection(
+                vec![expr("b * (1 + 1)", &range_table)?],
+                filter(expr("a > 1 + 1 AND 1 = 1", &range_table)?, scan()),
+            ))
+        })?;
+        let expected = projection(
+            vec![expr("b * 2", &range_table)?],
+            filter(expr("a > 2", &range_table)?, scan()),
+        );
+        assert_eq!(describe(&plan), describe(&expected));
+        match plan.node_type {
+            PlanNodeType::Projection { state } => {
+                assert_eq!(state.exprs, vec![expr("b * 2", &range_table)?])
+            }
+            _ => unreachable!(),
+        }
+
+        // Filters that are never true are kept.
+        let plan = rewrite(&ConstantFolding, || {
+            Ok(projection(
+                vec![expr("a", &range_table)?],
+                filter(expr("1 = 2", &range_table)?, scan()),
+            ))
+        })?;
+        assert_eq!(
+            describe(&plan),
+            format!(
+                "Projection(Filter[{:?}](VirtualScan))",
+                expr("false", &range_table)?
+            )
+        );
+
+        // Join quals that are always true are removed.
+        let plan = rewrite(&ConstantFolding, || {
+            let range_table = join_range_table()?;
+            Ok(projection(
+                vec![expr("x.a", &range_table)?],
+                join(Some(expr("1 = 1", &range_table)?)),
+            ))
+        })?;
+        assert_eq!(
+            describe(&plan),
+            "Projection(NestedLoopJoin[None](VirtualScan, VirtualScan))"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_predicate_pushdown() -> Result<()> {
+        let joined = join_range_table()?;
+        let x = range_table("x");
+        let y = range_table("y");
+
+        // Conditions are pushed to the side of the join whose attributes they reference, and
+        // the conditions referencing both sides are evaluated by the join.
+        let plan = rewrite(&PredicatePushdown, || {
+            Ok(projection(
+                vec![expr("x.a", &joined)?, expr("y.b", &joined)?],
+                filter(
+                    expr("x.a > 1 AND y.a < 4 AND x.b = y.b AND y.b <> 4", &joined)?,
+                    join(None),
+                ),
+            ))
+        })?;
+        assert_eq!(
+            describe(&plan),
+            format!(
+                "Projection(NestedLoopJoin[{:?}](Filter[{:?}](VirtualScan), Filter[{:?}](VirtualScan)))",
+                Some(expr("x.b = y.b", &joined)?),
+                expr("a > 1", &x)?,
+                expr("a < 4 AND b <> 4", &y)?,
+            )
+        );
+
+        // Conditions of the join qual are also pushed down.
+        let plan = rewrite(&PredicatePushdown, || {
+            Ok(projection(
+                vec![expr("x.a", &joined)?],
+                join(Some(expr("x.a = y.a AND y.b > 4", &joined)?)),
+            ))
+        })?;
+        assert_eq!(
+            describe(&plan),
+            format!(
+                "Projection(NestedLoopJoin[{:?}](VirtualScan, Filter[{:?}](VirtualScan)))",
+                Some(expr("x.a = y.a", &joined)?),
+                expr("b > 4", &y)?,
+            )
+        );
+
+        // Conditions are not split if any of them is not boolean, so evaluating them still
+        // fails.
+        let plan = PredicatePushdown.rewrite(projection(
+            vec![expr("x.a", &joined)?],
+            filter(expr("x.a > 1 AND y.a", &joined)?, join(None)),
+        ))?;
+        assert_eq!(
+            describe(&plan),
+            format!(
+                "Projection(NestedLoopJoin[{:?}](VirtualScan, VirtualScan))",
+                Some(expr("x.a > 1 AND y.a", &joined)?),
+            )
+        );
+        assert!(execute(plan).is_err());
+
+        // Filters that are not above a join are kept.
+        let plan = rewrite(&PredicatePushdown, || {
+            Ok(projection(
+                vec![expr("a", &x)?],
+                filter(expr("a > 1", &x)?, scan()),
+            ))
+        })?;
+        assert_eq!(
+            describe(&plan),
+            format!("Projection(Filter[{:?}](VirtualScan))", expr("a > 1", &x)?)
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,474 @@
+//! A minimal client of the Postgres wire protocol, used to test the backend over a real
+//! connection without external tools like psql.
+//!
+//! Only the simple query protocol is supported. Values are always returned on the text format
+//! and COPY FROM STDIN is refused by sending a CopyFail, since the client has no data to send.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{BufRead, Cursor},
+};
+
+use anyhow::{bail, Result};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpStream, ToSocketAddrs},
+};
+
+use crate::{backend::auth, Oid};
+
+use super::{
+    commands::{
+        BackendKeyData, AUTHENTICATION_TAG, AUTH_TYPE_CLEARTEXT_PASSWORD, AUTH_TYPE_MD5_PASSWORD,
+        AUTH_TYPE_OK, BACKEND_KEY_DATA_TAG, CANCEL_REQUEST_CODE, COMMAND_COMPLETE_TAG,
+        COPY_DATA_TAG, COPY_DONE_TAG, COPY_IN_RESPONSE_TAG, COPY_OUT_RESPONSE_TAG, DATA_ROW_TAG,
+        EMPTY_QUERY_RESPONSE_TAG, ERROR_RESPONSE_TAG, NOTICE_RESPONSE_TAG, PARAMETER_STATUS_TAG,
+        PROTOCOL_VERSION_NUMBER, READY_FOR_QUERY_TAG, ROW_DESCRIPTION_TAG,
+    },
+    Stream,
+};
+
+/// A column of the rows returned by a query.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Column {
+    pub name: String,
+
+    /// Oid of the type of the column values.
+    pub type_oid: Oid,
+}
+
+/// Result of a single statement executed by Client::simple_query.
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryResult {
+    /// Rows returned by a statement, formatted as text. NULL values are None.
+    Rows {
+        columns: Vec<Column>,
+        rows: Vec<Vec<Option<String>>>,
+        tag: String,
+    },
+
+    /// A statement that don't return rows, with its command tag.
+    Command(String),
+
+    /// Data sent by COPY TO STDOUT, with its command tag.
+    CopyOut { data: Vec<u8>, tag: String },
+
+    /// An empty query.
+    Empty,
+}
+
+/// An error or notice sent by the server.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerError {
+    pub severity: String,
+
+    /// SQLSTATE code of the error.
+    pub code: String,
+
+    pub message: String,
+    pub detail: Option<String>,
+    pub hint: Option<String>,
+}
+
+impl fmt::Display for ServerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:  {}", self.severity, self.message)?;
+        if let Some(detail) = &self.detail {
+            write!(f, "\nDETAIL:  {}", detail)?;
+        }
+        if let Some(hint) = &self.hint {
+            write!(f, "\nHINT:  {}", hint)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ServerError {}
+
+/// A connection to the server, authenticated and ready to execute queries.
+pub struct Client<S: Stream = TcpStream> {
+    stream: BufReader<S>,
+
+    /// Run-time parameters reported by the server with ParameterStatus.
+    parameters: HashMap<String, String>,
+
+    /// Key data needed to cancel queries of this connection.
+    key: Option<BackendKeyData>,
+}
+
+impl Client {
+    /// Connect to the server at the given address as the given user to the given database.
+    /// The password is only sent if the server asks for it.
+    pub async fn connect<A: ToSocketAddrs>(
+        addr: A,
+        user: &str,
+        database: &str,
+        password: Option<&str>,
+    ) -> Result<Self> {
+        let parameters = [("user", user), ("database", database)];
+        Self::startup(TcpStream::connect(addr).await?, &parameters, password).await
+    }
+
+    /// Ask the server at the given address to cancel the query running on the connection with
+    /// the given key data. The server never replies to a cancel request, so this only waits
+    /// until the server closes the new connection after handling it.
+    pub async fn cancel<A: ToSocketAddrs>(addr: A, key: &BackendKeyData) -> Result<()> {
+        let mut stream = TcpStream::connect(addr).await?;
+        let mut buf = 16_u32.to_be_bytes().to_vec();
+        buf.extend_from_slice(&CANCEL_REQUEST_CODE.to_be_bytes());
+        buf.extend_from_slice(&key.process_id.to_be_bytes());
+        buf.extend_from_slice(&key.secret_key.to_be_bytes());
+        stream.write_all(&buf).await?;
+        stream.read_to_end(&mut Vec::new()).await?;
+        Ok(())
+    }
+}
+
+impl<S: Stream> Client<S> {
+    /// Send a StartupMessage with the given parameters over the given stream and authenticate,
+    /// waiting until the server is ready for queries. The parameters must include the user.
+    pub async fn startup(
+        stream: S,
+        parameters: &[(&str, &str)],
+        password: Option<&str>,
+    ) -> Result<Self> {
+        let user = match parameters.iter().find(|(name, _)| *name == "user") {
+            Some((_, user)) => *user,
+            None => bail!("no user given on startup parameters"),
+        };
+        let mut client = Self {
+            stream: BufReader::new(stream),
+            parameters: HashMap::new(),
+            key: None,
+        };
+
+        let mut body = PROTOCOL_VERSION_NUMBER.to_be_bytes().to_vec();
+        for (name, value) in parameters {
+            put_cstring(&mut body, name);
+            put_cstring(&mut body, value);
+        }
+        body.push(0);
+        client.send(None, &body).await?;
+
+        loop {
+            let (tag, mut body) = client.receive().await?;
+            match tag {
+                AUTHENTICATION_TAG => match body.read_u32().await? {
+                    AUTH_TYPE_OK => {}
+                    AUTH_TYPE_CLEARTEXT_PASSWORD => {
+                        let password = required_password(password)?;
+                        client.send_password(password).await?;
+                    }
+                    AUTH_TYPE_MD5_PASSWORD => {
+                        let mut salt = [0; 4];
+                        body.read_exact(&mut salt).await?;
+                        let password = required_password(password)?;
+                        client
+                            .send_password(&md5_password(user, password, &salt))
+                            .await?;
+                    }
+                    auth_type => bail!("unsupported authentication type {}", auth_type),
+                },
+                PARAMETER_STATUS_TAG => {
+                    let name = get_cstring(&mut body)?;
+                    let value = get_cstring(&mut body)?;
+                    client.parameters.insert(name, value);
+                }
+                BACKEND_KEY_DATA_TAG => {
+                    client.key = Some(BackendKeyData::decode(body.get_ref())?);
+                }
+                ERROR_RESPONSE_TAG => return Err(parse_error(&mut body).await?.into()),
+                NOTICE_RESPONSE_TAG => {}
+                READY_FOR_QUERY_TAG => return Ok(client),
+                tag => bail!("unexpected message {:?} on startup", tag as char),
+            }
+        }
+    }
+
+    /// Return the current value of a run-time parameter reported by the server.
+    pub fn parameter(&self, name: &str) -> Option<&str> {
+        self.parameters.get(name).map(String::as_str)
+    }
+
+    /// Return the key data sent by the server to cancel queries of this connection.
+    pub fn backend_key(&self) -> Option<BackendKeyData> {
+        self.key
+    }
+
+    /// Execute the given query using the simple query protocol, returning the result of each
+    /// statement. If a statement fails, the error is returned as a ServerError after the server
+    /// is ready for a new query, so the client can still be used.
+    pub async fn simple_query(&mut self, query: &str) -> Result<Vec<QueryResult>> {
+        let mut body = Vec::new();
+        put_cstring(&mut body, query);
+        self.send(Some(b'Q'), &body).await?;
+
+        let mut results = Vec::new();
+        let mut columns = None;
+        let mut rows = Vec::new();
+        let mut copy_data = None;
+        let mut error: Option<ServerError> = None;
+        loop {
+            // The server closes the connection after a FATAL error, which is the one reported.
+            let (tag, mut body) = match self.receive().await {
+                Ok(message) => message,
+                Err(err) => match error {
+                    Some(error) => return Err(error.into()),
+                    None => return Err(err),
+                },
+            };
+            match tag {
+                ROW_DESCRIPTION_TAG => {
+                    columns = Some(parse_row_description(&mut body).await?);
+                    rows = Vec::new();
+                }
+                DATA_ROW_TAG => rows.push(parse_data_row(&mut body).await?),
+                COPY_OUT_RESPONSE_TAG => copy_data = Some(Vec::new()),
+                COPY_DATA_TAG => match &mut copy_data {
+                    Some(data) => data.extend_from_slice(body.get_ref()),
+                    None => bail!("unexpected CopyData outside of COPY"),
+                },
+                COPY_DONE_TAG => {}
+                COPY_IN_RESPONSE_TAG => {
+                    let mut body = Vec::new();
+                    put_cstring(&mut body, "COPY FROM STDIN is not supported by the client");
+                    self.send(Some(b'f'), &body).await?;
+                }
+                COMMAND_COMPLETE_TAG => {
+                    let tag = get_cstring(&mut body)?;
+                    results.push(match (columns.take(), copy_data.take()) {
+                        (Some(columns), _) => QueryResult::Rows {
+                            columns,
+                            rows: std::mem::take(&mut rows),
+                            tag,
+                        },
+                        (None, Some(data)) => QueryResult::CopyOut { data, tag },
+                        (None, None) => QueryResult::Command(tag),
+                    });
+                }
+                EMPTY_QUERY_RESPONSE_TAG => results.push(QueryResult::Empty),
+                ERROR_RESPONSE_TAG => error = Some(parse_error(&mut body).await?),
+                NOTICE_RESPONSE_TAG => {}
+                PARAMETER_STATUS_TAG => {
+                    let name = get_cstring(&mut body)?;
+                    let value = get_cstring(&mut body)?;
+                    self.parameters.insert(name, value);
+                }
+                READY_FOR_QUERY_TAG => break,
+                tag => bail!("unexpected message {:?} on query", tag as char),
+            }
+        }
+
+        match error {
+            Some(err) => Err(err.into()),
+            None => Ok(results),
+        }
+    }
+
+    /// Send a Terminate and close the connection.
+    pub async fn terminate(mut self) -> Result<()> {
+        self.send(Some(b'X'), &[]).await?;
+        self.stream.get_mut().shutdown().await?;
+        Ok(())
+    }
+
+    async fn send_password(&mut self, password: &str) -> Result<()> {
+        let mut body = Vec::new();
+        put_cstring(&mut body, password);
+        self.send(Some(b'p'), &body).await
+    }
+
+    /// Send a message with the given type and body. The StartupMessage is the only message
+    /// without a type.
+    async fn send(&mut self, msg_type: Option<u8>, body: &[u8]) -> Result<()> {
+        let mut buf = Vec::with_capacity(body.len() + 5);
+        buf.extend(msg_type);
+        buf.extend_from_slice(&(body.len() as u32 + 4).to_be_bytes());
+        buf.extend_from_slice(body);
+        self.stream.get_mut().write_all(&buf).await?;
+        Ok(())
+    }
+
+    /// Receive the type and the body of the next message sent by the server.
+    async fn receive(&mut self) -> Result<(u8, Cursor<Vec<u8>>)> {
+        let msg_type = self.stream.read_u8().await?;
+        let msg_len = self.stream.read_u32().await?;
+        if msg_len < 4 {
+            bail!("invalid message length {}", msg_len);
+        }
+        let mut body = vec![0; msg_len as usize - 4];
+        self.stream.read_exact(&mut body).await?;
+        Ok((msg_type, Cursor::new(body)))
+    }
+}
+
+fn required_password(password: Option<&str>) -> Result<&str> {
+    match password {
+        Some(password) => Ok(password),
+        None => bail!("the server requested password authentication, but no password was given"),
+    }
+}
+
+/// Return the password hashed as expected by AuthenticationMD5Password: "md5" followed by the
+/// md5 of the encrypted password (without the md5 prefix) concatenated with the salt.
+fn md5_password(user: &str, password: &str, salt: &[u8; 4]) -> String {
+    let encrypted = auth::encrypt_password(user, password);
+    let mut data = encrypted.trim_start_matches("md5").as_bytes().to_vec();
+    data.extend_from_slice(salt);
+    format!("md5{}", auth::md5_hex(&data))
+}
+
+async fn parse_row_description(body: &mut Cursor<Vec<u8>>) -> Result<Vec<Column>> {
+    let mut columns = Vec::new();
+    for _ in 0..body.read_u16().await? {
+        let name = get_cstring(body)?;
+        let _table_oid = body.read_u32().await?;
+        let _attnum = body.read_i16().await?;
+        let type_oid = body.read_u32().await? as Oid;
+        let _type_len = body.read_i16().await?;
+        let _type_modifier = body.read_i32().await?;
+        let _format = body.read_i16().await?;
+        columns.push(Column { name, type_oid });
+    }
+    Ok(columns)
+}
+
+async fn parse_data_row(body: &mut Cursor<Vec<u8>>) -> Result<Vec<Option<String>>> {
+    let mut values = Vec::new();
+    for _ in 0..body.read_u16().await? {
+        let len = body.read_i32().await?;
+        if len < 0 {
+            values.push(None);
+            continue;
+        }
+        let mut value = vec![0; len as usize];
+        body.read_exact(&mut value).await?;
+        values.push(Some(String::from_utf8(value)?));
+    }
+    Ok(values)
+}
+
+/// Parse the fields of an ErrorResponse or NoticeResponse.
+async fn parse_error(body: &mut Cursor<Vec<u8>>) -> Result<ServerError> {
+    let mut err = ServerError {
+        severity: String::new(),
+        code: String::new(),
+        message: String::new(),
+        detail: None,
+        hint: None,
+    };
+    loop {
+        let field = body.read_u8().await?;
+        if field == 0 {
+            return Ok(err);
+        }
+        let value = get_cstring(body)?;
+        match field {
+            b'S' => err.severity = value,
+            b'C' => err.code = value,
+            b'M' => err.message = value,
+            b'D' => err.detail = Some(value),
+            b'H' => err.hint = Some(value),
+            _ => {}
+        }
+    }
+}
+
+fn put_cstring(buf: &mut Vec<u8>, value: &str) {
+    buf.extend_from_slice(value.as_bytes());
+    buf.push(0);
+}
+
+/// Read a null terminated string.
+fn get_cstring(body: &mut Cursor<Vec<u8>>) -> Result<String> {
+    let mut buf = Vec::new();
+    body.read_until(0, &mut buf)?;
+    if buf.pop() != Some(0) {
+        bail!("invalid string in message");
+    }
+    Ok(String::from_utf8(buf)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        backend::{auth::AuthMethod, Backend},
+        initdb::init_database,
+        storage::{smgr::StorageManager, BufferPool},
+    };
+    use tokio::net::TcpListener;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_simple_query() -> Result<()> {
+        let data_dir = tempfile::tempdir()?;
+        let buffer_pool = BufferPool::new(50, StorageManager::new(data_dir.path()));
+        init_database(&buffer_pool, data_dir.path(), "tinydb", Some("secret"))?;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let backend = Backend::new(listener, buffer_pool, AuthMethod::Md5);
+        let server = tokio::spawn(async move { backend.start().await });
+
+        let err = Client::connect(addr, "tinydb", "tinydb", Some("wrong"))
+            .await
+            .err()
+            .expect("connected with a wrong password");
+        assert_eq!(err.downcast_ref::<ServerError>().unwrap().code, "28P01");
+
+        let mut client = Client::connect(addr, "tinydb", "tinydb", Some("secret")).await?;
+        assert!(client.backend_key().is_some());
+        assert!(client.parameter("server_version").is_some());
+
+        let results = client
+            .simple_query("CREATE TABLE t(a int, b text); INSERT INTO t VALUES (1, 'one'), (2, NULL); SELECT a, b FROM t; ;")
+            .await?;
+        assert_eq!(
+            results,
+            vec![
+                QueryResult::Command(String::from("CREATE")),
+                QueryResult::Command(String::from("INSERT 0 2")),
+                QueryResult::Rows {
+                    columns: vec![
+                        Column {
+                            name: String::from("a"),
+                            type_oid: crate::catalog::pg_type::INT_OID,
+                        },
+                        Column {
+                            name: String::from("b"),
+                            type_oid: crate::catalog::pg_type::TEXT_OID,
+                        },
+                    ],
+                    rows: vec![
+                        vec![Some(String::from("1")), Some(String::from("one"))],
+                        vec![Some(String::from("2")), None],
+                    ],
+                    tag: String::from("SELECT 2"),
+                },
+            ]
+        );
+
+        let results = client.simple_query("COPY t TO STDOUT").await?;
+        assert_eq!(
+            results,
+            vec![QueryResult::CopyOut {
+                data: b"1\tone\n2\t\\N\n".to_vec(),
+                tag: String::from("COPY 2"),
+            }]
+        );
+        assert_eq!(client.simple_query("").await?, vec![QueryResult::Empty]);
+
+        // The client can still be used after an error.
+        let err = client
+            .simple_query("SELECT a FROM missing")
+            .await
+            .unwrap_err();
+        assert_eq!(err.downcast_ref::<ServerError>().unwrap().code, "42P01");
+        assert_eq!(client.simple_query("SELECT a FROM t").await?.len(), 1);
+
+        client.terminate().await?;
+        server.abort();
+        Ok(())
+    }
+}
@@ -10,11 +10,17 @@ use byteorder::{BigEndian, ByteOrder};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
 use crate::{
-    sql::{encode, PGResult, RowDescriptor},
+    sql::{
+        encode,
+        error::{PgError, SqlState},
+        PGResult, RowDescriptor, FORMAT_BINARY, FORMAT_TEXT,
+    },
     Oid,
 };
 
 pub const AUTH_TYPE_OK: u32 = 0;
+pub const AUTH_TYPE_CLEARTEXT_PASSWORD: u32 = 3;
+pub const AUTH_TYPE_MD5_PASSWORD: u32 = 5;
 pub const PROTOCOL_VERSION_NUMBER: u32 = 196608; // 3.0
 pub const SSL_REQUEST_NUMBER: u32 = 80877103;
 pub const CANCEL_REQUEST_CODE: u32 = 80877102;
@@ -42,19 +48,69 @@ pub const PARAMETER_DESCRIPTION_TAG: u8 = b't';
 pub const ROW_DESCRIPTION_TAG: u8 = b'T';
 pub const READY_FOR_QUERY_TAG: u8 = b'Z';
 
+/// Number of DataRow messages encoded in memory before writing them at once to the client.
+pub const DATA_ROW_BATCH_SIZE: usize = 256;
+
 #[derive(Debug)]
 pub enum Message {
     StartupMessage(StartupMessage),
     Query(Query),
     Terminate,
-    ReadyForQuery,
+    ReadyForQuery(TransactionStatus),
     CommandComplete(String),
     RowDescriptor(RowDescriptor),
     AuthenticationOk,
-    BackendKeyData,
+    AuthenticationCleartextPassword,
+    AuthenticationMD5Password([u8; 4]),
+    PasswordMessage(String),
+    BackendKeyData(BackendKeyData),
+    CancelRequest(BackendKeyData),
     ParameterStatus(ParameterStatus),
     DataRow(PGResult),
     ErrorResponse(ErrorResponse),
+    Parse(Parse),
+    Bind(Bind),
+    Describe(Target),
+    Execute(Execute),
+    Close(Target),
+    Sync,
+    Flush,
+    ParseComplete,
+    BindComplete,
+    CloseComplete,
+    PortalSuspended,
+    EmptyQueryResponse,
+    NoData,
+    ParameterDescription(Vec<Oid>),
+    CopyInResponse(usize),
+    CopyOutResponse(usize),
+    CopyData(Vec<u8>),
+    CopyDone,
+    CopyFail(String),
+}
+
+/// Transaction status of a backend connection, sent to the client on each ReadyForQuery.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransactionStatus {
+    /// Not in a transaction block.
+    Idle,
+
+    /// In a transaction block.
+    InTransaction,
+
+    /// In a failed transaction block, queries will be rejected until block is ended.
+    Failed,
+}
+
+impl TransactionStatus {
+    /// Return the status indicator byte sent on ReadyForQuery.
+    pub fn indicator(&self) -> u8 {
+        match self {
+            Self::Idle => b'I',
+            Self::InTransaction => b'T',
+            Self::Failed => b'E',
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -62,6 +118,72 @@ pub struct Query {
     pub query: String,
 }
 
+/// Parse message of the extended query protocol, creating a prepared statement.
+#[derive(Debug)]
+pub struct Parse {
+    /// Name of the prepared statement, empty for the unnamed statement.
+    pub statement: String,
+
+    pub query: String,
+
+    /// Declared type of the parameters, INVALID_OID if the type is not specified.
+    pub param_types: Vec<Oid>,
+}
+
+/// Bind message of the extended query protocol, creating a portal from a prepared statement
+/// bound with parameter values.
+#[derive(Debug)]
+pub struct Bind {
+    /// Name of the portal, empty for the unnamed portal.
+    pub portal: String,
+
+    /// Name of the prepared statement, empty for the unnamed statement.
+    pub statement: String,
+
+    /// Format of the parameter values. Empty if all parameters are text, or a single format used
+    /// by all parameters.
+    pub param_formats: Vec<i16>,
+
+    /// Value of each parameter, None for NULL.
+    pub params: Vec<Option<Vec<u8>>>,
+
+    /// Format of the result columns. Empty if all columns are text, or a single format used by
+    /// all columns.
+    pub result_formats: Vec<i16>,
+}
+
+/// Execute message of the extended query protocol.
+#[derive(Debug)]
+pub struct Execute {
+    /// Name of the portal, empty for the unnamed portal.
+    pub portal: String,
+
+    /// Maximum number of rows to return, 0 for no limit.
+    pub max_rows: i32,
+}
+
+/// Prepared statement or portal that a Describe or Close message refers to.
+#[derive(Debug, PartialEq)]
+pub enum Target {
+    Statement(String),
+    Portal(String),
+}
+
+impl Target {
+    async fn decode(src: &mut Cursor<Vec<u8>>) -> anyhow::Result<Self> {
+        let kind = src.read_u8().await?;
+        let name = read_cstring(src)?;
+        match kind {
+            b'S' => Ok(Self::Statement(name)),
+            b'P' => Ok(Self::Portal(name)),
+            _ => bail!(PgError::new(
+                SqlState::ProtocolViolation,
+                format!("invalid describe or close target {}", kind)
+            )),
+        }
+    }
+}
+
 pub async fn decode<R>(decode_from: &mut R) -> anyhow::Result<Message>
 where
     R: AsyncReadExt + std::marker::Unpin,
@@ -74,26 +196,149 @@ where
 
             // Exclude the msg_len when reading
             let mut msg_body = vec![0; (msg_len as usize) - 4];
-            decode_from.read(&mut msg_body).await?;
+            decode_from.read_exact(&mut msg_body).await?;
 
             // Exclude the \0 at the end when parsing.
             let _ = msg_body.pop();
             let query = String::from_utf8(msg_body)?;
             Ok(Message::Query(Query { query }))
         }
+        b'p' => {
+            let msg_len = decode_from.read_u32().await?;
+
+            // Exclude the msg_len when reading
+            let mut msg_body = vec![0; (msg_len as usize) - 4];
+            decode_from.read_exact(&mut msg_body).await?;
+
+            // Exclude the \0 at the end when parsing.
+            let _ = msg_body.pop();
+            let password = String::from_utf8(msg_body)?;
+            Ok(Message::PasswordMessage(password))
+        }
         b'X' => Ok(Message::Terminate),
+        b'P' => {
+            let mut src = read_body(decode_from).await?;
+            let statement = read_cstring(&mut src)?;
+            let query = read_cstring(&mut src)?;
+            let mut param_types = Vec::new();
+            for _ in 0..src.read_u16().await? {
+                param_types.push(src.read_u32().await? as Oid);
+            }
+            Ok(Message::Parse(Parse {
+                statement,
+                query,
+                param_types,
+            }))
+        }
+        b'B' => {
+            let mut src = read_body(decode_from).await?;
+            let portal = read_cstring(&mut src)?;
+            let statement = read_cstring(&mut src)?;
+            let param_formats = read_formats(&mut src).await?;
+            let mut params = Vec::new();
+            for _ in 0..src.read_u16().await? {
+                let len = src.read_i32().await?;
+                if len < 0 {
+                    params.push(None);
+                } else {
+                    let mut value = vec![0; len as usize];
+                    src.read_exact(&mut value).await?;
+                    params.push(Some(value));
+                }
+            }
+            let result_formats = read_formats(&mut src).await?;
+            Ok(Message::Bind(Bind {
+                portal,
+                statement,
+                param_formats,
+                params,
+                result_formats,
+            }))
+        }
+        b'D' => Ok(Message::Describe(
+            Target::decode(&mut read_body(decode_from).await?).await?,
+        )),
+        b'E' => {
+            let mut src = read_body(decode_from).await?;
+            Ok(Message::Execute(Execute {
+                portal: read_cstring(&mut src)?,
+                max_rows: src.read_i32().await?,
+            }))
+        }
+        b'C' => Ok(Message::Close(
+            Target::decode(&mut read_body(decode_from).await?).await?,
+        )),
+        b'S' => {
+            read_body(decode_from).await?;
+            Ok(Message::Sync)
+        }
+        b'H' => {
+            read_body(decode_from).await?;
+            Ok(Message::Flush)
+        }
+        b'd' => Ok(Message::CopyData(
+            read_body(decode_from).await?.into_inner(),
+        )),
+        b'c' => {
+            read_body(decode_from).await?;
+            Ok(Message::CopyDone)
+        }
+        b'f' => Ok(Message::CopyFail(read_cstring(
+            &mut read_body(decode_from).await?,
+        )?)),
         _ => anyhow::bail!("Message type {} not supported", msg_type),
     }
 }
 
+/// Read the body of a message, after the message type.
+async fn read_body<R>(decode_from: &mut R) -> anyhow::Result<Cursor<Vec<u8>>>
+where
+    R: AsyncReadExt + std::marker::Unpin,
+{
+    let msg_len = decode_from.read_u32().await?;
+    if msg_len < 4 {
+        bail!(PgError::new(
+            SqlState::ProtocolViolation,
+            format!("invalid message length {}", msg_len)
+        ));
+    }
+
+    // Exclude the msg_len when reading
+    let mut msg_body = vec![0; (msg_len as usize) - 4];
+    decode_from.read_exact(&mut msg_body).await?;
+    Ok(Cursor::new(msg_body))
+}
+
+/// Read a null terminated string.
+fn read_cstring(src: &mut Cursor<Vec<u8>>) -> anyhow::Result<String> {
+    let mut buf = Vec::new();
+    src.read_until(0, &mut buf)?;
+    if buf.pop() != Some(0) {
+        bail!(PgError::new(
+            SqlState::ProtocolViolation,
+            "invalid string in message"
+        ));
+    }
+    Ok(String::from_utf8(buf)?)
+}
+
+/// Read a list of format codes, where 0 is text and 1 is binary.
+async fn read_formats(src: &mut Cursor<Vec<u8>>) -> anyhow::Result<Vec<i16>> {
+    let mut formats = Vec::new();
+    for _ in 0..src.read_u16().await? {
+        formats.push(src.read_i16().await?);
+    }
+    Ok(formats)
+}
+
 pub async fn encode<W>(encode_to: &mut W, message: Message) -> anyhow::Result<()>
 where
     W: AsyncWriteExt + std::marker::Unpin,
 {
     match message {
-        Message::ReadyForQuery => {
+        Message::ReadyForQuery(status) => {
             encode_to
-                .write(&[READY_FOR_QUERY_TAG, 0, 0, 0, 5, EMPTY_QUERY_RESPONSE_TAG])
+                .write_all(&[READY_FOR_QUERY_TAG, 0, 0, 0, 5, status.indicator()])
                 .await?;
             Ok(())
         }
@@ -131,14 +376,25 @@ where
             encode_to.write_u32(AUTH_TYPE_OK).await?;
             Ok(())
         }
-        Message::BackendKeyData => {
+        Message::AuthenticationCleartextPassword => {
+            encode_to.write_u8(AUTHENTICATION_TAG).await?;
+            encode_to.write_i32(8).await?;
+            encode_to.write_u32(AUTH_TYPE_CLEARTEXT_PASSWORD).await?;
+            Ok(())
+        }
+        Message::AuthenticationMD5Password(salt) => {
+            encode_to.write_u8(AUTHENTICATION_TAG).await?;
+            encode_to.write_i32(12).await?;
+            encode_to.write_u32(AUTH_TYPE_MD5_PASSWORD).await?;
+            encode_to.write_all(&salt).await?;
+            Ok(())
+        }
+        Message::BackendKeyData(key) => {
             encode_to.write_u8(BACKEND_KEY_DATA_TAG).await?;
             // message lenght
             encode_to.write_u32(12).await?;
-            // process id
-            encode_to.write_u32(42).await?;
-            // secret key
-            encode_to.write_u32(12345).await?;
+            encode_to.write_u32(key.process_id).await?;
+            encode_to.write_u32(key.secret_key).await?;
             Ok(())
         }
         Message::ParameterStatus(status) => {
@@ -154,21 +410,32 @@ where
             Ok(())
         }
         Message::DataRow(result) => {
+            // Rows are written in batches, so the encoded result is never entirely in memory
+            // and each row is released as soon as it is encoded.
             let mut data_rows = Vec::new();
+            let mut buf_row = Vec::new();
+
+            for (i, row) in result.tuples.into_iter().enumerate() {
+                if i > 0 && i % DATA_ROW_BATCH_SIZE == 0 {
+                    encode_to.write_all(&data_rows).await?;
+                    data_rows.clear();
+                }
 
-            for row in result.tuples {
                 let row = row.iter();
-                let mut buf_row = Vec::new();
+                buf_row.clear();
 
                 buf_row.write_u16(row.len() as u16).await?;
                 for (attnum, datum) in row.enumerate() {
                     match datum {
                         Some(datum) => match &result.desc.fields.get(attnum) {
                             Some(att_desc) => {
-                                let datum = encode::decode(datum, att_desc.data_type_oid as Oid)?;
-                                let datum = datum.as_bytes();
+                                let typ = att_desc.data_type_oid as Oid;
+                                let datum = match att_desc.format {
+                                    FORMAT_BINARY => encode::decode_binary(datum, typ)?,
+                                    _ => encode::decode(datum, typ)?.into_bytes(),
+                                };
                                 buf_row.write_u32(datum.len() as u32).await?;
-                                buf_row.write(datum).await?;
+                                buf_row.write_all(&datum).await?;
                             }
                             None => {
                                 bail!("Can not find field desc for attnum {}", attnum)
@@ -182,23 +449,113 @@ where
                 }
                 data_rows.write_u8(DATA_ROW_TAG).await?;
                 data_rows.write_i32((buf_row.len() as i32) + 4).await?;
-                data_rows.write(&buf_row).await?;
+                data_rows.write_all(&buf_row).await?;
             }
 
-            encode_to.write(&data_rows).await?;
+            encode_to.write_all(&data_rows).await?;
 
             Ok(())
         }
-        Message::StartupMessage(_) | Message::Query(_) => {
+        Message::ParseComplete => {
+            encode_to
+                .write_all(&[PARSE_COMPLETE_TAG, 0, 0, 0, 4])
+                .await?;
+            Ok(())
+        }
+        Message::BindComplete => {
+            encode_to
+                .write_all(&[BIND_COMPLETE_TAG, 0, 0, 0, 4])
+                .await?;
+            Ok(())
+        }
+        Message::CloseComplete => {
+            encode_to
+                .write_all(&[CLOSE_COMPLETE_TAG, 0, 0, 0, 4])
+                .await?;
+            Ok(())
+        }
+        Message::PortalSuspended => {
+            encode_to
+                .write_all(&[PORTAL_SUSPENDED_TAG, 0, 0, 0, 4])
+                .await?;
+            Ok(())
+        }
+        Message::EmptyQueryResponse => {
+            encode_to
+                .write_all(&[EMPTY_QUERY_RESPONSE_TAG, 0, 0, 0, 4])
+                .await?;
+            Ok(())
+        }
+        Message::NoData => {
+            encode_to.write_all(&[NO_DATA_TAG, 0, 0, 0, 4]).await?;
+            Ok(())
+        }
+        Message::ParameterDescription(types) => {
+            let mut buf = Vec::new();
+            buf.write_u16(types.len() as u16).await?;
+            for typ in types {
+                buf.write_u32(typ as u32).await?;
+            }
+
+            encode_to.write_u8(PARAMETER_DESCRIPTION_TAG).await?;
+            encode_to.write_i32((buf.len() as i32) + 4).await?;
+            encode_to.write_all(&buf).await?;
+            Ok(())
+        }
+        Message::CopyInResponse(columns) => {
+            encode_copy_response(encode_to, COPY_IN_RESPONSE_TAG, columns).await
+        }
+        Message::CopyOutResponse(columns) => {
+            encode_copy_response(encode_to, COPY_OUT_RESPONSE_TAG, columns).await
+        }
+        Message::CopyData(data) => {
+            encode_to.write_u8(COPY_DATA_TAG).await?;
+            encode_to.write_i32((data.len() as i32) + 4).await?;
+            encode_to.write_all(&data).await?;
+            Ok(())
+        }
+        Message::CopyDone => {
+            encode_to.write_all(&[COPY_DONE_TAG, 0, 0, 0, 4]).await?;
+            Ok(())
+        }
+        Message::StartupMessage(_)
+        | Message::CopyFail(_)
+        | Message::Query(_)
+        | Message::PasswordMessage(_)
+        | Message::CancelRequest(_)
+        | Message::Parse(_)
+        | Message::Bind(_)
+        | Message::Describe(_)
+        | Message::Execute(_)
+        | Message::Close(_)
+        | Message::Sync
+        | Message::Flush => {
             bail!("can not encode message {:?}", message)
         }
         Message::ErrorResponse(err) => {
             encode_to.write_u8(ERROR_RESPONSE_TAG).await?;
             let mut buf = Vec::new();
 
-            buf.write_u8(b'M').await?;
-            buf.write(&err.error.to_string().as_bytes()).await?;
-            buf.write_u8(0).await?;
+            let err = PgError::from_error(&err.error);
+            let severity = err.severity.to_string();
+            let mut fields = vec![
+                (b'S', severity.as_str()),
+                (b'V', severity.as_str()),
+                (b'C', err.code.code()),
+                (b'M', err.message.as_str()),
+            ];
+            if let Some(detail) = &err.detail {
+                fields.push((b'D', detail));
+            }
+            if let Some(hint) = &err.hint {
+                fields.push((b'H', hint));
+            }
+
+            for (field, value) in fields {
+                buf.write_u8(field).await?;
+                buf.write_all(value.as_bytes()).await?;
+                buf.write_u8(0).await?;
+            }
 
             // Mark the the end of error response.
             buf.write_u8(0).await?;
@@ -211,6 +568,25 @@ where
     }
 }
 
+/// Encode a CopyInResponse or CopyOutResponse for the given number of columns. All columns use
+/// the text format.
+async fn encode_copy_response<W>(encode_to: &mut W, tag: u8, columns: usize) -> anyhow::Result<()>
+where
+    W: AsyncWriteExt + std::marker::Unpin,
+{
+    let mut buf = Vec::new();
+    buf.write_i8(FORMAT_TEXT as i8).await?;
+    buf.write_u16(columns as u16).await?;
+    for _ in 0..columns {
+        buf.write_i16(FORMAT_TEXT).await?;
+    }
+
+    encode_to.write_u8(tag).await?;
+    encode_to.write_i32((buf.len() as i32) + 4).await?;
+    encode_to.write_all(&buf).await?;
+    Ok(())
+}
+
 #[derive(Debug)]
 pub struct ParameterStatus {
     pub key: String,
@@ -222,6 +598,27 @@ pub struct ErrorResponse {
     pub error: Error,
 }
 
+/// Key data that identifies a backend connection. Clients must send the same key data on a cancel
+/// request to cancel the query that is running on the backend connection.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BackendKeyData {
+    pub process_id: u32,
+    pub secret_key: u32,
+}
+
+impl BackendKeyData {
+    pub fn decode(src: &[u8]) -> anyhow::Result<Self> {
+        if src.len() < 8 {
+            anyhow::bail!("cancel request to short");
+        }
+
+        Ok(Self {
+            process_id: BigEndian::read_u32(src),
+            secret_key: BigEndian::read_u32(&src[4..]),
+        })
+    }
+}
+
 #[derive(Debug)]
 pub struct StartupMessage {
     pub protocol_version: u32,
@@ -263,3 +660,43 @@ impl StartupMessage {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Datum;
+
+    #[tokio::test]
+    async fn test_encode_data_rows_in_batches() -> anyhow::Result<()> {
+        let rows = DATA_ROW_BATCH_SIZE * 2 + 1;
+        let tuples = (0..rows)
+            .map(|i| Ok(vec![Some(Datum::from(i.to_string().into_bytes())), None]))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let mut buf = Vec::new();
+        encode(
+            &mut buf,
+            Message::DataRow(PGResult::text(&["a", "b"], tuples)),
+        )
+        .await?;
+
+        let mut src = Cursor::new(buf);
+        for i in 0..rows {
+            assert_eq!(src.read_u8().await?, DATA_ROW_TAG);
+            let len = src.read_i32().await? as usize;
+            let mut row = vec![0; len - 4];
+            src.read_exact(&mut row).await?;
+
+            let value = i.to_string();
+            let mut expected = Vec::new();
+            expected.extend_from_slice(&2u16.to_be_bytes());
+            expected.extend_from_slice(&(value.len() as u32).to_be_bytes());
+            expected.extend_from_slice(value.as_bytes());
+            expected.extend_from_slice(&(-1i32).to_be_bytes());
+            assert_eq!(row, expected);
+        }
+        assert_eq!(src.position() as usize, src.get_ref().len());
+
+        Ok(())
+    }
+}
@@ -1,19 +1,48 @@
+pub mod client;
 pub mod commands;
 
-use std::{io, net::SocketAddr};
-
-use async_recursion::async_recursion;
 use byteorder::{BigEndian, ByteOrder};
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt, BufReader},
-    net::TcpStream,
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+    net::{TcpStream, UnixStream},
 };
 
-use anyhow::{Error, Result};
+use anyhow::{bail, Error, Result};
+
+use crate::{
+    sql::{
+        error::{PgError, SqlState},
+        PGResult, RowDescriptor,
+    },
+    Oid,
+};
 
-use crate::sql::PGResult;
+use self::commands::{
+    BackendKeyData, Message, ParameterStatus, StartupMessage, TransactionStatus,
+    CANCEL_REQUEST_CODE, PROTOCOL_VERSION_NUMBER, SSL_REQUEST_NUMBER,
+};
 
-use self::commands::{Message, StartupMessage, PROTOCOL_VERSION_NUMBER, SSL_REQUEST_NUMBER};
+/// A stream that connections use to read and write messages from a client.
+pub trait Stream: AsyncRead + AsyncWrite + Unpin + Send {
+    /// Return a description of the client connected on the other side of the stream.
+    fn peer(&self) -> String;
+}
+
+impl Stream for TcpStream {
+    fn peer(&self) -> String {
+        match self.peer_addr() {
+            Ok(addr) => addr.to_string(),
+            Err(_) => String::from("unknown"),
+        }
+    }
+}
+
+impl Stream for UnixStream {
+    fn peer(&self) -> String {
+        // Like Postgres, clients connected using a unix socket are reported as local.
+        String::from("[local]")
+    }
+}
 
 /// Connection implements the Postgres wire protocol (version 3 of the protocol, implemented
 /// by Postgres 7.4 an later). receive() reads protocol messages, and return a Message type
@@ -21,18 +50,28 @@ use self::commands::{Message, StartupMessage, PROTOCOL_VERSION_NUMBER, SSL_REQUE
 ///
 /// The connection handler execute the commands returned by receive() method and use the
 /// connection to send the appropriate messages back to the client.
+///
+/// A connection can be used over a TCP or unix domain socket stream.
 #[derive(Debug)]
-pub struct Connection {
-    // The `TcpStream` used to read and write data back and from the client.
-    stream: BufReader<TcpStream>,
+pub struct Connection<S: Stream = TcpStream> {
+    // The `Stream` used to read and write data back and from the client.
+    stream: BufReader<S>,
+
+    /// Transaction status sent to the client on each ReadyForQuery.
+    transaction_status: TransactionStatus,
+
+    /// Tag of the last CommandComplete sent to the client.
+    last_command_tag: Option<String>,
 }
 
-impl Connection {
+impl<S: Stream> Connection<S> {
     /// Create a new `Connection`, backed by `socket`. Read and write buffers
     /// are initialized.
-    pub fn new(socket: TcpStream) -> Connection {
+    pub fn new(socket: S) -> Connection<S> {
         Connection {
             stream: BufReader::new(socket),
+            transaction_status: TransactionStatus::Idle,
+            last_command_tag: None,
         }
     }
 
@@ -46,15 +85,87 @@ impl Connection {
 
     /// Send a query result back to the client.
     pub async fn send_result(&mut self, result: PGResult) -> Result<()> {
-        let rows = result.tuples.len();
+        self.send_row_description(result.desc.clone()).await?;
+        let tag = format!("SELECT {}", result.tuples.len());
+        self.send_rows(result, &tag).await
+    }
 
-        commands::encode(
-            &mut self.stream,
-            Message::RowDescriptor(result.desc.clone()),
-        )
-        .await?;
+    /// Send the rows of a statement result back to the client, without the row description,
+    /// followed by the given command tag. Used by the extended query protocol, where the row
+    /// description is sent on Describe.
+    pub async fn send_rows(&mut self, result: PGResult, tag: &str) -> Result<()> {
+        commands::encode(&mut self.stream, Message::DataRow(result)).await?;
+        self.command_complete(tag).await?;
+        Ok(())
+    }
+
+    /// Send the rows returned by an Execute message that reached its row limit, followed by a
+    /// PortalSuspended instead of a command tag, since the portal has more rows to return.
+    pub async fn send_suspended_rows(&mut self, result: PGResult) -> Result<()> {
         commands::encode(&mut self.stream, Message::DataRow(result)).await?;
-        self.command_complete(&format!("SELECT {}", rows)).await?;
+        commands::encode(&mut self.stream, Message::PortalSuspended).await?;
+        Ok(())
+    }
+
+    /// Send a RowDescription describing the rows that will be returned.
+    pub async fn send_row_description(&mut self, desc: RowDescriptor) -> Result<()> {
+        commands::encode(&mut self.stream, Message::RowDescriptor(desc)).await?;
+        Ok(())
+    }
+
+    /// Send a ParameterDescription with the types of the parameters of a prepared statement.
+    pub async fn send_parameter_description(&mut self, types: Vec<Oid>) -> Result<()> {
+        commands::encode(&mut self.stream, Message::ParameterDescription(types)).await?;
+        Ok(())
+    }
+
+    /// Send a NoData when a described statement or portal don't return rows.
+    pub async fn send_no_data(&mut self) -> Result<()> {
+        commands::encode(&mut self.stream, Message::NoData).await?;
+        Ok(())
+    }
+
+    /// Send to the client that a Parse message is completed.
+    pub async fn parse_complete(&mut self) -> Result<()> {
+        commands::encode(&mut self.stream, Message::ParseComplete).await?;
+        Ok(())
+    }
+
+    /// Send to the client that a Bind message is completed.
+    pub async fn bind_complete(&mut self) -> Result<()> {
+        commands::encode(&mut self.stream, Message::BindComplete).await?;
+        Ok(())
+    }
+
+    /// Send to the client that a Close message is completed.
+    pub async fn close_complete(&mut self) -> Result<()> {
+        commands::encode(&mut self.stream, Message::CloseComplete).await?;
+        Ok(())
+    }
+
+    /// Send a CopyInResponse, so the client start sending the data of a COPY FROM STDIN with the
+    /// given number of columns.
+    pub async fn send_copy_in_response(&mut self, columns: usize) -> Result<()> {
+        commands::encode(&mut self.stream, Message::CopyInResponse(columns)).await?;
+        Ok(())
+    }
+
+    /// Send a CopyOutResponse, before sending the data of a COPY TO STDOUT with the given number
+    /// of columns.
+    pub async fn send_copy_out_response(&mut self, columns: usize) -> Result<()> {
+        commands::encode(&mut self.stream, Message::CopyOutResponse(columns)).await?;
+        Ok(())
+    }
+
+    /// Send a chunk of data of a COPY TO STDOUT.
+    pub async fn send_copy_data(&mut self, data: Vec<u8>) -> Result<()> {
+        commands::encode(&mut self.stream, Message::CopyData(data)).await?;
+        Ok(())
+    }
+
+    /// Send to the client that all data of a COPY TO STDOUT was sent.
+    pub async fn send_copy_done(&mut self) -> Result<()> {
+        commands::encode(&mut self.stream, Message::CopyDone).await?;
         Ok(())
     }
 
@@ -68,51 +179,132 @@ impl Connection {
         Ok(())
     }
 
-    /// Send to the client that the command returned by receive() is completed.
+    /// Send to the client that a command is completed. A ReadyForQuery must be sent after all
+    /// commands of a query are completed.
     pub async fn command_complete(&mut self, tag: &str) -> Result<()> {
         commands::encode(
             &mut self.stream,
             Message::CommandComplete(String::from(tag)),
         )
         .await?;
-        self.ready_for_query().await?;
+        self.last_command_tag = Some(String::from(tag));
+        Ok(())
+    }
+
+    /// Return the tag of the last CommandComplete sent to the client, if any.
+    pub fn last_command_tag(&self) -> Option<&str> {
+        self.last_command_tag.as_deref()
+    }
+
+    /// Send an EmptyQueryResponse instead of a command tag when the query has no statements.
+    pub async fn empty_query_response(&mut self) -> Result<()> {
+        commands::encode(&mut self.stream, Message::EmptyQueryResponse).await?;
         Ok(())
     }
 
-    /// Send a ReadyForQuery to the client.
+    /// Send a ReadyForQuery with the current transaction status to the client.
     pub async fn ready_for_query(&mut self) -> Result<()> {
-        commands::encode(&mut self.stream, Message::ReadyForQuery).await?;
+        commands::encode(
+            &mut self.stream,
+            Message::ReadyForQuery(self.transaction_status),
+        )
+        .await?;
         Ok(())
     }
 
-    /// Return the startup message from the client.
-    #[async_recursion]
-    pub async fn startup_message(&mut self) -> Result<StartupMessage> {
-        let msg_size = self.stream.read_u32().await? - 4;
+    /// Return the transaction status sent to the client on ReadyForQuery.
+    pub fn transaction_status(&self) -> TransactionStatus {
+        self.transaction_status
+    }
+
+    /// Change the transaction status sent to the client on the next ReadyForQuery messages.
+    pub fn set_transaction_status(&mut self, status: TransactionStatus) {
+        self.transaction_status = status;
+    }
+
+    /// Return the startup message from the client, which is a Message::StartupMessage or a
+    /// Message::CancelRequest if the client wants to cancel a query running on other connection.
+    ///
+    /// TLS connections are not supported, so an SSLRequest is declined with 'N': the client can
+    /// continue the startup without encryption on the same connection, or close it if it
+    /// requires TLS (e.g psql with sslmode=require). The request can only be sent once.
+    pub async fn startup_message(&mut self) -> Result<Message> {
+        let mut ssl_requested = false;
+        loop {
+            let msg_size = self.stream.read_u32().await? - 4;
 
-        let mut buf = vec![0; msg_size as usize];
-        self.stream.read(&mut buf).await?;
-        let code = BigEndian::read_u32(&buf);
+            let mut buf = vec![0; msg_size as usize];
+            self.stream.read_exact(&mut buf).await?;
+            let code = BigEndian::read_u32(&buf);
 
-        match code {
-            PROTOCOL_VERSION_NUMBER => StartupMessage::decode(&buf),
-            SSL_REQUEST_NUMBER => {
-                self.stream.write(&"N".as_bytes()).await?;
-                self.startup_message().await
+            match code {
+                PROTOCOL_VERSION_NUMBER => {
+                    return Ok(Message::StartupMessage(StartupMessage::decode(&buf)?))
+                }
+                CANCEL_REQUEST_CODE => {
+                    return Ok(Message::CancelRequest(BackendKeyData::decode(&buf[4..])?))
+                }
+                SSL_REQUEST_NUMBER if !ssl_requested => {
+                    log::info!(
+                        "rejecting SSL request from {}, TLS connections are not supported",
+                        self.peer()
+                    );
+                    self.stream.write_all(b"N").await?;
+                    ssl_requested = true;
+                }
+                SSL_REQUEST_NUMBER => bail!(PgError::new(
+                    SqlState::ProtocolViolation,
+                    "duplicate SSL negotiation request"
+                )),
+                _ => bail!("Unexpected startup code: {}", code),
             }
-            _ => anyhow::bail!("Unexpected startup code: {}", code),
         }
     }
 
-    /// Send an AuthenticationOk with a ReadForQuery command back to the client.
-    pub async fn send_authentication_ok(&mut self) -> Result<()> {
+    /// Send an AuthenticationOk, the given parameters status and backend key data with a
+    /// ReadForQuery command back to the client.
+    pub async fn send_authentication_ok(
+        &mut self,
+        parameters: Vec<(&str, &str)>,
+        key: BackendKeyData,
+    ) -> Result<()> {
         commands::encode(&mut self.stream, Message::AuthenticationOk).await?;
+        for (name, value) in parameters {
+            self.send_parameter_status(name, value).await?;
+        }
+        commands::encode(&mut self.stream, Message::BackendKeyData(key)).await?;
         self.ready_for_query().await?;
         Ok(())
     }
 
-    /// Returns the remote address that this stream is connected to.
-    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
-        self.stream.get_ref().peer_addr()
+    /// Send a ParameterStatus reporting the current value of the given parameter.
+    pub async fn send_parameter_status(&mut self, name: &str, value: &str) -> Result<()> {
+        commands::encode(
+            &mut self.stream,
+            Message::ParameterStatus(ParameterStatus {
+                key: String::from(name),
+                value: String::from(value),
+            }),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Send an AuthenticationCleartextPassword asking the client for a cleartext password.
+    pub async fn send_authentication_cleartext_password(&mut self) -> Result<()> {
+        commands::encode(&mut self.stream, Message::AuthenticationCleartextPassword).await?;
+        Ok(())
+    }
+
+    /// Send an AuthenticationMD5Password asking the client for a password hashed with the given
+    /// salt.
+    pub async fn send_authentication_md5_password(&mut self, salt: [u8; 4]) -> Result<()> {
+        commands::encode(&mut self.stream, Message::AuthenticationMD5Password(salt)).await?;
+        Ok(())
+    }
+
+    /// Returns a description of the client that this stream is connected to.
+    pub fn peer(&self) -> String {
+        self.stream.get_ref().peer()
     }
 }
@@ -0,0 +1,663 @@
+//! Interactive shell executing statements on an embedded database.
+//!
+//! SQL statements can span multiple lines, each statement is executed when a line ends it with a
+//! semicolon. Lines starting with a backslash outside of statements are meta-commands of the
+//! shell itself:
+//!
+//! - `\dt`: list the user tables.
+//! - `\d table`: describe the columns of a table.
+//! - `\l`: list the databases.
+//! - `\format table|csv|json`: change how query results are printed.
+//! - `\timing [on|off]`: toggle printing the execution time of each statement.
+//! - `\q`: quit the shell.
+
+use std::{
+    fmt,
+    io::{BufRead, Write},
+    str::FromStr,
+    time::Instant,
+};
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::{
+    catalog::pg_type,
+    database::{Database, Rows, StatementResult},
+    Oid,
+};
+
+/// Prompt printed before reading each line of interactive shells.
+const PROMPT: &str = "tinydb=> ";
+
+/// Prompt printed before reading lines that continue a statement.
+const CONTINUATION_PROMPT: &str = "tinydb-> ";
+
+/// Formats of printing query results.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    /// Aligned table with a header and a footer with the number of rows, like psql.
+    Table,
+
+    /// Comma separated values with a header line. Null values are empty fields.
+    Csv,
+
+    /// Array of JSON objects, one per row, with the column names as keys.
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "table" => Ok(Self::Table),
+            "csv" => Ok(Self::Csv),
+            "json" => Ok(Self::Json),
+            _ => Err(anyhow!(
+                "invalid output format \"{}\", expected table, csv or json",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Table => write!(f, "table"),
+            Self::Csv => write!(f, "csv"),
+            Self::Json => write!(f, "json"),
+        }
+    }
+}
+
+/// Shell reading statements and meta-commands from an input and writing their results on an
+/// output.
+pub struct Shell {
+    db: Database,
+    format: OutputFormat,
+
+    /// Print the execution time of lines of SQL.
+    timing: bool,
+
+    /// Print each statement and meta-command before its result.
+    echo: bool,
+}
+
+impl Shell {
+    /// Create a shell executing statements on the given database.
+    pub fn new(db: Database) -> Self {
+        Self {
+            db,
+            format: OutputFormat::Table,
+            timing: false,
+            echo: false,
+        }
+    }
+
+    /// Print each statement and meta-command executed by run before its result, like psql
+    /// --echo-all, so the output of a script can be read without the script.
+    pub fn with_echo(mut self, echo: bool) -> Self {
+        self.echo = echo;
+        self
+    }
+
+    /// Execute all statements and meta-commands of the given input, writing the results on the
+    /// given output. A prompt is printed before each line if interactive is true. Errors of
+    /// executing a statement are printed and the next statements are still executed. A
+    /// statement without a terminating semicolon at the end of the input is also executed.
+    pub fn run<R: BufRead, W: Write>(
+        &mut self,
+        input: R,
+        mut out: W,
+        interactive: bool,
+    ) -> Result<()> {
+        let mut buffer = StatementBuffer::default();
+        let mut lines = input.lines();
+        loop {
+            if interactive {
+                let prompt = if buffer.is_empty() {
+                    PROMPT
+                } else {
+                    CONTINUATION_PROMPT
+                };
+                write!(out, "{}", prompt)?;
+                out.flush()?;
+            }
+            let line = match lines.next() {
+                Some(line) => line?,
+                None => break,
+            };
+
+            if buffer.is_empty() && line.trim_start().starts_with('\\') {
+                let line = line.trim();
+                if line[1..].trim() == "q" {
+                    break;
+                }
+                self.exec_and_print(line, &mut out)?;
+                continue;
+            }
+            for stmt in buffer.push_line(&line) {
+                self.exec_and_print(&stmt, &mut out)?;
+            }
+        }
+
+        if !buffer.is_empty() {
+            let stmt = buffer.take();
+            self.exec_and_print(&stmt, &mut out)?;
+        }
+        Ok(())
+    }
+
+    /// Execute the given line, printing the error if it fails.
+    fn exec_and_print<W: Write>(&mut self, line: &str, out: &mut W) -> Result<()> {
+        if self.echo {
+            writeln!(out, "{}", line)?;
+        }
+        if let Err(err) = self.exec_line(line, out) {
+            print_error(&err, line.starts_with('\\'), out)?;
+        }
+        out.flush()?;
+        Ok(())
+    }
+
+    /// Execute a single line of SQL statements or a meta-command, writing the results on the
+    /// given output. Errors are returned instead of printed.
+    pub fn exec_line<W: Write>(&mut self, line: &str, out: &mut W) -> Result<()> {
+        let line = line.trim();
+        if line.is_empty() {
+            return Ok(());
+        }
+        if let Some(command) = line.strip_prefix('\\') {
+            return self.exec_meta_command(command, out);
+        }
+
+        let start = Instant::now();
+        let result = self.exec_sql(line, out);
+        if self.timing {
+            let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+            writeln!(out, "Time: {:.3} ms", elapsed)?;
+        }
+        result
+    }
+
+    /// Execute the given meta-command, without the leading backslash.
+    fn exec_meta_command<W: Write>(&mut self, command: &str, out: &mut W) -> Result<()> {
+        let mut args = command.split_whitespace();
+        match (args.next(), args.next()) {
+            (Some("dt"), None) | (Some("d"), None) => {
+                print_rows(&self.db.list_tables()?, self.format, out)?
+            }
+            (Some("d"), Some(table)) => {
+                print_rows(&self.db.describe_table(table)?, self.format, out)?
+            }
+            (Some("l"), None) => print_rows(&self.db.list_databases()?, self.format, out)?,
+            (Some("timing"), value) => {
+                self.timing = match value {
+                    Some("on") => true,
+                    Some("off") => false,
+                    Some(value) => bail!("unrecognized value \"{}\" for \"\\timing\"", value),
+                    None => !self.timing,
+                };
+                let state = if self.timing { "on" } else { "off" };
+                writeln!(out, "Timing is {}.", state)?;
+            }
+            (Some("format"), Some(format)) => {
+                self.format = format.parse()?;
+                writeln!(out, "Output format is {}.", self.format)?;
+            }
+            (Some("format"), None) => writeln!(out, "Output format is {}.", self.format)?,
+            _ => writeln!(out, "invalid command \\{}", command)?,
+        }
+        Ok(())
+    }
+
+    /// Execute the given SQL statements, printing the rows of queries and the command tag of
+    /// other statements.
+    fn exec_sql<W: Write>(&mut self, sql: &str, out: &mut W) -> Result<()> {
+        let format = self.format;
+        self.db.execute_each(sql, |result| {
+            match result {
+                StatementResult::Rows(rows) => print_rows(&rows, format, out)?,
+                StatementResult::Command { tag, .. } => writeln!(out, "{}", tag)?,
+            }
+            Ok(())
+        })
+    }
+}
+
+/// SQL text read from the input that is split into statements at semicolons, ignoring the
+/// semicolons inside of quotes and comments.
+#[derive(Debug, Default)]
+struct StatementBuffer {
+    /// Text of the incomplete statement.
+    text: String,
+
+    /// Quote character of the quoted string or identifier that is not closed yet.
+    quote: Option<char>,
+}
+
+impl StatementBuffer {
+    /// Append the given line, returning the statements that it completes.
+    fn push_line(&mut self, line: &str) -> Vec<String> {
+        let mut stmts = Vec::new();
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            match (self.quote, c) {
+                (Some(quote), c) if c == quote => self.quote = None,
+                (Some(_), _) => {}
+                (None, '\'' | '"') => self.quote = Some(c),
+                // Comments are removed, since they would also comment out the next lines.
+                (None, '-') if chars.peek() == Some(&'-') => break,
+                (None, ';') => {
+                    self.text.push(';');
+                    stmts.push(self.take());
+                    continue;
+                }
+                _ => {}
+            }
+            self.text.push(c);
+        }
+        if !self.is_empty() {
+            self.text.push('\n');
+        }
+        stmts
+    }
+
+    /// Return true if there is not any incomplete statement.
+    fn is_empty(&self) -> bool {
+        self.quote.is_none() && self.text.trim().is_empty()
+    }
+
+    /// Return the text of the incomplete statement, clearing the buffer.
+    fn take(&mut self) -> String {
+        self.quote = None;
+        std::mem::take(&mut self.text).trim().to_string()
+    }
+}
+
+/// Write the given error of executing a line, SQL errors are prefixed with ERROR like psql.
+fn print_error<W: Write>(err: &anyhow::Error, meta_command: bool, out: &mut W) -> Result<()> {
+    if meta_command {
+        writeln!(out, "{}", err)?;
+    } else {
+        writeln!(out, "ERROR:  {}", err)?;
+    }
+    Ok(())
+}
+
+/// Write the given rows on the given output using the given format.
+pub fn print_rows<W: Write>(rows: &Rows, format: OutputFormat, out: &mut W) -> Result<()> {
+    let columns = rows.columns();
+    let types = rows.column_types();
+    let values = rows
+        .iter()
+        .map(|row| {
+            (0..row.len())
+                .map(|idx| row.get::<_, Option<String>>(idx))
+                .collect::<Result<Vec<_>>>()
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    match format {
+        OutputFormat::Table => {
+            print_table(&columns, &types, &values, out)?;
+            let rows = rows.stats().rows;
+            let plural = if rows == 1 { "" } else { "s" };
+            writeln!(out, "({} row{})", rows, plural)?;
+            Ok(())
+        }
+        OutputFormat::Csv => print_csv(&columns, &values, out),
+        OutputFormat::Json => print_json(&columns, &types, &values, out),
+    }
+}
+
+/// Return true if values of the given type are numbers, which are right aligned on tables and
+/// not quoted on JSON.
+fn is_numeric(typ: Oid) -> bool {
+    matches!(
+        typ,
+        pg_type::INT2_OID
+            | pg_type::INT_OID
+            | pg_type::INT8_OID
+            | pg_type::FLOAT4_OID
+            | pg_type::FLOAT8_OID
+    )
+}
+
+fn print_table<W: Write>(
+    columns: &[String],
+    types: &[Oid],
+    values: &[Vec<Option<String>>],
+    out: &mut W,
+) -> Result<()> {
+    let mut widths = columns
+        .iter()
+        .map(|column| column.chars().count())
+        .collect::<Vec<_>>();
+    for row in values {
+        for (width, value) in widths.iter_mut().zip(row) {
+            let len = value.as_deref().unwrap_or_default().chars().count();
+            *width = (*width).max(len);
+        }
+    }
+
+    let header = columns
+        .iter()
+        .zip(&widths)
+        .map(|(column, width)| format!(" {:^width$} ", column, width = width))
+        .collect::<Vec<_>>();
+    writeln!(out, "{}", header.join("|").trim_end())?;
+    let separator = widths
+        .iter()
+        .map(|width| "-".repeat(width + 2))
+        .collect::<Vec<_>>();
+    writeln!(out, "{}", separator.join("+"))?;
+
+    for row in values {
+        let fields = row
+            .iter()
+            .zip(widths.iter().zip(types))
+            .map(|(value, (width, typ))| {
+                let value = value.as_deref().unwrap_or_default();
+                if is_numeric(*typ) {
+                    format!(" {:>width$} ", value, width = width)
+                } else {
+                    format!(" {:<width$} ", value, width = width)
+                }
+            })
+            .collect::<Vec<_>>();
+        writeln!(out, "{}", fields.join("|").trim_end())?;
+    }
+    Ok(())
+}
+
+fn print_csv<W: Write>(
+    columns: &[String],
+    values: &[Vec<Option<String>>],
+    out: &mut W,
+) -> Result<()> {
+    let header = columns
+        .iter()
+        .map(|column| csv_field(column))
+        .collect::<Vec<_>>();
+    writeln!(out, "{}", header.join(","))?;
+    for row in values {
+        let fields = row
+            .iter()
+            .map(|value| value.as_deref().map(csv_field).unwrap_or_default())
+            .collect::<Vec<_>>();
+        writeln!(out, "{}", fields.join(","))?;
+    }
+    Ok(())
+}
+
+/// Return the given value as a CSV field, quoted if it contains a separator, a quote or a line
+/// break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn print_json<W: Write>(
+    columns: &[String],
+    types: &[Oid],
+    values: &[Vec<Option<String>>],
+    out: &mut W,
+) -> Result<()> {
+    if values.is_empty() {
+        writeln!(out, "[]")?;
+        return Ok(());
+    }
+
+    writeln!(out, "[")?;
+    for (idx, row) in values.iter().enumerate() {
+        let fields = row
+            .iter()
+            .zip(columns.iter().zip(types))
+            .map(|(value, (column, typ))| {
+                let value = match value.as_deref() {
+                    None => String::from("null"),
+                    // Infinite and NaN floats have no JSON representation.
+                    Some(value) if is_numeric(*typ) && value.parse::<f64>()?.is_finite() => {
+                        value.to_string()
+                    }
+                    Some(value) if *typ == pg_type::BOOL_OID => value.to_string(),
+                    Some(value) => json_string(value),
+                };
+                Ok(format!("{}:{}", json_string(column), value))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let separator = if idx + 1 < values.len() { "," } else { "" };
+        writeln!(out, "  {{{}}}{}", fields.join(","), separator)?;
+    }
+    writeln!(out, "]")?;
+    Ok(())
+}
+
+/// Return the given value as a quoted JSON string.
+fn json_string(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Run the given input on a shell of a new in-memory database, returning the output.
+    fn run(input: &str) -> Result<String> {
+        let mut shell = Shell::new(Database::open_in_memory()?);
+        let mut out = Vec::new();
+        shell.run(input.as_bytes(), &mut out, false)?;
+        Ok(String::from_utf8(out)?)
+    }
+
+    #[test]
+    fn test_output_formats() -> Result<()> {
+        let output = run(concat!(
+            "CREATE TABLE t(id int, name text, active boolean);\n",
+            "INSERT INTO t VALUES (1, 'tiny', true), (20, 'a \"quoted\", name', NULL);\n",
+            "SELECT * FROM t;\n",
+            "\\format csv\n",
+            "SELECT * FROM t;\n",
+            "\\format json\n",
+            "SELECT * FROM t;\n",
+            "SELECT * FROM t WHERE id = 5;\n",
+            "\\format xml\n",
+            "\\format\n",
+            "\\q\n",
+            "SELECT * FROM t;\n",
+        ))?;
+
+        assert_eq!(
+            output,
+            concat!(
+                "CREATE TABLE\n",
+                "INSERT 0 2\n",
+                " id |       name       | active\n",
+                "----+------------------+--------\n",
+                "  1 | tiny             | true\n",
+                " 20 | a \"quoted\", name |\n",
+                "(2 rows)\n",
+                "Output format is csv.\n",
+                "id,name,active\n",
+                "1,tiny,true\n",
+                "20,\"a \"\"quoted\"\", name\",\n",
+                "Output format is json.\n",
+                "[\n",
+                "  {\"id\":1,\"name\":\"tiny\",\"active\":true},\n",
+                "  {\"id\":20,\"name\":\"a \\\"quoted\\\", name\",\"active\":null}\n",
+                "]\n",
+                "[]\n",
+                "invalid output format \"xml\", expected table, csv or json\n",
+                "Output format is json.\n",
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_meta_commands() -> Result<()> {
+        let output = run(concat!(
+            "CREATE TABLE t(id int NOT NULL, name varchar(10) DEFAULT 'none');\n",
+            "CREATE INDEX t_id ON t(id);\n",
+            "\\dt\n",
+            "\\d t\n",
+            "\\l\n",
+            "\\timing\n",
+            "SELECT * FROM t;\n",
+            "\\timing off\n",
+            "\\timing maybe\n",
+            "\\d missing\n",
+        ))?;
+
+        let mut lines = output.lines().skip(2);
+        assert_eq!(
+            lines.by_ref().take(4).collect::<Vec<_>>(),
+            vec![
+                " Schema | Name | Type  | Owner",
+                "--------+------+-------+--------",
+                " public | t    | table | tinydb",
+                "(1 row)",
+            ]
+        );
+        assert_eq!(
+            lines.by_ref().take(4).collect::<Vec<_>>(),
+            vec![
+                " Column |         Type          | Nullable | Default",
+                "--------+-----------------------+----------+---------",
+                " id     | integer               | not null |",
+                " name   | character varying(10) |          | 'none'",
+            ]
+        );
+        assert_eq!(lines.next(), Some("(2 rows)"));
+        assert_eq!(
+            lines.by_ref().take(4).collect::<Vec<_>>(),
+            vec![
+                "  Name  | Owner",
+                "--------+--------",
+                " tinydb | tinydb",
+                "(1 row)"
+            ]
+        );
+        assert_eq!(lines.next(), Some("Timing is on."));
+        assert_eq!(lines.next(), Some(" id | name"));
+        assert_eq!(lines.next(), Some("----+------"));
+        assert_eq!(lines.next(), Some("(0 rows)"));
+        assert!(lines.next().unwrap().starts_with("Time: "));
+        assert_eq!(lines.next(), Some("Timing is off."));
+        assert_eq!(
+            lines.next(),
+            Some("unrecognized value \"maybe\" for \"\\timing\"")
+        );
+        assert_eq!(lines.next(), Some("relation missing does not exist"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_multi_line_statements() -> Result<()> {
+        let output = run(concat!(
+            "CREATE TABLE t(\n",
+            "  a int, -- first column; with a comment\n",
+            "  b text\n",
+            ");\n",
+            "\n",
+            "INSERT INTO t VALUES (1, 'a;\n",
+            "b'); INSERT INTO t VALUES (2, 'it''s'); SELECT * FROM missing; SELECT\n",
+            "  a FROM t;\n",
+            "SELECT b FROM t WHERE a = 2\n",
+        ))?;
+
+        assert_eq!(
+            output,
+            concat!(
+                "CREATE TABLE\n",
+                "INSERT 0 1\n",
+                "INSERT 0 1\n",
+                "ERROR:  relation missing does not exist\n",
+                " a\n",
+                "---\n",
+                " 1\n",
+                " 2\n",
+                "(2 rows)\n",
+                "  b\n",
+                "------\n",
+                " it's\n",
+                "(1 row)\n",
+            )
+        );
+
+        let mut buffer = StatementBuffer::default();
+        assert!(buffer.push_line("SELECT 'a").is_empty());
+        assert!(!buffer.is_empty());
+        assert_eq!(
+            buffer.push_line("'; SELECT \"x;\";"),
+            vec!["SELECT 'a\n';", "SELECT \"x;\";"]
+        );
+        assert!(buffer.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_echo() -> Result<()> {
+        let mut shell = Shell::new(Database::open_in_memory()?).with_echo(true);
+        let mut out = Vec::new();
+        let input =
+            "CREATE TABLE t(a int);\nINSERT INTO t\nVALUES (1); SELECT * FROM missing;\n\\dt\n";
+        shell.run(input.as_bytes(), &mut out, false)?;
+
+        let output = String::from_utf8(out)?;
+        let mut lines = output.lines();
+        assert_eq!(
+            lines.by_ref().take(6).collect::<Vec<_>>(),
+            vec![
+                "CREATE TABLE t(a int);",
+                "CREATE TABLE",
+                "INSERT INTO t",
+                "VALUES (1);",
+                "INSERT 0 1",
+                "SELECT * FROM missing;",
+            ]
+        );
+        assert_eq!(
+            lines.next(),
+            Some("ERROR:  relation missing does not exist")
+        );
+        assert_eq!(lines.next(), Some("\\dt"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_shell_errors() -> Result<()> {
+        let output = run("SELECT * FROM missing;\n\\foo\nSELECT 1;\n")?;
+        let mut lines = output.lines();
+        assert!(lines.next().unwrap().starts_with("ERROR:  "));
+        assert_eq!(lines.next(), Some("invalid command \\foo"));
+
+        // Errors of single lines are returned, so non-interactive callers can fail.
+        let mut shell = Shell::new(Database::open_in_memory()?);
+        let mut out = Vec::new();
+        assert!(shell.exec_line("SELECT * FROM missing;", &mut out).is_err());
+        assert!(shell.exec_line("\\format xml", &mut out).is_err());
+        shell.exec_line("CREATE TABLE t(a int); INSERT INTO t VALUES (1);", &mut out)?;
+        assert_eq!(String::from_utf8(out)?, "CREATE TABLE\nINSERT 0 1\n");
+        Ok(())
+    }
+}
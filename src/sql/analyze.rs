@@ -0,0 +1,213 @@
+//! Statistics collection of tables, executed by the ANALYZE statement.
+//!
+//! All rows of a table are counted, but only a random sample of them is used to estimate the
+//! number of distinct values of each column, so analyzing large tables don't need to keep all
+//! of their values in memory.
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use rand::Rng;
+use sqlparser::{
+    ast,
+    keywords::Keyword,
+    parser::{Parser, ParserError},
+    tokenizer::Token,
+};
+
+use crate::{
+    access::{heaptuple::TupleDesc, tableam::TableAm},
+    catalog::pg_statistic::PgStatistic,
+    relation::Relation,
+    storage::BufferPool,
+    Datum, NullableDatum,
+};
+
+/// Maximum number of rows sampled from each table, the same of Postgres with the default
+/// statistics target.
+pub const SAMPLE_ROWS: usize = 30000;
+
+/// Parse the next statement of the given parser if it is an ANALYZE statement. Return None if
+/// the next statement is not ANALYZE.
+///
+/// The SQL parser only supports the Hive syntax, which requires the TABLE keyword, so the
+/// Postgres syntax ANALYZE [TABLE] [name] is parsed here. An ANALYZE without a table name, that
+/// analyze all tables of the database, is returned with an empty table name.
+pub fn parse_analyze(parser: &mut Parser) -> Option<Result<ast::Statement, ParserError>> {
+    match parser.peek_token() {
+        Token::Word(word) if word.keyword == Keyword::ANALYZE => parser.next_token(),
+        _ => return None,
+    };
+    let _ = parser.parse_keyword(Keyword::TABLE);
+
+    let table_name = match parser.peek_token() {
+        Token::SemiColon | Token::EOF => ast::ObjectName(Vec::new()),
+        _ => match parser.parse_object_name() {
+            Ok(name) => name,
+            Err(err) => return Some(Err(err)),
+        },
+    };
+
+    Some(Ok(ast::Statement::Analyze {
+        table_name,
+        partitions: None,
+        for_columns: false,
+        columns: Vec::new(),
+        cache_metadata: false,
+        noscan: false,
+        compute_statistics: false,
+    }))
+}
+
+/// Scan all tuples of the given relation, stored using the given table access method, and
+/// return the statistics of each of its columns.
+pub fn analyze_relation(
+    buffer_pool: &BufferPool,
+    rel: &Relation,
+    table_am: &dyn TableAm,
+    tuple_desc: &TupleDesc,
+) -> Result<Vec<PgStatistic>> {
+    let mut rng = rand::thread_rng();
+    let mut sample: Vec<Vec<NullableDatum>> = Vec::new();
+    let mut rows = 0;
+
+    let mut scan = table_am.begin_scan(buffer_pool, rel)?;
+    while let Some(tuple) = scan.next_tuple()? {
+        rows += 1;
+
+        // Reservoir sampling, so every row has the same probability to be on the sample
+        // without knowing the number of rows in advance.
+        let position = if sample.len() < SAMPLE_ROWS {
+            sample.len()
+        } else {
+            match rng.gen_range(0..rows) as usize {
+                position if position < SAMPLE_ROWS => position,
+                _ => continue,
+            }
+        };
+
+        // Copy the sampled values, so the sample don't keep the pages of the tuples in memory.
+        let values = tuple
+            .deform(tuple_desc, None)?
+            .into_iter()
+            .map(|value| value.map(|datum| Datum::from(datum.to_vec())))
+            .collect();
+        if position == sample.len() {
+            sample.push(values);
+        } else {
+            sample[position] = values;
+        }
+    }
+
+    Ok(tuple_desc
+        .attrs
+        .iter()
+        .enumerate()
+        .map(|(index, attr)| PgStatistic {
+            starelid: rel.locator.oid,
+            staattnum: attr.attnum,
+            stareltuples: rows,
+            stadistinct: estimate_distinct(sample.iter().map(|row| &row[index]), rows),
+        })
+        .collect())
+}
+
+/// Estimate the number of distinct non-null values of a column of a table with the given number
+/// of rows, from the column values of the sampled rows.
+///
+/// Like Postgres, the estimate uses the Haas and Stokes estimator based on the number of values
+/// that appear only once on the sample. The count is exact when all rows were sampled.
+fn estimate_distinct<'a, I>(values: I, total_rows: u64) -> u64
+where
+    I: Iterator<Item = &'a NullableDatum>,
+{
+    let mut counts: HashMap<&[u8], u64> = HashMap::new();
+    let mut sample_rows = 0;
+    let mut nonnull = 0;
+    for value in values {
+        sample_rows += 1;
+        if let Some(datum) = value {
+            *counts.entry(datum).or_default() += 1;
+            nonnull += 1;
+        }
+    }
+
+    let distinct = counts.len() as u64;
+    if sample_rows >= total_rows || distinct == 0 {
+        return distinct;
+    }
+
+    // Estimated number of non-null values of the whole table.
+    let total_nonnull = total_rows as f64 * nonnull as f64 / sample_rows as f64;
+
+    let singletons = counts.values().filter(|count| **count == 1).count() as f64;
+    if singletons as u64 == nonnull {
+        // All sampled values are unique, so the column is assumed to be unique.
+        return total_nonnull.round() as u64;
+    }
+
+    let (n, d) = (sample_rows as f64, distinct as f64);
+    let estimate = n * d / (n - singletons + singletons * n / total_rows as f64);
+    estimate.clamp(d, total_nonnull).round() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlparser::dialect::PostgreSqlDialect;
+
+    use super::*;
+
+    fn parse(sql: &str) -> Option<Result<ast::Statement, ParserError>> {
+        let dialect = PostgreSqlDialect {};
+        let mut parser = Parser::new(
+            sqlparser::tokenizer::Tokenizer::new(&dialect, sql)
+                .tokenize()
+                .unwrap(),
+            &dialect,
+        );
+        parse_analyze(&mut parser)
+    }
+
+    fn table_name(sql: &str) -> String {
+        match parse(sql).unwrap().unwrap() {
+            ast::Statement::Analyze { table_name, .. } => table_name.to_string(),
+            statement => panic!("unexpected statement {}", statement),
+        }
+    }
+
+    #[test]
+    fn test_parse_analyze() {
+        assert!(parse("SELECT 1").is_none());
+        assert_eq!(table_name("ANALYZE t"), "t");
+        assert_eq!(table_name("analyze table t;"), "t");
+        assert_eq!(table_name("ANALYZE"), "");
+        assert_eq!(table_name("ANALYZE;"), "");
+    }
+
+    #[test]
+    fn test_estimate_distinct() {
+        let values = |values: &[Option<u8>]| {
+            values
+                .iter()
+                .map(|value| value.map(|value| Datum::from(vec![value])))
+                .collect::<Vec<_>>()
+        };
+
+        // All rows were sampled, so the count is exact.
+        let all = values(&[Some(1), Some(2), Some(2), None, Some(3)]);
+        assert_eq!(estimate_distinct(all.iter(), 5), 3);
+        assert_eq!(estimate_distinct(values(&[None, None]).iter(), 2), 0);
+
+        // Unique values on the sample are assumed to be unique on the table.
+        let unique = values(&[Some(1), Some(2), None, Some(3)]);
+        assert_eq!(estimate_distinct(unique.iter(), 400), 300);
+
+        // Values repeated on the sample are likely repeated on the table.
+        let repeated = values(&[Some(1), Some(1), Some(2), Some(2), Some(3), Some(3)]);
+        assert_eq!(estimate_distinct(repeated.iter(), 6000), 3);
+
+        let mixed = values(&[Some(1), Some(1), Some(2), Some(3)]);
+        let estimate = estimate_distinct(mixed.iter(), 1000);
+        assert!(estimate > 3 && estimate < 1000, "{}", estimate);
+    }
+}
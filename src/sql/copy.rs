@@ -0,0 +1,500 @@
+use anyhow::{bail, Result};
+use sqlparser::{
+    ast::{self, CopyLegacyCsvOption, CopyLegacyOption, CopyOption},
+    dialect::PostgreSqlDialect,
+    parser::Parser,
+    tokenizer::{Token, Tokenizer},
+};
+
+use super::{
+    error::{PgError, SqlState},
+    SQLError,
+};
+
+/// Format of the rows read or written by a COPY statement.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CopyFormat {
+    /// Each row is a line with values separated by the delimiter, special characters are
+    /// escaped with a backslash.
+    Text,
+
+    /// Comma separated values, values with special characters are quoted.
+    Csv,
+}
+
+/// Options of a COPY statement, using the same defaults of Postgres.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CopyOptions {
+    pub format: CopyFormat,
+
+    /// Character that separates the values of a row.
+    pub delimiter: char,
+
+    /// String that represents a NULL value.
+    pub null: String,
+
+    /// The first line has the column names. Only used on CSV format.
+    pub header: bool,
+
+    /// Character used to quote values on CSV format.
+    pub quote: char,
+
+    /// Character used to escape a quote inside a quoted value on CSV format.
+    pub escape: char,
+}
+
+impl CopyOptions {
+    /// Return the options of a COPY statement from the options declared on the statement.
+    pub fn new(options: &[CopyOption], legacy_options: &[CopyLegacyOption]) -> Result<Self> {
+        let mut format = CopyFormat::Text;
+        for option in options {
+            if let CopyOption::Format(name) = option {
+                format = match name.value.to_lowercase().as_str() {
+                    "text" => CopyFormat::Text,
+                    "csv" => CopyFormat::Csv,
+                    "binary" => bail!(SQLError::Unsupported(String::from("COPY BINARY"))),
+                    _ => bail!(PgError::new(
+                        SqlState::InvalidParameterValue,
+                        format!("COPY format \"{}\" not recognized", name.value)
+                    )),
+                };
+            }
+        }
+        for option in legacy_options {
+            match option {
+                CopyLegacyOption::Csv(_) => format = CopyFormat::Csv,
+                CopyLegacyOption::Binary => {
+                    bail!(SQLError::Unsupported(String::from("COPY BINARY")))
+                }
+                _ => {}
+            }
+        }
+
+        let mut copy_options = match format {
+            CopyFormat::Text => Self {
+                format,
+                delimiter: '\t',
+                null: String::from("\\N"),
+                header: false,
+                quote: '"',
+                escape: '"',
+            },
+            CopyFormat::Csv => Self {
+                format,
+                delimiter: ',',
+                null: String::new(),
+                header: false,
+                quote: '"',
+                escape: '"',
+            },
+        };
+
+        let mut escape = None;
+        for option in options {
+            match option {
+                CopyOption::Format(_) => {}
+                CopyOption::Delimiter(delimiter) => copy_options.delimiter = *delimiter,
+                CopyOption::Null(null) => copy_options.null = null.clone(),
+                CopyOption::Header(header) => copy_options.header = *header,
+                CopyOption::Quote(quote) => copy_options.quote = *quote,
+                CopyOption::Escape(value) => escape = Some(*value),
+                _ => bail!(SQLError::Unsupported(format!("COPY option {}", option))),
+            }
+        }
+        for option in legacy_options {
+            match option {
+                CopyLegacyOption::Binary => {}
+                CopyLegacyOption::Delimiter(delimiter) => copy_options.delimiter = *delimiter,
+                CopyLegacyOption::Null(null) => copy_options.null = null.clone(),
+                CopyLegacyOption::Csv(csv_options) => {
+                    for option in csv_options {
+                        match option {
+                            CopyLegacyCsvOption::Header => copy_options.header = true,
+                            CopyLegacyCsvOption::Quote(quote) => copy_options.quote = *quote,
+                            CopyLegacyCsvOption::Escape(value) => escape = Some(*value),
+                            _ => bail!(SQLError::Unsupported(format!("COPY option {}", option))),
+                        }
+                    }
+                }
+            }
+        }
+
+        // The escape character is the same as the quote character if not declared.
+        copy_options.escape = escape.unwrap_or(copy_options.quote);
+
+        if copy_options.format == CopyFormat::Text && copy_options.header {
+            bail!(PgError::new(
+                SqlState::FeatureNotSupported,
+                "COPY HEADER available only in CSV mode"
+            ));
+        }
+        if copy_options.delimiter == '\n' || copy_options.delimiter == '\r' {
+            bail!(PgError::new(
+                SqlState::InvalidParameterValue,
+                "COPY delimiter cannot be newline or carriage return"
+            ));
+        }
+
+        Ok(copy_options)
+    }
+}
+
+/// Parse a COPY statement. Return None if the given sql is not a COPY statement.
+///
+/// The SQL parser expects that the data of COPY FROM STDIN follows the statement after a
+/// semicolon, but clients send the data using the COPY sub-protocol and the statement may not
+/// end with a semicolon.
+pub fn parse_copy(sql: &str) -> Option<Result<ast::Statement>> {
+    let dialect = PostgreSqlDialect {};
+    let mut tokens = Tokenizer::new(&dialect, sql).tokenize().ok()?;
+
+    match tokens
+        .iter()
+        .find(|token| !matches!(token, Token::Whitespace(_)))
+    {
+        Some(Token::Word(word)) if word.value.eq_ignore_ascii_case("copy") => {}
+        _ => return None,
+    }
+
+    while matches!(
+        tokens.last(),
+        Some(Token::Whitespace(_)) | Some(Token::SemiColon)
+    ) {
+        tokens.pop();
+    }
+    tokens.push(Token::SemiColon);
+
+    Some(
+        Parser::new(tokens, &dialect)
+            .parse_statement()
+            .map_err(anyhow::Error::from),
+    )
+}
+
+/// A row of a COPY statement, None for NULL values.
+pub type CopyRow = Vec<Option<String>>;
+
+/// Reader of the rows sent by a client on COPY FROM STDIN. The data can be received on multiple
+/// chunks that don't need to be aligned with the rows.
+pub struct CopyReader {
+    options: CopyOptions,
+
+    /// Data received that don't have a complete row yet.
+    buffer: Vec<u8>,
+
+    /// The header line was already skipped.
+    header_skipped: bool,
+
+    /// The end of data marker was received, so the following data is ignored.
+    done: bool,
+}
+
+impl CopyReader {
+    pub fn new(options: CopyOptions) -> Self {
+        Self {
+            header_skipped: !options.header,
+            options,
+            buffer: Vec::new(),
+            done: false,
+        }
+    }
+
+    /// Return the rows that are completed by the given data.
+    pub fn read(&mut self, data: &[u8]) -> Result<Vec<CopyRow>> {
+        if self.done {
+            return Ok(Vec::new());
+        }
+        self.buffer.extend_from_slice(data);
+        let buffer = std::mem::take(&mut self.buffer);
+
+        let mut rows = Vec::new();
+        let mut start = 0;
+        let mut in_quotes = false;
+        let mut pos = 0;
+        while pos < buffer.len() {
+            let c = buffer[pos] as char;
+            if self.options.format == CopyFormat::Csv && in_quotes && c == self.options.escape {
+                // An escaped quote don't end the quoted value. If the escape is the same character
+                // as the quote, a single quote just end the quoted value.
+                if buffer.get(pos + 1) == Some(&(self.options.quote as u8)) {
+                    pos += 1;
+                } else if c == self.options.quote {
+                    in_quotes = false;
+                }
+            } else if self.options.format == CopyFormat::Csv && c == self.options.quote {
+                in_quotes = !in_quotes;
+            } else if c == '\n' && !in_quotes {
+                let line = &buffer[start..pos];
+                start = pos + 1;
+                if let Some(row) = self.parse_line(line)? {
+                    rows.push(row);
+                }
+                if self.done {
+                    return Ok(rows);
+                }
+            }
+            pos += 1;
+        }
+        self.buffer = buffer[start..].to_vec();
+
+        Ok(rows)
+    }
+
+    /// Return the last row if the data don't end with a newline.
+    pub fn finish(&mut self) -> Result<Vec<CopyRow>> {
+        if self.done || self.buffer.is_empty() {
+            return Ok(Vec::new());
+        }
+        let line = std::mem::take(&mut self.buffer);
+        Ok(self.parse_line(&line)?.into_iter().collect())
+    }
+
+    /// Parse a single line. Return None if the line is not a row.
+    fn parse_line(&mut self, line: &[u8]) -> Result<Option<CopyRow>> {
+        let line = String::from_utf8(line.to_vec())?;
+        let line = line.strip_suffix('\r').unwrap_or(&line);
+
+        if line == "\\." {
+            self.done = true;
+            return Ok(None);
+        }
+        if !self.header_skipped {
+            self.header_skipped = true;
+            return Ok(None);
+        }
+
+        match self.options.format {
+            CopyFormat::Text => Ok(Some(parse_text_line(line, &self.options))),
+            CopyFormat::Csv => Ok(Some(parse_csv_line(line, &self.options)?)),
+        }
+    }
+}
+
+/// Parse a line on text format.
+fn parse_text_line(line: &str, options: &CopyOptions) -> CopyRow {
+    line.split(options.delimiter)
+        .map(|value| {
+            // The null string is compared before removing the escapes.
+            if value == options.null {
+                return None;
+            }
+
+            let mut unescaped = String::with_capacity(value.len());
+            let mut chars = value.chars();
+            while let Some(c) = chars.next() {
+                if c != '\\' {
+                    unescaped.push(c);
+                    continue;
+                }
+                match chars.next() {
+                    Some('b') => unescaped.push('\u{8}'),
+                    Some('f') => unescaped.push('\u{c}'),
+                    Some('n') => unescaped.push('\n'),
+                    Some('r') => unescaped.push('\r'),
+                    Some('t') => unescaped.push('\t'),
+                    Some('v') => unescaped.push('\u{b}'),
+                    Some(c) => unescaped.push(c),
+                    None => {}
+                }
+            }
+            Some(unescaped)
+        })
+        .collect()
+}
+
+/// Parse a line on CSV format.
+fn parse_csv_line(line: &str, options: &CopyOptions) -> Result<CopyRow> {
+    let mut row = Vec::new();
+    let mut value = String::new();
+    let mut quoted = false;
+    let mut in_quotes = false;
+
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            if c == options.escape && chars.peek() == Some(&options.quote) {
+                value.push(options.quote);
+                chars.next();
+            } else if c == options.quote {
+                in_quotes = false;
+            } else {
+                value.push(c);
+            }
+        } else if c == options.quote {
+            in_quotes = true;
+            quoted = true;
+        } else if c == options.delimiter {
+            row.push(csv_value(value, quoted, options));
+            value = String::new();
+            quoted = false;
+        } else {
+            value.push(c);
+        }
+    }
+
+    if in_quotes {
+        bail!(PgError::new(
+            SqlState::BadCopyFileFormat,
+            "unterminated CSV quoted field"
+        ));
+    }
+    row.push(csv_value(value, quoted, options));
+
+    Ok(row)
+}
+
+/// Return a value read from CSV. Quoted values are never NULL.
+fn csv_value(value: String, quoted: bool, options: &CopyOptions) -> Option<String> {
+    if !quoted && value == options.null {
+        None
+    } else {
+        Some(value)
+    }
+}
+
+/// Return the line of the given row using the format of the given options.
+pub fn format_row(row: &[Option<String>], options: &CopyOptions) -> String {
+    let mut line = String::new();
+    for (i, value) in row.iter().enumerate() {
+        if i > 0 {
+            line.push(options.delimiter);
+        }
+        match (value, options.format) {
+            (None, _) => line.push_str(&options.null),
+            (Some(value), CopyFormat::Text) => {
+                for c in value.chars() {
+                    match c {
+                        '\\' => line.push_str("\\\\"),
+                        '\n' => line.push_str("\\n"),
+                        '\r' => line.push_str("\\r"),
+                        '\t' if options.delimiter == '\t' => line.push_str("\\t"),
+                        c if c == options.delimiter => {
+                            line.push('\\');
+                            line.push(c);
+                        }
+                        c => line.push(c),
+                    }
+                }
+            }
+            (Some(value), CopyFormat::Csv) => {
+                let needs_quotes = *value == options.null
+                    || value.contains(|c| {
+                        c == options.delimiter || c == options.quote || c == '\n' || c == '\r'
+                    });
+                if !needs_quotes {
+                    line.push_str(value);
+                    continue;
+                }
+
+                line.push(options.quote);
+                for c in value.chars() {
+                    if c == options.quote || c == options.escape {
+                        line.push(options.escape);
+                    }
+                    line.push(c);
+                }
+                line.push(options.quote);
+            }
+        }
+    }
+    line.push('\n');
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(sql: &str) -> Result<CopyOptions> {
+        match parse_copy(sql).unwrap()? {
+            ast::Statement::Copy {
+                options,
+                legacy_options,
+                ..
+            } => CopyOptions::new(&options, &legacy_options),
+            statement => bail!("unexpected statement {}", statement),
+        }
+    }
+
+    fn row(values: &[Option<&str>]) -> CopyRow {
+        values.iter().map(|value| value.map(String::from)).collect()
+    }
+
+    #[test]
+    fn test_parse_copy() -> Result<()> {
+        assert!(parse_copy("SELECT 1").is_none());
+        assert!(parse_copy("COPY t FROM STDIN").unwrap().is_ok());
+        assert!(parse_copy("copy t (a, b) to stdout;").unwrap().is_ok());
+
+        assert_eq!(options("COPY t FROM STDIN")?.format, CopyFormat::Text);
+        let csv = options("COPY t FROM STDIN WITH (FORMAT csv, HEADER, DELIMITER ';')")?;
+        assert_eq!(csv.format, CopyFormat::Csv);
+        assert_eq!(csv.delimiter, ';');
+        assert!(csv.header);
+        assert_eq!(options("COPY t TO STDOUT CSV")?.delimiter, ',');
+
+        assert!(options("COPY t FROM STDIN WITH (HEADER)").is_err());
+        assert!(options("COPY t FROM STDIN WITH (FORMAT binary)").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_text_rows() -> Result<()> {
+        let mut reader = CopyReader::new(options("COPY t FROM STDIN")?);
+
+        // Rows can be split between multiple chunks.
+        assert_eq!(reader.read(b"1\tfoo\n2\t\\N\n3\ta\\")?.len(), 2);
+        assert_eq!(
+            reader.read(b"tb\\\\c\n")?,
+            vec![row(&[Some("3"), Some("a\tb\\c")])]
+        );
+        assert!(reader.read(b"4\tlast")?.is_empty());
+        assert_eq!(reader.finish()?, vec![row(&[Some("4"), Some("last")])]);
+
+        let mut reader = CopyReader::new(options("COPY t FROM STDIN")?);
+        assert_eq!(
+            reader.read(b"1\t\\N\n\\.\n2\tignored\n")?,
+            vec![row(&[Some("1"), None])]
+        );
+        assert!(reader.finish()?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_csv_rows() -> Result<()> {
+        let mut reader = CopyReader::new(options("COPY t FROM STDIN WITH (FORMAT csv, HEADER)")?);
+        assert_eq!(
+            reader.read(b"a,b\r\n1,\"x, \"\"y\"\"\"\n2,\n3,\"\"\n4,\"multi\nline\"\n")?,
+            vec![
+                row(&[Some("1"), Some("x, \"y\"")]),
+                row(&[Some("2"), None]),
+                row(&[Some("3"), Some("")]),
+                row(&[Some("4"), Some("multi\nline")]),
+            ]
+        );
+
+        assert!(reader.read(b"5,\"unterminated\n").is_ok());
+        assert!(reader.finish().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_row() -> Result<()> {
+        let text = options("COPY t TO STDOUT")?;
+        assert_eq!(
+            format_row(&row(&[Some("1"), None, Some("a\tb\\c")]), &text),
+            "1\t\\N\ta\\tb\\\\c\n"
+        );
+
+        let csv = options("COPY t TO STDOUT WITH (FORMAT csv)")?;
+        assert_eq!(
+            format_row(&row(&[Some("1"), None, Some(""), Some("x, \"y\"")]), &csv),
+            "1,,\"\",\"x, \"\"y\"\"\"\n"
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,293 @@
+//! Cursors declared by statements that the SQL parser don't support: DECLARE, FETCH and CLOSE.
+//!
+//! A cursor keeps the plan of its query suspended between FETCH statements, so the rows are
+//! computed on demand and the relations are only scanned up to the last fetched row. The plan
+//! keeps the locks of the scanned relations until the cursor is closed. There are no transaction
+//! blocks, so, unlike Postgres, cursors can be declared outside of them and are kept open until
+//! they are closed or the connection ends, like cursors declared WITH HOLD.
+
+use std::{collections::VecDeque, sync::Arc};
+
+use anyhow::{anyhow, Result};
+use sqlparser::{
+    ast,
+    dialect::PostgreSqlDialect,
+    keywords::Keyword,
+    parser::{Parser, ParserError},
+    tokenizer::{Token, Tokenizer},
+};
+
+use crate::{
+    access::heaptuple::TupleDesc,
+    executor::{Executor, TupleTable},
+    planner::Plan,
+    sql::error::{PgError, SqlState},
+    NullableDatum,
+};
+
+/// A statement of cursors parsed by parse_cursor_statement.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CursorStatement {
+    /// DECLARE name CURSOR FOR query.
+    Declare {
+        name: String,
+        query: Box<ast::Query>,
+    },
+
+    /// FETCH count FROM name, where a count of None fetches all remaining rows and a count of 0
+    /// fetches no rows without moving the cursor.
+    Fetch { name: String, count: Option<u64> },
+
+    /// CLOSE name, or CLOSE ALL if the name is None.
+    Close { name: Option<String> },
+}
+
+impl CursorStatement {
+    /// Return the command tag sent to the client when the statement completes. The tag of
+    /// FETCH is followed by the number of fetched rows.
+    pub fn command_tag(&self) -> &'static str {
+        match self {
+            Self::Declare { .. } => "DECLARE CURSOR",
+            Self::Fetch { .. } => "FETCH",
+            Self::Close { name: Some(_) } => "CLOSE CURSOR",
+            Self::Close { name: None } => "CLOSE CURSOR ALL",
+        }
+    }
+}
+
+/// Parse a statement of cursors, which is not supported by the SQL parser. Return None if the
+/// given sql is not a statement of cursors.
+///
+/// Statements are parsed on the Postgres syntax, but only cursors that scan forward are
+/// supported: DECLARE name [NO SCROLL] CURSOR [{WITH | WITHOUT} HOLD] FOR query,
+/// FETCH [NEXT | FORWARD [count | ALL] | count | ALL] [FROM | IN] name and
+/// CLOSE {name | ALL}.
+pub fn parse_cursor_statement(sql: &str) -> Option<Result<CursorStatement>> {
+    let dialect = PostgreSqlDialect {};
+    let tokens = Tokenizer::new(&dialect, sql).tokenize().ok()?;
+    let mut parser = Parser::new(tokens, &dialect);
+
+    let statement = match parser.next_token() {
+        Token::Word(word) if word.keyword == Keyword::DECLARE => parse_declare(&mut parser),
+        Token::Word(word) if word.keyword == Keyword::FETCH => parse_fetch(&mut parser),
+        Token::Word(word) if word.keyword == Keyword::CLOSE => parse_close(&mut parser),
+        _ => return None,
+    };
+    Some(statement.and_then(|statement| {
+        while parser.consume_token(&Token::SemiColon) {}
+        match parser.peek_token() {
+            Token::EOF => Ok(statement),
+            token => Err(anyhow!(ParserError::ParserError(format!(
+                "Expected end of statement, found: {}",
+                token
+            )))),
+        }
+    }))
+}
+
+fn parse_declare(parser: &mut Parser) -> Result<CursorStatement> {
+    let name = parser.parse_identifier()?.value;
+    if parser.parse_keyword(Keyword::SCROLL) {
+        return Err(anyhow!(PgError::new(
+            SqlState::FeatureNotSupported,
+            "SCROLL cursors are not supported"
+        )));
+    }
+    let _ = parser.parse_keywords(&[Keyword::NO, Keyword::SCROLL]);
+    parser.expect_keyword(Keyword::CURSOR)?;
+    if !parser.parse_keywords(&[Keyword::WITH, Keyword::HOLD]) {
+        let _ = parser.parse_keywords(&[Keyword::WITHOUT, Keyword::HOLD]);
+    }
+    parser.expect_keyword(Keyword::FOR)?;
+
+    Ok(CursorStatement::Declare {
+        name,
+        query: Box::new(parser.parse_query()?),
+    })
+}
+
+fn parse_fetch(parser: &mut Parser) -> Result<CursorStatement> {
+    let count = match parser.next_token() {
+        Token::Word(word) if word.keyword == Keyword::NEXT => Some(1),
+        Token::Word(word) if word.keyword == Keyword::ALL => None,
+        Token::Word(word) if word.value.eq_ignore_ascii_case("forward") => {
+            match parser.peek_token() {
+                Token::Word(word) if word.keyword == Keyword::ALL => {
+                    parser.next_token();
+                    None
+                }
+                Token::Number(..) => Some(parser.parse_literal_uint()?),
+                _ => Some(1),
+            }
+        }
+        Token::Number(..) => {
+            parser.prev_token();
+            Some(parser.parse_literal_uint()?)
+        }
+        Token::Word(word)
+            if ["prior", "backward", "absolute", "relative"]
+                .contains(&word.value.to_lowercase().as_str())
+                || matches!(word.keyword, Keyword::FIRST | Keyword::LAST) =>
+        {
+            return Err(backward_scan());
+        }
+        Token::Minus => return Err(backward_scan()),
+        _ => {
+            // Without a direction, the next row is fetched.
+            parser.prev_token();
+            Some(1)
+        }
+    };
+    let _ = parser.parse_one_of_keywords(&[Keyword::FROM, Keyword::IN]);
+
+    Ok(CursorStatement::Fetch {
+        name: parser.parse_identifier()?.value,
+        count,
+    })
+}
+
+fn backward_scan() -> anyhow::Error {
+    anyhow!(PgError::new(
+        SqlState::ObjectNotInPrerequisiteState,
+        "cursor can only scan forward"
+    )
+    .with_hint("Declare it with SCROLL option to enable backward scan."))
+}
+
+fn parse_close(parser: &mut Parser) -> Result<CursorStatement> {
+    if parser.parse_keyword(Keyword::ALL) {
+        return Ok(CursorStatement::Close { name: None });
+    }
+    Ok(CursorStatement::Close {
+        name: Some(parser.parse_identifier()?.value),
+    })
+}
+
+/// An open cursor, returning the rows of its query on demand.
+pub struct Cursor {
+    /// Plan of the query, executed up to the last fetched row.
+    plan: Plan,
+
+    executor: Executor,
+
+    /// Description of the rows returned by the query.
+    tuple_desc: Arc<TupleDesc>,
+
+    /// Rows returned by the plan that were not fetched yet.
+    pending: VecDeque<Vec<NullableDatum>>,
+
+    /// Set when the plan has returned all of its rows.
+    done: bool,
+}
+
+impl Cursor {
+    /// Create a new cursor over the rows of the given plan of a query, executed by the given
+    /// executor.
+    pub fn new(plan: Plan, executor: Executor) -> Result<Self> {
+        let tuple_desc = match plan.tuple_desc() {
+            Some(tuple_desc) => Arc::new(tuple_desc),
+            None => {
+                return Err(anyhow!(PgError::new(
+                    SqlState::InvalidCursorDefinition,
+                    "cursor query does not return rows"
+                )))
+            }
+        };
+        Ok(Self {
+            plan,
+            executor,
+            tuple_desc,
+            pending: VecDeque::new(),
+            done: false,
+        })
+    }
+
+    /// Return the next rows of the cursor, at most the given count or all remaining rows if
+    /// the count is None. Fewer rows are returned when the end of the query is reached.
+    pub fn fetch(&mut self, count: Option<u64>) -> Result<TupleTable> {
+        let count = count.map_or(usize::MAX, |count| count as usize);
+
+        let mut values = Vec::new();
+        while values.len() < count {
+            if let Some(row) = self.pending.pop_front() {
+                values.push(row);
+                continue;
+            }
+            if self.done {
+                break;
+            }
+            match self.executor.exec_next(&mut self.plan)? {
+                Some(rows) => self.pending.extend(rows),
+                None => self.done = true,
+            }
+        }
+
+        Ok(TupleTable {
+            tuple_desc: self.tuple_desc.clone(),
+            values,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cursor_statement() {
+        let parse = |sql| parse_cursor_statement(sql).map(|statement| statement.unwrap());
+        assert_eq!(parse("SELECT 1"), None);
+
+        match parse("DECLARE c NO SCROLL CURSOR WITHOUT HOLD FOR SELECT a FROM t;") {
+            Some(CursorStatement::Declare { name, query }) => {
+                assert_eq!(name, "c");
+                assert_eq!(query.to_string(), "SELECT a FROM t");
+            }
+            statement => panic!("unexpected statement {:?}", statement),
+        }
+
+        let fetch = |count| {
+            Some(CursorStatement::Fetch {
+                name: String::from("c"),
+                count,
+            })
+        };
+        assert_eq!(parse("FETCH c"), fetch(Some(1)));
+        assert_eq!(parse("fetch next from c"), fetch(Some(1)));
+        assert_eq!(parse("FETCH 10 IN c;"), fetch(Some(10)));
+        assert_eq!(parse("FETCH FORWARD 5 FROM c"), fetch(Some(5)));
+        assert_eq!(parse("FETCH FORWARD ALL FROM c"), fetch(None));
+        assert_eq!(parse("FETCH ALL c"), fetch(None));
+        assert_eq!(parse("FETCH 0 FROM c"), fetch(Some(0)));
+        assert_eq!(parse("FETCH FORWARD 0 c"), fetch(Some(0)));
+
+        assert_eq!(
+            parse("CLOSE c"),
+            Some(CursorStatement::Close {
+                name: Some(String::from("c"))
+            })
+        );
+        assert_eq!(
+            parse("CLOSE ALL"),
+            Some(CursorStatement::Close { name: None })
+        );
+
+        let err = |sql| PgError::from_error(&parse_cursor_statement(sql).unwrap().unwrap_err());
+        assert_eq!(
+            err("FETCH PRIOR FROM c").code,
+            SqlState::ObjectNotInPrerequisiteState
+        );
+        assert_eq!(
+            err("FETCH -1 FROM c").code,
+            SqlState::ObjectNotInPrerequisiteState
+        );
+        assert_eq!(
+            err("DECLARE c SCROLL CURSOR FOR SELECT 1").code,
+            SqlState::FeatureNotSupported
+        );
+        assert!(parse_cursor_statement("DECLARE c CURSOR SELECT 1")
+            .unwrap()
+            .is_err());
+        assert!(parse_cursor_statement("CLOSE c d").unwrap().is_err());
+    }
+}
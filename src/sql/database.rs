@@ -0,0 +1,139 @@
+//! Creation of databases, executed by the CREATE DATABASE statement.
+//!
+//! A new database is created by copying all relation files of a template database, so the new
+//! database starts with the same tables and rows of the template.
+
+use anyhow::{anyhow, bail, Result};
+use sqlparser::{
+    dialect::PostgreSqlDialect,
+    keywords::Keyword,
+    parser::{Parser, ParserError},
+    tokenizer::{Token, Tokenizer},
+};
+
+use crate::{
+    catalog,
+    storage::{relation_locator::database_path, BufferPool},
+    Oid,
+};
+
+use super::error::{PgError, SqlState};
+
+/// Name of the database used as template when CREATE DATABASE don't have a TEMPLATE option.
+pub const DEFAULT_TEMPLATE: &str = "tinydb";
+
+/// A CREATE DATABASE statement parsed by parse_create_database.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreateDatabaseStatement {
+    pub name: String,
+
+    /// Set by IF NOT EXISTS, nothing is done if the database already exists.
+    pub if_not_exists: bool,
+
+    /// Name of the template database, None to copy DEFAULT_TEMPLATE.
+    pub template: Option<String>,
+}
+
+/// Parse a CREATE DATABASE statement, which the SQL parser don't support with the TEMPLATE
+/// option. Return None if the given sql is not a CREATE DATABASE statement.
+///
+/// Statements are parsed on the Postgres syntax
+/// CREATE DATABASE [IF NOT EXISTS] name [WITH] [TEMPLATE [=] template].
+pub fn parse_create_database(sql: &str) -> Option<Result<CreateDatabaseStatement>> {
+    let dialect = PostgreSqlDialect {};
+    let tokens = Tokenizer::new(&dialect, sql).tokenize().ok()?;
+    let mut parser = Parser::new(tokens, &dialect);
+    if !parser.parse_keywords(&[Keyword::CREATE, Keyword::DATABASE]) {
+        return None;
+    }
+
+    Some(
+        parse_create_database_options(&mut parser).and_then(|statement| {
+            while parser.consume_token(&Token::SemiColon) {}
+            match parser.peek_token() {
+                Token::EOF => Ok(statement),
+                token => Err(anyhow!(ParserError::ParserError(format!(
+                    "Expected end of statement, found: {}",
+                    token
+                )))),
+            }
+        }),
+    )
+}
+
+fn parse_create_database_options(parser: &mut Parser) -> Result<CreateDatabaseStatement> {
+    let if_not_exists = parser.parse_keywords(&[Keyword::IF, Keyword::NOT, Keyword::EXISTS]);
+    let name = parser.parse_identifier()?.value;
+    let _ = parser.parse_keyword(Keyword::WITH);
+
+    let template = match parser.peek_token() {
+        Token::Word(word) if word.value.eq_ignore_ascii_case("template") => {
+            parser.next_token();
+            let _ = parser.consume_token(&Token::Eq);
+            Some(parser.parse_identifier()?.value)
+        }
+        _ => None,
+    };
+
+    Ok(CreateDatabaseStatement {
+        name,
+        if_not_exists,
+        template,
+    })
+}
+
+/// Return the oid of the database with the given name, used by a new connection to the
+/// database. An error is returned if the database does not exist on pg_database or if its
+/// directory is missing.
+pub fn connect_database(buffer_pool: &BufferPool, datname: &str) -> Result<Oid> {
+    let db_oid = catalog::get_datase_oid(buffer_pool, datname)?;
+    if !buffer_pool.database_exists(&db_oid) {
+        bail!(PgError::new(
+            SqlState::InvalidCatalogName,
+            format!("database \"{}\" does not exist", datname)
+        )
+        .with_detail(format!(
+            "The database subdirectory \"{}\" is missing.",
+            database_path(&db_oid).display()
+        )));
+    }
+    Ok(db_oid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_create_database() {
+        let parse = |sql| parse_create_database(sql).map(|statement| statement.unwrap());
+        assert_eq!(parse("SELECT 1"), None);
+        assert_eq!(parse("CREATE TABLE t(a int)"), None);
+
+        let create_database = |name: &str, if_not_exists, template: Option<&str>| {
+            Some(CreateDatabaseStatement {
+                name: String::from(name),
+                if_not_exists,
+                template: template.map(String::from),
+            })
+        };
+        assert_eq!(
+            parse("CREATE DATABASE db"),
+            create_database("db", false, None)
+        );
+        assert_eq!(
+            parse("create database if not exists db template other;"),
+            create_database("db", true, Some("other"))
+        );
+        assert_eq!(
+            parse("CREATE DATABASE db WITH TEMPLATE = other"),
+            create_database("db", false, Some("other"))
+        );
+        assert!(parse_create_database("CREATE DATABASE db TEMPLATE")
+            .unwrap()
+            .is_err());
+        assert!(parse_create_database("CREATE DATABASE db OWNER r")
+            .unwrap()
+            .is_err());
+    }
+}
@@ -0,0 +1,286 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::sql::error::{PgError, SqlState};
+
+/// Number of days between the unix epoch (1970-01-01) and the Postgres epoch (2000-01-01).
+const POSTGRES_EPOCH_DAYS: i64 = 10957;
+
+const USECS_PER_SEC: i64 = 1_000_000;
+const USECS_PER_DAY: i64 = 86_400 * USECS_PER_SEC;
+
+/// Parse a date literal on the format YYYY-MM-DD and return the number of days since
+/// 2000-01-01.
+pub fn parse_date(value: &str) -> Result<i32> {
+    let invalid = || {
+        anyhow!(PgError::new(
+            SqlState::InvalidDatetimeFormat,
+            format!("invalid input syntax for type date: \"{}\"", value)
+        ))
+    };
+
+    let (year, month, day) = parse_ymd(value.trim()).ok_or_else(invalid)?;
+    let days = days_from_civil(year, month, day).ok_or_else(|| {
+        anyhow!(PgError::new(
+            SqlState::DatetimeFieldOverflow,
+            format!("date/time field value out of range: \"{}\"", value)
+        ))
+    })?;
+
+    Ok(days as i32)
+}
+
+/// Parse a timestamp literal on the format YYYY-MM-DD HH:MM:SS[.ffffff] and return the number of
+/// microseconds since 2000-01-01 00:00:00. The time part is optional.
+pub fn parse_timestamp(value: &str) -> Result<i64> {
+    let invalid = || {
+        anyhow!(PgError::new(
+            SqlState::InvalidDatetimeFormat,
+            format!("invalid input syntax for type timestamp: \"{}\"", value)
+        ))
+    };
+    let out_of_range = || {
+        anyhow!(PgError::new(
+            SqlState::DatetimeFieldOverflow,
+            format!("date/time field value out of range: \"{}\"", value)
+        ))
+    };
+
+    let value_trimmed = value.trim();
+    let (date, time) = match value_trimmed.find([' ', 'T']) {
+        Some(pos) => (&value_trimmed[..pos], Some(value_trimmed[pos + 1..].trim())),
+        None => (value_trimmed, None),
+    };
+
+    let (year, month, day) = parse_ymd(date).ok_or_else(invalid)?;
+    let days = days_from_civil(year, month, day).ok_or_else(out_of_range)?;
+
+    let usecs = match time {
+        Some(time) => {
+            let (hour, minute, usec) = parse_time(time).ok_or_else(invalid)?;
+            if hour > 23 || minute > 59 || usec >= 60 * USECS_PER_SEC {
+                bail!(out_of_range());
+            }
+            (hour * 60 + minute) * 60 * USECS_PER_SEC + usec
+        }
+        None => 0,
+    };
+
+    Ok(days * USECS_PER_DAY + usecs)
+}
+
+/// Return the text representation of the given number of days since 2000-01-01.
+pub fn format_date(days: i32) -> String {
+    let (year, month, day) = civil_from_days(days as i64);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Return the text representation of the given number of microseconds since 2000-01-01.
+///
+/// Fractional seconds are only included if not zero, using the minimum number of digits.
+pub fn format_timestamp(usecs: i64) -> String {
+    let days = usecs.div_euclid(USECS_PER_DAY);
+    let time = usecs.rem_euclid(USECS_PER_DAY);
+
+    let secs = time / USECS_PER_SEC;
+    let fraction = time % USECS_PER_SEC;
+
+    let mut value = format!(
+        "{} {:02}:{:02}:{:02}",
+        format_date(days as i32),
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    );
+    if fraction != 0 {
+        let fraction = format!(".{:06}", fraction);
+        value.push_str(fraction.trim_end_matches('0'));
+    }
+    value
+}
+
+/// Return the number of microseconds since 2000-01-01 00:00:00 UTC of the given system time.
+pub fn timestamp_from_system_time(time: SystemTime) -> i64 {
+    let unix_usecs = match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_micros() as i64,
+        Err(err) => -(err.duration().as_micros() as i64),
+    };
+    unix_usecs - POSTGRES_EPOCH_DAYS * USECS_PER_DAY
+}
+
+/// Convert a date to a timestamp at midnight of the same day.
+pub fn date_to_timestamp(days: i32) -> i64 {
+    days as i64 * USECS_PER_DAY
+}
+
+/// Convert a timestamp to the date of the same day, discarding the time.
+pub fn timestamp_to_date(usecs: i64) -> i32 {
+    usecs.div_euclid(USECS_PER_DAY) as i32
+}
+
+/// Parse the YYYY-MM-DD date fields.
+fn parse_ymd(value: &str) -> Option<(i64, u32, u32)> {
+    let mut fields = value.split('-');
+    let year = parse_field(fields.next()?, 4)?;
+    let month = parse_field(fields.next()?, 2)?;
+    let day = parse_field(fields.next()?, 2)?;
+    if fields.next().is_some() {
+        return None;
+    }
+    Some((year, month as u32, day as u32))
+}
+
+/// Parse the HH:MM:SS[.ffffff] time fields. Return the hours, minutes and the seconds in
+/// microseconds.
+fn parse_time(value: &str) -> Option<(i64, i64, i64)> {
+    let mut fields = value.split(':');
+    let hour = parse_field(fields.next()?, 2)?;
+    let minute = parse_field(fields.next()?, 2)?;
+    let second = fields.next().unwrap_or("0");
+    if fields.next().is_some() {
+        return None;
+    }
+
+    let (second, fraction) = match second.find('.') {
+        Some(pos) => (&second[..pos], &second[pos + 1..]),
+        None => (second, ""),
+    };
+    if fraction.len() > 6 || !fraction.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let usec = parse_field(second, 2)? * USECS_PER_SEC
+        + format!("{:0<6}", fraction).parse::<i64>().ok()?;
+
+    Some((hour, minute, usec))
+}
+
+/// Parse a numeric date/time field with at most the given number of digits.
+fn parse_field(value: &str, max_digits: usize) -> Option<i64> {
+    if value.is_empty() || value.len() > max_digits || !value.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    value.parse().ok()
+}
+
+/// Return the number of days since 2000-01-01 of the given date. None if the date is not valid.
+fn days_from_civil(year: i64, month: u32, day: u32) -> Option<i64> {
+    if year < 1 || !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+        return None;
+    }
+
+    // Algorithm from http://howardhinnant.github.io/date_algorithms.html
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let mp = (month as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+
+    Some(era * 146097 + doe - 719468 - POSTGRES_EPOCH_DAYS)
+}
+
+/// Return the year, month and day of the given number of days since 2000-01-01.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + POSTGRES_EPOCH_DAYS + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_date() -> Result<()> {
+        assert_eq!(parse_date("2000-01-01")?, 0);
+        assert_eq!(parse_date("2000-01-02")?, 1);
+        assert_eq!(parse_date("1999-12-31")?, -1);
+        assert_eq!(parse_date("1970-01-01")?, -10957);
+        assert_eq!(parse_date("2024-02-29")?, 8825);
+
+        assert!(parse_date("2023-02-29").is_err());
+        assert!(parse_date("2024-13-01").is_err());
+        assert!(parse_date("2024-01").is_err());
+        assert!(parse_date("abc").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_date_round_trip() -> Result<()> {
+        for value in &[
+            "0001-01-01",
+            "1900-02-28",
+            "2000-02-29",
+            "2024-12-31",
+            "9999-12-31",
+        ] {
+            assert_eq!(format_date(parse_date(value)?), *value);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_timestamp() -> Result<()> {
+        assert_eq!(parse_timestamp("2000-01-01 00:00:00")?, 0);
+        assert_eq!(parse_timestamp("2000-01-01")?, 0);
+        assert_eq!(parse_timestamp("2000-01-01 00:00:01.5")?, 1_500_000);
+        assert_eq!(parse_timestamp("1999-12-31 23:59:59")?, -USECS_PER_SEC);
+        assert_eq!(
+            parse_timestamp("2024-01-01T10:00:00")?,
+            parse_timestamp("2024-01-01 10:00")?
+        );
+
+        assert!(parse_timestamp("2024-01-01 24:00:00").is_err());
+        assert!(parse_timestamp("2024-01-01 10:00:00.1234567").is_err());
+        assert!(parse_timestamp("2024-01-01 10").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_timestamp_round_trip() -> Result<()> {
+        for value in &[
+            "2000-01-01 00:00:00",
+            "1999-12-31 23:59:59",
+            "2024-01-01 10:00:00.5",
+            "2024-06-15 08:30:00.000001",
+        ] {
+            assert_eq!(format_timestamp(parse_timestamp(value)?), *value);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_timestamp_from_system_time() -> Result<()> {
+        let time = UNIX_EPOCH + std::time::Duration::from_micros(1_666_000_000_000_001);
+        assert_eq!(
+            format_timestamp(timestamp_from_system_time(time)),
+            "2022-10-17 09:46:40.000001"
+        );
+        assert_eq!(
+            timestamp_from_system_time(UNIX_EPOCH),
+            parse_timestamp("1970-01-01")?
+        );
+        Ok(())
+    }
+}
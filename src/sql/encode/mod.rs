@@ -1,37 +1,106 @@
-use std::{convert::TryFrom, mem::size_of};
+pub mod datetime;
 
-use anyhow::{bail, Result};
-use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::convert::{TryFrom, TryInto};
+
+use anyhow::{anyhow, bail, Result};
 use sqlparser::ast;
 
 use crate::{
-    catalog::{pg_attribute::PgAttribute, pg_type},
+    catalog::{
+        pg_attribute::PgAttribute,
+        pg_type::{self, type_name, CastContext},
+    },
+    expr::{const_value, ScalarValue},
+    sql::error::{PgError, SqlState},
     Datum, NullableDatum, Oid,
 };
 
 /// Encode the ast value to a Datum representation.
+///
+/// Values are converted to the attribute type using the casts allowed on assignment. String
+/// literals have an unknown type, so they are parsed using the text representation of the
+/// attribute type, like numeric literals stored on numeric and string attributes.
 pub fn encode(
     encode_to: &mut Vec<NullableDatum>,
     value: &ast::Value,
     attr: &PgAttribute,
 ) -> Result<()> {
-    match value {
-        ast::Value::Number(value, _) => {
-            let value = value.parse::<i32>()?;
-            encode_to.push(Some(Datum::try_from(value)?))
+    let typ = attr.atttypid;
+    let value = match value {
+        ast::Value::SingleQuotedString(value) => ScalarValue::Varchar(value.clone()).cast(typ)?,
+        ast::Value::Number(value, _)
+            if matches!(
+                typ,
+                pg_type::INT2_OID
+                    | pg_type::INT_OID
+                    | pg_type::INT8_OID
+                    | pg_type::FLOAT4_OID
+                    | pg_type::FLOAT8_OID
+                    | pg_type::TEXT_OID
+                    | pg_type::VARCHAR_OID
+            ) =>
+        {
+            ScalarValue::Varchar(value.clone()).cast(typ)?
         }
-        ast::Value::SingleQuotedString(value) => {
-            if attr.attlen >= 0 && (value.len() > attr.attlen as usize) {
-                bail!("value too long for type character varying({})", attr.attlen);
-            }
-            encode_to.push(Some(Datum::try_from(value)?))
+        value => assignment_cast(const_value(value)?, attr)?,
+    };
+    encode_checked(encode_to, value, attr)
+}
+
+/// Encode the value of an expression to the Datum representation of the given attribute, like
+/// encode but without the special handling of literals.
+pub fn encode_scalar(
+    encode_to: &mut Vec<NullableDatum>,
+    value: ScalarValue,
+    attr: &PgAttribute,
+) -> Result<()> {
+    let value = assignment_cast(value, attr)?;
+    encode_checked(encode_to, value, attr)
+}
+
+/// Cast the given value to the type of the attribute, if the cast is allowed on assignment.
+fn assignment_cast(value: ScalarValue, attr: &PgAttribute) -> Result<ScalarValue> {
+    let typ = attr.atttypid;
+    match value.typ() {
+        Some(source) if !pg_type::can_cast(source, typ, CastContext::Assignment) => {
+            bail!(PgError::new(
+                SqlState::DatatypeMismatch,
+                format!(
+                    "column \"{}\" is of type {} but expression is of type {}",
+                    attr.attname,
+                    type_name(typ),
+                    type_name(source)
+                )
+            )
+            .with_hint("You will need to rewrite or cast the expression."))
         }
-        ast::Value::Null => {
-            encode_to.push(None);
+        _ => value.cast(typ),
+    }
+}
+
+/// Encode the given value, already of the attribute type, checking the attribute length.
+fn encode_checked(
+    encode_to: &mut Vec<NullableDatum>,
+    value: ScalarValue,
+    attr: &PgAttribute,
+) -> Result<()> {
+    let typ = attr.atttypid;
+
+    // Only varchar have a maximum length, text values are unlimited.
+    match &value {
+        ScalarValue::Varchar(value)
+            if typ == pg_type::VARCHAR_OID
+                && attr.attlen >= 0
+                && value.len() > attr.attlen as usize =>
+        {
+            bail!(PgError::new(
+                SqlState::StringDataRightTruncation,
+                format!("value too long for type character varying({})", attr.attlen)
+            ));
         }
-        ast::Value::Boolean(value) => encode_to.push(Some(Datum::try_from(value)?)),
-        _ => bail!("Unsupported value {}", value.to_string()),
-    };
+        _ => {}
+    }
+    encode_to.push(value.to_datum()?);
     Ok(())
 }
 
@@ -41,69 +110,146 @@ pub fn encode(
 // that represents a SQL value.
 pub fn decode(datum: &Datum, typ: Oid) -> Result<String> {
     match typ {
-        pg_type::INT_OID => Ok(bincode::deserialize::<i32>(&datum)?.to_string()),
-        pg_type::VARCHAR_OID => Ok(bincode::deserialize::<String>(&datum)?),
-        pg_type::BOOL_OID => Ok(bincode::deserialize::<bool>(&datum)?.to_string()),
+        pg_type::INT2_OID => Ok(bincode::deserialize::<i16>(datum)?.to_string()),
+        pg_type::INT_OID => Ok(bincode::deserialize::<i32>(datum)?.to_string()),
+        pg_type::INT8_OID => Ok(bincode::deserialize::<i64>(datum)?.to_string()),
+        pg_type::FLOAT4_OID => Ok(float_to_string(bincode::deserialize::<f32>(datum)? as f64)),
+        pg_type::FLOAT8_OID => Ok(float_to_string(bincode::deserialize::<f64>(datum)?)),
+        pg_type::TEXT_OID | pg_type::VARCHAR_OID => Ok(String::from_utf8(datum.to_vec())?),
+        pg_type::BOOL_OID => Ok(bincode::deserialize::<bool>(datum)?.to_string()),
+        pg_type::DATE_OID => Ok(datetime::format_date(bincode::deserialize(datum)?)),
+        pg_type::TIMESTAMP_OID => Ok(datetime::format_timestamp(bincode::deserialize(datum)?)),
         _ => bail!("decode: Unsupported type to decode"),
     }
 }
 
-/// Variable-length datatypes all share the 'struct varlena' header.
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Varlena {
-    /// Total length of the value in bytes
-    ///
-    /// v_len originally does no include itself, call len()
-    /// to get the total length of varlena value (v_len + v_data).
-    pub v_len: u32,
+/// Decode a raw tuple to the binary representation of a SQL value, which is the same
+/// representation used by Postgres to send values on binary format.
+pub fn decode_binary(datum: &Datum, typ: Oid) -> Result<Vec<u8>> {
+    match typ {
+        pg_type::INT2_OID => Ok(bincode::deserialize::<i16>(datum)?.to_be_bytes().to_vec()),
+        pg_type::INT_OID => Ok(bincode::deserialize::<i32>(datum)?.to_be_bytes().to_vec()),
+        pg_type::INT8_OID => Ok(bincode::deserialize::<i64>(datum)?.to_be_bytes().to_vec()),
+        pg_type::FLOAT4_OID => Ok(bincode::deserialize::<f32>(datum)?.to_be_bytes().to_vec()),
+        pg_type::FLOAT8_OID => Ok(bincode::deserialize::<f64>(datum)?.to_be_bytes().to_vec()),
+        pg_type::TEXT_OID | pg_type::VARCHAR_OID => Ok(datum.to_vec()),
+        pg_type::BOOL_OID => Ok(vec![bincode::deserialize::<bool>(datum)? as u8]),
+        // Dates and timestamps are stored with the same epoch used by Postgres.
+        pg_type::DATE_OID => Ok(bincode::deserialize::<i32>(datum)?.to_be_bytes().to_vec()),
+        pg_type::TIMESTAMP_OID => Ok(bincode::deserialize::<i64>(datum)?.to_be_bytes().to_vec()),
+        _ => bail!(PgError::new(
+            SqlState::FeatureNotSupported,
+            format!(
+                "no binary output function available for type {}",
+                type_name(typ)
+            )
+        )),
+    }
+}
+
+/// Return the SQL value of a parameter sent on binary format.
+pub fn value_from_binary(data: &[u8], typ: Oid) -> Result<ast::Value> {
+    let invalid = || {
+        anyhow!(PgError::new(
+            SqlState::InvalidBinaryRepresentation,
+            format!("incorrect binary data format for type {}", type_name(typ))
+        ))
+    };
+    let number = |value: String| ast::Value::Number(value, false);
+
+    match typ {
+        pg_type::INT2_OID => Ok(number(
+            i16::from_be_bytes(data.try_into().map_err(|_| invalid())?).to_string(),
+        )),
+        pg_type::INT_OID => Ok(number(
+            i32::from_be_bytes(data.try_into().map_err(|_| invalid())?).to_string(),
+        )),
+        pg_type::INT8_OID => Ok(number(
+            i64::from_be_bytes(data.try_into().map_err(|_| invalid())?).to_string(),
+        )),
+        pg_type::FLOAT4_OID => Ok(number(
+            f32::from_be_bytes(data.try_into().map_err(|_| invalid())?).to_string(),
+        )),
+        pg_type::FLOAT8_OID => Ok(number(
+            f64::from_be_bytes(data.try_into().map_err(|_| invalid())?).to_string(),
+        )),
+        pg_type::TEXT_OID | pg_type::VARCHAR_OID => Ok(ast::Value::SingleQuotedString(
+            String::from_utf8(data.to_vec()).map_err(|_| invalid())?,
+        )),
+        pg_type::BOOL_OID => match data {
+            [value] => Ok(ast::Value::Boolean(*value != 0)),
+            _ => Err(invalid()),
+        },
+        pg_type::DATE_OID => Ok(ast::Value::SingleQuotedString(datetime::format_date(
+            i32::from_be_bytes(data.try_into().map_err(|_| invalid())?),
+        ))),
+        pg_type::TIMESTAMP_OID => Ok(ast::Value::SingleQuotedString(datetime::format_timestamp(
+            i64::from_be_bytes(data.try_into().map_err(|_| invalid())?),
+        ))),
+        _ => bail!(PgError::new(
+            SqlState::FeatureNotSupported,
+            format!(
+                "no binary input function available for type {}",
+                type_name(typ)
+            )
+        )),
+    }
+}
 
-    /// Data contents
-    pub v_data: Vec<u8>,
+/// Return the text representation of a float value using the same special values names of
+/// Postgres.
+fn float_to_string(value: f64) -> String {
+    if value.is_nan() {
+        String::from("NaN")
+    } else if value == f64::INFINITY {
+        String::from("Infinity")
+    } else if value == f64::NEG_INFINITY {
+        String::from("-Infinity")
+    } else {
+        value.to_string()
+    }
 }
 
-impl TryFrom<&String> for Varlena {
-    type Error = bincode::Error;
+impl TryFrom<i32> for Datum {
+    type Error = anyhow::Error;
 
-    /// Create a new varlena from a string.
-    fn try_from(value: &String) -> Result<Self, Self::Error> {
-        let data = bincode::serialize(&value)?;
-        Ok(Self {
-            v_len: bincode::serialize(&data)?.len() as u32,
-            v_data: data,
-        })
+    fn try_from(value: i32) -> std::result::Result<Self, Self::Error> {
+        let datum = Datum::from(bincode::serialize(&value)?);
+        Ok(datum)
     }
 }
 
-impl Varlena {
-    /// Compute the total length of varlena value.
-    pub fn len(&self) -> usize {
-        size_of::<u32>() + self.v_len as usize
+impl TryFrom<i16> for Datum {
+    type Error = anyhow::Error;
+
+    fn try_from(value: i16) -> std::result::Result<Self, Self::Error> {
+        let datum = Datum::from(bincode::serialize(&value)?);
+        Ok(datum)
     }
 }
 
-/// Serialize a string value into varlena struct format with len and raw  bytes representation.
-pub fn varlena_serializer<S>(value: &String, serializer: S) -> Result<S::Ok, S::Error>
-where
-    S: Serializer,
-{
-    let varlena = Varlena::try_from(value).unwrap();
-    varlena.serialize(serializer)
+impl TryFrom<i64> for Datum {
+    type Error = anyhow::Error;
+
+    fn try_from(value: i64) -> std::result::Result<Self, Self::Error> {
+        let datum = Datum::from(bincode::serialize(&value)?);
+        Ok(datum)
+    }
 }
 
-/// Deserialize a varlena string value.
-pub fn varlena_deserializer<'de, D>(deserializer: D) -> Result<String, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    let varlena = Varlena::deserialize(deserializer)?;
-    let value = bincode::deserialize(&varlena.v_data).unwrap();
-    Ok(value)
+impl TryFrom<f32> for Datum {
+    type Error = anyhow::Error;
+
+    fn try_from(value: f32) -> std::result::Result<Self, Self::Error> {
+        let datum = Datum::from(bincode::serialize(&value)?);
+        Ok(datum)
+    }
 }
 
-impl TryFrom<i32> for Datum {
+impl TryFrom<f64> for Datum {
     type Error = anyhow::Error;
 
-    fn try_from(value: i32) -> std::result::Result<Self, Self::Error> {
+    fn try_from(value: f64) -> std::result::Result<Self, Self::Error> {
         let datum = Datum::from(bincode::serialize(&value)?);
         Ok(datum)
     }
@@ -118,17 +264,122 @@ impl TryFrom<&bool> for Datum {
     }
 }
 
+/// String values are represented by their UTF-8 bytes.
 impl TryFrom<&String> for Datum {
     type Error = anyhow::Error;
 
     fn try_from(value: &String) -> std::result::Result<Self, Self::Error> {
-        let varlena = Varlena::try_from(value)?;
-
-        // TODO: Try to understand why I can't use bincode::serialize_into(encode_to, &varlena)
-        // here.
-        // Seems that using serialize_into the datums that are appended is splited between
-        // multiple vectors, which cause erros when reading.
-        let data = bincode::serialize(&varlena)?;
-        Ok(Datum::from(data))
+        Ok(Datum::from(value.as_bytes().to_vec()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_binary() -> Result<()> {
+        let datum = Datum::try_from(-2_i32)?;
+        assert_eq!(
+            decode_binary(&datum, pg_type::INT_OID)?,
+            vec![0xff, 0xff, 0xff, 0xfe]
+        );
+
+        let datum = Datum::try_from(20_000_000_000_i64)?;
+        assert_eq!(
+            decode_binary(&datum, pg_type::INT8_OID)?,
+            20_000_000_000_i64.to_be_bytes()
+        );
+
+        let datum = Datum::try_from(&true)?;
+        assert_eq!(decode_binary(&datum, pg_type::BOOL_OID)?, vec![1]);
+
+        let datum = Datum::try_from(&String::from("tinydb"))?;
+        assert_eq!(decode_binary(&datum, pg_type::VARCHAR_OID)?, b"tinydb");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_encode_assignment_casts() -> Result<()> {
+        let attr = |typ| PgAttribute {
+            attrelid: 1,
+            attname: String::from("a"),
+            attnum: 1,
+            attlen: pg_type::type_len(typ),
+            atttypid: typ,
+            attnotnull: false,
+            attdefault: String::new(),
+        };
+        let encode_value = |value: ast::Value, typ| -> Result<NullableDatum> {
+            let mut datums = Vec::new();
+            encode(&mut datums, &value, &attr(typ))?;
+            Ok(datums.pop().unwrap())
+        };
+        let string = |value: &str| ast::Value::SingleQuotedString(value.to_string());
+        let number = |value: &str| ast::Value::Number(value.to_string(), false);
+
+        assert_eq!(
+            encode_value(string("42"), pg_type::INT_OID)?,
+            Some(Datum::try_from(42)?)
+        );
+        assert_eq!(
+            encode_value(number("42"), pg_type::INT8_OID)?,
+            Some(Datum::try_from(42_i64)?)
+        );
+        assert_eq!(
+            encode_value(string("on"), pg_type::BOOL_OID)?,
+            Some(Datum::try_from(&true)?)
+        );
+        assert_eq!(
+            encode_value(number("1.50"), pg_type::TEXT_OID)?,
+            Some(Datum::try_from(&String::from("1.50"))?)
+        );
+        assert_eq!(
+            encode_value(ast::Value::Boolean(true), pg_type::VARCHAR_OID)?,
+            Some(Datum::try_from(&String::from("true"))?)
+        );
+
+        let err = |value, typ| {
+            encode_value(value, typ)
+                .unwrap_err()
+                .downcast::<PgError>()
+                .unwrap()
+        };
+        assert_eq!(
+            err(string("x"), pg_type::INT_OID).message,
+            "invalid input syntax for type integer: \"x\""
+        );
+        assert_eq!(
+            err(number("70000"), pg_type::INT2_OID).message,
+            "value \"70000\" is out of range for type smallint"
+        );
+        assert_eq!(
+            err(ast::Value::Boolean(true), pg_type::INT_OID).message,
+            "column \"a\" is of type integer but expression is of type boolean"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_value_from_binary() -> Result<()> {
+        assert_eq!(
+            value_from_binary(&42_i32.to_be_bytes(), pg_type::INT_OID)?,
+            ast::Value::Number(String::from("42"), false)
+        );
+        assert_eq!(
+            value_from_binary(&[0], pg_type::BOOL_OID)?,
+            ast::Value::Boolean(false)
+        );
+        assert_eq!(
+            value_from_binary(&8825_i32.to_be_bytes(), pg_type::DATE_OID)?,
+            ast::Value::SingleQuotedString(String::from("2024-02-29"))
+        );
+
+        // The size of the value must match the type.
+        assert!(value_from_binary(&[0, 1], pg_type::INT_OID).is_err());
+
+        Ok(())
     }
 }
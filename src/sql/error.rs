@@ -0,0 +1,269 @@
+use std::fmt;
+
+use sqlparser::parser::ParserError;
+
+use crate::{catalog, storage::disk};
+
+use super::SQLError;
+
+/// Severity of an error sent to the client.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    /// The error aborted the current command.
+    Error,
+
+    /// The error aborted the current session.
+    Fatal,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Error => write!(f, "ERROR"),
+            Self::Fatal => write!(f, "FATAL"),
+        }
+    }
+}
+
+/// SQLSTATE codes of errors. Codes are the same used by Postgres, so clients can handle errors
+/// from tinydb and Postgres in the same way.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SqlState {
+    FeatureNotSupported,
+    InvalidAuthorizationSpecification,
+    InvalidGrantOperation,
+    InvalidPassword,
+    InsufficientPrivilege,
+    InvalidCatalogName,
+    InvalidSchemaName,
+    ProtocolViolation,
+    StringDataRightTruncation,
+    NumericValueOutOfRange,
+    DivisionByZero,
+    InvalidDatetimeFormat,
+    DatetimeFieldOverflow,
+    InvalidParameterValue,
+    InvalidRowCountInLimitClause,
+    InvalidTextRepresentation,
+    InvalidBinaryRepresentation,
+    BadCopyFileFormat,
+    CardinalityViolation,
+    NotNullViolation,
+    UniqueViolation,
+    InvalidCursorName,
+    InvalidSqlStatementName,
+    SyntaxError,
+    GroupingError,
+    DatatypeMismatch,
+    WrongObjectType,
+    CannotCoerce,
+    UndefinedColumn,
+    UndefinedFunction,
+    UndefinedTable,
+    UndefinedObject,
+    DuplicateColumn,
+    DuplicateCursor,
+    DuplicateDatabase,
+    DuplicatePreparedStatement,
+    DuplicateSchema,
+    DuplicateTable,
+    DuplicateAlias,
+    DuplicateObject,
+    AmbiguousColumn,
+    InvalidColumnReference,
+    InvalidCursorDefinition,
+    ReservedName,
+    ObjectNotInPrerequisiteState,
+    ObjectInUse,
+    CantChangeRuntimeParam,
+    QueryCanceled,
+    IdleSessionTimeout,
+    DeadlockDetected,
+    TooManyConnections,
+    OutOfMemory,
+    ProgramLimitExceeded,
+    InternalError,
+    DataCorrupted,
+}
+
+impl SqlState {
+    /// Return the five characters SQLSTATE code.
+    pub fn code(&self) -> &str {
+        match self {
+            Self::FeatureNotSupported => "0A000",
+            Self::InvalidAuthorizationSpecification => "28000",
+            Self::InvalidGrantOperation => "0LP01",
+            Self::InvalidPassword => "28P01",
+            Self::InsufficientPrivilege => "42501",
+            Self::InvalidCatalogName => "3D000",
+            Self::InvalidSchemaName => "3F000",
+            Self::ProtocolViolation => "08P01",
+            Self::StringDataRightTruncation => "22001",
+            Self::NumericValueOutOfRange => "22003",
+            Self::DivisionByZero => "22012",
+            Self::InvalidDatetimeFormat => "22007",
+            Self::DatetimeFieldOverflow => "22008",
+            Self::InvalidParameterValue => "22023",
+            Self::InvalidRowCountInLimitClause => "2201W",
+            Self::InvalidTextRepresentation => "22P02",
+            Self::InvalidBinaryRepresentation => "22P03",
+            Self::BadCopyFileFormat => "22P04",
+            Self::CardinalityViolation => "21000",
+            Self::NotNullViolation => "23502",
+            Self::UniqueViolation => "23505",
+            Self::InvalidCursorName => "34000",
+            Self::InvalidSqlStatementName => "26000",
+            Self::SyntaxError => "42601",
+            Self::GroupingError => "42803",
+            Self::DatatypeMismatch => "42804",
+            Self::WrongObjectType => "42809",
+            Self::CannotCoerce => "42846",
+            Self::UndefinedColumn => "42703",
+            Self::UndefinedFunction => "42883",
+            Self::UndefinedTable => "42P01",
+            Self::UndefinedObject => "42704",
+            Self::DuplicateColumn => "42701",
+            Self::DuplicateCursor => "42P03",
+            Self::DuplicateDatabase => "42P04",
+            Self::DuplicatePreparedStatement => "42P05",
+            Self::DuplicateSchema => "42P06",
+            Self::DuplicateTable => "42P07",
+            Self::DuplicateAlias => "42712",
+            Self::DuplicateObject => "42710",
+            Self::AmbiguousColumn => "42702",
+            Self::InvalidColumnReference => "42P10",
+            Self::InvalidCursorDefinition => "42P11",
+            Self::ReservedName => "42939",
+            Self::ObjectNotInPrerequisiteState => "55000",
+            Self::ObjectInUse => "55006",
+            Self::CantChangeRuntimeParam => "55P02",
+            Self::QueryCanceled => "57014",
+            Self::IdleSessionTimeout => "57P05",
+            Self::DeadlockDetected => "40P01",
+            Self::TooManyConnections => "53300",
+            Self::OutOfMemory => "53200",
+            Self::ProgramLimitExceeded => "54000",
+            Self::InternalError => "XX000",
+            Self::DataCorrupted => "XX001",
+        }
+    }
+}
+
+/// An error sent to the client with a SQLSTATE code, and optionally a detail and hint messages.
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+#[error("{message}")]
+pub struct PgError {
+    pub severity: Severity,
+    pub code: SqlState,
+    pub message: String,
+    pub detail: Option<String>,
+    pub hint: Option<String>,
+}
+
+impl PgError {
+    /// Create a new error with the given SQLSTATE code and message.
+    pub fn new<M: Into<String>>(code: SqlState, message: M) -> Self {
+        Self {
+            severity: Severity::Error,
+            code,
+            message: message.into(),
+            detail: None,
+            hint: None,
+        }
+    }
+
+    /// Add a secondary message with more details about the error.
+    pub fn with_detail<D: Into<String>>(mut self, detail: D) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    /// Add a suggestion of what to do about the error.
+    pub fn with_hint<H: Into<String>>(mut self, hint: H) -> Self {
+        self.hint = Some(hint.into());
+        self
+    }
+
+    /// Change the severity of the error.
+    pub fn with_severity(mut self, severity: Severity) -> Self {
+        self.severity = severity;
+        self
+    }
+
+    /// Return the error that should be sent to the client for any error. Errors without a known
+    /// SQLSTATE code are reported as internal errors.
+    pub fn from_error(err: &anyhow::Error) -> Self {
+        if let Some(err) = err.downcast_ref::<PgError>() {
+            return err.clone();
+        }
+
+        let code = if err.is::<ParserError>() {
+            SqlState::SyntaxError
+        } else if err.is::<SQLError>() {
+            SqlState::FeatureNotSupported
+        } else if let Some(err) = err.downcast_ref::<catalog::Error>() {
+            match err {
+                catalog::Error::RelationNotFound(_) => SqlState::UndefinedTable,
+                catalog::Error::DatabaseNotFound(_) => SqlState::InvalidCatalogName,
+                catalog::Error::RelationAlreadyExists(_) => SqlState::DuplicateTable,
+                catalog::Error::RoleNotFound(_) => SqlState::InvalidAuthorizationSpecification,
+                catalog::Error::RoleAlreadyExists(_) => SqlState::DuplicateObject,
+                catalog::Error::NamespaceNotFound(_) => SqlState::InvalidSchemaName,
+                catalog::Error::NamespaceAlreadyExists(_) => SqlState::DuplicateSchema,
+            }
+        } else if let Some(disk::Error::ChecksumFailure { .. }) = err.downcast_ref::<disk::Error>()
+        {
+            SqlState::DataCorrupted
+        } else {
+            SqlState::InternalError
+        };
+
+        Self::new(code, err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+    use sqlparser::{dialect::PostgreSqlDialect, parser::Parser};
+
+    use super::*;
+
+    #[test]
+    fn test_error_sqlstate() {
+        let err = anyhow!(
+            PgError::new(SqlState::UndefinedColumn, "column a does not exist")
+                .with_hint("Perhaps you meant to reference the column b.")
+        );
+        let pg_err = PgError::from_error(&err);
+        assert_eq!(pg_err.code.code(), "42703");
+        assert_eq!(
+            pg_err.hint.as_deref(),
+            Some("Perhaps you meant to reference the column b.")
+        );
+        assert_eq!(err.to_string(), "column a does not exist");
+
+        let err = Parser::parse_sql(&PostgreSqlDialect {}, "SELEC 1").unwrap_err();
+        assert_eq!(
+            PgError::from_error(&anyhow!(err)).code,
+            SqlState::SyntaxError
+        );
+
+        let err = anyhow!(catalog::Error::RelationNotFound(String::from("t")));
+        assert_eq!(PgError::from_error(&err).code, SqlState::UndefinedTable);
+
+        let err = anyhow!(disk::Error::ChecksumFailure {
+            rel_name: String::from("t"),
+            page_number: 1,
+            calculated: 1,
+            expected: 2,
+        });
+        assert_eq!(PgError::from_error(&err).code, SqlState::DataCorrupted);
+
+        let err = anyhow!("unexpected error");
+        let pg_err = PgError::from_error(&err);
+        assert_eq!(pg_err.code, SqlState::InternalError);
+        assert_eq!(pg_err.severity, Severity::Error);
+        assert_eq!(pg_err.message, "unexpected error");
+    }
+}
@@ -0,0 +1,453 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Result};
+use sqlparser::{
+    ast::{SetVariableValue, Value},
+    dialect::PostgreSqlDialect,
+    tokenizer::{Token, Tokenizer},
+};
+
+use super::error::{PgError, SqlState};
+
+/// A configuration variable that can be changed per session using SET.
+struct Variable {
+    /// Name of variable as reported to clients.
+    name: &'static str,
+
+    /// Value used when the variable is not set or it's reset.
+    default: &'static str,
+
+    /// Report the value of the variable with a ParameterStatus message after the connection
+    /// startup and each time that it's changed.
+    reported: bool,
+
+    /// Variables that only describe the server can not be changed.
+    read_only: bool,
+}
+
+/// All configuration variables supported, following the same names and defaults of Postgres.
+const VARIABLES: &[Variable] = &[
+    Variable {
+        name: "application_name",
+        default: "",
+        reported: true,
+        read_only: false,
+    },
+    Variable {
+        name: "client_encoding",
+        default: "UTF8",
+        reported: true,
+        read_only: false,
+    },
+    Variable {
+        name: "DateStyle",
+        default: "ISO, MDY",
+        reported: true,
+        read_only: false,
+    },
+    Variable {
+        name: "extra_float_digits",
+        default: "1",
+        reported: false,
+        read_only: false,
+    },
+    Variable {
+        name: "integer_datetimes",
+        default: "on",
+        reported: true,
+        read_only: true,
+    },
+    Variable {
+        name: "IntervalStyle",
+        default: "postgres",
+        reported: true,
+        read_only: false,
+    },
+    Variable {
+        name: "search_path",
+        default: "\"$user\", public",
+        reported: false,
+        read_only: false,
+    },
+    Variable {
+        name: "server_encoding",
+        default: "UTF8",
+        reported: true,
+        read_only: true,
+    },
+    Variable {
+        name: "server_version",
+        default: "14.0",
+        reported: true,
+        read_only: true,
+    },
+    Variable {
+        name: "standard_conforming_strings",
+        default: "on",
+        reported: true,
+        read_only: false,
+    },
+    Variable {
+        name: "TimeZone",
+        default: "UTC",
+        reported: true,
+        read_only: false,
+    },
+    Variable {
+        name: "work_mem",
+        default: "4MB",
+        reported: false,
+        read_only: false,
+    },
+];
+
+/// Default value of work_mem, in bytes.
+pub const DEFAULT_WORK_MEM: usize = 4 * 1024 * 1024;
+
+/// Minimum value of work_mem, in kilobytes.
+const MIN_WORK_MEM: usize = 64;
+
+/// Return the variable with the given case insensitive name.
+fn variable(name: &str) -> Result<&'static Variable> {
+    match VARIABLES
+        .iter()
+        .find(|variable| variable.name.eq_ignore_ascii_case(name))
+    {
+        Some(variable) => Ok(variable),
+        None => bail!(PgError::new(
+            SqlState::UndefinedObject,
+            format!("unrecognized configuration parameter \"{}\"", name)
+        )),
+    }
+}
+
+/// Return the value to store for the given variable, or an error if the value is invalid.
+fn check_value(variable: &Variable, value: &str) -> Result<String> {
+    match variable.name {
+        // Values are always sent as UTF8, so there is no other encoding that clients can use.
+        "client_encoding" => match value.to_uppercase().as_str() {
+            "UTF8" | "UTF-8" | "UNICODE" => Ok(String::from("UTF8")),
+            _ => bail!(PgError::new(
+                SqlState::InvalidParameterValue,
+                format!(
+                    "invalid value for parameter \"{}\": \"{}\"",
+                    variable.name, value
+                )
+            )),
+        },
+        "work_mem" => {
+            parse_memory(variable.name, value)?;
+            Ok(value.to_string())
+        }
+        _ => Ok(value.to_string()),
+    }
+}
+
+/// Parse the value of a memory variable, returning the amount of bytes. Like Postgres, values
+/// without unit are in kilobytes.
+fn parse_memory(name: &str, value: &str) -> Result<usize> {
+    let trimmed = value.trim();
+    let digits = trimmed
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(trimmed.len());
+    let multiplier = match trimmed[digits..].trim() {
+        "B" => 1,
+        "" | "kB" => 1024,
+        "MB" => 1024 * 1024,
+        "GB" => 1024 * 1024 * 1024,
+        _ => 0,
+    };
+    let kilobytes = match trimmed[..digits].parse::<usize>() {
+        Ok(amount) if multiplier > 0 => amount.saturating_mul(multiplier) / 1024,
+        _ => bail!(PgError::new(
+            SqlState::InvalidParameterValue,
+            format!("invalid value for parameter \"{}\": \"{}\"", name, value)
+        )),
+    };
+    if kilobytes < MIN_WORK_MEM {
+        bail!(PgError::new(
+            SqlState::InvalidParameterValue,
+            format!(
+                "{} kB is outside the valid range for parameter \"{}\" ({} .. {})",
+                kilobytes,
+                name,
+                MIN_WORK_MEM,
+                i32::MAX
+            )
+        ));
+    }
+    Ok(kilobytes.saturating_mul(1024))
+}
+
+/// Values of the configuration variables of a session. Variables that were not set have the
+/// server default value.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SessionVariables {
+    /// Values of variables changed on the session, by the variable name.
+    values: HashMap<&'static str, String>,
+
+    /// Server default values that override the builtin defaults, by the variable name.
+    defaults: HashMap<&'static str, String>,
+}
+
+impl SessionVariables {
+    /// Set the value of the given variable.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<()> {
+        let variable = variable(name)?;
+        if variable.read_only {
+            bail!(PgError::new(
+                SqlState::CantChangeRuntimeParam,
+                format!("parameter \"{}\" cannot be changed", variable.name)
+            ));
+        }
+        self.values
+            .insert(variable.name, check_value(variable, value)?);
+        Ok(())
+    }
+
+    /// Set the server default value of the given variable, used when the variable is not set
+    /// or it's reset. Read only variables can also have the server default changed, e.g to
+    /// report a different server_version to clients.
+    pub fn set_default(&mut self, name: &str, value: &str) -> Result<()> {
+        let variable = variable(name)?;
+        self.defaults
+            .insert(variable.name, check_value(variable, value)?);
+        Ok(())
+    }
+
+    /// Set the value of the given variable from the values of a SET statement. A DEFAULT value
+    /// reset the variable.
+    pub fn set_values(&mut self, name: &str, values: &[SetVariableValue]) -> Result<()> {
+        if let [SetVariableValue::Ident(ident)] = values {
+            if ident.quote_style.is_none() && ident.value.eq_ignore_ascii_case("default") {
+                return self.reset(name);
+            }
+        }
+
+        let values = values
+            .iter()
+            .map(|value| match value {
+                SetVariableValue::Ident(ident) => ident.value.clone(),
+                SetVariableValue::Literal(Value::SingleQuotedString(value)) => value.clone(),
+                SetVariableValue::Literal(value) => value.to_string(),
+            })
+            .collect::<Vec<_>>();
+
+        self.set(name, &values.join(", "))
+    }
+
+    /// Return the name of the given variable using the same case reported to clients.
+    pub fn name(&self, name: &str) -> Result<&'static str> {
+        Ok(variable(name)?.name)
+    }
+
+    /// Return the value of the given variable.
+    pub fn get(&self, name: &str) -> Result<&str> {
+        Ok(self.value(variable(name)?))
+    }
+
+    /// Return the amount of memory, in bytes, that each query operation like a sort can use before
+    /// writing data to temporary files.
+    pub fn work_mem(&self) -> Result<usize> {
+        let variable = variable("work_mem")?;
+        parse_memory(variable.name, self.value(variable))
+    }
+
+    /// Return the names of the schemas of search_path, in order, without quotes.
+    pub fn search_path(&self) -> Vec<String> {
+        self.get("search_path")
+            .unwrap_or_default()
+            .split(',')
+            .map(|schema| schema.trim().trim_matches('"').to_string())
+            .filter(|schema| !schema.is_empty())
+            .collect()
+    }
+
+    /// Return the value of the given variable, or the default value if it was not set.
+    fn value(&self, variable: &Variable) -> &str {
+        match self.values.get(variable.name) {
+            Some(value) => value,
+            None => match self.defaults.get(variable.name) {
+                Some(value) => value,
+                None => variable.default,
+            },
+        }
+    }
+
+    /// Change the given variable back to the default value.
+    pub fn reset(&mut self, name: &str) -> Result<()> {
+        let variable = variable(name)?;
+        if variable.read_only {
+            bail!(PgError::new(
+                SqlState::CantChangeRuntimeParam,
+                format!("parameter \"{}\" cannot be changed", variable.name)
+            ));
+        }
+        self.values.remove(variable.name);
+        Ok(())
+    }
+
+    /// Change all variables back to the default values.
+    pub fn reset_all(&mut self) {
+        self.values.clear();
+    }
+
+    /// Return the name and value of all variables.
+    pub fn all(&self) -> Vec<(&'static str, &str)> {
+        VARIABLES
+            .iter()
+            .map(|variable| (variable.name, self.value(variable)))
+            .collect()
+    }
+
+    /// Return the name and value of the given variable if changes on it should be reported to
+    /// the client.
+    pub fn reported(&self, name: &str) -> Result<Option<(&'static str, &str)>> {
+        let variable = variable(name)?;
+        if !variable.reported {
+            return Ok(None);
+        }
+        Ok(Some((variable.name, self.get(name)?)))
+    }
+
+    /// Return the name and value of all variables that are reported to the client.
+    pub fn all_reported(&self) -> Vec<(&'static str, &str)> {
+        VARIABLES
+            .iter()
+            .filter(|variable| variable.reported)
+            .map(|variable| (variable.name, self.value(variable)))
+            .collect()
+    }
+}
+
+/// Variables reset by a RESET statement.
+#[derive(Debug, PartialEq)]
+pub enum Reset {
+    /// RESET ALL
+    All,
+
+    /// RESET <variable>
+    Variable(String),
+}
+
+/// Parse a RESET statement, which is not supported by the SQL parser. Return None if the given
+/// sql is not a RESET statement.
+pub fn parse_reset(sql: &str) -> Option<Reset> {
+    let dialect = PostgreSqlDialect {};
+    let tokens = Tokenizer::new(&dialect, sql).tokenize().ok()?;
+    let words = tokens
+        .into_iter()
+        .filter(|token| !matches!(token, Token::Whitespace(_) | Token::SemiColon))
+        .collect::<Vec<_>>();
+
+    match words.as_slice() {
+        [Token::Word(reset), Token::Word(name)] if reset.value.eq_ignore_ascii_case("reset") => {
+            if name.quote_style.is_none() && name.value.eq_ignore_ascii_case("all") {
+                Some(Reset::All)
+            } else {
+                Some(Reset::Variable(name.value.clone()))
+            }
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_variables() -> Result<()> {
+        let mut variables = SessionVariables::default();
+        assert_eq!(variables.get("datestyle")?, "ISO, MDY");
+
+        variables.set("DATESTYLE", "German")?;
+        assert_eq!(variables.get("DateStyle")?, "German");
+        assert_eq!(
+            variables.reported("datestyle")?,
+            Some(("DateStyle", "German"))
+        );
+
+        variables.set("client_encoding", "unicode")?;
+        assert_eq!(variables.get("client_encoding")?, "UTF8");
+        assert!(variables.set("client_encoding", "LATIN1").is_err());
+
+        assert!(variables.set("server_version", "1.0").is_err());
+        assert!(variables.set("unknown", "1").is_err());
+
+        variables.reset("datestyle")?;
+        assert_eq!(variables.get("datestyle")?, "ISO, MDY");
+
+        variables.set("extra_float_digits", "3")?;
+        assert_eq!(variables.reported("extra_float_digits")?, None);
+        variables.reset_all();
+        assert_eq!(variables.get("extra_float_digits")?, "1");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_path() -> Result<()> {
+        let mut variables = SessionVariables::default();
+        assert_eq!(variables.search_path(), vec!["$user", "public"]);
+
+        variables.set("search_path", "app,  \"Public\" ")?;
+        assert_eq!(variables.search_path(), vec!["app", "Public"]);
+        variables.set("search_path", "")?;
+        assert!(variables.search_path().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_work_mem() -> Result<()> {
+        let mut variables = SessionVariables::default();
+        assert_eq!(variables.work_mem()?, DEFAULT_WORK_MEM);
+
+        variables.set("work_mem", "64kB")?;
+        assert_eq!(variables.work_mem()?, 64 * 1024);
+        variables.set("work_mem", "1024")?;
+        assert_eq!(variables.work_mem()?, 1024 * 1024);
+        variables.set("work_mem", "1GB")?;
+        assert_eq!(variables.work_mem()?, 1024 * 1024 * 1024);
+
+        assert!(variables.set("work_mem", "1 MiB").is_err());
+        assert!(variables.set("work_mem", "MB").is_err());
+        assert_eq!(
+            variables.set("work_mem", "32kB").unwrap_err().to_string(),
+            "32 kB is outside the valid range for parameter \"work_mem\" (64 .. 2147483647)"
+        );
+        assert_eq!(variables.get("work_mem")?, "1GB");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_server_defaults() -> Result<()> {
+        let mut variables = SessionVariables::default();
+        variables.set_default("timezone", "America/Sao_Paulo")?;
+        variables.set_default("server_version", "15.0")?;
+        assert!(variables.set_default("client_encoding", "LATIN1").is_err());
+
+        variables.set("timezone", "UTC")?;
+        assert_eq!(variables.get("TimeZone")?, "UTC");
+        variables.reset_all();
+        assert_eq!(variables.get("TimeZone")?, "America/Sao_Paulo");
+        assert_eq!(
+            variables.reported("server_version")?,
+            Some(("server_version", "15.0"))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_reset() {
+        assert_eq!(parse_reset("RESET ALL;"), Some(Reset::All));
+        assert_eq!(
+            parse_reset("reset DateStyle"),
+            Some(Reset::Variable(String::from("DateStyle")))
+        );
+        assert_eq!(parse_reset("SELECT 1"), None);
+        assert_eq!(parse_reset("RESET"), None);
+    }
+}
@@ -0,0 +1,596 @@
+//! INSERT statements with ON CONFLICT or RETURNING clauses, that the SQL parser don't support.
+//!
+//! Like Postgres rewrites them, an INSERT ... RETURNING items statement is parsed as the query
+//! SELECT items FROM (INSERT ...), a projection of the inserted rows, so the statement is
+//! described and bound like any other query. The statement is executed by exec_insert, which
+//! evaluates the RETURNING items against each inserted row.
+//!
+//! The ON CONFLICT clause is stored on fields of the INSERT statement that are only used by other
+//! dialects: the conflict target columns on after_columns, DO NOTHING as the OR IGNORE action
+//! and the assignments of DO UPDATE SET as the assignments of ON DUPLICATE KEY UPDATE.
+
+use std::collections::HashSet;
+
+use anyhow::{bail, Result};
+use sqlparser::{
+    ast,
+    keywords::Keyword,
+    parser::{IsOptional, Parser, ParserError},
+    tokenizer::Token,
+};
+
+use crate::{
+    access::{
+        heaptuple::TupleDesc,
+        lmgr::{LockGuard, LockOwner},
+        tableam::TableAm,
+    },
+    catalog::index::{self, UniqueIndex},
+    expr::{self, RangeTable, ScalarValue},
+    relation::Relation,
+    sql::{
+        encode::encode_scalar,
+        error::{PgError, SqlState},
+    },
+    storage::{page::ItemPointer, BufferPool},
+    NullableDatum,
+};
+
+/// Parse the next statement of the given parser if it is an INSERT statement, with or without
+/// ON CONFLICT and RETURNING clauses. Return None if the next statement is not INSERT.
+pub fn parse_insert(parser: &mut Parser) -> Option<Result<ast::Statement, ParserError>> {
+    match parser.peek_token() {
+        Token::Word(word) if word.keyword == Keyword::INSERT => parser.next_token(),
+        _ => return None,
+    };
+    Some(parse_insert_returning(parser))
+}
+
+fn parse_insert_returning(parser: &mut Parser) -> Result<ast::Statement, ParserError> {
+    let insert = parse_insert_on_conflict(parser)?;
+    if !parse_word(parser, "returning") {
+        return Ok(insert);
+    }
+    let returning = parser.parse_comma_separated(Parser::parse_select_item)?;
+
+    let inserted = ast::TableWithJoins {
+        relation: ast::TableFactor::Derived {
+            lateral: false,
+            subquery: Box::new(query(ast::SetExpr::Insert(insert))),
+            alias: None,
+        },
+        joins: Vec::new(),
+    };
+    Ok(ast::Statement::Query(Box::new(query(
+        ast::SetExpr::Select(Box::new(ast::Select {
+            distinct: false,
+            top: None,
+            projection: returning,
+            into: None,
+            from: vec![inserted],
+            lateral_views: Vec::new(),
+            selection: None,
+            group_by: Vec::new(),
+            cluster_by: Vec::new(),
+            distribute_by: Vec::new(),
+            sort_by: Vec::new(),
+            having: None,
+            qualify: None,
+        })),
+    ))))
+}
+
+/// Parse an INSERT statement after the INSERT keyword, on the Postgres syntax
+/// INSERT INTO table [(columns)] query [ON CONFLICT [(column)] DO {NOTHING | UPDATE SET ...}].
+fn parse_insert_on_conflict(parser: &mut Parser) -> Result<ast::Statement, ParserError> {
+    let into = parser.parse_keyword(Keyword::INTO);
+    let table_name = parser.parse_object_name()?;
+    let columns = parser.parse_parenthesized_column_list(IsOptional::Optional)?;
+    let source = Box::new(parser.parse_query()?);
+
+    let (mut or, mut on, mut after_columns) = (None, None, Vec::new());
+    if parser.parse_keyword(Keyword::ON) {
+        expect_word(parser, "conflict")?;
+        after_columns = parser.parse_parenthesized_column_list(IsOptional::Optional)?;
+        expect_word(parser, "do")?;
+        if parse_word(parser, "nothing") {
+            or = Some(ast::SqliteOnConflict::Ignore);
+        } else {
+            parser.expect_keywords(&[Keyword::UPDATE, Keyword::SET])?;
+            on = Some(ast::OnInsert::DuplicateKeyUpdate(
+                parser.parse_comma_separated(Parser::parse_assignment)?,
+            ));
+        }
+    }
+
+    Ok(ast::Statement::Insert {
+        or,
+        into,
+        table_name,
+        columns,
+        overwrite: false,
+        source,
+        partitioned: None,
+        after_columns,
+        table: false,
+        on,
+    })
+}
+
+/// Consume the next token if it is the given word, which is not a keyword of the SQL parser.
+fn parse_word(parser: &mut Parser, word: &str) -> bool {
+    match parser.peek_token() {
+        Token::Word(w) if w.value.eq_ignore_ascii_case(word) => {
+            parser.next_token();
+            true
+        }
+        _ => false,
+    }
+}
+
+fn expect_word(parser: &mut Parser, word: &str) -> Result<(), ParserError> {
+    if parse_word(parser, word) {
+        return Ok(());
+    }
+    Err(ParserError::ParserError(format!(
+        "Expected {}, found: {}",
+        word.to_uppercase(),
+        parser.peek_token()
+    )))
+}
+
+fn query(body: ast::SetExpr) -> ast::Query {
+    ast::Query {
+        with: None,
+        body,
+        order_by: Vec::new(),
+        limit: None,
+        offset: None,
+        fetch: None,
+        lock: None,
+    }
+}
+
+/// Return the INSERT statement and the RETURNING items of a statement parsed from an
+/// INSERT ... RETURNING statement. None for any other statement.
+pub fn insert_returning(
+    statement: &ast::Statement,
+) -> Option<(&ast::Statement, &[ast::SelectItem])> {
+    let select = match statement {
+        ast::Statement::Query(query) => match &query.body {
+            ast::SetExpr::Select(select) => select,
+            _ => return None,
+        },
+        _ => return None,
+    };
+    match select.from.as_slice() {
+        [ast::TableWithJoins {
+            relation: ast::TableFactor::Derived { subquery, .. },
+            joins,
+        }] if joins.is_empty() => match &subquery.body {
+            ast::SetExpr::Insert(insert) => Some((insert, &select.projection)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The ON CONFLICT clause of an INSERT statement.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OnConflict<'a> {
+    /// ON CONFLICT [(target)] DO NOTHING skips the rows that conflict with another row on the
+    /// unique index of the target column, or on any unique index if there is no target.
+    DoNothing { target: &'a [ast::Ident] },
+
+    /// ON CONFLICT (target) DO UPDATE SET assignments updates the row that conflicts on the
+    /// unique index of the target column instead of inserting the new row.
+    DoUpdate {
+        target: &'a [ast::Ident],
+        assignments: &'a [ast::Assignment],
+    },
+}
+
+/// Return the ON CONFLICT clause of the given INSERT statement, or None if it has no clause.
+pub fn on_conflict(insert: &ast::Statement) -> Option<OnConflict<'_>> {
+    match insert {
+        ast::Statement::Insert {
+            after_columns,
+            on: Some(ast::OnInsert::DuplicateKeyUpdate(assignments)),
+            ..
+        } => Some(OnConflict::DoUpdate {
+            target: after_columns,
+            assignments,
+        }),
+        ast::Statement::Insert {
+            after_columns,
+            or: Some(ast::SqliteOnConflict::Ignore),
+            ..
+        } => Some(OnConflict::DoNothing {
+            target: after_columns,
+        }),
+        _ => None,
+    }
+}
+
+/// Tuples written by an INSERT statement, after resolving the conflicts of its rows with the
+/// unique indexes of the table.
+#[derive(Default)]
+pub struct InsertTuples {
+    /// Location of the existing tuples updated by ON CONFLICT DO UPDATE, which are deleted and
+    /// inserted again with the new values.
+    pub deleted: Vec<ItemPointer>,
+
+    /// Values of the tuples to insert: the rows without conflicts and the new versions of the
+    /// updated tuples.
+    pub values: Vec<Vec<NullableDatum>>,
+
+    /// Locks of the keys of the unique indexes, that must be held until the tuples are written,
+    /// so that concurrent statements don't insert conflicting tuples after the conflicts were
+    /// resolved.
+    pub locks: Vec<LockGuard>,
+}
+
+/// Resolve the conflicts of the given rows of an INSERT statement on the given relation with
+/// the live tuples of the relation and with the previous rows of the statement.
+///
+/// Like Postgres, a row that conflicts on a unique index that is not an arbiter of the ON
+/// CONFLICT clause is an error, as well as a row of DO UPDATE that conflicts with a row
+/// inserted or updated by the same statement. Nothing is written, so the statement can be
+/// rejected before writing any tuple. The keys of the unique indexes are locked on behalf of the
+/// given owner until the returned tuples are dropped.
+pub fn resolve_conflicts(
+    buffer_pool: &BufferPool,
+    owner: &LockOwner,
+    rel: &Relation,
+    table_am: &dyn TableAm,
+    tuple_desc: &TupleDesc,
+    on_conflict: Option<OnConflict>,
+    rows: Vec<Vec<NullableDatum>>,
+) -> Result<InsertTuples> {
+    let indexes = index::unique_indexes(buffer_pool, rel, tuple_desc)?;
+    let (arbiters, assignments) = match on_conflict {
+        None => (Vec::new(), None),
+        Some(OnConflict::DoNothing { target }) => (arbiter_indexes(&indexes, target)?, None),
+        Some(OnConflict::DoUpdate {
+            target,
+            assignments,
+        }) => {
+            if target.is_empty() {
+                bail!(PgError::new(
+                    SqlState::SyntaxError,
+                    "ON CONFLICT DO UPDATE requires inference specification or constraint name"
+                )
+                .with_hint("For example, ON CONFLICT (column_name)."));
+            }
+            (
+                arbiter_indexes(&indexes, target)?,
+                Some(Assignments::new(&rel.rel_name, tuple_desc, assignments)?),
+            )
+        }
+    };
+    if indexes.is_empty() {
+        return Ok(InsertTuples {
+            values: rows,
+            ..Default::default()
+        });
+    }
+    let locks = indexes
+        .iter()
+        .map(|index| index.lock_keys(buffer_pool, owner))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut resolver = ConflictResolver {
+        buffer_pool,
+        rel,
+        table_am,
+        indexes,
+        keys: Vec::new(),
+        deleted: HashSet::new(),
+        tuples: InsertTuples {
+            locks,
+            ..Default::default()
+        },
+    };
+    resolver.keys.resize(resolver.indexes.len(), HashSet::new());
+
+    for values in rows {
+        let conflict = match resolver.find_conflict(&values, &arbiters)? {
+            Some(conflict) => conflict,
+            None => {
+                resolver.insert(values)?;
+                continue;
+            }
+        };
+        match (&assignments, conflict) {
+            // Only DO NOTHING has arbiters without assignments, so the row is skipped.
+            (None, _) => {}
+            (Some(_), Conflict::Proposed) => bail!(PgError::new(
+                SqlState::CardinalityViolation,
+                "ON CONFLICT DO UPDATE command cannot affect row a second time"
+            )
+            .with_hint(
+                "Ensure that no rows proposed for insertion within the same command have duplicate constrained values."
+            )),
+            (Some(assignments), Conflict::Existing(tid)) => {
+                let existing = match table_am.fetch(buffer_pool, rel, &tid)? {
+                    Some(tuple) => tuple.deform(tuple_desc, None)?,
+                    None => bail!("tuple {:?} of relation {} not found", tid, rel.rel_name),
+                };
+                let updated = assignments.apply(&rel.rel_name, tuple_desc, existing, &values)?;
+                resolver.deleted.insert(tid);
+                resolver.tuples.deleted.push(tid);
+                resolver.insert(updated)?;
+            }
+        }
+    }
+
+    Ok(resolver.tuples)
+}
+
+/// Return the position of the unique indexes that are arbiters of an ON CONFLICT clause with the
+/// given target columns: all unique indexes if there is no target, or the unique indexes of the
+/// target column.
+fn arbiter_indexes(indexes: &[UniqueIndex], target: &[ast::Ident]) -> Result<Vec<usize>> {
+    let arbiters = indexes
+        .iter()
+        .enumerate()
+        .filter(|(_, index)| match target {
+            [] => true,
+            [column] => index.attr.attname == column.value,
+            _ => false,
+        })
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+    if arbiters.is_empty() && !target.is_empty() {
+        bail!(PgError::new(
+            SqlState::InvalidColumnReference,
+            "there is no unique or exclusion constraint matching the ON CONFLICT specification"
+        ));
+    }
+    Ok(arbiters)
+}
+
+/// A row that has the same key of a new row on a unique index.
+enum Conflict {
+    /// A live tuple of the relation stored on the given location.
+    Existing(ItemPointer),
+
+    /// A row inserted or updated by the same statement.
+    Proposed,
+}
+
+struct ConflictResolver<'a> {
+    buffer_pool: &'a BufferPool,
+    rel: &'a Relation,
+    table_am: &'a dyn TableAm,
+    indexes: Vec<UniqueIndex>,
+
+    /// Keys of the proposed rows on each unique index.
+    keys: Vec<HashSet<Vec<u8>>>,
+
+    /// Location of the updated tuples, which don't conflict with new rows anymore.
+    deleted: HashSet<ItemPointer>,
+
+    tuples: InsertTuples,
+}
+
+impl ConflictResolver<'_> {
+    /// Return the first conflict of the given row on the unique indexes of the given positions.
+    fn find_conflict(
+        &self,
+        values: &[NullableDatum],
+        indexes: &[usize],
+    ) -> Result<Option<Conflict>> {
+        for i in indexes {
+            let index = &self.indexes[*i];
+            let key = match index.key(values)? {
+                Some(key) => key,
+                None => continue,
+            };
+            if self.keys[*i].contains(&key) {
+                return Ok(Some(Conflict::Proposed));
+            }
+            if let Some(tid) = index.find(
+                self.buffer_pool,
+                self.rel,
+                self.table_am,
+                &key,
+                &self.deleted,
+            )? {
+                return Ok(Some(Conflict::Existing(tid)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Propose the given row to be inserted. Return an error if the row conflicts on any unique
+    /// index.
+    fn insert(&mut self, values: Vec<NullableDatum>) -> Result<()> {
+        for i in 0..self.indexes.len() {
+            if self.find_conflict(&values, &[i])?.is_some() {
+                bail!(self.indexes[i].violation(&values)?);
+            }
+        }
+        for (i, index) in self.indexes.iter().enumerate() {
+            if let Some(key) = index.key(&values)? {
+                self.keys[i].insert(key);
+            }
+        }
+        self.tuples.values.push(values);
+        Ok(())
+    }
+}
+
+/// The assignments of ON CONFLICT DO UPDATE SET, evaluated against the values of the existing
+/// row followed by the values of the row proposed for insertion, named excluded.
+struct Assignments {
+    /// Index of the assigned attribute and the assigned expression.
+    exprs: Vec<(usize, expr::Expr)>,
+}
+
+impl Assignments {
+    fn new(
+        rel_name: &str,
+        tuple_desc: &TupleDesc,
+        assignments: &[ast::Assignment],
+    ) -> Result<Self> {
+        let range_table = RangeTable::new(rel_name, tuple_desc.clone())
+            .join(RangeTable::new("excluded", tuple_desc.clone()))?;
+
+        let mut exprs = Vec::with_capacity(assignments.len());
+        for assignment in assignments {
+            let column = match assignment.id.as_slice() {
+                [column] => column,
+                _ => bail!(PgError::new(
+                    SqlState::SyntaxError,
+                    format!(
+                        "column reference {} can not be qualified",
+                        ast::ObjectName(assignment.id.clone())
+                    )
+                )),
+            };
+            let index = match tuple_desc
+                .attrs
+                .iter()
+                .position(|attr| attr.attname == column.value)
+            {
+                Some(index) => index,
+                None => bail!(PgError::new(
+                    SqlState::UndefinedColumn,
+                    format!(
+                        "column \"{}\" of relation \"{}\" does not exist",
+                        column.value, rel_name
+                    )
+                )),
+            };
+            if exprs.iter().any(|(i, _)| *i == index) {
+                bail!(PgError::new(
+                    SqlState::SyntaxError,
+                    format!("multiple assignments to same column \"{}\"", column.value)
+                ));
+            }
+            exprs.push((index, expr::Expr::create(&assignment.value, &range_table)?));
+        }
+        Ok(Self { exprs })
+    }
+
+    /// Return the values of the existing row after applying the assignments.
+    fn apply(
+        &self,
+        rel_name: &str,
+        tuple_desc: &TupleDesc,
+        existing: Vec<NullableDatum>,
+        excluded: &[NullableDatum],
+    ) -> Result<Vec<NullableDatum>> {
+        let values = existing
+            .iter()
+            .chain(excluded.iter())
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let mut updated = existing;
+        for (index, expr) in &self.exprs {
+            let attr = &tuple_desc.attrs[*index];
+            let value = expr.eval(&values)?;
+            if attr.attnotnull && value == ScalarValue::Null {
+                bail!(PgError::new(
+                    SqlState::NotNullViolation,
+                    format!(
+                        "null value in column \"{}\" of relation \"{}\" violates not-null constraint",
+                        attr.attname, rel_name
+                    )
+                ));
+            }
+            let mut datum = Vec::with_capacity(1);
+            encode_scalar(&mut datum, value, attr)?;
+            updated[*index] = datum.pop().unwrap_or_default();
+        }
+        Ok(updated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlparser::dialect::PostgreSqlDialect;
+
+    use super::*;
+
+    fn parse(sql: &str) -> Option<Result<ast::Statement, ParserError>> {
+        let dialect = PostgreSqlDialect {};
+        let mut parser = Parser::new(
+            sqlparser::tokenizer::Tokenizer::new(&dialect, sql)
+                .tokenize()
+                .unwrap(),
+            &dialect,
+        );
+        parse_insert(&mut parser)
+    }
+
+    #[test]
+    fn test_parse_insert_returning() {
+        assert!(parse("SELECT 1").is_none());
+
+        let statement = parse("INSERT INTO t VALUES (1)").unwrap().unwrap();
+        assert!(matches!(statement, ast::Statement::Insert { .. }));
+        assert!(insert_returning(&statement).is_none());
+
+        let statement = parse("insert into t (a, b) values (1, 2) returning a, b + 1 AS c, *")
+            .unwrap()
+            .unwrap();
+        let (insert, returning) = insert_returning(&statement).unwrap();
+        assert_eq!(insert.to_string(), "INSERT INTO t (a, b) VALUES (1, 2)");
+        assert_eq!(
+            returning
+                .iter()
+                .map(|item| item.to_string())
+                .collect::<Vec<_>>(),
+            vec!["a", "b + 1 AS c", "*"]
+        );
+
+        let query = Parser::parse_sql(&PostgreSqlDialect {}, "SELECT a FROM (SELECT 1 AS a)");
+        assert!(insert_returning(&query.unwrap()[0]).is_none());
+        assert!(parse("INSERT INTO t VALUES (1) RETURNING")
+            .unwrap()
+            .is_err());
+    }
+
+    #[test]
+    fn test_parse_insert_on_conflict() {
+        let statement = parse("INSERT INTO t VALUES (1)").unwrap().unwrap();
+        assert_eq!(on_conflict(&statement), None);
+
+        let statement = parse("INSERT INTO t VALUES (1) ON CONFLICT DO NOTHING RETURNING a")
+            .unwrap()
+            .unwrap();
+        let (insert, _) = insert_returning(&statement).unwrap();
+        assert_eq!(
+            on_conflict(insert),
+            Some(OnConflict::DoNothing { target: &[] })
+        );
+
+        let statement =
+            parse("insert into t values (1, 2) on conflict (a) do update set b = excluded.b")
+                .unwrap()
+                .unwrap();
+        match on_conflict(&statement) {
+            Some(OnConflict::DoUpdate {
+                target,
+                assignments,
+            }) => {
+                assert_eq!(target, &[ast::Ident::new("a")]);
+                assert_eq!(assignments.len(), 1);
+                assert_eq!(assignments[0].value.to_string(), "excluded.b");
+            }
+            on_conflict => panic!("unexpected ON CONFLICT clause {:?}", on_conflict),
+        }
+
+        assert!(
+            parse("INSERT INTO t VALUES (1) ON DUPLICATE KEY UPDATE a = 1")
+                .unwrap()
+                .is_err()
+        );
+        assert!(parse("INSERT INTO t VALUES (1) ON CONFLICT (a) DO")
+            .unwrap()
+            .is_err());
+    }
+}
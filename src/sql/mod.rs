@@ -1,72 +1,1319 @@
 use crate::{
     access::{
         self,
-        heap::heap_insert,
+        btree::BTREE_AM_OID,
         heaptuple::{HeapTuple, TupleDesc},
+        lmgr::{self, LockGuard, LockMode, LockOwner, LockTag},
+        tableam::{self, HeapTableAm, TableAm},
     },
     catalog::{
-        self, heap::heap_create, pg_attribute::PgAttribute, pg_tablespace::DEFAULTTABLESPACE_OID,
+        self,
+        acl::{
+            Acl, AclMode, RolePrivileges, ACL_ALL_RIGHTS_RELATION, ACL_DELETE, ACL_ID_PUBLIC,
+            ACL_INSERT, ACL_SELECT, ACL_UPDATE,
+        },
+        heap::{heap_drop, table_create, update_relation_acl, update_relation_statistics},
+        index::index_create,
+        namespace::{self, RelationName},
+        pg_attribute::PgAttribute,
+        pg_authid::{self, PgAuthId, BOOTSTRAP_SUPERUSER_ID},
+        pg_class::PgClass,
+        pg_database, pg_namespace,
+        pg_tablespace::DEFAULTTABLESPACE_OID,
         pg_type,
+        virtual_table::VirtualTables,
     },
-    executor::{Executor, TupleTable},
-    planner::Plan,
+    executor::{ExecutionStats, Executor, TupleTable},
+    expr::{self, aggregate::AggregateExpr, RangeTable},
+    planner::{self, Plan, PlanNodeType},
+    relation::Relation,
     storage::BufferPool,
-    NullableDatum, Oid,
+    Datum, NullableDatum, Oid, FIRST_NORMAL_OBJECT_ID, INVALID_OID,
 };
-use anyhow::{bail, Result};
+use anyhow::{anyhow, bail, Result};
+use copy::CopyRow;
+use cursor::{Cursor, CursorStatement};
+use database::CreateDatabaseStatement;
 use encode::encode;
-use sqlparser::ast::{self, Expr, Value};
-use std::mem::size_of;
+use error::{PgError, SqlState};
+use guc::{Reset, SessionVariables};
+use prepared::{format_codes, Portal, PreparedStatement};
+use psql::ListRelations;
+use role::RoleStatement;
+use sqlparser::{
+    ast::{self, Expr, Value},
+    dialect::PostgreSqlDialect,
+    keywords::Keyword,
+    parser::{Parser, ParserError},
+    tokenizer::{Token, Tokenizer},
+};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+pub mod analyze;
+pub mod copy;
+pub mod cursor;
+pub mod database;
+pub mod encode;
+pub mod error;
+pub mod guc;
+pub mod insert;
+pub mod prepared;
+pub mod psql;
+pub mod role;
+
+/// Errors related with a SQL command
+#[derive(Debug, thiserror::Error)]
+pub enum SQLError {
+    /// Unsupported SQL operation.
+    #[error("unsuported operation {0}")]
+    Unsupported(String),
+}
+
+/// An executor config holds per connection configuration values.
+pub struct ExecutorConfig {
+    /// Oid of database that is connect in.
+    pub database: Oid,
+
+    /// Configuration variables of the session, changed using SET.
+    pub variables: SessionVariables,
+
+    /// Virtual relations that can be queried on the session.
+    pub virtual_tables: VirtualTables,
+
+    /// Oid of the role that started the session, the user name of the connection.
+    pub session_role: Oid,
+}
+
+/// A connection executor is in charge of executing queries on a give database connection.
+pub struct ConnectionExecutor {
+    /// Configuration options for a database connection.
+    config: ExecutorConfig,
+
+    /// Buffer pool shared with the query planner and executor.
+    buffer_pool: BufferPool,
+
+    /// Prepared statements created on this connection, by name. The unnamed statement of the
+    /// extended query protocol has an empty name.
+    prepared_statements: HashMap<String, PreparedStatement>,
+
+    /// Portals created by the extended query protocol, by name. The unnamed portal has an empty
+    /// name.
+    portals: HashMap<String, Portal>,
+
+    /// Cursors declared on this connection, by name. Portals and cursors share the same names,
+    /// like on Postgres.
+    cursors: HashMap<String, Cursor>,
+
+    /// Flag set when a cancel request is received for this connection.
+    canceled: Arc<AtomicBool>,
+
+    /// Owner of the relation locks acquired by the statements of this connection.
+    lock_owner: LockOwner,
+
+    /// Role whose privileges are checked by the statements of this connection. The session
+    /// role, unless changed by SET ROLE.
+    current_role: Oid,
+}
+
+impl ConnectionExecutor {
+    pub fn new(config: ExecutorConfig, buffer_pool: BufferPool) -> Self {
+        let canceled = Arc::new(AtomicBool::new(false));
+        Self {
+            current_role: config.session_role,
+            config,
+            buffer_pool,
+            prepared_statements: HashMap::new(),
+            portals: HashMap::new(),
+            cursors: HashMap::new(),
+            lock_owner: LockOwner::new().with_cancel_flag(canceled.clone()),
+            canceled,
+        }
+    }
+
+    /// Return the buffer pool used to execute the statements of this connection.
+    pub fn buffer_pool(&self) -> &BufferPool {
+        &self.buffer_pool
+    }
+
+    /// Return the oid of the database that this connection is connected to.
+    pub fn database(&self) -> Oid {
+        self.config.database
+    }
+
+    /// Return the flag that cancel the statement being executed when it is set.
+    pub fn cancel_flag(&self) -> Arc<AtomicBool> {
+        self.canceled.clone()
+    }
+
+    /// Clear a cancellation that was not handled by a previous statement.
+    pub fn reset_cancel(&self) {
+        self.canceled.store(false, Ordering::SeqCst);
+    }
+
+    /// Create a new prepared statement with the given name. The type of parameters that are not
+    /// declared on data_types are inferred from the columns of the statement relations.
+    pub fn exec_prepare(
+        &mut self,
+        name: &ast::Ident,
+        data_types: &[ast::DataType],
+        statement: ast::Statement,
+    ) -> Result<()> {
+        if self.prepared_statements.contains_key(&name.value) {
+            bail!(PgError::new(
+                SqlState::DuplicatePreparedStatement,
+                format!("prepared statement \"{}\" already exists", name.value)
+            ));
+        }
+
+        match statement {
+            ast::Statement::Query(_)
+            | ast::Statement::Insert { .. }
+            | ast::Statement::Delete { .. } => {}
+            _ => bail!(SQLError::Unsupported(format!("PREPARE {}", statement))),
+        }
+
+        let param_types = data_types
+            .iter()
+            .map(|typ| Ok(pg_type::oid_type_and_size(typ)?.0))
+            .collect::<Result<Vec<_>>>()?;
+
+        let prepared = self.prepare(statement, param_types)?;
+        self.prepared_statements
+            .insert(name.value.clone(), prepared);
+
+        Ok(())
+    }
+
+    /// Create a new prepared statement from a Parse message of the extended query protocol. The
+    /// unnamed statement is replaced if it already exists.
+    pub fn exec_parse(&mut self, name: &str, query: &str, param_types: &[Oid]) -> Result<()> {
+        if !name.is_empty() && self.prepared_statements.contains_key(name) {
+            bail!(PgError::new(
+                SqlState::DuplicatePreparedStatement,
+                format!("prepared statement \"{}\" already exists", name)
+            ));
+        }
+
+        let mut statements = parse_sql(query)?;
+        if statements.len() != 1 {
+            bail!(PgError::new(
+                SqlState::SyntaxError,
+                "cannot insert multiple commands into a prepared statement"
+            ));
+        }
+
+        let prepared = self.prepare(statements.remove(0), param_types.to_vec())?;
+        self.prepared_statements.insert(name.to_string(), prepared);
+
+        Ok(())
+    }
+
+    /// Return a new prepared statement for the given statement. The type of parameters that are
+    /// not declared on param_types are inferred from the columns of the statement relations.
+    fn prepare(
+        &self,
+        statement: ast::Statement,
+        param_types: Vec<Oid>,
+    ) -> Result<PreparedStatement> {
+        let mut tuple_descs = Vec::new();
+        for rel_name in statement_relations(&statement)? {
+            tuple_descs.push(catalog::tuple_desc_from_relation(
+                &self.buffer_pool,
+                &self.config.database,
+                &self.search_path(),
+                &rel_name,
+            )?);
+        }
+
+        let mut prepared = PreparedStatement::new(statement, param_types)?;
+        prepared.infer_param_types(&tuple_descs)?;
+        Ok(prepared)
+    }
+
+    /// Create a new portal from a Bind message of the extended query protocol, binding the given
+    /// prepared statement with the parameter values. The unnamed portal is replaced if it
+    /// already exists.
+    pub fn exec_bind(
+        &mut self,
+        portal: &str,
+        statement: &str,
+        param_formats: &[i16],
+        params: &[Option<Vec<u8>>],
+        result_formats: Vec<i16>,
+    ) -> Result<()> {
+        if !portal.is_empty() && self.cursor_exists(portal) {
+            bail!(PgError::new(
+                SqlState::DuplicateCursor,
+                format!("cursor \"{}\" already exists", portal)
+            ));
+        }
+
+        let prepared = self.prepared(statement)?;
+        let param_formats = format_codes(param_formats, params.len()).ok_or_else(|| {
+            anyhow!(PgError::new(
+                SqlState::ProtocolViolation,
+                format!(
+                    "bind message has {} parameter formats but {} parameters",
+                    param_formats.len(),
+                    params.len()
+                )
+            ))
+        })?;
+
+        let values = params
+            .iter()
+            .zip(param_formats)
+            .enumerate()
+            .map(|(i, (param, format))| match (param, format) {
+                (None, _) => Ok(Value::Null),
+                (Some(param), FORMAT_TEXT) => {
+                    Ok(Value::SingleQuotedString(String::from_utf8(param.clone())?))
+                }
+                (Some(param), FORMAT_BINARY) => {
+                    let typ = prepared
+                        .param_types()
+                        .get(i)
+                        .copied()
+                        .unwrap_or(INVALID_OID);
+                    encode::value_from_binary(param, typ)
+                }
+                (_, format) => bail!(PgError::new(
+                    SqlState::ProtocolViolation,
+                    format!("unsupported format code: {}", format)
+                )),
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let statement = prepared.bind(statement, &values)?;
+        self.portals.insert(
+            portal.to_string(),
+            Portal {
+                statement,
+                result_formats,
+                cursor: None,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Return the type of the parameters and the description of the rows returned by the given
+    /// prepared statement. The row description is None if the statement don't return rows.
+    pub fn describe_statement(&self, name: &str) -> Result<(Vec<Oid>, Option<RowDescriptor>)> {
+        let prepared = self.prepared(name)?;
+
+        // The returned rows don't depend on parameter values, so the statement is described
+        // with all parameters set to NULL.
+        let params = vec![Value::Null; prepared.param_types().len()];
+        let statement = prepared.bind(name, &params)?;
+
+        Ok((prepared.param_types().to_vec(), self.describe(&statement)?))
+    }
+
+    /// Return the description of the rows returned by the given portal, using the result
+    /// formats of the portal. None if the portal don't return rows.
+    pub fn describe_portal(&self, name: &str) -> Result<Option<RowDescriptor>> {
+        let portal = self.portal(name)?;
+        self.describe(&portal.statement)?
+            .map(|desc| desc.with_formats(&portal.result_formats))
+            .transpose()
+    }
+
+    /// Return the description of the rows returned by the given statement, without executing it.
+    fn describe(&self, statement: &ast::Statement) -> Result<Option<RowDescriptor>> {
+        if let Some((ast::Statement::Insert { table_name, .. }, returning)) =
+            insert::insert_returning(statement)
+        {
+            let rel_name = RelationName::from_object_name(table_name)?;
+            let tuple_desc = catalog::tuple_desc_from_relation(
+                &self.buffer_pool,
+                &self.config.database,
+                &self.search_path(),
+                &rel_name,
+            )?;
+            let (projection, _) = returning_projection(&rel_name.name, &tuple_desc, returning)?;
+            return Ok(Some(RowDescriptor::from(&TupleDesc { attrs: projection })));
+        }
+
+        match statement {
+            ast::Statement::Query(query) => {
+                let plan = self.create_plan(query)?;
+                Ok(plan.tuple_desc().map(|desc| RowDescriptor::from(&desc)))
+            }
+            ast::Statement::ShowVariable { variable } => Ok(Some(self.exec_show(variable)?.desc)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Return the prepared statement with the given name.
+    fn prepared(&self, name: &str) -> Result<&PreparedStatement> {
+        self.prepared_statements.get(name).ok_or_else(|| {
+            anyhow!(PgError::new(
+                SqlState::InvalidSqlStatementName,
+                format!("prepared statement \"{}\" does not exist", name)
+            ))
+        })
+    }
+
+    /// Return the portal with the given name.
+    pub fn portal(&self, name: &str) -> Result<&Portal> {
+        self.portals.get(name).ok_or_else(|| {
+            anyhow!(PgError::new(
+                SqlState::InvalidCursorName,
+                format!("portal \"{}\" does not exist", name)
+            ))
+        })
+    }
+
+    /// Return true if a portal or a cursor with the given name exists.
+    fn cursor_exists(&self, name: &str) -> bool {
+        self.portals.contains_key(name) || self.cursors.contains_key(name)
+    }
+
+    /// Execute a statement of cursors. Return the fetched rows for FETCH, and None for the other
+    /// statements.
+    pub fn exec_cursor_statement(
+        &mut self,
+        statement: &CursorStatement,
+    ) -> Result<Option<PGResult>> {
+        match statement {
+            CursorStatement::Declare { name, query } => {
+                if self.cursor_exists(name) {
+                    bail!(PgError::new(
+                        SqlState::DuplicateCursor,
+                        format!("cursor \"{}\" already exists", name)
+                    ));
+                }
+                let plan = self.create_plan(query)?;
+                self.check_plan_privileges(&plan)?;
+                let cursor = Cursor::new(plan, self.executor())?;
+                self.cursors.insert(name.clone(), cursor);
+                Ok(None)
+            }
+            CursorStatement::Fetch { name, count } => {
+                let table = self.cursor(name)?.fetch(*count)?;
+                Ok(Some(PGResult::from(table)))
+            }
+            CursorStatement::Close { name: Some(name) } => {
+                self.cursor(name)?;
+                self.cursors.remove(name);
+                Ok(None)
+            }
+            CursorStatement::Close { name: None } => {
+                self.cursors.clear();
+                Ok(None)
+            }
+        }
+    }
+
+    /// Return the cursor with the given name.
+    fn cursor(&mut self, name: &str) -> Result<&mut Cursor> {
+        self.cursors.get_mut(name).ok_or_else(|| {
+            anyhow!(PgError::new(
+                SqlState::InvalidCursorName,
+                format!("cursor \"{}\" does not exist", name)
+            ))
+        })
+    }
+
+    /// Execute the query of the given portal returning at most max_rows rows, or all remaining
+    /// rows if max_rows is None. The query is suspended when the limit is reached, and the next
+    /// execution of the portal continues from the last returned row. Return the rows and true if
+    /// the portal was suspended.
+    pub fn exec_portal_rows(
+        &mut self,
+        name: &str,
+        max_rows: Option<u64>,
+    ) -> Result<(PGResult, bool)> {
+        let mut cursor = match self
+            .portals
+            .get_mut(name)
+            .and_then(|portal| portal.cursor.take())
+        {
+            Some(cursor) => cursor,
+            None => match &self.portal(name)?.statement {
+                ast::Statement::Query(query) => {
+                    let plan = self.create_plan(query)?;
+                    self.check_plan_privileges(&plan)?;
+                    Cursor::new(plan, self.executor())?
+                }
+                statement => bail!(SQLError::Unsupported(format!("row limit on {}", statement))),
+            },
+        };
+
+        let table = cursor.fetch(max_rows);
+        if let Some(portal) = self.portals.get_mut(name) {
+            portal.cursor = Some(cursor);
+        }
+        let table = table?;
+
+        let suspended = max_rows == Some(table.values.len() as u64);
+        Ok((PGResult::from(table), suspended))
+    }
+
+    /// Remove the prepared statement with the given name, if exists.
+    pub fn close_statement(&mut self, name: &str) {
+        self.prepared_statements.remove(name);
+    }
+
+    /// Remove the portal with the given name, if exists.
+    pub fn close_portal(&mut self, name: &str) {
+        self.portals.remove(name);
+    }
+
+    /// Return the statement of the prepared statement with the given name bound with the given
+    /// parameter values, ready to be executed.
+    pub fn bind_prepared(&self, name: &ast::Ident, parameters: &[Expr]) -> Result<ast::Statement> {
+        let prepared = self.prepared(&name.value)?;
+
+        let params = parameters
+            .iter()
+            .map(|param| match param {
+                Expr::Value(value) => Ok(value.clone()),
+                _ => bail!(SQLError::Unsupported(param.to_string())),
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        prepared.bind(&name.value, &params)
+    }
+
+    /// Change the value of the given session variable.
+    pub fn exec_set(
+        &mut self,
+        variable: &ast::ObjectName,
+        values: &[ast::SetVariableValue],
+    ) -> Result<()> {
+        self.config
+            .variables
+            .set_values(&variable.to_string(), values)
+    }
+
+    /// Return the value of the given session variable, or all variables for SHOW ALL.
+    pub fn exec_show(&self, variable: &[ast::Ident]) -> Result<PGResult> {
+        let name = variable
+            .iter()
+            .map(|ident| ident.value.as_str())
+            .collect::<Vec<_>>()
+            .join(".");
+
+        if name.eq_ignore_ascii_case("all") {
+            let tuples = self
+                .config
+                .variables
+                .all()
+                .into_iter()
+                .map(|(name, value)| text_row(&[name, value]))
+                .collect::<Result<Vec<_>>>()?;
+            return Ok(PGResult::text(&["name", "setting"], tuples));
+        }
+
+        let value = self.config.variables.get(&name)?;
+        let name = self.config.variables.name(&name)?;
+        Ok(PGResult::text(&[name], vec![text_row(&[value])?]))
+    }
+
+    /// Change the given session variables back to the default values.
+    pub fn exec_reset(&mut self, reset: &Reset) -> Result<()> {
+        match reset {
+            Reset::All => {
+                self.config.variables.reset_all();
+                self.current_role = self.config.session_role;
+                Ok(())
+            }
+            Reset::Variable(name) if name.eq_ignore_ascii_case("role") => {
+                self.current_role = self.config.session_role;
+                Ok(())
+            }
+            Reset::Variable(name) => self.config.variables.reset(name),
+        }
+    }
+
+    /// Change the role whose privileges are checked by the next statements, or go back to the
+    /// session role if the role name is None. The session role must be a superuser or a member
+    /// of the new role.
+    pub fn exec_set_role(&mut self, role_name: &Option<ast::Ident>) -> Result<()> {
+        self.current_role = match role_name {
+            Some(name) => {
+                let role = self.get_role(&name.value)?;
+                let session = RolePrivileges::new(&self.buffer_pool, &self.config.session_role)?;
+                if !session.has_privileges_of(&role.oid) {
+                    bail!(PgError::new(
+                        SqlState::InsufficientPrivilege,
+                        format!("permission denied to set role \"{}\"", name.value)
+                    ));
+                }
+                role.oid
+            }
+            None => self.config.session_role,
+        };
+        Ok(())
+    }
+
+    /// Return the oid of the role that started the session.
+    pub fn session_role(&self) -> Oid {
+        self.config.session_role
+    }
+
+    /// Return the privileges of the current role.
+    fn privileges(&self) -> Result<RolePrivileges> {
+        RolePrivileges::new(&self.buffer_pool, &self.current_role)
+    }
+
+    /// Return the role with the given name.
+    fn get_role(&self, rolname: &str) -> Result<PgAuthId> {
+        catalog::get_pg_authid(&self.buffer_pool, rolname).map_err(|err| {
+            match err.downcast_ref::<catalog::Error>() {
+                Some(catalog::Error::RoleNotFound(_)) => anyhow!(PgError::new(
+                    SqlState::UndefinedObject,
+                    format!("role \"{}\" does not exist", rolname)
+                )),
+                _ => err,
+            }
+        })
+    }
+
+    /// Execute a statement of roles. Only superusers can create roles and change the members of
+    /// roles.
+    pub fn exec_role_statement(&self, statement: &RoleStatement) -> Result<()> {
+        let superuser = self.privileges()?.is_superuser();
+
+        // Roles are changed one statement at a time, so memberships are never circular.
+        let tag = LockTag {
+            database: INVALID_OID,
+            relation: pg_authid::RELATION_OID,
+        };
+        let _lock = self.buffer_pool.lock_manager().acquire(
+            tag,
+            LockMode::AccessExclusive,
+            &self.lock_owner,
+        )?;
+
+        match statement {
+            RoleStatement::CreateRole { name, options } => {
+                if !superuser {
+                    bail!(PgError::new(
+                        SqlState::InsufficientPrivilege,
+                        "permission denied to create role"
+                    ));
+                }
+                catalog::role::role_create(&self.buffer_pool, name, options)?;
+            }
+            RoleStatement::GrantRole { role, member } => {
+                if !superuser {
+                    bail!(PgError::new(
+                        SqlState::InsufficientPrivilege,
+                        format!("permission denied to grant role \"{}\"", role)
+                    ));
+                }
+                catalog::role::add_role_member(
+                    &self.buffer_pool,
+                    &self.get_role(role)?,
+                    &self.get_role(member)?,
+                )?;
+            }
+            RoleStatement::RevokeRole { role, member } => {
+                if !superuser {
+                    bail!(PgError::new(
+                        SqlState::InsufficientPrivilege,
+                        format!("permission denied to revoke role \"{}\"", role)
+                    ));
+                }
+                catalog::role::remove_role_member(
+                    &self.buffer_pool,
+                    &self.get_role(role)?.oid,
+                    &self.get_role(member)?.oid,
+                )?;
+            }
+        }
+
+        self.flush_wal()
+    }
+
+    /// Grant the given privileges on the given tables to the given roles, where PUBLIC means all
+    /// roles. Only the owners of the tables can change their privileges, so privileges can not
+    /// be granted WITH GRANT OPTION.
+    pub fn exec_grant(
+        &self,
+        privileges: &ast::Privileges,
+        objects: &ast::GrantObjects,
+        grantees: &[ast::Ident],
+        with_grant_option: bool,
+    ) -> Result<()> {
+        if with_grant_option {
+            bail!(SQLError::Unsupported(String::from("WITH GRANT OPTION")));
+        }
+        self.change_privileges(privileges, objects, grantees, true)
+    }
+
+    /// Revoke the given privileges on the given tables from the given roles, like exec_grant.
+    pub fn exec_revoke(
+        &self,
+        privileges: &ast::Privileges,
+        objects: &ast::GrantObjects,
+        grantees: &[ast::Ident],
+    ) -> Result<()> {
+        self.change_privileges(privileges, objects, grantees, false)
+    }
+
+    fn change_privileges(
+        &self,
+        privileges: &ast::Privileges,
+        objects: &ast::GrantObjects,
+        grantees: &[ast::Ident],
+        grant: bool,
+    ) -> Result<()> {
+        let mode = acl_mode(privileges)?;
+        let tables = match objects {
+            ast::GrantObjects::Tables(tables) => tables,
+            _ => bail!(SQLError::Unsupported(format!("privileges on {}", objects))),
+        };
+        let grantees = grantees
+            .iter()
+            .map(|grantee| {
+                if grantee.quote_style.is_none() && grantee.value.eq_ignore_ascii_case("public") {
+                    Ok(ACL_ID_PUBLIC)
+                } else {
+                    Ok(self.get_role(&grantee.value)?.oid)
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let role_privileges = self.privileges()?;
+        for table in tables {
+            // The pg_class tuple is replaced, so the table can not be used at the same time.
+            let (pg_class_rel, _lock) = self.lock_relation(
+                &RelationName::from_object_name(table)?,
+                LockMode::AccessExclusive,
+            )?;
+            role_privileges.check_owner(&pg_class_rel)?;
+
+            let mut acl = Acl::of_relation(&pg_class_rel)?;
+            for grantee in &grantees {
+                if grant {
+                    acl.grant(*grantee, pg_class_rel.relowner, mode);
+                } else {
+                    acl.revoke(*grantee, mode);
+                }
+            }
+            update_relation_acl(
+                &self.buffer_pool,
+                &self.config.database,
+                &pg_class_rel,
+                &acl,
+            )?;
+        }
+
+        self.flush_wal()
+    }
+
+    /// Return an error if the current role can not read the relations scanned by the given
+    /// plan.
+    fn check_plan_privileges(&self, plan: &Plan) -> Result<()> {
+        let privileges = self.privileges()?;
+        if privileges.is_superuser() {
+            return Ok(());
+        }
+        for relation in plan.scanned_relations() {
+            privileges.check_relation(&self.relation_pg_class(relation)?, ACL_SELECT)?;
+        }
+        Ok(())
+    }
+
+    /// Return the pg_class tuple of the given relation opened by a plan.
+    fn relation_pg_class(&self, relation: &Relation) -> Result<PgClass> {
+        catalog::get_pg_class_relation_by_oid(
+            &self.buffer_pool,
+            &self.config.database,
+            &relation.rel_name,
+            &relation.locator.oid,
+        )
+    }
+
+    /// Return the session configuration variables.
+    pub fn variables(&self) -> &SessionVariables {
+        &self.config.variables
+    }
+
+    /// Return the schemas where unqualified relation names are searched.
+    fn search_path(&self) -> Vec<String> {
+        self.config.variables.search_path()
+    }
+
+    /// Remove the prepared statement with the given name.
+    pub fn exec_deallocate(&mut self, name: &ast::Ident) -> Result<()> {
+        if self.prepared_statements.remove(&name.value).is_none() {
+            bail!(PgError::new(
+                SqlState::InvalidSqlStatementName,
+                format!("prepared statement \"{}\" does not exist", name.value)
+            ));
+        }
+        Ok(())
+    }
+
+    /// Return the relations listed by a psql meta-command.
+    pub fn exec_list_relations(&self, list: &ListRelations) -> Result<PGResult> {
+        let db_oid = &self.config.database;
+        let relations = catalog::get_pg_class_relations(&self.buffer_pool, db_oid)?;
+        let indexes = catalog::get_pg_indexes(&self.buffer_pool, db_oid)?;
+
+        let mut schemas = HashMap::new();
+        let mut owners = HashMap::new();
+        for relation in &relations {
+            if let Entry::Vacant(entry) = schemas.entry(relation.relnamespace) {
+                entry.insert(namespace::get_namespace_name(
+                    &self.buffer_pool,
+                    db_oid,
+                    &relation.relnamespace,
+                )?);
+            }
+            if let Entry::Vacant(entry) = owners.entry(relation.relowner) {
+                entry.insert(
+                    catalog::get_pg_authid_by_oid(&self.buffer_pool, &relation.relowner)?.rolname,
+                );
+            }
+        }
+
+        let mut rows = Vec::new();
+        for relation in &relations {
+            let schema = schemas[&relation.relnamespace].as_str();
+            let index = indexes
+                .iter()
+                .find(|index| index.indexrelid == relation.oid);
+            let (relkind, kind) = match index {
+                Some(_) => ('i', "index"),
+                None => ('r', "table"),
+            };
+            if !list.lists(relkind) || !list.matches(schema, &relation.relname) {
+                continue;
+            }
+
+            let mut row = vec![
+                schema,
+                relation.relname.as_str(),
+                kind,
+                owners[&relation.relowner].as_str(),
+            ];
+            if list.with_table {
+                let table = index.and_then(|index| {
+                    relations
+                        .iter()
+                        .find(|relation| relation.oid == index.indrelid)
+                });
+                row.push(table.map_or("", |table| table.relname.as_str()));
+            }
+            rows.push(row);
+        }
+        rows.sort();
+
+        let mut columns = vec!["Schema", "Name", "Type", "Owner"];
+        if list.with_table {
+            columns.push("Table");
+        }
+        let tuples = rows
+            .iter()
+            .map(|row| text_row(row))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(PGResult::text(&columns, tuples))
+    }
+
+    /// Return the columns of the given relation, as listed by the psql \d meta-command.
+    pub fn exec_describe_relation(&self, rel_name: &str) -> Result<PGResult> {
+        let tuple_desc = catalog::tuple_desc_from_relation(
+            &self.buffer_pool,
+            &self.config.database,
+            &self.search_path(),
+            &RelationName::parse(rel_name),
+        )?;
+
+        let tuples = tuple_desc
+            .attrs
+            .iter()
+            .map(|attr| {
+                let typ = attr.type_name();
+                let nullable = if attr.attnotnull { "not null" } else { "" };
+                text_row(&[&attr.attname, &typ, nullable, &attr.attdefault])
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(PGResult::text(
+            &["Column", "Type", "Nullable", "Default"],
+            tuples,
+        ))
+    }
+
+    /// Return all databases, as listed by the psql \l meta-command. All databases are owned by
+    /// the bootstrap superuser.
+    pub fn exec_list_databases(&self) -> Result<PGResult> {
+        let owner = catalog::get_pg_authid_by_oid(&self.buffer_pool, &BOOTSTRAP_SUPERUSER_ID)?;
+        let mut databases = catalog::get_pg_databases(&self.buffer_pool)?;
+        databases.sort_by(|a, b| a.datname.cmp(&b.datname));
+
+        let tuples = databases
+            .iter()
+            .map(|database| text_row(&[&database.datname, &owner.rolname]))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(PGResult::text(&["Name", "Owner"], tuples))
+    }
+
+    /// Create a new database with a copy of the template database of the given statement, or of
+    /// the default template if it has none. Nothing is done if the statement has IF NOT EXISTS and
+    /// a database with the same name already exists.
+    ///
+    /// Databases are created one at a time, so two databases with the same name are never
+    /// created. The template must not be changed while it is copied, so the caller must ensure
+    /// that no other connection is using it.
+    pub fn exec_create_database(&self, statement: &CreateDatabaseStatement) -> Result<()> {
+        let datname = &statement.name;
+        let template = statement
+            .template
+            .as_deref()
+            .unwrap_or(database::DEFAULT_TEMPLATE);
+
+        let tag = LockTag {
+            database: INVALID_OID,
+            relation: pg_database::RELATION_OID,
+        };
+        let _lock = self.buffer_pool.lock_manager().acquire(
+            tag,
+            LockMode::AccessExclusive,
+            &self.lock_owner,
+        )?;
+
+        let databases = catalog::get_pg_databases(&self.buffer_pool)?;
+        if databases
+            .iter()
+            .any(|database| &database.datname == datname)
+        {
+            if statement.if_not_exists {
+                return Ok(());
+            }
+            bail!(PgError::new(
+                SqlState::DuplicateDatabase,
+                format!("database \"{}\" already exists", datname)
+            ));
+        }
+        let template_oid = match databases
+            .iter()
+            .find(|database| database.datname == template)
+        {
+            Some(database) => database.oid,
+            None => bail!(PgError::new(
+                SqlState::InvalidCatalogName,
+                format!("template database \"{}\" does not exist", template)
+            )),
+        };
+
+        catalog::create_database(&self.buffer_pool, datname, &template_oid)?;
+        self.flush_wal()
+    }
+
+    /// Create a new schema on the connected database. Nothing is done if if_not_exists is true
+    /// and a schema with the same name already exists.
+    pub fn exec_create_schema(&self, name: &ast::ObjectName, if_not_exists: bool) -> Result<()> {
+        let nspname = &name.0[0].value;
+
+        let tag = LockTag {
+            database: self.config.database,
+            relation: pg_namespace::RELATION_OID,
+        };
+        let _lock = self.buffer_pool.lock_manager().acquire(
+            tag,
+            LockMode::AccessExclusive,
+            &self.lock_owner,
+        )?;
+
+        match namespace::namespace_create(&self.buffer_pool, &self.config.database, nspname) {
+            Ok(_) => self.flush_wal(),
+            Err(err) => match err.downcast_ref::<catalog::Error>() {
+                Some(catalog::Error::NamespaceAlreadyExists(_)) if if_not_exists => Ok(()),
+                _ => Err(err),
+            },
+        }
+    }
+
+    /// Create a plan for the given query on the connected database.
+    fn create_plan(&self, query: &ast::Query) -> Result<Plan> {
+        Plan::create(
+            &self.buffer_pool,
+            &self.config.database,
+            &self.search_path(),
+            &self.config.virtual_tables,
+            &self.lock_owner,
+            query,
+        )
+    }
+
+    /// Return a new executor for plans of this connection, that can be interrupted by a cancel
+    /// request.
+    fn executor(&self) -> Executor {
+        Executor::new(&self.buffer_pool)
+            .with_cancel_flag(self.canceled.clone())
+            .with_session_variables(&self.config.variables)
+    }
+
+    pub fn exec_query(&self, query: &Box<ast::Query>) -> Result<PGResult> {
+        let mut plan = self.create_plan(query)?;
+        self.check_plan_privileges(&plan)?;
+        let executor = self.executor();
+        let (tuple_table, stats) = executor.exec_with_stats(&mut plan)?;
+        Ok(PGResult {
+            stats,
+            ..PGResult::from(tuple_table)
+        })
+    }
+
+    /// Delete all tuples from the given table that satisfy the given selection and return the
+    /// number of deleted tuples. The current role must also be able to read the table if there
+    /// is a selection.
+    pub fn exec_delete(
+        &self,
+        table_name: &ast::ObjectName,
+        selection: &Option<Expr>,
+    ) -> Result<u64> {
+        let mut plan = Plan::create_delete(
+            &self.buffer_pool,
+            &self.config.database,
+            &self.search_path(),
+            &self.lock_owner,
+            table_name,
+            selection,
+        )?;
+        if let PlanNodeType::Delete { state } = &plan.node_type {
+            let mode = match selection {
+                Some(_) => ACL_DELETE | ACL_SELECT,
+                None => ACL_DELETE,
+            };
+            self.privileges()?
+                .check_relation(&self.relation_pg_class(&state.relation)?, mode)?;
+        }
+        let executor = self.executor();
+        let deleted = executor.exec_delete(&mut plan)?;
+        self.flush_wal()?;
+        Ok(deleted)
+    }
+
+    /// Insert the rows of the VALUES source of the given INSERT statement on its table and return
+    /// the given RETURNING items evaluated against each inserted row. Each row is inserted as a
+    /// new tuple, unless it conflicts with another row on a unique index of the table and the
+    /// statement has an ON CONFLICT clause, which skips or updates the conflicting row.
+    ///
+    /// There is a returned tuple for each inserted or updated row, so the number of affected rows
+    /// is the number of returned tuples, which have no values when there is no RETURNING item.
+    pub fn exec_insert(
+        &self,
+        insert: &ast::Statement,
+        returning: &[ast::SelectItem],
+    ) -> Result<PGResult> {
+        let (table_name, columns, source) = match insert {
+            ast::Statement::Insert {
+                table_name,
+                columns,
+                source,
+                ..
+            } => (table_name, columns, source),
+            _ => bail!(SQLError::Unsupported(insert.to_string())),
+        };
+        let on_conflict = insert::on_conflict(insert);
+
+        let (pg_class_rel, _lock) = self.lock_relation(
+            &RelationName::from_object_name(table_name)?,
+            LockMode::RowExclusive,
+        )?;
+        let privileges = self.privileges()?;
+        privileges.check_relation(&pg_class_rel, ACL_INSERT)?;
+        if let Some(insert::OnConflict::DoUpdate { .. }) = on_conflict {
+            privileges.check_relation(&pg_class_rel, ACL_UPDATE)?;
+        }
+        // Like Postgres, the inserted rows are only returned to roles that can read them.
+        if !returning.is_empty() {
+            privileges.check_relation(&pg_class_rel, ACL_SELECT)?;
+        }
+        let rel_name = pg_class_rel.relname.clone();
+
+        let rel = access::open_relation(
+            pg_class_rel.oid,
+            pg_class_rel.reltablespace,
+            &self.config.database,
+            &rel_name,
+        );
+
+        let values = match &source.body {
+            ast::SetExpr::Values(values) => values,
+            _ => bail!(SQLError::Unsupported(source.to_string())),
+        };
+
+        let tuple_desc = catalog::tuple_desc_from_relation_oid(
+            &self.buffer_pool,
+            &self.config.database,
+            &pg_class_rel.oid,
+        )?;
+
+        let (projection, exprs) = returning_projection(&rel_name, &tuple_desc, returning)?;
+
+        // Build the tuples of all rows, and the values returned for them, before inserting any
+        // of them, so that an invalid or conflicting row don't leave the previous rows inserted.
+        let rows = values
+            .0
+            .iter()
+            .map(|row| self.tuple_datums(&rel_name, &tuple_desc, columns, row))
+            .collect::<Result<Vec<_>>>()?;
+
+        let table_am = tableam::table_am(pg_class_rel.relam)?;
+        let resolved = insert::resolve_conflicts(
+            &self.buffer_pool,
+            &self.lock_owner,
+            &rel,
+            table_am,
+            &tuple_desc,
+            on_conflict,
+            rows,
+        )?;
+
+        let mut tuples = Vec::with_capacity(resolved.values.len());
+        let mut returned = Vec::with_capacity(resolved.values.len());
+        for datums in resolved.values {
+            returned.push(
+                exprs
+                    .iter()
+                    .map(|expr| expr.eval(&datums)?.to_datum())
+                    .collect::<Result<Vec<_>>>()?,
+            );
+            tuples.push(HeapTuple::from_datums(datums, &tuple_desc)?);
+        }
+
+        for tid in &resolved.deleted {
+            table_am.delete(&self.buffer_pool, &rel, tid)?;
+        }
+        table_am.multi_insert(&self.buffer_pool, &rel, &tuples)?;
+
+        self.flush_wal()?;
+        Ok(PGResult::from(TupleTable {
+            tuple_desc: Arc::new(TupleDesc { attrs: projection }),
+            values: returned,
+        }))
+    }
+
+    /// Return the attributes of the given table that are read or written by a COPY statement,
+    /// in the same order of the given columns. All attributes are returned if there is no
+    /// column.
+    pub fn copy_attributes(
+        &self,
+        table_name: &ast::ObjectName,
+        columns: &[ast::Ident],
+    ) -> Result<Vec<PgAttribute>> {
+        let rel_name = RelationName::from_object_name(table_name)?;
+        let tuple_desc = catalog::tuple_desc_from_relation(
+            &self.buffer_pool,
+            &self.config.database,
+            &self.search_path(),
+            &rel_name,
+        )?;
+
+        if columns.is_empty() {
+            return Ok(tuple_desc.attrs);
+        }
+
+        columns
+            .iter()
+            .map(|column| {
+                match tuple_desc
+                    .attrs
+                    .iter()
+                    .find(|attr| attr.attname == column.value)
+                {
+                    Some(attr) => Ok(attr.clone()),
+                    None => bail!(PgError::new(
+                        SqlState::UndefinedColumn,
+                        format!(
+                            "column \"{}\" of relation \"{}\" does not exist",
+                            column.value, rel_name.name
+                        )
+                    )),
+                }
+            })
+            .collect()
+    }
 
-pub mod encode;
+    /// Insert the given rows of a COPY FROM statement on the given table and return the number
+    /// of inserted rows. Values of the columns that are not copied are set to their default.
+    pub fn exec_copy_from(
+        &self,
+        table_name: &ast::ObjectName,
+        columns: &[ast::Ident],
+        rows: &[CopyRow],
+    ) -> Result<u64> {
+        let (pg_class_rel, _lock) = self.lock_relation(
+            &RelationName::from_object_name(table_name)?,
+            LockMode::RowExclusive,
+        )?;
+        self.privileges()?
+            .check_relation(&pg_class_rel, ACL_INSERT)?;
+        let rel_name = pg_class_rel.relname.clone();
+        let rel = access::open_relation(
+            pg_class_rel.oid,
+            pg_class_rel.reltablespace,
+            &self.config.database,
+            &rel_name,
+        );
+        let tuple_desc = catalog::tuple_desc_from_relation_oid(
+            &self.buffer_pool,
+            &self.config.database,
+            &pg_class_rel.oid,
+        )?;
+        let attrs = self.copy_attributes(table_name, columns)?;
+        let columns = columns.to_vec();
+        let table_am = tableam::table_am(pg_class_rel.relam)?;
 
-/// Errors related with a SQL command
-#[derive(Debug, thiserror::Error)]
-pub enum SQLError {
-    /// Unsupported SQL operation.
-    #[error("unsuported operation {0}")]
-    Unsupported(String),
-}
+        let mut tuples = Vec::with_capacity(rows.len());
+        for row in rows {
+            if row.len() > attrs.len() {
+                bail!(PgError::new(
+                    SqlState::BadCopyFileFormat,
+                    "extra data after last expected column"
+                ));
+            }
+            if let Some(attr) = attrs.get(row.len()) {
+                bail!(PgError::new(
+                    SqlState::BadCopyFileFormat,
+                    format!("missing data for column \"{}\"", attr.attname)
+                ));
+            }
 
-/// An executor config holds per connection configuration values.
-pub struct ExecutorConfig {
-    /// Oid of database that is connect in.
-    pub database: Oid,
-}
+            let row = row
+                .iter()
+                .zip(&attrs)
+                .map(|(value, attr)| match value {
+                    Some(value) => Ok(Expr::Value(prepared::coerce_param(
+                        &Value::SingleQuotedString(value.clone()),
+                        attr.atttypid,
+                    )?)),
+                    None => Ok(Expr::Value(Value::Null)),
+                })
+                .collect::<Result<Vec<_>>>()?;
 
-/// A connection executor is in charge of executing queries on a give database connection.
-pub struct ConnectionExecutor {
-    /// Configuration options for a database connection.
-    config: ExecutorConfig,
+            tuples.push(self.tuple_datums(&rel_name, &tuple_desc, &columns, &row)?);
+        }
 
-    /// Buffer pool shared with the query planner and executor.
-    buffer_pool: BufferPool,
-}
+        // Copied rows are rejected if they conflict on a unique index, like inserted rows.
+        let resolved = insert::resolve_conflicts(
+            &self.buffer_pool,
+            &self.lock_owner,
+            &rel,
+            table_am,
+            &tuple_desc,
+            None,
+            tuples,
+        )?;
+        let tuples = resolved
+            .values
+            .into_iter()
+            .map(|datums| HeapTuple::from_datums(datums, &tuple_desc))
+            .collect::<Result<Vec<_>>>()?;
+        table_am.multi_insert(&self.buffer_pool, &rel, &tuples)?;
 
-impl ConnectionExecutor {
-    pub fn new(config: ExecutorConfig, buffer_pool: BufferPool) -> Self {
-        Self {
-            config,
-            buffer_pool,
+        // The rows are made durable on the write-ahead log at once, instead of once per row.
+        self.flush_wal()?;
+        Ok(rows.len() as u64)
+    }
+
+    /// Return all rows of the given columns of a table for a COPY TO statement.
+    pub fn exec_copy_to(
+        &self,
+        table_name: &ast::ObjectName,
+        columns: &[ast::Ident],
+    ) -> Result<PGResult> {
+        // Make sure that the columns exist before building the query.
+        self.copy_attributes(table_name, columns)?;
+
+        let projection = if columns.is_empty() {
+            String::from("*")
+        } else {
+            columns
+                .iter()
+                .map(|column| column.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let query = format!("SELECT {} FROM {}", projection, table_name);
+
+        match Parser::parse_sql(&PostgreSqlDialect {}, &query)?.pop() {
+            Some(ast::Statement::Query(query)) => self.exec_query(&query),
+            _ => bail!("unexpected statement for COPY {}", table_name),
         }
     }
 
-    pub fn exec_query(&self, query: &Box<ast::Query>) -> Result<PGResult> {
-        let mut plan = Plan::create(&self.buffer_pool, &self.config.database, query)?;
-        let executor = Executor::new();
-        let tuple_table = executor.exec(&mut plan)?;
-        Ok(PGResult::from(tuple_table))
+    /// Return the datums of a new tuple of the given relation with the values of an INSERT row,
+    /// checking the constraints of the relation attributes.
+    fn tuple_datums(
+        &self,
+        rel_name: &str,
+        tuple_desc: &TupleDesc,
+        columns: &Vec<ast::Ident>,
+        row: &Vec<Expr>,
+    ) -> Result<Vec<NullableDatum>> {
+        let mut heap_values = Vec::new();
+        for (attr, value) in tuple_values_from_insert_row(columns, row, tuple_desc)? {
+            if attr.attnotnull && value == Value::Null {
+                bail!(PgError::new(
+                    SqlState::NotNullViolation,
+                    format!(
+                        "null value in column \"{}\" of relation \"{}\" violates not-null constraint",
+                        attr.attname, rel_name
+                    )
+                ));
+            }
+            encode(&mut heap_values, &value, attr)?;
+        }
+        Ok(heap_values)
     }
 
-    pub fn exec_insert(
+    /// Create a new B-tree index with the given name on a single column of the given table. Unique
+    /// indexes also reject the inserted rows that duplicate the value of the column of a live row.
+    pub fn exec_create_index(
         &self,
+        name: &ast::ObjectName,
         table_name: &ast::ObjectName,
-        columns: &Vec<ast::Ident>,
-        source: &Box<ast::Query>,
+        columns: &[ast::OrderByExpr],
+        unique: bool,
     ) -> Result<()> {
-        let rel_name = table_name.0[0].to_string();
-        let pg_class_rel =
-            catalog::get_pg_class_relation(&self.buffer_pool, &self.config.database, &rel_name)?;
+        let column = match columns {
+            [ast::OrderByExpr {
+                expr: Expr::Identifier(column),
+                ..
+            }] => column,
+            _ => bail!(SQLError::Unsupported(String::from(
+                "index with multiple columns or expressions"
+            ))),
+        };
+
+        let (pg_class_rel, _lock) = self.lock_relation(
+            &RelationName::from_object_name(table_name)?,
+            LockMode::Share,
+        )?;
+        self.privileges()?.check_owner(&pg_class_rel)?;
+        let rel_name = pg_class_rel.relname.clone();
+        let tuple_desc = catalog::tuple_desc_from_relation_oid(
+            &self.buffer_pool,
+            &self.config.database,
+            &pg_class_rel.oid,
+        )?;
+
+        let attr = match tuple_desc
+            .attrs
+            .iter()
+            .find(|attr| attr.attname == column.value)
+        {
+            Some(attr) => attr,
+            None => bail!(PgError::new(
+                SqlState::UndefinedColumn,
+                format!("column {} does not exist", column.value)
+            )),
+        };
 
         let rel = access::open_relation(
             pg_class_rel.oid,
@@ -75,43 +1322,209 @@ impl ConnectionExecutor {
             &rel_name,
         );
 
-        match &source.body {
-            ast::SetExpr::Values(values) => {
-                let tuple_desc = catalog::tuple_desc_from_relation(
-                    &self.buffer_pool,
-                    &self.config.database,
-                    &rel_name,
-                )?;
+        let index_oid = catalog::new_relation_oid(
+            &self.buffer_pool,
+            &pg_class_rel.reltablespace,
+            &self.config.database,
+        )?;
+
+        // Indexes are always created on the schema of their table.
+        index_create(
+            &self.buffer_pool,
+            &rel,
+            pg_class_rel.relnamespace,
+            pg_class_rel.relowner,
+            tableam::table_am(pg_class_rel.relam)?,
+            &tuple_desc,
+            &name.0[0].to_string(),
+            index_oid,
+            attr.attnum,
+            unique,
+        )?;
 
-                let mut heap_values = Vec::new();
+        self.flush_wal()
+    }
 
-                // Iterate over all rows on insert to write new tuples.
-                for row in &values.0 {
-                    let attr_values = tuple_values_from_insert_row(columns, row, &tuple_desc)?;
-                    for (attr, value) in attr_values.iter() {
-                        encode(&mut heap_values, value, attr)?;
-                    }
+    /// Compute the statistics of the given table, or of all tables of the database if the name is
+    /// empty, and store them on pg_statistic. Only the owners of the tables can analyze them, so
+    /// tables of other roles are skipped when all tables are analyzed.
+    pub fn exec_analyze(&self, table_name: &ast::ObjectName) -> Result<()> {
+        let privileges = self.privileges()?;
+        let rel_names = if table_name.0.is_empty() {
+            // Tables of all schemas are analyzed, even if they are not on the search path.
+            let mut rel_names = Vec::new();
+            for rel in catalog::get_pg_class_relations(&self.buffer_pool, &self.config.database)? {
+                if rel.oid >= FIRST_NORMAL_OBJECT_ID
+                    && rel.relam != BTREE_AM_OID
+                    && privileges.has_privileges_of(&rel.relowner)
+                {
+                    rel_names.push(RelationName {
+                        schema: Some(namespace::get_namespace_name(
+                            &self.buffer_pool,
+                            &self.config.database,
+                            &rel.relnamespace,
+                        )?),
+                        name: rel.relname,
+                    });
                 }
+            }
+            rel_names
+        } else {
+            vec![RelationName::from_object_name(table_name)?]
+        };
 
-                heap_insert(
-                    &self.buffer_pool,
-                    &rel,
-                    &mut HeapTuple::from_datums(heap_values, &tuple_desc)?,
-                )?;
+        for rel_name in rel_names {
+            // Rows can still be inserted and deleted while the table is analyzed.
+            let (pg_class_rel, _lock) = self.lock_relation(&rel_name, LockMode::AccessShare)?;
+            privileges.check_owner(&pg_class_rel)?;
+            let rel_name = pg_class_rel.relname.clone();
+            if pg_class_rel.relam == BTREE_AM_OID {
+                bail!(PgError::new(
+                    SqlState::WrongObjectType,
+                    format!("cannot analyze non-tables like \"{}\"", rel_name)
+                ));
             }
-            _ => bail!(SQLError::Unsupported(source.to_string())),
+
+            let tuple_desc = catalog::tuple_desc_from_relation_oid(
+                &self.buffer_pool,
+                &self.config.database,
+                &pg_class_rel.oid,
+            )?;
+            let rel = access::open_relation(
+                pg_class_rel.oid,
+                pg_class_rel.reltablespace,
+                if pg_class_rel.relisshared {
+                    &INVALID_OID
+                } else {
+                    &self.config.database
+                },
+                &rel_name,
+            );
+
+            let statistics = analyze::analyze_relation(
+                &self.buffer_pool,
+                &rel,
+                tableam::table_am(pg_class_rel.relam)?,
+                &tuple_desc,
+            )?;
+            update_relation_statistics(
+                &self.buffer_pool,
+                &self.config.database,
+                &pg_class_rel.oid,
+                &statistics,
+            )?;
         }
 
-        Ok(())
+        self.flush_wal()
+    }
+
+    /// Remove the given tables and all of their indexes. Tables that don't exist are skipped if
+    /// if_exists is true.
+    ///
+    /// All tables are locked before any of them is removed, and the locks are kept until all of
+    /// them are removed, so an error don't leave only some of the tables dropped. Only the
+    /// owners of the tables can drop them.
+    pub fn exec_drop_table(&self, names: &[ast::ObjectName], if_exists: bool) -> Result<()> {
+        let privileges = self.privileges()?;
+        let mut locked: Vec<(PgClass, LockGuard)> = Vec::with_capacity(names.len());
+        for name in names {
+            let rel_name = RelationName::from_object_name(name)?;
+            let (pg_class_rel, lock) =
+                match self.lock_relation(&rel_name, LockMode::AccessExclusive) {
+                    Ok(locked) => locked,
+                    Err(err) => match err.downcast_ref::<catalog::Error>() {
+                        Some(catalog::Error::RelationNotFound(_)) if if_exists => continue,
+                        _ => return Err(err),
+                    },
+                };
+
+            if pg_class_rel.oid < FIRST_NORMAL_OBJECT_ID {
+                bail!(PgError::new(
+                    SqlState::InsufficientPrivilege,
+                    format!(
+                        "permission denied: \"{}\" is a system catalog",
+                        pg_class_rel.relname
+                    )
+                ));
+            }
+            privileges.check_owner(&pg_class_rel)?;
+
+            if !locked.iter().any(|(rel, _)| rel.oid == pg_class_rel.oid) {
+                locked.push((pg_class_rel, lock));
+            }
+        }
+
+        for (pg_class_rel, _) in &locked {
+            heap_drop(&self.buffer_pool, &self.config.database, pg_class_rel)?;
+        }
+
+        self.flush_wal()
+    }
+
+    /// Lock the relation with the given name of the connected database until the returned guard
+    /// is dropped.
+    fn lock_relation(
+        &self,
+        rel_name: &RelationName,
+        mode: LockMode,
+    ) -> Result<(PgClass, LockGuard)> {
+        lmgr::lock_relation(
+            &self.buffer_pool,
+            &self.config.database,
+            &self.search_path(),
+            rel_name,
+            mode,
+            &self.lock_owner,
+        )
+    }
+
+    /// Make sure that all changes made by the statement are durable on write-ahead log before
+    /// reporting the command as completed to the client.
+    fn flush_wal(&self) -> Result<()> {
+        match self.buffer_pool.wal() {
+            Some(wal) => wal.flush_all(),
+            None => Ok(()),
+        }
+    }
+
+    /// Return the oid of the schema where a new relation with the given name is created, or an
+    /// error if the schema already has a relation with the same name.
+    fn creation_namespace(&self, rel_name: &RelationName) -> Result<Oid> {
+        let namespace = namespace::creation_namespace(
+            &self.buffer_pool,
+            &self.config.database,
+            &self.search_path(),
+            rel_name,
+        )?;
+        if catalog::get_namespace_relation(
+            &self.buffer_pool,
+            &self.config.database,
+            &namespace,
+            &rel_name.name,
+        )?
+        .is_some()
+        {
+            bail!(catalog::Error::RelationAlreadyExists(rel_name.name.clone()));
+        }
+        Ok(namespace)
     }
 
     pub fn exec_create_table(
         &self,
         name: &ast::ObjectName,
         columns: &Vec<ast::ColumnDef>,
+        with_options: &[ast::SqlOption],
     ) -> Result<()> {
-        // Create a new unique oid to the new heap relation.
-        let new_oid = catalog::new_relation_oid(&DEFAULTTABLESPACE_OID, &self.config.database)?;
+        let table_am = table_access_method(with_options)?;
+        let rel_name = RelationName::from_object_name(name)?;
+        let namespace = self.creation_namespace(&rel_name)?;
+
+        // Create a new unique oid to the new relation.
+        let new_oid = catalog::new_relation_oid(
+            &self.buffer_pool,
+            &DEFAULTTABLESPACE_OID,
+            &self.config.database,
+        )?;
 
         let mut tupledesc = TupleDesc::default();
         for (i, attr) in columns.iter().enumerate() {
@@ -121,15 +1534,93 @@ impl ConnectionExecutor {
                 .push(self.new_pg_attribute(new_oid, attr, i + 1)?)
         }
 
-        heap_create(
+        table_create(
             &self.buffer_pool,
             DEFAULTTABLESPACE_OID,
             &self.config.database,
-            &name.0[0].to_string(),
+            namespace,
+            self.current_role,
+            &rel_name.name,
             new_oid,
             &tupledesc,
+            table_am,
         )?;
-        Ok(())
+
+        self.flush_wal()
+    }
+
+    /// Create a new table with the columns and the rows returned by the given query and return
+    /// the number of inserted rows.
+    pub fn exec_create_table_as(
+        &self,
+        name: &ast::ObjectName,
+        query: Box<ast::Query>,
+        with_options: &[ast::SqlOption],
+    ) -> Result<u64> {
+        let table_am = table_access_method(with_options)?;
+        let rel_name = RelationName::from_object_name(name)?;
+        let namespace = self.creation_namespace(&rel_name)?;
+
+        let mut plan = self.create_plan(&query)?;
+        self.check_plan_privileges(&plan)?;
+        let executor = self.executor();
+        let tuple_table = executor.exec(&mut plan)?;
+
+        // Create a new unique oid to the new heap relation.
+        let new_oid = catalog::new_relation_oid(
+            &self.buffer_pool,
+            &DEFAULTTABLESPACE_OID,
+            &self.config.database,
+        )?;
+
+        // The new columns have the same names and types of the query output, without any
+        // constraint or default value.
+        let mut tupledesc = TupleDesc::default();
+        for (i, attr) in tuple_table.tuple_desc.attrs.iter().enumerate() {
+            if tupledesc
+                .attrs
+                .iter()
+                .any(|other| other.attname == attr.attname)
+            {
+                bail!(PgError::new(
+                    SqlState::DuplicateColumn,
+                    format!("column \"{}\" specified more than once", attr.attname)
+                ));
+            }
+
+            // Attributes numbers start at 1
+            tupledesc.attrs.push(PgAttribute {
+                attrelid: new_oid,
+                attname: attr.attname.clone(),
+                attnum: i + 1,
+                attlen: attr.attlen,
+                atttypid: attr.atttypid,
+                attnotnull: false,
+                attdefault: String::new(),
+            });
+        }
+
+        let rel = table_create(
+            &self.buffer_pool,
+            DEFAULTTABLESPACE_OID,
+            &self.config.database,
+            namespace,
+            self.current_role,
+            &rel_name.name,
+            new_oid,
+            &tupledesc,
+            table_am,
+        )?;
+
+        let tuples = tuple_table
+            .values
+            .iter()
+            .map(|values| HeapTuple::from_datums(values.clone(), &tupledesc))
+            .collect::<Result<Vec<_>>>()?;
+        table_am.multi_insert(&self.buffer_pool, &rel, &tuples)?;
+
+        self.flush_wal()?;
+        Ok(tuple_table.values.len() as u64)
     }
 
     fn new_pg_attribute(
@@ -138,43 +1629,48 @@ impl ConnectionExecutor {
         columndef: &ast::ColumnDef,
         attnum: usize,
     ) -> Result<PgAttribute> {
-        let (atttypid, attlen) = self.oid_type_and_size(&columndef.data_type)?;
-        Ok(PgAttribute {
+        let (atttypid, attlen) = pg_type::oid_type_and_size(&columndef.data_type)?;
+        let mut attr = PgAttribute {
             attrelid,
             attname: columndef.name.to_string(),
             attnum,
             attlen,
             atttypid,
-        })
-    }
+            attnotnull: false,
+            attdefault: String::new(),
+        };
 
-    /// Return the oid and the lenght of the given data type.
-    fn oid_type_and_size(&self, typ: &ast::DataType) -> Result<(Oid, i64)> {
-        match typ {
-            ast::DataType::Int(len) => Ok((
-                pg_type::INT_OID,
-                (len.unwrap_or(size_of::<i32>() as u64)) as i64,
-            )),
-            ast::DataType::Varchar(len) => match len {
-                Some(len) => Ok((pg_type::VARCHAR_OID, *len as i64)),
-                None => Ok((pg_type::VARCHAR_OID, -1)),
-            },
-            ast::DataType::Boolean => Ok((pg_type::BOOL_OID, size_of::<bool>() as i64)),
-            _ => bail!("Not supported data type: {}", typ),
+        for option in &columndef.options {
+            match &option.option {
+                ast::ColumnOption::Null => attr.attnotnull = false,
+                ast::ColumnOption::NotNull => attr.attnotnull = true,
+                ast::ColumnOption::Default(Expr::Value(value)) => {
+                    // Make sure that the default value can be stored on the column.
+                    encode(&mut Vec::new(), value, &attr)?;
+                    attr.attdefault = value.to_string();
+                }
+                ast::ColumnOption::Default(expr) => {
+                    bail!(SQLError::Unsupported(format!("DEFAULT {}", expr)))
+                }
+                // Other column constraints are not enforced yet.
+                _ => {}
+            }
         }
+
+        Ok(attr)
     }
 }
 
 /// Return a Vector of tuples, wich each tuple contains a attribute and their respective value on a
 /// row from insert statetment.
 ///
-/// The attribute and value returned is a reference on the given tuple desc attributes and vector
-/// of rows.
+/// The attribute returned is a reference on the given tuple desc attributes. Attributes that are
+/// omitted from the insert row are set to their default value, or NULL if there is no default.
 fn tuple_values_from_insert_row<'a>(
     columns: &Vec<ast::Ident>,
     row: &'a Vec<Expr>,
     tuple_desc: &'a TupleDesc,
-) -> Result<Vec<(&'a PgAttribute, &'a Value)>> {
+) -> Result<Vec<(&'a PgAttribute, Value)>> {
     let mut map = Vec::with_capacity(tuple_desc.attrs.len());
 
     if columns.len() == 0 {
@@ -185,17 +1681,20 @@ fn tuple_values_from_insert_row<'a>(
             match row.get(attr.attnum - 1) {
                 Some(value) => match value {
                     ast::Expr::Value(value) => {
-                        map.push((attr, value));
+                        map.push((attr, value.clone()));
                     }
                     _ => bail!(SQLError::Unsupported(value.to_string())),
                 },
                 None => {
-                    map.push((attr, &Value::Null));
+                    map.push((attr, default_value(attr)?));
                 }
             };
         }
     } else if row.len() != columns.len() {
-        bail!("INSERT has more expressions than target columns");
+        bail!(PgError::new(
+            SqlState::SyntaxError,
+            "INSERT has more expressions than target columns"
+        ));
     } else {
         // Iterate over relation attrs and try to find the value that is being inserted for each
         // attr. If the value does not exists on statment the value of attr is set to NULL
@@ -207,13 +1706,13 @@ fn tuple_values_from_insert_row<'a>(
                     let value = &row[index];
                     match value {
                         ast::Expr::Value(value) => {
-                            map.push((attr, value));
+                            map.push((attr, value.clone()));
                         }
                         _ => bail!(SQLError::Unsupported(value.to_string())),
                     }
                 }
                 None => {
-                    map.push((attr, &Value::Null));
+                    map.push((attr, default_value(attr)?));
                 }
             }
         }
@@ -222,6 +1721,235 @@ fn tuple_values_from_insert_row<'a>(
     Ok(map)
 }
 
+/// Split the given query into its statements at semicolons, ignoring the semicolons inside of
+/// quotes and comments, so statements that are not supported by the SQL parser can also be
+/// executed from a query with multiple statements. The returned statements don't have the
+/// semicolon, and statements with only whitespace and comments are skipped.
+pub fn split_statements(query: &str) -> Vec<&str> {
+    let mut statements = Vec::new();
+    let mut start = 0;
+    let mut has_content = false;
+
+    let mut chars = query.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\'' | '"' => {
+                has_content = true;
+                for (_, next) in chars.by_ref() {
+                    if next == c {
+                        break;
+                    }
+                }
+            }
+            '-' if matches!(chars.peek(), Some((_, '-'))) => {
+                for (_, next) in chars.by_ref() {
+                    if next == '\n' {
+                        break;
+                    }
+                }
+            }
+            '/' if matches!(chars.peek(), Some((_, '*'))) => {
+                chars.next();
+                let mut prev = ' ';
+                for (_, next) in chars.by_ref() {
+                    if prev == '*' && next == '/' {
+                        break;
+                    }
+                    prev = next;
+                }
+            }
+            ';' => {
+                if has_content {
+                    statements.push(query[start..i].trim());
+                }
+                start = i + 1;
+                has_content = false;
+            }
+            c if !c.is_whitespace() => has_content = true,
+            _ => {}
+        }
+    }
+    if has_content {
+        statements.push(query[start..].trim());
+    }
+    statements
+}
+
+/// Parse the given query. Statements that the SQL parser don't handle in the same way as
+/// Postgres are parsed separately.
+pub fn parse_sql(query: &str) -> Result<Vec<ast::Statement>> {
+    if let Some(statement) = copy::parse_copy(query) {
+        return Ok(vec![statement?]);
+    }
+
+    let dialect = PostgreSqlDialect {};
+    let tokens = Tokenizer::new(&dialect, query).tokenize()?;
+    let mut parser = Parser::new(tokens, &dialect);
+
+    // The same of Parser::parse_sql, also parsing ANALYZE statements on the Postgres syntax.
+    let mut statements = Vec::new();
+    let mut expecting_statement_delimiter = false;
+    loop {
+        while parser.consume_token(&Token::SemiColon) {
+            expecting_statement_delimiter = false;
+        }
+        if parser.peek_token() == Token::EOF {
+            return Ok(statements);
+        }
+        if expecting_statement_delimiter {
+            bail!(ParserError::ParserError(format!(
+                "Expected end of statement, found: {}",
+                parser.peek_token()
+            )));
+        }
+
+        statements.push(parse_statement(&mut parser)?);
+        expecting_statement_delimiter = true;
+    }
+}
+
+/// Parse the next statement of the given parser, including the statements that are parsed
+/// separately from the SQL parser, which may also be prepared by a PREPARE statement.
+fn parse_statement(parser: &mut Parser) -> Result<ast::Statement, ParserError> {
+    if parser.parse_keyword(Keyword::PREPARE) {
+        // The same of Parser::parse_prepare, using this function for the prepared statement.
+        let name = parser.parse_identifier()?;
+        let mut data_types = Vec::new();
+        if parser.consume_token(&Token::LParen) {
+            data_types = parser.parse_comma_separated(Parser::parse_data_type)?;
+            parser.expect_token(&Token::RParen)?;
+        }
+        parser.expect_keyword(Keyword::AS)?;
+        return Ok(ast::Statement::Prepare {
+            name,
+            data_types,
+            statement: Box::new(parse_statement(parser)?),
+        });
+    }
+
+    match analyze::parse_analyze(parser).or_else(|| insert::parse_insert(parser)) {
+        Some(statement) => statement,
+        None => parser.parse_statement(),
+    }
+}
+
+/// Return the output attributes of the given RETURNING items of an INSERT on the given
+/// relation, and the expressions that compute them over the inserted tuples.
+fn returning_projection(
+    rel_name: &str,
+    tuple_desc: &TupleDesc,
+    returning: &[ast::SelectItem],
+) -> Result<(Vec<PgAttribute>, Vec<expr::Expr>)> {
+    for item in returning {
+        if let ast::SelectItem::UnnamedExpr(expr) | ast::SelectItem::ExprWithAlias { expr, .. } =
+            item
+        {
+            if AggregateExpr::is_aggregate(expr) {
+                bail!(PgError::new(
+                    SqlState::GroupingError,
+                    "aggregate functions are not allowed in RETURNING"
+                ));
+            }
+        }
+    }
+    planner::create_projection(returning, &RangeTable::new(rel_name, tuple_desc.clone()))
+}
+
+/// Return the name of the relations referenced on the FROM clause or as the target of the given
+/// statement. The target relation of INSERT statements is returned first.
+fn statement_relations(statement: &ast::Statement) -> Result<Vec<RelationName>> {
+    if let Some((insert, _)) = insert::insert_returning(statement) {
+        return statement_relations(insert);
+    }
+
+    let mut relations = Vec::new();
+    match statement {
+        ast::Statement::Insert { table_name, .. } | ast::Statement::Delete { table_name, .. } => {
+            relations.push(RelationName::from_object_name(table_name)?)
+        }
+        ast::Statement::Query(query) => {
+            if let ast::SetExpr::Select(select) = &query.body {
+                for table in &select.from {
+                    for factor in std::iter::once(&table.relation)
+                        .chain(table.joins.iter().map(|join| &join.relation))
+                    {
+                        if let ast::TableFactor::Table { name, .. } = factor {
+                            relations.push(RelationName::from_object_name(name)?);
+                        }
+                    }
+                }
+            }
+        }
+        _ => {}
+    }
+    Ok(relations)
+}
+
+/// Return the default value of the given attribute, or NULL if the attribute has no default.
+fn default_value(attr: &PgAttribute) -> Result<Value> {
+    if attr.attdefault.is_empty() {
+        return Ok(Value::Null);
+    }
+
+    let dialect = PostgreSqlDialect {};
+    let tokens = Tokenizer::new(&dialect, &attr.attdefault).tokenize()?;
+    match Parser::new(tokens, &dialect).parse_expr()? {
+        Expr::Value(value) => Ok(value),
+        expr => bail!(PgError::new(
+            SqlState::DatatypeMismatch,
+            format!("invalid default value {} for column {}", expr, attr.attname)
+        )),
+    }
+}
+
+/// Return the privileges of a GRANT or REVOKE statement. Only the privileges of tables that are
+/// checked by statements can be granted, and not on specific columns.
+fn acl_mode(privileges: &ast::Privileges) -> Result<AclMode> {
+    let actions = match privileges {
+        ast::Privileges::All { .. } => return Ok(ACL_ALL_RIGHTS_RELATION),
+        ast::Privileges::Actions(actions) => actions,
+    };
+
+    let mut mode = 0;
+    for action in actions {
+        mode |= match action {
+            ast::Action::Select { columns: None } => ACL_SELECT,
+            ast::Action::Insert { columns: None } => ACL_INSERT,
+            ast::Action::Update { columns: None } => ACL_UPDATE,
+            ast::Action::Delete => ACL_DELETE,
+            ast::Action::Select { .. }
+            | ast::Action::Insert { .. }
+            | ast::Action::Update { .. } => {
+                bail!(SQLError::Unsupported(String::from("column privileges")))
+            }
+            action => bail!(SQLError::Unsupported(format!("{} privilege", action))),
+        };
+    }
+    Ok(mode)
+}
+
+/// Return the table access method selected by the access_method option of a CREATE TABLE
+/// statement, or the heap access method if the option is not set.
+fn table_access_method(with_options: &[ast::SqlOption]) -> Result<&'static dyn TableAm> {
+    let mut table_am: &'static dyn TableAm = &HeapTableAm;
+    for option in with_options {
+        match (option.name.value.to_lowercase().as_str(), &option.value) {
+            ("access_method", Value::SingleQuotedString(name)) => {
+                table_am = tableam::table_am_by_name(name)?
+            }
+            ("access_method", value) => bail!(PgError::new(
+                SqlState::InvalidParameterValue,
+                format!("invalid value for parameter \"access_method\": {}", value)
+            )),
+            (name, _) => bail!(PgError::new(
+                SqlState::InvalidParameterValue,
+                format!("unrecognized parameter \"{}\"", name)
+            )),
+        }
+    }
+    Ok(table_am)
+}
+
 /// Describe an attribute in a row.
 #[derive(Debug, Clone)]
 pub struct FieldDescription {
@@ -246,12 +1974,70 @@ pub struct FieldDescription {
     pub format: i16,
 }
 
+/// Format code of values sent on text format.
+pub const FORMAT_TEXT: i16 = 0;
+
+/// Format code of values sent on binary format.
+pub const FORMAT_BINARY: i16 = 1;
+
 /// A descriptor for all attributes in a pg result row.
 #[derive(Debug, Clone)]
 pub struct RowDescriptor {
     pub fields: Vec<FieldDescription>,
 }
 
+impl RowDescriptor {
+    /// Change the format of the fields to the given result formats of a Bind message.
+    pub fn with_formats(mut self, formats: &[i16]) -> Result<Self> {
+        let formats = format_codes(formats, self.fields.len()).ok_or_else(|| {
+            anyhow!(PgError::new(
+                SqlState::ProtocolViolation,
+                format!(
+                    "bind message has {} result formats but query has {} columns",
+                    formats.len(),
+                    self.fields.len()
+                )
+            ))
+        })?;
+
+        for (field, format) in self.fields.iter_mut().zip(formats) {
+            if format != FORMAT_TEXT && format != FORMAT_BINARY {
+                bail!(PgError::new(
+                    SqlState::ProtocolViolation,
+                    format!("unsupported format code: {}", format)
+                ));
+            }
+            field.format = format;
+        }
+
+        Ok(self)
+    }
+}
+
+impl From<&TupleDesc> for RowDescriptor {
+    fn from(tuple_desc: &TupleDesc) -> Self {
+        let fields = tuple_desc
+            .attrs
+            .iter()
+            .map(|attr| FieldDescription {
+                name: attr.attname.as_bytes().to_vec(),
+                table_oid: attr.attrelid as u32,
+                table_attribute_number: attr.attnum as u16,
+                data_type_oid: attr.atttypid as u32,
+                data_type_size: if attr.is_varlena() {
+                    -1
+                } else {
+                    attr.attlen as i16
+                },
+                type_modifier: -1,
+                format: FORMAT_TEXT,
+            })
+            .collect();
+
+        Self { fields }
+    }
+}
+
 /// A query result contaning the data for all rows an a descriptor for each attribute in a row.
 #[derive(Debug)]
 pub struct PGResult {
@@ -260,27 +2046,57 @@ pub struct PGResult {
 
     /// All values returned from a query.
     pub tuples: Vec<Vec<NullableDatum>>,
-}
 
-impl From<TupleTable> for PGResult {
-    fn from(table: TupleTable) -> Self {
-        let mut fields = Vec::with_capacity(table.tuple_desc.attrs.len());
+    /// Statistics of executing the query. Empty for results that are not returned by a plan.
+    pub stats: ExecutionStats,
+}
 
-        for attr in &table.tuple_desc.attrs {
-            fields.push(FieldDescription {
-                name: attr.attname.as_bytes().to_vec(),
-                table_oid: attr.attrelid as u32,
-                table_attribute_number: attr.attnum as u16,
-                data_type_oid: attr.atttypid as u32,
-                data_type_size: attr.attlen as i16,
+impl PGResult {
+    /// Create a new result with the given text columns.
+    pub fn text(columns: &[&str], tuples: Vec<Vec<NullableDatum>>) -> Self {
+        let fields = columns
+            .iter()
+            .map(|column| FieldDescription {
+                name: column.as_bytes().to_vec(),
+                table_oid: 0,
+                table_attribute_number: 0,
+                data_type_oid: pg_type::TEXT_OID as u32,
+                data_type_size: -1,
                 type_modifier: -1,
-                format: 0,
+                format: FORMAT_TEXT,
             })
-        }
+            .collect();
 
+        let stats = ExecutionStats {
+            rows: tuples.len() as u64,
+            ..ExecutionStats::default()
+        };
         Self {
             desc: RowDescriptor { fields },
+            tuples,
+            stats,
+        }
+    }
+}
+
+/// Return a row with the given text values.
+fn text_row(values: &[&str]) -> Result<Vec<NullableDatum>> {
+    values
+        .iter()
+        .map(|value| Ok(Some(Datum::from(value.as_bytes().to_vec()))))
+        .collect()
+}
+
+impl From<TupleTable> for PGResult {
+    fn from(table: TupleTable) -> Self {
+        let stats = ExecutionStats {
+            rows: table.values.len() as u64,
+            ..ExecutionStats::default()
+        };
+        Self {
+            desc: RowDescriptor::from(table.tuple_desc.as_ref()),
             tuples: table.values,
+            stats,
         }
     }
 }
@@ -0,0 +1,532 @@
+use anyhow::{anyhow, bail, Result};
+use sqlparser::ast::{self, Expr, Value};
+
+use crate::{access::heaptuple::TupleDesc, catalog::pg_type, Oid, INVALID_OID};
+
+use super::{
+    cursor::Cursor,
+    error::{PgError, SqlState},
+    insert::insert_returning,
+    FORMAT_TEXT,
+};
+
+/// A prepared statement holds a parsed statement that may contain `$n` parameters, so it can be
+/// executed multiple times with different parameter values.
+#[derive(Debug, Clone)]
+pub struct PreparedStatement {
+    /// Parsed statement containing the parameters placeholders.
+    statement: ast::Statement,
+
+    /// Type of each parameter, INVALID_OID if the type could not be determined.
+    param_types: Vec<Oid>,
+}
+
+impl PreparedStatement {
+    /// Create a new prepared statement with the given declared parameter types. Parameters that
+    /// are not declared have an unknown type until infer_param_types is called.
+    ///
+    /// Only queries, INSERT and DELETE statements can have parameters.
+    pub fn new(mut statement: ast::Statement, mut param_types: Vec<Oid>) -> Result<Self> {
+        let mut params = 0;
+        visit_statement(&mut statement, &mut |expr| {
+            if let Expr::Value(Value::Placeholder(placeholder)) = expr {
+                params = params.max(param_number(placeholder)?);
+            }
+            Ok(())
+        })?;
+
+        if param_types.len() < params {
+            param_types.resize(params, INVALID_OID);
+        }
+
+        Ok(Self {
+            statement,
+            param_types,
+        })
+    }
+
+    /// Return the type of each parameter.
+    pub fn param_types(&self) -> &[Oid] {
+        &self.param_types
+    }
+
+    /// Infer the type of the parameters that are not declared from the columns that they are
+    /// assigned or compared with, using the given tuple descriptions of the relations referenced
+    /// by the statement.
+    ///
+    /// For INSERT statements the first tuple description must be the target relation.
+    pub fn infer_param_types(&mut self, tuple_descs: &[TupleDesc]) -> Result<()> {
+        let mut inferred = Vec::new();
+
+        // The target relation of INSERT ... RETURNING is the relation of its INSERT statement.
+        let insert = match insert_returning(&self.statement) {
+            Some((insert, _)) => insert,
+            None => &self.statement,
+        };
+        if let ast::Statement::Insert {
+            columns, source, ..
+        } = insert
+        {
+            if let (ast::SetExpr::Values(values), Some(tuple_desc)) =
+                (&source.body, tuple_descs.first())
+            {
+                for row in &values.0 {
+                    for (i, value) in row.iter().enumerate() {
+                        let attr = match columns.get(i) {
+                            Some(column) => tuple_desc
+                                .attrs
+                                .iter()
+                                .find(|attr| attr.attname == column.value),
+                            None if columns.is_empty() => tuple_desc.attrs.get(i),
+                            None => None,
+                        };
+                        if let (Expr::Value(Value::Placeholder(placeholder)), Some(attr)) =
+                            (value, attr)
+                        {
+                            inferred.push((param_number(placeholder)?, attr.atttypid));
+                        }
+                    }
+                }
+            }
+        }
+
+        let column_type = |ident: &ast::Ident| {
+            tuple_descs
+                .iter()
+                .flat_map(|tuple_desc| tuple_desc.attrs.iter())
+                .find(|attr| attr.attname == ident.value)
+                .map(|attr| attr.atttypid)
+        };
+
+        // Parameters compared with a column have the same type of the column.
+        let mut statement = self.statement.clone();
+        visit_statement(&mut statement, &mut |expr| {
+            if let Expr::BinaryOp { left, right, .. } = expr {
+                let (column, placeholder) = match (left.as_ref(), right.as_ref()) {
+                    (column, Expr::Value(Value::Placeholder(placeholder)))
+                    | (Expr::Value(Value::Placeholder(placeholder)), column) => {
+                        (column, placeholder)
+                    }
+                    _ => return Ok(()),
+                };
+                let typ = match column {
+                    Expr::Identifier(ident) => column_type(ident),
+                    Expr::CompoundIdentifier(idents) => idents.last().and_then(column_type),
+                    _ => None,
+                };
+                if let Some(typ) = typ {
+                    inferred.push((param_number(placeholder)?, typ));
+                }
+            }
+            Ok(())
+        })?;
+
+        for (param, typ) in inferred {
+            if self.param_types[param - 1] == INVALID_OID {
+                self.param_types[param - 1] = typ;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Return the statement with all parameters replaced by the given values. The values are
+    /// converted to the parameter types if needed.
+    pub fn bind(&self, name: &str, params: &[Value]) -> Result<ast::Statement> {
+        if params.len() != self.param_types.len() {
+            bail!(PgError::new(SqlState::SyntaxError, format!("wrong number of parameters for prepared statement \"{}\": expected {} parameters but got {}",
+                name,
+                self.param_types.len(),
+                params.len())));
+        }
+
+        let params = params
+            .iter()
+            .zip(&self.param_types)
+            .map(|(value, typ)| coerce_param(value, *typ))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut statement = self.statement.clone();
+        visit_statement(&mut statement, &mut |expr| {
+            if let Expr::Value(Value::Placeholder(placeholder)) = expr {
+                *expr = Expr::Value(params[param_number(placeholder)? - 1].clone());
+            }
+            Ok(())
+        })?;
+
+        Ok(statement)
+    }
+}
+
+/// A portal holds a prepared statement bound with parameter values by the extended query
+/// protocol, ready to be executed.
+pub struct Portal {
+    /// Statement with all parameters replaced by the bound values.
+    pub statement: ast::Statement,
+
+    /// Format of the result columns as sent on the Bind message, see format_codes.
+    pub result_formats: Vec<i16>,
+
+    /// Cursor of a query executed with a row limit, so the next Execute messages of the portal
+    /// continue returning rows from where the previous one stopped.
+    pub cursor: Option<Cursor>,
+}
+
+/// Return the format code of each one of `count` values, given the format codes of a Bind
+/// message. The format codes are empty if all values are text, or have a single format used by
+/// all values. Return None if the number of format codes don't match with the values.
+pub fn format_codes(formats: &[i16], count: usize) -> Option<Vec<i16>> {
+    match formats {
+        [] => Some(vec![FORMAT_TEXT; count]),
+        [format] => Some(vec![*format; count]),
+        formats if formats.len() == count => Some(formats.to_vec()),
+        _ => None,
+    }
+}
+
+/// Return the number of the given $n parameter placeholder.
+fn param_number(placeholder: &str) -> Result<usize> {
+    match placeholder.strip_prefix('$').map(str::parse::<usize>) {
+        Some(Ok(number)) if number > 0 => Ok(number),
+        _ => bail!(PgError::new(
+            SqlState::SyntaxError,
+            format!("invalid parameter placeholder {}", placeholder)
+        )),
+    }
+}
+
+/// Convert the given parameter value to a literal of the given parameter type.
+pub fn coerce_param(value: &Value, typ: Oid) -> Result<Value> {
+    let invalid = |value: &str| {
+        anyhow!(PgError::new(
+            SqlState::InvalidTextRepresentation,
+            format!(
+                "invalid input syntax for type {}: \"{}\"",
+                pg_type::type_name(typ),
+                value
+            )
+        ))
+    };
+
+    match (typ, value) {
+        (_, Value::Null) | (INVALID_OID, _) => Ok(value.clone()),
+        (
+            pg_type::INT2_OID
+            | pg_type::INT_OID
+            | pg_type::INT8_OID
+            | pg_type::FLOAT4_OID
+            | pg_type::FLOAT8_OID,
+            Value::SingleQuotedString(value),
+        ) => match value.trim().parse::<f64>() {
+            Ok(_) => Ok(Value::Number(value.trim().to_string(), false)),
+            Err(_) => Err(invalid(value)),
+        },
+        (pg_type::BOOL_OID, Value::SingleQuotedString(value)) => {
+            match value.trim().to_lowercase().as_str() {
+                "t" | "true" => Ok(Value::Boolean(true)),
+                "f" | "false" => Ok(Value::Boolean(false)),
+                _ => Err(invalid(value)),
+            }
+        }
+        (
+            pg_type::VARCHAR_OID | pg_type::TEXT_OID | pg_type::DATE_OID | pg_type::TIMESTAMP_OID,
+            Value::Number(value, _),
+        ) => Ok(Value::SingleQuotedString(value.clone())),
+        (
+            pg_type::VARCHAR_OID | pg_type::TEXT_OID | pg_type::DATE_OID | pg_type::TIMESTAMP_OID,
+            Value::Boolean(value),
+        ) => Ok(Value::SingleQuotedString(value.to_string())),
+        (_, Value::Placeholder(placeholder)) => Err(invalid(placeholder)),
+        _ => Ok(value.clone()),
+    }
+}
+
+/// Call the given function on each expression of the statement, including nested expressions.
+fn visit_statement(
+    statement: &mut ast::Statement,
+    f: &mut dyn FnMut(&mut Expr) -> Result<()>,
+) -> Result<()> {
+    match statement {
+        ast::Statement::Query(query) => visit_query(query, f),
+        ast::Statement::Insert { source, on, .. } => {
+            visit_query(source, f)?;
+            if let Some(ast::OnInsert::DuplicateKeyUpdate(assignments)) = on {
+                for assignment in assignments {
+                    visit_expr(&mut assignment.value, f)?;
+                }
+            }
+            Ok(())
+        }
+        ast::Statement::Delete { selection, .. } => visit_option(selection, f),
+        _ => Ok(()),
+    }
+}
+
+fn visit_query(query: &mut ast::Query, f: &mut dyn FnMut(&mut Expr) -> Result<()>) -> Result<()> {
+    visit_set_expr(&mut query.body, f)?;
+    for order_by in &mut query.order_by {
+        visit_expr(&mut order_by.expr, f)?;
+    }
+    visit_option(&mut query.limit, f)?;
+    if let Some(offset) = &mut query.offset {
+        visit_expr(&mut offset.value, f)?;
+    }
+    Ok(())
+}
+
+fn visit_set_expr(
+    body: &mut ast::SetExpr,
+    f: &mut dyn FnMut(&mut Expr) -> Result<()>,
+) -> Result<()> {
+    match body {
+        ast::SetExpr::Select(select) => {
+            for item in &mut select.projection {
+                match item {
+                    ast::SelectItem::UnnamedExpr(expr)
+                    | ast::SelectItem::ExprWithAlias { expr, .. } => visit_expr(expr, f)?,
+                    _ => {}
+                }
+            }
+            for table in &mut select.from {
+                visit_table_factor(&mut table.relation, f)?;
+                for join in &mut table.joins {
+                    visit_table_factor(&mut join.relation, f)?;
+                    match &mut join.join_operator {
+                        ast::JoinOperator::Inner(ast::JoinConstraint::On(expr))
+                        | ast::JoinOperator::LeftOuter(ast::JoinConstraint::On(expr))
+                        | ast::JoinOperator::RightOuter(ast::JoinConstraint::On(expr))
+                        | ast::JoinOperator::FullOuter(ast::JoinConstraint::On(expr)) => {
+                            visit_expr(expr, f)?
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            visit_option(&mut select.selection, f)?;
+            for expr in &mut select.group_by {
+                visit_expr(expr, f)?;
+            }
+            visit_option(&mut select.having, f)
+        }
+        ast::SetExpr::Query(query) => visit_query(query, f),
+        ast::SetExpr::SetOperation { left, right, .. } => {
+            visit_set_expr(left, f)?;
+            visit_set_expr(right, f)
+        }
+        ast::SetExpr::Values(values) => {
+            for row in &mut values.0 {
+                for expr in row {
+                    visit_expr(expr, f)?;
+                }
+            }
+            Ok(())
+        }
+        ast::SetExpr::Insert(statement) => visit_statement(statement, f),
+    }
+}
+
+fn visit_table_factor(
+    relation: &mut ast::TableFactor,
+    f: &mut dyn FnMut(&mut Expr) -> Result<()>,
+) -> Result<()> {
+    match relation {
+        ast::TableFactor::Derived { subquery, .. } => visit_query(subquery, f),
+        _ => Ok(()),
+    }
+}
+
+fn visit_option(expr: &mut Option<Expr>, f: &mut dyn FnMut(&mut Expr) -> Result<()>) -> Result<()> {
+    match expr {
+        Some(expr) => visit_expr(expr, f),
+        None => Ok(()),
+    }
+}
+
+fn visit_expr(expr: &mut Expr, f: &mut dyn FnMut(&mut Expr) -> Result<()>) -> Result<()> {
+    f(expr)?;
+
+    match expr {
+        Expr::BinaryOp { left, right, .. } => {
+            visit_expr(left, f)?;
+            visit_expr(right, f)
+        }
+        Expr::UnaryOp { expr, .. }
+        | Expr::Nested(expr)
+        | Expr::IsNull(expr)
+        | Expr::IsNotNull(expr)
+        | Expr::Cast { expr, .. } => visit_expr(expr, f),
+        Expr::Between {
+            expr, low, high, ..
+        } => {
+            visit_expr(expr, f)?;
+            visit_expr(low, f)?;
+            visit_expr(high, f)
+        }
+        Expr::InList { expr, list, .. } => {
+            visit_expr(expr, f)?;
+            for expr in list {
+                visit_expr(expr, f)?;
+            }
+            Ok(())
+        }
+        Expr::Function(function) => {
+            for arg in &mut function.args {
+                match arg {
+                    ast::FunctionArg::Named {
+                        arg: ast::FunctionArgExpr::Expr(expr),
+                        ..
+                    }
+                    | ast::FunctionArg::Unnamed(ast::FunctionArgExpr::Expr(expr)) => {
+                        visit_expr(expr, f)?
+                    }
+                    _ => {}
+                }
+            }
+            Ok(())
+        }
+        Expr::Case {
+            operand,
+            conditions,
+            results,
+            else_result,
+        } => {
+            if let Some(operand) = operand {
+                visit_expr(operand, f)?;
+            }
+            for expr in conditions.iter_mut().chain(results) {
+                visit_expr(expr, f)?;
+            }
+            match else_result {
+                Some(else_result) => visit_expr(else_result, f),
+                None => Ok(()),
+            }
+        }
+        Expr::Subquery(query) => visit_query(query, f),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog::pg_attribute::PgAttribute;
+    use sqlparser::{dialect::PostgreSqlDialect, parser::Parser};
+
+    fn prepare(sql: &str, param_types: Vec<Oid>) -> Result<PreparedStatement> {
+        let mut statements = Parser::parse_sql(&PostgreSqlDialect {}, sql)?;
+        PreparedStatement::new(statements.remove(0), param_types)
+    }
+
+    fn tuple_desc() -> TupleDesc {
+        TupleDesc {
+            attrs: vec![
+                PgAttribute {
+                    attrelid: 1,
+                    attname: String::from("a"),
+                    attnum: 1,
+                    attlen: 4,
+                    atttypid: pg_type::INT_OID,
+                    attnotnull: false,
+                    attdefault: String::new(),
+                },
+                PgAttribute {
+                    attrelid: 1,
+                    attname: String::from("b"),
+                    attnum: 2,
+                    attlen: -1,
+                    atttypid: pg_type::VARCHAR_OID,
+                    attnotnull: false,
+                    attdefault: String::new(),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_bind() -> Result<()> {
+        let stmt = prepare("SELECT a FROM t WHERE a = $1 AND b = $2", vec![])?;
+        assert_eq!(stmt.param_types(), &[INVALID_OID, INVALID_OID]);
+
+        let bound = stmt.bind(
+            "p",
+            &[
+                Value::Number(String::from("1"), false),
+                Value::SingleQuotedString(String::from("x")),
+            ],
+        )?;
+        assert_eq!(bound.to_string(), "SELECT a FROM t WHERE a = 1 AND b = 'x'");
+
+        assert!(stmt.bind("p", &[Value::Null]).is_err());
+
+        let stmt = prepare(
+            "SELECT * FROM (SELECT a FROM t WHERE a = $1) AS sub",
+            vec![],
+        )?;
+        let bound = stmt.bind("p", &[Value::Number(String::from("1"), false)])?;
+        assert_eq!(
+            bound.to_string(),
+            "SELECT * FROM (SELECT a FROM t WHERE a = 1) AS sub"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_infer_param_types() -> Result<()> {
+        let mut stmt = prepare("INSERT INTO t VALUES ($1, $2)", vec![])?;
+        stmt.infer_param_types(&[tuple_desc()])?;
+        assert_eq!(
+            stmt.param_types(),
+            &[pg_type::INT_OID, pg_type::VARCHAR_OID]
+        );
+
+        let mut stmt = prepare("INSERT INTO t (b) VALUES ($1)", vec![])?;
+        stmt.infer_param_types(&[tuple_desc()])?;
+        assert_eq!(stmt.param_types(), &[pg_type::VARCHAR_OID]);
+
+        let mut stmt = prepare("DELETE FROM t WHERE $1 < t.a", vec![])?;
+        stmt.infer_param_types(&[tuple_desc()])?;
+        assert_eq!(stmt.param_types(), &[pg_type::INT_OID]);
+
+        // Declared types are not overridden.
+        let mut stmt = prepare("SELECT * FROM t WHERE a = $1", vec![pg_type::INT8_OID])?;
+        stmt.infer_param_types(&[tuple_desc()])?;
+        assert_eq!(stmt.param_types(), &[pg_type::INT8_OID]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bind_coerce_param_types() -> Result<()> {
+        let stmt = prepare(
+            "INSERT INTO t VALUES ($1, $2)",
+            vec![pg_type::INT_OID, pg_type::VARCHAR_OID],
+        )?;
+
+        let bound = stmt.bind(
+            "p",
+            &[
+                Value::SingleQuotedString(String::from("42")),
+                Value::Number(String::from("7"), false),
+            ],
+        )?;
+        assert_eq!(bound.to_string(), "INSERT INTO t VALUES (42, '7')");
+
+        assert!(stmt
+            .bind(
+                "p",
+                &[Value::SingleQuotedString(String::from("abc")), Value::Null]
+            )
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_codes() {
+        assert_eq!(format_codes(&[], 2), Some(vec![FORMAT_TEXT, FORMAT_TEXT]));
+        assert_eq!(format_codes(&[1], 3), Some(vec![1, 1, 1]));
+        assert_eq!(format_codes(&[0, 1], 2), Some(vec![0, 1]));
+        assert_eq!(format_codes(&[0, 1], 3), None);
+    }
+}
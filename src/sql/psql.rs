@@ -0,0 +1,215 @@
+/// A query used by psql meta-commands that list relations (\d, \dt, \di). These queries use
+/// catalog views, joins and functions that are not supported, so they are recognized by their
+/// text and answered directly from the system catalogs.
+#[derive(Debug, PartialEq)]
+pub struct ListRelations {
+    /// Kinds of relations to list, using the pg_class.relkind codes (e.g 'r' for tables and 'i'
+    /// for indexes).
+    pub relkinds: Vec<char>,
+
+    /// Regular expression that the relation names must match.
+    pub name_pattern: Option<String>,
+
+    /// Regular expression that the schema names must match.
+    pub schema_pattern: Option<String>,
+
+    /// List only relations that are not on pg_catalog schema.
+    pub exclude_system: bool,
+
+    /// Include the table of each index on the result, used by \di.
+    pub with_table: bool,
+}
+
+impl ListRelations {
+    /// Return true if relations of the given relkind should be listed.
+    pub fn lists(&self, relkind: char) -> bool {
+        self.relkinds.contains(&relkind)
+    }
+
+    /// Return true if a relation with the given schema and name should be listed.
+    pub fn matches(&self, schema: &str, name: &str) -> bool {
+        if self.exclude_system && schema == "pg_catalog" {
+            return false;
+        }
+        let matches = |pattern: &Option<String>, value| match pattern {
+            Some(pattern) => pattern_matches(pattern, value),
+            None => true,
+        };
+        matches(&self.schema_pattern, schema) && matches(&self.name_pattern, name)
+    }
+}
+
+/// Parse a query sent by psql to list relations. Return None if the given sql is not one of
+/// these queries.
+pub fn parse_list_relations(sql: &str) -> Option<ListRelations> {
+    if !sql.contains("FROM pg_catalog.pg_class c")
+        || !sql.contains("as \"Schema\"")
+        || !sql.contains("c.relname as \"Name\"")
+    {
+        return None;
+    }
+
+    let relkinds = after(sql, "c.relkind IN (")?;
+    let relkinds = &relkinds[..relkinds.find(')')?];
+    let relkinds = relkinds
+        .split(',')
+        .filter_map(|relkind| relkind.trim().trim_matches('\'').chars().next())
+        .collect();
+
+    Some(ListRelations {
+        relkinds,
+        name_pattern: after(sql, "c.relname OPERATOR(pg_catalog.~) ").and_then(string_literal),
+        schema_pattern: after(sql, "n.nspname OPERATOR(pg_catalog.~) ").and_then(string_literal),
+        exclude_system: sql.contains("n.nspname <> 'pg_catalog'"),
+        with_table: sql.contains("as \"Table\""),
+    })
+}
+
+/// Return the remaining of the given sql after the first occurrence of the given text.
+fn after<'a>(sql: &'a str, text: &str) -> Option<&'a str> {
+    sql.find(text).map(|pos| &sql[pos + text.len()..])
+}
+
+/// Return the value of the string literal at the start of the given sql.
+fn string_literal(sql: &str) -> Option<String> {
+    let mut chars = sql.strip_prefix('\'')?.chars().peekable();
+    let mut value = String::new();
+    while let Some(c) = chars.next() {
+        if c == '\'' {
+            if chars.peek() != Some(&'\'') {
+                return Some(value);
+            }
+            chars.next();
+        }
+        value.push(c);
+    }
+    None
+}
+
+/// Element of a regular expression generated by psql from a name pattern.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PatternToken {
+    /// A character that match itself.
+    Literal(char),
+
+    /// `.` that match any character.
+    Any,
+
+    /// `.*` that match any sequence of characters.
+    AnySequence,
+}
+
+/// Return true if the given value match the given regular expression. Only the subset of regular
+/// expressions that psql generates from name patterns using `*` and `?` wildcards are supported,
+/// other regular expressions never match.
+pub fn pattern_matches(pattern: &str, value: &str) -> bool {
+    let pattern = pattern
+        .strip_prefix("^(")
+        .and_then(|pattern| pattern.strip_suffix(")$"))
+        .unwrap_or(pattern);
+
+    let mut tokens = Vec::new();
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            '\\' => match chars.next() {
+                Some(c) => PatternToken::Literal(c),
+                None => return false,
+            },
+            '.' if chars.peek() == Some(&'*') => {
+                chars.next();
+                PatternToken::AnySequence
+            }
+            '.' => PatternToken::Any,
+            '^' | '$' | '(' | ')' | '[' | ']' | '{' | '}' | '|' | '*' | '+' | '?' => return false,
+            c => PatternToken::Literal(c),
+        };
+        tokens.push(token);
+    }
+
+    let value = value.chars().collect::<Vec<_>>();
+    tokens_match(&tokens, &value)
+}
+
+/// Return true if the given pattern tokens match the whole value.
+fn tokens_match(tokens: &[PatternToken], value: &[char]) -> bool {
+    match tokens.split_first() {
+        None => value.is_empty(),
+        Some((PatternToken::AnySequence, rest)) => {
+            (0..=value.len()).any(|skip| tokens_match(rest, &value[skip..]))
+        }
+        Some((PatternToken::Any, rest)) => !value.is_empty() && tokens_match(rest, &value[1..]),
+        Some((PatternToken::Literal(c), rest)) => {
+            value.first() == Some(c) && tokens_match(rest, &value[1..])
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_list_relations() {
+        let sql = r#"SELECT n.nspname as "Schema",
+  c.relname as "Name",
+  CASE c.relkind WHEN 'r' THEN 'table' WHEN 'i' THEN 'index' END as "Type",
+  pg_catalog.pg_get_userbyid(c.relowner) as "Owner"
+FROM pg_catalog.pg_class c
+     LEFT JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace
+WHERE c.relkind IN ('r','p','')
+      AND n.nspname <> 'pg_catalog'
+      AND n.nspname !~ '^pg_toast'
+      AND n.nspname <> 'information_schema'
+  AND pg_catalog.pg_table_is_visible(c.oid)
+ORDER BY 1,2;"#;
+        assert_eq!(
+            parse_list_relations(sql),
+            Some(ListRelations {
+                relkinds: vec!['r', 'p'],
+                name_pattern: None,
+                schema_pattern: None,
+                exclude_system: true,
+                with_table: false,
+            })
+        );
+
+        let sql = r#"SELECT n.nspname as "Schema",
+  c.relname as "Name",
+  CASE c.relkind WHEN 'r' THEN 'table' WHEN 'i' THEN 'index' END as "Type",
+  pg_catalog.pg_get_userbyid(c.relowner) as "Owner",
+ c2.relname as "Table"
+FROM pg_catalog.pg_class c
+     LEFT JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace
+     LEFT JOIN pg_catalog.pg_index i ON i.indexrelid = c.oid
+     LEFT JOIN pg_catalog.pg_class c2 ON i.indrelid = c2.oid
+WHERE c.relkind IN ('i','I','')
+  AND c.relname OPERATOR(pg_catalog.~) '^(t''s.*)$' COLLATE pg_catalog.default
+  AND n.nspname OPERATOR(pg_catalog.~) '^(public)$' COLLATE pg_catalog.default
+ORDER BY 1,2;"#;
+        assert_eq!(
+            parse_list_relations(sql),
+            Some(ListRelations {
+                relkinds: vec!['i', 'I'],
+                name_pattern: Some(String::from("^(t's.*)$")),
+                schema_pattern: Some(String::from("^(public)$")),
+                exclude_system: false,
+                with_table: true,
+            })
+        );
+
+        assert_eq!(parse_list_relations("SELECT * FROM pg_class"), None);
+    }
+
+    #[test]
+    fn test_pattern_matches() {
+        assert!(pattern_matches("^(t.*)$", "t"));
+        assert!(pattern_matches("^(t.*)$", "t_users"));
+        assert!(!pattern_matches("^(t.*)$", "users"));
+        assert!(pattern_matches("^(t.)$", "t1"));
+        assert!(!pattern_matches("^(t.)$", "t12"));
+        assert!(pattern_matches("^(.*_idx)$", "t_a_idx"));
+        assert!(pattern_matches("^(t\\$x)$", "t$x"));
+        assert!(!pattern_matches("^(t[0-9])$", "t1"));
+    }
+}
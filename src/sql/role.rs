@@ -0,0 +1,177 @@
+//! Statements of roles that the SQL parser don't support: CREATE ROLE, CREATE USER, and GRANT or
+//! REVOKE of memberships between roles.
+
+use anyhow::Result;
+use sqlparser::{
+    dialect::PostgreSqlDialect,
+    keywords::Keyword,
+    parser::{Parser, ParserError},
+    tokenizer::{Token, Tokenizer},
+};
+
+use crate::catalog::role::RoleOptions;
+
+/// A statement of roles parsed by parse_role_statement.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RoleStatement {
+    /// CREATE ROLE or CREATE USER, which is the same of CREATE ROLE with LOGIN by default.
+    CreateRole { name: String, options: RoleOptions },
+
+    /// GRANT role TO member.
+    GrantRole { role: String, member: String },
+
+    /// REVOKE role FROM member.
+    RevokeRole { role: String, member: String },
+}
+
+impl RoleStatement {
+    /// Return the command tag sent to the client when the statement completes.
+    pub fn command_tag(&self) -> &'static str {
+        match self {
+            Self::CreateRole { .. } => "CREATE ROLE",
+            Self::GrantRole { .. } => "GRANT ROLE",
+            Self::RevokeRole { .. } => "REVOKE ROLE",
+        }
+    }
+}
+
+/// Parse a statement of roles, which is not supported by the SQL parser. Return None if the given
+/// sql is not a statement of roles.
+///
+/// CREATE ROLE and CREATE USER are parsed on the Postgres syntax
+/// CREATE {ROLE | USER} name [WITH] [SUPERUSER | NOSUPERUSER | LOGIN | NOLOGIN | PASSWORD 'x'].
+/// GRANT and REVOKE statements are only returned if they have no ON clause, since privileges
+/// on tables are parsed by the SQL parser.
+pub fn parse_role_statement(sql: &str) -> Option<Result<RoleStatement>> {
+    let dialect = PostgreSqlDialect {};
+    let tokens = Tokenizer::new(&dialect, sql).tokenize().ok()?;
+
+    let words = tokens
+        .iter()
+        .filter(|token| !matches!(token, Token::Whitespace(_) | Token::SemiColon))
+        .collect::<Vec<_>>();
+    match words.as_slice() {
+        [Token::Word(grant), Token::Word(role), Token::Word(to), Token::Word(member)]
+            if grant.keyword == Keyword::GRANT && to.keyword == Keyword::TO =>
+        {
+            return Some(Ok(RoleStatement::GrantRole {
+                role: role.value.clone(),
+                member: member.value.clone(),
+            }));
+        }
+        [Token::Word(revoke), Token::Word(role), Token::Word(from), Token::Word(member)]
+            if revoke.keyword == Keyword::REVOKE && from.keyword == Keyword::FROM =>
+        {
+            return Some(Ok(RoleStatement::RevokeRole {
+                role: role.value.clone(),
+                member: member.value.clone(),
+            }));
+        }
+        _ => {}
+    }
+
+    let mut parser = Parser::new(tokens, &dialect);
+    let login = if parser.parse_keywords(&[Keyword::CREATE, Keyword::ROLE]) {
+        false
+    } else if parser.parse_keywords(&[Keyword::CREATE, Keyword::USER]) {
+        true
+    } else {
+        return None;
+    };
+    Some(parse_create_role(&mut parser, login).map_err(anyhow::Error::from))
+}
+
+fn parse_create_role(parser: &mut Parser, login: bool) -> Result<RoleStatement, ParserError> {
+    let name = parser.parse_identifier()?.value;
+    let _ = parser.parse_keyword(Keyword::WITH);
+
+    let mut options = RoleOptions {
+        login,
+        ..Default::default()
+    };
+    loop {
+        match parser.next_token() {
+            Token::Word(word) => match word.value.to_lowercase().as_str() {
+                "superuser" => options.superuser = true,
+                "nosuperuser" => options.superuser = false,
+                "login" => options.login = true,
+                "nologin" => options.login = false,
+                "password" => {
+                    options.password = if parser.parse_keyword(Keyword::NULL) {
+                        None
+                    } else {
+                        Some(parser.parse_literal_string()?)
+                    }
+                }
+                _ => return Err(expected_role_option(Token::Word(word))),
+            },
+            Token::SemiColon | Token::EOF => break,
+            token => return Err(expected_role_option(token)),
+        }
+    }
+
+    Ok(RoleStatement::CreateRole { name, options })
+}
+
+fn expected_role_option(found: Token) -> ParserError {
+    ParserError::ParserError(format!("Expected role option, found: {}", found))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_role_statement() {
+        let parse = |sql| parse_role_statement(sql).map(|statement| statement.unwrap());
+        assert_eq!(parse("SELECT 1"), None);
+        assert_eq!(parse("GRANT SELECT ON t TO alice"), None);
+        assert_eq!(
+            parse("CREATE ROLE readers;"),
+            Some(RoleStatement::CreateRole {
+                name: String::from("readers"),
+                options: RoleOptions::default(),
+            })
+        );
+        assert_eq!(
+            parse("create user alice with password 'secret' superuser"),
+            Some(RoleStatement::CreateRole {
+                name: String::from("alice"),
+                options: RoleOptions {
+                    superuser: true,
+                    login: true,
+                    password: Some(String::from("secret")),
+                },
+            })
+        );
+        assert_eq!(
+            parse("CREATE USER bob NOLOGIN PASSWORD NULL"),
+            Some(RoleStatement::CreateRole {
+                name: String::from("bob"),
+                options: RoleOptions::default(),
+            })
+        );
+        assert_eq!(
+            parse("GRANT readers TO alice;"),
+            Some(RoleStatement::GrantRole {
+                role: String::from("readers"),
+                member: String::from("alice"),
+            })
+        );
+        assert_eq!(
+            parse("revoke readers from alice"),
+            Some(RoleStatement::RevokeRole {
+                role: String::from("readers"),
+                member: String::from("alice"),
+            })
+        );
+
+        assert!(parse_role_statement("CREATE ROLE").unwrap().is_err());
+        assert!(parse_role_statement("CREATE ROLE r CREATEDB")
+            .unwrap()
+            .is_err());
+        assert!(parse_role_statement("CREATE ROLE r PASSWORD")
+            .unwrap()
+            .is_err());
+    }
+}
@@ -1,18 +1,42 @@
 use std::{
     collections::HashMap,
+    fs::File,
+    io::Read,
+    ops::{Deref, RangeInclusive},
+    path::PathBuf,
     sync::{
-        atomic::{self, Ordering},
+        atomic::{self, AtomicU64, Ordering},
+        mpsc::{self, SyncSender, TryRecvError},
         Arc,
     },
+    thread,
+    time::Instant,
 };
 
 use anyhow::{bail, Result};
 use log::debug;
-use std::sync::{Mutex, RwLock};
-
-use crate::{lru::LRU, relation::Relation, Oid, INVALID_OID};
+use std::sync::{Mutex, MutexGuard, RwLock, RwLockWriteGuard};
+use tokio::task;
+
+use crate::{
+    access::lmgr::LockManager,
+    catalog::cache::CatalogCache,
+    lru::{ReplacementPolicy, Replacer},
+    relation::Relation,
+    Oid, INVALID_OID,
+};
 
-use super::{smgr::StorageManager, Page, PageNumber, INVALID_PAGE_NUMBER};
+use super::{
+    buffile::BufFile,
+    control::ControlFile,
+    disk,
+    freespace::FreeSpaceMap,
+    page::{page_checksum, page_get_lsn, page_set_checksum, page_verify_checksum, PageHeader},
+    relation_locator::RelationLocatorData,
+    smgr::StorageManager,
+    wal::{Wal, WalRecord},
+    Page, PageNumber, INVALID_PAGE_NUMBER,
+};
 
 /// Buffer identifiers.
 ///
@@ -50,6 +74,39 @@ impl Default for BufferTag {
     }
 }
 
+/// Counters of the buffer pool activity since it was created.
+#[derive(Default)]
+struct BufferCounters {
+    hits: AtomicU64,
+    reads: AtomicU64,
+    writes: AtomicU64,
+}
+
+/// Usage and activity statistics of a buffer pool.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct BufferPoolStats {
+    /// Number of buffers of the buffer pool.
+    pub buffers: u64,
+
+    /// Number of buffers holding a page.
+    pub used: u64,
+
+    /// Number of buffers holding a page changed since it was read or last written to disk.
+    pub dirty: u64,
+
+    /// Number of buffers currently pinned.
+    pub pinned: u64,
+
+    /// Number of page requests found in memory.
+    pub hits: u64,
+
+    /// Number of pages read from disk.
+    pub reads: u64,
+
+    /// Number of pages written to disk.
+    pub writes: u64,
+}
+
 /// Shared descriptor/state data for a single shared buffer.
 ///
 /// Buffer represents a a page that is mapped by buffer pool in memory. Each
@@ -104,6 +161,11 @@ impl Buffer {
         }
     }
 
+    /// Return the page number of relation that this buffer holds.
+    pub fn page_number(&self) -> PageNumber {
+        self.tag.read().unwrap().page_number
+    }
+
     fn relation(&self) -> Result<Relation> {
         let rel = self.rel.read().unwrap();
         // Match the reference from the de-refenrece value of RwLock
@@ -114,6 +176,86 @@ impl Buffer {
     }
 }
 
+/// A pinned buffer returned by the buffer pool. The buffer is unpinned when the guard is dropped,
+/// so multiple pages can be held pinned at the same time and pins are released even if an error
+/// is returned while the buffer is used.
+pub struct BufferGuard {
+    /// Pinned buffer.
+    buffer: Buffer,
+
+    /// Replacer of the buffer pool, where the buffer is added back when it has no more pins.
+    replacer: Arc<dyn Replacer<BufferID>>,
+
+    /// The page was changed and must be written to disk before the buffer is reused.
+    is_dirty: bool,
+}
+
+impl BufferGuard {
+    /// Mark the page as changed, so it is written to disk before the buffer is reused.
+    pub fn mark_dirty(&mut self) {
+        self.is_dirty = true;
+    }
+}
+
+impl Deref for BufferGuard {
+    type Target = Buffer;
+
+    fn deref(&self) -> &Buffer {
+        &self.buffer
+    }
+}
+
+impl Drop for BufferGuard {
+    /// Make the buffer available for replacement. The buffer is also unpinned on the replacer if
+    /// this was the last pin of the buffer.
+    fn drop(&mut self) {
+        let bufid = self.buffer.id.read().unwrap();
+
+        // Change the is_dirty flag to false only if the current value is false.
+        self.buffer
+            .is_dirty
+            .fetch_or(self.is_dirty, Ordering::SeqCst);
+        let refs = self.buffer.refs.fetch_sub(1, Ordering::SeqCst);
+        log::trace!("page {} de-referenced; original_ref: {}", bufid, refs);
+
+        // The buffer has no more references if this was the last one.
+        if refs == 1 {
+            self.replacer.unpin(&bufid);
+        }
+    }
+}
+
+/// Maximum number of buffers used by a bulk read access strategy.
+const BULK_READ_RING_SIZE: usize = 32;
+
+/// A buffer access strategy limits the number of buffers used by bulk operations, like sequential
+/// scans of large relations. Pages that are not in memory are read into a small ring of buffers
+/// that are reused, so the scan don't replace all pages that other operations are using.
+#[derive(Debug)]
+pub struct BufferAccessStrategy {
+    /// Buffers of the ring. None if the slot was not used yet.
+    ring: Vec<Option<BufferID>>,
+
+    /// Index of the last buffer used from the ring.
+    current: usize,
+}
+
+impl BufferAccessStrategy {
+    /// Create a new access strategy that use at most the given number of buffers.
+    pub fn new(size: usize) -> Self {
+        Self {
+            ring: vec![None; size.max(1)],
+            current: 0,
+        }
+    }
+
+    /// Create a new access strategy to read large relations on the given buffer pool. The ring
+    /// use at most 1/8 of the buffer pool.
+    pub fn bulk_read(buffer_pool: &BufferPool) -> Self {
+        Self::new(BULK_READ_RING_SIZE.min(buffer_pool.size() / 8))
+    }
+}
+
 /// Shared buffer pool manager interface used by almost all other database
 /// components.
 ///
@@ -126,7 +268,7 @@ pub struct BufferPool {
     smgr: Arc<Mutex<StorageManager>>,
 
     /// Replacer used to find a page that can be removed from memory.
-    lru: Arc<Mutex<LRU<BufferID>>>,
+    replacer: Arc<dyn Replacer<BufferID>>,
 
     /// Fixed array all pages.
     pages: Arc<RwLock<Vec<Buffer>>>,
@@ -139,6 +281,106 @@ pub struct BufferPool {
 
     /// How many strong references the buffer pool had.
     refs: Arc<atomic::AtomicUsize>,
+
+    /// Free space of relation pages shared by all buffer pool users.
+    fsm: Arc<Mutex<FreeSpaceMap>>,
+
+    /// System catalog tuples shared by all buffer pool users.
+    catalog_cache: Arc<Mutex<CatalogCache>>,
+
+    /// Relation locks shared by all buffer pool users.
+    lock_manager: LockManager,
+
+    /// Lock of each index relation that serializes the inserts on the index, created when the
+    /// index is first inserted.
+    index_insert_locks: Arc<Mutex<HashMap<RelationLocatorData, Arc<Mutex<()>>>>>,
+
+    /// Held exclusively while a base backup copies the data directory. Checkpoints and relation
+    /// unlinks hold it shared, so they wait until the copy is finished.
+    backup: Arc<RwLock<()>>,
+
+    /// Held shared while a page is written to a relation file, and exclusively while a base
+    /// backup reads a chunk of a relation file, so the backup never reads a page partially
+    /// written.
+    page_writes: Arc<RwLock<()>>,
+
+    /// Write-ahead log used to log page changes. None if changes are not logged.
+    wal: Option<Wal>,
+
+    /// Verify the checksum of pages read from disk.
+    verify_checksums: bool,
+
+    /// Force the relation files to be stored on disk on checkpoints.
+    fsync: bool,
+
+    /// Control file updated with the location of each checkpoint. None if checkpoints are not
+    /// recorded.
+    control: Option<ControlFile>,
+
+    /// Activity counters shared by all buffer pool users.
+    counters: Arc<BufferCounters>,
+
+    /// Index of the next buffer to be scanned by the background writer.
+    bgwriter_next: Arc<atomic::AtomicUsize>,
+
+    /// Number of pages that sequential scans read ahead of the page being scanned. Zero disables
+    /// the read-ahead.
+    prefetch_depth: usize,
+
+    /// Queue of the read-ahead requests executed by the prefetch worker thread. None if the
+    /// read-ahead is disabled.
+    prefetch_queue: Option<SyncSender<PrefetchRequest>>,
+}
+
+/// Maximum number of read-ahead requests waiting for the prefetch worker. Scans don't read ahead
+/// while the queue is full, so they never wait for the read-ahead of other scans.
+const PREFETCH_QUEUE_SIZE: usize = 32;
+
+/// Request to read ahead pages of a relation, executed by the prefetch worker.
+struct PrefetchRequest {
+    buffer_pool: BufferPool,
+    rel: Relation,
+    pages: RangeInclusive<PageNumber>,
+
+    /// Dropped when the request is finished, see Prefetch.
+    _done: mpsc::Sender<()>,
+}
+
+/// Read-ahead requested by BufferPool::prefetch.
+pub struct Prefetch {
+    done: mpsc::Receiver<()>,
+}
+
+impl Prefetch {
+    /// Return true if the pages were already read.
+    pub fn is_finished(&self) -> bool {
+        matches!(self.done.try_recv(), Err(TryRecvError::Disconnected))
+    }
+
+    /// Wait until the pages are read.
+    pub fn wait(self) {
+        let _ = self.done.recv();
+    }
+}
+
+/// Execute the read-ahead requests of the given queue, until all buffer pools that send requests
+/// to it are dropped.
+fn prefetch_worker(queue: mpsc::Receiver<PrefetchRequest>) {
+    for request in queue {
+        for page_num in request.pages {
+            match request.buffer_pool.prefetch_page(&request.rel, page_num) {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(err) => {
+                    debug!(
+                        "failed to prefetch page {} of relation {}: {}",
+                        page_num, request.rel.rel_name, err
+                    );
+                    break;
+                }
+            }
+        }
+    }
 }
 
 impl BufferPool {
@@ -157,81 +399,310 @@ impl BufferPool {
             free_list: Arc::new(Mutex::new(free_list)),
             pages: Arc::new(RwLock::new(pages)),
             smgr: Arc::new(Mutex::new(smgr)),
-            lru: Arc::new(Mutex::new(LRU::new(size))),
+            replacer: ReplacementPolicy::Lru.replacer(size),
             page_table: Arc::new(RwLock::new(HashMap::with_capacity(size))),
             refs: Arc::new(atomic::AtomicUsize::new(1)),
+            fsm: Arc::new(Mutex::new(FreeSpaceMap::default())),
+            catalog_cache: Arc::new(Mutex::new(CatalogCache::default())),
+            lock_manager: LockManager::default(),
+            index_insert_locks: Arc::new(Mutex::new(HashMap::new())),
+            backup: Arc::new(RwLock::new(())),
+            page_writes: Arc::new(RwLock::new(())),
+            wal: None,
+            verify_checksums: false,
+            fsync: true,
+            control: None,
+            counters: Arc::new(BufferCounters::default()),
+            bgwriter_next: Arc::new(atomic::AtomicUsize::new(0)),
+            prefetch_depth: 0,
+            prefetch_queue: None,
         }
     }
 
+    /// Use the given replacement policy to choose which buffer should be replaced when the buffer
+    /// pool is full.
+    pub fn with_replacement_policy(mut self, policy: ReplacementPolicy) -> Self {
+        self.replacer = policy.replacer(self.pages.read().unwrap().len());
+        self
+    }
+
+    /// Verify the checksum of pages when they are read from disk, failing with
+    /// [ChecksumFailure](super::disk::Error::ChecksumFailure) if the page is corrupted. Checksums
+    /// are always computed when pages are written, even if they are not verified.
+    pub fn with_checksum_verification(mut self, verify_checksums: bool) -> Self {
+        self.verify_checksums = verify_checksums;
+        self
+    }
+
+    /// Force the relation files to be stored on the physical device on each checkpoint. If fsync
+    /// is false pages can be lost if the operating system crash, even after a checkpoint.
+    pub fn with_fsync(mut self, fsync: bool) -> Self {
+        self.fsync = fsync;
+        self
+    }
+
+    /// Record the location of the checkpoints of this buffer pool on the given control file.
+    pub fn with_control_file(mut self, control: ControlFile) -> Self {
+        self.control = Some(control);
+        self
+    }
+
+    /// Use the given write-ahead log to log page changes of this buffer pool.
+    pub fn with_wal(mut self, wal: Wal) -> Self {
+        self.wal = Some(wal);
+        self
+    }
+
+    /// Read ahead the given number of pages on sequential scans. Pages are read by a single
+    /// worker thread shared by all users of the buffer pool.
+    pub fn with_prefetch_depth(mut self, prefetch_depth: usize) -> Self {
+        self.prefetch_depth = prefetch_depth;
+        if prefetch_depth > 0 && self.prefetch_queue.is_none() {
+            let (sender, receiver) = mpsc::sync_channel(PREFETCH_QUEUE_SIZE);
+            thread::Builder::new()
+                .name(String::from("prefetch"))
+                .spawn(move || prefetch_worker(receiver))
+                .expect("failed to spawn prefetch worker");
+            self.prefetch_queue = Some(sender);
+        }
+        self
+    }
+
+    /// Return the number of pages that sequential scans read ahead.
+    pub fn prefetch_depth(&self) -> usize {
+        self.prefetch_depth
+    }
+
     /// Returns the buffer number for the buffer containing the block read. The
     /// returned buffer has been pinned.
-    pub fn fetch_buffer(&self, rel: &Relation, page_num: PageNumber) -> Result<Buffer> {
-        let buf_tag = BufferTag::new(page_num, rel);
-        let page_table = self.page_table.read().unwrap();
-        let buffer = page_table.get(&buf_tag);
+    pub fn fetch_buffer(&self, rel: &Relation, page_num: PageNumber) -> Result<BufferGuard> {
+        Ok(self.guard(self.read_buffer(rel, page_num, None, true)?))
+    }
 
-        match buffer {
-            Some(buffer) => {
-                debug!(
-                    "page {} exists on memory on buffer {} for relation {}",
-                    page_num, buffer, rel.rel_name,
-                );
+    /// Same as fetch_buffer, but the checksum of the page is not verified if it is read from
+    /// disk. Used by recovery to fetch pages that are overwritten entirely, which may have been
+    /// torn by a crash.
+    pub fn fetch_buffer_to_overwrite(
+        &self,
+        rel: &Relation,
+        page_num: PageNumber,
+    ) -> Result<BufferGuard> {
+        Ok(self.guard(self.read_buffer(rel, page_num, None, false)?))
+    }
 
-                let buffer = self.get_buffer(buffer)?;
+    /// Same as fetch_buffer, but if the page is not in memory it is read on the blocking thread
+    /// pool of the tokio runtime, so the task awaiting the page don't block the runtime threads
+    /// while the page is read, or while a dirty buffer is written to make room for the page.
+    pub async fn fetch_buffer_async(
+        &self,
+        rel: &Relation,
+        page_num: PageNumber,
+    ) -> Result<BufferGuard> {
+        if let Some(buffer) = self.cached_buffer(rel, page_num)? {
+            return Ok(self.guard(buffer));
+        }
 
-                drop(page_table);
-                self.pin_buffer(&buffer);
+        let buffer_pool = self.clone();
+        let rel = rel.clone();
+        task::spawn_blocking(move || buffer_pool.fetch_buffer(&rel, page_num)).await?
+    }
 
-                Ok(buffer)
-            }
+    /// Same as fetch_buffer, but if the page is not in memory it is read into a buffer chosen by
+    /// the given access strategy.
+    pub fn fetch_buffer_with_strategy(
+        &self,
+        rel: &Relation,
+        page_num: PageNumber,
+        strategy: &mut BufferAccessStrategy,
+    ) -> Result<BufferGuard> {
+        Ok(self.guard(self.read_buffer(rel, page_num, Some(strategy), true)?))
+    }
+
+    /// Return a guard that unpin the given pinned buffer when dropped.
+    fn guard(&self, buffer: Buffer) -> BufferGuard {
+        BufferGuard {
+            buffer,
+            replacer: self.replacer.clone(),
+            is_dirty: false,
+        }
+    }
+
+    fn read_buffer(
+        &self,
+        rel: &Relation,
+        page_num: PageNumber,
+        strategy: Option<&mut BufferAccessStrategy>,
+        verify_checksum: bool,
+    ) -> Result<Buffer> {
+        match self.cached_buffer(rel, page_num)? {
+            Some(buffer) => Ok(buffer),
             None => {
                 debug!(
                     "fething page {} from disk for relation {}",
                     page_num, rel.rel_name
                 );
-                drop(page_table);
 
                 // Find a new buffer id for page.
-                let new_buffer = self.new_free_buffer()?;
-                let new_buffer = self.get_buffer(&new_buffer)?;
-
-                {
-                    // Crate a short live write mutex for buffer desc tag.
-                    let mut new_buffer_tag = new_buffer.tag.write().unwrap();
-                    new_buffer_tag.tablespace = buf_tag.tablespace;
-                    new_buffer_tag.db = buf_tag.db;
-                    new_buffer_tag.relation = buf_tag.relation;
-                    new_buffer_tag.page_number = buf_tag.page_number;
-                }
+                let new_buffer = match strategy {
+                    Some(strategy) => self.strategy_buffer(strategy)?,
+                    None => self.new_free_buffer()?,
+                };
+                self.load_buffer(rel, page_num, new_buffer, verify_checksum)
+            }
+        }
+    }
 
-                {
-                    // Create a short live write mutex for buffer desc relation.
-                    let mut new_buffer_rel = new_buffer.rel.write().unwrap();
-                    let _ = new_buffer_rel.take();
-                    *new_buffer_rel = Some(rel.clone());
-                }
+    /// Return the pinned buffer holding the given page of relation. None if the page is not in
+    /// memory.
+    fn cached_buffer(&self, rel: &Relation, page_num: PageNumber) -> Result<Option<Buffer>> {
+        let buf_tag = BufferTag::new(page_num, rel);
+        let page_table = self.page_table.read().unwrap();
+        let bufid = match page_table.get(&buf_tag) {
+            Some(bufid) => bufid,
+            None => return Ok(None),
+        };
+        debug!(
+            "page {} exists on memory on buffer {} for relation {}",
+            page_num, bufid, rel.rel_name,
+        );
 
-                new_buffer.refs.store(0, Ordering::SeqCst);
-                new_buffer.is_dirty.store(false, atomic::Ordering::SeqCst);
+        let buffer = self.get_buffer(bufid)?;
+        drop(page_table);
+        self.pin_buffer(&buffer);
+        self.counters.hits.fetch_add(1, Ordering::Relaxed);
 
-                // Read page from disk and store inside buffer descriptor.
-                {
-                    let mut smgr = self.smgr.lock().unwrap();
-                    smgr.read(rel, page_num, &new_buffer.page)?;
-                }
+        Ok(Some(buffer))
+    }
 
-                // Add buffer descriptior on cache and pinned.
-                {
-                    let mut page_table = self.page_table.write().unwrap();
-                    page_table.insert(buf_tag, *new_buffer.id.read().unwrap());
-                }
-                self.pin_buffer(&new_buffer);
+    /// Read the given pages of relation from disk into free buffers on the prefetch worker, so
+    /// they are already in memory when they are requested. Pages already in memory are skipped
+    /// and the prefetch stops when there are no more free buffers, pages in memory are never
+    /// replaced by prefetched pages.
+    ///
+    /// Return None, without reading the pages, if the read-ahead is disabled or the queue of the
+    /// worker is full.
+    pub fn prefetch(&self, rel: &Relation, pages: RangeInclusive<PageNumber>) -> Option<Prefetch> {
+        let queue = self.prefetch_queue.as_ref()?;
+        let (done, receiver) = mpsc::channel();
+        queue
+            .try_send(PrefetchRequest {
+                buffer_pool: self.clone(),
+                rel: rel.clone(),
+                pages,
+                _done: done,
+            })
+            .ok()?;
+        Some(Prefetch { done: receiver })
+    }
 
-                Ok(new_buffer)
+    /// Read the given page into a free buffer if it is not in memory, leaving the buffer unpinned.
+    /// Return false if there is no free buffer to read the page.
+    fn prefetch_page(&self, rel: &Relation, page_num: PageNumber) -> Result<bool> {
+        let buf_tag = BufferTag::new(page_num, rel);
+        if self.page_table.read().unwrap().contains_key(&buf_tag) {
+            return Ok(true);
+        }
+
+        let bufid = self.free_list.lock().unwrap().pop();
+        match bufid {
+            Some(bufid) => {
+                // Dropping the guard make the buffer available for replacement.
+                drop(self.guard(self.load_buffer(rel, page_num, bufid, true)?));
+                Ok(true)
             }
+            None => Ok(false),
         }
     }
 
+    /// Read the given page from disk into the given unused buffer and add it on page table. The
+    /// returned buffer has been pinned. The checksum of the page is verified if verify_checksum
+    /// is true and the buffer pool verifies checksums.
+    fn load_buffer(
+        &self,
+        rel: &Relation,
+        page_num: PageNumber,
+        bufid: BufferID,
+        verify_checksum: bool,
+    ) -> Result<Buffer> {
+        let buf_tag = BufferTag::new(page_num, rel);
+        let new_buffer = self.get_buffer(&bufid)?;
+
+        {
+            // Crate a short live write mutex for buffer desc tag.
+            let mut new_buffer_tag = new_buffer.tag.write().unwrap();
+            new_buffer_tag.tablespace = buf_tag.tablespace;
+            new_buffer_tag.db = buf_tag.db;
+            new_buffer_tag.relation = buf_tag.relation;
+            new_buffer_tag.page_number = buf_tag.page_number;
+        }
+
+        {
+            // Create a short live write mutex for buffer desc relation.
+            let mut new_buffer_rel = new_buffer.rel.write().unwrap();
+            let _ = new_buffer_rel.take();
+            *new_buffer_rel = Some(rel.clone());
+        }
+
+        new_buffer.refs.store(0, Ordering::SeqCst);
+        new_buffer.is_dirty.store(false, atomic::Ordering::SeqCst);
+
+        // Read page from disk and store inside buffer descriptor. The storage manager is only
+        // held to find the relation storage, so pages of other buffers are read at the same time.
+        let start = Instant::now();
+        let storage = self.smgr.lock().unwrap().smgr_from_relation(rel)?;
+        storage.read_page(page_num, &new_buffer.page)?;
+        log::trace!(
+            "read page {} of relation {} from disk in {:?}",
+            page_num,
+            rel.rel_name,
+            start.elapsed()
+        );
+        self.counters.reads.fetch_add(1, Ordering::Relaxed);
+
+        if verify_checksum
+            && self.verify_checksums
+            && !page_verify_checksum(&new_buffer.page, page_num)?
+        {
+            // The buffer don't hold any valid page, so it can be used again.
+            self.release_buffer(&new_buffer);
+
+            bail!(disk::Error::ChecksumFailure {
+                rel_name: rel.rel_name.clone(),
+                page_number: page_num,
+                calculated: page_checksum(&new_buffer.page, page_num),
+                expected: PageHeader::new(&new_buffer.page)?.checksum,
+            });
+        }
+
+        // Add buffer descriptior on cache and pinned.
+        {
+            let mut page_table = self.page_table.write().unwrap();
+            if let Some(bufid) = page_table.get(&buf_tag) {
+                // The page was read concurrently into another buffer, e.g by a prefetch,
+                // so use that buffer and release this one.
+                let buffer = self.get_buffer(bufid)?;
+                self.pin_buffer(&buffer);
+                drop(page_table);
+                self.release_buffer(&new_buffer);
+                return Ok(buffer);
+            }
+            page_table.insert(buf_tag, *new_buffer.id.read().unwrap());
+        }
+        self.pin_buffer(&new_buffer);
+
+        Ok(new_buffer)
+    }
+
+    /// Add the given buffer that don't hold a valid page back on free list.
+    fn release_buffer(&self, buffer: &Buffer) {
+        *buffer.tag.write().unwrap() = BufferTag::default();
+        let _ = buffer.rel.write().unwrap().take();
+        self.free_list
+            .lock()
+            .unwrap()
+            .push(*buffer.id.read().unwrap());
+    }
+
     /// Physically write out a shared page to disk.
     ///
     /// Return error if the page could not be found in the page table, None
@@ -242,12 +713,35 @@ impl BufferPool {
             buffer.id.read().unwrap(),
             buffer.relation()?.rel_name
         );
-        let mut smgr = self.smgr.lock().unwrap();
-        smgr.write(
-            &buffer.relation()?,
-            buffer.tag.read().unwrap().page_number,
-            &buffer.page,
-        )?;
+
+        // WAL records of the page changes must be on disk before the page itself.
+        if let Some(wal) = &self.wal {
+            wal.flush(page_get_lsn(&buffer.page)?)?;
+        }
+
+        // Compute the checksum on a copy of the page, so that concurrent changes on the buffer
+        // page can not be written without being included on the checksum.
+        let page_number = buffer.tag.read().unwrap().page_number;
+        let page = Page::new(**buffer.page.0.read().unwrap());
+        page_set_checksum(&page, page_number)?;
+
+        let start = Instant::now();
+        let storage = self
+            .smgr
+            .lock()
+            .unwrap()
+            .smgr_from_relation(&buffer.relation()?)?;
+        {
+            let _page_writes = self.page_writes.read().unwrap();
+            storage.write_page(page_number, &page)?;
+        }
+        self.counters.writes.fetch_add(1, Ordering::Relaxed);
+        log::trace!(
+            "wrote page {} of relation {} to disk in {:?}",
+            page_number,
+            buffer.relation()?.rel_name,
+            start.elapsed()
+        );
 
         Ok(())
     }
@@ -260,7 +754,7 @@ impl BufferPool {
     /// page.
     ///
     /// Return error if no new pages could be created, otherwise the buffer.
-    pub fn alloc_buffer(&self, rel: &Relation) -> Result<Buffer> {
+    pub fn alloc_buffer(&self, rel: &Relation) -> Result<BufferGuard> {
         let mut smgr = self.smgr.lock().unwrap();
         let page_num = smgr.extend(rel)?;
         // Force drop to avoid trying use multiple mutable references of self.
@@ -276,13 +770,6 @@ impl BufferPool {
     /// Return a new free buffer from free list or victim if there is no more
     /// free buffers to use.
     fn new_free_buffer(&self) -> Result<BufferID> {
-        let page_table = self.page_table.read().unwrap();
-        assert!(
-            page_table.len() < page_table.capacity(),
-            "Buffer pool exceeded the maximum capacity"
-        );
-        drop(page_table);
-
         let mut free_list = self.free_list.lock().unwrap();
         let buffer = free_list.pop();
         // Force drop to avoid trying use multiple mutable references of self.
@@ -294,20 +781,49 @@ impl BufferPool {
         }
     }
 
-    /// Use the LRU replacement policy to choose a page to victim. This function
-    /// panic if the LRU don't have any page id to victim. Otherwise the page
+    /// Use the replacement policy to choose a page to victim. This function
+    /// panic if the replacer don't have any page id to victim. Otherwise the page
     /// will be removed from page table. If the choosen page is dirty victim
     /// will flush to disk before removing from page table.
     fn victim(&self) -> Result<BufferID> {
         let bufid = self
-            .lru
-            .lock()
-            .unwrap()
+            .replacer
             .victim()
             .expect("replacer does not contain any page id to victim");
 
         debug!("page {} was chosen for victim", bufid);
 
+        let start = Instant::now();
+        self.evict(bufid)?;
+        log::trace!("evicted buffer {} in {:?}", bufid, start.elapsed());
+
+        Ok(bufid)
+    }
+
+    /// Return the next buffer from the ring of the given strategy if it can be reused. If the
+    /// buffer is being used by someone else a new free buffer is returned and added to the ring
+    /// instead.
+    fn strategy_buffer(&self, strategy: &mut BufferAccessStrategy) -> Result<BufferID> {
+        strategy.current = (strategy.current + 1) % strategy.ring.len();
+
+        if let Some(bufid) = strategy.ring[strategy.current] {
+            // Only reuse the buffer if it is not pinned, removing it from the replacer make sure
+            // that it can not be chosen as a victim concurrently.
+            if self.replacer.remove(&bufid) {
+                debug!("reusing buffer {} from access strategy ring", bufid);
+                self.evict(bufid)?;
+                return Ok(bufid);
+            }
+        }
+
+        let bufid = self.new_free_buffer()?;
+        strategy.ring[strategy.current] = Some(bufid);
+        Ok(bufid)
+    }
+
+    /// Remove the page of the given buffer from page table, flushing to disk if the page is
+    /// dirty.
+    fn evict(&self, bufid: BufferID) -> Result<()> {
         let buffer = self.get_buffer(&bufid)?;
         let buf_tag = buffer.tag.read().unwrap();
 
@@ -322,7 +838,7 @@ impl BufferPool {
         let mut page_table = self.page_table.write().unwrap();
         page_table.remove(&buf_tag);
 
-        Ok(bufid)
+        Ok(())
     }
 
     fn get_buffer(&self, buffer: &BufferID) -> Result<Buffer> {
@@ -337,31 +853,7 @@ impl BufferPool {
 
         let refs = buffer.refs.fetch_add(1, Ordering::SeqCst);
         log::trace!("page {} referenced; original_ref: {}", bufid, refs);
-        self.lru.lock().unwrap().pin(&bufid);
-    }
-
-    /// Make the buffer available for replacement. The buffer is also unpined on
-    /// lru if the ref count is 0.
-    ///
-    /// Return error if the buffer does not exists on buffer pool, None
-    /// otherwise.
-    pub fn unpin_buffer(&self, buffer: &Buffer, is_dirty: bool) -> Result<()> {
-        let bufid = buffer.id.read().unwrap();
-        let buffer = self.get_buffer(&bufid)?;
-
-        // Change the is_dirty flag to false only if the current value is false.
-        buffer.is_dirty.fetch_or(is_dirty, Ordering::SeqCst);
-        let refs = buffer.refs.fetch_sub(1, Ordering::SeqCst);
-        log::trace!(
-            "page {} de-referenced; original_ref: {}",
-            buffer.id.read().unwrap(),
-            refs
-        );
-
-        if self.refs.load(Ordering::SeqCst) == 0 {
-            self.lru.lock().unwrap().unpin(&bufid);
-        }
-        Ok(())
+        self.replacer.pin(&bufid);
     }
 
     pub fn flush_all_buffers(&self) -> Result<()> {
@@ -372,10 +864,269 @@ impl BufferPool {
         Ok(())
     }
 
+    /// Return the number of buffers of the buffer pool.
+    pub fn size(&self) -> usize {
+        self.pages.read().unwrap().len()
+    }
+
+    /// Return the current usage and activity statistics of the buffer pool.
+    pub fn stats(&self) -> BufferPoolStats {
+        let mut stats = BufferPoolStats {
+            buffers: self.size() as u64,
+            used: self.page_table.read().unwrap().len() as u64,
+            hits: self.counters.hits.load(Ordering::Relaxed),
+            reads: self.counters.reads.load(Ordering::Relaxed),
+            writes: self.counters.writes.load(Ordering::Relaxed),
+            ..BufferPoolStats::default()
+        };
+        for buffer in self.pages.read().unwrap().iter() {
+            if buffer.is_dirty.load(Ordering::SeqCst) {
+                stats.dirty += 1;
+            }
+            if buffer.refs.load(Ordering::SeqCst) > 0 {
+                stats.pinned += 1;
+            }
+        }
+        stats
+    }
+
+    /// Remove the pages that were not completely written at the end of relation files, see
+    /// StorageManager::truncate_invalid_pages.
+    pub fn truncate_invalid_pages(&self) -> Result<()> {
+        self.smgr.lock().unwrap().truncate_invalid_pages()
+    }
+
+    /// Create a new temporary file, see StorageManager::create_temp_file.
+    pub fn create_temp_file(&self) -> Result<BufFile> {
+        self.smgr.lock().unwrap().create_temp_file()
+    }
+
+    /// Remove the temporary files left by a previous run of the server.
+    pub fn remove_temp_files(&self) -> Result<()> {
+        self.smgr.lock().unwrap().remove_temp_files()
+    }
+
     /// Return the number of pages of the given relation.
     pub fn size_of_relation(&self, rel: &Relation) -> Result<u32> {
         self.smgr.lock().unwrap().size(rel)
     }
+
+    /// Write at most max_pages dirty buffers that are not pinned, so that buffers chosen as
+    /// victims don't need to be written on the query path. Buffers are scanned in a circular
+    /// order, continuing from where the last call stopped, until the number of free and clean
+    /// unpinned buffers reaches the given target.
+    ///
+    /// Return the number of written buffers.
+    pub fn write_dirty_buffers(&self, max_pages: usize, clean_target: usize) -> Result<usize> {
+        let pages = self.pages.read().unwrap().clone();
+        let reusable = |buffer: &Buffer| {
+            buffer.refs.load(Ordering::SeqCst) == 0 && !buffer.is_dirty.load(Ordering::SeqCst)
+        };
+        let mut clean = self.free_list.lock().unwrap().len()
+            + pages
+                .iter()
+                .filter(|buffer| buffer.rel.read().unwrap().is_some() && reusable(buffer))
+                .count();
+
+        let mut written = 0;
+        for _ in 0..pages.len() {
+            if written >= max_pages || clean >= clean_target {
+                break;
+            }
+            let next = self.bgwriter_next.fetch_add(1, Ordering::SeqCst) % pages.len();
+            let buffer = &pages[next];
+            if buffer.refs.load(Ordering::SeqCst) > 0 {
+                continue;
+            }
+
+            // Clear the dirty flag before writing, like checkpoints, so changes made while the
+            // buffer is being written are not lost.
+            if buffer.is_dirty.swap(false, Ordering::SeqCst) {
+                self.flush_buffer(buffer)?;
+                written += 1;
+                clean += 1;
+            }
+        }
+
+        if written > 0 {
+            debug!("background writer wrote {} buffers", written);
+        }
+        Ok(written)
+    }
+
+    /// Return the free space map of relations that use this buffer pool.
+    pub fn free_space_map(&self) -> MutexGuard<'_, FreeSpaceMap> {
+        self.fsm.lock().unwrap()
+    }
+
+    /// Return the cache of system catalog tuples of relations that use this buffer pool.
+    pub fn catalog_cache(&self) -> MutexGuard<'_, CatalogCache> {
+        self.catalog_cache.lock().unwrap()
+    }
+
+    /// Return the lock manager of relations that use this buffer pool.
+    pub fn lock_manager(&self) -> &LockManager {
+        &self.lock_manager
+    }
+
+    /// Return the lock that serializes the inserts on the given index relation, shared by all
+    /// users of this buffer pool. Inserts on different indexes never wait for each other.
+    pub fn index_insert_lock(&self, rel: &Relation) -> Arc<Mutex<()>> {
+        self.index_insert_locks
+            .lock()
+            .unwrap()
+            .entry(rel.locator.as_ref().clone())
+            .or_default()
+            .clone()
+    }
+
+    /// Discard all buffers of the given relation, without writing them to disk, and remove the
+    /// relation storage. The relation must not be used by anyone else, an error is returned if
+    /// any page of the relation is still pinned.
+    pub fn drop_relation(&self, rel: &Relation) -> Result<()> {
+        let _backup = self.backup.read().unwrap();
+        let mut page_table = self.page_table.write().unwrap();
+        let buffers: Vec<(BufferTag, BufferID)> = page_table
+            .iter()
+            .filter(|(tag, _)| {
+                tag.tablespace == rel.locator.tablespace
+                    && tag.db == rel.locator.database
+                    && tag.relation == rel.locator.oid
+            })
+            .map(|(tag, bufid)| (tag.clone(), *bufid))
+            .collect();
+
+        for (tag, bufid) in &buffers {
+            if self.get_buffer(bufid)?.refs.load(Ordering::SeqCst) > 0 {
+                bail!(
+                    "could not drop relation {}: page {} is still in use",
+                    rel.rel_name,
+                    tag.page_number
+                );
+            }
+        }
+
+        for (tag, bufid) in &buffers {
+            let buffer = self.get_buffer(bufid)?;
+            self.replacer.remove(bufid);
+            buffer.is_dirty.store(false, Ordering::SeqCst);
+            page_table.remove(tag);
+            self.release_buffer(&buffer);
+        }
+        drop(page_table);
+
+        self.free_space_map().forget(rel);
+        self.index_insert_locks
+            .lock()
+            .unwrap()
+            .remove(rel.locator.as_ref());
+        self.smgr.lock().unwrap().unlink(rel)
+    }
+
+    /// Hold off checkpoints and relation unlinks until the returned guard is dropped, so the files
+    /// of the data directory can be copied by a base backup. Waits for running checkpoints and
+    /// unlinks to finish.
+    pub fn start_backup(&self) -> RwLockWriteGuard<'_, ()> {
+        self.backup.write().unwrap()
+    }
+
+    /// Return true if a relation with the given oid has storage on the given tablespace and
+    /// database.
+    pub fn relation_exists(&self, tablespace: &Oid, db_oid: &Oid, rel_oid: &Oid) -> Result<bool> {
+        self.smgr
+            .lock()
+            .unwrap()
+            .exists(tablespace, db_oid, rel_oid)
+    }
+
+    /// Return true if the directory of the given database exists.
+    pub fn database_exists(&self, db_oid: &Oid) -> bool {
+        self.smgr.lock().unwrap().database_exists(db_oid)
+    }
+
+    /// Create the storage of a new database with a copy of all relations of the template
+    /// database. All dirty buffers are written first, so the copy has the last version of each
+    /// page of the template, and pages are not written to relation files while they are copied,
+    /// so the bytes of a page are never copied partially written.
+    pub fn copy_database(&self, template_oid: &Oid, db_oid: &Oid) -> Result<()> {
+        self.checkpoint()?;
+
+        // Base backups must not copy the new database while its files are being copied.
+        let _backup = self.backup.read().unwrap();
+        let mut smgr = self.smgr.lock().unwrap();
+        let _page_writes = self.page_writes.write().unwrap();
+        smgr.copy_database(template_oid, db_oid)
+    }
+
+    /// Return the data directory where relation files are stored. None if the pages are kept in
+    /// memory.
+    pub fn data_dir(&self) -> Option<PathBuf> {
+        self.smgr
+            .lock()
+            .unwrap()
+            .data_dir()
+            .map(|path| path.to_path_buf())
+    }
+
+    /// Read the next bytes of the given file of the data directory into the given buffer,
+    /// returning the number of bytes read, which is only less than the buffer size at the end of
+    /// the file.
+    ///
+    /// Pages are not written to relation files while the file is read, so the bytes of a page
+    /// are never read partially written.
+    pub fn read_data_file(&self, file: &mut File, buf: &mut [u8]) -> Result<usize> {
+        let _smgr = self.smgr.lock().unwrap();
+        let _page_writes = self.page_writes.write().unwrap();
+        let mut read = 0;
+        while read < buf.len() {
+            match file.read(&mut buf[read..])? {
+                0 => break,
+                n => read += n,
+            }
+        }
+        Ok(read)
+    }
+
+    /// Return the write-ahead log used by this buffer pool, if any.
+    pub fn wal(&self) -> Option<&Wal> {
+        self.wal.as_ref()
+    }
+
+    /// Flush all dirty buffers to disk, fsync the relation files and write a checkpoint record on
+    /// WAL, so that recovery don't need to replay records before the start of the checkpoint,
+    /// which are removed from the WAL file.
+    pub fn checkpoint(&self) -> Result<()> {
+        let _backup = self.backup.read().unwrap();
+        let redo = self.wal.as_ref().map(|wal| wal.start_checkpoint());
+
+        // Don't hold the page table lock while writing pages, so other users of the buffer pool
+        // are not blocked by the checkpoint.
+        let bufids: Vec<BufferID> = self.page_table.read().unwrap().values().copied().collect();
+        for bufid in &bufids {
+            let buffer = self.get_buffer(bufid)?;
+            // Clear the dirty flag before writing, so changes made while the buffer is being
+            // flushed are written by the next checkpoint.
+            if buffer.is_dirty.swap(false, Ordering::SeqCst) {
+                self.flush_buffer(&buffer)?;
+            }
+        }
+        if self.fsync {
+            self.smgr.lock().unwrap().sync_all()?;
+        }
+
+        if let (Some(wal), Some(redo)) = (&self.wal, redo) {
+            let lsn = wal.insert(&WalRecord::Checkpoint { redo })?;
+            wal.flush_all()?;
+
+            if let Some(control) = &self.control {
+                control.set_checkpoint_lsn(lsn)?;
+            }
+
+            // Recovery starts at the redo LSN of the new checkpoint record.
+            wal.remove_records_before(redo)?;
+        }
+        Ok(())
+    }
 }
 
 impl Drop for BufferPool {
@@ -386,7 +1137,7 @@ impl Drop for BufferPool {
 
         if self.refs.load(Ordering::SeqCst) == 0 {
             log::debug!("flushing all buffers to disk");
-            self.flush_all_buffers()
+            self.checkpoint()
                 .expect("failed to flush all buffers to disk");
         }
     }
@@ -400,11 +1151,291 @@ impl Clone for BufferPool {
 
         Self {
             smgr: self.smgr.clone(),
-            lru: self.lru.clone(),
+            replacer: self.replacer.clone(),
             pages: self.pages.clone(),
             free_list: self.free_list.clone(),
             page_table: self.page_table.clone(),
             refs: self.refs.clone(),
+            fsm: self.fsm.clone(),
+            catalog_cache: self.catalog_cache.clone(),
+            lock_manager: self.lock_manager.clone(),
+            index_insert_locks: self.index_insert_locks.clone(),
+            backup: self.backup.clone(),
+            page_writes: self.page_writes.clone(),
+            wal: self.wal.clone(),
+            verify_checksums: self.verify_checksums,
+            fsync: self.fsync,
+            control: self.control.clone(),
+            counters: self.counters.clone(),
+            bgwriter_next: self.bgwriter_next.clone(),
+            prefetch_depth: self.prefetch_depth,
+            prefetch_queue: self.prefetch_queue.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::{
+        access::{
+            self,
+            heap::{heap_insert, HeapScanner},
+            heaptuple::HeapTuple,
+        },
+        catalog::pg_tablespace::DEFAULTTABLESPACE_OID,
+        storage::PAGE_SIZE,
+    };
+
+    #[test]
+    fn test_replace_buffers_on_full_buffer_pool() -> Result<()> {
+        for policy in &[ReplacementPolicy::Lru, ReplacementPolicy::ClockSweep] {
+            replace_buffers_on_full_buffer_pool(*policy)?;
+        }
+        Ok(())
+    }
+
+    fn replace_buffers_on_full_buffer_pool(policy: ReplacementPolicy) -> Result<()> {
+        let db_data = tempfile::tempdir()?;
+        let db_oid: Oid = 5;
+        fs::create_dir_all(db_data.path().join("base").join(db_oid.to_string()))?;
+
+        // The relation has more pages than the buffer pool can hold.
+        let buffer_pool =
+            BufferPool::new(3, StorageManager::new(db_data.path())).with_replacement_policy(policy);
+        let rel = access::open_relation(10000, DEFAULTTABLESPACE_OID, &db_oid, "test");
+
+        let total_tuples = 5000;
+        for i in 0..total_tuples {
+            heap_insert(&buffer_pool, &rel, &HeapTuple::with_default_header(&i)?)?;
         }
+        assert!(buffer_pool.size_of_relation(&rel)? > 3);
+
+        let mut scanner = HeapScanner::new(&buffer_pool, &rel)?;
+        let mut tuples = 0;
+        while let Some(tuple) = scanner.next_tuple()? {
+            assert_eq!(bincode::deserialize::<i32>(&tuple.data)?, tuples);
+            tuples += 1;
+        }
+        assert_eq!(tuples, total_tuples);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_buffer_pool_stats() -> Result<()> {
+        let db_data = tempfile::tempdir()?;
+        let db_oid: Oid = 5;
+        fs::create_dir_all(db_data.path().join("base").join(db_oid.to_string()))?;
+        let rel = access::open_relation(10000, DEFAULTTABLESPACE_OID, &db_oid, "test");
+
+        let buffer_pool = BufferPool::new(3, StorageManager::new(db_data.path()));
+        heap_insert(&buffer_pool, &rel, &HeapTuple::with_default_header(&42)?)?;
+        let stats = buffer_pool.stats();
+        assert_eq!(stats.buffers, 3);
+        assert_eq!(stats.used, 1);
+        assert_eq!(stats.dirty, 1);
+        assert_eq!(stats.pinned, 0);
+
+        let buffer = buffer_pool.fetch_buffer(&rel, 1)?;
+        let other = buffer_pool.fetch_buffer(&rel, 1)?;
+        assert_eq!(buffer_pool.stats().pinned, 1);
+        drop(buffer);
+        assert_eq!(buffer_pool.stats().pinned, 1);
+        drop(other);
+        assert_eq!(buffer_pool.stats().pinned, 0);
+
+        let hits = buffer_pool.stats().hits;
+        buffer_pool.checkpoint()?;
+        let stats = buffer_pool.stats();
+        assert_eq!(stats.dirty, 0);
+        assert_eq!(stats.writes, 1);
+        assert_eq!(stats.hits, hits);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_dirty_buffers() -> Result<()> {
+        let db_data = tempfile::tempdir()?;
+        let db_oid: Oid = 5;
+        fs::create_dir_all(db_data.path().join("base").join(db_oid.to_string()))?;
+        let rel = access::open_relation(10000, DEFAULTTABLESPACE_OID, &db_oid, "test");
+
+        let buffer_pool = BufferPool::new(4, StorageManager::new(db_data.path()));
+        for _ in 0..4 {
+            buffer_pool.alloc_buffer(&rel)?.mark_dirty();
+        }
+        assert_eq!(buffer_pool.stats().dirty, 4);
+
+        // Enough clean buffers, nothing is written.
+        assert_eq!(buffer_pool.write_dirty_buffers(10, 0)?, 0);
+
+        // Pinned buffers are not written.
+        let pinned = buffer_pool.fetch_buffer(&rel, 1)?;
+        assert_eq!(buffer_pool.write_dirty_buffers(2, 4)?, 2);
+        assert_eq!(buffer_pool.stats().dirty, 2);
+        assert_eq!(buffer_pool.write_dirty_buffers(10, 4)?, 1);
+        assert_eq!(buffer_pool.stats().dirty, 1);
+        assert!(pinned.buffer.is_dirty.load(Ordering::SeqCst));
+        drop(pinned);
+
+        assert_eq!(buffer_pool.write_dirty_buffers(10, 4)?, 1);
+        assert_eq!(buffer_pool.stats().dirty, 0);
+        assert_eq!(buffer_pool.stats().writes, 4);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_prefetch() -> Result<()> {
+        let db_data = tempfile::tempdir()?;
+        let db_oid: Oid = 5;
+        fs::create_dir_all(db_data.path().join("base").join(db_oid.to_string()))?;
+        let rel = access::open_relation(10000, DEFAULTTABLESPACE_OID, &db_oid, "test");
+
+        let buffer_pool = BufferPool::new(4, StorageManager::new(db_data.path()));
+        for _ in 0..4 {
+            buffer_pool.alloc_buffer(&rel)?.mark_dirty();
+        }
+        buffer_pool.flush_all_buffers()?;
+        drop(buffer_pool);
+
+        let buffer_pool = BufferPool::new(3, StorageManager::new(db_data.path()));
+        assert!(buffer_pool.prefetch(&rel, 1..=4).is_none());
+
+        let buffer_pool = buffer_pool.with_prefetch_depth(4);
+        let buffer = buffer_pool.fetch_buffer(&rel, 1)?;
+
+        // Pages already in memory are skipped and the prefetch stops without free buffers.
+        buffer_pool.prefetch(&rel, 1..=4).unwrap().wait();
+        let stats = buffer_pool.stats();
+        assert_eq!(stats.used, 3);
+        assert_eq!(stats.reads, 3);
+        assert_eq!(stats.pinned, 1);
+        drop(buffer);
+
+        assert_eq!(buffer_pool.fetch_buffer(&rel, 3)?.page_number(), 3);
+        assert_eq!(buffer_pool.stats().hits, 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_fetch_buffer_async() -> Result<()> {
+        let db_data = tempfile::tempdir()?;
+        let db_oid: Oid = 5;
+        fs::create_dir_all(db_data.path().join("base").join(db_oid.to_string()))?;
+        let rel = access::open_relation(10000, DEFAULTTABLESPACE_OID, &db_oid, "test");
+
+        let mut smgr = StorageManager::new(db_data.path());
+        for i in 1..=4 {
+            let page_number = smgr.extend(&rel)?;
+            smgr.write_async(&rel, page_number, &Page::new([i; PAGE_SIZE]))
+                .await?;
+        }
+        let page = Page::default();
+        smgr.read_async(&rel, 2, &page).await?;
+        assert_eq!(page, Page::new([2; PAGE_SIZE]));
+        assert!(smgr.read_async(&rel, 5, &page).await.is_err());
+
+        // Pages not in memory are read on the blocking thread pool, replacing unpinned buffers.
+        let buffer_pool = BufferPool::new(2, smgr);
+        for page_number in 1..=4 {
+            let buffer = buffer_pool.fetch_buffer_async(&rel, page_number).await?;
+            assert_eq!(buffer.page, Page::new([page_number as u8; PAGE_SIZE]));
+        }
+        let buffer = buffer_pool.fetch_buffer_async(&rel, 4).await?;
+        assert_eq!(buffer.page_number(), 4);
+        let stats = buffer_pool.stats();
+        assert_eq!(stats.reads, 4);
+        assert_eq!(stats.hits, 1);
+        assert_eq!(stats.pinned, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_strategy_buffer_reuse_ring() -> Result<()> {
+        let db_data = tempfile::tempdir()?;
+        let db_oid: Oid = 5;
+        fs::create_dir_all(db_data.path().join("base").join(db_oid.to_string()))?;
+
+        let buffer_pool = BufferPool::new(16, StorageManager::new(db_data.path()));
+        let large = access::open_relation(10000, DEFAULTTABLESPACE_OID, &db_oid, "large");
+        let hot = access::open_relation(10001, DEFAULTTABLESPACE_OID, &db_oid, "hot");
+
+        for _ in 0..40 {
+            buffer_pool.alloc_buffer(&large)?.mark_dirty();
+        }
+        for _ in 0..4 {
+            buffer_pool.alloc_buffer(&hot)?.mark_dirty();
+        }
+
+        let mut strategy = BufferAccessStrategy::new(2);
+        for page_number in 1..=40 {
+            let buffer =
+                buffer_pool.fetch_buffer_with_strategy(&large, page_number, &mut strategy)?;
+            assert_eq!(buffer.page_number(), page_number);
+        }
+
+        // The pages of hot relation are still in memory after the scan.
+        let page_table = buffer_pool.page_table.read().unwrap();
+        for page_number in 1..=4 {
+            assert!(page_table.contains_key(&BufferTag::new(page_number, &hot)));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_page_checksum() -> Result<()> {
+        let db_data = tempfile::tempdir()?;
+        let db_oid: Oid = 5;
+        fs::create_dir_all(db_data.path().join("base").join(db_oid.to_string()))?;
+        let rel = access::open_relation(10000, DEFAULTTABLESPACE_OID, &db_oid, "test");
+
+        {
+            let buffer_pool = BufferPool::new(3, StorageManager::new(db_data.path()));
+            heap_insert(&buffer_pool, &rel, &HeapTuple::with_default_header(&42)?)?;
+            buffer_pool.checkpoint()?;
+        }
+
+        let buffer_pool = BufferPool::new(3, StorageManager::new(db_data.path()))
+            .with_checksum_verification(true);
+        buffer_pool.fetch_buffer(&rel, 1)?;
+        drop(buffer_pool);
+
+        // Corrupt the tuple data stored at the end of the page.
+        let path = db_data
+            .path()
+            .join("base")
+            .join(db_oid.to_string())
+            .join("10000");
+        let mut data = fs::read(&path)?;
+        data[PAGE_SIZE - 1] ^= 0xFF;
+        fs::write(&path, data)?;
+
+        let buffer_pool = BufferPool::new(3, StorageManager::new(db_data.path()))
+            .with_checksum_verification(true);
+        let err = match buffer_pool.fetch_buffer(&rel, 1) {
+            Ok(_) => panic!("expected checksum failure on corrupted page"),
+            Err(err) => err,
+        };
+        assert!(matches!(
+            err.downcast_ref::<disk::Error>(),
+            Some(disk::Error::ChecksumFailure { page_number: 1, .. })
+        ));
+
+        // The buffer of the corrupted page can be used again.
+        assert_eq!(buffer_pool.free_list.lock().unwrap().len(), 3);
+
+        // Corrupted pages can still be read if checksums are not verified.
+        let buffer_pool = buffer_pool.with_checksum_verification(false);
+        buffer_pool.fetch_buffer(&rel, 1)?;
+
+        Ok(())
     }
 }
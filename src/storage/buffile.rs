@@ -0,0 +1,134 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use anyhow::Result;
+
+/// Directory, relative to the data directory, where temporary files are created.
+pub const TEMP_FILES_DIR: &str = "base/pgsql_tmp";
+
+/// Prefix of the name of temporary files, followed by the server process id and a counter.
+const TEMP_FILE_PREFIX: &str = "pgsql_tmp";
+
+/// Number of temporary files created by the server process, used to give each file an unique
+/// name.
+static TEMP_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// A temporary file used by queries to store data that don't fit in memory, like the sorted runs
+/// of a large sort. The file is removed when dropped.
+pub struct BufFile {
+    /// Opened file, for both reading and writing.
+    file: File,
+
+    /// Location of the file, removed when the file is dropped.
+    path: PathBuf,
+}
+
+impl BufFile {
+    /// Create a new empty temporary file inside the given directory, creating the directory if it
+    /// does not exist.
+    pub fn create(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join(format!(
+            "{}{}.{}",
+            TEMP_FILE_PREFIX,
+            process::id(),
+            TEMP_FILE_COUNTER.fetch_add(1, Ordering::SeqCst)
+        ));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        Ok(Self { file, path })
+    }
+
+    /// Return the opened file. Reads and writes start at the current position of the file.
+    pub fn file(&self) -> &File {
+        &self.file
+    }
+
+    /// Move the position of the file back to the start, so the data written can be read.
+    pub fn rewind(&mut self) -> Result<()> {
+        self.file.seek(SeekFrom::Start(0))?;
+        Ok(())
+    }
+
+    /// Return the location of the file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for BufFile {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_file(&self.path) {
+            log::warn!("could not remove temporary file {:?}: {}", self.path, err);
+        }
+    }
+}
+
+/// Remove all temporary files of the given directory, left by queries that were running when the
+/// server stopped.
+pub fn remove_temp_files(dir: &Path) -> Result<()> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err.into()),
+    };
+    for entry in entries {
+        let path = entry?.path();
+        let is_temp_file = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with(TEMP_FILE_PREFIX));
+        if is_temp_file {
+            log::info!("removing temporary file {:?}", path);
+            fs::remove_file(&path)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+
+    use super::*;
+
+    #[test]
+    fn test_buf_file() -> Result<()> {
+        let data_dir = tempfile::tempdir()?;
+        let dir = data_dir.path().join(TEMP_FILES_DIR);
+
+        let mut file = BufFile::create(&dir)?;
+        let other = BufFile::create(&dir)?;
+        assert_ne!(file.path(), other.path());
+
+        file.file().write_all(b"sorted run")?;
+        file.rewind()?;
+        let mut data = String::new();
+        file.file().read_to_string(&mut data)?;
+        assert_eq!(data, "sorted run");
+
+        // Files are removed when dropped.
+        let path = file.path().to_path_buf();
+        drop(file);
+        assert!(!path.exists());
+
+        // Files left by a previous server run are removed.
+        let path = other.path().to_path_buf();
+        std::mem::forget(other);
+        fs::write(dir.join("other"), b"")?;
+        remove_temp_files(&dir)?;
+        assert!(!path.exists());
+        assert!(dir.join("other").exists());
+        remove_temp_files(&data_dir.path().join("missing"))?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,270 @@
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    wal::{Lsn, INVALID_LSN},
+    PAGE_SIZE,
+};
+
+/// Path of the control file inside the data directory.
+pub const CONTROL_FILE: &str = "global/pg_control";
+
+/// Version of the system catalogs format. Data directories initialized with a different version
+/// can not be used by this server.
+///
+/// The version must be changed when a change on the catalogs or on the format of pages makes the
+/// existing data directories incompatible.
+pub const CATALOG_VERSION: u32 = 202610142;
+
+/// Catalog version of the previous format, where tuples were serialized with bincode. Data
+/// directories initialized with this version can be converted to CATALOG_VERSION using the
+/// upgrade subcommand.
+pub const PREVIOUS_CATALOG_VERSION: u32 = 202210141;
+
+/// Errors of reading and validating the control file.
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum Error {
+    #[error(
+        "control file {0:?} does not exist, the data directory must be initialized with --init"
+    )]
+    NotFound(PathBuf),
+
+    #[error("control file {0:?} is corrupted")]
+    Corrupted(PathBuf),
+
+    #[error("database files are incompatible with server: the data directory was initialized with catalog version {found}, but the server was compiled with catalog version {expected}")]
+    IncompatibleCatalogVersion { found: u32, expected: u32 },
+
+    #[error("database files were initialized with catalog version {found} of a previous server version, run tinydb upgrade to convert them to catalog version {expected}")]
+    UpgradeRequired { found: u32, expected: u32 },
+
+    #[error("database files are incompatible with server: the data directory was initialized with page size {found}, but the server was compiled with page size {expected}")]
+    IncompatiblePageSize { found: u32, expected: u32 },
+}
+
+/// State of the database cluster.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum ClusterState {
+    /// The server was shut down cleanly, all changes are stored on relation files.
+    Shutdown,
+
+    /// The server is shutting down and a last checkpoint is being performed.
+    ShuttingDown,
+
+    /// The server is running. If the server is started on this state the last shutdown was not
+    /// clean and changes must be recovered from WAL.
+    InProduction,
+}
+
+impl fmt::Display for ClusterState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Shutdown => write!(f, "shut down"),
+            Self::ShuttingDown => write!(f, "shutting down"),
+            Self::InProduction => write!(f, "in production"),
+        }
+    }
+}
+
+/// Contents of the control file.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ControlFileData {
+    /// Catalog version used to initialize the data directory.
+    pub catalog_version: u32,
+
+    /// Size of pages of relation files.
+    pub page_size: u32,
+
+    /// LSN of the last checkpoint record.
+    pub checkpoint_lsn: Lsn,
+
+    /// Current state of the cluster.
+    pub state: ClusterState,
+}
+
+impl Default for ControlFileData {
+    fn default() -> Self {
+        Self {
+            catalog_version: CATALOG_VERSION,
+            page_size: PAGE_SIZE as u32,
+            checkpoint_lsn: INVALID_LSN,
+            state: ClusterState::Shutdown,
+        }
+    }
+}
+
+/// Control file handle, storing global information about the database cluster.
+///
+/// ControlFile is reference counted and clonning will just increase the reference counter.
+#[derive(Clone)]
+pub struct ControlFile {
+    path: PathBuf,
+    data: Arc<Mutex<ControlFileData>>,
+}
+
+impl ControlFile {
+    /// Create a new control file with the default values inside the given data directory,
+    /// replacing the existing one if any.
+    pub fn create(data_dir: &Path) -> Result<Self> {
+        let control = Self {
+            path: data_dir.join(CONTROL_FILE),
+            data: Arc::new(Mutex::new(ControlFileData::default())),
+        };
+        control.write(&ControlFileData::default())?;
+        Ok(control)
+    }
+
+    /// Open the control file of the given data directory, returning an error if the data
+    /// directory is not initialized or is incompatible with this server.
+    pub fn open(data_dir: &Path) -> Result<Self> {
+        let control = Self::open_for_upgrade(data_dir)?;
+        let data = control.data();
+        if data.catalog_version == PREVIOUS_CATALOG_VERSION {
+            bail!(Error::UpgradeRequired {
+                found: data.catalog_version,
+                expected: CATALOG_VERSION,
+            });
+        }
+        if data.catalog_version != CATALOG_VERSION {
+            bail!(Error::IncompatibleCatalogVersion {
+                found: data.catalog_version,
+                expected: CATALOG_VERSION,
+            });
+        }
+        if data.page_size != PAGE_SIZE as u32 {
+            bail!(Error::IncompatiblePageSize {
+                found: data.page_size,
+                expected: PAGE_SIZE as u32,
+            });
+        }
+
+        Ok(control)
+    }
+
+    /// Open the control file of the given data directory without checking if the data directory
+    /// is compatible with this server, so that data directories initialized by previous versions
+    /// can be upgraded.
+    pub fn open_for_upgrade(data_dir: &Path) -> Result<Self> {
+        let path = data_dir.join(CONTROL_FILE);
+        if !path.exists() {
+            bail!(Error::NotFound(path));
+        }
+
+        let data = match bincode::deserialize::<ControlFileData>(&fs::read(&path)?) {
+            Ok(data) => data,
+            Err(_) => bail!(Error::Corrupted(path)),
+        };
+        Ok(Self {
+            path,
+            data: Arc::new(Mutex::new(data)),
+        })
+    }
+
+    /// Return the current contents of the control file.
+    pub fn data(&self) -> ControlFileData {
+        self.data.lock().unwrap().clone()
+    }
+
+    /// Change the cluster state.
+    pub fn set_state(&self, state: ClusterState) -> Result<()> {
+        self.update(|data| data.state = state)
+    }
+
+    /// Change the catalog version of the data directory, after its files were converted.
+    pub fn set_catalog_version(&self, version: u32) -> Result<()> {
+        self.update(|data| data.catalog_version = version)
+    }
+
+    /// Change the location of the last checkpoint record.
+    pub fn set_checkpoint_lsn(&self, lsn: Lsn) -> Result<()> {
+        self.update(|data| data.checkpoint_lsn = lsn)
+    }
+
+    /// Apply the given change on control file contents and write it on disk.
+    fn update<F>(&self, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut ControlFileData),
+    {
+        let mut data = self.data.lock().unwrap();
+        f(&mut data);
+        self.write(&data)
+    }
+
+    /// Write the given contents on control file. The contents are written on a temporary file
+    /// that replace the control file, so a crash in the middle of a write don't leave the
+    /// control file corrupted.
+    fn write(&self, data: &ControlFileData) -> Result<()> {
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, bincode::serialize(data)?)?;
+        fs::File::open(&tmp)?.sync_all()?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_control_file() -> Result<()> {
+        let data_dir = tempfile::tempdir()?;
+        fs::create_dir_all(data_dir.path().join("global"))?;
+
+        let err = ControlFile::open(data_dir.path()).err().unwrap();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::NotFound(_))
+        ));
+
+        let control = ControlFile::create(data_dir.path())?;
+        control.set_state(ClusterState::InProduction)?;
+        control.set_checkpoint_lsn(42)?;
+
+        let data = ControlFile::open(data_dir.path())?.data();
+        assert_eq!(data.state, ClusterState::InProduction);
+        assert_eq!(data.checkpoint_lsn, 42);
+        assert_eq!(data.page_size, PAGE_SIZE as u32);
+
+        control.update(|data| data.catalog_version = 1)?;
+        let err = ControlFile::open(data_dir.path()).err().unwrap();
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::IncompatibleCatalogVersion {
+                found: 1,
+                expected: CATALOG_VERSION
+            })
+        );
+
+        control.set_catalog_version(PREVIOUS_CATALOG_VERSION)?;
+        let err = ControlFile::open(data_dir.path()).err().unwrap();
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::UpgradeRequired {
+                found: PREVIOUS_CATALOG_VERSION,
+                expected: CATALOG_VERSION
+            })
+        );
+        assert_eq!(
+            ControlFile::open_for_upgrade(data_dir.path())?
+                .data()
+                .catalog_version,
+            PREVIOUS_CATALOG_VERSION
+        );
+
+        fs::write(data_dir.path().join(CONTROL_FILE), b"invalid")?;
+        let err = ControlFile::open(data_dir.path()).err().unwrap();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::Corrupted(_))
+        ));
+
+        Ok(())
+    }
+}
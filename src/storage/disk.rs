@@ -1,12 +1,16 @@
 use anyhow::{bail, Result};
 use log::debug;
 use std::fs::{File, OpenOptions};
-use std::io::{Read, Seek, SeekFrom, Write};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::os::unix::fs::FileExt;
 use std::path::Path;
 use std::sync::atomic::{AtomicU32, Ordering};
-use std::sync::Mutex;
 
-use crate::storage::{Page, PageNumber, PAGE_SIZE};
+use crate::storage::{
+    page::{page_init, page_set_checksum, PageHeader},
+    smgr::RelationStorage,
+    Page, PageNumber, PAGE_SIZE,
+};
 
 /// Represents errors that disk can have.
 #[derive(thiserror::Error, Debug, PartialEq)]
@@ -19,6 +23,15 @@ pub enum Error {
     /// is different than [MAGIC_BYTES].
     #[error("Corrupted database file")]
     CorruptedFile,
+
+    /// The checksum stored on a page read from disk does not match the page contents.
+    #[error("invalid page {page_number} of relation {rel_name}: page verification failed, calculated checksum {calculated} but expected {expected}")]
+    ChecksumFailure {
+        rel_name: String,
+        page_number: PageNumber,
+        calculated: u16,
+        expected: u16,
+    },
 }
 
 /// Disk handle all read/write operations on database file.
@@ -29,10 +42,32 @@ pub enum Error {
 /// The Disk is very simple and always creates an in-memory copy of any page
 /// that is read (even if that page has already been read before).
 /// More specifically, pages are read into a MemPage structure.
+///
+/// Pages are read and written at their offset on the file without changing the position of the
+/// file, so many pages of the same file can be read and written at the same time.
 #[derive(Debug)]
 pub struct Disk {
-    file: Mutex<File>,
+    file: File,
     total_pages: AtomicU32,
+
+    /// The file was opened with O_DIRECT, so pages are read and written using aligned buffers.
+    direct_io: bool,
+}
+
+/// Alignment of the memory and file offsets used by direct I/O, which is the logical block size
+/// of most devices. Pages are always stored on offsets multiple of the page size.
+const DIRECT_IO_ALIGNMENT: usize = 4096;
+
+/// A page buffer aligned to DIRECT_IO_ALIGNMENT, used to read and write pages of files opened
+/// with direct I/O.
+#[repr(C, align(4096))]
+struct AlignedPage([u8; PAGE_SIZE]);
+
+impl AlignedPage {
+    fn new() -> Box<Self> {
+        debug_assert_eq!(std::mem::align_of::<Self>(), DIRECT_IO_ALIGNMENT);
+        Box::new(Self([0; PAGE_SIZE]))
+    }
 }
 
 impl Disk {
@@ -43,13 +78,38 @@ impl Disk {
     /// Disk is given a filename for a file that does not exist) then this
     /// function will initialize the file header using the default values.
     pub fn open(filename: &Path) -> Result<Self> {
-        let file = OpenOptions::new()
-            .create(true)
-            .read(true)
-            .write(true)
-            .open(filename)?;
+        Self::open_with_direct_io(filename, false)
+    }
+
+    /// Same as open, but if direct_io is true the file is opened with O_DIRECT, so pages are read
+    /// and written directly from the device without being cached by the operating system. If the
+    /// platform or the file system don't support direct I/O, the file is opened without it.
+    pub fn open_with_direct_io(filename: &Path, direct_io: bool) -> Result<Self> {
+        let mut options = OpenOptions::new();
+        options.create(true).read(true).write(true);
+
+        let direct_file = if direct_io {
+            match open_direct(&options, filename) {
+                Ok(file) => Some(file),
+                Err(err) => {
+                    log::warn!(
+                        "could not open {:?} with direct I/O, using buffered I/O: {}",
+                        filename,
+                        err
+                    );
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
         let disk = Self {
-            file: Mutex::new(file),
+            direct_io: direct_file.is_some(),
+            file: match direct_file {
+                Some(file) => file,
+                None => options.open(filename)?,
+            },
             total_pages: AtomicU32::new(0),
         };
         disk.total_pages.store(disk.size()?, Ordering::Relaxed);
@@ -57,6 +117,11 @@ impl Disk {
         Ok(disk)
     }
 
+    /// Return true if the file was opened with direct I/O.
+    pub fn is_direct_io(&self) -> bool {
+        self.direct_io
+    }
+
     /// Read a page from file.  
     ///
     /// Reads a page from the disk, and updates the given in-memory Page struct. Any changes done
@@ -64,11 +129,16 @@ impl Disk {
     /// Page.
     pub fn read_page(&self, page_number: PageNumber, page: &Page) -> Result<()> {
         self.validate_page(page_number)?;
-        let mut file = self.file.lock().unwrap();
-        file.seek(SeekFrom::Start(self.offset(page_number)))?;
 
-        let mut page = page.0.write().unwrap();
-        let count = file.read(page.as_mut())?;
+        let offset = self.offset(page_number);
+        let count = if self.direct_io {
+            let mut aligned = AlignedPage::new();
+            let count = self.file.read_at(&mut aligned.0, offset)?;
+            page.write(|page| page.copy_from_slice(&aligned.0));
+            count
+        } else {
+            page.write(|page| self.file.read_at(page, offset))?
+        };
         debug!("read {} bytes from page {}", count, page_number);
 
         Ok(())
@@ -80,28 +150,76 @@ impl Disk {
     pub fn write_page(&self, number: PageNumber, page: &Page) -> Result<()> {
         self.validate_page(number)?;
 
-        let mut file = self.file.lock().unwrap();
-        file.seek(SeekFrom::Start(self.offset(number)))?;
-
-        let page = page.0.read().unwrap();
-        file.write_all(page.as_ref())?;
-        file.flush()?;
+        if self.direct_io {
+            let mut aligned = AlignedPage::new();
+            aligned.0.copy_from_slice(page.0.read().unwrap().as_ref());
+            self.file.write_all_at(&aligned.0, self.offset(number))?;
+        } else {
+            let page = page.0.read().unwrap();
+            self.file.write_all_at(page.as_ref(), self.offset(number))?;
+        }
         debug!("wrote page {} to disk", number);
 
         Ok(())
     }
 
-    /// Allocate an extra page on the file and returns the page number
+    /// Force all written pages of the file to be stored on the physical device.
+    pub fn sync(&self) -> Result<()> {
+        self.file.sync_all()?;
+        Ok(())
+    }
+
+    /// Allocate an extra page on the file and returns the page number.
+    ///
+    /// The new page is written already initialized, so if the server crashes before the page
+    /// contents are written the page is read as a valid empty page, which can be reused by new
+    /// tuples.
     pub fn allocate_page(&self) -> Result<u32> {
         let new_page = self.total_pages.fetch_add(1, Ordering::SeqCst) + 1;
-        self.write_page(new_page, &Page::default())?;
+        let page = Page::default();
+        page_init(&page)?;
+        page_set_checksum(&page, new_page)?;
+        self.write_page(new_page, &page)?;
         Ok(new_page)
     }
 
+    /// Remove the pages at the end of the given file that were not completely written, e.g.
+    /// because the server crashed while extending the file: a partial page at the end of the
+    /// file and the last pages without a valid page header. Extensions that were logged on WAL
+    /// are redone by recovery.
+    ///
+    /// The file must not be opened by any Disk. Return the number of bytes removed.
+    pub fn truncate_invalid_pages(filename: &Path) -> Result<u64> {
+        let mut file = OpenOptions::new().read(true).write(true).open(filename)?;
+        let len = file.metadata()?.len();
+
+        let mut pages = len / PAGE_SIZE as u64;
+        while pages > 0 {
+            let mut data = [0; PAGE_SIZE];
+            file.seek(SeekFrom::Start((pages - 1) * PAGE_SIZE as u64))?;
+            file.read_exact(&mut data)?;
+            if PageHeader::new(&Page::new(data))?.is_valid() {
+                break;
+            }
+            pages -= 1;
+        }
+
+        let valid_len = pages * PAGE_SIZE as u64;
+        if valid_len < len {
+            log::warn!(
+                "removing {} bytes of pages not completely written at the end of {:?}",
+                len - valid_len,
+                filename
+            );
+            file.set_len(valid_len)?;
+            file.sync_all()?;
+        }
+        Ok(len - valid_len)
+    }
+
     /// Computes the number of pages in a file.
     pub fn size(&self) -> Result<u32> {
-        let file = self.file.lock().unwrap();
-        let len = file.metadata()?.len();
+        let len = self.file.metadata()?.len();
         if len == 0 || len as usize == 0 {
             // If len is equal 0 means that the file is empty.
             // If len - HEADER_SIZE is equal 0 means that the
@@ -130,9 +248,48 @@ impl Disk {
     }
 }
 
+/// Open the given file with O_DIRECT using the given options.
+#[cfg(target_os = "linux")]
+fn open_direct(options: &OpenOptions, filename: &Path) -> io::Result<File> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    options.clone().custom_flags(libc::O_DIRECT).open(filename)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn open_direct(_options: &OpenOptions, _filename: &Path) -> io::Result<File> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "direct I/O is not supported on this platform",
+    ))
+}
+
+impl RelationStorage for Disk {
+    fn read_page(&self, page_number: PageNumber, page: &Page) -> Result<()> {
+        Disk::read_page(self, page_number, page)
+    }
+
+    fn write_page(&self, page_number: PageNumber, page: &Page) -> Result<()> {
+        Disk::write_page(self, page_number, page)
+    }
+
+    fn allocate_page(&self) -> Result<PageNumber> {
+        Disk::allocate_page(self)
+    }
+
+    fn size(&self) -> Result<u32> {
+        Disk::size(self)
+    }
+
+    fn sync(&self) -> Result<()> {
+        Disk::sync(self)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::io::Write;
     use tempfile::NamedTempFile;
 
     #[test]
@@ -185,8 +342,8 @@ mod tests {
             let page_data = Page::new([i; PAGE_SIZE]);
             disk.write_page(page_number, &page_data)?;
 
-            let mut page = Page::default();
-            disk.read_page(page_number, &mut page)?;
+            let page = Page::default();
+            disk.read_page(page_number, &page)?;
 
             assert_eq!(page_data, page);
         }
@@ -194,11 +351,54 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_allocate_initialized_page() -> Result<()> {
+        let disk = open_test_disk()?;
+        let page_number = disk.allocate_page()?;
+
+        let page = Page::default();
+        disk.read_page(page_number, &page)?;
+        let header = PageHeader::new(&page)?;
+        assert!(header.is_valid());
+        assert_eq!(header.free_space(), PageHeader::default().free_space());
+        assert!(crate::storage::page::page_verify_checksum(
+            &page,
+            page_number
+        )?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_truncate_invalid_pages() -> Result<()> {
+        let file = NamedTempFile::new()?;
+        {
+            let disk = Disk::open(file.path())?;
+            disk.allocate_page()?;
+            disk.allocate_page()?;
+
+            // A page allocated on a previous version, never initialized.
+            let page_number = disk.allocate_page()?;
+            disk.write_page(page_number, &Page::default())?;
+        }
+        assert_eq!(Disk::truncate_invalid_pages(file.path())?, PAGE_SIZE as u64);
+
+        // A torn extension, where only part of the page was written.
+        OpenOptions::new()
+            .append(true)
+            .open(file.path())?
+            .write_all(&[1; 100])?;
+        assert_eq!(Disk::truncate_invalid_pages(file.path())?, 100);
+
+        assert_eq!(Disk::truncate_invalid_pages(file.path())?, 0);
+        assert_eq!(Disk::open(file.path())?.size()?, 2);
+        Ok(())
+    }
+
     #[test]
     fn test_read_invalid_page() -> Result<()> {
         let disk = open_test_disk()?;
-        let mut page = Page::default();
-        let result = disk.read_page(1, &mut page);
+        let page = Page::default();
+        let result = disk.read_page(1, &page);
 
         let err = result.unwrap_err();
         assert_eq!(
@@ -208,6 +408,29 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_direct_io() -> Result<()> {
+        // Direct I/O is not supported by some file systems, e.g. tmpfs on older kernels, where
+        // the file is opened with buffered I/O instead.
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("1");
+        let disk = Disk::open_with_direct_io(&path, true)?;
+        for i in 0..4 {
+            let page_number = disk.allocate_page()?;
+            disk.write_page(page_number, &Page::new([i; PAGE_SIZE]))?;
+        }
+        drop(disk);
+
+        let disk = Disk::open_with_direct_io(&path, true)?;
+        assert_eq!(disk.size()?, 4);
+        let page = Page::default();
+        disk.read_page(3, &page)?;
+        assert_eq!(page, Page::new([2; PAGE_SIZE]));
+
+        assert!(!Disk::open(&path)?.is_direct_io());
+        Ok(())
+    }
+
     fn open_test_disk() -> Result<Disk> {
         let file = NamedTempFile::new()?;
         Disk::open(file.path())
@@ -0,0 +1,167 @@
+//! Fault injection on the page writes of a storage manager, used to test crash recovery.
+//!
+//! A FaultInjector simulates a crash of the server on the Nth page write: the write fails or is
+//! torn, and all page writes after it fail, so the relation files are left as they were at the
+//! moment of the crash. The database using the storage manager can then be discarded without a
+//! shutdown checkpoint and opened again to recover from the write-ahead log.
+
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use anyhow::{bail, Result};
+
+use super::{smgr::RelationStorage, Page, PageNumber, PAGE_SIZE};
+
+/// Fault injected on a page write.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fault {
+    /// The write fails without changing the page on storage.
+    Fail,
+
+    /// Only the first half of the page is written before the write fails, like a crash in the
+    /// middle of an operating system write that is not atomic.
+    TornWrite,
+}
+
+/// Error returned by the page writes of a storage manager after it crashed by an injected fault.
+#[derive(thiserror::Error, Debug, PartialEq)]
+#[error("storage crashed by injected fault")]
+pub struct InjectedFault;
+
+/// Inject a fault on the Nth page write of the storage managers that use it, counting from 1.
+///
+/// Writes are counted on all relations, so the same workload always crashes on the same write.
+/// Extensions of relations are not counted, but fail after the crash like page writes.
+#[derive(Debug)]
+pub struct FaultInjector {
+    fault: Fault,
+
+    /// Number of the write that crashes the storage.
+    crash_at: u64,
+
+    /// Number of page writes attempted, including the ones that failed.
+    writes: AtomicU64,
+}
+
+impl FaultInjector {
+    /// Create a new fault injector that injects the given fault on the given page write.
+    pub fn new(fault: Fault, crash_at: u64) -> Self {
+        Self {
+            fault,
+            crash_at: crash_at.max(1),
+            writes: AtomicU64::new(0),
+        }
+    }
+
+    /// Create a new fault injector that never injects a fault, e.g. to count the page writes of
+    /// a workload before choosing where it should crash.
+    pub fn disabled() -> Self {
+        Self::new(Fault::Fail, u64::MAX)
+    }
+
+    /// Return the number of page writes attempted so far.
+    pub fn writes(&self) -> u64 {
+        self.writes.load(Ordering::SeqCst)
+    }
+
+    /// Return true if the fault was already injected.
+    pub fn crashed(&self) -> bool {
+        self.writes() >= self.crash_at
+    }
+}
+
+/// Relation storage that injects the faults of a FaultInjector on the writes of another storage.
+pub(super) struct FaultyStorage {
+    storage: Arc<dyn RelationStorage>,
+    injector: Arc<FaultInjector>,
+}
+
+impl FaultyStorage {
+    pub(super) fn new(storage: Arc<dyn RelationStorage>, injector: Arc<FaultInjector>) -> Self {
+        Self { storage, injector }
+    }
+}
+
+impl RelationStorage for FaultyStorage {
+    fn read_page(&self, page_number: PageNumber, page: &Page) -> Result<()> {
+        self.storage.read_page(page_number, page)
+    }
+
+    fn write_page(&self, page_number: PageNumber, page: &Page) -> Result<()> {
+        let write = self.injector.writes.fetch_add(1, Ordering::SeqCst) + 1;
+        if write < self.injector.crash_at {
+            return self.storage.write_page(page_number, page);
+        }
+
+        if write == self.injector.crash_at && self.injector.fault == Fault::TornWrite {
+            log::debug!("injecting torn write of page {}", page_number);
+            let torn = Page::default();
+            self.storage.read_page(page_number, &torn)?;
+            let contents = page.0.read().unwrap();
+            torn.write(|torn| torn[..PAGE_SIZE / 2].copy_from_slice(&contents[..PAGE_SIZE / 2]));
+            self.storage.write_page(page_number, &torn)?;
+        }
+        bail!(InjectedFault)
+    }
+
+    fn allocate_page(&self) -> Result<PageNumber> {
+        if self.injector.crashed() {
+            bail!(InjectedFault);
+        }
+        self.storage.allocate_page()
+    }
+
+    fn size(&self) -> Result<u32> {
+        self.storage.size()
+    }
+
+    fn sync(&self) -> Result<()> {
+        if self.injector.crashed() {
+            bail!(InjectedFault);
+        }
+        self.storage.sync()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::memory::MemoryStorage;
+
+    #[test]
+    fn test_fault_injection() -> Result<()> {
+        for fault in [Fault::Fail, Fault::TornWrite] {
+            let memory = Arc::new(MemoryStorage::default());
+            let injector = Arc::new(FaultInjector::new(fault, 2));
+            let storage = FaultyStorage::new(memory.clone(), injector.clone());
+
+            let page_number = storage.allocate_page()?;
+            storage.write_page(page_number, &Page::new([1; PAGE_SIZE]))?;
+            assert!(!injector.crashed());
+
+            let err = storage
+                .write_page(page_number, &Page::new([2; PAGE_SIZE]))
+                .unwrap_err();
+            assert_eq!(err.downcast_ref(), Some(&InjectedFault));
+            assert!(injector.crashed());
+
+            let page = Page::default();
+            memory.read_page(page_number, &page)?;
+            let mut expected = [1; PAGE_SIZE];
+            if fault == Fault::TornWrite {
+                expected[..PAGE_SIZE / 2].fill(2);
+            }
+            assert_eq!(page, Page::new(expected));
+
+            // Nothing is written after the crash.
+            assert!(storage.write_page(page_number, &Page::default()).is_err());
+            assert!(storage.allocate_page().is_err());
+            memory.read_page(page_number, &page)?;
+            assert_eq!(page, Page::new(expected));
+            assert_eq!(injector.writes(), 3);
+        }
+        Ok(())
+    }
+}
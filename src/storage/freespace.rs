@@ -1,12 +1,178 @@
+use std::collections::HashMap;
+
 use anyhow::Result;
 
 use crate::relation::Relation;
 
-use super::{buffer::Buffer, BufferPool};
+use super::{
+    buffer::BufferGuard,
+    page::{page_get_free_space, page_init},
+    relation_locator::RelationLocator,
+    wal::{self, WalRecord},
+    BufferPool, PageNumber,
+};
+
+/// In-memory free space map that records the amount of free bytes on each page of a relation.
+///
+/// The map is not persisted on disk, when a relation is used for the first time the free space of
+/// each page is computed from the page headers and cached for future lookups.
+#[derive(Default)]
+pub struct FreeSpaceMap {
+    /// Free space of each page of a relation. The free space of page number N is stored on N - 1
+    /// index since pages start at 1.
+    relations: HashMap<RelationLocator, Vec<usize>>,
+}
+
+impl FreeSpaceMap {
+    /// Return the first page number with at least the given amount of free space.
+    fn search(&self, rel: &Relation, space_needed: usize) -> Option<PageNumber> {
+        self.relations.get(&rel.locator).and_then(|pages| {
+            pages
+                .iter()
+                .position(|free_space| *free_space >= space_needed)
+                .map(|index| (index + 1) as PageNumber)
+        })
+    }
+
+    /// Remove the free space of all pages of the given relation, e.g. when it is dropped.
+    pub fn forget(&mut self, rel: &Relation) {
+        self.relations.remove(&rel.locator);
+    }
+
+    /// Record the free space of the given page number.
+    fn record(&mut self, rel: &Relation, page_number: PageNumber, free_space: usize) {
+        let pages = self.relations.entry(rel.locator.clone()).or_default();
+        let index = (page_number - 1) as usize;
+        if index >= pages.len() {
+            pages.resize(index + 1, 0);
+        }
+        pages[index] = free_space;
+    }
+}
 
-/// Try to find a page in the given relation with at least the specified amount of free space.
+/// Try to find a page in the given relation with at least the specified amount of free space. If
+/// there is no page with enough free space the relation is extended and the new page is returned.
 ///
-// TODO: Implement visibility map to find free page to add a new tuple
-pub fn get_page_with_free_space(buffer: &BufferPool, rel: &Relation) -> Result<Buffer> {
-    buffer.fetch_buffer(rel, 1)
+/// The returned buffer is pinned.
+pub fn get_page_with_free_space(
+    buffer_pool: &BufferPool,
+    rel: &Relation,
+    space_needed: usize,
+) -> Result<BufferGuard> {
+    load_relation_free_space(buffer_pool, rel)?;
+
+    let page_number = buffer_pool.free_space_map().search(rel, space_needed);
+
+    match page_number {
+        Some(page_number) => buffer_pool.fetch_buffer(rel, page_number),
+        None => extend_relation(buffer_pool, rel),
+    }
+}
+
+/// Extend the given relation with a new initialized page and return the pinned buffer of the new
+/// page.
+pub fn extend_relation(buffer_pool: &BufferPool, rel: &Relation) -> Result<BufferGuard> {
+    let buffer = buffer_pool.alloc_buffer(rel)?;
+    page_init(&buffer.page)?;
+    wal::log_page_change(
+        buffer_pool,
+        &buffer,
+        &WalRecord::ExtendRelation {
+            locator: rel.locator.as_ref().clone(),
+            page_number: buffer.page_number(),
+        },
+    )?;
+
+    record_page_with_free_space(
+        buffer_pool,
+        rel,
+        buffer.page_number(),
+        page_get_free_space(&buffer.page)?,
+    );
+
+    Ok(buffer)
+}
+
+/// Update the free space map with the amount of free space available on the given page.
+pub fn record_page_with_free_space(
+    buffer_pool: &BufferPool,
+    rel: &Relation,
+    page_number: PageNumber,
+    free_space: usize,
+) {
+    buffer_pool
+        .free_space_map()
+        .record(rel, page_number, free_space);
+}
+
+/// Compute the free space of all pages of the given relation if the relation is not being tracked
+/// yet by the free space map.
+fn load_relation_free_space(buffer_pool: &BufferPool, rel: &Relation) -> Result<()> {
+    if buffer_pool
+        .free_space_map()
+        .relations
+        .contains_key(&rel.locator)
+    {
+        return Ok(());
+    }
+
+    let mut pages = Vec::new();
+    for page_number in 1..=buffer_pool.size_of_relation(rel)? {
+        let buffer = buffer_pool.fetch_buffer(rel, page_number)?;
+        pages.push(page_get_free_space(&buffer.page)?);
+    }
+
+    buffer_pool
+        .free_space_map()
+        .relations
+        .insert(rel.locator.clone(), pages);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::{
+        access::{
+            self,
+            heap::{heap_insert, HeapScanner},
+            heaptuple::HeapTuple,
+        },
+        catalog::pg_tablespace::DEFAULTTABLESPACE_OID,
+        storage::smgr::StorageManager,
+        Oid,
+    };
+
+    #[test]
+    fn test_heap_insert_extend_relation() -> Result<()> {
+        let db_data = tempfile::tempdir()?;
+        let db_oid: Oid = 5;
+        fs::create_dir_all(db_data.path().join("base").join(db_oid.to_string()))?;
+
+        let buffer_pool = BufferPool::new(20, StorageManager::new(db_data.path()));
+        let rel = access::open_relation(10000, DEFAULTTABLESPACE_OID, &db_oid, "test");
+
+        let total_tuples = 1000;
+        for i in 0..total_tuples {
+            heap_insert(&buffer_pool, &rel, &HeapTuple::with_default_header(&i)?)?;
+        }
+
+        assert!(
+            buffer_pool.size_of_relation(&rel)? > 1,
+            "Expected relation with more than one page"
+        );
+
+        let mut scanner = HeapScanner::new(&buffer_pool, &rel)?;
+        let mut tuples = 0;
+        while let Some(tuple) = scanner.next_tuple()? {
+            assert_eq!(bincode::deserialize::<i32>(&tuple.data)?, tuples);
+            tuples += 1;
+        }
+        assert_eq!(tuples, total_tuples);
+
+        Ok(())
+    }
 }
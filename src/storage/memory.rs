@@ -0,0 +1,107 @@
+use std::sync::RwLock;
+
+use anyhow::{bail, Result};
+
+use super::{
+    disk::Error,
+    page::{page_init, page_set_checksum},
+    smgr::RelationStorage,
+    Page, PageNumber, PAGE_SIZE,
+};
+
+/// Relation storage that keep all pages in memory, without any file. The pages are lost when the
+/// storage is dropped.
+#[derive(Default)]
+pub struct MemoryStorage {
+    /// Contents of each page. The contents of page number N is stored on N - 1 index since pages
+    /// start at 1.
+    pages: RwLock<Vec<Box<[u8; PAGE_SIZE]>>>,
+}
+
+impl MemoryStorage {
+    /// Return a new storage with a copy of all pages of this storage.
+    pub fn copy(&self) -> Self {
+        Self {
+            pages: RwLock::new(self.pages.read().unwrap().clone()),
+        }
+    }
+
+    /// Return the index of the given page number, failing if the page does not exist.
+    fn index(&self, page_number: PageNumber) -> Result<usize> {
+        let total_pages = self.pages.read().unwrap().len();
+        if page_number == 0 || page_number as usize > total_pages {
+            bail!(Error::IncorrectPageNumber(page_number));
+        }
+        Ok(page_number as usize - 1)
+    }
+}
+
+impl RelationStorage for MemoryStorage {
+    fn read_page(&self, page_number: PageNumber, page: &Page) -> Result<()> {
+        let index = self.index(page_number)?;
+        let pages = self.pages.read().unwrap();
+        page.write(|page| page.copy_from_slice(pages[index].as_ref()));
+        Ok(())
+    }
+
+    fn write_page(&self, page_number: PageNumber, page: &Page) -> Result<()> {
+        let index = self.index(page_number)?;
+        let mut pages = self.pages.write().unwrap();
+        pages[index].copy_from_slice(page.0.read().unwrap().as_ref());
+        Ok(())
+    }
+
+    fn allocate_page(&self) -> Result<PageNumber> {
+        let mut pages = self.pages.write().unwrap();
+        let page_number = pages.len() as PageNumber + 1;
+
+        let page = Page::default();
+        page_init(&page)?;
+        page_set_checksum(&page, page_number)?;
+        pages.push(Box::new(**page.0.read().unwrap()));
+        Ok(page_number)
+    }
+
+    fn size(&self) -> Result<u32> {
+        Ok(self.pages.read().unwrap().len() as u32)
+    }
+
+    fn sync(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_storage() -> Result<()> {
+        let storage = MemoryStorage::default();
+        assert_eq!(storage.size()?, 0);
+
+        for i in 0..10 {
+            let page_number = storage.allocate_page()?;
+            assert_eq!(page_number, i as u32 + 1);
+            storage.write_page(page_number, &Page::new([i; PAGE_SIZE]))?;
+        }
+        assert_eq!(storage.size()?, 10);
+
+        let page = Page::default();
+        storage.read_page(3, &page)?;
+        assert_eq!(page, Page::new([2; PAGE_SIZE]));
+
+        // Changes on a page read from the storage are only stored when the page is written.
+        page_init(&page)?;
+        assert_ne!(page, Page::new([2; PAGE_SIZE]));
+        storage.read_page(3, &page)?;
+        assert_eq!(page, Page::new([2; PAGE_SIZE]));
+
+        let err = storage.read_page(11, &page).unwrap_err();
+        assert_eq!(
+            err.downcast::<Error>().unwrap(),
+            Error::IncorrectPageNumber(11)
+        );
+        Ok(())
+    }
+}
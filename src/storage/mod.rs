@@ -1,15 +1,22 @@
 pub mod buffer;
+pub mod buffile;
+pub mod control;
 pub mod disk;
+pub mod fault;
 pub mod freespace;
+pub mod memory;
 pub mod page;
 pub mod relation_locator;
 pub mod smgr;
+pub mod wal;
 
 use std::{
     io::{self, Cursor, Read, Seek, Write},
     sync::{Arc, RwLock},
 };
 
+use bytes::Bytes;
+
 use self::page::{ItemId, PageHeader, ITEM_ID_SIZE, PAGE_HEADER_SIZE};
 
 pub use buffer::BufferPool;
@@ -39,18 +46,43 @@ pub const PAGE_SIZE: usize = 8192;
 ///
 /// The storage manager is the only pieces of code that should be accessing disk
 /// blocks directly.
+///
+/// The contents of the page are copied on write: writing a page while a snapshot of it is alive
+/// copies the page first, so snapshots can be read without holding the page lock.
 #[derive(Debug)]
-pub struct Page(Arc<RwLock<[u8; PAGE_SIZE]>>);
+pub struct Page(Arc<RwLock<Arc<[u8; PAGE_SIZE]>>>);
 
 impl Page {
     pub fn new(page: [u8; PAGE_SIZE]) -> Self {
-        Self(Arc::new(RwLock::new(page)))
+        Self(Arc::new(RwLock::new(Arc::new(page))))
+    }
+
+    /// Return the current contents of the page without copying them. The snapshot never
+    /// changes, even if the page is written after the snapshot was taken.
+    pub fn snapshot(&self) -> Bytes {
+        Bytes::from_owner(PageSnapshot(self.0.read().unwrap().clone()))
+    }
+
+    /// Call f with a mutable reference to the contents of the page, copying the contents first
+    /// if a snapshot of the page is alive.
+    fn write<R>(&self, f: impl FnOnce(&mut [u8; PAGE_SIZE]) -> R) -> R {
+        let mut contents = self.0.write().unwrap();
+        f(Arc::make_mut(&mut contents))
     }
 }
 
 impl Default for Page {
     fn default() -> Self {
-        Self(Arc::new(RwLock::new([0; PAGE_SIZE])))
+        Self::new([0; PAGE_SIZE])
+    }
+}
+
+/// Contents of a page shared by the datums of the tuples stored on the page.
+struct PageSnapshot(Arc<[u8; PAGE_SIZE]>);
+
+impl AsRef<[u8]> for PageSnapshot {
+    fn as_ref(&self) -> &[u8] {
+        self.0.as_ref()
     }
 }
 
@@ -101,6 +133,15 @@ pub fn value_from_page_item(page: &Page, item: &ItemId) -> anyhow::Result<Vec<u8
     Ok(page[item.offset as usize..(item.offset + item.length) as usize].to_vec())
 }
 
+/// Return the value of the given item of a page snapshot, sharing the memory of the snapshot.
+pub fn value_from_page_snapshot(snapshot: &Bytes, item: &ItemId) -> anyhow::Result<Bytes> {
+    let (start, end) = (item.offset as usize, (item.offset + item.length) as usize);
+    if end > snapshot.len() {
+        anyhow::bail!("item {:?} out of bounds of page", item);
+    }
+    Ok(snapshot.slice(start..end))
+}
+
 /// A buffer page writer.
 ///
 /// BufferPageWriter implements std::io::Write and std::io::Seek traits
@@ -118,27 +159,20 @@ impl io::Write for PageWriter {
     ///
     /// The incomming buf lenght can not exceed the PAGE_SIZE.
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        let mut page = self.page.0.write().unwrap();
-
         let new_size = self.pos + buf.len();
-        if new_size > page.len() {
+        if new_size > PAGE_SIZE {
             return Err(io::Error::new(
                 io::ErrorKind::InvalidInput,
                 format!(
                     "Size of buffer {} can not be greater than {}",
-                    new_size,
-                    page.len(),
+                    new_size, PAGE_SIZE,
                 ),
             ));
         }
 
-        let mut current_pos = self.pos;
-        for b in buf {
-            page[current_pos] = b.clone();
-            current_pos += 1;
-        }
-
-        self.pos = current_pos;
+        self.page
+            .write(|page| page[self.pos..new_size].copy_from_slice(buf));
+        self.pos = new_size;
 
         Ok(buf.len())
     }
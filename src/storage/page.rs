@@ -4,21 +4,38 @@ use std::{
     mem::size_of,
 };
 
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
-use super::{Page, PageWriter, PAGE_SIZE};
+use super::{
+    wal::{Lsn, INVALID_LSN},
+    Page, PageNumber, PageWriter, PAGE_SIZE,
+};
+
+/// Represents the fixed size of a page header when written on page.
+pub const PAGE_HEADER_SIZE: usize = size_of::<Lsn>() + size_of::<u16>() * 4;
 
-/// Represents the fixed size of a page header.
-pub const PAGE_HEADER_SIZE: usize = size_of::<PageHeader>();
+/// Offset of the checksum field on page header.
+const CHECKSUM_OFFSET: usize = size_of::<Lsn>();
 
 /// Space management information generic to any page.
 #[derive(Serialize, Deserialize, Debug)]
 pub struct PageHeader {
+    /// LSN of the WAL record that describes the last change on this page.
+    pub lsn: Lsn,
+
+    /// Checksum of the page contents, computed when the page is written on disk. Checksums are
+    /// never 0, so a 0 checksum means that the page was never written with a checksum.
+    pub checksum: u16,
+
     /// Offset to start of free space
     pub start_free_space: u16,
 
     /// Offset to end of free space
     pub end_free_space: u16,
+
+    /// Number of line pointers that point to a live item (LP_NORMAL), so pages without live
+    /// items can be skipped without reading their line pointers.
+    pub item_count: u16,
 }
 
 impl PageHeader {
@@ -29,11 +46,34 @@ impl PageHeader {
     }
 }
 
+impl PageHeader {
+    /// Return true if the free space pointers are consistent. Pages that were never initialized
+    /// or were partially written usually have invalid pointers.
+    pub fn is_valid(&self) -> bool {
+        let start = self.start_free_space as usize;
+        let end = self.end_free_space as usize;
+        // A full page may have the start of free space right after the end.
+        start >= PAGE_HEADER_SIZE
+            && start <= end + 1
+            && end <= PAGE_SIZE
+            && self.item_count as usize <= (start - PAGE_HEADER_SIZE) / ITEM_ID_SIZE
+    }
+
+    /// Return the amount of free space between the end of line pointers array and the start of
+    /// items data.
+    pub fn free_space(&self) -> usize {
+        self.end_free_space.saturating_sub(self.start_free_space) as usize
+    }
+}
+
 impl Default for PageHeader {
     fn default() -> Self {
         Self {
+            lsn: INVALID_LSN,
+            checksum: 0,
             start_free_space: PAGE_HEADER_SIZE as u16,
             end_free_space: PAGE_SIZE as u16,
+            item_count: 0,
         }
     }
 }
@@ -41,22 +81,202 @@ impl Default for PageHeader {
 /// Offset number of an item on buffer page.
 pub type OffsetNumber = u16;
 
+/// Line pointers are numbered sequentially, starting at 1.
+pub const FIRST_OFFSET_NUMBER: OffsetNumber = 1;
+
+/// State of a line pointer on a buffer page.
+pub type ItemIdFlags = u8;
+
+/// Unused line pointer. Line pointers of deleted tuples that are not referenced by any index are
+/// marked as unused and keep the length of the tuple storage, so a new item can reuse both.
+pub const LP_UNUSED: ItemIdFlags = 0;
+
+/// Used line pointer (should always have length > 0).
+pub const LP_NORMAL: ItemIdFlags = 1;
+
+/// HOT redirect (should have length = 0).
+pub const LP_REDIRECT: ItemIdFlags = 2;
+
+/// Dead, may or may not have storage.
+pub const LP_DEAD: ItemIdFlags = 3;
+
 /// A line pointer on a buffer page.
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+///
+/// On disk the line pointer is packed in 32 bits, following the same layout
+/// used by Postgres: 15 bits of offset, 2 bits of flags and 15 bits of length.
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub struct ItemId {
     /// Offset to tuple (from start of page)
     pub offset: OffsetNumber,
 
+    /// State of line pointer, see LP_* constants.
+    pub flags: ItemIdFlags,
+
     /// Byte length of tuple.
     pub length: u16,
 }
 
 /// Size of an item id on heap page.
-pub const ITEM_ID_SIZE: usize = size_of::<ItemId>();
+pub const ITEM_ID_SIZE: usize = size_of::<u32>();
+
+impl ItemId {
+    /// Return true if the line pointer is in use and points to a live tuple.
+    pub fn is_normal(&self) -> bool {
+        self.flags == LP_NORMAL
+    }
+}
+
+impl Serialize for ItemId {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let raw = (self.offset as u32 & 0x7FFF)
+            | ((self.flags as u32 & 0x03) << 15)
+            | ((self.length as u32 & 0x7FFF) << 17);
+        serializer.serialize_u32(raw)
+    }
+}
+
+impl<'de> Deserialize<'de> for ItemId {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let raw = u32::deserialize(deserializer)?;
+        Ok(Self {
+            offset: (raw & 0x7FFF) as OffsetNumber,
+            flags: ((raw >> 15) & 0x03) as ItemIdFlags,
+            length: ((raw >> 17) & 0x7FFF) as u16,
+        })
+    }
+}
+
+/// Identify the physical location of a tuple: the page of relation where the
+/// tuple is stored and the line pointer that points to it inside the page.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ItemPointer {
+    /// Page number of relation.
+    pub page_number: PageNumber,
+
+    /// Line pointer number on page (start at 1).
+    pub offset: OffsetNumber,
+}
+
+/// Initialize the contents of an empty page with the default page header values.
+pub fn page_init(page: &Page) -> Result<()> {
+    let mut page_writer = PageWriter::new(page);
+    bincode::serialize_into(&mut page_writer, &PageHeader::default())?;
+    Ok(())
+}
+
+/// Return the LSN of the last change on page.
+pub fn page_get_lsn(page: &Page) -> Result<Lsn> {
+    Ok(PageHeader::new(page)?.lsn)
+}
+
+/// Set the LSN of the last change on page.
+pub fn page_set_lsn(page: &Page, lsn: Lsn) -> Result<()> {
+    let mut header = PageHeader::new(page)?;
+    header.lsn = lsn;
+
+    let mut page_writer = PageWriter::new(page);
+    bincode::serialize_into(&mut page_writer, &header)?;
+    Ok(())
+}
+
+/// Compute the checksum of the given page, ignoring the checksum currently stored on page header.
+///
+/// The checksum is a FNV-1a hash of the page contents mixed with the page number, so a page
+/// written on the wrong location is also detected, folded to 16 bits.
+pub fn page_checksum(page: &Page, page_number: PageNumber) -> u16 {
+    let page = page.0.read().unwrap();
+
+    let mut hash: u32 = 0x811c_9dc5;
+    for (offset, byte) in page.iter().enumerate() {
+        let byte = if (CHECKSUM_OFFSET..CHECKSUM_OFFSET + size_of::<u16>()).contains(&offset) {
+            0
+        } else {
+            *byte
+        };
+        hash ^= byte as u32;
+        hash = hash.wrapping_mul(0x0100_0193);
+    }
+    hash ^= page_number;
+
+    // Reduce to a value between 1 and 65535, so a valid checksum is never 0.
+    ((hash % 65535) + 1) as u16
+}
+
+/// Compute and store the checksum of the given page on page header.
+pub fn page_set_checksum(page: &Page, page_number: PageNumber) -> Result<()> {
+    let mut header = PageHeader::new(page)?;
+    header.checksum = page_checksum(page, page_number);
+
+    let mut page_writer = PageWriter::new(page);
+    bincode::serialize_into(&mut page_writer, &header)?;
+    Ok(())
+}
+
+/// Return true if the checksum stored on page header match the page contents. New pages that
+/// were allocated but never written (all zeros) are always valid.
+pub fn page_verify_checksum(page: &Page, page_number: PageNumber) -> Result<bool> {
+    if page.0.read().unwrap().iter().all(|byte| *byte == 0) {
+        return Ok(true);
+    }
+    Ok(PageHeader::new(page)?.checksum == page_checksum(page, page_number))
+}
+
+/// Return the number of line pointers of the page, including the ones that don't point to a live
+/// item, see page_get_item_count for the number of live items. The offset number of the last
+/// line pointer is the same of the count, since line pointers are numbered sequentially.
+pub fn page_get_max_offset_number(page: &Page) -> Result<OffsetNumber> {
+    let header = PageHeader::new(page)?;
+    Ok(
+        (header.start_free_space as usize).saturating_sub(PAGE_HEADER_SIZE) as OffsetNumber
+            / ITEM_ID_SIZE as OffsetNumber,
+    )
+}
+
+/// Return the number of live items of the page, see PageHeader::item_count.
+pub fn page_get_item_count(page: &Page) -> Result<u16> {
+    Ok(PageHeader::new(page)?.item_count)
+}
+
+/// Return the amount of free space available on page to store a new item and its line pointer.
+///
+/// An item that reuse an unused line pointer only need the space of its data, which may also be
+/// the storage left by the deleted tuple.
+pub fn page_get_free_space(page: &Page) -> Result<usize> {
+    let header = PageHeader::new(page)?;
+    let mut free_space = header.free_space();
+    for item_id in item_ids(page, &header)? {
+        if item_id.flags == LP_UNUSED {
+            free_space = free_space
+                .max(header.free_space() + ITEM_ID_SIZE)
+                .max(item_id.length as usize + ITEM_ID_SIZE);
+        }
+    }
+    Ok(free_space)
+}
+
+/// Return the line pointers of the given page.
+fn item_ids(page: &Page, header: &PageHeader) -> Result<Vec<ItemId>> {
+    let page = page.0.read().unwrap();
+    page[PAGE_HEADER_SIZE..header.start_free_space as usize]
+        .chunks(ITEM_ID_SIZE)
+        .map(|item_id| Ok(bincode::deserialize(item_id)?))
+        .collect()
+}
 
 /// Add a new item to a page. The page header start_free_space and end_free_space is also updated
 /// to point to the new offsets after the item is inserted on in-memory page.
-pub fn page_add_item(page: &Page, item: &Vec<u8>) -> Result<()> {
+///
+/// Like Postgres, the first unused line pointer is reused before a new line pointer is added, and
+/// the item is stored on the storage left by the deleted tuple if it fits there.
+///
+/// Return the offset number of the line pointer of the new item.
+pub fn page_add_item(page: &Page, item: &Vec<u8>) -> Result<OffsetNumber> {
     let mut header = PageHeader::new(page)?;
 
     if header.start_free_space < PAGE_HEADER_SIZE as u16
@@ -65,10 +285,32 @@ pub fn page_add_item(page: &Page, item: &Vec<u8>) -> Result<()> {
         bail!("corrupted page pointers: {:#?}", header);
     }
 
+    let item_ids = item_ids(page, &header)?;
+    let unused = item_ids
+        .iter()
+        .position(|item_id| item_id.flags == LP_UNUSED);
+    let fits = item_ids
+        .iter()
+        .position(|item_id| item_id.flags == LP_UNUSED && item_id.length as usize >= item.len());
+    let (index, in_place) = match (fits, unused) {
+        (Some(index), _) => (index, true),
+        (None, Some(index)) if header.free_space() >= item.len() => (index, false),
+        _ if header.free_space() >= item.len() + ITEM_ID_SIZE => (item_ids.len(), false),
+        _ => bail!(
+            "not enough free space on page to add an item of {} bytes",
+            item.len()
+        ),
+    };
+
     // Select the offset number to place the new item
-    let item_id_offset = header.start_free_space as usize;
+    let item_id_offset = PAGE_HEADER_SIZE + index * ITEM_ID_SIZE;
     let item_id = ItemId {
-        offset: header.end_free_space - item.len() as u16,
+        offset: if in_place {
+            item_ids[index].offset
+        } else {
+            header.end_free_space - item.len() as u16
+        },
+        flags: LP_NORMAL,
         length: item.len() as u16,
     };
     let mut page_writer = PageWriter::new(page);
@@ -80,13 +322,54 @@ pub fn page_add_item(page: &Page, item: &Vec<u8>) -> Result<()> {
     page_writer.write_at(item, io::SeekFrom::Start(item_id.offset as u64))?;
 
     // Adjust the page header
-    header.start_free_space = (item_id_offset + size_of::<ItemId>()) as u16;
-    header.end_free_space = item_id.offset - 1;
+    if index == item_ids.len() {
+        header.start_free_space = (item_id_offset + ITEM_ID_SIZE) as u16;
+    }
+    if !in_place {
+        header.end_free_space = item_id.offset - 1;
+    }
+    header.item_count += 1;
 
     // Write the adjusted page header at the in-memory page.
     page_writer.seek(io::SeekFrom::Start(0))?;
     bincode::serialize_into(&mut page_writer, &header)?;
 
+    Ok(index as OffsetNumber + FIRST_OFFSET_NUMBER)
+}
+
+/// Change the flags of the line pointer at the given offset number, updating the number of live
+/// items on page header. Return error if the offset does not point to an existing line pointer
+/// on page.
+pub fn page_set_item_flags(page: &Page, offset: OffsetNumber, flags: ItemIdFlags) -> Result<()> {
+    let mut header = PageHeader::new(page)?;
+
+    if offset < FIRST_OFFSET_NUMBER {
+        bail!("invalid line pointer offset number {}", offset);
+    }
+
+    let item_id_offset = PAGE_HEADER_SIZE + (offset - FIRST_OFFSET_NUMBER) as usize * ITEM_ID_SIZE;
+    if item_id_offset >= header.start_free_space as usize {
+        bail!("invalid line pointer offset number {}", offset);
+    }
+
+    let mut item_id: ItemId = {
+        let page = page.0.read().unwrap();
+        bincode::deserialize(&page[item_id_offset..item_id_offset + ITEM_ID_SIZE])?
+    };
+    match (item_id.flags == LP_NORMAL, flags == LP_NORMAL) {
+        (true, false) => header.item_count = header.item_count.saturating_sub(1),
+        (false, true) => header.item_count += 1,
+        _ => {}
+    }
+    item_id.flags = flags;
+
+    let mut page_writer = PageWriter::new(page);
+    page_writer.seek(io::SeekFrom::Start(item_id_offset as u64))?;
+    bincode::serialize_into(&mut page_writer, &item_id)?;
+
+    page_writer.seek(io::SeekFrom::Start(0))?;
+    bincode::serialize_into(&mut page_writer, &header)?;
+
     Ok(())
 }
 
@@ -111,9 +394,9 @@ mod tests {
 
         let header = PageHeader::new(&page)?;
         assert_eq!(
-            header.start_free_space, 8,
+            header.start_free_space, 20,
             "Expected start free space {}, got {}",
-            header.start_free_space, 8
+            20, header.start_free_space
         );
         assert_eq!(
             header.end_free_space, 8187,
@@ -134,16 +417,183 @@ mod tests {
         assert!(header.end_free_space > header.start_free_space);
     }
 
+    #[test]
+    fn test_page_set_item_flags() -> Result<()> {
+        let page = Page::default();
+
+        let mut page_writer = PageWriter::new(&page);
+        bincode::serialize_into(&mut page_writer, &PageHeader::default())?;
+
+        assert_eq!(page_add_item(&page, &bincode::serialize(&150)?)?, 1);
+        assert_eq!(page_add_item(&page, &bincode::serialize(&300)?)?, 2);
+
+        page_set_item_flags(&page, 2, LP_DEAD)?;
+
+        let item_ids = crate::storage::item_id_data_from_page(&page)?;
+        assert_eq!(item_ids.len(), 2);
+        assert!(item_ids[0].is_normal());
+        assert_eq!(item_ids[1].flags, LP_DEAD);
+        assert_eq!(item_ids[1].length, 4);
+
+        assert!(page_set_item_flags(&page, 3, LP_DEAD).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_page_item_count() -> Result<()> {
+        let page = Page::default();
+        page_init(&page)?;
+        assert_eq!(page_get_item_count(&page)?, 0);
+
+        for value in [1u64, 2, 3] {
+            page_add_item(&page, &bincode::serialize(&value)?)?;
+        }
+        assert_eq!(page_get_item_count(&page)?, 3);
+
+        // Only changes from and to a live item change the count.
+        page_set_item_flags(&page, 2, LP_DEAD)?;
+        assert_eq!(page_get_item_count(&page)?, 2);
+        page_set_item_flags(&page, 2, LP_UNUSED)?;
+        assert_eq!(page_get_item_count(&page)?, 2);
+        page_set_item_flags(&page, 1, LP_NORMAL)?;
+        assert_eq!(page_get_item_count(&page)?, 2);
+
+        // Reusing the unused line pointer adds a live item without a new line pointer.
+        assert_eq!(page_add_item(&page, &bincode::serialize(&4u64)?)?, 2);
+        assert_eq!(page_get_item_count(&page)?, 3);
+        assert_eq!(page_get_max_offset_number(&page)?, 3);
+        assert!(PageHeader::new(&page)?.is_valid());
+
+        // The count is covered by the checksum.
+        page_set_checksum(&page, 1)?;
+        page_set_item_flags(&page, 3, LP_DEAD)?;
+        assert!(!page_verify_checksum(&page, 1)?);
+
+        // A count larger than the number of line pointers is invalid.
+        let mut header = PageHeader::new(&page)?;
+        header.item_count = 4;
+        assert!(!header.is_valid());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_page_reuse_unused_item() -> Result<()> {
+        let page = Page::default();
+        page_init(&page)?;
+        assert_eq!(page_get_max_offset_number(&page)?, 0);
+
+        for value in [1u64, 2, 3] {
+            page_add_item(&page, &bincode::serialize(&value)?)?;
+        }
+        assert_eq!(page_get_max_offset_number(&page)?, 3);
+
+        // A deleted item keeps its line pointer and storage, so an item that fits is stored on the
+        // same place without using free space. Items stored on free space don't need a new line
+        // pointer while the unused line pointer exists.
+        let free_space = page_get_free_space(&page)?;
+        page_set_item_flags(&page, 2, LP_UNUSED)?;
+        assert_eq!(page_get_free_space(&page)?, free_space + ITEM_ID_SIZE);
+        assert_eq!(page_add_item(&page, &bincode::serialize(&4u32)?)?, 2);
+        assert_eq!(page_get_max_offset_number(&page)?, 3);
+        assert_eq!(page_get_free_space(&page)?, free_space);
+
+        let item_ids = crate::storage::item_id_data_from_page(&page)?;
+        assert!(item_ids[1].is_normal());
+        assert_eq!(
+            bincode::deserialize::<u32>(&crate::storage::value_from_page_item(
+                &page,
+                &item_ids[1]
+            )?)?,
+            4
+        );
+
+        // A larger item reuses the line pointer but is stored on free space.
+        page_set_item_flags(&page, 1, LP_UNUSED)?;
+        assert_eq!(page_add_item(&page, &vec![0; 100])?, 1);
+        assert_eq!(page_get_max_offset_number(&page)?, 3);
+        assert!(page_get_free_space(&page)? <= free_space - 100);
+
+        // Dead line pointers are never reused.
+        page_set_item_flags(&page, 3, LP_DEAD)?;
+        assert_eq!(page_add_item(&page, &vec![0; 4])?, 4);
+
+        // The storage of an unused item is reported as free space of a full page.
+        let page = Page::default();
+        page_init(&page)?;
+        let size = (PAGE_SIZE - PAGE_HEADER_SIZE) / 2 - ITEM_ID_SIZE - 1;
+        page_add_item(&page, &vec![1; size])?;
+        page_add_item(&page, &vec![2; size])?;
+        assert!(page_get_free_space(&page)? < size);
+        page_set_item_flags(&page, 1, LP_UNUSED)?;
+        assert_eq!(page_get_free_space(&page)?, size + ITEM_ID_SIZE);
+        assert_eq!(page_add_item(&page, &vec![3; size])?, 1);
+        assert!(page_add_item(&page, &vec![4; size]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_page_snapshot() -> Result<()> {
+        let page = Page::default();
+        page_init(&page)?;
+        page_add_item(&page, &vec![1, 2, 3])?;
+
+        // Writing the page after a snapshot was taken don't change the snapshot.
+        let snapshot = page.snapshot();
+        let item_ids = crate::storage::item_id_data_from_page(&page)?;
+        let value = crate::storage::value_from_page_snapshot(&snapshot, &item_ids[0])?;
+        page_set_item_flags(&page, 1, LP_UNUSED)?;
+        page_add_item(&page, &vec![4, 5, 6])?;
+
+        assert_eq!(&value[..], &[1, 2, 3]);
+        assert_eq!(
+            crate::storage::value_from_page_item(&page, &item_ids[0])?,
+            vec![4, 5, 6]
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn test_item_id_size() {
         assert_eq!(ITEM_ID_SIZE, 4, "Item id size should have 4 bytes long");
     }
 
+    #[test]
+    fn test_page_checksum() -> Result<()> {
+        // New pages are valid without a checksum.
+        let page = Page::default();
+        assert!(page_verify_checksum(&page, 1)?);
+
+        page_init(&page)?;
+        page_add_item(&page, &bincode::serialize(&150)?)?;
+        assert!(!page_verify_checksum(&page, 1)?);
+
+        page_set_checksum(&page, 1)?;
+        assert_ne!(PageHeader::new(&page)?.checksum, 0);
+        assert!(page_verify_checksum(&page, 1)?);
+
+        // The same contents on a different page number is not valid.
+        assert!(!page_verify_checksum(&page, 2)?);
+
+        // Any change on page data invalidate the checksum.
+        page_add_item(&page, &bincode::serialize(&300)?)?;
+        assert!(!page_verify_checksum(&page, 1)?);
+
+        Ok(())
+    }
+
     #[test]
     fn test_page_header_size() {
         assert_eq!(
-            PAGE_HEADER_SIZE, 4,
-            "Page header size should have 4 bytes long"
+            PAGE_HEADER_SIZE, 16,
+            "Page header size should have 16 bytes long"
+        );
+        assert_eq!(
+            bincode::serialize(&PageHeader::default()).unwrap().len(),
+            PAGE_HEADER_SIZE
         );
     }
 }
@@ -1,4 +1,5 @@
 use anyhow::Result;
+use serde::{Deserialize, Serialize};
 use std::{
     path::{Path, PathBuf},
     sync::Arc,
@@ -12,7 +13,7 @@ use crate::{
 pub type RelationLocator = Arc<RelationLocatorData>;
 
 /// RelFileLocator provide all that we need to know to physically access a relation.
-#[derive(Eq, PartialEq, Hash)]
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq, Hash)]
 pub struct RelationLocatorData {
     /// Tablespace oid where relation is stored.
     pub tablespace: Oid,
@@ -32,9 +33,7 @@ pub fn relation_path(tablespace: &Oid, db_oid: &Oid, rel_oid: &Oid) -> Result<Pa
     match *tablespace {
         DEFAULTTABLESPACE_OID => {
             assert_ne!(*db_oid, INVALID_OID);
-            Ok(Path::new("base")
-                .join(&db_oid.to_string())
-                .join(&rel_oid.to_string()))
+            Ok(database_path(db_oid).join(&rel_oid.to_string()))
         }
         GLOBALTABLESPACE_OID => {
             assert_ne!(*tablespace, INVALID_OID);
@@ -45,3 +44,9 @@ pub fn relation_path(tablespace: &Oid, db_oid: &Oid, rel_oid: &Oid) -> Result<Pa
         }
     }
 }
+
+/// Return the physical path of the directory where the relations of a database are stored on
+/// the default tablespace.
+pub fn database_path(db_oid: &Oid) -> PathBuf {
+    Path::new("base").join(db_oid.to_string())
+}
@@ -1,18 +1,28 @@
-use std::env;
+use std::future::Future;
+use std::{env, fs};
 
+use std::fs::File;
 use std::path::Path;
 use std::sync::Arc;
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+};
 
-use crate::relation::Relation;
+use crate::{relation::Relation, Oid};
 
 use anyhow::Result;
+use tokio::task;
 
 use super::disk::Disk;
 use super::{
-    relation_locator::{relation_path, RelationLocator},
+    buffile::{self, BufFile, TEMP_FILES_DIR},
+    fault::{FaultInjector, FaultyStorage},
+    memory::MemoryStorage,
+    relation_locator::{database_path, relation_path, RelationLocator},
     Page, PageNumber,
 };
+use crate::lru::{Replacer, LRU};
 
 /// A SMgrRelationData is used as StorageManager entry key to store a physical disk page handler of
 /// a relation.
@@ -32,51 +42,188 @@ impl SMgrRelationData {
     }
 }
 
+/// Default maximum number of relation files kept open by a storage manager, the same default of
+/// max_files_per_process of Postgres.
+pub const DEFAULT_MAX_OPEN_FILES: usize = 1000;
+
+/// Storage of the pages of a single relation. Pages are numbered sequentially, starting at 1.
+pub trait RelationStorage: Send + Sync {
+    /// Read the given page number and store the contents inside the given page.
+    fn read_page(&self, page_number: PageNumber, page: &Page) -> Result<()>;
+
+    /// Store the contents of the given page on the given page number.
+    fn write_page(&self, page_number: PageNumber, page: &Page) -> Result<()>;
+
+    /// Add a new initialized page at the end of the relation and return its page number.
+    fn allocate_page(&self) -> Result<PageNumber>;
+
+    /// Return the number of pages of the relation.
+    fn size(&self) -> Result<u32>;
+
+    /// Force all written pages to be stored on the physical device.
+    fn sync(&self) -> Result<()>;
+}
+
 /// Storage manager that handle read and write page operations.
 ///
 /// The storge manager also have his own cache to store disk page handler to a given relation to
-/// avoid re-open a file every time that an read/write operation is requested.
+/// avoid re-open a file every time that an read/write operation is requested. Storage managers
+/// created by in_memory store the pages of each relation on the cache itself, without any file.
 ///
-/// TODO: Add a configuration to limit the cache size.
+/// At most max_open_files relation files are kept open. When the limit is reached, the least
+/// recently used file is closed and opened again the next time that it is used.
 pub struct StorageManager {
-    /// Base data directory where database files are stored.
-    ///
-    /// At startup the current working directory is the path where the tinydb binary was started,
-    /// so this field is used to open the disk page handler of relations, after startup the current
-    /// working directory is changed to data_dir absolute path, so all functions and read/write
-    /// files from database directory without needing the base data_dir path.
+    /// Absolute path of the base data directory where database files are stored. All files of
+    /// the data directory are accessed from this path, so the current working directory of the
+    /// process is never used and many storage managers can be used on the same process.
     data_dir: PathBuf,
 
-    /// Hashmap to store the disk page handler for each relation.
-    relation_smgr: HashMap<SMgrRelation, Arc<Disk>>,
+    /// Keep the pages of relations in memory instead of files inside data_dir.
+    in_memory: bool,
+
+    /// Open relation files with direct I/O, bypassing the page cache of the operating system.
+    /// Disabled after the first file that can not be opened with direct I/O.
+    direct_io: bool,
+
+    /// Pages of in-memory relations, by the relation path. The same relation may be opened with
+    /// different locators that have the same path, e.g. shared catalogs.
+    memory_relations: HashMap<PathBuf, Arc<MemoryStorage>>,
+
+    /// Hashmap to store the page storage for each relation.
+    relation_smgr: HashMap<SMgrRelation, Arc<dyn RelationStorage>>,
+
+    /// Maximum number of relation files kept open.
+    max_open_files: usize,
+
+    /// Relations with an open file, ordered by the last time that they were used.
+    open_files: LRU<SMgrRelation>,
+
+    /// Files closed since the last sync_all, which are opened again to be synced, since pages
+    /// written on a closed file may not be stored on the physical device yet.
+    pending_syncs: HashSet<PathBuf>,
+
+    /// Inject faults on the page writes of all relations, to test crash recovery.
+    fault_injector: Option<Arc<FaultInjector>>,
 }
 
 impl StorageManager {
-    /// Create a new storage manager using the given data_dir as base data directory path.
+    /// Create a new storage manager using the given data_dir as base data directory path. A
+    /// relative data_dir is resolved from the current working directory.
     pub fn new(data_dir: &Path) -> Self {
         Self {
-            data_dir: data_dir.to_path_buf(),
+            data_dir: std::path::absolute(data_dir).unwrap_or_else(|_| data_dir.to_path_buf()),
+            in_memory: false,
+            direct_io: false,
+            memory_relations: HashMap::new(),
+            relation_smgr: HashMap::new(),
+            max_open_files: DEFAULT_MAX_OPEN_FILES,
+            open_files: LRU::new(DEFAULT_MAX_OPEN_FILES),
+            pending_syncs: HashSet::new(),
+            fault_injector: None,
+        }
+    }
+
+    /// Create a new storage manager that keep the pages of all relations in memory. All pages
+    /// are lost when the storage manager is dropped.
+    pub fn in_memory() -> Self {
+        Self {
+            data_dir: PathBuf::new(),
+            in_memory: true,
+            direct_io: false,
+            memory_relations: HashMap::new(),
             relation_smgr: HashMap::new(),
+            max_open_files: DEFAULT_MAX_OPEN_FILES,
+            open_files: LRU::new(DEFAULT_MAX_OPEN_FILES),
+            pending_syncs: HashSet::new(),
+            fault_injector: None,
+        }
+    }
+
+    /// Open relation files with O_DIRECT, so the pages cached by the buffer pool are not also
+    /// cached by the operating system, e.g. to benchmark the buffer pool. Relation files are
+    /// opened with buffered I/O if direct I/O is not supported.
+    pub fn with_direct_io(mut self, direct_io: bool) -> Self {
+        self.direct_io = direct_io;
+        self
+    }
+
+    /// Keep at most the given number of relation files open, at least one.
+    pub fn with_max_open_files(mut self, max_open_files: usize) -> Self {
+        self.max_open_files = max_open_files.max(1);
+        self
+    }
+
+    /// Inject the faults of the given injector on the page writes of all relations, e.g. to
+    /// simulate a crash in the middle of a checkpoint.
+    pub fn with_fault_injector(mut self, injector: Arc<FaultInjector>) -> Self {
+        self.fault_injector = Some(injector);
+        self
+    }
+
+    /// Return the number of relation files currently open.
+    pub fn open_files(&self) -> usize {
+        self.open_files.size()
+    }
+
+    /// Return the base data directory of relation files. None if pages are kept in memory.
+    pub fn data_dir(&self) -> Option<&Path> {
+        if self.in_memory {
+            None
+        } else {
+            Some(&self.data_dir)
         }
     }
 
     /// Write the supplied page at the appropriate location.
     pub fn write(&mut self, rel: &Relation, page_number: PageNumber, page: &Page) -> Result<()> {
-        let disk = self.smgr_from_relation(rel)?;
-        disk.write_page(page_number, page)
+        let storage = self.smgr_from_relation(rel)?;
+        storage.write_page(page_number, page)
     }
 
     /// Read the specified block from the storage manager relation and store the contents inside
     /// the given page.
     pub fn read(&mut self, rel: &Relation, page_number: PageNumber, page: &Page) -> Result<()> {
-        let disk = self.smgr_from_relation(rel)?;
-        disk.read_page(page_number, page)
+        let storage = self.smgr_from_relation(rel)?;
+        storage.read_page(page_number, page)
+    }
+
+    /// Same as write, but the page is written on the blocking thread pool of the tokio runtime,
+    /// so the task awaiting the write don't block the runtime threads. The returned future does
+    /// not borrow the storage manager, which can be used while the page is written.
+    pub fn write_async(
+        &mut self,
+        rel: &Relation,
+        page_number: PageNumber,
+        page: &Page,
+    ) -> impl Future<Output = Result<()>> + Send + 'static {
+        let storage = self.smgr_from_relation(rel);
+        let page = page.clone();
+        async move {
+            let storage = storage?;
+            task::spawn_blocking(move || storage.write_page(page_number, &page)).await?
+        }
+    }
+
+    /// Same as read, but the page is read on the blocking thread pool of the tokio runtime, like
+    /// write_async.
+    pub fn read_async(
+        &mut self,
+        rel: &Relation,
+        page_number: PageNumber,
+        page: &Page,
+    ) -> impl Future<Output = Result<()>> + Send + 'static {
+        let storage = self.smgr_from_relation(rel);
+        let page = page.clone();
+        async move {
+            let storage = storage?;
+            task::spawn_blocking(move || storage.read_page(page_number, &page)).await?
+        }
     }
 
     /// Add a new page block to a file.
     pub fn extend(&mut self, rel: &Relation) -> Result<PageNumber> {
-        let disk = self.smgr_from_relation(rel)?;
-        disk.allocate_page()
+        let storage = self.smgr_from_relation(rel)?;
+        storage.allocate_page()
     }
 
     /// Computes the number of pages in a file.
@@ -84,28 +231,272 @@ impl StorageManager {
         self.smgr_from_relation(rel)?.size()
     }
 
+    /// Return true if a relation with the given oid has storage on the given tablespace and
+    /// database.
+    pub fn exists(&self, tablespace: &Oid, db_oid: &Oid, rel_oid: &Oid) -> Result<bool> {
+        let relpath = relation_path(tablespace, db_oid, rel_oid)?;
+        if self.in_memory {
+            Ok(self.memory_relations.contains_key(&relpath))
+        } else {
+            Ok(self.data_dir.join(relpath).exists())
+        }
+    }
+
+    /// Return true if the directory of the given database exists on the default tablespace.
+    pub fn database_exists(&self, db_oid: &Oid) -> bool {
+        let dbpath = database_path(db_oid);
+        if self.in_memory {
+            self.memory_relations
+                .keys()
+                .any(|relpath| relpath.starts_with(&dbpath))
+        } else {
+            self.data_dir.join(dbpath).is_dir()
+        }
+    }
+
+    /// Copy all relations of the template database to the directory of a new database, which
+    /// must not exist yet. Pages cached by the buffer pool are not copied, so they must be
+    /// written before.
+    ///
+    /// The copied files are synced before returning, and the new directory is removed if the
+    /// copy fails.
+    pub fn copy_database(&mut self, template_oid: &Oid, db_oid: &Oid) -> Result<()> {
+        let template_path = database_path(template_oid);
+        let db_path = database_path(db_oid);
+        if self.in_memory {
+            let copies = self
+                .memory_relations
+                .iter()
+                .filter_map(|(relpath, storage)| {
+                    let name = relpath.strip_prefix(&template_path).ok()?;
+                    Some((db_path.join(name), Arc::new(storage.copy())))
+                })
+                .collect::<Vec<_>>();
+            self.memory_relations.extend(copies);
+            return Ok(());
+        }
+
+        let target = self.data_dir.join(db_path);
+        fs::create_dir(&target)?;
+        if let Err(err) = copy_dir_files(&self.data_dir.join(template_path), &target) {
+            if let Err(err) = fs::remove_dir_all(&target) {
+                log::warn!("could not remove directory {:?}: {}", target, err);
+            }
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// Remove the storage of the given relation. Nothing is done if the relation has no storage.
+    pub fn unlink(&mut self, rel: &Relation) -> Result<()> {
+        self.relation_smgr.remove(&rel.smgr);
+        self.open_files.remove(&rel.smgr);
+
+        let locator = &rel.locator;
+        let relpath = relation_path(&locator.tablespace, &locator.database, &locator.oid)?;
+        if self.in_memory {
+            self.memory_relations.remove(&relpath);
+            return Ok(());
+        }
+
+        match fs::remove_file(self.relation_path(rel)?) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Remove the pages that were not completely written at the end of all relation files of the
+    /// data directory. Must be called before any relation is opened, e.g. when starting recovery.
+    pub fn truncate_invalid_pages(&self) -> Result<()> {
+        if self.in_memory {
+            return Ok(());
+        }
+
+        let mut dirs = vec![self.data_dir.join("global")];
+        let base = self.data_dir.join("base");
+        if base.exists() {
+            for entry in fs::read_dir(base)? {
+                dirs.push(entry?.path());
+            }
+        }
+
+        for dir in dirs.iter().filter(|dir| dir.is_dir()) {
+            for entry in fs::read_dir(dir)? {
+                let path = entry?.path();
+                // Relation files are named by the relation oid.
+                let is_relation = path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name.parse::<Oid>().is_ok());
+                if is_relation {
+                    Disk::truncate_invalid_pages(&path)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Create a new temporary file, removed when dropped. Temporary files of in-memory storage
+    /// managers are created on the temporary directory of the operating system.
+    pub fn create_temp_file(&self) -> Result<BufFile> {
+        BufFile::create(&self.temp_files_dir()?)
+    }
+
+    /// Remove the temporary files left by a previous run of the server.
+    pub fn remove_temp_files(&self) -> Result<()> {
+        if self.in_memory {
+            return Ok(());
+        }
+        buffile::remove_temp_files(&self.temp_files_dir()?)
+    }
+
+    /// Return the directory where temporary files are created.
+    fn temp_files_dir(&self) -> Result<PathBuf> {
+        if self.in_memory {
+            Ok(env::temp_dir())
+        } else {
+            Ok(self.data_dir.join(TEMP_FILES_DIR))
+        }
+    }
+
+    /// Force all written pages of relation files to be stored on the physical device, including
+    /// the files closed since the last sync.
+    pub fn sync_all(&mut self) -> Result<()> {
+        for storage in self.relation_smgr.values() {
+            storage.sync()?;
+        }
+        for path in self.pending_syncs.drain() {
+            match File::open(&path) {
+                Ok(file) => file.sync_all()?,
+                // The relation was removed after the file was closed.
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Ok(())
+    }
+
     /// Return a cached page handler for the given relation. If a page handler does not exists for
     /// relation, create a new one and cached it.
-    fn smgr_from_relation(&mut self, rel: &Relation) -> Result<Arc<Disk>> {
+    ///
+    /// Pages can be read and written using the returned handler without holding the storage
+    /// manager, so reads and writes of different pages don't wait for each other.
+    pub fn smgr_from_relation(&mut self, rel: &Relation) -> Result<Arc<dyn RelationStorage>> {
         match self.relation_smgr.get(&rel.smgr) {
-            Some(disk) => Ok(disk.clone()),
+            Some(storage) => {
+                if !self.in_memory {
+                    // Move the relation to the end of the least recently used order.
+                    self.open_files.remove(&rel.smgr);
+                    self.open_files.unpin(&rel.smgr);
+                }
+                Ok(storage.clone())
+            }
             None => {
-                let relpath = self.relation_path(rel)?;
-                let disk = Arc::new(Disk::open(&self.data_dir.join(relpath))?);
-                self.relation_smgr.insert(rel.smgr.clone(), disk.clone());
-                Ok(disk)
+                let storage: Arc<dyn RelationStorage> = if self.in_memory {
+                    let locator = &rel.locator;
+                    let relpath =
+                        relation_path(&locator.tablespace, &locator.database, &locator.oid)?;
+                    self.memory_relations.entry(relpath).or_default().clone()
+                } else {
+                    while self.open_files.size() >= self.max_open_files {
+                        self.close_least_recently_used()?;
+                    }
+
+                    let disk =
+                        Disk::open_with_direct_io(&self.relation_path(rel)?, self.direct_io)?;
+                    // Don't try direct I/O again if it is not supported, which is already
+                    // logged when the file is opened.
+                    self.direct_io = disk.is_direct_io();
+                    self.open_files.unpin(&rel.smgr);
+                    Arc::new(disk)
+                };
+                let storage = match &self.fault_injector {
+                    Some(injector) => Arc::new(FaultyStorage::new(storage, injector.clone())),
+                    None => storage,
+                };
+                self.relation_smgr.insert(rel.smgr.clone(), storage.clone());
+                Ok(storage)
             }
         }
     }
 
+    /// Close the file of the relation used least recently. The file is closed after the
+    /// operations that are already using it are finished.
+    fn close_least_recently_used(&mut self) -> Result<()> {
+        if let Some(smgr) = self.open_files.victim() {
+            self.relation_smgr.remove(&smgr);
+            let path = self.locator_path(&smgr.locator)?;
+            log::debug!("closing relation file {:?}", path);
+            self.pending_syncs.insert(path);
+        }
+        Ok(())
+    }
+
+    /// Return the absolute path of the file of the given relation.
     fn relation_path(&self, rel: &Relation) -> Result<PathBuf> {
-        let locator = &rel.locator;
+        self.locator_path(&rel.locator)
+    }
 
-        let relpath = &relation_path(&locator.tablespace, &locator.database, &locator.oid)?;
-        if env::current_dir()? == self.data_dir {
-            Ok(relpath.to_path_buf())
-        } else {
-            Ok(self.data_dir.join(relpath))
+    fn locator_path(&self, locator: &RelationLocator) -> Result<PathBuf> {
+        let relpath = relation_path(&locator.tablespace, &locator.database, &locator.oid)?;
+        Ok(self.data_dir.join(relpath))
+    }
+}
+
+/// Copy all files of the source directory to the target directory, syncing the copied files and
+/// the target directory.
+fn copy_dir_files(source: &Path, target: &Path) -> Result<()> {
+    for entry in fs::read_dir(source)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_file() {
+            continue;
         }
+        let path = target.join(entry.file_name());
+        fs::copy(entry.path(), &path)?;
+        File::open(&path)?.sync_all()?;
+    }
+    File::open(target)?.sync_all()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{access, catalog::pg_tablespace::DEFAULTTABLESPACE_OID, storage::PAGE_SIZE};
+
+    #[test]
+    fn test_max_open_files() -> Result<()> {
+        let db_data = tempfile::tempdir()?;
+        let db_oid: Oid = 5;
+        fs::create_dir_all(db_data.path().join("base").join(db_oid.to_string()))?;
+
+        let mut smgr = StorageManager::new(db_data.path()).with_max_open_files(2);
+        let rels = (0..4)
+            .map(|i| access::open_relation(10000 + i, DEFAULTTABLESPACE_OID, &db_oid, "test"))
+            .collect::<Vec<_>>();
+
+        for (i, rel) in rels.iter().enumerate() {
+            let page_number = smgr.extend(rel)?;
+            smgr.write(rel, page_number, &Page::new([i as u8; PAGE_SIZE]))?;
+            assert!(smgr.open_files() <= 2);
+        }
+
+        // The least recently used files were closed, and are opened again when used.
+        assert_eq!(smgr.pending_syncs.len(), 2);
+        for (i, rel) in rels.iter().enumerate() {
+            let page = Page::default();
+            smgr.read(rel, 1, &page)?;
+            assert_eq!(page, Page::new([i as u8; PAGE_SIZE]));
+            assert_eq!(smgr.open_files(), 2);
+        }
+        assert!(smgr.relation_smgr.contains_key(&rels[3].smgr));
+        assert!(!smgr.relation_smgr.contains_key(&rels[1].smgr));
+
+        // Closed files are synced, even if they were removed.
+        smgr.unlink(&rels[0])?;
+        smgr.sync_all()?;
+        assert!(smgr.pending_syncs.is_empty());
+
+        Ok(())
     }
 }
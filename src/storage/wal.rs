@@ -0,0 +1,1198 @@
+use std::{
+    convert::TryInto,
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, BufReader, Read, Seek, SeekFrom, Write},
+    mem::size_of,
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{access, relation::Relation};
+
+use super::{
+    buffer::Buffer,
+    page::{
+        page_add_item, page_get_lsn, page_init, page_set_item_flags, page_set_lsn, ItemIdFlags,
+    },
+    relation_locator::RelationLocatorData,
+    BufferPool, PageNumber,
+};
+
+/// Log sequence number. Represents the byte position on write-ahead log right
+/// after the end of a WAL record, counting the records removed by checkpoints.
+pub type Lsn = u64;
+
+/// An invalid LSN that is used on pages that was never changed by a WAL record.
+pub const INVALID_LSN: Lsn = 0;
+
+/// Name of the directory inside the data directory that store the WAL file.
+pub const WAL_DIR: &str = "pg_wal";
+
+/// Name of the WAL file inside the WAL_DIR.
+pub const WAL_FILE: &str = "wal";
+
+/// Magic number at the start of the WAL file.
+const WAL_MAGIC: u32 = 0x5457_414c;
+
+/// Version of the format of the WAL file and its records. WAL files written with a different
+/// version are rejected instead of being replayed.
+///
+/// The version must be changed when a change on the file header, the record header or on
+/// WalRecord makes the existing WAL files incompatible.
+pub const WAL_VERSION: u32 = 1;
+
+/// Size of the header of the WAL file, which contains the magic number, the version and the LSN
+/// of the start of the first record on file.
+pub const WAL_FILE_HEADER_SIZE: usize = size_of::<u32>() * 2 + size_of::<Lsn>();
+
+/// Size of the header of each WAL record on file, which contains the record length and the
+/// CRC-32C of the record data.
+const WAL_RECORD_HEADER_SIZE: usize = size_of::<u32>() * 2;
+
+/// Errors of reading the WAL file.
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum Error {
+    #[error("WAL file {0:?} has an invalid header")]
+    InvalidHeader(PathBuf),
+
+    #[error("WAL file is incompatible with server: the WAL file was written with WAL version {found}, but the server was compiled with WAL version {expected}")]
+    IncompatibleVersion { found: u32, expected: u32 },
+
+    #[error("invalid WAL record at {lsn}: {reason}")]
+    InvalidRecord { lsn: Lsn, reason: String },
+}
+
+/// Describe a change on a relation page that should be replayed on recovery.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub enum WalRecord {
+    /// A new tuple was added on a heap page.
+    HeapInsert {
+        locator: RelationLocatorData,
+        page_number: PageNumber,
+        data: Vec<u8>,
+    },
+
+    /// A tuple was deleted from a heap page, changing its line pointer to the given flags.
+    HeapDelete {
+        locator: RelationLocatorData,
+        page_number: PageNumber,
+        offset: u16,
+        flags: ItemIdFlags,
+    },
+
+    /// A relation was extended with a new initialized page.
+    ExtendRelation {
+        locator: RelationLocatorData,
+        page_number: PageNumber,
+    },
+
+    /// Full image of a page after a change. Used by pages that don't have a
+    /// specific record type to describe the change, such as index pages, and
+    /// by the first change of a page after a checkpoint.
+    PageImage {
+        locator: RelationLocatorData,
+        page_number: PageNumber,
+        image: Vec<u8>,
+    },
+
+    /// All changes before the redo LSN, the end of the log when the checkpoint started, are
+    /// already flushed to relation files. Changes made while the checkpoint was flushing the
+    /// buffers may not be, so recovery replays all records after the redo LSN.
+    Checkpoint { redo: Lsn },
+
+    /// The storage of a relation was removed.
+    DropRelation { locator: RelationLocatorData },
+}
+
+/// Method used to force WAL writes to disk.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum WalSyncMethod {
+    /// Call fdatasync after writing the records on file.
+    #[default]
+    Fdatasync,
+
+    /// Open the WAL file with O_DSYNC, so each write only returns after the data is on disk.
+    OpenDatasync,
+}
+
+impl FromStr for WalSyncMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "fdatasync" => Ok(Self::Fdatasync),
+            "open_datasync" => Ok(Self::OpenDatasync),
+            _ => Err(format!("invalid wal sync method {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for WalSyncMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Fdatasync => write!(f, "fdatasync"),
+            Self::OpenDatasync => write!(f, "open_datasync"),
+        }
+    }
+}
+
+/// Write-ahead log handle.
+///
+/// WAL records are appended on an in-memory buffer and written on disk when flush is called. The
+/// buffer pool must flush the WAL up to the page LSN before writing a dirty page on disk, so that
+/// all changes on relation files can be replayed from the log after a crash.
+///
+/// Wal is reference counted and clonning will just increase the reference counter.
+#[derive(Clone)]
+pub struct Wal {
+    state: Arc<Mutex<WalState>>,
+}
+
+/// Activity statistics of the WAL since it was opened.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct WalStats {
+    /// Number of records inserted.
+    pub records: u64,
+
+    /// Number of bytes inserted, including the record headers.
+    pub bytes: u64,
+
+    /// Number of times that records were written on disk.
+    pub flushes: u64,
+
+    /// LSN of the end of the last inserted record.
+    pub insert_lsn: Lsn,
+
+    /// LSN up to where the WAL was flushed on disk.
+    pub flushed_lsn: Lsn,
+}
+
+struct WalState {
+    /// Directory of the WAL file.
+    wal_dir: PathBuf,
+
+    /// WAL file opened in append mode.
+    file: File,
+
+    /// LSN of the start of the first record on file. Records before it were removed by a
+    /// checkpoint.
+    start_lsn: Lsn,
+
+    /// Open the WAL file with O_DSYNC.
+    dsync: bool,
+
+    /// Force the WAL file to disk when it is replaced.
+    fsync: bool,
+
+    /// LSN of the end of the last inserted record.
+    insert_lsn: Lsn,
+
+    /// LSN up to where the WAL was flushed on disk.
+    flushed_lsn: Lsn,
+
+    /// Redo LSN of the last checkpoint started since the WAL was opened, or the end of the log
+    /// when it was opened, since recovery may replay the records before it.
+    redo_lsn: Lsn,
+
+    /// Records inserted but not flushed yet.
+    buffer: Vec<u8>,
+
+    /// Call fdatasync after writing records on file. False if writes are already synced by
+    /// O_DSYNC or if fsync is disabled.
+    sync_data: bool,
+
+    /// Activity statistics since the WAL was opened.
+    stats: WalStats,
+}
+
+impl Wal {
+    /// Open the WAL file inside the given data directory, creating it if it don't exists.
+    pub fn open(data_dir: &Path) -> Result<Self> {
+        Self::open_with_sync_method(data_dir, true, WalSyncMethod::default())
+    }
+
+    /// Same as open, but force the WAL writes to disk using the given sync method. If fsync is
+    /// false writes are never forced to disk, so records can be lost if the operating system
+    /// crash.
+    pub fn open_with_sync_method(
+        data_dir: &Path,
+        fsync: bool,
+        sync_method: WalSyncMethod,
+    ) -> Result<Self> {
+        let wal_dir = data_dir.join(WAL_DIR);
+        if !wal_dir.exists() {
+            fs::create_dir_all(&wal_dir)?;
+        }
+
+        // A WAL file is only replaced after the new file is completely written, see
+        // write_wal_file.
+        let path = wal_dir.join(WAL_FILE);
+        let tmp = path.with_extension("tmp");
+        if tmp.exists() {
+            fs::remove_file(&tmp)?;
+        }
+        if !path.exists() {
+            write_wal_file(&wal_dir, INVALID_LSN, &[], fsync)?;
+        }
+
+        let dsync = fsync && sync_method == WalSyncMethod::OpenDatasync;
+        let file = open_wal_file(&path, dsync)?;
+
+        // A record partially written by a crash is removed, otherwise the records inserted after
+        // it would be appended behind an invalid record and never be replayed.
+        let mut reader = RecordReader::open(&path)?;
+        while reader.next_record()?.is_some() {}
+        if reader.pos < reader.len {
+            log::warn!(
+                "removing partially written WAL record at {} ({} bytes)",
+                reader.lsn(),
+                reader.len - reader.pos
+            );
+            file.set_len(reader.pos)?;
+            if fsync {
+                file.sync_data()?;
+            }
+        }
+        let lsn = reader.lsn();
+
+        Ok(Self {
+            state: Arc::new(Mutex::new(WalState {
+                wal_dir,
+                file,
+                start_lsn: reader.start_lsn,
+                dsync,
+                fsync,
+                insert_lsn: lsn,
+                flushed_lsn: lsn,
+                redo_lsn: lsn,
+                buffer: Vec::new(),
+                sync_data: fsync && sync_method == WalSyncMethod::Fdatasync,
+                stats: WalStats::default(),
+            })),
+        })
+    }
+
+    /// Insert a new record on WAL and return the LSN of the end of the record.
+    pub fn insert(&self, record: &WalRecord) -> Result<Lsn> {
+        let data = bincode::serialize(record)?;
+
+        let mut state = self.state.lock().unwrap();
+        state
+            .buffer
+            .extend_from_slice(&(data.len() as u32).to_le_bytes());
+        state.buffer.extend_from_slice(&crc32c(&data).to_le_bytes());
+        state.buffer.extend_from_slice(&data);
+        state.insert_lsn += (WAL_RECORD_HEADER_SIZE + data.len()) as Lsn;
+        state.stats.records += 1;
+        state.stats.bytes += (WAL_RECORD_HEADER_SIZE + data.len()) as u64;
+
+        Ok(state.insert_lsn)
+    }
+
+    /// Make sure that all records up to the given LSN are written and synced on disk.
+    pub fn flush(&self, upto: Lsn) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if state.flushed_lsn >= upto {
+            return Ok(());
+        }
+
+        let buffer = std::mem::take(&mut state.buffer);
+        state.file.write_all(&buffer)?;
+        if state.sync_data {
+            state.file.sync_data()?;
+        }
+        state.flushed_lsn = state.insert_lsn;
+        state.stats.flushes += 1;
+
+        Ok(())
+    }
+
+    /// Flush all records inserted on WAL.
+    pub fn flush_all(&self) -> Result<()> {
+        let lsn = self.state.lock().unwrap().insert_lsn;
+        self.flush(lsn)
+    }
+
+    /// Start a new checkpoint and return its redo LSN, the end of the log before any buffer is
+    /// flushed by the checkpoint. Changes of pages after this point log the full page image
+    /// again, since the checkpoint may write the page before the change reaches the file.
+    pub fn start_checkpoint(&self) -> Lsn {
+        let mut state = self.state.lock().unwrap();
+        state.redo_lsn = state.insert_lsn;
+        state.redo_lsn
+    }
+
+    /// Return the redo LSN of the last checkpoint, see WalState::redo_lsn.
+    pub fn redo_lsn(&self) -> Lsn {
+        self.state.lock().unwrap().redo_lsn
+    }
+
+    /// Remove the records before the given redo LSN of a completed checkpoint from the WAL file,
+    /// since recovery only replays the records after the redo LSN of the last checkpoint.
+    ///
+    /// The records after the redo LSN are copied to a new WAL file that replaces the current
+    /// one, so a crash in the middle of the removal keeps the current file. Records inserted but
+    /// not flushed yet are written on the new file.
+    pub fn remove_records_before(&self, redo: Lsn) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let redo = redo.min(state.flushed_lsn);
+        if redo <= state.start_lsn {
+            return Ok(());
+        }
+
+        let path = state.wal_dir.join(WAL_FILE);
+        let mut file = File::open(&path)?;
+        file.seek(SeekFrom::Start(
+            WAL_FILE_HEADER_SIZE as u64 + (redo - state.start_lsn),
+        ))?;
+        let mut records = Vec::new();
+        file.read_to_end(&mut records)?;
+
+        write_wal_file(&state.wal_dir, redo, &records, state.fsync)?;
+        state.file = open_wal_file(&path, state.dsync)?;
+        state.start_lsn = redo;
+
+        Ok(())
+    }
+
+    /// Return the size of the WAL file up to the end of the last flushed record.
+    pub fn flushed_size(&self) -> u64 {
+        let state = self.state.lock().unwrap();
+        WAL_FILE_HEADER_SIZE as u64 + (state.flushed_lsn - state.start_lsn)
+    }
+
+    /// Return the activity statistics since the WAL was opened.
+    pub fn stats(&self) -> WalStats {
+        let state = self.state.lock().unwrap();
+        WalStats {
+            insert_lsn: state.insert_lsn,
+            flushed_lsn: state.flushed_lsn,
+            ..state.stats
+        }
+    }
+
+    /// Read all records flushed on WAL file, returning each record with its LSN.
+    ///
+    /// A record that was partially written (e.g. the server crashed in the middle of a write)
+    /// marks the end of the log.
+    pub fn read_records(&self) -> Result<Vec<(Lsn, WalRecord)>> {
+        let state = self.state.lock().unwrap();
+
+        let mut reader = RecordReader::open(&state.wal_dir.join(WAL_FILE))?;
+        let mut records = Vec::new();
+        while let Some(record) = reader.next_record()? {
+            records.push(record);
+        }
+        Ok(records)
+    }
+}
+
+/// Replace the WAL file of a data directory written by the previous catalog version, which had
+/// no file header, with an empty WAL file of the current format. Do nothing if the WAL file is
+/// already on the current format.
+///
+/// The server must have been shut down cleanly, so there are no records to recover. The LSNs
+/// of the new file start after the end of the previous file, so they are still newer than the
+/// LSNs of all pages.
+pub fn upgrade_wal(data_dir: &Path) -> Result<()> {
+    let wal_dir = data_dir.join(WAL_DIR);
+    let path = wal_dir.join(WAL_FILE);
+    let mut magic = [0; size_of::<u32>()];
+    let len = match File::open(&path) {
+        Ok(mut file) => {
+            let len = file.metadata()?.len();
+            if len >= magic.len() as u64 {
+                file.read_exact(&mut magic)?;
+            }
+            len
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => 0,
+        Err(err) => return Err(err.into()),
+    };
+    if u32::from_le_bytes(magic) == WAL_MAGIC {
+        // Reject the WAL files of other versions.
+        RecordReader::open(&path)?;
+        return Ok(());
+    }
+
+    write_wal_file(&wal_dir, len, &[], true)
+}
+
+/// Open the WAL file on the given path to append records, with O_DSYNC if dsync is true.
+fn open_wal_file(path: &Path, dsync: bool) -> Result<File> {
+    let mut options = OpenOptions::new();
+    options.read(true).append(true);
+    if dsync {
+        options.custom_flags(libc::O_DSYNC);
+    }
+    Ok(options.open(path)?)
+}
+
+/// Write a new WAL file inside the given directory, with the given records starting at the given
+/// LSN. The file is written on a temporary file that replace the WAL file, so a crash in the
+/// middle of a write don't leave the WAL file corrupted.
+fn write_wal_file(wal_dir: &Path, start_lsn: Lsn, records: &[u8], fsync: bool) -> Result<()> {
+    let path = wal_dir.join(WAL_FILE);
+    let tmp = path.with_extension("tmp");
+
+    let mut data = Vec::with_capacity(WAL_FILE_HEADER_SIZE + records.len());
+    data.extend_from_slice(&WAL_MAGIC.to_le_bytes());
+    data.extend_from_slice(&WAL_VERSION.to_le_bytes());
+    data.extend_from_slice(&start_lsn.to_le_bytes());
+    data.extend_from_slice(records);
+    fs::write(&tmp, &data)?;
+    if fsync {
+        File::open(&tmp)?.sync_all()?;
+    }
+    fs::rename(&tmp, &path)?;
+    if fsync {
+        File::open(wal_dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+/// Reader of the records of a WAL file, from the first record to the last valid record.
+struct RecordReader {
+    reader: BufReader<File>,
+
+    /// Size of the WAL file.
+    len: u64,
+
+    /// Position on file of the end of the last record read.
+    pos: u64,
+
+    /// LSN of the start of the first record on file.
+    start_lsn: Lsn,
+}
+
+impl RecordReader {
+    /// Open the WAL file on the given path, returning an error if it was not written with the
+    /// current WAL version.
+    fn open(path: &Path) -> Result<Self> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        let mut reader = BufReader::new(file);
+
+        let mut header = [0; WAL_FILE_HEADER_SIZE];
+        if reader.read_exact(&mut header).is_err() {
+            bail!(Error::InvalidHeader(path.to_path_buf()));
+        }
+        let (magic, rest) = header.split_at(size_of::<u32>());
+        let (version, start_lsn) = rest.split_at(size_of::<u32>());
+        if magic != WAL_MAGIC.to_le_bytes() {
+            bail!(Error::InvalidHeader(path.to_path_buf()));
+        }
+        let version = u32::from_le_bytes(version.try_into()?);
+        if version != WAL_VERSION {
+            bail!(Error::IncompatibleVersion {
+                found: version,
+                expected: WAL_VERSION,
+            });
+        }
+
+        Ok(Self {
+            reader,
+            len,
+            pos: WAL_FILE_HEADER_SIZE as u64,
+            start_lsn: Lsn::from_le_bytes(start_lsn.try_into()?),
+        })
+    }
+
+    /// LSN of the end of the last record read.
+    fn lsn(&self) -> Lsn {
+        self.start_lsn + (self.pos - WAL_FILE_HEADER_SIZE as u64)
+    }
+
+    /// Read the next record with its LSN. Return None at the end of the file, or if the next
+    /// record was partially written by a crash: a record at the end of the file that is shorter
+    /// than its length or that don't match its CRC. Any other invalid record is an error, since
+    /// the records after it would be lost.
+    fn next_record(&mut self) -> Result<Option<(Lsn, WalRecord)>> {
+        let remaining = self.len - self.pos;
+        if remaining < WAL_RECORD_HEADER_SIZE as u64 {
+            return Ok(None);
+        }
+
+        let mut header = [0; WAL_RECORD_HEADER_SIZE];
+        self.reader.read_exact(&mut header)?;
+        let (len, crc) = header.split_at(size_of::<u32>());
+        let len = u32::from_le_bytes(len.try_into()?) as u64;
+        let crc = u32::from_le_bytes(crc.try_into()?);
+        let size = WAL_RECORD_HEADER_SIZE as u64 + len;
+        if size > remaining {
+            return Ok(None);
+        }
+
+        let mut data = vec![0; len as usize];
+        self.reader.read_exact(&mut data)?;
+        if crc32c(&data) != crc {
+            if size == remaining {
+                return Ok(None);
+            }
+            bail!(Error::InvalidRecord {
+                lsn: self.lsn(),
+                reason: String::from("incorrect record checksum"),
+            });
+        }
+        let record = match bincode::deserialize::<WalRecord>(&data) {
+            Ok(record) => record,
+            Err(err) => bail!(Error::InvalidRecord {
+                lsn: self.lsn(),
+                reason: err.to_string(),
+            }),
+        };
+
+        self.pos += size;
+        Ok(Some((self.lsn(), record)))
+    }
+}
+
+/// Compute the CRC-32C of the given data, the checksum of WAL records of Postgres.
+fn crc32c(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0x82f6_3b78
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// Insert a WAL record describing a change on the page of the given buffer and set the page LSN
+/// to the new record. Do nothing if the buffer pool don't have a WAL configured.
+///
+/// Must be called after the change is applied on the page. The first change of a page after a
+/// checkpoint is logged with the full image of the page instead of the given record, so recovery
+/// can restore pages that were torn by a crash in the middle of a write.
+pub fn log_page_change(
+    buffer_pool: &BufferPool,
+    buffer: &Buffer,
+    record: &WalRecord,
+) -> Result<()> {
+    if let Some(wal) = buffer_pool.wal() {
+        let lsn = match record {
+            WalRecord::HeapInsert {
+                locator,
+                page_number,
+                ..
+            }
+            | WalRecord::HeapDelete {
+                locator,
+                page_number,
+                ..
+            } if page_get_lsn(&buffer.page)? <= wal.redo_lsn() => {
+                wal.insert(&WalRecord::PageImage {
+                    locator: locator.clone(),
+                    page_number: *page_number,
+                    image: buffer.page.0.read().unwrap().to_vec(),
+                })?
+            }
+            _ => wal.insert(record)?,
+        };
+        page_set_lsn(&buffer.page, lsn)?;
+    }
+    Ok(())
+}
+
+/// Insert a WAL record with the full image of the page of the given buffer and set the page LSN to
+/// the new record. Do nothing if the buffer pool don't have a WAL configured.
+pub fn log_page_image(buffer_pool: &BufferPool, rel: &Relation, buffer: &Buffer) -> Result<()> {
+    let image = buffer.page.0.read().unwrap().to_vec();
+    log_page_change(
+        buffer_pool,
+        buffer,
+        &WalRecord::PageImage {
+            locator: rel.locator.as_ref().clone(),
+            page_number: buffer.page_number(),
+            image,
+        },
+    )
+}
+
+/// Log the removal of the storage of the given relation and flush the WAL, so the removal is
+/// redone by recovery if the server crashes before the next checkpoint. Do nothing if the buffer
+/// pool don't have a WAL configured.
+///
+/// Must be called before the storage is removed, since changes logged before the removal
+/// recreate the relation files when they are replayed.
+pub fn log_drop_relation(buffer_pool: &BufferPool, rel: &Relation) -> Result<()> {
+    if let Some(wal) = buffer_pool.wal() {
+        wal.insert(&WalRecord::DropRelation {
+            locator: rel.locator.as_ref().clone(),
+        })?;
+        wal.flush_all()?;
+    }
+    Ok(())
+}
+
+/// Replay all WAL records after the redo LSN of the last checkpoint record.
+///
+/// Each record is only applied if the page LSN is older than the record LSN, so the recovery can
+/// be executed multiple times without applying the same change twice. Records that describe the
+/// whole page, extensions and page images, are always applied, since a page torn by a crash may
+/// have the LSN of a newer change without its contents. Every page changed after the checkpoint
+/// has one of these records before its other changes, see log_page_change.
+pub fn recover(buffer_pool: &BufferPool) -> Result<()> {
+    // Pages partially written by a crash while extending relations must be removed before any
+    // relation is opened, the extensions logged on WAL are redone below.
+    buffer_pool.truncate_invalid_pages()?;
+    buffer_pool.remove_temp_files()?;
+
+    let wal = match buffer_pool.wal() {
+        Some(wal) => wal,
+        None => return Ok(()),
+    };
+
+    let records = wal.read_records()?;
+    let redo_lsn = records
+        .iter()
+        .rev()
+        .find_map(|(_, record)| match record {
+            WalRecord::Checkpoint { redo } => Some(*redo),
+            _ => None,
+        })
+        .unwrap_or(INVALID_LSN);
+
+    let records = records
+        .iter()
+        .filter(|(lsn, record)| *lsn > redo_lsn && !matches!(record, WalRecord::Checkpoint { .. }))
+        .collect::<Vec<_>>();
+    if records.is_empty() {
+        return Ok(());
+    }
+
+    log::info!("starting WAL recovery of {} records", records.len());
+    for (lsn, record) in records {
+        redo(buffer_pool, *lsn, record)?;
+    }
+    log::info!("WAL recovery completed");
+
+    Ok(())
+}
+
+/// Apply the change described by the given record.
+fn redo(buffer_pool: &BufferPool, lsn: Lsn, record: &WalRecord) -> Result<()> {
+    let (locator, page_number) = match record {
+        WalRecord::HeapInsert {
+            locator,
+            page_number,
+            ..
+        }
+        | WalRecord::HeapDelete {
+            locator,
+            page_number,
+            ..
+        }
+        | WalRecord::ExtendRelation {
+            locator,
+            page_number,
+        }
+        | WalRecord::PageImage {
+            locator,
+            page_number,
+            ..
+        } => (locator, *page_number),
+        WalRecord::DropRelation { locator } => {
+            return buffer_pool.drop_relation(&redo_relation(locator))
+        }
+        WalRecord::Checkpoint { .. } => return Ok(()),
+    };
+
+    let rel = redo_relation(locator);
+
+    // Make sure that the page exists on relation file.
+    while buffer_pool.size_of_relation(&rel)? < page_number {
+        buffer_pool.alloc_buffer(&rel)?;
+    }
+
+    let mut buffer = match record {
+        // The page is overwritten, so its checksum is not verified.
+        WalRecord::ExtendRelation { .. } | WalRecord::PageImage { .. } => {
+            buffer_pool.fetch_buffer_to_overwrite(&rel, page_number)?
+        }
+        _ => buffer_pool.fetch_buffer(&rel, page_number)?,
+    };
+
+    match record {
+        WalRecord::ExtendRelation { .. } => {
+            page_init(&buffer.page)?;
+        }
+        WalRecord::PageImage { image, .. } => {
+            buffer.page.write(|page| page.copy_from_slice(image));
+        }
+        _ if page_get_lsn(&buffer.page)? >= lsn => {
+            // Change already applied on page.
+            return Ok(());
+        }
+        WalRecord::HeapInsert { data, .. } => {
+            page_add_item(&buffer.page, data)?;
+        }
+        WalRecord::HeapDelete { offset, flags, .. } => {
+            page_set_item_flags(&buffer.page, *offset, *flags)?
+        }
+        WalRecord::Checkpoint { .. } | WalRecord::DropRelation { .. } => {}
+    }
+    page_set_lsn(&buffer.page, lsn)?;
+    buffer.mark_dirty();
+
+    Ok(())
+}
+
+/// Open a relation for the given locator to replay WAL records.
+fn redo_relation(locator: &RelationLocatorData) -> Relation {
+    access::open_relation(
+        locator.oid,
+        locator.tablespace,
+        &locator.database,
+        &locator.oid.to_string(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        access::{
+            heap::{heap_insert, HeapScanner},
+            heaptuple::HeapTuple,
+        },
+        catalog::pg_tablespace::DEFAULTTABLESPACE_OID,
+        storage::{page::page_get_item_count, smgr::StorageManager, PAGE_SIZE},
+        Oid,
+    };
+
+    #[test]
+    fn test_sync_methods() -> Result<()> {
+        for (fsync, sync_method) in &[
+            (true, WalSyncMethod::Fdatasync),
+            (true, WalSyncMethod::OpenDatasync),
+            (false, WalSyncMethod::OpenDatasync),
+        ] {
+            let data_dir = tempfile::tempdir()?;
+            {
+                let wal = Wal::open_with_sync_method(data_dir.path(), *fsync, *sync_method)?;
+                wal.insert(&WalRecord::Checkpoint { redo: INVALID_LSN })?;
+                wal.flush_all()?;
+            }
+
+            let wal = Wal::open(data_dir.path())?;
+            assert_eq!(wal.read_records()?.len(), 1);
+
+            wal.insert(&WalRecord::Checkpoint { redo: INVALID_LSN })?;
+            wal.flush_all()?;
+            wal.flush_all()?;
+            let stats = wal.stats();
+            assert_eq!(stats.records, 1);
+            assert_eq!(stats.flushes, 1);
+            assert_eq!(stats.bytes, stats.insert_lsn / 2);
+            assert_eq!(stats.flushed_lsn, stats.insert_lsn);
+        }
+
+        assert_eq!(
+            "open_datasync".parse::<WalSyncMethod>(),
+            Ok(WalSyncMethod::OpenDatasync)
+        );
+        assert!("fsync".parse::<WalSyncMethod>().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_records() -> Result<()> {
+        let data_dir = tempfile::tempdir()?;
+        let locator = RelationLocatorData {
+            tablespace: DEFAULTTABLESPACE_OID,
+            database: 5,
+            oid: 10000,
+        };
+
+        {
+            let wal = Wal::open(data_dir.path())?;
+            wal.insert(&WalRecord::ExtendRelation {
+                locator: locator.clone(),
+                page_number: 1,
+            })?;
+            wal.insert(&WalRecord::Checkpoint { redo: 1 })?;
+            wal.flush_all()?;
+
+            // Not flushed records are lost.
+            wal.insert(&WalRecord::Checkpoint { redo: 2 })?;
+        }
+
+        // A record partially written by a crash is removed when the WAL is opened, so the
+        // records inserted after it are not lost.
+        let wal_path = data_dir.path().join(WAL_DIR).join(WAL_FILE);
+        let size = fs::metadata(&wal_path)?.len();
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&wal_path)?
+            .write_all(&[100, 0, 0, 0, 1, 2, 3])?;
+        {
+            let wal = Wal::open(data_dir.path())?;
+            assert_eq!(fs::metadata(&wal_path)?.len(), size);
+            assert_eq!(wal.stats().insert_lsn, size - WAL_FILE_HEADER_SIZE as u64);
+            wal.insert(&WalRecord::Checkpoint { redo: 3 })?;
+            wal.flush_all()?;
+        }
+
+        // The same for a record at the end of the file that don't match its CRC.
+        let size = fs::metadata(&wal_path)?.len();
+        let data = bincode::serialize(&WalRecord::Checkpoint { redo: 4 })?;
+        let mut record = (data.len() as u32).to_le_bytes().to_vec();
+        record.extend_from_slice(&(crc32c(&data) + 1).to_le_bytes());
+        record.extend_from_slice(&data);
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&wal_path)?
+            .write_all(&record)?;
+        drop(Wal::open(data_dir.path())?);
+        assert_eq!(fs::metadata(&wal_path)?.len(), size);
+
+        let wal = Wal::open(data_dir.path())?;
+        let records = wal.read_records()?;
+        assert_eq!(records.len(), 3);
+        assert_eq!(
+            records[0].1,
+            WalRecord::ExtendRelation {
+                locator,
+                page_number: 1
+            }
+        );
+        assert_eq!(records[1].1, WalRecord::Checkpoint { redo: 1 });
+        assert_eq!(records[2].1, WalRecord::Checkpoint { redo: 3 });
+        assert!(records[0].0 < records[1].0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_wal_file() -> Result<()> {
+        let data_dir = tempfile::tempdir()?;
+        {
+            let wal = Wal::open(data_dir.path())?;
+            wal.insert(&WalRecord::Checkpoint { redo: 1 })?;
+            wal.insert(&WalRecord::Checkpoint { redo: 2 })?;
+            wal.flush_all()?;
+        }
+        let wal_path = data_dir.path().join(WAL_DIR).join(WAL_FILE);
+        let data = fs::read(&wal_path)?;
+
+        // A record that don't match its CRC is not a partially written record if there are more
+        // records after it, so the WAL is not truncated.
+        let mut corrupted = data.clone();
+        corrupted[WAL_FILE_HEADER_SIZE + WAL_RECORD_HEADER_SIZE] ^= 1;
+        fs::write(&wal_path, &corrupted)?;
+        let err = Wal::open(data_dir.path()).err().unwrap();
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::InvalidRecord {
+                lsn: INVALID_LSN,
+                reason: String::from("incorrect record checksum")
+            })
+        );
+        assert_eq!(fs::read(&wal_path)?, corrupted);
+
+        // A record that matches its CRC but can not be decoded is also an error.
+        let mut corrupted = data.clone();
+        let record = bincode::serialize(&(u32::MAX, 1u64))?;
+        corrupted[WAL_FILE_HEADER_SIZE..WAL_FILE_HEADER_SIZE + WAL_RECORD_HEADER_SIZE]
+            .copy_from_slice(
+                &[
+                    (record.len() as u32).to_le_bytes(),
+                    crc32c(&record).to_le_bytes(),
+                ]
+                .concat(),
+            );
+        corrupted[WAL_FILE_HEADER_SIZE + WAL_RECORD_HEADER_SIZE..][..record.len()]
+            .copy_from_slice(&record);
+        fs::write(&wal_path, &corrupted)?;
+        let err = Wal::open(data_dir.path()).err().unwrap();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::InvalidRecord {
+                lsn: INVALID_LSN,
+                ..
+            })
+        ));
+
+        // WAL files of other formats are rejected.
+        let mut other_version = data.clone();
+        other_version[size_of::<u32>()..2 * size_of::<u32>()]
+            .copy_from_slice(&(WAL_VERSION + 1).to_le_bytes());
+        fs::write(&wal_path, &other_version)?;
+        let err = Wal::open(data_dir.path()).err().unwrap();
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::IncompatibleVersion {
+                found: WAL_VERSION + 1,
+                expected: WAL_VERSION
+            })
+        );
+        fs::write(&wal_path, &data[WAL_FILE_HEADER_SIZE..])?;
+        let err = Wal::open(data_dir.path()).err().unwrap();
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::InvalidHeader(wal_path.clone()))
+        );
+
+        // The WAL of the previous catalog version, without header, is replaced by an empty WAL
+        // starting after its end.
+        upgrade_wal(data_dir.path())?;
+        let wal = Wal::open(data_dir.path())?;
+        assert!(wal.read_records()?.is_empty());
+        assert_eq!(
+            wal.stats().insert_lsn,
+            (data.len() - WAL_FILE_HEADER_SIZE) as Lsn
+        );
+        upgrade_wal(data_dir.path())?;
+        assert_eq!(fs::metadata(&wal_path)?.len(), WAL_FILE_HEADER_SIZE as u64);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove_records_before() -> Result<()> {
+        let data_dir = tempfile::tempdir()?;
+        let wal_path = data_dir.path().join(WAL_DIR).join(WAL_FILE);
+        let (redo, end) = {
+            let wal = Wal::open(data_dir.path())?;
+            let redo = wal.insert(&WalRecord::Checkpoint { redo: 1 })?;
+            wal.insert(&WalRecord::Checkpoint { redo: 2 })?;
+            wal.flush_all()?;
+
+            // Not flushed records are written on the new file.
+            let end = wal.insert(&WalRecord::Checkpoint { redo: 3 })?;
+            wal.remove_records_before(redo)?;
+            assert_eq!(wal.flushed_size(), fs::metadata(&wal_path)?.len());
+            wal.flush_all()?;
+            assert_eq!(wal.flushed_size(), fs::metadata(&wal_path)?.len());
+            (redo, end)
+        };
+
+        // Records keep their LSNs, so LSNs of pages are still valid.
+        let wal = Wal::open(data_dir.path())?;
+        let records = wal.read_records()?;
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].1, WalRecord::Checkpoint { redo: 2 });
+        assert!(records[0].0 > redo);
+        assert_eq!(records[1], (end, WalRecord::Checkpoint { redo: 3 }));
+        assert_eq!(wal.stats().insert_lsn, end);
+
+        // Records after the end of the flushed records are never removed.
+        wal.remove_records_before(end + 100)?;
+        assert!(wal.read_records()?.is_empty());
+        assert!(wal.insert(&WalRecord::Checkpoint { redo: 4 })? > end);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_recover_not_flushed_pages() -> Result<()> {
+        let data_dir = tempfile::tempdir()?;
+        let db_oid: Oid = 5;
+        fs::create_dir_all(data_dir.path().join("base").join(db_oid.to_string()))?;
+
+        let rel = access::open_relation(10000, DEFAULTTABLESPACE_OID, &db_oid, "test");
+        let total_tuples = 1000;
+
+        {
+            let buffer_pool = BufferPool::new(20, StorageManager::new(data_dir.path()))
+                .with_wal(Wal::open(data_dir.path())?);
+            for i in 0..total_tuples {
+                heap_insert(&buffer_pool, &rel, &HeapTuple::with_default_header(&i)?)?;
+            }
+            buffer_pool.wal().unwrap().flush_all()?;
+
+            // Simulate a crash, dirty pages are never written on disk.
+            std::mem::forget(buffer_pool);
+        }
+
+        // Simulate a crash while extending the relation, before the extension was logged: a page
+        // that was never initialized followed by a partially written page.
+        let relpath = data_dir
+            .path()
+            .join("base")
+            .join(db_oid.to_string())
+            .join("10000");
+        let mut garbage = vec![0; PAGE_SIZE];
+        garbage.extend_from_slice(&[1; 100]);
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&relpath)?
+            .write_all(&garbage)?;
+
+        let buffer_pool = BufferPool::new(20, StorageManager::new(data_dir.path()))
+            .with_wal(Wal::open(data_dir.path())?);
+        recover(&buffer_pool)?;
+        // Recovery is idempotent.
+        recover(&buffer_pool)?;
+
+        let mut scanner = HeapScanner::new(&buffer_pool, &rel)?;
+        let mut tuples = 0;
+        while let Some(tuple) = scanner.next_tuple()? {
+            assert_eq!(bincode::deserialize::<i32>(&tuple.data)?, tuples);
+            tuples += 1;
+        }
+        assert_eq!(tuples, total_tuples);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_checkpoint_persist_pages() -> Result<()> {
+        let data_dir = tempfile::tempdir()?;
+        let db_oid: Oid = 5;
+        fs::create_dir_all(data_dir.path().join("base").join(db_oid.to_string()))?;
+
+        let rel = access::open_relation(10000, DEFAULTTABLESPACE_OID, &db_oid, "test");
+        let total_tuples = 100;
+
+        {
+            let buffer_pool = BufferPool::new(20, StorageManager::new(data_dir.path()))
+                .with_wal(Wal::open(data_dir.path())?);
+            for i in 0..total_tuples {
+                heap_insert(&buffer_pool, &rel, &HeapTuple::with_default_header(&i)?)?;
+            }
+            buffer_pool.checkpoint()?;
+
+            // Simulate a crash after the checkpoint.
+            std::mem::forget(buffer_pool);
+        }
+
+        // Only the checkpoint record is kept on WAL, since there were no changes after its redo
+        // LSN.
+        let wal = Wal::open(data_dir.path())?;
+        let records = wal.read_records()?;
+        assert_eq!(records.len(), 1);
+        assert!(matches!(records[0].1, WalRecord::Checkpoint { .. }));
+
+        // All pages are on relation files without needing to replay the WAL.
+        let buffer_pool = BufferPool::new(20, StorageManager::new(data_dir.path()));
+        let mut scanner = HeapScanner::new(&buffer_pool, &rel)?;
+        let mut tuples = 0;
+        while scanner.next_tuple()?.is_some() {
+            tuples += 1;
+        }
+        assert_eq!(tuples, total_tuples);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_full_page_writes() -> Result<()> {
+        let data_dir = tempfile::tempdir()?;
+        let db_oid: Oid = 5;
+        fs::create_dir_all(data_dir.path().join("base").join(db_oid.to_string()))?;
+
+        let rel = access::open_relation(10000, DEFAULTTABLESPACE_OID, &db_oid, "test");
+        let relpath = data_dir
+            .path()
+            .join("base")
+            .join(db_oid.to_string())
+            .join("10000");
+
+        {
+            let buffer_pool = BufferPool::new(20, StorageManager::new(data_dir.path()))
+                .with_wal(Wal::open(data_dir.path())?);
+            heap_insert(&buffer_pool, &rel, &HeapTuple::with_default_header(&0)?)?;
+            buffer_pool.checkpoint()?;
+
+            // Only the first change after the checkpoint logs the page image.
+            for i in 1..3 {
+                heap_insert(&buffer_pool, &rel, &HeapTuple::with_default_header(&i)?)?;
+            }
+            let wal = buffer_pool.wal().unwrap();
+            wal.flush_all()?;
+            let records = wal.read_records()?;
+            let changes = &records[records.len() - 2..];
+            assert!(matches!(changes[0].1, WalRecord::PageImage { .. }));
+            assert!(matches!(changes[1].1, WalRecord::HeapInsert { .. }));
+
+            // Simulate a crash while the page is written, only its first half reaches the file
+            // and the page header has the LSN of the last change.
+            buffer_pool.flush_all_buffers()?;
+            std::mem::forget(buffer_pool);
+        }
+        let mut file = fs::OpenOptions::new().write(true).open(&relpath)?;
+        file.seek(SeekFrom::Start(PAGE_SIZE as u64 / 2))?;
+        file.write_all(&[0; PAGE_SIZE / 2])?;
+        drop(file);
+
+        let buffer_pool = BufferPool::new(20, StorageManager::new(data_dir.path()))
+            .with_checksum_verification(true)
+            .with_wal(Wal::open(data_dir.path())?);
+        recover(&buffer_pool)?;
+
+        let mut scanner = HeapScanner::new(&buffer_pool, &rel)?;
+        let mut tuples = 0;
+        while let Some(tuple) = scanner.next_tuple()? {
+            assert_eq!(bincode::deserialize::<i32>(&tuple.data)?, tuples);
+            tuples += 1;
+        }
+        assert_eq!(tuples, 3);
+
+        // The page header restored from the page image keeps counting the redone changes.
+        let buffer = buffer_pool.fetch_buffer(&rel, 1)?;
+        assert_eq!(page_get_item_count(&buffer.page)?, 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_checkpoint_redo_lsn() -> Result<()> {
+        let data_dir = tempfile::tempdir()?;
+        let db_oid: Oid = 5;
+        fs::create_dir_all(data_dir.path().join("base").join(db_oid.to_string()))?;
+
+        let rel = access::open_relation(10000, DEFAULTTABLESPACE_OID, &db_oid, "test");
+
+        {
+            let buffer_pool = BufferPool::new(20, StorageManager::new(data_dir.path()))
+                .with_wal(Wal::open(data_dir.path())?);
+            heap_insert(&buffer_pool, &rel, &HeapTuple::with_default_header(&0)?)?;
+            buffer_pool.checkpoint()?;
+
+            // Simulate a checkpoint that writes the page before a change made while the
+            // checkpoint is running, the change is only on the WAL.
+            let wal = buffer_pool.wal().unwrap();
+            let redo = wal.start_checkpoint();
+            buffer_pool.flush_all_buffers()?;
+            heap_insert(&buffer_pool, &rel, &HeapTuple::with_default_header(&1)?)?;
+            wal.insert(&WalRecord::Checkpoint { redo })?;
+            wal.flush_all()?;
+
+            // The first change after the redo LSN logs the page image.
+            let records = wal.read_records()?;
+            assert!(matches!(
+                records[records.len() - 2].1,
+                WalRecord::PageImage { .. }
+            ));
+            std::mem::forget(buffer_pool);
+        }
+
+        let buffer_pool = BufferPool::new(20, StorageManager::new(data_dir.path()))
+            .with_wal(Wal::open(data_dir.path())?);
+        recover(&buffer_pool)?;
+
+        let mut scanner = HeapScanner::new(&buffer_pool, &rel)?;
+        let mut tuples = 0;
+        while let Some(tuple) = scanner.next_tuple()? {
+            assert_eq!(bincode::deserialize::<i32>(&tuple.data)?, tuples);
+            tuples += 1;
+        }
+        assert_eq!(tuples, 2);
+
+        Ok(())
+    }
+}
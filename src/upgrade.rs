@@ -0,0 +1,511 @@
+//! Conversion of data directories initialized by the previous server version to the current
+//! on-disk format.
+//!
+//! Only relations storing heap tuples are rewritten. Keys of B-tree indexes are encoded
+//! independently of the tuple format, and the tuple pointers stored on indexes remain valid
+//! because each tuple is kept on the same line pointer of the same page.
+
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Result};
+
+use crate::{
+    access::heaptuple::{
+        HeapTuple, HeapTupleData, TupleDesc, HEAP_HASNULL, HEAP_TUPLE_HEADER_SIZE,
+        VARLENA_HEADER_SIZE,
+    },
+    catalog::{
+        pg_attribute::{self, PgAttribute},
+        pg_authid::{self, PgAuthId},
+        pg_class::{self, PgClass},
+        pg_database::{self, PgDatabase},
+        pg_index::{self, PgIndex},
+        pg_tablespace::{self, PgTablespace, GLOBALTABLESPACE_OID},
+    },
+    storage::{
+        control::{ClusterState, ControlFile, CATALOG_VERSION, PREVIOUS_CATALOG_VERSION},
+        disk::Disk,
+        item_id_data_from_page,
+        page::{
+            page_add_item, page_get_lsn, page_init, page_set_checksum, page_set_item_flags,
+            page_set_lsn, PageHeader, LP_NORMAL, PAGE_HEADER_SIZE,
+        },
+        relation_locator::relation_path,
+        value_from_page_item, wal, Page, PageNumber,
+    },
+    Datum, NullableDatum, Oid, FIRST_NORMAL_OBJECT_ID, INVALID_OID,
+};
+
+/// Errors of upgrading a data directory.
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum Error {
+    #[error("data directory was initialized with catalog version {0}, only data directories of catalog version {} can be upgraded", PREVIOUS_CATALOG_VERSION)]
+    UnsupportedCatalogVersion(u32),
+
+    #[error("database system was not shut down cleanly (state: {0}), start and stop the server before upgrading")]
+    NotShutDown(ClusterState),
+}
+
+/// Size of each attribute, as stored on pg_attribute.attlen, of the rows of system catalogs.
+const CATALOGS: &[(Oid, &[i64])] = &[
+    (pg_tablespace::RELATION_OID, &[8, -1]),
+    (pg_authid::RELATION_OID, &[8, -1, -1]),
+    (pg_database::RELATION_OID, &[8, -1, 8]),
+    (pg_class::RELATION_OID, &[8, -1, 8, 1]),
+    (pg_attribute::RELATION_OID, &[8, -1, 8, 8, 8, 1, -1]),
+    (pg_index::RELATION_OID, &[8, 8, 8]),
+];
+
+/// Convert a single tuple between two formats, using the given tuple description.
+type ConvertTuple = fn(&[u8], &TupleDesc) -> Result<Vec<u8>>;
+
+/// Convert all relations of the given data directory to the current catalog version.
+///
+/// The server must have been shut down cleanly, so that there are no changes on the previous
+/// format waiting to be recovered from WAL. The converted relations are written on new files
+/// that replace the original files only after all relations were converted, so the data
+/// directory is kept unchanged if any relation can not be converted.
+pub fn upgrade_database(data_dir: &Path) -> Result<()> {
+    let control = ControlFile::open_for_upgrade(data_dir)?;
+    let data = control.data();
+    if data.catalog_version == CATALOG_VERSION {
+        log::info!(
+            "data directory is already on catalog version {}",
+            CATALOG_VERSION
+        );
+        return Ok(());
+    }
+    if data.catalog_version != PREVIOUS_CATALOG_VERSION {
+        bail!(Error::UnsupportedCatalogVersion(data.catalog_version));
+    }
+    if data.state != ClusterState::Shutdown {
+        bail!(Error::NotShutDown(data.state));
+    }
+
+    let mut rewriter = Rewriter::new(data_dir, convert_previous_tuple);
+    rewriter.rewrite_catalog::<PgTablespace>(
+        GLOBALTABLESPACE_OID,
+        INVALID_OID,
+        pg_tablespace::RELATION_OID,
+    )?;
+    rewriter.rewrite_catalog::<PgAuthId>(
+        GLOBALTABLESPACE_OID,
+        INVALID_OID,
+        pg_authid::RELATION_OID,
+    )?;
+    let databases = rewriter.rewrite_catalog::<PgDatabase>(
+        GLOBALTABLESPACE_OID,
+        INVALID_OID,
+        pg_database::RELATION_OID,
+    )?;
+    for database in &databases {
+        log::info!("upgrading database {}", database.datname);
+        rewriter.rewrite_database(database)?;
+    }
+    rewriter.finish()?;
+    wal::upgrade_wal(data_dir)?;
+
+    control.set_catalog_version(CATALOG_VERSION)?;
+    log::info!(
+        "data directory upgraded from catalog version {} to {}",
+        PREVIOUS_CATALOG_VERSION,
+        CATALOG_VERSION
+    );
+    Ok(())
+}
+
+/// Rewrite the tuples of relation files using a tuple conversion.
+struct Rewriter<'a> {
+    data_dir: &'a Path,
+
+    convert: ConvertTuple,
+
+    /// Rewritten files and the relation files that they replace.
+    files: Vec<(PathBuf, PathBuf)>,
+}
+
+impl<'a> Rewriter<'a> {
+    fn new(data_dir: &'a Path, convert: ConvertTuple) -> Self {
+        Self {
+            data_dir,
+            convert,
+            files: Vec::new(),
+        }
+    }
+
+    /// Rewrite the system catalogs and the tables of the given database.
+    fn rewrite_database(&mut self, database: &PgDatabase) -> Result<()> {
+        let (tablespace, oid) = (database.dattablespace, database.oid);
+        let attributes =
+            self.rewrite_catalog::<PgAttribute>(tablespace, oid, pg_attribute::RELATION_OID)?;
+        let classes = self.rewrite_catalog::<PgClass>(tablespace, oid, pg_class::RELATION_OID)?;
+        let indexes = self
+            .rewrite_catalog::<PgIndex>(tablespace, oid, pg_index::RELATION_OID)?
+            .iter()
+            .map(|index| index.indexrelid)
+            .collect::<HashSet<_>>();
+
+        for class in &classes {
+            // The other system catalogs, like pg_namespace, were added after the previous version,
+            // so they are never stored on the previous format.
+            if class.oid < FIRST_NORMAL_OBJECT_ID || indexes.contains(&class.oid) {
+                continue;
+            }
+            let mut attrs = attributes
+                .iter()
+                .filter(|attr| attr.attrelid == class.oid)
+                .cloned()
+                .collect::<Vec<_>>();
+            attrs.sort_by_key(|attr| attr.attnum);
+
+            log::debug!("upgrading relation {}", class.relname);
+            self.rewrite_relation(
+                &relation_path(&class.reltablespace, &oid, &class.oid)?,
+                &TupleDesc { attrs },
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Rewrite the given system catalog and return its live rows on the new format.
+    fn rewrite_catalog<T: HeapTupleData>(
+        &mut self,
+        tablespace: Oid,
+        database: Oid,
+        oid: Oid,
+    ) -> Result<Vec<T>> {
+        let tuple_desc = catalog_tuple_desc(oid).expect("catalog tuple desc");
+        self.rewrite_relation(&relation_path(&tablespace, &database, &oid)?, &tuple_desc)?
+            .iter()
+            .map(|tuple| HeapTuple::decode(tuple)?.decode_data())
+            .collect()
+    }
+
+    /// Rewrite all pages of the relation file stored on the given path, relative to the data
+    /// directory, and return the live tuples on the new format.
+    fn rewrite_relation(&mut self, relpath: &Path, tuple_desc: &TupleDesc) -> Result<Vec<Vec<u8>>> {
+        let path = self.data_dir.join(relpath);
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+
+        // Remove the leftover of a previous upgrade that failed.
+        let new_path = path.with_extension("upgrade");
+        if new_path.exists() {
+            fs::remove_file(&new_path)?;
+        }
+
+        let source = Disk::open(&path)?;
+        let target = Disk::open(&new_path)?;
+        let mut tuples = Vec::new();
+        for page_number in 1..=source.size()? {
+            let page = Page::default();
+            source.read_page(page_number, &page)?;
+            let page = self.rewrite_page(&page, page_number, tuple_desc, &mut tuples)?;
+            target.allocate_page()?;
+            target.write_page(page_number, &page)?;
+        }
+        target.sync()?;
+
+        self.files.push((new_path, path));
+        Ok(tuples)
+    }
+
+    /// Return a copy of the given page with all tuples converted, appending the live tuples to
+    /// the given vector. Line pointers keep the same offset numbers and flags.
+    fn rewrite_page(
+        &self,
+        page: &Page,
+        page_number: PageNumber,
+        tuple_desc: &TupleDesc,
+        tuples: &mut Vec<Vec<u8>>,
+    ) -> Result<Page> {
+        // Pages allocated but never initialized don't have any tuple.
+        if (PageHeader::new(page)?.start_free_space as usize) < PAGE_HEADER_SIZE {
+            return Ok(page.clone());
+        }
+
+        let new_page = Page::default();
+        page_init(&new_page)?;
+        page_set_lsn(&new_page, page_get_lsn(page)?)?;
+        for item_id in item_id_data_from_page(page)? {
+            let item = if item_id.length > 0 {
+                (self.convert)(&value_from_page_item(page, &item_id)?, tuple_desc)?
+            } else {
+                Vec::new()
+            };
+            let offset = page_add_item(&new_page, &item)?;
+            if item_id.flags != LP_NORMAL {
+                page_set_item_flags(&new_page, offset, item_id.flags)?;
+            } else {
+                tuples.push(item);
+            }
+        }
+        page_set_checksum(&new_page, page_number)?;
+        Ok(new_page)
+    }
+
+    /// Replace the relation files with the rewritten files.
+    fn finish(self) -> Result<()> {
+        for (new_path, path) in &self.files {
+            fs::rename(new_path, path)?;
+        }
+        Ok(())
+    }
+}
+
+/// Return the tuple description of the rows of the given system catalog on disk, if the given
+/// oid is a system catalog.
+fn catalog_tuple_desc(oid: Oid) -> Option<TupleDesc> {
+    let (_, attlens) = CATALOGS.iter().find(|(catalog, _)| *catalog == oid)?;
+    let attrs = attlens
+        .iter()
+        .enumerate()
+        .map(|(attnum, attlen)| PgAttribute {
+            attrelid: oid,
+            attname: String::new(),
+            attnum: attnum + 1,
+            attlen: *attlen,
+            atttypid: INVALID_OID,
+            attnotnull: false,
+            attdefault: String::new(),
+        })
+        .collect();
+    Some(TupleDesc { attrs })
+}
+
+/// Convert a tuple stored on the previous format to the current format.
+fn convert_previous_tuple(tuple: &[u8], tuple_desc: &TupleDesc) -> Result<Vec<u8>> {
+    HeapTuple::from_datums(decode_previous_tuple(tuple, tuple_desc)?, tuple_desc)?.encode()
+}
+
+/// Decode the values of a tuple stored on the previous format.
+///
+/// The previous format had the same header fields, but the NULL bitmap was the bincode
+/// serialization of a vector of booleans. Varlena values had a u32 length header followed by the
+/// bincode serialization of the value bytes, which were themselves the bincode serialization of
+/// the string.
+fn decode_previous_tuple(tuple: &[u8], tuple_desc: &TupleDesc) -> Result<Vec<NullableDatum>> {
+    if tuple.len() < HEAP_TUPLE_HEADER_SIZE {
+        bail!("heap tuple too short: {} bytes", tuple.len());
+    }
+    let t_infomask = u16::from_le_bytes([tuple[0], tuple[1]]);
+    let t_hoff = u16::from_le_bytes([tuple[4], tuple[5]]) as usize;
+    if t_hoff < HEAP_TUPLE_HEADER_SIZE || t_hoff > tuple.len() {
+        bail!("invalid heap tuple header offset {}", t_hoff);
+    }
+    let t_bits: Vec<bool> = if t_infomask & HEAP_HASNULL != 0 {
+        bincode::deserialize(&tuple[HEAP_TUPLE_HEADER_SIZE..t_hoff])?
+    } else {
+        Vec::new()
+    };
+
+    let mut data = &tuple[t_hoff..];
+    let mut values = Vec::with_capacity(tuple_desc.attrs.len());
+    for (attr, pg_attr) in tuple_desc.attrs.iter().enumerate() {
+        if t_bits.get(attr).copied().unwrap_or(false) {
+            values.push(None);
+            continue;
+        }
+
+        let size = if pg_attr.attlen < 0 {
+            match data.get(..VARLENA_HEADER_SIZE) {
+                Some(header) => {
+                    let len = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+                    VARLENA_HEADER_SIZE + len as usize
+                }
+                None => bail!("unexpected end of tuple data"),
+            }
+        } else {
+            pg_attr.attlen as usize
+        };
+        if data.len() < size {
+            bail!("unexpected end of tuple data");
+        }
+        let (value, rest) = data.split_at(size);
+        data = rest;
+
+        let value = if pg_attr.attlen < 0 {
+            let bytes: Vec<u8> = bincode::deserialize(&value[VARLENA_HEADER_SIZE..])?;
+            bincode::deserialize::<String>(&bytes)?.into_bytes()
+        } else {
+            value.to_vec()
+        };
+        values.push(Some(Datum::from(value)));
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        access::tableam::HEAP_TABLE_AM_OID,
+        catalog::{
+            pg_database::TINYDB_OID, pg_namespace::PG_CATALOG_NAMESPACE,
+            pg_tablespace::DEFAULTTABLESPACE_OID,
+        },
+        database::Database,
+    };
+
+    /// Encode a tuple on the current format to the previous format.
+    fn encode_previous_tuple(tuple: &[u8], tuple_desc: &TupleDesc) -> Result<Vec<u8>> {
+        let tuple = HeapTuple::decode(tuple)?;
+        let mut nulls = Vec::new();
+        let mut data = Vec::new();
+        for attr in &tuple_desc.attrs {
+            match tuple.get_attr(attr.attnum, tuple_desc)? {
+                Some(value) if attr.attlen < 0 => {
+                    let bytes = bincode::serialize(&String::from_utf8(value.to_vec())?)?;
+                    let bytes = bincode::serialize(&bytes)?;
+                    data.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+                    data.extend_from_slice(&bytes);
+                    nulls.push(false);
+                }
+                Some(value) => {
+                    data.extend_from_slice(&value);
+                    nulls.push(false);
+                }
+                None => nulls.push(true),
+            }
+        }
+
+        let has_nulls = tuple.header.has_nulls();
+        let t_bits = if has_nulls {
+            bincode::serialize(&nulls)?
+        } else {
+            Vec::new()
+        };
+        let t_nattrs = nulls.iter().filter(|is_null| !**is_null).count();
+        let mut previous = Vec::new();
+        previous.extend_from_slice(&tuple.header.fields.t_infomask.to_le_bytes());
+        previous.extend_from_slice(&(t_nattrs as u16).to_le_bytes());
+        previous.extend_from_slice(&((HEAP_TUPLE_HEADER_SIZE + t_bits.len()) as u16).to_le_bytes());
+        previous.extend_from_slice(&t_bits);
+        previous.extend_from_slice(&data);
+        Ok(previous)
+    }
+
+    #[test]
+    fn test_decode_previous_tuple() -> Result<()> {
+        let tuple_desc = catalog_tuple_desc(pg_class::RELATION_OID).unwrap();
+        let class = PgClass {
+            oid: 42,
+            relname: String::from("t"),
+            reltablespace: 1663,
+            relisshared: false,
+            relam: HEAP_TABLE_AM_OID,
+            relnamespace: PG_CATALOG_NAMESPACE,
+            relowner: pg_authid::BOOTSTRAP_SUPERUSER_ID,
+            relacl: String::new(),
+        };
+        let tuple = HeapTuple::with_default_header(&class)?.encode()?;
+        let previous = encode_previous_tuple(&tuple, &tuple_desc)?;
+        // Each varlena value had 16 more bytes, the lengths of the two bincode serializations,
+        // and the previous pg_class rows did not have the 8 bytes of relam, relnamespace and
+        // relowner, nor the 4 bytes of the empty relacl.
+        assert_eq!(previous.len(), tuple.len() + 16 - 28);
+
+        let tuple = HeapTuple::decode(&convert_previous_tuple(&previous, &tuple_desc)?)?;
+        let converted = tuple.decode_data::<PgClass>()?;
+        assert_eq!(converted.oid, 42);
+        assert_eq!(converted.relname, "t");
+        assert_eq!(converted.reltablespace, 1663);
+        assert!(!converted.relisshared);
+        assert_eq!(converted.relam, HEAP_TABLE_AM_OID);
+        assert_eq!(converted.relnamespace, PG_CATALOG_NAMESPACE);
+        assert_eq!(converted.relowner, pg_authid::BOOTSTRAP_SUPERUSER_ID);
+        assert!(converted.relacl.is_empty());
+
+        assert!(decode_previous_tuple(&previous[..previous.len() - 2], &tuple_desc).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_upgrade_database() -> Result<()> {
+        let data_dir = tempfile::tempdir()?;
+        let relation = {
+            let mut db = Database::open(data_dir.path())?;
+            db.execute("CREATE TABLE t(a int, b text, c boolean);")?;
+            db.execute(
+                "INSERT INTO t VALUES (1, 'one', true), (2, NULL, NULL), (3, 'three', false);",
+            )?;
+            db.execute("CREATE INDEX t_a ON t(a);")?;
+            db.execute("DELETE FROM t WHERE a = 3;")?;
+            db.query("SELECT oid FROM pg_class WHERE relname = 't';")?
+                .get(0)
+                .unwrap()
+                .get::<_, i64>(0)? as Oid
+        };
+
+        // Convert the data directory back to the previous format.
+        let control = ControlFile::open(data_dir.path())?;
+        let mut rewriter = Rewriter::new(data_dir.path(), encode_previous_tuple);
+        for (oid, _) in CATALOGS {
+            let (tablespace, database) = match *oid {
+                pg_tablespace::RELATION_OID
+                | pg_authid::RELATION_OID
+                | pg_database::RELATION_OID => (GLOBALTABLESPACE_OID, INVALID_OID),
+                _ => (DEFAULTTABLESPACE_OID, TINYDB_OID),
+            };
+            rewriter.rewrite_relation(
+                &relation_path(&tablespace, &database, oid)?,
+                &catalog_tuple_desc(*oid).unwrap(),
+            )?;
+        }
+        let tuple_desc = TupleDesc {
+            attrs: [4, -1, 1]
+                .iter()
+                .enumerate()
+                .map(|(attnum, attlen)| PgAttribute {
+                    attrelid: relation,
+                    attname: String::new(),
+                    attnum: attnum + 1,
+                    attlen: *attlen,
+                    atttypid: INVALID_OID,
+                    attnotnull: false,
+                    attdefault: String::new(),
+                })
+                .collect(),
+        };
+        let relpath = relation_path(&DEFAULTTABLESPACE_OID, &TINYDB_OID, &relation)?;
+        rewriter.rewrite_relation(&relpath, &tuple_desc)?;
+        rewriter.finish()?;
+        control.set_catalog_version(PREVIOUS_CATALOG_VERSION)?;
+        assert!(Database::open(data_dir.path()).is_err());
+
+        control.set_state(ClusterState::InProduction)?;
+        let err = upgrade_database(data_dir.path()).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<Error>(),
+            Some(&Error::NotShutDown(ClusterState::InProduction))
+        );
+        control.set_state(ClusterState::Shutdown)?;
+
+        upgrade_database(data_dir.path())?;
+        assert!(!data_dir
+            .path()
+            .join(relpath.with_extension("upgrade"))
+            .exists());
+
+        let mut db = Database::open(data_dir.path())?;
+        let rows = db.query("SELECT a, b, c FROM t ORDER BY a;")?;
+        assert_eq!(rows.len(), 2);
+        let row = rows.get(0).unwrap();
+        assert_eq!(row.get::<_, i32>("a")?, 1);
+        assert_eq!(row.get::<_, String>("b")?, "one");
+        assert!(row.get::<_, bool>("c")?);
+        let row = rows.get(1).unwrap();
+        assert_eq!(row.get::<_, Option<String>>("b")?, None);
+        assert_eq!(row.get::<_, Option<bool>>("c")?, None);
+
+        // Index entries still point to the same tuples.
+        let rows = db.query("SELECT b FROM t WHERE a = 1;")?;
+        assert_eq!(rows.get(0).unwrap().get::<_, String>(0)?, "one");
+        Ok(())
+    }
+}
@@ -0,0 +1,223 @@
+//! Tests of a server with many connections running at the same time.
+
+mod common;
+
+use std::time::Duration;
+
+use tinydb::{
+    backend::auth::AuthMethod,
+    postgres_protocol::{
+        client::{Client, QueryResult, ServerError},
+        commands::{PROTOCOL_VERSION_NUMBER, SSL_REQUEST_NUMBER},
+    },
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+use common::{TestServer, USER};
+
+/// Return the server error of the given result.
+fn server_error<T>(result: anyhow::Result<T>) -> ServerError {
+    match result {
+        Ok(_) => panic!("expected an error from the server"),
+        Err(err) => err.downcast().expect("error not sent by the server"),
+    }
+}
+
+/// Return the value of the single row and column returned by the given results.
+fn single_value(results: &[QueryResult]) -> Option<String> {
+    match results {
+        [QueryResult::Rows { rows, .. }] => rows[0][0].clone(),
+        results => panic!("unexpected results {:?}", results),
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_concurrent_unique_inserts() -> anyhow::Result<()> {
+    let server = TestServer::start().await?;
+    let mut client = server.connect("setup").await?;
+    client
+        .simple_query("CREATE TABLE t(a int); CREATE UNIQUE INDEX t_a ON t(a);")
+        .await?;
+
+    // All connections insert the same keys at the same time, so only one of them inserts each
+    // key and the others skip it.
+    let keys = 200;
+    let rows_per_insert = 20;
+    let mut tasks = Vec::new();
+    for i in 0..8 {
+        let mut client = server.connect(&format!("insert {}", i)).await?;
+        tasks.push(tokio::spawn(async move {
+            for start in (0..keys).step_by(rows_per_insert) {
+                let values = (start..start + rows_per_insert)
+                    .map(|key| format!("({})", key))
+                    .collect::<Vec<_>>();
+                client
+                    .simple_query(&format!(
+                        "INSERT INTO t VALUES {} ON CONFLICT DO NOTHING;",
+                        values.join(", ")
+                    ))
+                    .await?;
+            }
+            client.terminate().await
+        }));
+    }
+    for task in tasks {
+        task.await??;
+    }
+
+    let count = client.simple_query("SELECT count(*) FROM t;").await?;
+    assert_eq!(single_value(&count), Some(keys.to_string()));
+    client.terminate().await?;
+
+    server.stop().await
+}
+
+#[tokio::test]
+async fn test_current_thread_runtime() -> anyhow::Result<()> {
+    // Statements run on the blocking thread pool, so the server also runs on a runtime with a
+    // single thread, shared by all connections.
+    let server = TestServer::start().await?;
+    let mut client = server.connect("setup").await?;
+    client
+        .simple_query("CREATE TABLE t(a int); INSERT INTO t VALUES (1), (2), (3);")
+        .await?;
+
+    let mut other = server.connect("other").await?;
+    let (count, sum) = tokio::join!(
+        client.simple_query("SELECT count(*) FROM t;"),
+        other.simple_query("SELECT sum(a) FROM t;")
+    );
+    assert_eq!(single_value(&count?), Some(String::from("3")));
+    assert_eq!(single_value(&sum?), Some(String::from("6")));
+    client.terminate().await?;
+    other.terminate().await?;
+
+    server.stop().await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_max_connections() -> anyhow::Result<()> {
+    let server = TestServer::start_with(|config| config.max_connections = 2).await?;
+    let mut first = server.connect("first").await?;
+    let mut second = server.connect("second").await?;
+
+    let err = server_error(server.connect("third").await);
+    assert_eq!(err.severity, "FATAL");
+    assert_eq!(err.code, "53300");
+    assert_eq!(err.message, "sorry, too many clients already");
+
+    // Cancel requests don't take a slot, so the query of the second connection, waiting for
+    // the lock of the cursor of the first one, is canceled while all slots are taken.
+    first
+        .simple_query("CREATE TABLE t(a int); DECLARE c CURSOR FOR SELECT * FROM t;")
+        .await?;
+    let key = second.backend_key().unwrap();
+    let drop_table = tokio::spawn(async move {
+        let result = second.simple_query("DROP TABLE t;").await;
+        (second, result)
+    });
+    // The request is sent again if the query had not started yet when it was handled.
+    let cancel = async {
+        while !drop_table.is_finished() {
+            Client::cancel(server.addr, &key).await?;
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        anyhow::Ok(())
+    };
+    tokio::time::timeout(Duration::from_secs(10), cancel).await??;
+    let (second, result) = drop_table.await?;
+    assert_eq!(server_error(result).code, "57014");
+
+    second.terminate().await?;
+    first.terminate().await?;
+    server.stop().await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_ssl_request() -> anyhow::Result<()> {
+    let server = TestServer::start().await?;
+    let mut ssl_request = 8u32.to_be_bytes().to_vec();
+    ssl_request.extend(SSL_REQUEST_NUMBER.to_be_bytes());
+
+    // The request is declined and the startup continues without encryption.
+    let mut stream = TcpStream::connect(server.addr).await?;
+    stream.write_all(&ssl_request).await?;
+    assert_eq!(stream.read_u8().await?, b'N');
+    let parameters = [("user", USER), ("database", USER)];
+    let mut client = Client::startup(stream, &parameters, None).await?;
+    client.simple_query("CREATE TABLE t(a int);").await?;
+    client.terminate().await?;
+
+    // The request can't be sent again after it was declined.
+    let mut stream = TcpStream::connect(server.addr).await?;
+    stream.write_all(&ssl_request).await?;
+    assert_eq!(stream.read_u8().await?, b'N');
+    stream.write_all(&ssl_request).await?;
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await?;
+    assert!(String::from_utf8_lossy(&response).contains("duplicate SSL negotiation request"));
+
+    server.stop().await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_idle_session_timeout() -> anyhow::Result<()> {
+    let server = TestServer::start_with(|config| {
+        config.idle_session_timeout = Some(Duration::from_millis(200));
+    })
+    .await?;
+    let mut client = server.connect("idle").await?;
+    client.simple_query("CREATE TABLE t(a int);").await?;
+
+    tokio::time::sleep(Duration::from_millis(600)).await;
+    let err = server_error(client.simple_query("SELECT * FROM t;").await);
+    assert_eq!(err.severity, "FATAL");
+    assert_eq!(err.code, "57P05");
+    assert_eq!(
+        err.message,
+        "terminating connection due to idle-session timeout"
+    );
+
+    // The connection is closed.
+    assert!(client.simple_query("SELECT * FROM t;").await.is_err());
+
+    server.stop().await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_authentication_timeout() -> anyhow::Result<()> {
+    let server = TestServer::start_with(|config| {
+        config.auth_method = AuthMethod::Md5;
+        config.max_connections = 1;
+        config.authentication_timeout = Duration::from_millis(500);
+    })
+    .await?;
+
+    // The client sends the startup message and never answers the password request.
+    let mut stalled = TcpStream::connect(server.addr).await?;
+    let mut body = PROTOCOL_VERSION_NUMBER.to_be_bytes().to_vec();
+    body.extend(format!("user\0{}\0\0", USER).as_bytes());
+    stalled
+        .write_all(&(body.len() as u32 + 4).to_be_bytes())
+        .await?;
+    stalled.write_all(&body).await?;
+    assert_eq!(stalled.read_u8().await?, b'R');
+
+    let err = server_error(server.connect("rejected").await);
+    assert_eq!(err.code, "53300");
+
+    let mut response = Vec::new();
+    tokio::time::timeout(Duration::from_secs(10), stalled.read_to_end(&mut response)).await??;
+    assert!(String::from_utf8_lossy(&response).contains("canceling authentication due to timeout"));
+
+    // The slot of the stalled client is released, so the next client is authenticated.
+    let parameters = [("user", USER), ("database", USER)];
+    let stream = TcpStream::connect(server.addr).await?;
+    let err = server_error(Client::startup(stream, &parameters, Some("wrong")).await);
+    assert_eq!(err.code, "28P01");
+
+    server.stop().await
+}
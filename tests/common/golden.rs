@@ -0,0 +1,72 @@
+//! Comparison of the output of SQL files with their expected outputs, the golden files.
+
+use std::{env, fs, io, path::Path};
+
+/// Environment variable that, when set to 1, writes the actual outputs to the expected output
+/// files instead of comparing them, e.g `TINYDB_BLESS=1 cargo test`. Used to add new cases and
+/// to update the expected outputs after an intended change, which must be reviewed on the diff.
+pub const BLESS_VAR: &str = "TINYDB_BLESS";
+
+/// Return true if the expected outputs should be updated instead of compared.
+pub fn bless() -> bool {
+    env::var(BLESS_VAR).is_ok_and(|value| value == "1")
+}
+
+/// Return the paths of all sql files of the given directory, sorted by name.
+pub fn sql_files(dir: &Path) -> io::Result<Vec<std::path::PathBuf>> {
+    let mut files = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .filter(|path| match path {
+            Ok(path) => path.extension().is_some_and(|ext| ext == "sql"),
+            Err(_) => true,
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+    files.sort();
+    Ok(files)
+}
+
+/// Compare the given output with the contents of the expected output file, or write the output
+/// to the file if blessing. Return a description of the first different line if they don't
+/// match.
+pub fn check(expected_file: &Path, output: &str) -> Result<(), String> {
+    if bless() {
+        return fs::write(expected_file, output)
+            .map_err(|err| format!("failed to write {:?}: {}", expected_file, err));
+    }
+
+    let expected = match fs::read_to_string(expected_file) {
+        Ok(expected) => expected,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            return Err(format!(
+                "expected output {:?} does not exist, run with {}=1 to create it",
+                expected_file, BLESS_VAR
+            ))
+        }
+        Err(err) => return Err(format!("failed to read {:?}: {}", expected_file, err)),
+    };
+    if expected == output {
+        return Ok(());
+    }
+
+    let mut expected_lines = expected.lines();
+    let mut output_lines = output.lines();
+    let mut line = 1;
+    loop {
+        match (expected_lines.next(), output_lines.next()) {
+            (Some(expected), Some(actual)) if expected == actual => line += 1,
+            (None, None) => {
+                return Err(format!(
+                    "output differs from {:?} on line endings",
+                    expected_file
+                ))
+            }
+            (expected, actual) => {
+                return Err(format!(
+                    "output differs from {:?} at line {}:\n  expected: {:?}\n  actual:   {:?}\n\
+                     run with {}=1 to update the expected output",
+                    expected_file, line, expected, actual, BLESS_VAR
+                ))
+            }
+        }
+    }
+}
@@ -0,0 +1,133 @@
+// Each test crate that includes this module only uses some of its helpers.
+#![allow(dead_code)]
+
+pub mod golden;
+
+use std::{net::SocketAddr, time::Duration};
+
+use tempfile::TempDir;
+use tinydb::{
+    backend::{self, auth::AuthMethod, DEFAULT_AUTHENTICATION_TIMEOUT, DEFAULT_MAX_CONNECTIONS},
+    initdb::init_database,
+    lru::ReplacementPolicy,
+    postgres_protocol::client::Client,
+    sql::guc::SessionVariables,
+    storage::{
+        smgr::{StorageManager, DEFAULT_MAX_OPEN_FILES},
+        wal::{Wal, WalSyncMethod},
+        BufferPool,
+    },
+};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::oneshot,
+    task::JoinHandle,
+};
+
+/// Name of the superuser and of the database created on the data directory of the server.
+pub const USER: &str = "tinydb";
+
+/// A tinydb server running on a temporary data directory, accepting connections on an
+/// ephemeral port of localhost without authentication.
+///
+/// The server runs on the tokio runtime of the test.
+pub struct TestServer {
+    pub addr: SocketAddr,
+    shutdown: Option<oneshot::Sender<()>>,
+    server: JoinHandle<()>,
+    _data_dir: TempDir,
+}
+
+impl TestServer {
+    /// Initialize a new data directory and start a server on it.
+    pub async fn start() -> anyhow::Result<Self> {
+        Self::start_with(|_| {}).await
+    }
+
+    /// Same as start, but the server configuration is changed by the given function before the
+    /// server starts.
+    pub async fn start_with<F>(configure: F) -> anyhow::Result<Self>
+    where
+        F: FnOnce(&mut backend::Config),
+    {
+        let data_dir = tempfile::tempdir()?;
+
+        let wal = Wal::open_with_sync_method(data_dir.path(), false, WalSyncMethod::default())?;
+        let buffer = BufferPool::new(120, StorageManager::new(data_dir.path()))
+            .with_fsync(false)
+            .with_wal(wal);
+        init_database(&buffer, data_dir.path(), USER, None)?;
+        drop(buffer);
+
+        let mut config = backend::Config {
+            data_dir: data_dir.path().to_path_buf(),
+            buffer_pool_size: 120,
+            auth_method: AuthMethod::Trust,
+            checkpoint_interval: Duration::from_secs(300),
+            bgwriter_delay: Duration::from_millis(200),
+            bgwriter_lru_maxpages: 100,
+            prefetch_depth: 8,
+            direct_io: false,
+            max_open_files: DEFAULT_MAX_OPEN_FILES,
+            replacement_policy: ReplacementPolicy::Lru,
+            data_checksums: true,
+            fsync: false,
+            wal_sync_method: WalSyncMethod::default(),
+            log_min_duration: None,
+            metrics_addr: None,
+            unix_socket: None,
+            session_variables: SessionVariables::default(),
+            idle_session_timeout: None,
+            tcp_keepalives_idle: None,
+            tcp_keepalives_interval: None,
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            authentication_timeout: DEFAULT_AUTHENTICATION_TIMEOUT,
+        };
+        configure(&mut config);
+
+        // The listener is bound before the server starts, so clients can connect right away.
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+
+        let (shutdown, shutdown_rx) = oneshot::channel();
+        let server = tokio::spawn(async move {
+            backend::start(&config, listener, async {
+                let _ = shutdown_rx.await;
+            })
+            .await
+        });
+
+        Ok(Self {
+            addr,
+            shutdown: Some(shutdown),
+            server,
+            _data_dir: data_dir,
+        })
+    }
+
+    /// Open a new connection to the server as the superuser, sending the given application
+    /// name on startup.
+    pub async fn connect(&self, application_name: &str) -> anyhow::Result<Client> {
+        let parameters = [
+            ("user", USER),
+            ("database", USER),
+            ("application_name", application_name),
+        ];
+        Client::startup(TcpStream::connect(self.addr).await?, &parameters, None).await
+    }
+
+    /// Shut down the server, waiting for the shutdown checkpoint.
+    pub async fn stop(mut self) -> anyhow::Result<()> {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+        (&mut self.server).await?;
+        Ok(())
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        self.server.abort();
+    }
+}
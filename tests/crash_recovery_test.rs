@@ -0,0 +1,337 @@
+//! Crash recovery tests. A workload runs on a database whose storage crashes on a chosen page
+//! write, the database is discarded without the shutdown checkpoint, and the changes of all
+//! statements that completed before the crash must be recovered from the write-ahead log when the
+//! database is opened again.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    io::Write,
+    mem,
+    ops::Range,
+    path::Path,
+    sync::Arc,
+};
+
+use anyhow::Result;
+use tinydb::{
+    database::Database,
+    storage::{
+        fault::{Fault, FaultInjector, InjectedFault},
+        smgr::StorageManager,
+        wal::{WAL_DIR, WAL_FILE},
+    },
+};
+
+/// Tables of the workload, each one with an index on its id column.
+const TABLES: [&str; 2] = ["accounts", "events"];
+
+/// Number of rows inserted on each table by each round of the workload.
+const ROWS_PER_ROUND: i32 = 25;
+
+/// Number of rounds of the workload.
+const ROUNDS: i32 = 12;
+
+/// Number of page writes of the workload where it crashes, spread over all of its writes.
+const CRASH_POINTS: u64 = 8;
+
+/// A statement of the workload.
+#[derive(Debug, Clone)]
+enum Step {
+    Insert {
+        table: &'static str,
+        ids: Range<i32>,
+    },
+    Delete {
+        table: &'static str,
+        ids: Range<i32>,
+    },
+    Analyze {
+        table: &'static str,
+    },
+    Checkpoint,
+}
+
+impl Step {
+    fn execute(&self, db: &mut Database) -> Result<()> {
+        match self {
+            Self::Insert { table, ids } => {
+                let values = ids
+                    .clone()
+                    .map(|id| format!("({}, '{}')", id, value(table, id)))
+                    .collect::<Vec<_>>();
+                db.execute(&format!(
+                    "INSERT INTO {} VALUES {};",
+                    table,
+                    values.join(", ")
+                ))?;
+            }
+            Self::Delete { table, ids } => {
+                db.execute(&format!(
+                    "DELETE FROM {} WHERE id >= {} AND id < {};",
+                    table, ids.start, ids.end
+                ))?;
+            }
+            Self::Analyze { table } => {
+                db.execute(&format!("ANALYZE {};", table))?;
+            }
+            Self::Checkpoint => db.checkpoint()?,
+        }
+        Ok(())
+    }
+
+    /// Apply the changes of the statement on the ids of each table.
+    fn apply(&self, ids: &mut BTreeMap<&'static str, BTreeSet<i32>>) {
+        match self {
+            Self::Insert { table, ids: range } => {
+                ids.entry(table).or_default().extend(range.clone());
+            }
+            Self::Delete { table, ids: range } => {
+                ids.entry(table)
+                    .or_default()
+                    .retain(|id| !range.contains(id));
+            }
+            Self::Analyze { .. } | Self::Checkpoint => {}
+        }
+    }
+}
+
+/// Return the value stored on the row with the given id. Values are large, so the tables have
+/// enough pages for lookups by id to use the index.
+fn value(table: &str, id: i32) -> String {
+    format!(
+        "{} {:06} {}",
+        table,
+        id,
+        "x".repeat(500 + id as usize % 500)
+    )
+}
+
+/// Return the statements of the workload: rows are inserted on all tables, some of them are
+/// deleted, and the statistics of the tables are updated on the catalogs and a checkpoint is
+/// performed after every few rounds.
+fn workload() -> Vec<Step> {
+    let mut steps = Vec::new();
+    for round in 0..ROUNDS {
+        let start = round * ROWS_PER_ROUND;
+        for table in TABLES {
+            steps.push(Step::Insert {
+                table,
+                ids: start..start + ROWS_PER_ROUND,
+            });
+        }
+        if round % 3 == 2 {
+            steps.push(Step::Delete {
+                table: TABLES[round as usize % TABLES.len()],
+                ids: start - ROWS_PER_ROUND / 2..start + ROWS_PER_ROUND / 4,
+            });
+        }
+        if round % 4 == 1 {
+            steps.push(Step::Analyze {
+                table: TABLES[round as usize / 4 % TABLES.len()],
+            });
+        }
+        if round % 4 == 3 {
+            steps.push(Step::Checkpoint);
+        }
+    }
+    steps
+}
+
+/// Ids of the rows of each table after a crash.
+struct Expected {
+    /// Ids after the last statement that completed before the crash.
+    committed: BTreeMap<&'static str, BTreeSet<i32>>,
+
+    /// Ids after the statement that failed by the crash, the same as committed if no statement
+    /// failed. Statements are not atomic, so its changes may be recovered only partially.
+    failed: BTreeMap<&'static str, BTreeSet<i32>>,
+}
+
+/// Create the tables of the workload on a new data directory, then run the workload until it
+/// crashes by the given injector and kill the database without the shutdown checkpoint. Return
+/// the ids expected after recovery.
+fn run_until_crash(data_dir: &Path, injector: Arc<FaultInjector>) -> Result<Expected> {
+    let mut db = Database::open(data_dir)?;
+    for table in TABLES {
+        db.execute(&format!(
+            "CREATE TABLE {0}(id int, value text); CREATE INDEX {0}_id ON {0}(id);",
+            table
+        ))?;
+    }
+    drop(db);
+
+    let mut db = Database::open_with_storage_manager(
+        data_dir,
+        StorageManager::new(data_dir).with_fault_injector(injector),
+    )?;
+
+    let mut committed = BTreeMap::new();
+    let mut failed = None;
+    for step in workload() {
+        match step.execute(&mut db) {
+            Ok(()) => step.apply(&mut committed),
+            Err(err) => {
+                assert!(
+                    err.chain().any(|err| err.is::<InjectedFault>()),
+                    "{:?} failed without an injected fault: {:?}",
+                    step,
+                    err
+                );
+                let mut ids = committed.clone();
+                step.apply(&mut ids);
+                failed = Some(ids);
+                break;
+            }
+        }
+    }
+
+    // Simulate a crash, the remaining dirty pages are never written.
+    mem::forget(db);
+    Ok(Expected {
+        failed: failed.unwrap_or_else(|| committed.clone()),
+        committed,
+    })
+}
+
+/// Check that the tables, indexes and rows of the given database match the expected ids.
+fn check_database(db: &mut Database, expected: &Expected) -> Result<()> {
+    let tables = db.list_tables()?;
+    let names = tables
+        .iter()
+        .map(|row| row.get::<_, String>("Name"))
+        .collect::<Result<BTreeSet<_>>>()?;
+    assert_eq!(names, TABLES.iter().map(|t| t.to_string()).collect());
+
+    let indexes = db.query(
+        "SELECT c.relname, t.relname FROM pg_index i \
+         JOIN pg_class c ON c.oid = i.indexrelid JOIN pg_class t ON t.oid = i.indrelid;",
+    )?;
+    let indexes = indexes
+        .iter()
+        .map(|row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))
+        .collect::<Result<BTreeSet<_>>>()?;
+    assert_eq!(
+        indexes,
+        TABLES
+            .iter()
+            .map(|t| (format!("{}_id", t), t.to_string()))
+            .collect()
+    );
+
+    for table in TABLES {
+        assert_eq!(db.describe_table(table)?.len(), 2);
+
+        let rows = db.query(&format!("SELECT id, value FROM {};", table))?;
+        let mut ids = BTreeSet::new();
+        for row in rows.iter() {
+            let id = row.get::<_, i32>(0)?;
+            assert_eq!(row.get::<_, String>(1)?, value(table, id));
+            assert!(ids.insert(id), "duplicated id {} on {}", id, table);
+        }
+
+        let empty = BTreeSet::new();
+        let committed = expected.committed.get(table).unwrap_or(&empty);
+        let failed = expected.failed.get(table).unwrap_or(&empty);
+        for id in committed.union(failed).chain(ids.iter()) {
+            let in_doubt = committed.contains(id) != failed.contains(id);
+            if !in_doubt {
+                assert_eq!(
+                    ids.contains(id),
+                    committed.contains(id),
+                    "id {} of {}",
+                    id,
+                    table
+                );
+            }
+        }
+
+        // Changes of the failed statement may not be on the index, so only ids that are not in
+        // doubt are looked up.
+        db.execute(&format!("ANALYZE {};", table))?;
+        for id in committed.iter().step_by(17) {
+            if committed.contains(id) == failed.contains(id) {
+                let rows = db.query(&format!("SELECT value FROM {} WHERE id = {};", table, id))?;
+                assert_eq!(rows.len(), 1, "id {} of {}", id, table);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Crash the workload on some of its page writes with the given fault and check the data
+/// recovered after each crash.
+fn test_crash_recovery(fault: Fault) -> Result<()> {
+    // Run the workload without faults to count its page writes.
+    let total_writes = {
+        let data_dir = tempfile::tempdir()?;
+        let injector = Arc::new(FaultInjector::disabled());
+        let expected = run_until_crash(data_dir.path(), injector.clone())?;
+        let mut db = Database::open(data_dir.path())?;
+        check_database(&mut db, &expected)?;
+        injector.writes()
+    };
+    assert!(total_writes > CRASH_POINTS, "{} page writes", total_writes);
+
+    for crash_at in (1..=total_writes).step_by((total_writes / CRASH_POINTS) as usize) {
+        let data_dir = tempfile::tempdir()?;
+        let injector = Arc::new(FaultInjector::new(fault, crash_at));
+        let mut expected = run_until_crash(data_dir.path(), injector.clone())?;
+        assert!(injector.crashed(), "write {} was never executed", crash_at);
+
+        let mut db = Database::open(data_dir.path())?;
+        check_database(&mut db, &expected)?;
+
+        // The recovered database can be changed and opened again.
+        let step = Step::Insert {
+            table: TABLES[0],
+            ids: ROUNDS * ROWS_PER_ROUND..ROUNDS * ROWS_PER_ROUND + 1,
+        };
+        step.execute(&mut db)?;
+        drop(db);
+        let mut db = Database::open(data_dir.path())?;
+        step.apply(&mut expected.committed);
+        step.apply(&mut expected.failed);
+        check_database(&mut db, &expected)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_crash_on_failed_write() -> Result<()> {
+    test_crash_recovery(Fault::Fail)
+}
+
+#[test]
+fn test_crash_on_torn_write() -> Result<()> {
+    test_crash_recovery(Fault::TornWrite)
+}
+
+#[test]
+fn test_crash_on_torn_wal_write() -> Result<()> {
+    let data_dir = tempfile::tempdir()?;
+    let mut db = Database::open(data_dir.path())?;
+    db.execute("CREATE TABLE t(a int);")?;
+    db.execute("INSERT INTO t VALUES (1);")?;
+
+    // Crash in the middle of a WAL write, only the start of the record reaches the file.
+    mem::forget(db);
+    fs::OpenOptions::new()
+        .append(true)
+        .open(data_dir.path().join(WAL_DIR).join(WAL_FILE))?
+        .write_all(&[200, 0, 0, 0, 1, 2, 3, 4])?;
+
+    // The changes made after the recovery are also recovered after the next crash.
+    let mut db = Database::open(data_dir.path())?;
+    db.execute("INSERT INTO t VALUES (2), (3);")?;
+    mem::forget(db);
+
+    let mut db = Database::open(data_dir.path())?;
+    let rows = db.query("SELECT a FROM t;")?;
+    let values = rows
+        .iter()
+        .map(|row| row.get::<_, i32>(0))
+        .collect::<Result<BTreeSet<_>>>()?;
+    assert_eq!(values, BTreeSet::from([1, 2, 3]));
+    Ok(())
+}
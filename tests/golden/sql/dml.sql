@@ -0,0 +1,10 @@
+-- Statements that change rows, and the rows that they return.
+CREATE TABLE accounts(id int, owner varchar(20), balance int);
+INSERT INTO accounts VALUES (1, 'alice', 100), (2, 'bob', 50);
+INSERT INTO accounts(id, owner) VALUES (3, 'carol') RETURNING *;
+INSERT INTO accounts VALUES (4, 'dave', 10), (5, 'erin', 20) RETURNING id, balance * 2 AS doubled;
+
+DELETE FROM accounts WHERE id = 1;
+DELETE FROM accounts WHERE id = 42;
+DELETE FROM accounts WHERE balance IS NULL;
+SELECT * FROM accounts ORDER BY id;
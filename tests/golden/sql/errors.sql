@@ -0,0 +1,12 @@
+-- Errors are printed and the next statements are still executed.
+CREATE TABLE t(a int NOT NULL, b text);
+CREATE UNIQUE INDEX t_a_key ON t(a);
+INSERT INTO t VALUES (1, 'one');
+INSERT INTO t VALUES (1, 'again');
+INSERT INTO t(b) VALUES ('no a');
+INSERT INTO t VALUES ('x', 'not a number');
+SELECT c FROM t;
+SELECT * FROM missing;
+SELEC * FROM t;
+CREATE TABLE t(a int);
+SELECT * FROM t;
@@ -0,0 +1,15 @@
+-- Queries over a single table.
+CREATE TABLE products(id int, name text, price float8, stock int);
+INSERT INTO products VALUES
+  (1, 'keyboard', 49.9, 10),
+  (2, 'mouse', 19.5, 0),
+  (3, 'monitor', 199.0, 3),
+  (4, 'cable', 4.25, NULL);
+
+SELECT * FROM products;
+SELECT name, price FROM products WHERE price > 10 ORDER BY price DESC;
+SELECT name FROM products WHERE stock IS NULL;
+SELECT id, name FROM products ORDER BY name LIMIT 2;
+SELECT count(*), sum(stock), max(price) FROM products;
+SELECT id * 10 AS tens, name || '!' AS shout FROM products WHERE id <= 2;
+SELECT * FROM products WHERE name = 'missing';
@@ -0,0 +1,14 @@
+-- Meta-commands of the shell.
+CREATE TABLE items(id int NOT NULL, label varchar(10) DEFAULT 'none');
+CREATE INDEX items_id ON items(id);
+INSERT INTO items(id) VALUES (1), (2);
+\dt
+\d items
+\format csv
+SELECT * FROM items;
+\format json
+SELECT * FROM items;
+\format table
+SELECT * FROM items WHERE id = 2;
+\d missing
+\foo
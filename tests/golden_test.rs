@@ -0,0 +1,38 @@
+mod common;
+
+use std::{fs::File, io::BufReader, path::Path};
+
+use tinydb::{database::Database, shell::Shell};
+
+use common::golden;
+
+/// Execute each sql file of tests/golden/sql on a new in-memory database through the shell, and
+/// compare the output, with each statement echoed before its result, with the expected output
+/// of tests/golden/expected. Unlike the regression tests, no server is started and each file
+/// is independent of the others, so errors are also part of the output.
+#[test]
+fn test_golden() -> anyhow::Result<()> {
+    let golden_path = Path::new("tests").join("golden");
+
+    let mut failures = Vec::new();
+    for sql_file in golden::sql_files(&golden_path.join("sql"))? {
+        let sql_name = sql_file
+            .file_name()
+            .and_then(|name| name.to_str())
+            .expect("Failed to get name of sql file");
+
+        let mut shell = Shell::new(Database::open_in_memory()?).with_echo(true);
+        let mut output = Vec::new();
+        shell.run(BufReader::new(File::open(&sql_file)?), &mut output, false)?;
+
+        let expected_file = golden_path
+            .join("expected")
+            .join(format!("{}.out", sql_name));
+        if let Err(err) = golden::check(&expected_file, &String::from_utf8(output)?) {
+            failures.push(err);
+        }
+    }
+
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+    Ok(())
+}
@@ -0,0 +1,9 @@
+create table t_agg(a int, b varchar);
+select count(*), sum(a), min(a), max(a) from t_agg;
+insert into t_agg(a, b) values(3, 'c');
+insert into t_agg(a, b) values(1, 'a');
+insert into t_agg(b) values('null');
+insert into t_agg(a, b) values(5, 'b');
+select count(*), count(a), sum(a), min(a), max(a) from t_agg;
+select min(b) as first, max(b) as last from t_agg;
+select count(*) from t_agg where a > 1;
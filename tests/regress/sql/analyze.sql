@@ -0,0 +1,8 @@
+create table t_analyze(a int, b varchar);
+create index t_analyze_a on t_analyze(a);
+insert into t_analyze(a, b) values(1, 'one'), (2, 'two'), (2, 'two'), (3, null);
+analyze t_analyze;
+select s.staattnum, s.stareltuples, s.stadistinct from pg_statistic s join pg_class c on c.oid = s.starelid where c.relname = 't_analyze';
+select * from t_analyze where a = 2;
+drop table t_analyze;
+select * from pg_statistic;
@@ -0,0 +1,8 @@
+create table t_case(a int, b varchar);
+insert into t_case values (1, 'one');
+insert into t_case values (2, 'two');
+insert into t_case values (3, NULL);
+select a, case when a = 1 then 'first' when a = 2 then 'second' else 'other' end from t_case;
+select a, case b when 'one' then 1 when 'two' then 2 end as n from t_case;
+select a, case when b is null then 0.5 else a end as value from t_case;
+select b from t_case where case when a > 1 then b = 'two' else false end;
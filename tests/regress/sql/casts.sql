@@ -0,0 +1,10 @@
+create table t_casts(a int, b bigint, c text, d boolean, e date);
+insert into t_casts values ('1', 10, 100, 'yes', '2024-01-01');
+insert into t_casts values (2, '20', 'two', false, '2024-02-29');
+insert into t_casts values (3, 30, true, 't', '2024-03-15');
+select * from t_casts;
+select * from t_casts where a = '2';
+select * from t_casts where b > 15.5;
+select * from t_casts where e < '2024-03-01' and d = 'true';
+select cast(a as text) || '!' as text_a, b::int + 1 as int_b, d::int as int_d, e::timestamp from t_casts;
+select a::bigint, cast(2.5 as int) as rounded, '42'::smallint as small from t_casts where a in ('1', '3');
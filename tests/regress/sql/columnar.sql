@@ -0,0 +1,11 @@
+create table t_columnar(a int, b text, c float8) with (access_method = 'columnar');
+insert into t_columnar values (1, 'one', 1.5), (2, NULL, 2.5), (3, 'three', NULL);
+select * from t_columnar;
+select count(*), sum(a), max(c) from t_columnar;
+delete from t_columnar where a = 2;
+insert into t_columnar values (4, 'four', 4.5);
+select a, b from t_columnar where a > 1;
+create index t_columnar_a on t_columnar(a);
+create table t_columnar_as with (access_method = 'columnar') as select a, b from t_columnar;
+select * from t_columnar_as;
+select relname, relam from pg_class where relname = 't_columnar' or relname = 't_columnar_a' or relname = 't_columnar_as';
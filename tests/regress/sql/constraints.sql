@@ -0,0 +1,7 @@
+create table t_constraints(a int not null, b int default 10, c varchar default 'none', d boolean null);
+insert into t_constraints values (1, 2, 'abc', true);
+insert into t_constraints(a) values (2);
+insert into t_constraints(a, c) values (3, 'xyz');
+insert into t_constraints(a, b) values (4, null);
+insert into t_constraints values (5);
+select * from t_constraints;
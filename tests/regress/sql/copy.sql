@@ -0,0 +1,10 @@
+CREATE TABLE copy_t(a int, b varchar, c boolean);
+INSERT INTO copy_t VALUES (1, 'one', true);
+INSERT INTO copy_t VALUES (2, NULL, false);
+INSERT INTO copy_t VALUES (3, 'tab	and \ backslash', true);
+INSERT INTO copy_t VALUES (4, 'comma, "quoted"', false);
+COPY copy_t TO STDOUT;
+COPY copy_t (c, a) TO STDOUT;
+COPY copy_t TO STDOUT WITH (FORMAT csv, HEADER);
+COPY copy_t TO STDOUT WITH (FORMAT csv, DELIMITER ';', NULL 'NULL');
+COPY copy_t TO STDOUT CSV;
@@ -0,0 +1,8 @@
+create table t_index(a int, b varchar);
+insert into t_index(a, b) values(1, 'one');
+insert into t_index(a, b) values(2, 'two');
+create index t_index_a on t_index(a);
+insert into t_index(a, b) values(3, 'three');
+insert into t_index(b) values('null key');
+select * from pg_index;
+select * from t_index;
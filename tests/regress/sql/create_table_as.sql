@@ -0,0 +1,12 @@
+create table t_ctas(a int, b varchar, c boolean);
+insert into t_ctas values (1, 'one', true), (2, 'two', false), (3, null, null);
+create table t_ctas_all as select * from t_ctas;
+select * from t_ctas_all;
+create table t_ctas_columns as select b, a from t_ctas where a > 1;
+select * from t_ctas_columns;
+insert into t_ctas_columns values ('four', 4);
+select * from t_ctas_columns;
+create table t_ctas_aggregate as select count(*), sum(a) from t_ctas;
+select * from t_ctas_aggregate;
+create table t_ctas_empty as select * from t_ctas limit 0;
+select * from t_ctas_empty;
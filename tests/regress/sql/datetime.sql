@@ -0,0 +1,9 @@
+create table t_datetime(a int, d date, ts timestamp);
+insert into t_datetime values (1, '2024-01-01', '2024-01-01 10:00:00');
+insert into t_datetime values (2, '1999-12-31', '1999-12-31 23:59:59.5');
+insert into t_datetime values (3, '2024-02-29', '2024-02-29');
+select * from t_datetime;
+select a from t_datetime where d > '2000-01-01';
+select a from t_datetime where ts < timestamp '2024-01-01 10:00:01' and d >= date '2024-01-01';
+select a from t_datetime where d = ts;
+select min(d), max(ts) from t_datetime;
@@ -0,0 +1,12 @@
+create table t_delete(a int, b varchar);
+insert into t_delete(a, b) values(1, 'one');
+insert into t_delete(a, b) values(2, 'two');
+insert into t_delete(a) values(3);
+insert into t_delete(a, b) values(4, 'four');
+select * from t_delete where a >= 2;
+delete from t_delete where a = 2;
+select * from t_delete;
+delete from t_delete where b is null or a > 3;
+select * from t_delete;
+delete from t_delete;
+select * from t_delete;
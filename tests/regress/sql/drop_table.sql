@@ -0,0 +1,13 @@
+create table t_drop(a int, b text);
+create index t_drop_a on t_drop(a);
+insert into t_drop values (1, 'one'), (2, 'two');
+create table t_drop_columnar(a int) with (access_method = 'columnar');
+insert into t_drop_columnar values (1);
+drop table t_drop, t_drop_columnar;
+drop table if exists t_drop;
+select relname from pg_class where relname = 't_drop';
+select relname from pg_class where relname = 't_drop_a';
+create table t_drop(a text);
+insert into t_drop values ('recreated');
+select * from t_drop;
+drop table t_drop;
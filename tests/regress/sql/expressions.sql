@@ -0,0 +1,11 @@
+create table t_expressions(price int, quantity int, name varchar, weight float8);
+insert into t_expressions values (10, 3, 'apple', 0.5);
+insert into t_expressions values (25, 2, 'pear', 1.25);
+insert into t_expressions values (7, NULL, 'plum', 2);
+select name, price * quantity from t_expressions;
+select name, price * quantity as total from t_expressions where price * quantity > 25;
+select price + 1, price - 1, price / 2, price % 4, -price from t_expressions;
+select weight * 2, price * weight from t_expressions;
+select name || ' x' || quantity as label from t_expressions;
+select 1 as one, 'tinydb' as db, 2.5 from t_expressions limit 1;
+select (price) from t_expressions where -price < -20;
@@ -0,0 +1,7 @@
+create table t_functions(name varchar, nickname varchar, balance int);
+insert into t_functions values ('Alice', NULL, 10);
+insert into t_functions values ('bob', 'Bobby', 20);
+select upper(name), lower(name), length(name) from t_functions;
+select name, coalesce(nickname, name) as display from t_functions;
+select abs(balance - 30) as diff from t_functions where abs(balance - 30) > 15;
+select now() > '2024-01-01' as after from t_functions limit 1;
@@ -17,6 +17,14 @@ create table t3(a boolean, b boolean);
 insert into t3(a, b) values (true, false);
 select * from t3;
 
+create table t4(a int, b varchar);
+insert into t4 values (1, 'a'), (2, 'b'), (3, 'c');
+insert into t4(b) values ('d'), ('e');
+select * from t4;
+
+insert into t4 values (4, 'f'), (5, 'g') returning a;
+insert into t4(b) values ('h') returning *, a is null as no_a;
+
 
 -- Test the projection behaviour specifing the columns
 
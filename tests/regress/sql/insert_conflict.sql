@@ -0,0 +1,8 @@
+create table t_upsert(a int, b text, c int);
+create unique index t_upsert_a on t_upsert(a);
+insert into t_upsert values (1, 'one', 10), (2, 'two', 20), (null, 'none', 0), (null, 'none', 0);
+insert into t_upsert values (1, 'uno', 11), (3, 'three', 30) on conflict do nothing;
+insert into t_upsert values (3, 'tres', 31) on conflict (a) do nothing returning *;
+insert into t_upsert values (2, 'dos', 21), (4, 'four', 40) on conflict (a) do update set b = t_upsert.b || '/' || excluded.b, c = excluded.c returning a, b, c;
+select * from t_upsert order by a;
+drop table t_upsert;
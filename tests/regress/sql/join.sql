@@ -0,0 +1,13 @@
+create table t_join_users(id int, name varchar);
+create table t_join_orders(user_id int, amount int);
+insert into t_join_users values (1, 'alice');
+insert into t_join_users values (2, 'bob');
+insert into t_join_users values (3, 'carol');
+insert into t_join_orders values (1, 10);
+insert into t_join_orders values (1, 20);
+insert into t_join_orders values (3, 30);
+select name, amount from t_join_users join t_join_orders on id = user_id;
+select name, amount from t_join_users, t_join_orders where id = user_id and amount > 10;
+select u.name, o.amount from t_join_users u join t_join_orders o on u.id = o.user_id where o.amount < 30;
+select t_join_orders.* from t_join_users join t_join_orders on t_join_users.id = t_join_orders.user_id where name = 'carol';
+select count(*) from t_join_users, t_join_orders;
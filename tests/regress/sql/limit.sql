@@ -0,0 +1,12 @@
+create table t_limit(a int);
+insert into t_limit values (1);
+insert into t_limit values (2);
+insert into t_limit values (3);
+insert into t_limit values (4);
+select * from t_limit limit 2;
+select * from t_limit limit 2 offset 1;
+select * from t_limit offset 3;
+select * from t_limit where a > 1 limit 1;
+select * from t_limit limit 0;
+select count(*) from t_limit limit 1;
+select count(*) from t_limit offset 1;
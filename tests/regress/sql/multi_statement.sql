@@ -0,0 +1,7 @@
+create table t_multi(a int, b text);;
+insert into t_multi values (1, 'one;'), (2, 'two'); select * from t_multi;
+;
+select a from t_multi where b = 'one;'; -- trailing comment;
+declare c_multi cursor for select a, b from t_multi; fetch 1 from c_multi; fetch all from c_multi; close c_multi;
+set datestyle = 'ISO, DMY'; reset all; select count(*) from t_multi;
+drop table t_multi;
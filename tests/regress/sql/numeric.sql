@@ -0,0 +1,9 @@
+create table t_numeric(a smallint, b bigint, c real, d double precision, e int8, f float8);
+insert into t_numeric values (1, 3000000000, 1.5, 2.25, 10, 0.1);
+insert into t_numeric values (2, 9223372036854775807, 0.5, 0.001, 20, 100);
+insert into t_numeric values (3, 1, null, 3, 30, 2.5);
+select * from t_numeric;
+select a, b from t_numeric where b > 2147483647;
+select a from t_numeric where c < 1.5;
+select a from t_numeric where d = 3;
+select sum(a), sum(e), sum(f), max(b), min(c) from t_numeric;
@@ -0,0 +1,22 @@
+create table t_order(a int, b varchar);
+insert into t_order values (3, 'c'), (1, 'a'), (null, 'n'), (2, 'b'), (1, 'z');
+select * from t_order order by a;
+select * from t_order order by a desc;
+select * from t_order order by a nulls first;
+select * from t_order order by a desc nulls last;
+select * from t_order order by a, b desc;
+select b from t_order order by a;
+select a + 1 as x, b from t_order order by x;
+select b, a from t_order order by 2, 1;
+select * from t_order order by a limit 2 offset 1;
+select * from t_order where a > 1 order by b desc;
+select count(*) from t_order order by 1;
+create table t_digits(d int);
+insert into t_digits values (0), (1), (2), (3), (4), (5), (6), (7), (8), (9);
+create table t_large as select d1.d * 1000 + d2.d * 100 + d3.d * 10 + d4.d as n, d4.d as m from t_digits d1, t_digits d2, t_digits d3, t_digits d4;
+select * from t_large order by m desc, n limit 5;
+select * from t_large order by n desc limit 3 offset 5000;
+select count(*) from t_large;
+drop table t_order;
+drop table t_digits;
+drop table t_large;
@@ -0,0 +1,10 @@
+create table t_predicates(a int, b varchar);
+insert into t_predicates values (1, 'one');
+insert into t_predicates values (2, 'two');
+insert into t_predicates values (3, 'three');
+insert into t_predicates values (4, 'four');
+select * from t_predicates where a in (1, 3);
+select * from t_predicates where b not in ('one', 'four');
+select * from t_predicates where a between 2 and 3;
+select * from t_predicates where a not between 2 and 3;
+select a, a in (2, 4) as even from t_predicates;
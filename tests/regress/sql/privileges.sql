@@ -0,0 +1,14 @@
+CREATE ROLE r_priv_reader;
+CREATE USER r_priv_user PASSWORD 'secret';
+GRANT r_priv_reader TO r_priv_user;
+CREATE TABLE t_priv(a int);
+INSERT INTO t_priv VALUES (1);
+GRANT SELECT ON t_priv TO r_priv_reader;
+GRANT INSERT ON TABLE t_priv TO PUBLIC;
+SET ROLE r_priv_user; INSERT INTO t_priv VALUES (2);
+SET ROLE r_priv_user; SELECT * FROM t_priv;
+SET ROLE r_priv_user; CREATE TABLE t_priv_owned(a int);
+\dt t_priv*;
+REVOKE ALL ON t_priv FROM PUBLIC;
+REVOKE r_priv_reader FROM r_priv_user;
+DROP TABLE t_priv_owned;
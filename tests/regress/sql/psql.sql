@@ -0,0 +1,7 @@
+CREATE TABLE t_psql(a int, b text);
+CREATE TABLE t_psql2(a int);
+CREATE INDEX t_psql_a_idx ON t_psql(a);
+\dt t_psql*;
+\dt t_psql?;
+\di t_psql*;
+\dt pg_catalog.pg_class;
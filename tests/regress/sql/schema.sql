@@ -0,0 +1,10 @@
+CREATE SCHEMA s_app;
+CREATE TABLE t_schema(a int);
+CREATE TABLE s_app.t_schema(a text);
+INSERT INTO t_schema VALUES (1);
+INSERT INTO s_app.t_schema VALUES ('app');
+SELECT * FROM t_schema;
+SELECT * FROM public.t_schema;
+SELECT * FROM s_app.t_schema;
+SELECT relname FROM pg_catalog.pg_class WHERE relname = 't_schema';
+\dt *.t_schema;
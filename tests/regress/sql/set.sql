@@ -0,0 +1,6 @@
+SET client_encoding TO 'UTF8';
+SET datestyle = 'ISO, DMY';
+SET application_name TO DEFAULT;
+SHOW datestyle;
+SHOW server_version;
+SHOW ALL;
@@ -0,0 +1,11 @@
+create table t_subquery(a int, b varchar);
+insert into t_subquery values (1, 'one');
+insert into t_subquery values (2, 'two');
+insert into t_subquery values (3, 'three');
+select * from (select a, b from t_subquery) as sub;
+select sub.b from (select * from t_subquery where a > 1) sub where sub.a < 3;
+select x, y from (select a, b from t_subquery) as sub(x, y) where x = 3;
+select * from (select * from t_subquery limit 2) as sub;
+select * from (select count(*) from t_subquery) as sub;
+select * from (select * from (select a from t_subquery) as inner_sub) as outer_sub;
+select t.a, sub.a from t_subquery t join (select a from t_subquery where a > 2) sub on t.a < sub.a;
@@ -0,0 +1,5 @@
+SELECT name, setting FROM pg_settings WHERE name = 'DateStyle';
+SELECT count(*) FROM pg_settings;
+SELECT datname, application_name, state, query FROM pg_stat_activity;
+SELECT buffers FROM pg_stat_bufferpool;
+SELECT count(*) FROM pg_stat_bufferpool WHERE used > 0 AND used <= buffers;
@@ -0,0 +1,8 @@
+create table t_text(a int, b text, c varchar);
+insert into t_text values (1, 'a text value that is longer than any varchar length', 'abc');
+insert into t_text values (2, 'abc', 'abc');
+insert into t_text values (3, null, 'xyz');
+select * from t_text;
+select a from t_text where b = c;
+select a, b from t_text where b > 'abc';
+select min(b), max(b), count(b) from t_text;
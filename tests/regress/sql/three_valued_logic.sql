@@ -0,0 +1,9 @@
+create table t_three_valued(a int, b boolean);
+insert into t_three_valued values (1, true);
+insert into t_three_valued values (2, false);
+insert into t_three_valued values (3, null);
+select a, b and true as and_true, b and false as and_false, b or true as or_true, b or false as or_false, not b as not_b from t_three_valued;
+select * from t_three_valued where b or a = 3;
+select * from t_three_valued where not b;
+select * from t_three_valued where not (b and a = 1);
+select a, a not in (1, null) as not_in from t_three_valued;
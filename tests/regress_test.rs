@@ -1,29 +1,28 @@
-use std::{
-    fs, io,
-    path::Path,
-    process::{Child, Command},
+mod common;
+
+use std::{fs, path::Path};
+
+use tinydb::{
+    catalog::pg_type,
+    postgres_protocol::client::{Column, QueryResult},
+    Oid,
 };
 
-#[test]
-fn test_regress() -> anyhow::Result<()> {
-    let sql_entries = fs::read_dir(Path::new("tests").join("regress").join("sql"))
-        .expect("Failed to read regress sql dir")
-        .map(|res| res.map(|e| e.path()))
-        .collect::<Result<Vec<_>, io::Error>>()?;
+use common::{golden, TestServer};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_regress() -> anyhow::Result<()> {
+    // All sql files are executed on the same database, so they run sorted by name.
+    let sql_entries = golden::sql_files(&Path::new("tests").join("regress").join("sql"))?;
 
     let expected_path = Path::new("tests").join("regress").join("expected");
     let output_path = Path::new("tests").join("regress").join("output");
 
-    let temp_dir = tempfile::tempdir().expect("Failed to create temp dir to regress tests");
-
-    // tinydb command will be killed when _tinydb is dropped.
-    let _tinydb = TinyDBCommand::start(&temp_dir.path())?;
-
-    // Wait the server to start completely.
-    std::thread::sleep(std::time::Duration::from_millis(500));
+    let server = TestServer::start().await?;
+    let mut failures = Vec::new();
 
     for sql_file in sql_entries {
-        let mut output = Vec::new();
+        let mut output = String::new();
 
         let sql_name = sql_file
             .file_name()
@@ -31,70 +30,241 @@ fn test_regress() -> anyhow::Result<()> {
             .to_str()
             .expect("Failed to get name of sql file");
 
-        let expected_sql = fs::read_to_string(expected_path.join(format!("{}.out", sql_name)))?;
-
         let sql = fs::read_to_string(&sql_file)?;
         for sql in sql.lines() {
-            if sql.is_empty() || !sql.ends_with(";") {
+            if sql.is_empty() || !sql.ends_with(';') {
                 continue;
             }
-            output.extend_from_slice(&sql.as_bytes().to_vec());
-            if sql != "" {
-                output.extend_from_slice("\n".as_bytes());
+            output.push_str(sql);
+            output.push('\n');
+
+            // Each line runs on a new connection, like a psql -c invocation that generated the
+            // expected outputs.
+            let (query, title) = match list_relations_query(sql) {
+                Some(query) => (query, Some("List of relations")),
+                None => (sql.to_string(), None),
+            };
+            let mut client = server.connect("psql").await?;
+            let results = match client.simple_query(&query).await {
+                Ok(results) => results,
+                Err(err) => panic!("Failed to execute {:?} of {:?}: {}", sql, sql_file, err),
+            };
+            client.terminate().await?;
+
+            for result in &results {
+                format_result(result, title, &mut output);
             }
+        }
+
+        fs::write(output_path.join(sql_name), &output)?;
 
-            let result = Command::new("psql")
-                .arg("-h")
-                .arg("localhost")
-                .arg("-p")
-                .arg("6379")
-                .arg("-X")
-                .arg("-d")
-                .arg("tinydb")
-                .arg("-c")
-                .arg(sql)
-                .output()?;
-            output.extend_from_slice(&result.stdout);
-
-            assert_eq!(
-                result.stderr.len(),
-                0,
-                "Failed to execute psql: {}",
-                std::str::from_utf8(&result.stderr.as_slice())?
-            );
+        if let Err(err) = golden::check(&expected_path.join(format!("{}.out", sql_name)), &output) {
+            failures.push(err);
         }
+    }
 
-        let output =
-            std::str::from_utf8(&output.as_slice()).expect("Failed to convert output to string");
+    server.stop().await?;
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+    Ok(())
+}
 
-        fs::write(output_path.join(sql_name), output).unwrap();
+/// Format a statement result the same way of psql on its default aligned mode.
+fn format_result(result: &QueryResult, title: Option<&str>, output: &mut String) {
+    match result {
+        QueryResult::Rows { columns, rows, tag } => {
+            format_table(columns, rows, title, output);
 
-        assert_eq!(expected_sql, output, "Failed to match file {:?}", sql_file);
+            // The status of statements with RETURNING is printed after their rows.
+            if ["INSERT", "UPDATE", "DELETE"]
+                .iter()
+                .any(|command| tag.starts_with(command))
+            {
+                output.push_str(tag);
+                output.push('\n');
+            }
+        }
+        QueryResult::Command(tag) => {
+            output.push_str(tag);
+            output.push('\n');
+        }
+        QueryResult::CopyOut { data, .. } => output.push_str(&String::from_utf8_lossy(data)),
+        QueryResult::Empty => {}
     }
+}
 
-    Ok(())
+fn format_table(
+    columns: &[Column],
+    rows: &[Vec<Option<String>>],
+    title: Option<&str>,
+    output: &mut String,
+) {
+    let value = |row: &[Option<String>], i: usize| row[i].clone().unwrap_or_default();
+    let widths = columns
+        .iter()
+        .enumerate()
+        .map(|(i, column)| {
+            rows.iter()
+                .map(|row| value(row, i).chars().count())
+                .chain(std::iter::once(column.name.chars().count()))
+                .max()
+                .unwrap_or_default()
+        })
+        .collect::<Vec<_>>();
+
+    if let Some(title) = title {
+        let width = widths.iter().map(|width| width + 3).sum::<usize>() - 1;
+        let padding = width.saturating_sub(title.chars().count()) / 2;
+        output.push_str(&format!("{}{}\n", " ".repeat(padding), title));
+    }
+
+    let header = columns
+        .iter()
+        .zip(&widths)
+        .map(|(column, width)| format!(" {:^width$} ", column.name, width = width))
+        .collect::<Vec<_>>();
+    output.push_str(&header.join("|"));
+    output.push('\n');
+
+    let separator = widths
+        .iter()
+        .map(|width| "-".repeat(width + 2))
+        .collect::<Vec<_>>();
+    output.push_str(&separator.join("+"));
+    output.push('\n');
+
+    for row in rows {
+        let mut line = String::new();
+        for (i, (column, width)) in columns.iter().zip(&widths).enumerate() {
+            let value = value(row, i);
+            let last = i == columns.len() - 1;
+            if i > 0 {
+                line.push('|');
+            }
+            if is_numeric(column.type_oid) {
+                line.push_str(&format!(" {:>width$}", value, width = width));
+            } else if last {
+                // psql don't pad the last column when it is left aligned.
+                line.push_str(&format!(" {}", value));
+            } else {
+                line.push_str(&format!(" {:<width$}", value, width = width));
+            }
+            if !last {
+                line.push(' ');
+            }
+        }
+        output.push_str(&line);
+        output.push('\n');
+    }
+
+    match rows.len() {
+        1 => output.push_str("(1 row)\n\n"),
+        n => output.push_str(&format!("({} rows)\n\n", n)),
+    }
 }
 
-struct TinyDBCommand {
-    cmd: Child,
+/// Return true if psql align values of the given type to the right.
+fn is_numeric(typ: Oid) -> bool {
+    matches!(
+        typ,
+        pg_type::INT2_OID
+            | pg_type::INT_OID
+            | pg_type::INT8_OID
+            | pg_type::FLOAT4_OID
+            | pg_type::FLOAT8_OID
+    )
 }
 
-impl TinyDBCommand {
-    fn start(data_dir: &Path) -> anyhow::Result<Self> {
-        let cmd = Command::new("cargo")
-            .arg("run")
-            .arg("--")
-            .arg("--init")
-            .arg("--data-dir")
-            .arg(data_dir)
-            .spawn()?;
-
-        Ok(Self { cmd })
+/// Return the query that psql sends for the \dt and \di meta-commands on the given line, or None
+/// if the line is not one of these meta-commands.
+fn list_relations_query(line: &str) -> Option<String> {
+    let line = line.trim_end_matches(';');
+    let (command, pattern) = match line.split_once(' ') {
+        Some((command, pattern)) => (command, Some(pattern.trim())),
+        None => (line, None),
+    };
+    let indexes = match command {
+        "\\dt" => false,
+        "\\di" => true,
+        _ => return None,
+    };
+
+    let mut sql = String::from(
+        "SELECT n.nspname as \"Schema\",\n  c.relname as \"Name\",\n  \
+         CASE c.relkind WHEN 'r' THEN 'table' WHEN 'i' THEN 'index' END as \"Type\",\n  \
+         pg_catalog.pg_get_userbyid(c.relowner) as \"Owner\"",
+    );
+    if indexes {
+        sql.push_str(",\n c2.relname as \"Table\"");
+    }
+    sql.push_str(
+        "\nFROM pg_catalog.pg_class c\n     \
+         LEFT JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace\n",
+    );
+    if indexes {
+        sql.push_str(
+            "     LEFT JOIN pg_catalog.pg_index i ON i.indexrelid = c.oid\n     \
+             LEFT JOIN pg_catalog.pg_class c2 ON i.indrelid = c2.oid\n",
+        );
+        sql.push_str("WHERE c.relkind IN ('i','I','')\n");
+    } else {
+        sql.push_str("WHERE c.relkind IN ('r','p','')\n");
+    }
+
+    match pattern {
+        None => sql.push_str(
+            "      AND n.nspname <> 'pg_catalog'\n      \
+             AND n.nspname !~ '^pg_toast'\n      \
+             AND n.nspname <> 'information_schema'\n  \
+             AND pg_catalog.pg_table_is_visible(c.oid)\n",
+        ),
+        Some(pattern) => {
+            let (schema, name) = match pattern.rsplit_once('.') {
+                Some((schema, name)) => (Some(schema), name),
+                None => (None, pattern),
+            };
+            if let Some(regex) = pattern_regex(name) {
+                sql.push_str(&format!(
+                    "  AND c.relname OPERATOR(pg_catalog.~) '{}' COLLATE pg_catalog.default\n",
+                    regex
+                ));
+            }
+            match schema {
+                Some(schema) => {
+                    if let Some(regex) = pattern_regex(schema) {
+                        sql.push_str(&format!(
+                            "  AND n.nspname OPERATOR(pg_catalog.~) '{}' COLLATE pg_catalog.default\n",
+                            regex
+                        ));
+                    }
+                }
+                None => sql.push_str("  AND pg_catalog.pg_table_is_visible(c.oid)\n"),
+            }
+        }
     }
+    sql.push_str("ORDER BY 1,2;");
+    Some(sql)
 }
 
-impl Drop for TinyDBCommand {
-    fn drop(&mut self) {
-        self.cmd.kill().expect("Failed to kill tinydb server");
+/// Convert a psql name pattern to a regular expression quoted as a SQL string, where `*` match
+/// any sequence of characters and `?` any character. Return None if the pattern match all
+/// names, since psql omits these conditions.
+fn pattern_regex(pattern: &str) -> Option<String> {
+    if pattern == "*" {
+        return None;
+    }
+    let mut regex = String::from("^(");
+    for c in pattern.chars() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            '\'' => regex.push_str("''"),
+            c if c.is_alphanumeric() || c == '_' => regex.extend(c.to_lowercase()),
+            c => {
+                regex.push('\\');
+                regex.push(c);
+            }
+        }
     }
+    regex.push_str(")$");
+    Some(regex)
 }